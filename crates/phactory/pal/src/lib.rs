@@ -14,9 +14,43 @@ pub trait Sealing {
     fn unseal_data(&self, path: impl AsRef<Path>) -> Result<Option<Vec<u8>>, Self::UnsealError>;
 }
 
+/// The collateral fetched from the PCCS to verify a DCAP quote.
+///
+/// The issuer chains are PEM encoded, the CRLs are DER encoded and the TCB info and QE identity are
+/// the exact JSON bytes covered by their signatures.
+#[derive(Debug, Clone, Default)]
+pub struct SgxQuoteCollateral {
+    pub root_ca_crl: Vec<u8>,
+    pub pck_crl: Vec<u8>,
+    pub tcb_info_issuer_chain: Vec<u8>,
+    pub tcb_info: Vec<u8>,
+    pub tcb_info_signature: Vec<u8>,
+    pub qe_identity_issuer_chain: Vec<u8>,
+    pub qe_identity: Vec<u8>,
+    pub qe_identity_signature: Vec<u8>,
+}
+
+/// The attestation report created by the TEE.
+#[derive(Debug, Clone)]
+pub enum AttestationReport {
+    /// An EPID quote verified by the Intel Attestation Service.
+    ///
+    /// The signature and the signing cert are base64 encoded as returned by IAS.
+    SgxIas {
+        ra_report: String,
+        signature: String,
+        raw_signing_cert: String,
+    },
+    /// An ECDSA quote along with the collateral to verify it.
+    SgxDcap {
+        quote: Vec<u8>,
+        collateral: SgxQuoteCollateral,
+    },
+}
+
 pub trait RA {
     type Error: ErrorType;
-    fn create_attestation_report(&self, data: &[u8]) -> Result<(String, String, String), Self::Error>;
+    fn create_attestation_report(&self, data: &[u8]) -> Result<AttestationReport, Self::Error>;
     fn quote_test(&self) -> Result<(), Self::Error>;
 }
 
@@ -1,14 +1,15 @@
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use crate::contracts;
 use crate::system::{TransactionError, TransactionResult};
 use anyhow::{anyhow, Result};
 use parity_scale_codec::{Decode, Encode};
-use phala_mq::{ContractClusterId, ContractId, MessageOrigin};
+use phala_mq::{traits::MessageChannel, ContractClusterId, ContractId, MessageOrigin};
+use phala_scheduler::{AcquireOptions, Priority};
 use pink::predefined_accounts::pallet_account;
 use pink::runtime::{BoxedEventCallbacks, ExecSideEffects};
 use runtime::{AccountId, BlockNumber, Hash};
-use sidevm::service::{Command as SidevmCommand, CommandSender, SystemMessage};
+use sidevm::service::{Command as SidevmCommand, CommandSender, OutgoingRequest, SystemMessage};
 use sp_runtime::{traits::ConstU32, BoundedVec};
 
 use super::contract_address_to_id;
@@ -19,17 +20,25 @@ pub enum Command {
         nonce: BoundedVec<u8, ConstU32<32>>,
         message: Vec<u8>,
     },
+    /// An outgoing request of the sidevm instance, relayed by a worker of the cluster.
+    SidevmOutgoing(OutgoingRequest),
 }
 
+/// Max time a query waits in the scheduler queue before being dropped.
+const QUERY_QUEUE_TIMEOUT: Duration = Duration::from_secs(30);
+
 #[derive(Debug, Encode, Decode)]
 pub enum Query {
     InkMessage(Vec<u8>),
     SidevmQuery(Vec<u8>),
+    /// Get the latest crashes of the sidevm instance. Only for the deployer of the contract.
+    SidevmCrashHistory,
 }
 
 #[derive(Debug, Encode, Decode)]
 pub enum Response {
     Payload(Vec<u8>),
+    SidevmCrashHistory(Vec<contracts::SidevmCrashRecord>),
 }
 
 #[derive(Debug, Encode, Decode)]
@@ -96,6 +105,61 @@ impl Pink {
     pub fn set_on_block_end_selector(&mut self, selector: u32) {
         self.instance.set_on_block_end_selector(selector)
     }
+
+    /// Call the contract with an ink message as a transaction.
+    fn call(
+        &mut self,
+        origin: AccountId,
+        nonce: Vec<u8>,
+        message: Vec<u8>,
+        context: &mut contracts::NativeContext,
+    ) -> TransactionResult {
+        let storage = cluster_storage(&mut context.contract_clusters, &self.cluster_id)
+            .expect("Pink cluster should always exists!");
+
+        let (result, effects) = self.instance.bare_call(
+            storage,
+            origin.clone(),
+            message,
+            false,
+            context.block.block_number,
+            context.block.now_ms,
+            ContractEventCallback::from_log_sender(
+                &context.log_handler,
+                context.block.block_number,
+            ),
+        );
+
+        if let Some(log_handler) = &context.log_handler {
+            if let Err(_) = log_handler.try_send(SidevmCommand::PushSystemMessage(
+                SystemMessage::PinkMessageOutput {
+                    origin: origin.clone().into(),
+                    contract: self.instance.address.clone().into(),
+                    block_number: context.block.block_number,
+                    nonce,
+                    output: result.result.encode(),
+                },
+            )) {
+                error!("Pink emit message output to log handler failed");
+            }
+        }
+
+        let _ = pink::transpose_contract_result(&result).map_err(|err| {
+            log::error!("Pink [{:?}] command exec error: {:?}", self.id(), err);
+            TransactionError::Other(format!("Call contract method failed: {:?}", err))
+        })?;
+        Ok(effects)
+    }
+}
+
+/// The queries of the deployer take precedence over the other signed ones, which take precedence
+/// over the anonymous ones.
+fn query_priority(origin: Option<&AccountId>, deployer: Option<&AccountId>) -> Priority {
+    match origin {
+        Some(origin) if Some(origin) == deployer => Priority::High,
+        Some(_) => Priority::Normal,
+        None => Priority::Low,
+    }
 }
 
 #[async_trait::async_trait]
@@ -114,9 +178,13 @@ impl contracts::NativeContract for Pink {
     ) -> Result<Response, QueryError> {
         match req {
             Query::InkMessage(input_data) => {
+                let options = AcquireOptions {
+                    priority: query_priority(origin, context.deployer.as_ref()),
+                    deadline: Some(Instant::now() + QUERY_QUEUE_TIMEOUT),
+                };
                 let _guard = context
                     .query_scheduler
-                    .acquire(self.id(), 1)
+                    .acquire_with_options(self.id(), 1, options)
                     .await
                     .or(Err(QueryError::ServiceUnavailable))?;
 
@@ -173,6 +241,18 @@ impl contracts::NativeContract for Pink {
                 .await
                 .or(Err(QueryError::Timeout))?
             }
+            Query::SidevmCrashHistory => {
+                // The crash diagnostics may carry the logs of the guest, only for the deployer
+                if origin.is_none() || origin != context.deployer.as_ref() {
+                    return Err(QueryError::BadOrigin);
+                }
+                let history = context
+                    .sidevm_crash_history
+                    .as_ref()
+                    .ok_or(QueryError::SidevmNotFound)?;
+                let records = history.lock().unwrap().iter().cloned().collect();
+                Ok(Response::SidevmCrashHistory(records))
+            }
         }
     }
 
@@ -189,42 +269,31 @@ impl contracts::NativeContract for Pink {
                     MessageOrigin::Pallet(_) => pallet_account(),
                     _ => return Err(TransactionError::BadOrigin),
                 };
-
-                let storage = cluster_storage(&mut context.contract_clusters, &self.cluster_id)
-                    .expect("Pink cluster should always exists!");
-
-                let (result, effects) = self.instance.bare_call(
-                    storage,
-                    origin.clone(),
-                    message,
-                    false,
-                    context.block.block_number,
-                    context.block.now_ms,
-                    ContractEventCallback::from_log_sender(
-                        &context.log_handler,
-                        context.block.block_number,
-                    ),
-                );
-
-                if let Some(log_handler) = &context.log_handler {
-                    if let Err(_) = log_handler.try_send(SidevmCommand::PushSystemMessage(
-                        SystemMessage::PinkMessageOutput {
-                            origin: origin.clone().into(),
-                            contract: self.instance.address.clone().into(),
-                            block_number: context.block.block_number,
-                            nonce: nonce.into_inner(),
-                            output: result.result.encode(),
-                        },
-                    )) {
-                        error!("Pink emit message output to log handler failed");
+                self.call(origin, nonce.into_inner(), message, context)
+            }
+            Command::SidevmOutgoing(request) => {
+                let worker = match origin {
+                    MessageOrigin::Worker(worker) => worker,
+                    _ => return Err(TransactionError::BadOrigin),
+                };
+                if !context.claim_sidevm_relay(&self.cluster_id, &worker) {
+                    log::debug!(
+                        "Pink [{:?}] dropping sidevm request from a non-relay",
+                        self.id()
+                    );
+                    return Err(TransactionError::BadOrigin);
+                }
+                match request {
+                    OutgoingRequest::Message { topic, payload } => {
+                        context.mq().push_data(payload, topic);
+                        Ok(Default::default())
+                    }
+                    OutgoingRequest::ContractCommand { message } => {
+                        let origin = self.instance.address.clone();
+                        self.call(origin, vec![], message, context)
                     }
+                    _ => Err(TransactionError::Other("Unsupported sidevm request".into())),
                 }
-
-                let _ = pink::transpose_contract_result(&result).map_err(|err| {
-                    log::error!("Pink [{:?}] command exec error: {:?}", self.id(), err);
-                    TransactionError::Other(format!("Call contract method failed: {:?}", err))
-                })?;
-                Ok(effects)
             }
         }
     }
@@ -359,11 +428,19 @@ pub mod cluster {
         pub fn remove_cluster(&mut self, cluster_id: &ContractClusterId) -> Option<Cluster> {
             self.clusters.remove(cluster_id)
         }
+
+        pub fn iter(&self) -> impl Iterator<Item = (&ContractClusterId, &Cluster)> {
+            self.clusters.iter()
+        }
     }
 
     #[derive(Serialize, Deserialize, Default)]
     pub struct ClusterConfig {
         pub log_handler: Option<ContractId>,
+        /// The gas schedule version used by both the contracts and the sidevm instances in the
+        /// cluster. `None` for the legacy schedules.
+        #[serde(default)]
+        pub gas_schedule_version: Option<u32>,
     }
 
     #[derive(Serialize, Deserialize)]
@@ -381,6 +458,11 @@ pub mod cluster {
             self.contracts.insert(address)
         }
 
+        /// Remove a contract from the cluster. Returns true if the contract was in the cluster.
+        pub fn remove_contract(&mut self, address: &ContractId) -> bool {
+            self.contracts.remove(address)
+        }
+
         pub fn key(&self) -> &sr25519::Pair {
             &self.key
         }
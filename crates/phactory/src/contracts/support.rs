@@ -1,3 +1,4 @@
+use std::collections::VecDeque;
 use std::sync::{Arc, Mutex};
 
 use phala_crypto::ecdh::EcdhPublicKey;
@@ -6,16 +7,25 @@ use phala_mq::traits::MessageChannel;
 use runtime::BlockNumber;
 use serde::{Deserialize, Serialize};
 use sidevm::{
-    service::{CommandSender, ExitReason},
+    gas_schedule::GasSchedule,
+    service::{
+        CommandSender, CrashInfo, ExitReason, OutgoingRequest, OutgoingRequestSender, UpgradeHint,
+    },
     OcallAborted, VmId,
 };
+use tokio::sync::{
+    mpsc::{channel, error::TryRecvError, Receiver},
+    oneshot,
+};
 
 use super::pink::cluster::ClusterKeeper;
 use super::*;
 use crate::secret_channel::SecretReceiver;
+use crate::system::chain_state;
 use crate::types::BlockInfo;
 use anyhow::{anyhow, bail};
 use phala_serde_more as more;
+use phala_types::WorkerPublicKey;
 
 pub struct ExecuteEnv<'a, 'b> {
     pub block: &'a mut BlockInfo<'b>,
@@ -30,6 +40,53 @@ pub struct NativeContext<'a, 'b> {
     pub contract_clusters: &'a mut ClusterKeeper,
     pub self_id: ContractId,
     pub log_handler: Option<CommandSender>,
+    /// The relay of the sidevm outgoing requests. `None` if the contract has no sidevm instance.
+    pub sidevm_relay: Option<&'a mut SidevmRelay>,
+}
+
+/// The number of blocks without any relayed request after which the relay of the sidevm outgoing
+/// requests can be taken over by another worker of the cluster.
+pub const SIDEVM_RELAY_TIMEOUT: BlockNumber = 10;
+
+/// The worker relaying the outgoing requests of a sidevm instance.
+///
+/// The relay is claimed by the first worker of the cluster whose relayed request is dispatched,
+/// and can be taken over once it has relayed nothing for [`SIDEVM_RELAY_TIMEOUT`] blocks, e.g.
+/// when it went offline.
+#[derive(Serialize, Deserialize, Default, Clone, Debug)]
+pub struct SidevmRelay {
+    worker: Option<WorkerPublicKey>,
+    /// The block the last request relayed by the worker was dispatched at.
+    active_at: BlockNumber,
+}
+
+impl SidevmRelay {
+    /// Whether the requests relayed by the worker would be accepted at the block.
+    fn accepts(&self, worker: &WorkerPublicKey, block_number: BlockNumber) -> bool {
+        match &self.worker {
+            None => true,
+            Some(relay) => {
+                relay == worker
+                    || block_number.saturating_sub(self.active_at) > SIDEVM_RELAY_TIMEOUT
+            }
+        }
+    }
+
+    fn claim(&mut self, worker: &WorkerPublicKey, block_number: BlockNumber) -> bool {
+        if !self.accepts(worker, block_number) {
+            return false;
+        }
+        self.worker = Some(*worker);
+        self.active_at = block_number;
+        true
+    }
+}
+
+/// The worker identity the outgoing requests of the sidevm instances are relayed with.
+pub struct SidevmRelayer<'a> {
+    pub worker: WorkerPublicKey,
+    pub egress: &'a SignedMessageChannel,
+    pub ecdh_key: &'a KeyPair,
 }
 
 pub struct QueryContext {
@@ -37,6 +94,9 @@ pub struct QueryContext {
     pub now_ms: u64,
     pub storage: ::pink::Storage,
     pub sidevm_handle: Option<SidevmHandle>,
+    pub sidevm_crash_history: Option<SidevmCrashHistory>,
+    /// The account which deployed the contract.
+    pub deployer: Option<AccountId>,
     pub log_handler: Option<CommandSender>,
     pub query_scheduler: RequestScheduler<ContractId>,
 }
@@ -45,6 +105,23 @@ impl NativeContext<'_, '_> {
     pub fn mq(&self) -> &SignedMessageChannel {
         self.mq
     }
+
+    /// Accept the worker as the relay of the sidevm outgoing requests, unless it is not a worker
+    /// of the cluster or another worker holds the relay.
+    pub fn claim_sidevm_relay(
+        &mut self,
+        cluster: &phala_mq::ContractClusterId,
+        worker: &WorkerPublicKey,
+    ) -> bool {
+        let block_number = self.block.block_number;
+        if !chain_state::cluster_workers(self.block.storage, cluster).contains(worker) {
+            return false;
+        }
+        match &mut self.sidevm_relay {
+            Some(relay) => relay.claim(worker, block_number),
+            None => false,
+        }
+    }
 }
 
 #[async_trait::async_trait]
@@ -130,11 +207,224 @@ impl<'de> Deserialize<'de> for SidevmHandle {
     }
 }
 
+/// Max number of pending outgoing requests buffered per sidevm instance.
+const SIDEVM_OUTGOING_QUEUE_CAPACITY: usize = 64;
+
+/// The channel carrying outgoing requests from a sidevm instance to its contract.
+///
+/// It outlives the sidevm instance so that pending requests survive a restart.
+struct SidevmOutgoing {
+    tx: OutgoingRequestSender,
+    rx: Receiver<OutgoingRequest>,
+}
+
+impl Default for SidevmOutgoing {
+    fn default() -> Self {
+        let (tx, rx) = channel(SIDEVM_OUTGOING_QUEUE_CAPACITY);
+        Self { tx, rx }
+    }
+}
+
+/// Base delay before auto-restarting a crashed sidevm, doubled on each consecutive crash.
+const SIDEVM_RESTART_BACKOFF_BASE: BlockNumber = 1;
+/// Max delay before auto-restarting a crashed sidevm.
+const SIDEVM_RESTART_BACKOFF_MAX: BlockNumber = 600;
+/// Max number of consecutive auto-restarts before giving up.
+const SIDEVM_MAX_RESTARTS: u32 = 10;
+/// An instance running longer than this before crashing resets the consecutive restart count.
+const SIDEVM_STABLE_RUN_BLOCKS: BlockNumber = 100;
+/// Number of the latest crashes kept per sidevm instance.
+const SIDEVM_CRASH_HISTORY_LEN: usize = 16;
+
+/// The delay before auto-restarting a crashed sidevm after the given number of consecutive
+/// restarts.
+fn sidevm_restart_delay(restarts: u32) -> BlockNumber {
+    SIDEVM_RESTART_BACKOFF_BASE
+        .saturating_mul(1 << restarts.min(16))
+        .min(SIDEVM_RESTART_BACKOFF_MAX)
+}
+
+/// A crash of a sidevm instance.
+#[derive(Debug, Clone, Serialize, Deserialize, Encode, Decode)]
+pub struct SidevmCrashRecord {
+    /// The block at which the crash was observed.
+    pub block: BlockNumber,
+    pub reason: ExitReason,
+    /// Diagnostics collected by the host. `None` if the crash was not caused by a guest panic.
+    pub crash: Option<CrashInfo>,
+}
+
+pub type SidevmCrashHistory = Arc<Mutex<VecDeque<SidevmCrashRecord>>>;
+
+#[derive(Serialize, Deserialize, Default)]
+struct SidevmRestartState {
+    /// The block at which the current instance was started.
+    started_at: BlockNumber,
+    /// Number of consecutive auto-restarts.
+    restarts: u32,
+    /// The block to restart the crashed instance at. `None` if the crash has not been observed.
+    next_restart_at: Option<BlockNumber>,
+}
+
+impl SidevmRestartState {
+    /// Schedule the restart of the instance crashed at the block, returning the block to restart
+    /// it at. `BlockNumber::MAX` if it is not going to be restarted.
+    fn schedule_restart(&mut self, block_number: BlockNumber, auto_restart: bool) -> BlockNumber {
+        if block_number.saturating_sub(self.started_at) >= SIDEVM_STABLE_RUN_BLOCKS {
+            self.restarts = 0;
+        }
+        let restart_at = if !auto_restart || self.restarts >= SIDEVM_MAX_RESTARTS {
+            BlockNumber::MAX
+        } else {
+            block_number.saturating_add(sidevm_restart_delay(self.restarts))
+        };
+        self.next_restart_at = Some(restart_at);
+        restart_at
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 struct SidevmInfo {
     code: Vec<u8>,
     auto_restart: bool,
     handle: Arc<Mutex<SidevmHandle>>,
+    #[serde(skip)]
+    outgoing: SidevmOutgoing,
+    /// The gas schedule version of the cluster when the sidevm was started.
+    #[serde(default)]
+    gas_schedule_version: Option<u32>,
+    #[serde(default)]
+    restart_state: SidevmRestartState,
+    #[serde(default)]
+    crash_history: SidevmCrashHistory,
+    /// Diagnostics of the last terminated instance, waiting to be recorded in the history.
+    #[serde(skip)]
+    last_crash: Arc<Mutex<Option<CrashInfo>>>,
+    /// The worker relaying the outgoing requests.
+    #[serde(default)]
+    relay: SidevmRelay,
+    /// The upgrades sent to the running instance, in order, waiting for the new programs to
+    /// start.
+    #[serde(skip)]
+    pending_upgrades: VecDeque<PendingUpgrade>,
+}
+
+struct PendingUpgrade {
+    code: Vec<u8>,
+    result_rx: oneshot::Receiver<bool>,
+}
+
+impl SidevmInfo {
+    fn start(
+        &self,
+        spawner: &sidevm::service::Spawner,
+        id: VmId,
+    ) -> Result<Arc<Mutex<SidevmHandle>>> {
+        do_start_sidevm(
+            spawner,
+            &self.code,
+            id,
+            self.outgoing.tx.clone(),
+            self.gas_schedule_version,
+            self.last_crash.clone(),
+        )
+    }
+
+    /// Commit the code of the pending upgrades once the new programs have been started.
+    fn apply_pending_upgrades(&mut self, id: &VmId) {
+        let vmid = sidevm::ShortId(id);
+        while let Some(pending) = self.pending_upgrades.front_mut() {
+            match pending.result_rx.try_recv() {
+                Err(oneshot::error::TryRecvError::Empty) => return,
+                Ok(true) => {
+                    info!(target: "sidevm", "[{vmid}] Upgrade sidevm done");
+                    self.code = core::mem::take(&mut pending.code);
+                }
+                Ok(false) | Err(oneshot::error::TryRecvError::Closed) => {
+                    error!(target: "sidevm", "[{vmid}] Upgrade sidevm failed, keep the old code");
+                }
+            }
+            self.pending_upgrades.pop_front();
+        }
+    }
+
+    /// Replace the program with the new code.
+    ///
+    /// A running instance is upgraded in place, keeping its VmId, cache namespace and the
+    /// inputs not received by the old program yet. A terminated instance is restarted with the
+    /// new code. The new code replaces the old one only after the new program has been started.
+    /// Upgrades sent while a previous one is in progress are applied in order, so the last one
+    /// wins.
+    fn upgrade(
+        &mut self,
+        spawner: &sidevm::service::Spawner,
+        id: VmId,
+        code: Vec<u8>,
+    ) -> Result<()> {
+        let vmid = sidevm::ShortId(&id);
+        self.apply_pending_upgrades(&id);
+        let running_tx = match &*self.handle.lock().unwrap() {
+            SidevmHandle::Running(tx) => Some(tx.clone()),
+            SidevmHandle::Terminated(_) => None,
+        };
+        let tx = match running_tx {
+            Some(tx) => tx,
+            None => {
+                info!(target: "sidevm", "[{vmid}] Sidevm not running, starting it with the new code");
+                let previous_code = std::mem::replace(&mut self.code, code);
+                match self.start(spawner, id) {
+                    Ok(handle) => self.handle = handle,
+                    Err(err) => {
+                        self.code = previous_code;
+                        return Err(err);
+                    }
+                }
+                // The upgrades sent to the terminated instance would never be done.
+                self.pending_upgrades.clear();
+                self.restart_state.restarts = 0;
+                self.restart_state.next_restart_at = None;
+                return Ok(());
+            }
+        };
+        if !self.pending_upgrades.is_empty() {
+            info!(target: "sidevm", "[{vmid}] Previous upgrade in progress, queueing the new code");
+        }
+        // The code expected to be running when the instance receives this upgrade.
+        let previous_code = match self.pending_upgrades.back() {
+            Some(pending) => &pending.code,
+            None => &self.code,
+        };
+        let (result_tx, result_rx) = oneshot::channel();
+        let command = sidevm::service::Command::Upgrade {
+            code: code.clone(),
+            hint: UpgradeHint {
+                previous_code_hash: sp_core::blake2_256(previous_code),
+            },
+            result_tx,
+        };
+        self.pending_upgrades
+            .push_back(PendingUpgrade { code, result_rx });
+        spawner.spawn(async move {
+            let vmid = sidevm::ShortId(&id);
+            if tx.send(command).await.is_err() {
+                error!(target: "sidevm", "[{vmid}] Upgrade sidevm failed (channel closed), the VM might be already stopped");
+            }
+        });
+        Ok(())
+    }
+
+    fn record_crash(&mut self, block: BlockNumber, reason: ExitReason) {
+        let crash = self.last_crash.lock().unwrap().take();
+        let mut history = self.crash_history.lock().unwrap();
+        if history.len() == SIDEVM_CRASH_HISTORY_LEN {
+            history.pop_front();
+        }
+        history.push_back(SidevmCrashRecord {
+            block,
+            reason,
+            crash,
+        });
+    }
 }
 
 #[derive(Serialize, Deserialize)]
@@ -147,6 +437,9 @@ pub struct FatContract {
     ecdh_key: KeyPair,
     cluster_id: phala_mq::ContractClusterId,
     contract_id: phala_mq::ContractId,
+    /// `None` for the contracts deployed before the deployer was recorded.
+    #[serde(default)]
+    deployer: Option<AccountId>,
     sidevm_info: Option<SidevmInfo>,
 }
 
@@ -158,6 +451,7 @@ impl FatContract {
         ecdh_key: KeyPair,
         cluster_id: phala_mq::ContractClusterId,
         contract_id: phala_mq::ContractId,
+        deployer: AccountId,
     ) -> Self {
         FatContract {
             contract: contract.into(),
@@ -166,6 +460,7 @@ impl FatContract {
             ecdh_key,
             cluster_id,
             contract_id,
+            deployer: Some(deployer),
             sidevm_info: None,
         }
     }
@@ -178,10 +473,19 @@ impl FatContract {
         self.cluster_id
     }
 
+    pub(crate) fn deployer(&self) -> Option<&AccountId> {
+        self.deployer.as_ref()
+    }
+
     pub(crate) fn snapshot_for_query(&self) -> AnyContract {
         self.contract.snapshot()
     }
 
+    /// The number of commands failed to decrypt with the contract key.
+    pub(crate) fn decrypt_failures(&self) -> u64 {
+        self.cmd_rcv_mq.decrypt_failures()
+    }
+
     pub(crate) fn sidevm_handle(&self) -> Option<SidevmHandle> {
         self.sidevm_info
             .as_ref()
@@ -192,6 +496,15 @@ impl FatContract {
         &mut self,
         env: &mut ExecuteEnv,
     ) -> Option<TransactionResult> {
+        self.process_next_command(env).map(|(_, result)| result)
+    }
+
+    /// Process the next command, returning its origin along with the result. The origin is
+    /// `None` if the command could not be received.
+    pub(crate) fn process_next_command(
+        &mut self,
+        env: &mut ExecuteEnv,
+    ) -> Option<(Option<MessageOrigin>, TransactionResult)> {
         let secret_mq = SecretMessageChannel::new(&self.ecdh_key, &self.send_mq);
         let mut context = NativeContext {
             block: env.block,
@@ -200,16 +513,24 @@ impl FatContract {
             contract_clusters: &mut env.contract_clusters,
             self_id: self.id(),
             log_handler: env.log_handler.clone(),
+            sidevm_relay: self.sidevm_info.as_mut().map(|info| &mut info.relay),
         };
 
         phala_mq::select! {
             next_cmd = self.cmd_rcv_mq => match next_cmd {
                 Ok((_, cmd, origin)) => {
-                    info!(target: "contract", "Contract {:?} handling command", self.id());
-                    self.contract.handle_command(origin, cmd.0, &mut context)
+                    info!(target: "contract", "Contract {:?} handling command", self.contract_id);
+                    let result = self.contract.handle_command(origin.clone(), cmd.0, &mut context);
+                    (Some(origin), result)
                 }
-                Err(_e) => {
-                    Err(TransactionError::ChannelError)
+                Err(e) => {
+                    warn!(
+                        target: "contract",
+                        "Contract {:?} failed to receive command: {}",
+                        self.contract_id,
+                        e
+                    );
+                    (None, Err(TransactionError::ChannelError))
                 }
             },
         }
@@ -224,6 +545,7 @@ impl FatContract {
             contract_clusters: &mut env.contract_clusters,
             self_id: self.id(),
             log_handler: env.log_handler.clone(),
+            sidevm_relay: self.sidevm_info.as_mut().map(|info| &mut info.relay),
         };
         self.contract.on_block_end(&mut context)
     }
@@ -257,51 +579,119 @@ impl FatContract {
         spawner: &sidevm::service::Spawner,
         code: Vec<u8>,
         auto_restart: bool,
+        gas_schedule_version: Option<u32>,
+        block_number: BlockNumber,
     ) -> Result<()> {
         if self.sidevm_info.is_some() {
             bail!("Sidevm can only be started once");
         }
-        let handle = do_start_sidevm(spawner, &code, self.contract_id.0)?;
-        self.sidevm_info = Some(SidevmInfo {
+        let mut sidevm_info = SidevmInfo {
             code,
-            handle,
+            handle: Arc::new(Mutex::new(SidevmHandle::Terminated(ExitReason::Stopped))),
             auto_restart,
-        });
+            outgoing: Default::default(),
+            gas_schedule_version,
+            restart_state: SidevmRestartState {
+                started_at: block_number,
+                ..Default::default()
+            },
+            crash_history: Default::default(),
+            last_crash: Default::default(),
+            relay: Default::default(),
+            pending_upgrades: Default::default(),
+        };
+        sidevm_info.handle = sidevm_info.start(spawner, self.contract_id.0)?;
+        self.sidevm_info = Some(sidevm_info);
         Ok(())
     }
 
     pub(crate) fn restart_sidevm_if_needed(
         &mut self,
         spawner: &sidevm::service::Spawner,
+        block_number: BlockNumber,
     ) -> Result<()> {
-        if let Some(sidevm_info) = &mut self.sidevm_info {
-            let guard = sidevm_info.handle.lock().unwrap();
-            let handle = if let SidevmHandle::Terminated(reason) = &*guard {
-                let need_restart = match reason {
-                    ExitReason::Exited(_) => false,
-                    ExitReason::Stopped => false,
-                    ExitReason::InputClosed => false,
-                    ExitReason::Panicked => true,
-                    ExitReason::Cancelled => false,
-                    // TODO.kevin: Allow to charge new gas? How to charge gas or weather the gas
-                    // system works or not is not clear ATM.
-                    ExitReason::OcallAborted(OcallAborted::GasExhausted) => false,
-                    ExitReason::OcallAborted(OcallAborted::Stifled) => true,
-                    ExitReason::Restore => true,
-                };
-                if !need_restart {
-                    return Ok(());
+        let contract_id = self.contract_id.0;
+        let sidevm_info = match &mut self.sidevm_info {
+            Some(info) => info,
+            None => return Ok(()),
+        };
+        sidevm_info.apply_pending_upgrades(&contract_id);
+        let reason = match &*sidevm_info.handle.lock().unwrap() {
+            SidevmHandle::Terminated(reason) => *reason,
+            SidevmHandle::Running(_) => return Ok(()),
+        };
+        let crashed = match reason {
+            ExitReason::Exited(_) => return Ok(()),
+            ExitReason::Stopped => return Ok(()),
+            ExitReason::InputClosed => return Ok(()),
+            ExitReason::Panicked => true,
+            ExitReason::Cancelled => return Ok(()),
+            // TODO.kevin: Allow to charge new gas? How to charge gas or weather the gas
+            // system works or not is not clear ATM.
+            ExitReason::OcallAborted {
+                reason: OcallAborted::GasExhausted,
+                ..
+            } => return Ok(()),
+            ExitReason::OcallAborted {
+                reason: OcallAborted::Stifled,
+                ..
+            } => true,
+            ExitReason::Restore => false,
+        };
+        let vmid = sidevm::ShortId(&contract_id);
+        if crashed {
+            let restart_at = match sidevm_info.restart_state.next_restart_at {
+                Some(at) => at,
+                None => {
+                    // The crash is observed for the first time.
+                    sidevm_info.record_crash(block_number, reason);
+                    let auto_restart = sidevm_info.auto_restart;
+                    let state = &mut sidevm_info.restart_state;
+                    let restart_at = state.schedule_restart(block_number, auto_restart);
+                    if !auto_restart {
+                        info!(target: "sidevm", "[{vmid}] Sidevm crashed, auto restart disabled");
+                    } else if restart_at == BlockNumber::MAX {
+                        error!(target: "sidevm", "[{vmid}] Sidevm crashed {} times in a row, giving up", state.restarts);
+                    } else {
+                        let delay = restart_at - block_number;
+                        info!(target: "sidevm", "[{vmid}] Sidevm crashed, restarting in {delay} blocks");
+                    }
+                    restart_at
                 }
-                do_start_sidevm(spawner, &sidevm_info.code, self.contract_id.0)?
-            } else {
-                return Ok(());
             };
-            drop(guard);
-            sidevm_info.handle = handle;
+            if block_number < restart_at {
+                return Ok(());
+            }
+            sidevm_info.restart_state.restarts += 1;
         }
+        sidevm_info.handle = sidevm_info.start(spawner, contract_id)?;
+        sidevm_info.restart_state.next_restart_at = None;
+        sidevm_info.restart_state.started_at = block_number;
         Ok(())
     }
 
+    pub(crate) fn sidevm_crash_history(&self) -> Option<SidevmCrashHistory> {
+        self.sidevm_info
+            .as_ref()
+            .map(|info| info.crash_history.clone())
+    }
+
+    /// Replace the program of the sidevm instance with the new code.
+    ///
+    /// See [`SidevmInfo::upgrade`] for how the running instance and the pending upgrades are
+    /// handled.
+    pub(crate) fn upgrade_sidevm(
+        &mut self,
+        spawner: &sidevm::service::Spawner,
+        code: Vec<u8>,
+    ) -> Result<()> {
+        let contract_id = self.contract_id.0;
+        self.sidevm_info
+            .as_mut()
+            .ok_or_else(|| anyhow!("Upgrade sidevm failed, no sidevm instance"))?
+            .upgrade(spawner, contract_id, code)
+    }
+
     pub(crate) fn push_message_to_sidevm(&self, message: Vec<u8>) -> Result<()> {
         let handle = self
             .sidevm_info
@@ -336,6 +726,79 @@ impl FatContract {
         Ok(())
     }
 
+    /// Relay at most `quota` pending outgoing requests emitted by the sidevm instance.
+    ///
+    /// The sidevm instances don't run deterministically, so the requests can not be carried out
+    /// by every worker on its own. Instead, a single worker relays them to the command topic of
+    /// this contract, and every worker carries them out once they are dispatched. See
+    /// [`SidevmRelay`] for which worker relays them. The requests of the other workers are
+    /// dropped.
+    pub(crate) fn flush_sidevm_outgoing_requests(
+        &mut self,
+        quota: usize,
+        relayer: &SidevmRelayer,
+        block_number: BlockNumber,
+    ) {
+        let sidevm_info = match &mut self.sidevm_info {
+            Some(info) => info,
+            None => return,
+        };
+        let vmid = sidevm::ShortId(&self.contract_id.0);
+        let relaying = sidevm_info.relay.accepts(&relayer.worker, block_number);
+        for _ in 0..quota {
+            let request = match sidevm_info.outgoing.rx.try_recv() {
+                Ok(request) => request,
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    error!(target: "sidevm", "[{vmid}] BUG: the outgoing channel is closed");
+                    break;
+                }
+            };
+            if !relaying {
+                debug!(target: "sidevm", "[{vmid}] Dropping outgoing request, not the relay");
+                continue;
+            }
+            debug!(target: "sidevm", "[{vmid}] Relaying outgoing request to the contract");
+            let pubkey = self.ecdh_key.public();
+            SecretMessageChannel::new(relayer.ecdh_key, relayer.egress)
+                .bind_remote_key(Some(&pubkey))
+                .push_message_to(
+                    &super::pink::Command::SidevmOutgoing(request),
+                    command_topic(self.contract_id),
+                );
+        }
+    }
+
+    /// Attach a terminated sidevm instance, returning the sender of its outgoing requests.
+    #[cfg(test)]
+    pub(crate) fn attach_terminated_sidevm(&mut self) -> OutgoingRequestSender {
+        let sidevm_info = SidevmInfo {
+            code: vec![],
+            auto_restart: false,
+            handle: Arc::new(Mutex::new(SidevmHandle::Terminated(ExitReason::Stopped))),
+            outgoing: Default::default(),
+            gas_schedule_version: None,
+            restart_state: Default::default(),
+            crash_history: Default::default(),
+            last_crash: Default::default(),
+            relay: Default::default(),
+            pending_upgrades: Default::default(),
+        };
+        let tx = sidevm_info.outgoing.tx.clone();
+        self.sidevm_info = Some(sidevm_info);
+        tx
+    }
+
+    /// Let another worker claim the relay of the sidevm outgoing requests if the removed worker
+    /// was the relay.
+    pub(crate) fn on_cluster_worker_removed(&mut self, worker: &WorkerPublicKey) {
+        if let Some(sidevm_info) = &mut self.sidevm_info {
+            if sidevm_info.relay.worker.as_ref() == Some(worker) {
+                sidevm_info.relay = Default::default();
+            }
+        }
+    }
+
     pub(crate) fn get_system_message_handler(&self) -> Option<CommandSender> {
         let guard = self.sidevm_info.as_ref()?.handle.lock().unwrap();
         match &*guard {
@@ -364,7 +827,17 @@ fn do_start_sidevm(
     spawner: &sidevm::service::Spawner,
     code: &[u8],
     id: VmId,
+    outgoing_tx: OutgoingRequestSender,
+    gas_schedule_version: Option<u32>,
+    last_crash: Arc<Mutex<Option<CrashInfo>>>,
 ) -> Result<Arc<Mutex<SidevmHandle>>> {
+    let gas_schedule = match gas_schedule_version {
+        Some(version) => Some(
+            GasSchedule::by_version(version)
+                .ok_or_else(|| anyhow!("Unknown gas schedule version {version}"))?,
+        ),
+        None => None,
+    };
     let max_memory_pages: u32 = 1024; // 64MB
     let gas_per_breath = 50_000_000_000_u64; // about 20 ms bench
     let (sender, join_handle) = spawner.start(
@@ -374,6 +847,8 @@ fn do_start_sidevm(
         gas_per_breath,
         local_cache_ops(),
         1, // TODO: set actual weight
+        Some(outgoing_tx),
+        gas_schedule,
     )?;
     let handle = Arc::new(Mutex::new(SidevmHandle::Running(sender)));
     let cloned_handle = handle.clone();
@@ -382,8 +857,16 @@ fn do_start_sidevm(
     info!(target: "sidevm", "[{vmid}] Starting sidevm...");
     spawner.spawn(async move {
         let vmid = sidevm::ShortId(&id);
-        let reason = join_handle.await.unwrap_or(ExitReason::Cancelled);
+        let (reason, crash) = join_handle
+            .await
+            .unwrap_or((ExitReason::Cancelled, None));
         error!(target: "sidevm", "[{vmid}] Sidevm process terminated with reason: {:?}", reason);
+        if let Some(crash) = &crash {
+            if let Some(message) = &crash.panic_message {
+                error!(target: "sidevm", "[{vmid}] Sidevm panicked: {message}");
+            }
+        }
+        *last_crash.lock().unwrap() = crash;
         *cloned_handle.lock().unwrap() = SidevmHandle::Terminated(reason);
     });
     Ok(handle)
@@ -423,3 +906,175 @@ fn local_cache_ops() -> sidevm::DynCacheOps {
 
 pub use keeper::*;
 mod keeper;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sidevm_restart_delay_doubles_up_to_max() {
+        let delays: Vec<_> = (0..12).map(sidevm_restart_delay).collect();
+        assert_eq!(delays, [1, 2, 4, 8, 16, 32, 64, 128, 256, 512, 600, 600]);
+        assert_eq!(sidevm_restart_delay(u32::MAX), SIDEVM_RESTART_BACKOFF_MAX);
+    }
+
+    #[test]
+    fn sidevm_restart_gives_up_after_max_restarts() {
+        let mut state = SidevmRestartState::default();
+        let mut block = 10;
+        for restarts in 0..SIDEVM_MAX_RESTARTS {
+            let restart_at = state.schedule_restart(block, true);
+            assert_eq!(restart_at, block + sidevm_restart_delay(restarts));
+            // Restarted, then crashed again right away
+            state.restarts += 1;
+            state.started_at = restart_at;
+            block = restart_at;
+        }
+        assert_eq!(state.schedule_restart(block, true), BlockNumber::MAX);
+        assert_eq!(state.next_restart_at, Some(BlockNumber::MAX));
+    }
+
+    #[test]
+    fn sidevm_restart_count_resets_after_stable_run() {
+        let mut state = SidevmRestartState {
+            started_at: 100,
+            restarts: SIDEVM_MAX_RESTARTS,
+            next_restart_at: None,
+        };
+        let block = 100 + SIDEVM_STABLE_RUN_BLOCKS - 1;
+        assert_eq!(state.schedule_restart(block, true), BlockNumber::MAX);
+
+        let block = 100 + SIDEVM_STABLE_RUN_BLOCKS;
+        assert_eq!(state.schedule_restart(block, true), block + 1);
+        assert_eq!(state.restarts, 0);
+    }
+
+    #[test]
+    fn sidevm_restart_disabled() {
+        let mut state = SidevmRestartState::default();
+        assert_eq!(state.schedule_restart(10, false), BlockNumber::MAX);
+    }
+
+    /// A sidevm program creating its message channel on the first poll and then idling.
+    fn sidevm_guest(version: u32) -> Vec<u8> {
+        format!(
+            r#"(module
+                (import "env" "sidevm_ocall"
+                    (func $ocall (param i32 i32 i32 i32 i32 i32) (result i64)))
+                (memory (export "memory") 1)
+                (global $version i32 (i32.const {version}))
+                (global $started (mut i32) (i32.const 0))
+                (func (export "sidevm_poll") (result i32)
+                    (if (i32.eqz (global.get $started))
+                        (then
+                            ;; create_input_channel(GeneralMessage)
+                            (drop (call $ocall (i32.const 0) (i32.const 240) (i32.const 2)
+                                (i32.const 0) (i32.const 0) (i32.const 0)))
+                            (global.set $started (i32.const 1))))
+                    (i32.const 0)))"#
+        )
+        .into_bytes()
+    }
+
+    fn sidevm_spawner() -> sidevm::service::Spawner {
+        let (service, spawner) = sidevm::service::service(1);
+        spawner.spawn(service.run(|_| ()));
+        spawner
+    }
+
+    fn running_sidevm(spawner: &sidevm::service::Spawner, id: VmId, code: Vec<u8>) -> SidevmInfo {
+        let mut info = SidevmInfo {
+            code,
+            auto_restart: false,
+            handle: Arc::new(Mutex::new(SidevmHandle::Terminated(ExitReason::Stopped))),
+            outgoing: Default::default(),
+            gas_schedule_version: None,
+            restart_state: Default::default(),
+            crash_history: Default::default(),
+            last_crash: Default::default(),
+            relay: Default::default(),
+            pending_upgrades: Default::default(),
+        };
+        info.handle = info.start(spawner, id).unwrap();
+        info
+    }
+
+    fn is_running(info: &SidevmInfo) -> bool {
+        matches!(&*info.handle.lock().unwrap(), SidevmHandle::Running(_))
+    }
+
+    fn wait_for_upgrades(info: &mut SidevmInfo, id: &VmId) {
+        for _ in 0..500 {
+            info.apply_pending_upgrades(id);
+            if info.pending_upgrades.is_empty() {
+                return;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        panic!("Sidevm upgrade not done in time");
+    }
+
+    #[test]
+    fn sidevm_upgrade_while_running() {
+        let spawner = sidevm_spawner();
+        let id = [1; 32];
+        let mut info = running_sidevm(&spawner, id, sidevm_guest(1));
+        let tx = match &*info.handle.lock().unwrap() {
+            SidevmHandle::Running(tx) => tx.clone(),
+            SidevmHandle::Terminated(_) => panic!("Sidevm not running"),
+        };
+
+        info.upgrade(&spawner, id, sidevm_guest(2)).unwrap();
+        assert_eq!(info.pending_upgrades.len(), 1);
+        // The old code is kept until the new program has been started
+        assert_eq!(info.code, sidevm_guest(1));
+        wait_for_upgrades(&mut info, &id);
+        assert_eq!(info.code, sidevm_guest(2));
+        assert!(is_running(&info));
+        // Upgraded in place, the command channel is still open
+        assert!(!tx.is_closed());
+
+        // A bad program is rejected, keeping the old one running
+        info.upgrade(&spawner, id, b"bad code".to_vec()).unwrap();
+        wait_for_upgrades(&mut info, &id);
+        assert_eq!(info.code, sidevm_guest(2));
+        assert!(is_running(&info));
+    }
+
+    #[test]
+    fn sidevm_upgrade_while_stopped() {
+        let spawner = sidevm_spawner();
+        let id = [2; 32];
+        let mut info = running_sidevm(&spawner, id, sidevm_guest(1));
+        info.handle = Arc::new(Mutex::new(SidevmHandle::Terminated(ExitReason::Stopped)));
+        info.restart_state.restarts = 3;
+
+        // Started with the new code right away
+        info.upgrade(&spawner, id, sidevm_guest(2)).unwrap();
+        assert!(info.pending_upgrades.is_empty());
+        assert_eq!(info.code, sidevm_guest(2));
+        assert!(is_running(&info));
+        assert_eq!(info.restart_state.restarts, 0);
+
+        // The old code is kept if the new one can not be started
+        info.handle = Arc::new(Mutex::new(SidevmHandle::Terminated(ExitReason::Stopped)));
+        assert!(info.upgrade(&spawner, id, b"bad code".to_vec()).is_err());
+        assert_eq!(info.code, sidevm_guest(2));
+        assert!(!is_running(&info));
+    }
+
+    #[test]
+    fn sidevm_upgrade_while_pending() {
+        let spawner = sidevm_spawner();
+        let id = [3; 32];
+        let mut info = running_sidevm(&spawner, id, sidevm_guest(1));
+
+        info.upgrade(&spawner, id, sidevm_guest(2)).unwrap();
+        info.upgrade(&spawner, id, sidevm_guest(3)).unwrap();
+        assert_eq!(info.pending_upgrades.len(), 2);
+        wait_for_upgrades(&mut info, &id);
+        // The last upgrade wins
+        assert_eq!(info.code, sidevm_guest(3));
+        assert!(is_running(&info));
+    }
+}
@@ -6,12 +6,13 @@ use crate::{
     contracts::{
         assets::Assets, balances::Balances, btc_lottery::BtcLottery, btc_price_bot::BtcPriceBot,
         geolocation::Geolocation, guess_number::GuessNumber, pink::Pink, FatContract,
-        NativeContext, NativeContract as _, TransactionError, TransactionResult,
+        NativeContext, NativeContract as _, SidevmRelayer, TransactionError, TransactionResult,
     },
     types::{deopaque_query, OpaqueError, OpaqueQuery, OpaqueReply},
 };
 use parity_scale_codec::{Decode, Encode};
 use phala_mq::{ContractId, MessageOrigin};
+use runtime::BlockNumber;
 
 use super::QueryContext;
 
@@ -121,14 +122,25 @@ impl ContractsKeeper {
         self.0.len()
     }
 
-    pub fn try_restart_sidevms(&mut self, spawner: &Spawner) {
+    pub fn try_restart_sidevms(&mut self, spawner: &Spawner, block_number: BlockNumber) {
         for contract in self.0.values_mut() {
-            if let Err(err) = contract.restart_sidevm_if_needed(spawner) {
+            if let Err(err) = contract.restart_sidevm_if_needed(spawner, block_number) {
                 error!("Failed to restart sidevm instance: {:?}", err);
             }
         }
     }
 
+    pub fn flush_sidevm_outgoing_requests(
+        &mut self,
+        quota_per_contract: usize,
+        relayer: &SidevmRelayer,
+        block_number: BlockNumber,
+    ) {
+        for contract in self.0.values_mut() {
+            contract.flush_sidevm_outgoing_requests(quota_per_contract, relayer, block_number);
+        }
+    }
+
     pub fn remove(&mut self, id: &ContractId) -> Option<FatContract> {
         self.0.remove(id)
     }
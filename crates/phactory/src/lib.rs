@@ -58,7 +58,7 @@ pub use contracts::pink;
 pub use prpc_service::RpcService;
 pub use side_task::SideTaskManager;
 pub use storage::{Storage, StorageExt};
-pub use system::gk;
+pub use system::{gk, ClusterSummary, ContractSummary, System};
 pub use types::BlockInfo;
 
 pub mod benchmark;
@@ -6,8 +6,8 @@ use crate::benchmark::Flags;
 use crate::system::{chain_state, System};
 
 use super::*;
-use chain::pallet_registry::{Attestation, AttestationValidator, IasFields, IasValidator};
-use parity_scale_codec::Encode;
+use chain::pallet_registry::{Attestation, AttestationValidator, SgxQuoteCollateral, SgxValidator};
+use parity_scale_codec::{Decode, Encode};
 use pb::{
     phactory_api_server::{PhactoryApi, PhactoryApiServer},
     server::Error as RpcError,
@@ -43,6 +43,81 @@ fn now() -> u64 {
     now.as_secs()
 }
 
+/// The attestation provider of the IAS reports.
+const PROVIDER_SGX_IAS: &str = "SGX";
+/// The attestation provider of the DCAP quotes.
+const PROVIDER_SGX_DCAP: &str = "SGX_DCAP";
+
+/// Converts the attestation report created by the platform into its RPC representation.
+///
+/// A DCAP attestation carries the quote in `signature` and the SCALE encoded collateral in
+/// `signing_cert`.
+fn attestation_to_pb(report: pal::AttestationReport) -> RpcResult<pb::Attestation> {
+    let (provider, payload) = match report {
+        pal::AttestationReport::SgxIas {
+            ra_report,
+            signature,
+            raw_signing_cert,
+        } => (
+            PROVIDER_SGX_IAS,
+            pb::AttestationReport {
+                report: ra_report,
+                signature: base64::decode(signature).map_err(from_display)?,
+                signing_cert: base64::decode(raw_signing_cert).map_err(from_display)?,
+            },
+        ),
+        pal::AttestationReport::SgxDcap { quote, collateral } => {
+            let collateral = SgxQuoteCollateral {
+                root_ca_crl: collateral.root_ca_crl,
+                pck_crl: collateral.pck_crl,
+                tcb_info_issuer_chain: collateral.tcb_info_issuer_chain,
+                tcb_info: collateral.tcb_info,
+                tcb_info_signature: collateral.tcb_info_signature,
+                qe_identity_issuer_chain: collateral.qe_identity_issuer_chain,
+                qe_identity: collateral.qe_identity,
+                qe_identity_signature: collateral.qe_identity_signature,
+            };
+            (
+                PROVIDER_SGX_DCAP,
+                pb::AttestationReport {
+                    report: String::new(),
+                    signature: quote,
+                    signing_cert: collateral.encode(),
+                },
+            )
+        }
+    };
+    Ok(pb::Attestation {
+        version: 1,
+        provider: provider.to_string(),
+        payload: Some(payload),
+        timestamp: now(),
+    })
+}
+
+/// Converts the attestation received from the RPC into the form accepted by the validators.
+fn attestation_from_pb(attestation: pb::Attestation) -> RpcResult<Attestation> {
+    let payload = attestation
+        .payload
+        .ok_or_else(|| from_display("Missing attestation payload"))?;
+    match attestation.provider.as_str() {
+        PROVIDER_SGX_IAS => Ok(Attestation::SgxIas {
+            ra_report: payload.report.into_bytes(),
+            signature: payload.signature,
+            raw_signing_cert: payload.signing_cert,
+        }),
+        PROVIDER_SGX_DCAP => Ok(Attestation::SgxDcap {
+            quote: payload.signature,
+            collateral: Decode::decode(&mut &payload.signing_cert[..])
+                .map_err(|_| from_display("Invalid DCAP collateral"))?,
+        }),
+        provider => Err(from_display(format!(
+            "Unsupported attestation provider: {}",
+            provider
+        ))),
+    }
+}
+
 impl<Platform: pal::Platform + Serialize + DeserializeOwned> Phactory<Platform> {
     fn runtime_state(&mut self) -> RpcResult<&mut RuntimeState> {
         self.runtime_state
@@ -443,26 +518,16 @@ impl<Platform: pal::Platform + Serialize + DeserializeOwned> Phactory<Platform>
                 info!("Encoded runtime info");
                 info!("{:?}", hex::encode(&cached_resp.encoded_runtime_info));
 
-                let (attn_report, sig, cert) =
-                    match self.platform.create_attestation_report(&runtime_info_hash) {
-                        Ok(r) => r,
-                        Err(e) => {
-                            let message = format!("Failed to create attestation report: {:?}", e);
-                            error!("{}", message);
-                            return Err(from_display(message));
-                        }
-                    };
+                let report = match self.platform.create_attestation_report(&runtime_info_hash) {
+                    Ok(r) => r,
+                    Err(e) => {
+                        let message = format!("Failed to create attestation report: {:?}", e);
+                        error!("{}", message);
+                        return Err(from_display(message));
+                    }
+                };
 
-                cached_resp.attestation = Some(pb::Attestation {
-                    version: 1,
-                    provider: "SGX".to_string(),
-                    payload: Some(pb::AttestationReport {
-                        report: attn_report,
-                        signature: base64::decode(sig).map_err(from_display)?,
-                        signing_cert: base64::decode(cert).map_err(from_display)?,
-                    }),
-                    timestamp: now(),
-                });
+                cached_resp.attestation = Some(attestation_to_pb(report)?);
             }
         }
         Ok(cached_resp.clone())
@@ -844,7 +909,7 @@ impl<Platform: pal::Platform> RpcService<Platform> {
 
     fn create_attestation_report_on(&self, data: &[u8]) -> RpcResult<pb::Attestation> {
         let phactory = self.lock_phactory();
-        let (attn_report, sig, cert) = match phactory.platform.create_attestation_report(&data) {
+        let report = match phactory.platform.create_attestation_report(&data) {
             Ok(r) => r,
             Err(e) => {
                 let message = format!("Failed to create attestation report: {:?}", e);
@@ -852,16 +917,7 @@ impl<Platform: pal::Platform> RpcService<Platform> {
                 return Err(from_display(message));
             }
         };
-        Ok(pb::Attestation {
-            version: 1,
-            provider: "SGX".to_string(),
-            payload: Some(pb::AttestationReport {
-                report: attn_report,
-                signature: base64::decode(sig).map_err(from_display)?,
-                signing_cert: base64::decode(cert).map_err(from_display)?,
-            }),
-            timestamp: now(),
-        })
+        attestation_to_pb(report)
     }
 }
 
@@ -1027,6 +1083,8 @@ impl<Platform: pal::Platform + Serialize + DeserializeOwned> PhactoryApi for Rpc
     ) -> RpcResult<pb::HandoverWorkerKey> {
         let mut phactory = self.lock_phactory();
         let dev_mode = phactory.dev_mode;
+        let dcap_root_certs =
+            chain_state::dcap_trusted_root_certs(&phactory.runtime_state()?.chain_storage);
         let system = phactory.system()?;
         let my_identity_key = system.identity_key.clone();
 
@@ -1042,19 +1100,19 @@ impl<Platform: pal::Platform + Serialize + DeserializeOwned> PhactoryApi for Rpc
             let raw_attestation = request
                 .attestation
                 .ok_or_else(|| from_display("Attestation not found"))?;
-            let payload = raw_attestation
-                .payload
-                .ok_or_else(|| from_display("Missing attestation payload"))?;
-            let attn_to_validate = Attestation::SgxIas {
-                ra_report: payload.report.as_bytes().to_vec(),
-                signature: payload.signature,
-                raw_signing_cert: payload.signing_cert,
-            };
+            let attn_to_validate = attestation_from_pb(raw_attestation)?;
             // The time from attestation report is generated by IAS, thus trusted. By default, it's valid for 10h.
             // By ensuring our system timestamp is within the valid period, we know that this pRuntime is not hold back by
             // malicious workers.
-            IasValidator::validate(&attn_to_validate, &payload_hash, now_ms, false, vec![])
-                .map_err(|_| from_display("Invalid RA report"))?;
+            SgxValidator::validate(
+                &attn_to_validate,
+                &payload_hash,
+                now_ms / 1000,
+                false,
+                vec![],
+                dcap_root_certs,
+            )
+            .map_err(|_| from_display("Invalid RA report"))?;
             Some(attn_to_validate)
         };
         // 2. verify challenge validity to prevent replay attack
@@ -1078,31 +1136,18 @@ impl<Platform: pal::Platform + Serialize + DeserializeOwned> PhactoryApi for Rpc
                 .attestation
                 .as_ref()
                 .ok_or_else(|| from_display("My attestation not found"))?;
-            let my_attn_report = my_attn
-                .payload
-                .as_ref()
-                .ok_or_else(|| from_display("My RA report not found"))?;
-            let (my_ias_fields, _) =
-                IasFields::from_ias_report(&my_attn_report.report.as_bytes().to_vec())
-                    .map_err(|_| from_display("Invalid RA report"))?;
-            let my_mrenclave = my_ias_fields.extend_mrenclave();
+            let my_mrenclave = attestation_from_pb(my_attn.clone())?
+                .extend_mrenclave()
+                .map_err(|_| from_display("Invalid RA report"))?;
             let runtime_state = phactory.runtime_state()?;
             let my_runtime_timestamp =
                 chain_state::get_pruntime_added_at(&runtime_state.chain_storage, &my_mrenclave)
                     .ok_or_else(|| from_display("Key handover not supported in this pRuntime"))?;
 
             let attestation = attestation.ok_or_else(|| from_display("Attestation not found"))?;
-            let mrenclave = match attestation {
-                Attestation::SgxIas {
-                    ra_report,
-                    signature: _,
-                    raw_signing_cert: _,
-                } => {
-                    let (ias_fields, _) = IasFields::from_ias_report(&ra_report)
-                        .map_err(|_| from_display("Invalid received RA report"))?;
-                    ias_fields.extend_mrenclave()
-                }
-            };
+            let mrenclave = attestation
+                .extend_mrenclave()
+                .map_err(|_| from_display("Invalid received RA report"))?;
             let req_runtime_timestamp =
                 chain_state::get_pruntime_added_at(&runtime_state.chain_storage, &mrenclave)
                     .ok_or_else(|| from_display("Unknown target pRuntime version"))?;
@@ -1196,16 +1241,22 @@ impl<Platform: pal::Platform + Serialize + DeserializeOwned> PhactoryApi for Rpc
             let raw_attestation = request
                 .attestation
                 .ok_or_else(|| from_display("Attestation not found"))?;
-            let payload = raw_attestation
-                .payload
-                .ok_or_else(|| from_display("Missing attestation payload"))?;
-            let attn_to_validate = Attestation::SgxIas {
-                ra_report: payload.report.as_bytes().to_vec(),
-                signature: payload.signature,
-                raw_signing_cert: payload.signing_cert,
-            };
-            IasValidator::validate(&attn_to_validate, &worker_key_hash, now(), false, vec![])
-                .map_err(|_| from_display("Invalid RA report"))?;
+            let attn_to_validate = attestation_from_pb(raw_attestation)?;
+            // A pRuntime receiving the key usually hasn't synced the chain yet, thus has no trusted
+            // DCAP root certs and only accepts the keys from an IAS attested pRuntime.
+            let dcap_root_certs = phactory
+                .runtime_state()
+                .map(|state| chain_state::dcap_trusted_root_certs(&state.chain_storage))
+                .unwrap_or_default();
+            SgxValidator::validate(
+                &attn_to_validate,
+                &worker_key_hash,
+                now(),
+                false,
+                vec![],
+                dcap_root_certs,
+            )
+            .map_err(|_| from_display("Invalid RA report"))?;
         } else {
             info!("Skip RA report check in dev mode");
         }
@@ -164,6 +164,9 @@ mod receiver {
         receiver: TypedReceiver<Wrp>,
         peeler: Plr,
         _msg: PhantomData<Msg>,
+        /// The number of messages failed to decrypt.
+        #[serde(default)]
+        decrypt_failures: u64,
     }
 
     impl<Msg, Wrp> PeelingReceiver<Msg, Wrp, PlainPeeler<Msg>> {
@@ -173,6 +176,7 @@ mod receiver {
                 receiver,
                 peeler: PlainPeeler(Default::default()),
                 _msg: Default::default(),
+                decrypt_failures: 0,
             }
         }
     }
@@ -183,6 +187,7 @@ mod receiver {
                 receiver,
                 peeler: SecretPeeler::new(ecdh_key),
                 _msg: Default::default(),
+                decrypt_failures: 0,
             }
         }
     }
@@ -212,6 +217,7 @@ mod receiver {
                     }
                 }
                 Err(PeelError::CryptoError) => {
+                    self.decrypt_failures += 1;
                     bail!("Failed to decrypt the mq message");
                 }
             };
@@ -221,6 +227,10 @@ mod receiver {
         pub fn peek_ind(&self) -> Result<Option<u64>, ReceiveError> {
             self.receiver.peek_ind()
         }
+
+        pub fn decrypt_failures(&self) -> u64 {
+            self.decrypt_failures
+        }
     }
 }
 
@@ -1,4 +1,7 @@
-use super::{RotatedMasterKey, TransactionError, TypedReceiver, WorkerState};
+use super::{
+    master_key::{self, MasterKeyShare, PendingRotation},
+    RotatedMasterKey, TransactionError, TypedReceiver, WorkerState,
+};
 use chain::pallet_fat::ClusterRegistryEvent;
 use chain::pallet_registry::GatekeeperRegistryEvent;
 use phala_crypto::{
@@ -13,12 +16,11 @@ use phala_types::{
         ContractClusterId,
     },
     messaging::{
-        BatchRotateMasterKeyEvent, DispatchMasterKeyHistoryEvent, EncryptedKey, GatekeeperEvent,
-        KeyDistribution, MessageOrigin, MiningInfoUpdateEvent, MiningReportEvent, RandomNumber,
-        RandomNumberEvent, RotateMasterKeyEvent, SettleInfo, SystemEvent, WorkerEvent,
-        WorkerEventWithKey,
+        EncryptedKey, GatekeeperEvent, MessageOrigin, MiningInfoUpdateEvent, MiningReportEvent,
+        RandomNumber, RandomNumberEvent, RotateMasterKeyEvent, SettleInfo, SystemEvent,
+        WorkerEvent, WorkerEventWithKey,
     },
-    EcdhPublicKey, WorkerPublicKey,
+    EcdhPublicKey, WorkerIdentity, WorkerPublicKey,
 };
 use serde::{Deserialize, Serialize};
 use sp_core::{hashing, sr25519, Pair};
@@ -40,8 +42,6 @@ pub use tokenomic::{FixedPoint, TokenomicInfo};
 /// WARNING: this interval need to be large enough considering the latency of mq
 const VRF_INTERVAL: u32 = 5;
 
-const MASTER_KEY_SHARING_SALT: &[u8] = b"master_key_sharing";
-
 // pesudo_random_number = blake2_256(last_random_number, block_number, derived_master_key)
 //
 // NOTICE: we abandon the random number involving master key signature, since the malleability of sr25519 signature
@@ -108,6 +108,14 @@ impl WorkerInfo {
     pub fn pubkey(&self) -> &WorkerPublicKey {
         &self.state.pubkey
     }
+
+    pub fn unresponsive(&self) -> bool {
+        self.unresponsive
+    }
+
+    pub fn mining(&self) -> bool {
+        self.state.mining_state.is_some()
+    }
 }
 
 #[derive(Serialize, Deserialize)]
@@ -121,6 +129,9 @@ pub(crate) struct Gatekeeper<MsgChan> {
     registered_on_chain: bool,
     #[serde(with = "more::scale_bytes")]
     master_key_history: Vec<RotatedMasterKey>,
+    /// The master key rotation waiting for the contributions of the gatekeepers
+    #[serde(default, with = "more::scale_bytes")]
+    pending_rotation: Option<PendingRotation>,
     egress: MsgChan, // TODO.kevin: syncing the egress state while migrating.
     gatekeeper_events: TypedReceiver<GatekeeperEvent>,
     cluster_events: TypedReceiver<ClusterEvent>,
@@ -152,6 +163,7 @@ where
             master_pubkey_on_chain: false,
             registered_on_chain: false,
             master_key_history,
+            pending_rotation: None,
             egress: egress.clone(),
             gatekeeper_events: recv_mq.subscribe_bound(),
             cluster_events: recv_mq.subscribe_bound(),
@@ -266,108 +278,53 @@ where
         true
     }
 
-    pub fn share_master_key(
-        &mut self,
-        pubkey: &WorkerPublicKey,
-        ecdh_pubkey: &EcdhPublicKey,
-        block_number: chain::BlockNumber,
-    ) {
-        if self.master_key_history.len() > 1 {
-            self.share_master_key_history(pubkey, ecdh_pubkey, block_number);
-        } else {
-            self.share_latest_master_key(pubkey, ecdh_pubkey, block_number);
-        }
-    }
-
-    pub fn share_latest_master_key(
-        &mut self,
-        pubkey: &WorkerPublicKey,
-        ecdh_pubkey: &EcdhPublicKey,
-        block_number: chain::BlockNumber,
-    ) {
-        info!("Gatekeeper: try dispatch master key");
-        let master_key = self.master_key.dump_secret_key();
-        let encrypted_key = self.encrypt_key_to(
-            &[MASTER_KEY_SHARING_SALT],
-            ecdh_pubkey,
-            &master_key,
-            block_number,
-        );
-        self.egress.push_message(
-            &KeyDistribution::<chain::BlockNumber>::master_key_distribution(
-                *pubkey,
-                encrypted_key.ecdh_pubkey,
-                encrypted_key.encrypted_key,
-                encrypted_key.iv,
-            ),
-        );
-    }
-
-    pub fn share_master_key_history(
-        &mut self,
-        pubkey: &WorkerPublicKey,
-        ecdh_pubkey: &EcdhPublicKey,
-        block_number: chain::BlockNumber,
-    ) {
-        info!("Gatekeeper: try dispatch all historical master keys");
-        let encrypted_master_key_history = self
-            .master_key_history
-            .clone()
+    /// Compute the share at `index` of each historical master key for the gatekeeper `dest` registered at
+    /// `registered_at`
+    pub fn master_key_shares(
+        &self,
+        dest: &WorkerPublicKey,
+        registered_at: chain::BlockNumber,
+        threshold: u8,
+        index: u8,
+    ) -> Vec<MasterKeyShare> {
+        self.master_key_history
             .iter()
-            .map(|key| {
-                (
-                    key.rotation_id,
-                    key.block_height,
-                    self.encrypt_key_to(
-                        &[MASTER_KEY_SHARING_SALT],
-                        ecdh_pubkey,
-                        &key.secret,
-                        block_number,
-                    ),
-                )
-            })
-            .collect();
-        self.egress.push_message(&KeyDistribution::MasterKeyHistory(
-            DispatchMasterKeyHistoryEvent {
-                dest: pubkey.clone(),
-                encrypted_master_key_history,
-            },
-        ));
+            .map(|key| master_key::share_at(key, dest, registered_at, threshold, index))
+            .collect()
     }
 
-    pub fn process_master_key_rotation_request(
+    /// Wait for the contributions of the gatekeepers in the rotation
+    ///
+    /// The progress is kept if the rotation is requested again, return the pending rotation.
+    pub fn start_master_key_rotation(&mut self, event: &RotateMasterKeyEvent) -> &PendingRotation {
+        let pending = self
+            .pending_rotation
+            .as_ref()
+            .map(|rotation| rotation.rotation_id);
+        if pending != Some(event.rotation_id) {
+            let gatekeepers = event.gk_identities.iter().map(|gk| gk.pubkey).collect();
+            self.pending_rotation = Some(PendingRotation::new(event.rotation_id, gatekeepers));
+        }
+        self.pending_rotation.as_ref().expect("set above; qed.")
+    }
+
+    /// Record a chunk of contribution to the pending master key rotation, return the rotated master key once there are
+    /// enough contributions
+    pub fn add_rotation_contribution(
         &mut self,
-        block: &BlockInfo,
-        event: RotateMasterKeyEvent,
-        identity_key: sr25519::Pair,
-    ) {
-        let new_master_key = crate::new_sr25519_key();
-        let secret_key = new_master_key.dump_secret_key();
-        let secret_keys: BTreeMap<_, _> = event
-            .gk_identities
-            .into_iter()
-            .map(|gk_identity| {
-                let encrypted_key = self.encrypt_key_to(
-                    &[MASTER_KEY_SHARING_SALT],
-                    &gk_identity.ecdh_pubkey,
-                    &secret_key,
-                    block.block_number,
-                );
-                (gk_identity.pubkey, encrypted_key)
-            })
-            .collect();
-        let mut event = BatchRotateMasterKeyEvent {
-            rotation_id: event.rotation_id,
-            secret_keys,
-            sender: identity_key.public(),
-            sig: vec![],
-        };
-        let data_to_sign = event.data_be_signed();
-        event.sig = identity_key.sign(&data_to_sign).0.to_vec();
-        self.egress
-            .push_message(&KeyDistribution::<chain::BlockNumber>::MasterKeyRotation(
-                event,
-            ));
+        rotation_id: u64,
+        sender: WorkerPublicKey,
+        chunk: u32,
+        chunks: u32,
+        contribution: Option<Sr25519SecretKey>,
+    ) -> Option<sr25519::Pair> {
+        let rotation = self
+            .pending_rotation
+            .as_mut()
+            .filter(|rotation| rotation.rotation_id == rotation_id)?;
+        let new_master_key = rotation.contribute(sender, chunk, chunks, contribution)?;
+        self.pending_rotation = None;
+        Some(new_master_key)
     }
 
     pub fn will_process_block(&mut self, block: &BlockInfo<'_>) {
@@ -431,6 +388,9 @@ where
             GatekeeperEvent::UnrespFix => {
                 // Handled by MiningEconomics
             }
+            GatekeeperEvent::MasterKeyShare(_) | GatekeeperEvent::MasterKeyContribution(_) => {
+                // Handled by System
+            }
         }
     }
 
@@ -487,32 +447,63 @@ where
                     });
                 // then distribute cluster key to all workers in one event
                 // the on-chain deployment state should be updated by assigned workers
-                // TODO.shelven: set up expiration
-                let secret_key = cluster_key.dump_secret_key();
-                let secret_keys: BTreeMap<_, _> = workers
-                    .into_iter()
-                    .map(|worker| {
-                        let encrypted_key = self.encrypt_key_to(
-                            &[b"cluster_key_sharing"],
-                            &worker.ecdh_pubkey,
-                            &secret_key,
-                            block.block_number,
-                        );
-                        (worker.pubkey, encrypted_key)
+                self.dispatch_cluster_key(block, cluster, &cluster_key, workers);
+                Ok(())
+            }
+            ClusterEvent::AddWorkers {
+                cluster,
+                pubkey,
+                workers,
+            } => {
+                if !origin.is_pallet() {
+                    error!("Attempt to add cluster workers from bad origin");
+                    return Err(TransactionError::BadOrigin);
+                }
+
+                // The cluster key is derived from the master key in use at the time the cluster
+                // was deployed, which may have been rotated since then.
+                let cluster_key = self
+                    .master_key_history
+                    .iter()
+                    .rev()
+                    .map(|key| {
+                        let master_key = sr25519::Pair::restore_from_secret_key(&key.secret);
+                        get_cluster_key(&master_key, &cluster)
                     })
-                    .collect();
-                self.egress.push_message(
-                    &ClusterOperation::<chain::AccountId, _>::batch_distribution(
-                        secret_keys,
-                        cluster,
-                        0,
-                    ),
-                );
+                    .find(|key| key.public() == pubkey)
+                    .ok_or(TransactionError::UnknownClusterKey)?;
+                self.dispatch_cluster_key(block, cluster, &cluster_key, workers);
                 Ok(())
             }
         }
     }
 
+    fn dispatch_cluster_key(
+        &mut self,
+        block: &BlockInfo<'_>,
+        cluster: ContractClusterId,
+        cluster_key: &sr25519::Pair,
+        workers: Vec<WorkerIdentity>,
+    ) {
+        // TODO.shelven: set up expiration
+        let secret_key = cluster_key.dump_secret_key();
+        let secret_keys: BTreeMap<_, _> = workers
+            .into_iter()
+            .map(|worker| {
+                let encrypted_key = self.encrypt_key_to(
+                    &[b"cluster_key_sharing"],
+                    &worker.ecdh_pubkey,
+                    &secret_key,
+                    block.block_number,
+                );
+                (worker.pubkey, encrypted_key)
+            })
+            .collect();
+        self.egress.push_message(
+            &ClusterOperation::<chain::AccountId, _>::batch_distribution(secret_keys, cluster, 0),
+        );
+    }
+
     /// Verify on-chain random number
     fn process_random_number_event(&mut self, origin: MessageOrigin, event: RandomNumberEvent) {
         if !origin.is_gatekeeper() {
@@ -733,6 +724,10 @@ impl<MsgChan: MessageChannel<Signer = Sr25519Signer>> MiningEconomics<MsgChan> {
         self.workers.get(pubkey).map(Into::into)
     }
 
+    pub fn workers(&self) -> impl Iterator<Item = &WorkerInfo> {
+        self.workers.values()
+    }
+
     pub fn will_process_block(&mut self, block: &BlockInfo<'_>) {
         let sum_share = self.sum_share();
         let report = MiningInfoUpdateEvent::new(block.block_number, block.now_ms);
@@ -1169,6 +1164,9 @@ impl<MsgChan: MessageChannel<Signer = Sr25519Signer>> MiningEconomics<MsgChan> {
                     self.unresp_fix = true;
                 }
             }
+            GatekeeperEvent::MasterKeyShare(_) | GatekeeperEvent::MasterKeyContribution(_) => {
+                // Handled by System.
+            }
         }
     }
 
@@ -1,10 +1,16 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::convert::{TryFrom, TryInto};
 use std::path::PathBuf;
 use std::vec::Vec;
 
 use parity_scale_codec::{Decode, Encode};
-use sp_core::sr25519;
+use phala_types::WorkerPublicKey;
+use sp_core::{hashing, sr25519, Pair};
 
-use phala_crypto::sr25519::{Signature, Signing, Sr25519SecretKey};
+use phala_crypto::{
+    sr25519::{Signature, Signing, Sr25519SecretKey},
+    threshold::{self, KeyShare},
+};
 
 use crate::pal::Sealing;
 
@@ -24,6 +30,314 @@ pub struct RotatedMasterKey {
     pub secret: Sr25519SecretKey,
 }
 
+/// The share of a master key, where `secret` holds the share data instead of the key itself
+pub type MasterKeyShare = RotatedMasterKey;
+
+/// An ongoing master key rotation collecting the contributions of the gatekeepers
+///
+/// A contribution is split into chunks, and it only counts once all of its chunks are on chain.
+/// Since all the gatekeepers process the chain messages in the same order, they agree on the
+/// contributions deriving the rotated master key even if some chunks are missed and resent later.
+#[derive(Debug, Encode, Decode, Clone)]
+pub struct PendingRotation {
+    pub rotation_id: u64,
+    /// The gatekeepers taking part in the rotation
+    pub gatekeepers: Vec<WorkerPublicKey>,
+    /// The number of chunks and the received chunk indexes of each sender
+    pub received_chunks: BTreeMap<WorkerPublicKey, (u32, BTreeSet<u32>)>,
+    /// The senders with all their chunks received, in the order of completion
+    pub completed: Vec<WorkerPublicKey>,
+    /// The contributions to this gatekeeper
+    pub contributions: BTreeMap<WorkerPublicKey, Sr25519SecretKey>,
+}
+
+impl PendingRotation {
+    pub fn new(rotation_id: u64, gatekeepers: Vec<WorkerPublicKey>) -> Self {
+        Self {
+            rotation_id,
+            gatekeepers,
+            received_chunks: Default::default(),
+            completed: Default::default(),
+            contributions: Default::default(),
+        }
+    }
+
+    /// The chunks of the contribution of `sender` not received yet
+    pub fn missing_chunks(&self, sender: &WorkerPublicKey, chunks: u32) -> Vec<u32> {
+        match self.received_chunks.get(sender) {
+            Some((total, received)) if *total == chunks => {
+                (0..chunks).filter(|i| !received.contains(i)).collect()
+            }
+            _ => (0..chunks).collect(),
+        }
+    }
+
+    /// Record a chunk of the contribution of `sender`, return the rotated master key once there
+    /// are enough complete contributions
+    ///
+    /// `contribution` is `None` if the chunk does not cover this gatekeeper. Only the first
+    /// contribution of each gatekeeper counts.
+    pub fn contribute(
+        &mut self,
+        sender: WorkerPublicKey,
+        chunk: u32,
+        chunks: u32,
+        contribution: Option<Sr25519SecretKey>,
+    ) -> Option<sr25519::Pair> {
+        if !self.gatekeepers.contains(&sender) || chunk >= chunks {
+            warn!("Ignored master key contribution from {:?}", sender);
+            return None;
+        }
+        if self.completed.contains(&sender) {
+            return None;
+        }
+        if let Some(contribution) = contribution {
+            self.contributions.entry(sender).or_insert(contribution);
+        }
+        let (total, received) = self
+            .received_chunks
+            .entry(sender)
+            .or_insert_with(|| (chunks, Default::default()));
+        if *total != chunks {
+            // The sender resent with another chunk size, start over
+            *total = chunks;
+            received.clear();
+        }
+        received.insert(chunk);
+        if received.len() < chunks as usize {
+            return None;
+        }
+        self.completed.push(sender);
+
+        let threshold = share_threshold(self.gatekeepers.len());
+        if self.completed.len() < threshold {
+            return None;
+        }
+        let mut contributions = BTreeMap::new();
+        for sender in &self.completed[..threshold] {
+            match self.contributions.get(sender) {
+                Some(contribution) => {
+                    contributions.insert(*sender, *contribution);
+                }
+                None => {
+                    error!(
+                        "No master key contribution to this gatekeeper from {:?}",
+                        sender
+                    );
+                    return None;
+                }
+            }
+        }
+        let seed = hashing::blake2_256(&(self.rotation_id, &contributions).encode());
+        Some(sr25519::Pair::from_seed(&seed))
+    }
+}
+
+/// The number of gatekeepers needed to recover or rotate the master key, a majority of `holders`
+pub fn share_threshold(holders: usize) -> usize {
+    holders / 2 + 1
+}
+
+/// Compute the share at `index` of `master_key` for the gatekeeper `dest` registered at `registered_at`
+///
+/// The sharing polynomials are derived from the master key, so the shares computed by different
+/// gatekeepers are compatible with each other. The registration block is mixed in to draw new
+/// polynomials each time `dest` is registered.
+pub fn share_at(
+    master_key: &RotatedMasterKey,
+    dest: &WorkerPublicKey,
+    registered_at: chain::BlockNumber,
+    threshold: u8,
+    index: u8,
+) -> MasterKeyShare {
+    let seed = hashing::blake2_256(
+        &(
+            b"master_key_share",
+            master_key.secret,
+            dest,
+            registered_at,
+            threshold,
+        )
+            .encode(),
+    );
+    let fill_coefficients = |coefficients: &mut [u8]| {
+        for (i, chunk) in coefficients.chunks_mut(32).enumerate() {
+            let block = hashing::blake2_256(&(seed, i as u32).encode());
+            chunk.copy_from_slice(&block[..chunk.len()]);
+        }
+    };
+    let share =
+        threshold::split_secret_at(&master_key.secret, threshold, &[index], fill_coefficients)
+            .expect("should never fail with valid index; qed.")
+            .pop()
+            .expect("one share is split; qed.");
+    MasterKeyShare {
+        rotation_id: master_key.rotation_id,
+        block_height: master_key.block_height,
+        secret: share
+            .data
+            .try_into()
+            .expect("share has the same length with the secret; qed."),
+    }
+}
+
+/// Recover the master key history from the shares of each gatekeeper indexed by the share index
+///
+/// Return `None` if the shares do not agree on the rotations.
+pub fn combine_shares(shares: &[(u8, &Vec<MasterKeyShare>)]) -> Option<Vec<RotatedMasterKey>> {
+    let (_, first) = shares.first()?;
+    if shares
+        .iter()
+        .any(|(_, history)| history.len() != first.len())
+    {
+        return None;
+    }
+    let mut master_key_history = Vec::new();
+    for (i, rotation) in first.iter().enumerate() {
+        let mut key_shares = Vec::new();
+        for (index, history) in shares {
+            let share = history.get(i)?;
+            if share.rotation_id != rotation.rotation_id
+                || share.block_height != rotation.block_height
+            {
+                return None;
+            }
+            key_shares.push(KeyShare {
+                index: *index,
+                data: share.secret.to_vec(),
+            });
+        }
+        let secret = threshold::combine_shares(&key_shares).ok()?;
+        master_key_history.push(RotatedMasterKey {
+            rotation_id: rotation.rotation_id,
+            block_height: rotation.block_height,
+            secret: secret.try_into().ok()?,
+        });
+    }
+    Some(master_key_history)
+}
+
+/// The master key history being recovered by a newly-registered gatekeeper
+///
+/// The holders are the gatekeepers on chain when this gatekeeper is registered, in their on-chain
+/// order, which is also how they pick their share indexes. So the share at `index` is only taken
+/// from `holders[index - 1]`, and a holder can neither send the share of another one nor send more
+/// than one share.
+#[derive(Debug, Encode, Decode, Clone)]
+pub struct MasterKeyRecovery {
+    holders: Vec<WorkerPublicKey>,
+    shares: BTreeMap<u8, Vec<MasterKeyShare>>,
+}
+
+impl MasterKeyRecovery {
+    pub fn new(holders: Vec<WorkerPublicKey>) -> Self {
+        Self {
+            holders,
+            shares: Default::default(),
+        }
+    }
+
+    /// The number of shares needed to recover the master key history
+    pub fn threshold(&self) -> usize {
+        share_threshold(self.holders.len())
+    }
+
+    /// The share index of `holder`, starting from 1
+    pub fn index_of(&self, holder: &WorkerPublicKey) -> Option<u8> {
+        let position = self.holders.iter().position(|gk| gk == holder)?;
+        u8::try_from(position + 1).ok()
+    }
+
+    /// Record the shares from `sender`, return the master key history once it is recovered
+    ///
+    /// The recovered master key must match `master_pubkey` on chain. Each subset of `threshold`
+    /// shares including the new one is tried until one matches, since a faulty holder may send a
+    /// broken share. The subsets without the new share have been tried when the previous shares
+    /// arrived. Without `master_pubkey` the first subset is taken.
+    pub fn add_shares(
+        &mut self,
+        sender: &WorkerPublicKey,
+        index: u8,
+        threshold: u8,
+        shares: Vec<MasterKeyShare>,
+        master_pubkey: Option<&sr25519::Public>,
+    ) -> Option<Vec<RotatedMasterKey>> {
+        if self.index_of(sender) != Some(index) || threshold as usize != self.threshold() {
+            warn!(
+                "Ignored master key share {} of threshold {} from {:?}",
+                index, threshold, sender
+            );
+            return None;
+        }
+        if self.shares.contains_key(&index) {
+            return None;
+        }
+        self.shares.insert(index, shares);
+
+        let others: Vec<u8> = self
+            .shares
+            .keys()
+            .copied()
+            .filter(|i| *i != index)
+            .collect();
+        find_combination(&others, self.threshold() - 1, |picked| {
+            let received: Vec<_> = picked
+                .iter()
+                .chain(core::iter::once(&index))
+                .map(|i| (*i, &self.shares[i]))
+                .collect();
+            let history = combine_shares(&received)?;
+            match (master_pubkey, history.last()) {
+                (None, _) => Some(history),
+                (Some(pubkey), Some(latest)) => {
+                    let pair = sr25519::Pair::from_seed_slice(&latest.secret).ok()?;
+                    if pair.public() == *pubkey {
+                        Some(history)
+                    } else {
+                        warn!(
+                            "Master key recovered from shares {:?} and {} mismatches",
+                            picked, index
+                        );
+                        None
+                    }
+                }
+                (Some(_), None) => None,
+            }
+        })
+    }
+}
+
+/// Call `f` with the `k`-combinations of `items` in lexicographic order until it returns `Some`
+fn find_combination<T: Copy, R>(
+    items: &[T],
+    k: usize,
+    mut f: impl FnMut(&[T]) -> Option<R>,
+) -> Option<R> {
+    let n = items.len();
+    if k > n {
+        return None;
+    }
+    let mut picked: Vec<usize> = (0..k).collect();
+    loop {
+        let combination: Vec<T> = picked.iter().map(|&i| items[i]).collect();
+        if let Some(result) = f(&combination) {
+            return Some(result);
+        }
+        // Move the rightmost position not at its end yet, and reset the ones after it
+        let mut i = k;
+        while i > 0 && picked[i - 1] == i - 1 + n - k {
+            i -= 1;
+        }
+        if i == 0 {
+            return None;
+        }
+        picked[i - 1] += 1;
+        for j in i..k {
+            picked[j] = picked[j - 1] + 1;
+        }
+    }
+}
+
 #[derive(Debug, Encode, Decode, Clone)]
 struct MasterKeyHistory {
     rotations: Vec<RotatedMasterKey>,
@@ -115,3 +429,286 @@ pub fn try_unseal(
 
     secrets
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use phala_crypto::sr25519::Persistence;
+
+    fn master_key_history() -> Vec<RotatedMasterKey> {
+        (0..3)
+            .map(|i| RotatedMasterKey {
+                rotation_id: i,
+                block_height: i as chain::BlockNumber * 10,
+                secret: sr25519::Pair::from_seed(&[i as u8; 32]).dump_secret_key(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn recover_master_key_history_from_shares() {
+        let history = master_key_history();
+        let dest = WorkerPublicKey::from_raw([1u8; 32]);
+        let threshold = share_threshold(4) as u8;
+        // Each gatekeeper computes the share at its own index
+        let shares: Vec<(u8, Vec<MasterKeyShare>)> = (1..=4)
+            .map(|index| {
+                let shares = history
+                    .iter()
+                    .map(|key| share_at(key, &dest, 1, threshold, index))
+                    .collect();
+                (index, shares)
+            })
+            .collect();
+        let received: Vec<_> = shares
+            .iter()
+            .skip(1)
+            .take(threshold as usize)
+            .map(|(index, shares)| (*index, shares))
+            .collect();
+        assert_eq!(combine_shares(&received), Some(history.clone()));
+        // A single share reveals nothing
+        assert_ne!(shares[0].1[0].secret, history[0].secret);
+        // Shares for another gatekeeper are not compatible
+        let other = WorkerPublicKey::from_raw([2u8; 32]);
+        let other_shares: Vec<MasterKeyShare> = history
+            .iter()
+            .map(|key| share_at(key, &other, 1, threshold, 1))
+            .collect();
+        let mixed = vec![(1, &other_shares), (2, &shares[1].1), (3, &shares[2].1)];
+        assert_ne!(combine_shares(&mixed), Some(history.clone()));
+        // Nor are the shares for a former registration of the same gatekeeper
+        let stale_shares: Vec<MasterKeyShare> = history
+            .iter()
+            .map(|key| share_at(key, &dest, 2, threshold, 1))
+            .collect();
+        assert_ne!(stale_shares, shares[0].1);
+    }
+
+    fn holders(n: u8) -> Vec<WorkerPublicKey> {
+        (1..=n)
+            .map(|i| WorkerPublicKey::from_raw([i; 32]))
+            .collect()
+    }
+
+    fn shares_at(history: &[RotatedMasterKey], threshold: u8, index: u8) -> Vec<MasterKeyShare> {
+        let dest = WorkerPublicKey::from_raw([0u8; 32]);
+        history
+            .iter()
+            .map(|key| share_at(key, &dest, 1, threshold, index))
+            .collect()
+    }
+
+    fn master_pubkey(history: &[RotatedMasterKey]) -> sr25519::Public {
+        sr25519::Pair::restore_from_secret_key(&history.last().unwrap().secret).public()
+    }
+
+    #[test]
+    fn recovery_binds_shares_to_senders() {
+        let history = master_key_history();
+        let holders = holders(4);
+        let pubkey = master_pubkey(&history);
+        let mut recovery = MasterKeyRecovery::new(holders.clone());
+        assert_eq!(recovery.threshold(), 3);
+        assert_eq!(recovery.index_of(&holders[2]), Some(3));
+        assert_eq!(
+            recovery.index_of(&WorkerPublicKey::from_raw([9u8; 32])),
+            None
+        );
+
+        // Holder 1 can not send the shares of the others
+        for index in 1..=3 {
+            let shares = shares_at(&history, 3, index);
+            assert_eq!(
+                recovery.add_shares(&holders[0], index, 3, shares, Some(&pubkey)),
+                None
+            );
+        }
+        // Nor the shares of another threshold
+        let shares = shares_at(&history, 2, 2);
+        assert_eq!(
+            recovery.add_shares(&holders[1], 2, 2, shares, Some(&pubkey)),
+            None
+        );
+        assert_eq!(recovery.shares.keys().collect::<Vec<_>>(), vec![&1]);
+
+        for index in 2..=3 {
+            let shares = shares_at(&history, 3, index);
+            let recovered = recovery.add_shares(
+                &holders[index as usize - 1],
+                index,
+                3,
+                shares,
+                Some(&pubkey),
+            );
+            if index == 3 {
+                assert_eq!(recovered, Some(history.clone()));
+            } else {
+                assert_eq!(recovered, None);
+            }
+        }
+    }
+
+    #[test]
+    fn recovery_skips_broken_shares() {
+        let history = master_key_history();
+        let holders = holders(4);
+        let pubkey = master_pubkey(&history);
+        let mut recovery = MasterKeyRecovery::new(holders.clone());
+
+        let mut broken = shares_at(&history, 3, 1);
+        for share in broken.iter_mut() {
+            share.secret[0] ^= 1;
+        }
+        assert_eq!(
+            recovery.add_shares(&holders[0], 1, 3, broken, Some(&pubkey)),
+            None
+        );
+        // A resent share does not replace the first one
+        let shares = shares_at(&history, 3, 1);
+        assert_eq!(
+            recovery.add_shares(&holders[0], 1, 3, shares, Some(&pubkey)),
+            None
+        );
+        for index in 2..=3 {
+            let shares = shares_at(&history, 3, index);
+            assert_eq!(
+                recovery.add_shares(
+                    &holders[index as usize - 1],
+                    index,
+                    3,
+                    shares,
+                    Some(&pubkey)
+                ),
+                None
+            );
+        }
+        // The subset without the broken share matches the master pubkey
+        let shares = shares_at(&history, 3, 4);
+        assert_eq!(
+            recovery.add_shares(&holders[3], 4, 3, shares, Some(&pubkey)),
+            Some(history)
+        );
+    }
+
+    #[test]
+    fn recovery_without_master_pubkey_takes_first_subset() {
+        let history = master_key_history();
+        let holders = holders(2);
+        let mut recovery = MasterKeyRecovery::new(holders.clone());
+        assert_eq!(recovery.threshold(), 2);
+        let shares = shares_at(&history, 2, 1);
+        assert_eq!(recovery.add_shares(&holders[0], 1, 2, shares, None), None);
+        let shares = shares_at(&history, 2, 2);
+        assert_eq!(
+            recovery.add_shares(&holders[1], 2, 2, shares, None),
+            Some(history)
+        );
+    }
+
+    #[test]
+    fn find_combination_in_order() {
+        let mut combinations = vec![];
+        let found = find_combination(&[1, 2, 3, 4], 2, |picked| {
+            combinations.push(picked.to_vec());
+            None::<()>
+        });
+        assert_eq!(found, None);
+        assert_eq!(
+            combinations,
+            vec![
+                vec![1, 2],
+                vec![1, 3],
+                vec![1, 4],
+                vec![2, 3],
+                vec![2, 4],
+                vec![3, 4]
+            ]
+        );
+        assert_eq!(
+            find_combination(&[1, 2, 3], 2, |picked| (picked[0] == 2).then(|| picked[1])),
+            Some(3)
+        );
+        assert_eq!(
+            find_combination(&[1], 0, |picked| Some(picked.len())),
+            Some(0)
+        );
+        assert_eq!(find_combination(&[1], 2, |_| Some(())), None);
+    }
+
+    #[test]
+    fn rotate_master_key_with_majority_contributions() {
+        let gatekeepers: Vec<_> = (0..3u8)
+            .map(|i| WorkerPublicKey::from_raw([i; 32]))
+            .collect();
+        let mut rotation = PendingRotation::new(1, gatekeepers.clone());
+        let outsider = WorkerPublicKey::from_raw([9u8; 32]);
+        assert!(rotation
+            .contribute(outsider, 0, 1, Some([9u8; 64]))
+            .is_none());
+        assert!(rotation
+            .contribute(gatekeepers[2], 0, 1, Some([2u8; 64]))
+            .is_none());
+        // A duplicated contribution does not count
+        assert!(rotation
+            .contribute(gatekeepers[2], 0, 1, Some([3u8; 64]))
+            .is_none());
+        let key = rotation
+            .contribute(gatekeepers[0], 0, 1, Some([0u8; 64]))
+            .unwrap();
+
+        // The rotated key does not depend on the arrival order
+        let mut rotation = PendingRotation::new(1, gatekeepers.clone());
+        assert!(rotation
+            .contribute(gatekeepers[0], 0, 1, Some([0u8; 64]))
+            .is_none());
+        let same_key = rotation
+            .contribute(gatekeepers[2], 0, 1, Some([2u8; 64]))
+            .unwrap();
+        assert_eq!(key.public(), same_key.public());
+    }
+
+    #[test]
+    fn rotate_master_key_with_chunked_contributions() {
+        let gatekeepers: Vec<_> = (0..3u8)
+            .map(|i| WorkerPublicKey::from_raw([i; 32]))
+            .collect();
+        let mut rotation = PendingRotation::new(1, gatekeepers.clone());
+        // The contribution to this gatekeeper lands first, but the one of gatekeepers[1] completes
+        // first, so it counts before gatekeepers[0]
+        assert!(rotation
+            .contribute(gatekeepers[0], 0, 2, Some([0u8; 64]))
+            .is_none());
+        assert_eq!(rotation.missing_chunks(&gatekeepers[0], 2), vec![1]);
+        assert!(rotation
+            .contribute(gatekeepers[1], 0, 2, Some([1u8; 64]))
+            .is_none());
+        assert!(rotation.contribute(gatekeepers[1], 1, 2, None).is_none());
+        assert!(rotation.missing_chunks(&gatekeepers[1], 2).is_empty());
+        // A resent chunk does not complete the contribution
+        assert!(rotation
+            .contribute(gatekeepers[0], 0, 2, Some([0u8; 64]))
+            .is_none());
+        let key = rotation.contribute(gatekeepers[0], 1, 2, None).unwrap();
+        assert_eq!(rotation.completed, vec![gatekeepers[1], gatekeepers[0]]);
+
+        let mut rotation = PendingRotation::new(1, gatekeepers.clone());
+        assert!(rotation
+            .contribute(gatekeepers[1], 0, 1, Some([1u8; 64]))
+            .is_none());
+        let same_key = rotation
+            .contribute(gatekeepers[0], 0, 1, Some([0u8; 64]))
+            .unwrap();
+        assert_eq!(key.public(), same_key.public());
+
+        // A complete contribution without the share to this gatekeeper cannot rotate the key
+        let mut rotation = PendingRotation::new(1, gatekeepers.clone());
+        assert!(rotation
+            .contribute(gatekeepers[1], 0, 1, Some([1u8; 64]))
+            .is_none());
+        assert!(rotation.contribute(gatekeepers[2], 0, 1, None).is_none());
+        assert!(rotation
+            .contribute(gatekeepers[0], 0, 1, Some([0u8; 64]))
+            .is_none());
+    }
+}
@@ -4,7 +4,10 @@ mod side_tasks;
 
 use crate::{
     benchmark,
-    contracts::{pink::cluster::Cluster, AnyContract, ContractsKeeper, ExecuteEnv},
+    contracts::{
+        pink::cluster::Cluster, AnyContract, ContractsKeeper, ExecuteEnv, SidevmHandle,
+        SidevmRelayer,
+    },
     pink::{cluster::ClusterKeeper, ContractEventCallback, Pink},
     secret_channel::{ecdh_serde, SecretReceiver},
     types::{BlockInfo, OpaqueError, OpaqueQuery, OpaqueReply},
@@ -26,7 +29,8 @@ pub use phactory_api::prpc::{GatekeeperRole, GatekeeperStatus};
 use phala_crypto::{
     ecdh::EcdhKey,
     key_share,
-    sr25519::{Persistence, Signing, KDF},
+    sr25519::{Persistence, Signing, Sr25519SecretKey, KDF},
+    CryptoError,
 };
 use phala_mq::{
     traits::MessageChannel, BadOrigin, ContractId, MessageDispatcher, MessageOrigin,
@@ -44,11 +48,12 @@ use phala_types::{
     },
     messaging::{
         AeadIV, BatchRotateMasterKeyEvent, Condition, DispatchMasterKeyEvent,
-        DispatchMasterKeyHistoryEvent, GatekeeperChange, GatekeeperLaunch, HeartbeatChallenge,
-        KeyDistribution, MiningReportEvent, NewGatekeeperEvent, PRuntimeManagementEvent,
+        DispatchMasterKeyHistoryEvent, EncryptedKey, GatekeeperChange, GatekeeperEvent,
+        GatekeeperLaunch, HeartbeatChallenge, KeyDistribution, MasterKeyContributionEvent,
+        MasterKeyShareEvent, MiningReportEvent, NewGatekeeperEvent, PRuntimeManagementEvent,
         RemoveGatekeeperEvent, RotateMasterKeyEvent, SystemEvent, WorkerEvent,
     },
-    EcdhPublicKey, HandoverChallenge, HandoverChallengePayload, WorkerPublicKey,
+    EcdhPublicKey, HandoverChallenge, HandoverChallengePayload, MasterPublicKey, WorkerPublicKey,
 };
 use serde::{Deserialize, Serialize};
 use side_tasks::geo_probe;
@@ -56,12 +61,26 @@ use sidevm::service::{Command as SidevmCommand, CommandSender, Report, Spawner,
 use sp_core::{hashing::blake2_256, sr25519, Pair, U256};
 use sp_io;
 
+use std::collections::BTreeMap;
 use std::convert::TryFrom;
 use std::future::Future;
 use std::cell::Cell;
 
 pub type TransactionResult = Result<pink::runtime::ExecSideEffects, TransactionError>;
 
+/// Max number of outgoing requests from each sidevm instance carried out per block.
+const SIDEVM_OUTGOING_QUOTA_PER_BLOCK: usize = 8;
+
+/// Block interval to report the contract usage of the clusters on chain.
+const CLUSTER_USAGE_REPORT_INTERVAL: chain::BlockNumber = 100;
+
+const MASTER_KEY_SHARE_SALT: &[u8] = b"master_key_share";
+
+const MASTER_KEY_CONTRIBUTION_SALT: &[u8] = b"master_key_contribution";
+
+/// Max number of gatekeepers covered by each chunk of a master key rotation contribution.
+const MASTER_KEY_CONTRIBUTION_CHUNK_SIZE: usize = 16;
+
 #[derive(Encode, Decode, Debug, Clone, thiserror::Error)]
 #[error("TransactionError: {:?}", self)]
 pub enum TransactionError {
@@ -103,6 +122,7 @@ pub enum TransactionError {
     // for contract
     CodeNotFound,
     DuplicatedClusterDeploy,
+    UnknownClusterKey,
 }
 
 impl From<BadOrigin> for TransactionError {
@@ -111,6 +131,31 @@ impl From<BadOrigin> for TransactionError {
     }
 }
 
+/// The contract usage of a cluster, charged on chain.
+///
+/// The commands are dispatched on chain and processed by all the workers of the cluster, so each
+/// worker reports the same commands for a period and the chain charges them once. The queries
+/// are served by each worker alone and charged for each worker.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct ClusterUsage {
+    /// The number of commands by the account sending them, `None` for the commands not sent by
+    /// an account.
+    commands: BTreeMap<Option<phala_mq::AccountId>, u32>,
+    #[serde(default)]
+    queries: u32,
+}
+
+impl ClusterUsage {
+    fn count_command(&mut self, origin: &MessageOrigin) {
+        let sender = match origin {
+            MessageOrigin::AccountId(account) => Some(*account),
+            _ => None,
+        };
+        let commands = self.commands.entry(sender).or_default();
+        *commands = commands.saturating_add(1);
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 struct BenchState {
     start_block: chain::BlockNumber,
@@ -415,6 +460,24 @@ fn get_contract_key(cluster_key: &sr25519::Pair, contract_id: &ContractId) -> sr
         .expect("should not fail with valid info")
 }
 
+/// A deployed cluster, as listed by `System::clusters_summary`.
+#[derive(Serialize, Debug)]
+pub struct ClusterSummary {
+    pub id: phala_mq::ContractClusterId,
+    pub pubkey: String,
+    pub contracts: Vec<ContractSummary>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ContractSummary {
+    pub id: ContractId,
+    /// The state of the sidevm instance, if the contract has ever started one.
+    pub sidevm: Option<String>,
+    /// The number of commands failed to decrypt, e.g. those sent to the real contract key while
+    /// the cluster key is simulated.
+    pub decrypt_failures: u64,
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct System<Platform> {
     platform: Platform,
@@ -430,6 +493,7 @@ pub struct System<Platform> {
     gatekeeper_launch_events: TypedReceiver<GatekeeperLaunch>,
     gatekeeper_change_events: TypedReceiver<GatekeeperChange>,
     key_distribution_events: TypedReceiver<KeyDistribution<chain::BlockNumber>>,
+    gatekeeper_events: TypedReceiver<GatekeeperEvent>,
     cluster_key_distribution_events:
         TypedReceiver<ClusterOperation<chain::AccountId, chain::BlockNumber>>,
     contract_operation_events: TypedReceiver<ContractOperation<chain::Hash, chain::AccountId>>,
@@ -443,9 +507,15 @@ pub struct System<Platform> {
     worker_state: WorkerState,
     // Gatekeeper
     pub(crate) gatekeeper: Option<gk::Gatekeeper<SignedMessageChannel>>,
+    /// The master key history being recovered from the shares as a newly-registered gatekeeper
+    #[serde(default, with = "more::scale_bytes")]
+    master_key_recovery: Option<master_key::MasterKeyRecovery>,
 
     pub(crate) contracts: ContractsKeeper,
     pub(crate) contract_clusters: ClusterKeeper,
+    /// The contract usage of each cluster since the last report.
+    #[serde(default)]
+    cluster_usage: BTreeMap<phala_mq::ContractClusterId, ClusterUsage>,
     #[serde(skip)]
     #[serde(default = "create_sidevm_service_default")]
     sidevm_spawner: Spawner,
@@ -454,6 +524,11 @@ pub struct System<Platform> {
     pub(crate) block_number: BlockNumber,
     pub(crate) now_ms: u64,
     retired_versions: Vec<Condition>,
+
+    /// Deploy the clusters whose keys are not dispatched to this worker with simulated keys.
+    #[cfg(feature = "simulated-cluster-key")]
+    #[serde(default)]
+    simulate_cluster_keys: bool,
 }
 
 thread_local! {
@@ -472,9 +547,12 @@ fn create_sidevm_service_default() -> Spawner {
 fn create_sidevm_service(worker_threads: usize) -> Spawner {
     let (service, spawner) = sidevm::service::service(worker_threads);
     spawner.spawn(service.run(|report| match report {
-        Report::VmTerminated { id, reason } => {
+        Report::VmTerminated { id, reason, crash } => {
             let id = hex_fmt::HexFmt(&id[..4]);
             info!("Sidevm {id} terminated with reason: {reason:?}");
+            if let Some(crash) = crash {
+                info!("Sidevm {id} crash diagnostics: {crash:?}");
+            }
         }
     }));
     spawner
@@ -514,6 +592,7 @@ impl<Platform: pal::Platform> System<Platform> {
             gatekeeper_launch_events: recv_mq.subscribe_bound(),
             gatekeeper_change_events: recv_mq.subscribe_bound(),
             key_distribution_events: recv_mq.subscribe_bound(),
+            gatekeeper_events: recv_mq.subscribe_bound(),
             cluster_key_distribution_events: recv_mq.subscribe_bound(),
             contract_operation_events: recv_mq.subscribe_bound(),
             identity_key,
@@ -522,12 +601,16 @@ impl<Platform: pal::Platform> System<Platform> {
             last_challenge: None,
             worker_state: WorkerState::new(pubkey),
             gatekeeper: None,
+            master_key_recovery: None,
             contracts,
             contract_clusters: Default::default(),
+            cluster_usage: Default::default(),
             block_number: 0,
             now_ms: 0,
             sidevm_spawner: create_sidevm_service(worker_threads),
             retired_versions: vec![],
+            #[cfg(feature = "simulated-cluster-key")]
+            simulate_cluster_keys: false,
         }
     }
 
@@ -605,6 +688,8 @@ impl<Platform: pal::Platform> System<Platform> {
             .get_mut(contract_id)
             .ok_or(OpaqueError::ContractNotFound)?;
         let cluster_id = contract.cluster_id();
+        let usage = self.cluster_usage.entry(cluster_id).or_default();
+        usage.queries = usage.queries.saturating_add(1);
         let storage = self
             .contract_clusters
             .get_cluster_mut(&cluster_id)
@@ -612,12 +697,16 @@ impl<Platform: pal::Platform> System<Platform> {
             .storage
             .snapshot();
         let sidevm_handle = contract.sidevm_handle();
+        let sidevm_crash_history = contract.sidevm_crash_history();
+        let deployer = contract.deployer().cloned();
         let contract = contract.snapshot_for_query();
         let mut context = contracts::QueryContext {
             block_number: self.block_number,
             now_ms: self.now_ms,
             storage,
             sidevm_handle,
+            sidevm_crash_history,
+            deployer,
             log_handler: self.get_system_message_handler(&cluster_id),
             query_scheduler,
         };
@@ -652,6 +741,9 @@ impl<Platform: pal::Platform> System<Platform> {
             (event, origin) = self.key_distribution_events => {
                 self.process_key_distribution_event(block, origin, event);
             },
+            (event, origin) = self.gatekeeper_events => {
+                self.process_gatekeeper_event(block, origin, event);
+            },
             (event, origin) = self.cluster_key_distribution_events => {
                 self.process_cluster_operation_event(block, origin, event)?;
             },
@@ -731,10 +823,16 @@ impl<Platform: pal::Platform> System<Platform> {
                     contract_clusters: &mut self.contract_clusters,
                     log_handler: log_handler.clone(),
                 };
-                let result = match contract.process_next_message(&mut env) {
+                let (origin, result) = match contract.process_next_command(&mut env) {
                     Some(result) => result,
                     None => break,
                 };
+                if let Some(origin) = origin {
+                    self.cluster_usage
+                        .entry(cluster_id)
+                        .or_default()
+                        .count_command(&origin);
+                }
                 handle_contract_command_result(
                     result,
                     cluster_id,
@@ -769,12 +867,45 @@ impl<Platform: pal::Platform> System<Platform> {
                 log_handler,
             );
         }
-        self.contracts.try_restart_sidevms(&self.sidevm_spawner);
+        let relayer = SidevmRelayer {
+            worker: self.identity_key.public(),
+            egress: &self.egress,
+            ecdh_key: &self.ecdh_key,
+        };
+        self.contracts.flush_sidevm_outgoing_requests(
+            SIDEVM_OUTGOING_QUOTA_PER_BLOCK,
+            &relayer,
+            block.block_number,
+        );
+        self.contracts.try_restart_sidevms(&self.sidevm_spawner, self.block_number);
+
+        if block.block_number % CLUSTER_USAGE_REPORT_INTERVAL == 0 {
+            self.report_cluster_usage(block.block_number);
+        }
 
         let contract_running = !self.contract_clusters.is_empty();
         benchmark::set_flag(benchmark::Flags::CONTRACT_RUNNING, contract_running);
     }
 
+    /// Report the contract usage of the clusters deployed on this worker for billing.
+    ///
+    /// The usage is reported for the period ending at `block_number`. The clusters without any
+    /// command or query are reported as well, to be charged for their storage.
+    fn report_cluster_usage(&mut self, block_number: chain::BlockNumber) {
+        let mut usages = core::mem::take(&mut self.cluster_usage);
+        for (cluster_id, cluster) in self.contract_clusters.iter() {
+            let usage = usages.remove(cluster_id).unwrap_or_default();
+            self.egress
+                .push_message(&WorkerContractReport::ClusterUsage {
+                    cluster_id: *cluster_id,
+                    block_number,
+                    commands: usage.commands.into_iter().collect(),
+                    queries: usage.queries,
+                    storage_bytes: cluster.storage.size(),
+                });
+        }
+    }
+
     fn process_system_event(&mut self, block: &BlockInfo, event: &SystemEvent) {
         self.worker_state
             .process_event(block, event, &mut WorkerSMDelegate {
@@ -993,26 +1124,111 @@ impl<Platform: pal::Platform> System<Platform> {
 
     /// Rotate the master key
     ///
-    /// All the gatekeepers will generate the key, and only one will get published due to the nature of message queue.
+    /// All the gatekeepers in the rotation will send a contribution encrypted to each other through their own worker
+    /// egress, split into chunks of at most `MASTER_KEY_CONTRIBUTION_CHUNK_SIZE` gatekeepers. The rotated master key is
+    /// derived from the first complete contributions of a majority of the gatekeepers, so no single gatekeeper decides
+    /// it.
     ///
-    /// The generated master key will be shared to all the gatekeepers (include this one), and only then will they really
-    /// update the master key on-chain.
+    /// The rotation can be requested again with the same rotation id to resume it. The contribution is derived from the
+    /// identity key so the resent chunks are consistent with the landed ones, and only the chunks not on chain yet are
+    /// resent. A gatekeeper that has already rotated the key confirms it again.
+    ///
+    /// The gatekeepers not in the rotation stop silent syncing since they will not know the rotated key.
     fn process_master_key_rotation_request(
         &mut self,
-        block: &mut BlockInfo,
+        _block: &mut BlockInfo,
         _origin: MessageOrigin,
         event: RotateMasterKeyEvent,
     ) {
-        if let Some(gatekeeper) = &mut self.gatekeeper {
-            info!("Gatekeeper：Rotate master key");
-            gatekeeper.process_master_key_rotation_request(
-                block,
-                event,
-                self.identity_key.0.clone(),
-            );
+        let gatekeeper = match &mut self.gatekeeper {
+            Some(gatekeeper) => gatekeeper,
+            None => return,
+        };
+        let my_pubkey = self.identity_key.public();
+        let in_rotation = event.gk_identities.iter().any(|gk| gk.pubkey == my_pubkey);
+        if (event.rotation_id as usize) < gatekeeper.master_key_history().len() {
+            if in_rotation {
+                info!(
+                    "Gatekeeper: master key rotation {} retried, confirm again",
+                    event.rotation_id
+                );
+                self.confirm_master_key_rotation(event.rotation_id);
+            }
+            return;
+        }
+        if !in_rotation {
+            info!("Worker: master key rotation requested, stop unregistered gatekeeper silent syncing and cleanup");
+            self.gatekeeper = None;
+            return;
+        }
+
+        info!("Gatekeeper：Rotate master key");
+        let chunks = event
+            .gk_identities
+            .chunks(MASTER_KEY_CONTRIBUTION_CHUNK_SIZE)
+            .len() as u32;
+        let missing_chunks = gatekeeper
+            .start_master_key_rotation(&event)
+            .missing_chunks(&my_pubkey, chunks);
+        if missing_chunks.is_empty() {
+            info!("Gatekeeper: master key contribution already on chain");
+            return;
+        }
+        let contribution = self
+            .identity_key
+            .derive_sr25519_pair(&[
+                MASTER_KEY_CONTRIBUTION_SALT,
+                &event.rotation_id.to_be_bytes(),
+            ])
+            .expect("should never fail with valid identity key; qed.")
+            .dump_secret_key();
+        for (chunk, identities) in event
+            .gk_identities
+            .chunks(MASTER_KEY_CONTRIBUTION_CHUNK_SIZE)
+            .enumerate()
+        {
+            let chunk = chunk as u32;
+            if !missing_chunks.contains(&chunk) {
+                continue;
+            }
+            let secret_keys = identities
+                .iter()
+                .map(|gk| {
+                    (
+                        gk.pubkey,
+                        self.encrypt_key_to(&gk.ecdh_pubkey, &contribution),
+                    )
+                })
+                .collect();
+            self.egress
+                .push_message(&GatekeeperEvent::MasterKeyContribution(
+                    MasterKeyContributionEvent {
+                        rotation_id: event.rotation_id,
+                        chunk,
+                        chunks,
+                        secret_keys,
+                    },
+                ));
         }
     }
 
+    /// Confirm the rotated master key on chain, so the pallet knows which gatekeepers have switched to it
+    fn confirm_master_key_rotation(&self, rotation_id: u64) {
+        let master_pubkey = match self
+            .gatekeeper
+            .as_ref()
+            .and_then(|gk| gk.master_key_history().get(rotation_id as usize))
+        {
+            Some(master_key) => sr25519::Pair::restore_from_secret_key(&master_key.secret).public(),
+            None => return,
+        };
+        self.egress
+            .push_message(&RegistryEvent::RotatedMasterKeyConfirmed {
+                rotation_id,
+                master_pubkey,
+            });
+    }
+
     fn process_gatekeeper_change_event(
         &mut self,
         block: &mut BlockInfo,
@@ -1052,14 +1268,95 @@ impl<Platform: pal::Platform> System<Platform> {
             panic!("System state poisoned");
         }
 
-        if let Some(gatekeeper) = &mut self.gatekeeper {
-            gatekeeper.share_master_key(&event.pubkey, &event.ecdh_pubkey, block.block_number);
-
-            let my_pubkey = self.identity_key.public();
+        let my_pubkey = self.identity_key.public();
+        if self.gatekeeper.is_none() {
             if my_pubkey == event.pubkey {
-                gatekeeper.register_on_chain();
+                self.start_master_key_recovery(block);
             }
+            return;
+        }
+        self.share_master_key(block, &event.pubkey, &event.ecdh_pubkey);
+
+        if my_pubkey == event.pubkey {
+            self.gatekeeper
+                .as_mut()
+                .expect("checked; qed.")
+                .register_on_chain();
+        }
+    }
+
+    /// Wait for the master key shares from the gatekeepers on chain as a newly-registered gatekeeper
+    fn start_master_key_recovery(&mut self, block: &BlockInfo) {
+        let my_pubkey = self.identity_key.public();
+        let holders: Vec<_> = chain_state::gatekeepers(block.storage)
+            .into_iter()
+            .filter(|gk| *gk != my_pubkey)
+            .collect();
+        if holders.is_empty() {
+            // The first gatekeeper generates the master key itself
+            return;
         }
+        info!(
+            "Gatekeeper: wait for master key shares from {} gatekeepers",
+            holders.len()
+        );
+        self.master_key_recovery = Some(master_key::MasterKeyRecovery::new(holders));
+    }
+
+    /// Send the share of the master key history to the newly-registered gatekeeper
+    ///
+    /// The existing gatekeepers are indexed by their order on chain, and the shares of a majority of them are needed
+    /// to recover the master key history. The receiver only accepts the share at the index of its sender, and checks
+    /// the recovered master key against the master pubkey on chain, so a minority of faulty gatekeepers can neither
+    /// block the recovery nor forge the master key.
+    fn share_master_key(
+        &mut self,
+        block: &BlockInfo,
+        pubkey: &WorkerPublicKey,
+        ecdh_pubkey: &EcdhPublicKey,
+    ) {
+        let my_pubkey = self.identity_key.public();
+        let holders: Vec<_> = chain_state::gatekeepers(block.storage)
+            .into_iter()
+            .filter(|gk| gk != pubkey)
+            .collect();
+        let index = match holders.iter().position(|gk| *gk == my_pubkey) {
+            Some(position) => position + 1,
+            // Unregistered gatekeepers in silent syncing do not take part
+            None => return,
+        };
+        let threshold = master_key::share_threshold(holders.len());
+        let (threshold, index) = match (u8::try_from(threshold), u8::try_from(index)) {
+            (Ok(threshold), Ok(index)) => (threshold, index),
+            _ => {
+                error!("Too many gatekeepers to share the master key");
+                return;
+            }
+        };
+
+        info!("Gatekeeper: dispatch master key share {}", index);
+        let shares = self
+            .gatekeeper
+            .as_ref()
+            .expect("checked; qed.")
+            .master_key_shares(pubkey, block.block_number, threshold, index);
+        let encrypted_shares = shares
+            .iter()
+            .map(|share| {
+                (
+                    share.rotation_id,
+                    share.block_height,
+                    self.encrypt_key_to(ecdh_pubkey, &share.secret),
+                )
+            })
+            .collect();
+        self.egress
+            .push_message(&GatekeeperEvent::MasterKeyShare(MasterKeyShareEvent {
+                dest: *pubkey,
+                threshold,
+                index,
+                encrypted_shares,
+            }));
     }
 
     /// Turn gatekeeper to silent syncing. The real cleanup will happen in next key rotation since it will have no chance
@@ -1114,6 +1411,29 @@ impl<Platform: pal::Platform> System<Platform> {
         }
     }
 
+    fn process_gatekeeper_event(
+        &mut self,
+        block: &mut BlockInfo,
+        origin: MessageOrigin,
+        event: GatekeeperEvent,
+    ) {
+        match event {
+            GatekeeperEvent::MasterKeyShare(event) => {
+                if let Err(err) = self.process_master_key_share(block, origin, event) {
+                    error!("Failed to process master key share event: {:?}", err);
+                };
+            }
+            GatekeeperEvent::MasterKeyContribution(event) => {
+                if let Err(err) = self.process_master_key_contribution(block, origin, event) {
+                    error!("Failed to process master key contribution event: {:?}", err);
+                };
+            }
+            _ => {
+                // Handled by Gatekeeper
+            }
+        }
+    }
+
     fn process_cluster_operation_event(
         &mut self,
         block: &mut BlockInfo,
@@ -1150,21 +1470,76 @@ impl<Platform: pal::Platform> System<Platform> {
                     cluster.config.log_handler = Some(log_handler);
                 }
             }
+            ClusterOperation::SetGasSchedule {
+                cluster: cluster_id,
+                version,
+            } => {
+                if !origin.is_pallet() {
+                    error!("Invalid origin {:?} sent a {:?}", origin, event);
+                    anyhow::bail!("Invalid origin");
+                }
+                if let Some(version) = version {
+                    if sidevm::gas_schedule::GasSchedule::by_version(version).is_none() {
+                        error!("Unknown gas schedule version {}", version);
+                        anyhow::bail!("Unknown gas schedule version");
+                    }
+                }
+                let cluster = self.contract_clusters.get_cluster_mut(&cluster_id);
+                if let Some(cluster) = cluster {
+                    info!(
+                        "Set gas schedule for cluster {}: {:?}",
+                        hex_fmt::HexFmt(cluster_id),
+                        version
+                    );
+                    cluster.config.gas_schedule_version = version;
+                    cluster.storage.set_gas_schedule_version(version);
+                }
+            }
             ClusterOperation::DestroyCluster(cluster_id) => {
                 if !origin.is_pallet() {
                     error!("Invalid origin {:?} sent a {:?}", origin, event);
                     anyhow::bail!("Invalid origin");
                 }
-                let cluster = match self.contract_clusters.remove_cluster(&cluster_id) {
+                self.destroy_cluster(&cluster_id);
+            }
+            ClusterOperation::RemoveWorker {
+                cluster: cluster_id,
+                worker,
+            } => {
+                if !origin.is_pallet() {
+                    error!("Invalid origin {:?} sent a {:?}", origin, event);
+                    anyhow::bail!("Invalid origin");
+                }
+                if worker == self.identity_key.public() {
+                    info!("Removed from cluster {}", hex_fmt::HexFmt(&cluster_id));
+                    self.destroy_cluster(&cluster_id);
+                } else if let Some(cluster) = self.contract_clusters.get_cluster_mut(&cluster_id) {
+                    for contract_id in cluster.iter_contracts() {
+                        if let Some(contract) = self.contracts.get_mut(contract_id) {
+                            contract.on_cluster_worker_removed(&worker);
+                        }
+                    }
+                }
+            }
+            ClusterOperation::DestroyContract {
+                cluster: cluster_id,
+                contract: contract_id,
+            } => {
+                if !origin.is_pallet() {
+                    error!("Invalid origin {:?} sent a {:?}", origin, event);
+                    anyhow::bail!("Invalid origin");
+                }
+                let cluster = match self.contract_clusters.get_cluster_mut(&cluster_id) {
                     // The cluster is not deployed on this worker, just ignore it.
                     None => return Ok(()),
                     Some(cluster) => cluster,
                 };
-                info!("Destroying cluster {}", hex_fmt::HexFmt(&cluster_id));
-                for contract in cluster.iter_contracts() {
-                    if let Some(contract) = self.contracts.remove(&contract) {
-                        contract.destroy(&self.sidevm_spawner);
-                    }
+                if !cluster.remove_contract(&contract_id) {
+                    anyhow::bail!("Contract not found in the cluster");
+                }
+                info!("Destroying contract {}", hex_fmt::HexFmt(&contract_id));
+                if let Some(contract) = self.contracts.remove(&contract_id) {
+                    contract.destroy(&self.sidevm_spawner);
                 }
             }
             ClusterOperation::UploadResource {
@@ -1190,6 +1565,21 @@ impl<Platform: pal::Platform> System<Platform> {
         Ok(())
     }
 
+    /// Drop the cluster and destroy all the contracts in it if it is deployed on this worker.
+    fn destroy_cluster(&mut self, cluster_id: &phala_mq::ContractClusterId) {
+        let cluster = match self.contract_clusters.remove_cluster(cluster_id) {
+            // The cluster is not deployed on this worker, just ignore it.
+            None => return,
+            Some(cluster) => cluster,
+        };
+        info!("Destroying cluster {}", hex_fmt::HexFmt(cluster_id));
+        for contract in cluster.iter_contracts() {
+            if let Some(contract) = self.contracts.remove(&contract) {
+                contract.destroy(&self.sidevm_spawner);
+            }
+        }
+    }
+
     fn process_contract_operation_event(
         &mut self,
         block: &mut BlockInfo,
@@ -1241,6 +1631,7 @@ impl<Platform: pal::Platform> System<Platform> {
                                                 ecdh_key,
                                                 block,
                                                 cluster_id,
+                                                contract_info.deployer.clone(),
                                             )?;
                                             id
                                         }
@@ -1331,23 +1722,70 @@ impl<Platform: pal::Platform> System<Platform> {
         Ok(())
     }
 
-    /// Decrypt the key encrypted by `encrypt_key_to()`
+    /// Encrypt the secret to the worker with `ecdh_pubkey` using the identity key
+    ///
+    /// Unlike the keys shared by `Gatekeeper`, the secrets here differ between the gatekeepers, so the IV is generated
+    /// randomly.
+    fn encrypt_key_to(
+        &self,
+        ecdh_pubkey: &EcdhPublicKey,
+        secret: &Sr25519SecretKey,
+    ) -> EncryptedKey {
+        let iv = crate::generate_random_iv();
+        let (ecdh_pubkey, encrypted_key) = key_share::encrypt_secret_to(
+            &self.identity_key,
+            &[MASTER_KEY_SHARE_SALT],
+            &ecdh_pubkey.0,
+            secret,
+            &iv,
+        )
+        .expect("should never fail with valid identity key; qed.");
+        EncryptedKey {
+            ecdh_pubkey: sr25519::Public(ecdh_pubkey),
+            encrypted_key,
+            iv,
+        }
+    }
+
+    /// Decrypt the secret encrypted by `encrypt_key_to()`
     ///
     /// This function could panic a lot, thus should only handle data from other pRuntimes.
-    fn decrypt_key_from(
+    fn decrypt_secret_from(
         &self,
         ecdh_pubkey: &EcdhPublicKey,
         encrypted_key: &Vec<u8>,
         iv: &AeadIV,
-    ) -> sr25519::Pair {
+    ) -> Sr25519SecretKey {
+        self.try_decrypt_secret_from(ecdh_pubkey, encrypted_key, iv)
+            .expect("Failed to decrypt dispatched key")
+    }
+
+    /// Decrypt the secret encrypted by `encrypt_key_to()`, failing on a malformed secret
+    fn try_decrypt_secret_from(
+        &self,
+        ecdh_pubkey: &EcdhPublicKey,
+        encrypted_key: &Vec<u8>,
+        iv: &AeadIV,
+    ) -> Result<Sr25519SecretKey, CryptoError> {
         let my_ecdh_key = self
             .identity_key
             .derive_ecdh_key()
             .expect("Should never failed with valid identity key; qed.");
-        let secret =
-            key_share::decrypt_secret_from(&my_ecdh_key, &ecdh_pubkey.0, &encrypted_key, &iv)
-                .expect("Failed to decrypt dispatched key");
-        sr25519::Pair::restore_from_secret_key(&secret)
+        key_share::decrypt_secret_from(&my_ecdh_key, &ecdh_pubkey.0, &encrypted_key, &iv)
+    }
+
+    /// Decrypt the key encrypted by `encrypt_key_to()`
+    fn decrypt_key_from(
+        &self,
+        ecdh_pubkey: &EcdhPublicKey,
+        encrypted_key: &Vec<u8>,
+        iv: &AeadIV,
+    ) -> sr25519::Pair {
+        sr25519::Pair::restore_from_secret_key(&self.decrypt_secret_from(
+            ecdh_pubkey,
+            encrypted_key,
+            iv,
+        ))
     }
 
     /// Process encrypted master key from mq
@@ -1403,6 +1841,164 @@ impl<Platform: pal::Platform> System<Platform> {
         Ok(())
     }
 
+    /// Collect the master key shares, and recover the master key history once there are enough of them
+    fn process_master_key_share(
+        &mut self,
+        block: &mut BlockInfo,
+        origin: MessageOrigin,
+        event: MasterKeyShareEvent,
+    ) -> Result<(), TransactionError> {
+        let sender = match origin {
+            MessageOrigin::Worker(pubkey) if chain_state::is_gatekeeper(&pubkey, block.storage) => {
+                pubkey
+            }
+            _ => {
+                error!("Invalid origin {:?} sent a {:?}", origin, event);
+                return Err(TransactionError::BadOrigin);
+            }
+        };
+
+        let my_pubkey = self.identity_key.public();
+        if my_pubkey != event.dest || self.gatekeeper.is_some() {
+            return Ok(());
+        }
+        let recovery = match &self.master_key_recovery {
+            Some(recovery) => recovery,
+            None => {
+                warn!("Gatekeeper: received master key share while not recovering");
+                return Ok(());
+            }
+        };
+        if recovery.index_of(&sender) != Some(event.index) {
+            error!(
+                "Gatekeeper: master key share {} from {} does not match its position",
+                event.index,
+                hex::encode(sender)
+            );
+            return Err(TransactionError::BadInput);
+        }
+        // A gatekeeper sending a malformed share must not crash the recovering one, the share is
+        // skipped and the recovery waits for the shares of the others
+        let shares = event
+            .encrypted_shares
+            .iter()
+            .map(|(rotation_id, block_height, key)| {
+                let secret =
+                    self.try_decrypt_secret_from(&key.ecdh_pubkey, &key.encrypted_key, &key.iv)?;
+                Ok(master_key::MasterKeyShare {
+                    rotation_id: *rotation_id,
+                    block_height: *block_height,
+                    secret,
+                })
+            })
+            .collect::<Result<Vec<_>, CryptoError>>();
+        let shares = match shares {
+            Ok(shares) => shares,
+            Err(err) => {
+                error!(
+                    "Gatekeeper: failed to decrypt master key share {} from {}: {:?}",
+                    event.index,
+                    hex::encode(sender),
+                    err
+                );
+                return Err(TransactionError::BadInput);
+            }
+        };
+        info!(
+            "Gatekeeper: received master key share {} from {}",
+            event.index,
+            hex::encode(sender)
+        );
+        let master_pubkey = chain_state::master_pubkey(block.storage);
+        let recovery = self.master_key_recovery.as_mut().expect("checked; qed.");
+        let master_key_history = match recovery.add_shares(
+            &sender,
+            event.index,
+            event.threshold,
+            shares,
+            master_pubkey.as_ref(),
+        ) {
+            Some(history) => history,
+            None => return Ok(()),
+        };
+        info!(
+            "Gatekeeper: successfully recover master key from {} shares",
+            recovery.threshold()
+        );
+        self.master_key_recovery = None;
+        self.set_master_key_history(master_key_history);
+        Ok(())
+    }
+
+    /// Collect the chunks of contributions to the master key rotation, and rotate the master key once there are enough
+    /// complete contributions
+    ///
+    /// The new master key takes effect immediately after the GatekeeperRegistryEvent::RotatedMasterPubkey is sent
+    fn process_master_key_contribution(
+        &mut self,
+        block: &mut BlockInfo,
+        origin: MessageOrigin,
+        event: MasterKeyContributionEvent,
+    ) -> Result<(), TransactionError> {
+        let sender = match origin {
+            MessageOrigin::Worker(pubkey) if chain_state::is_gatekeeper(&pubkey, block.storage) => {
+                pubkey
+            }
+            _ => {
+                error!("Invalid origin {:?} sent a {:?}", origin, event);
+                return Err(TransactionError::BadOrigin);
+            }
+        };
+
+        let my_pubkey = self.identity_key.public();
+        if self.gatekeeper.is_none() {
+            if event.secret_keys.contains_key(&my_pubkey) {
+                panic!(
+                    "Master key contribution to a normal worker {:?}",
+                    &my_pubkey
+                );
+            }
+            return Ok(());
+        }
+
+        let contribution = event.secret_keys.get(&my_pubkey).map(|encrypted_key| {
+            self.decrypt_secret_from(
+                &encrypted_key.ecdh_pubkey,
+                &encrypted_key.encrypted_key,
+                &encrypted_key.iv,
+            )
+        });
+        let gatekeeper = self.gatekeeper.as_mut().expect("checked; qed.");
+        let new_master_key = match gatekeeper.add_rotation_contribution(
+            event.rotation_id,
+            sender,
+            event.chunk,
+            event.chunks,
+            contribution,
+        ) {
+            Some(new_master_key) => new_master_key,
+            None => return Ok(()),
+        };
+
+        info!("Worker: rotate master key with the contributions of gatekeepers");
+        if gatekeeper.append_master_key(RotatedMasterKey {
+            rotation_id: event.rotation_id,
+            block_height: self.block_number,
+            secret: new_master_key.dump_secret_key(),
+        }) {
+            master_key::seal(
+                self.sealing_path.clone(),
+                &gatekeeper.master_key_history(),
+                &self.identity_key,
+                &self.platform,
+            );
+        }
+        if gatekeeper.switch_master_key(event.rotation_id, self.block_number) {
+            self.confirm_master_key_rotation(event.rotation_id);
+        }
+        Ok(())
+    }
+
     /// Decrypt the rotated master key
     ///
     /// The new master key takes effect immediately after the GatekeeperRegistryEvent::RotatedMasterPubkey is sent
@@ -1499,33 +2095,107 @@ impl<Platform: pal::Platform> System<Platform> {
         }
 
         let my_pubkey = self.identity_key.public();
-        if event.secret_keys.contains_key(&my_pubkey) {
-            let encrypted_key = &event.secret_keys[&my_pubkey];
-            let cluster_key = self.decrypt_key_from(
-                &encrypted_key.ecdh_pubkey,
-                &encrypted_key.encrypted_key,
-                &encrypted_key.iv,
-            );
-            info!("Worker: successfully decrypt received cluster key");
-
-            // TODO(shelven): forget cluster key after expiration time
-            let cluster = self.contract_clusters.get_cluster_mut(&event.cluster);
-            if cluster.is_some() {
-                error!("Cluster {:?} is already deployed", &event.cluster);
-                return Err(TransactionError::DuplicatedClusterDeploy.into());
+        let cluster_key = match event.secret_keys.get(&my_pubkey) {
+            Some(encrypted_key) => {
+                let cluster_key = self.decrypt_key_from(
+                    &encrypted_key.ecdh_pubkey,
+                    &encrypted_key.encrypted_key,
+                    &encrypted_key.iv,
+                );
+                info!("Worker: successfully decrypt received cluster key");
+                cluster_key
             }
-            // register cluster
-            self.contract_clusters
-                .get_cluster_or_default_mut(&event.cluster, &cluster_key);
-            let message = WorkerClusterReport::ClusterDeployed {
-                id: event.cluster,
-                pubkey: cluster_key.public(),
-            };
-            self.egress.push_message(&message);
+            None => match self.simulated_cluster_key(&event.cluster) {
+                Some(cluster_key) => {
+                    info!(
+                        "Worker: deploy cluster {} with a simulated key",
+                        hex_fmt::HexFmt(&event.cluster)
+                    );
+                    cluster_key
+                }
+                None => return Ok(()),
+            },
+        };
+
+        // TODO(shelven): forget cluster key after expiration time
+        let cluster = self.contract_clusters.get_cluster_mut(&event.cluster);
+        if cluster.is_some() {
+            error!("Cluster {:?} is already deployed", &event.cluster);
+            return Err(TransactionError::DuplicatedClusterDeploy.into());
         }
+        // register cluster
+        self.contract_clusters
+            .get_cluster_or_default_mut(&event.cluster, &cluster_key);
+        let message = WorkerClusterReport::ClusterDeployed {
+            id: event.cluster,
+            pubkey: cluster_key.public(),
+        };
+        self.egress.push_message(&message);
         Ok(())
     }
 
+    /// The key to deploy a cluster with when the real one is not dispatched to this worker.
+    ///
+    /// It is derived from the cluster id only, so anyone can derive it. The contracts can be
+    /// instantiated and run with it, but the commands encrypted to the real contract keys can not
+    /// be decrypted.
+    #[cfg(feature = "simulated-cluster-key")]
+    fn simulated_cluster_key(
+        &self,
+        cluster: &phala_mq::ContractClusterId,
+    ) -> Option<sr25519::Pair> {
+        if !self.simulate_cluster_keys {
+            return None;
+        }
+        let seed = blake2_256(&(b"simulated cluster key", cluster).encode());
+        Some(sr25519::Pair::from_seed(&seed))
+    }
+
+    #[cfg(not(feature = "simulated-cluster-key"))]
+    fn simulated_cluster_key(
+        &self,
+        _cluster: &phala_mq::ContractClusterId,
+    ) -> Option<sr25519::Pair> {
+        None
+    }
+
+    /// Deploy the clusters whose keys are not dispatched to this worker with simulated keys.
+    ///
+    /// Used to replay the contracts of all the clusters outside of a TEE.
+    #[cfg(feature = "simulated-cluster-key")]
+    pub fn set_simulate_cluster_keys(&mut self, simulate: bool) {
+        self.simulate_cluster_keys = simulate;
+    }
+
+    /// The clusters deployed in this worker, with their contracts.
+    pub fn clusters_summary(&self) -> Vec<ClusterSummary> {
+        self.contract_clusters
+            .iter()
+            .map(|(id, cluster)| ClusterSummary {
+                id: *id,
+                pubkey: hex::encode(cluster.key().public()),
+                contracts: cluster
+                    .iter_contracts()
+                    .map(|contract_id| {
+                        let contract = self.contracts.get(contract_id);
+                        ContractSummary {
+                            id: *contract_id,
+                            sidevm: contract.and_then(|contract| contract.sidevm_handle()).map(
+                                |handle| match handle {
+                                    SidevmHandle::Running(_) => "running".into(),
+                                    SidevmHandle::Terminated(reason) => format!("{:?}", reason),
+                                },
+                            ),
+                            decrypt_failures: contract
+                                .map(|contract| contract.decrypt_failures())
+                                .unwrap_or_default(),
+                        }
+                    })
+                    .collect(),
+            })
+            .collect()
+    }
+
     pub fn is_registered(&self) -> bool {
         self.worker_state.registered
     }
@@ -1555,7 +2225,7 @@ impl<Platform: pal::Platform> System<Platform> {
 
 impl<P: pal::Platform> System<P> {
     pub fn on_restored(&mut self) -> Result<()> {
-        self.contracts.try_restart_sidevms(&self.sidevm_spawner);
+        self.contracts.try_restart_sidevms(&self.sidevm_spawner, self.block_number);
         self.check_retirement();
         Ok(())
     }
@@ -1626,6 +2296,7 @@ pub fn apply_pink_side_effects(
             ecdh_key.clone(),
             block,
             cluster_id,
+            deployer.clone(),
         );
 
         if let Err(err) = result {
@@ -1688,10 +2359,29 @@ pub fn apply_pink_side_effects(
                         continue;
                     }
                 };
-                if let Err(err) = contract.start_sidevm(&spawner, wasm_code, auto_restart) {
+                if let Err(err) = contract.start_sidevm(
+                    &spawner,
+                    wasm_code,
+                    auto_restart,
+                    cluster.config.gas_schedule_version,
+                    block.block_number,
+                ) {
                     error!(target: "sidevm", "[{vmid}] Start sidevm failed: {:?}", err);
                 }
             }
+            PinkEvent::UpgradeSidevm { code_hash } => {
+                let code_hash = code_hash.into();
+                let wasm_code = match cluster.get_resource(ResourceType::SidevmCode, &code_hash) {
+                    Some(code) => code,
+                    None => {
+                        error!(target: "sidevm", "[{vmid}] Upgrade sidevm failed: code not found, code_hash={code_hash:?}");
+                        continue;
+                    }
+                };
+                if let Err(err) = contract.upgrade_sidevm(&spawner, wasm_code) {
+                    error!(target: "sidevm", "[{vmid}] Upgrade sidevm failed: {:?}", err);
+                }
+            }
             PinkEvent::SidevmMessage(payload) => {
                 if let Err(err) = contract.push_message_to_sidevm(payload) {
                     error!(target: "sidevm", "[{vmid}] Push message to sidevm failed: {:?}", err);
@@ -1728,6 +2418,7 @@ pub fn install_contract(
     ecdh_key: EcdhKey,
     block: &mut BlockInfo,
     cluster_id: phala_mq::ContractClusterId,
+    deployer: chain::AccountId,
 ) -> anyhow::Result<()> {
     if contracts.get(&contract_id).is_some() {
         return Err(anyhow::anyhow!("Contract already exists"));
@@ -1748,6 +2439,7 @@ pub fn install_contract(
         ecdh_key.clone(),
         cluster_id,
         contract_id,
+        deployer,
     );
     contracts.insert(wrapped);
     Ok(())
@@ -1776,17 +2468,27 @@ pub mod chain_state {
     use crate::storage::{Storage, StorageExt};
     use parity_scale_codec::Decode;
 
-    pub fn is_gatekeeper(pubkey: &WorkerPublicKey, chain_storage: &Storage) -> bool {
+    pub fn gatekeepers(chain_storage: &Storage) -> Vec<WorkerPublicKey> {
         let key = storage_prefix("PhalaRegistry", "Gatekeeper");
-        let gatekeepers = chain_storage
+        chain_storage
             .get(&key)
             .map(|v| {
                 Vec::<WorkerPublicKey>::decode(&mut &v[..])
                     .expect("Decode value of Gatekeeper Failed. (This should not happen)")
             })
-            .unwrap_or_default();
+            .unwrap_or_default()
+    }
 
-        gatekeepers.contains(pubkey)
+    pub fn is_gatekeeper(pubkey: &WorkerPublicKey, chain_storage: &Storage) -> bool {
+        gatekeepers(chain_storage).contains(pubkey)
+    }
+
+    pub fn master_pubkey(chain_storage: &Storage) -> Option<MasterPublicKey> {
+        let key = storage_prefix("PhalaRegistry", "GatekeeperMasterPubkey");
+        chain_storage.get(&key).map(|v| {
+            MasterPublicKey::decode(&mut &v[..])
+                .expect("Decode value of MasterPubkey Failed. (This should not happen)")
+        })
     }
 
     /// Return `None` if given pruntime hash is not allowed on-chain
@@ -1798,6 +2500,22 @@ pub mod chain_state {
             storage_map_prefix_twox_64_concat(b"PhalaRegistry", b"PRuntimeAddedAt", runtime_hash);
         chain_storage.get_decoded(&key).unwrap_or(None)
     }
+
+    /// The workers of the cluster, as registered on chain.
+    pub fn cluster_workers(
+        chain_storage: &Storage,
+        cluster: &phala_mq::ContractClusterId,
+    ) -> Vec<WorkerPublicKey> {
+        let key =
+            storage_map_prefix_twox_64_concat(b"PhalaFatContracts", b"ClusterWorkers", cluster);
+        chain_storage.get_decoded(&key).unwrap_or_default()
+    }
+
+    /// The DER encoded root certificates trusted to sign the DCAP quotes.
+    pub fn dcap_trusted_root_certs(chain_storage: &Storage) -> Vec<Vec<u8>> {
+        let key = storage_prefix("PhalaRegistry", "DcapTrustedRootCerts");
+        chain_storage.get_decoded(&key).unwrap_or_default()
+    }
 }
 
 #[cfg(test)]
@@ -1874,4 +2592,399 @@ mod tests {
         // WorkerContractReport::ContractInstantiated
         insta::assert_debug_snapshot!(messages);
     }
+
+    #[test]
+    fn test_sidevm_outgoing_relay() {
+        use crate::light_validation::utils::storage_map_prefix_twox_64_concat;
+        use sidevm::service::OutgoingRequest;
+
+        let cluster_key = sp_core::Pair::from_seed(&Default::default());
+        let mut contracts = ContractsKeeper::default();
+        let mut keeper = ClusterKeeper::default();
+        let wasm_bin = pink::load_test_wasm("hooks_test");
+        let cluster_id = phala_mq::ContractClusterId(Default::default());
+        let cluster = keeper.get_cluster_or_default_mut(&cluster_id, &cluster_key);
+        let code_hash = cluster
+            .upload_resource(ALICE.clone(), ResourceType::InkCode, wasm_bin)
+            .unwrap();
+        let effects = keeper
+            .instantiate_contract(
+                cluster_id,
+                ALICE,
+                code_hash,
+                vec![0xed, 0x4b, 0x9d, 0x1b],
+                Default::default(),
+                1,
+                1,
+                None,
+            )
+            .unwrap();
+
+        let cluster = keeper.get_cluster_mut(&cluster_id).unwrap();
+        let mut builder = BlockInfo::builder().block_number(1).now_ms(1);
+        let signer = sr25519::Pair::from_seed(&Default::default());
+        let egress = builder
+            .send_mq
+            .channel(MessageOrigin::Gatekeeper, signer.into());
+        let mut block_info = builder.build();
+        let spawner = create_sidevm_service(4);
+        apply_pink_side_effects(
+            effects,
+            cluster_id,
+            &mut contracts,
+            cluster,
+            &mut block_info,
+            &egress,
+            &spawner,
+            None,
+        );
+        let contract = contracts.values_mut().next().unwrap();
+        let contract_id = contract.id();
+        let outgoing = contract.attach_terminated_sidevm();
+
+        // The workers 0 and 1 are in the cluster, the worker 2 is not
+        let workers: Vec<_> = (1..=3u8)
+            .map(|i| {
+                let key = sr25519::Pair::from_seed(&[i; 32]);
+                let ecdh_key = key.derive_ecdh_key().unwrap();
+                let queue = phala_mq::MessageSendQueue::default();
+                let egress = queue.channel(MessageOrigin::Worker(key.public()), key.clone().into());
+                (key.public(), ecdh_key, queue, egress)
+            })
+            .collect();
+        let cluster_workers = vec![workers[0].0, workers[1].0];
+        builder.storage.load(std::iter::once((
+            storage_map_prefix_twox_64_concat(b"PhalaFatContracts", b"ClusterWorkers", &cluster_id),
+            cluster_workers.encode(),
+        )));
+        let relay = |contract: &mut contracts::FatContract, worker: usize, block_number| {
+            let (pubkey, ecdh_key, _, egress) = &workers[worker];
+            contract.flush_sidevm_outgoing_requests(
+                SIDEVM_OUTGOING_QUOTA_PER_BLOCK,
+                &SidevmRelayer {
+                    worker: *pubkey,
+                    egress,
+                    ecdh_key,
+                },
+                block_number,
+            );
+        };
+        let request = |payload: &[u8]| OutgoingRequest::Message {
+            topic: b"sidevm/out".to_vec(),
+            payload: payload.to_vec(),
+        };
+
+        // No relay claimed yet, every worker relays the requests emitted on it
+        outgoing.try_send(request(b"from 0")).unwrap();
+        relay(contract, 0, 1);
+        outgoing.try_send(request(b"from 1")).unwrap();
+        relay(contract, 1, 1);
+        outgoing.try_send(request(b"from 2")).unwrap();
+        relay(contract, 2, 1);
+        let relayed: Vec<_> = workers
+            .iter()
+            .map(|(_, _, queue, _)| queue.all_messages())
+            .collect();
+        for messages in relayed.iter() {
+            assert_eq!(messages.len(), 1);
+            assert_eq!(
+                messages[0].message.destination.path(),
+                &contracts::command_topic(contract_id)
+            );
+        }
+
+        // The worker 2 can not claim the relay as it is not in the cluster, so the worker 1
+        // claims it as its request is dispatched before the one of the worker 0
+        for message in relayed[2]
+            .iter()
+            .chain(relayed[1].iter())
+            .chain(relayed[0].iter())
+        {
+            builder.recv_mq.dispatch(message.message.clone());
+        }
+        let mut block_info = builder.build();
+        let mut env = ExecuteEnv {
+            block: &mut block_info,
+            contract_clusters: &mut keeper,
+            log_handler: None,
+        };
+        let results: Vec<_> =
+            std::iter::from_fn(|| contract.process_next_message(&mut env)).collect();
+        assert_eq!(results.len(), 3);
+        assert!(matches!(results[0], Err(TransactionError::BadOrigin)));
+        assert!(results[1].is_ok());
+        assert!(matches!(results[2], Err(TransactionError::BadOrigin)));
+
+        let carried_out: Vec<_> = builder
+            .send_mq
+            .all_messages()
+            .into_iter()
+            .filter(|msg| msg.message.sender == MessageOrigin::Contract(contract_id))
+            .map(|msg| (msg.message.destination.path().clone(), msg.message.payload))
+            .collect();
+        assert_eq!(
+            carried_out,
+            vec![(b"sidevm/out".to_vec(), b"from 1".to_vec())]
+        );
+
+        // The requests emitted on the other workers are dropped
+        outgoing.try_send(request(b"dropped")).unwrap();
+        relay(contract, 0, 1);
+        assert_eq!(workers[0].2.all_messages().len(), 1);
+
+        // Another worker takes over once the relay has relayed nothing for a while
+        let expired_at = 2 + contracts::SIDEVM_RELAY_TIMEOUT;
+        outgoing.try_send(request(b"still dropped")).unwrap();
+        relay(contract, 0, expired_at - 1);
+        assert_eq!(workers[0].2.all_messages().len(), 1);
+        outgoing.try_send(request(b"timed out")).unwrap();
+        relay(contract, 0, expired_at);
+        let messages = workers[0].2.all_messages();
+        assert_eq!(messages.len(), 2);
+        builder.recv_mq.dispatch(messages[1].message.clone());
+        builder.block_number = expired_at;
+        let mut block_info = builder.build();
+        let mut env = ExecuteEnv {
+            block: &mut block_info,
+            contract_clusters: &mut keeper,
+            log_handler: None,
+        };
+        assert!(matches!(
+            contract.process_next_message(&mut env),
+            Some(Ok(_))
+        ));
+
+        // Another worker takes over once the relay is removed from the cluster
+        contract.on_cluster_worker_removed(&workers[0].0);
+        outgoing.try_send(request(b"taken over")).unwrap();
+        relay(contract, 1, expired_at);
+        assert_eq!(workers[1].2.all_messages().len(), 2);
+    }
+
+    #[test]
+    fn test_gas_schedule_versions_in_sync() {
+        assert_eq!(
+            chain::pallet_fat::LATEST_GAS_SCHEDULE_VERSION,
+            sidevm::gas_schedule::GasSchedule::LATEST_VERSION
+        );
+    }
+
+    #[derive(Clone, Serialize, Deserialize)]
+    struct TestPlatform;
+
+    impl pal::Sealing for TestPlatform {
+        type SealError = anyhow::Error;
+        type UnsealError = anyhow::Error;
+
+        fn seal_data(
+            &self,
+            _path: impl AsRef<std::path::Path>,
+            _data: &[u8],
+        ) -> Result<(), Self::SealError> {
+            Ok(())
+        }
+
+        fn unseal_data(
+            &self,
+            _path: impl AsRef<std::path::Path>,
+        ) -> Result<Option<Vec<u8>>, Self::UnsealError> {
+            Ok(None)
+        }
+    }
+
+    impl pal::RA for TestPlatform {
+        type Error = anyhow::Error;
+
+        fn create_attestation_report(
+            &self,
+            _data: &[u8],
+        ) -> Result<pal::AttestationReport, Self::Error> {
+            Err(anyhow!("Not supported in tests"))
+        }
+
+        fn quote_test(&self) -> Result<(), Self::Error> {
+            Err(anyhow!("Not supported in tests"))
+        }
+    }
+
+    impl pal::Machine for TestPlatform {
+        fn machine_id(&self) -> Vec<u8> {
+            vec![]
+        }
+
+        fn cpu_core_num(&self) -> u32 {
+            1
+        }
+
+        fn cpu_feature_level(&self) -> u32 {
+            1
+        }
+    }
+
+    impl pal::MemoryStats for TestPlatform {
+        fn memory_usage(&self) -> pal::MemoryUsage {
+            pal::MemoryUsage {
+                total_peak_used: 0,
+                rust_used: 0,
+                rust_peak_used: 0,
+            }
+        }
+    }
+
+    impl pal::AppInfo for TestPlatform {
+        fn app_version() -> pal::AppVersion {
+            pal::AppVersion {
+                major: 0,
+                minor: 0,
+                patch: 1,
+            }
+        }
+    }
+
+    fn new_test_system(send_mq: &MessageSendQueue) -> System<TestPlatform> {
+        let identity_key = sr25519::Pair::from_seed(&[1u8; 32]);
+        let ecdh_key = identity_key.derive_ecdh_key().unwrap();
+        System::new(
+            TestPlatform,
+            ".".into(),
+            ".".into(),
+            false,
+            Default::default(),
+            identity_key,
+            ecdh_key,
+            false,
+            send_mq,
+            &mut MessageDispatcher::default(),
+            Default::default(),
+            1,
+        )
+    }
+
+    #[test]
+    fn test_clusters_summary() {
+        use crate::secret_channel::SecretMessageChannel;
+
+        let send_mq = MessageSendQueue::default();
+        let mut system = new_test_system(&send_mq);
+        assert!(system.clusters_summary().is_empty());
+
+        let cluster_key = sr25519::Pair::from_seed(&Default::default());
+        let cluster_id = phala_mq::ContractClusterId(Default::default());
+        let cluster = system
+            .contract_clusters
+            .get_cluster_or_default_mut(&cluster_id, &cluster_key);
+        let code_hash = cluster
+            .upload_resource(
+                ALICE.clone(),
+                ResourceType::InkCode,
+                pink::load_test_wasm("hooks_test"),
+            )
+            .unwrap();
+        let effects = system
+            .contract_clusters
+            .instantiate_contract(
+                cluster_id,
+                ALICE,
+                code_hash,
+                vec![0xed, 0x4b, 0x9d, 0x1b],
+                Default::default(),
+                1,
+                1,
+                None,
+            )
+            .unwrap();
+        let mut builder = BlockInfo::builder().block_number(1).now_ms(1);
+        let mut block_info = builder.build();
+        apply_pink_side_effects(
+            effects,
+            cluster_id,
+            &mut system.contracts,
+            system.contract_clusters.get_cluster_mut(&cluster_id).unwrap(),
+            &mut block_info,
+            &system.egress,
+            &system.sidevm_spawner,
+            None,
+        );
+        let contract_id = *system.contracts.keys().next().unwrap();
+
+        let summary = system.clusters_summary();
+        assert_eq!(summary.len(), 1);
+        assert_eq!(summary[0].id, cluster_id);
+        assert_eq!(summary[0].pubkey, hex::encode(cluster_key.public()));
+        assert_eq!(summary[0].contracts.len(), 1);
+        assert_eq!(summary[0].contracts[0].id, contract_id);
+        assert_eq!(summary[0].contracts[0].decrypt_failures, 0);
+
+        // A command encrypted to another key can not be decrypted by the contract
+        let sender = sr25519::Pair::from_seed(&[2u8; 32]);
+        let sender_ecdh_key = sender.derive_ecdh_key().unwrap();
+        let other_pubkey = sr25519::Pair::from_seed(&[3u8; 32])
+            .derive_ecdh_key()
+            .unwrap()
+            .public();
+        let queue = MessageSendQueue::default();
+        let egress = queue.channel(MessageOrigin::Worker(sender.public()), sender.into());
+        SecretMessageChannel::new(&sender_ecdh_key, &egress)
+            .bind_remote_key(Some(&other_pubkey))
+            .push_data(vec![0], contracts::command_topic(contract_id));
+        for message in queue.all_messages() {
+            builder.recv_mq.dispatch(message.message);
+        }
+        let mut block_info = builder.build();
+        let mut env = ExecuteEnv {
+            block: &mut block_info,
+            contract_clusters: &mut system.contract_clusters,
+            log_handler: None,
+        };
+        let contract = system.contracts.get_mut(&contract_id).unwrap();
+        let result = contract.process_next_message(&mut env);
+        assert!(matches!(result, Some(Err(TransactionError::ChannelError))));
+
+        let summary = system.clusters_summary();
+        assert_eq!(summary[0].contracts[0].decrypt_failures, 1);
+    }
+
+    #[cfg(feature = "simulated-cluster-key")]
+    #[test]
+    fn test_deploy_cluster_with_simulated_key() {
+        let send_mq = MessageSendQueue::default();
+        let mut system = new_test_system(&send_mq);
+        let cluster_id = phala_mq::ContractClusterId([1u8; 32]);
+        let event = || BatchDispatchClusterKeyEvent {
+            secret_keys: Default::default(),
+            cluster: cluster_id,
+            expiration: 0,
+        };
+        let mut block = BlockInfo::builder().block_number(1).now_ms(1).build();
+
+        // The clusters whose keys are not dispatched to the worker are skipped by default
+        system
+            .process_cluster_key_distribution(&mut block, MessageOrigin::Gatekeeper, event())
+            .unwrap();
+        assert!(system.clusters_summary().is_empty());
+        assert!(send_mq.all_messages().is_empty());
+
+        system.set_simulate_cluster_keys(true);
+        system
+            .process_cluster_key_distribution(&mut block, MessageOrigin::Gatekeeper, event())
+            .unwrap();
+        let seed = blake2_256(&(b"simulated cluster key", cluster_id).encode());
+        let simulated_pubkey = sr25519::Pair::from_seed(&seed).public();
+        let summary = system.clusters_summary();
+        assert_eq!(summary.len(), 1);
+        assert_eq!(summary[0].id, cluster_id);
+        assert_eq!(summary[0].pubkey, hex::encode(simulated_pubkey));
+        let messages = send_mq.all_messages();
+        assert_eq!(messages.len(), 1);
+        assert!(matches!(
+            messages[0].message.decode_payload(),
+            Some(WorkerClusterReport::ClusterDeployed { id, pubkey })
+                if id == cluster_id && pubkey == simulated_pubkey
+        ));
+
+        // Deploying it again is rejected
+        assert!(system
+            .process_cluster_key_distribution(&mut block, MessageOrigin::Gatekeeper, event())
+            .is_err());
+    }
 }
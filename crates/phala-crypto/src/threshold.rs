@@ -0,0 +1,213 @@
+//! Shamir's secret sharing over GF(256)
+//!
+//! Every byte of the secret is shared with its own random polynomial of degree `threshold - 1`,
+//! so a share has the same length as the secret. Any `threshold` distinct shares recover the
+//! secret, while fewer shares reveal nothing about it.
+
+use crate::CryptoError;
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// A share of a secret, i.e. the evaluation of the sharing polynomials at `index`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyShare {
+    /// The x coordinate, never zero since the secret sits at zero
+    pub index: u8,
+    /// The y coordinate of each secret byte
+    pub data: Vec<u8>,
+}
+
+/// Multiplication in GF(256) with the AES polynomial `x^8 + x^4 + x^3 + x + 1`
+fn gf_mul(mut a: u8, mut b: u8) -> u8 {
+    let mut product = 0u8;
+    for _ in 0..8 {
+        // Branchless to not leak the secret through timing
+        product ^= a & 0u8.wrapping_sub(b & 1);
+        let carry = 0u8.wrapping_sub(a >> 7);
+        a = (a << 1) ^ (carry & 0x1b);
+        b >>= 1;
+    }
+    product
+}
+
+/// Multiplicative inverse in GF(256), computed as `a^254`
+fn gf_inv(a: u8) -> u8 {
+    let mut result = 1u8;
+    let mut base = a;
+    let mut exp = 254u8;
+    while exp > 0 {
+        if exp & 1 == 1 {
+            result = gf_mul(result, base);
+        }
+        base = gf_mul(base, base);
+        exp >>= 1;
+    }
+    result
+}
+
+/// Splits `secret` into the shares at the given `indexes`
+///
+/// `fill_random` provides the coefficients of the sharing polynomials. Parties sharing the same
+/// secret to the same recipient can hand out compatible shares by filling the coefficients
+/// deterministically.
+pub fn split_secret_at(
+    secret: &[u8],
+    threshold: u8,
+    indexes: &[u8],
+    mut fill_random: impl FnMut(&mut [u8]),
+) -> Result<Vec<KeyShare>, CryptoError> {
+    if threshold == 0 {
+        return Err(CryptoError::ShamirInvalidThreshold);
+    }
+    for (i, index) in indexes.iter().enumerate() {
+        if *index == 0 || indexes[..i].contains(index) {
+            return Err(CryptoError::ShamirInvalidShares);
+        }
+    }
+    // coefficients[k * len + i] is the coefficient of x^(k+1) for the i-th byte
+    let len = secret.len();
+    let mut coefficients = vec![0u8; (threshold as usize - 1) * len];
+    fill_random(&mut coefficients);
+
+    let shares = indexes
+        .iter()
+        .map(|&x| {
+            let data = (0..len)
+                .map(|i| {
+                    // Horner's method from the highest degree
+                    let mut y = 0u8;
+                    for k in (0..threshold as usize - 1).rev() {
+                        y = gf_mul(y, x) ^ coefficients[k * len + i];
+                    }
+                    gf_mul(y, x) ^ secret[i]
+                })
+                .collect();
+            KeyShare { index: x, data }
+        })
+        .collect();
+    Ok(shares)
+}
+
+/// Splits `secret` into `shares` shares indexed from 1, any `threshold` of which recover it
+pub fn split_secret(
+    secret: &[u8],
+    threshold: u8,
+    shares: u8,
+    fill_random: impl FnMut(&mut [u8]),
+) -> Result<Vec<KeyShare>, CryptoError> {
+    if threshold > shares {
+        return Err(CryptoError::ShamirInvalidThreshold);
+    }
+    let indexes: Vec<u8> = (1..=shares).collect();
+    split_secret_at(secret, threshold, &indexes, fill_random)
+}
+
+/// Recovers the secret from the given shares with Lagrange interpolation at zero
+///
+/// Exactly `threshold` shares should be given. Fewer shares recover a garbage secret silently.
+pub fn combine_shares(shares: &[KeyShare]) -> Result<Vec<u8>, CryptoError> {
+    let first = shares.first().ok_or(CryptoError::ShamirInvalidShares)?;
+    let len = first.data.len();
+    for (i, share) in shares.iter().enumerate() {
+        if share.index == 0
+            || share.data.len() != len
+            || shares[..i].iter().any(|s| s.index == share.index)
+        {
+            return Err(CryptoError::ShamirInvalidShares);
+        }
+    }
+
+    let mut secret = vec![0u8; len];
+    for (i, share) in shares.iter().enumerate() {
+        // l_i(0) = prod_{j != i} x_j / (x_j - x_i), where subtraction is xor in GF(256)
+        let mut basis = 1u8;
+        for (j, other) in shares.iter().enumerate() {
+            if i != j {
+                basis = gf_mul(
+                    basis,
+                    gf_mul(other.index, gf_inv(other.index ^ share.index)),
+                );
+            }
+        }
+        for (byte, y) in secret.iter_mut().zip(share.data.iter()) {
+            *byte ^= gf_mul(basis, *y);
+        }
+    }
+    Ok(secret)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::RngCore;
+
+    fn fill_random(buf: &mut [u8]) {
+        rand::thread_rng().fill_bytes(buf);
+    }
+
+    #[test]
+    fn gf_arithmetic() {
+        // Known values of the AES field
+        assert_eq!(gf_mul(0x57, 0x83), 0xc1);
+        assert_eq!(gf_mul(0x57, 0x13), 0xfe);
+        for a in 1..=255u8 {
+            assert_eq!(gf_mul(a, gf_inv(a)), 1);
+        }
+    }
+
+    #[test]
+    fn split_and_combine() {
+        let secret = [233_u8; 64];
+        let shares = split_secret(&secret, 3, 5, fill_random).unwrap();
+        assert_eq!(shares.len(), 5);
+        // Any 3 shares recover the secret
+        for a in 0..5 {
+            for b in a + 1..5 {
+                for c in b + 1..5 {
+                    let subset = [shares[a].clone(), shares[b].clone(), shares[c].clone()];
+                    assert_eq!(combine_shares(&subset).unwrap(), secret);
+                }
+            }
+        }
+        // More shares than the threshold also work
+        assert_eq!(combine_shares(&shares).unwrap(), secret);
+        // Fewer shares do not
+        assert_ne!(combine_shares(&shares[..2]).unwrap(), secret);
+    }
+
+    #[test]
+    fn deterministic_shares_are_compatible() {
+        let secret = [7_u8; 64];
+        let fill = |buf: &mut [u8]| buf.iter_mut().enumerate().for_each(|(i, b)| *b = i as u8);
+        // Two parties hand out the shares at their own index independently
+        let mut shares = split_secret_at(&secret, 2, &[3], fill).unwrap();
+        shares.extend(split_secret_at(&secret, 2, &[9], fill).unwrap());
+        assert_eq!(combine_shares(&shares).unwrap(), secret);
+    }
+
+    #[test]
+    fn threshold_of_one_shares_the_secret() {
+        let secret = [1_u8, 2, 3];
+        let shares = split_secret(&secret, 1, 3, fill_random).unwrap();
+        for share in shares {
+            assert_eq!(share.data, secret);
+        }
+    }
+
+    #[test]
+    fn invalid_parameters() {
+        let secret = [0_u8; 64];
+        assert!(split_secret(&secret, 0, 3, fill_random).is_err());
+        assert!(split_secret(&secret, 4, 3, fill_random).is_err());
+        assert!(split_secret_at(&secret, 2, &[0, 1], fill_random).is_err());
+        assert!(split_secret_at(&secret, 2, &[1, 1], fill_random).is_err());
+
+        let shares = split_secret(&secret, 2, 3, fill_random).unwrap();
+        assert!(combine_shares(&[]).is_err());
+        assert!(combine_shares(&[shares[0].clone(), shares[0].clone()]).is_err());
+        let mut truncated = shares[1].clone();
+        truncated.data.pop();
+        assert!(combine_shares(&[shares[0].clone(), truncated]).is_err());
+    }
+}
@@ -1,4 +1,6 @@
-pub use request_scheduler::RequestScheduler;
+pub use request_scheduler::{
+    AcquireError, AcquireOptions, ClassDumpInfo, DumpInfo, Priority, RequestScheduler,
+};
 pub use task_scheduler::TaskScheduler;
 
 mod request_scheduler;
@@ -12,16 +12,58 @@ pub type VirtualTime = u128;
 pub trait FlowIdType: Clone + Send + Eq + Hash + Debug + 'static {}
 impl<T: Clone + Send + Eq + Hash + Debug + 'static> FlowIdType for T {}
 
+/// Priority class of a request.
+///
+/// Requests in a higher class are always served before the ones in lower classes. Requests in the
+/// same class are fair queued by their flows.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Priority {
+    Low = 0,
+    Normal = 1,
+    High = 2,
+}
+
+impl Priority {
+    /// All the classes, from the highest to the lowest.
+    pub const ALL: [Priority; 3] = [Priority::High, Priority::Normal, Priority::Low];
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+impl Default for Priority {
+    fn default() -> Self {
+        Priority::Normal
+    }
+}
+
+/// Options of a single `acquire` call.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AcquireOptions {
+    pub priority: Priority,
+    /// The request is dropped if it can not be served before the deadline.
+    pub deadline: Option<Instant>,
+}
+
 #[derive(Clone)]
 pub struct RequestScheduler<FlowId: FlowIdType> {
     inner: Arc<Mutex<SchedulerInner<FlowId>>>,
 }
 
-pub struct DumpInfo<FlowId> {
+pub struct ClassDumpInfo<FlowId> {
+    pub priority: Priority,
     pub backlog: Vec<(FlowId, VirtualTime)>,
+}
+
+pub struct DumpInfo<FlowId> {
+    /// The backlog of each priority class, from the highest to the lowest.
+    pub classes: Vec<ClassDumpInfo<FlowId>>,
     pub flows: Vec<(FlowId, VirtualTime, VirtualTime)>,
     pub serving: u32,
     pub virtual_time: VirtualTime,
+    pub backlog_cap: usize,
+    pub depth: u32,
 }
 
 #[derive(Error, Debug)]
@@ -30,8 +72,12 @@ pub enum AcquireError {
     Overloaded,
     #[error("canceled while acquiring slot from the fair queue")]
     Canceled,
+    #[error("deadline exceeded while waiting in the fair queue")]
+    DeadlineExceeded,
 }
 
+type AcquireResult<FlowId> = Result<ServingGuard<FlowId>, AcquireError>;
+
 impl<FlowId: FlowIdType> RequestScheduler<FlowId> {
     pub fn new(backlog_cap: usize, depth: u32) -> Self {
         Self {
@@ -45,10 +91,44 @@ impl<FlowId: FlowIdType> RequestScheduler<FlowId> {
         &self,
         flow_id: FlowId,
         weight: u32,
+    ) -> Result<ServingGuard<FlowId>, AcquireError> {
+        self.acquire_with_options(flow_id, weight, Default::default())
+            .await
+    }
+
+    pub async fn acquire_with_options(
+        &self,
+        flow_id: FlowId,
+        weight: u32,
+        options: AcquireOptions,
     ) -> Result<ServingGuard<FlowId>, AcquireError> {
         // Don't merge the following 2 lines of code into one line or you would get a deadlock.
-        let rx = self.inner.lock().unwrap().acquire(flow_id, weight)?;
-        rx.await.or(Err(AcquireError::Canceled))
+        let rx = self
+            .inner
+            .lock()
+            .unwrap()
+            .acquire(flow_id, weight, options)?;
+        let result = match options.deadline {
+            Some(deadline) => {
+                let deadline = tokio::time::Instant::from_std(deadline);
+                tokio::time::timeout_at(deadline, rx)
+                    .await
+                    .or(Err(AcquireError::DeadlineExceeded))?
+            }
+            None => rx.await,
+        };
+        result.or(Err(AcquireError::Canceled))?
+    }
+
+    /// Change the max number of queued requests. Exceeding requests are dropped from the lowest
+    /// priority class.
+    pub fn set_backlog_cap(&self, backlog_cap: usize) {
+        self.inner.lock().unwrap().set_backlog_cap(backlog_cap);
+    }
+
+    /// Change the max number of requests being served concurrently.
+    pub fn set_depth(&self, depth: u32) {
+        self.inner.lock().unwrap().set_depth(depth);
     }
 
     pub fn purge_inactive_flows(&self, duration: Duration) {
@@ -58,10 +138,15 @@ impl<FlowId: FlowIdType> RequestScheduler<FlowId> {
     pub fn dump(&self) -> DumpInfo<FlowId> {
         let inner = self.inner.lock().unwrap();
         DumpInfo {
-            backlog: inner
-                .backlog
+            classes: Priority::ALL
                 .iter()
-                .map(|(k, v)| (v.flow_id.clone(), *k))
+                .map(|&priority| ClassDumpInfo {
+                    priority,
+                    backlog: inner.backlogs[priority.index()]
+                        .iter()
+                        .map(|(k, v)| (v.flow_id.clone(), *k))
+                        .collect(),
+                })
                 .collect(),
             flows: inner
                 .flows
@@ -70,6 +155,8 @@ impl<FlowId: FlowIdType> RequestScheduler<FlowId> {
                 .collect(),
             serving: inner.serving,
             virtual_time: inner.virtual_time,
+            backlog_cap: inner.backlog_cap,
+            depth: inner.depth,
         }
     }
 }
@@ -84,11 +171,13 @@ struct Request<FlowId: FlowIdType> {
     flow_id: FlowId,
     start_tag: VirtualTime,
     cost: VirtualTime,
-    start_signal: Sender<ServingGuard<FlowId>>,
+    deadline: Option<Instant>,
+    start_signal: Sender<AcquireResult<FlowId>>,
 }
 
 pub struct ServingGuard<FlowId: FlowIdType> {
-    queue: RequestScheduler<FlowId>,
+    // None if the guard was never handed out to the requester.
+    queue: Option<RequestScheduler<FlowId>>,
     flow_id: FlowId,
     start_time: Instant,
     actual_cost: Option<VirtualTime>,
@@ -96,10 +185,14 @@ pub struct ServingGuard<FlowId: FlowIdType> {
 
 impl<FlowId: FlowIdType> Drop for ServingGuard<FlowId> {
     fn drop(&mut self) {
+        let queue = match &self.queue {
+            Some(queue) => queue,
+            None => return,
+        };
         let actual_cost = self
             .actual_cost
             .unwrap_or_else(|| self.start_time.elapsed().as_micros() as VirtualTime);
-        self.queue
+        queue
             .inner
             .lock()
             .unwrap()
@@ -113,10 +206,13 @@ impl<FlowId: FlowIdType> ServingGuard<FlowId> {
     }
 }
 
+const N_CLASSES: usize = Priority::ALL.len();
+
 struct SchedulerInner<FlowId: FlowIdType> {
     weak_self: Weak<Mutex<SchedulerInner<FlowId>>>,
     flows: HashMap<FlowId, Flow>,
-    backlog: RBTree<VirtualTime, Request<FlowId>>,
+    /// Backlog of each priority class, indexed by `Priority::index`.
+    backlogs: [RBTree<VirtualTime, Request<FlowId>>; N_CLASSES],
     backlog_cap: usize,
     depth: u32,
     serving: u32,
@@ -125,12 +221,23 @@ struct SchedulerInner<FlowId: FlowIdType> {
 
 unsafe impl<T: FlowIdType> Send for SchedulerInner<T> {}
 
+/// The lowest priority class with queued requests, and the latest start tag in it.
+fn lowest_backlog_tail<FlowId: FlowIdType>(
+    backlogs: &[RBTree<VirtualTime, Request<FlowId>>; N_CLASSES],
+) -> Option<(Priority, VirtualTime)> {
+    Priority::ALL.iter().rev().find_map(|&priority| {
+        backlogs[priority.index()]
+            .get_last()
+            .map(|(start_tag, _)| (priority, *start_tag))
+    })
+}
+
 impl<FlowId: FlowIdType> SchedulerInner<FlowId> {
     fn new(backlog_cap: usize, depth: u32, weak_self: Weak<Mutex<SchedulerInner<FlowId>>>) -> Self {
         Self {
             weak_self,
             flows: HashMap::new(),
-            backlog: RBTree::new(),
+            backlogs: [RBTree::new(), RBTree::new(), RBTree::new()],
             backlog_cap,
             depth,
             serving: 0,
@@ -138,11 +245,27 @@ impl<FlowId: FlowIdType> SchedulerInner<FlowId> {
         }
     }
 
+    fn backlog_len(&self) -> usize {
+        self.backlogs.iter().map(|backlog| backlog.len()).sum()
+    }
+
     fn acquire(
         &mut self,
         flow_id: FlowId,
         weight: u32,
-    ) -> Result<Receiver<ServingGuard<FlowId>>, AcquireError> {
+        options: AcquireOptions,
+    ) -> Result<Receiver<AcquireResult<FlowId>>, AcquireError> {
+        if let Some(deadline) = options.deadline {
+            if deadline <= Instant::now() {
+                return Err(AcquireError::DeadlineExceeded);
+            }
+        }
+
+        if self.backlog_len() >= self.backlog_cap {
+            // Make room by the requests nobody is waiting for any more.
+            self.drop_expired();
+        }
+        let backlog_len = self.backlog_len();
         let flow = self.flows.entry(flow_id.clone()).or_insert_with(|| Flow {
             previous_finish_tag: 0,
             average_cost: 0,
@@ -155,22 +278,24 @@ impl<FlowId: FlowIdType> SchedulerInner<FlowId> {
         let finish_tag = start_tag + cost;
         flow.previous_finish_tag = finish_tag;
 
-        if self.backlog.len() >= self.backlog_cap {
-            let (max_start_tag, _) = self
-                .backlog
-                .get_last()
-                .expect("Get the latest request from non-empty backlog should not fail");
-            if start_tag >= *max_start_tag {
-                flow.previous_finish_tag -= cost;
-                return Err(AcquireError::Overloaded);
-            }
+        if backlog_len >= self.backlog_cap {
+            // A request can only take the place of a request in a lower class, or a later
+            // request in the same class.
+            let victim = match lowest_backlog_tail(&self.backlogs) {
+                Some((priority, max_start_tag))
+                    if priority < options.priority
+                        || (priority == options.priority && start_tag < max_start_tag) =>
+                {
+                    priority
+                }
+                _ => {
+                    flow.previous_finish_tag -= cost;
+                    return Err(AcquireError::Overloaded);
+                }
+            };
             // Drop the previous low priority request. This would cancel the corresponding
             // `async acquire`.
-            if let Some((_, req)) = self.backlog.pop_last() {
-                if let Some(flow) = self.flows.get_mut(&req.flow_id) {
-                    flow.previous_finish_tag -= req.cost;
-                }
-            }
+            self.evict_last(victim);
         }
 
         let (tx, rx) = channel();
@@ -179,13 +304,14 @@ impl<FlowId: FlowIdType> SchedulerInner<FlowId> {
             flow_id,
             start_tag,
             cost,
+            deadline: options.deadline,
             start_signal: tx,
         };
 
         if self.serving < self.depth {
             self.dispatch(request);
         } else {
-            self.backlog.insert(start_tag, request);
+            self.backlogs[options.priority.index()].insert(start_tag, request);
         }
 
         Ok(rx)
@@ -196,12 +322,76 @@ impl<FlowId: FlowIdType> SchedulerInner<FlowId> {
             flow.average_cost = (flow.average_cost * 4 + actual_cost) / 5;
         }
         self.serving -= 1;
-        self.try_pickup_next();
+        self.fill_slots();
+    }
+
+    fn set_backlog_cap(&mut self, backlog_cap: usize) {
+        self.backlog_cap = backlog_cap;
+        while self.backlog_len() > self.backlog_cap {
+            match lowest_backlog_tail(&self.backlogs) {
+                Some((priority, _)) => self.evict_last(priority),
+                None => break,
+            }
+        }
     }
 
-    fn try_pickup_next(&mut self) {
-        if let Some((_, request)) = self.backlog.pop_first() {
-            self.dispatch(request)
+    fn set_depth(&mut self, depth: u32) {
+        self.depth = depth;
+        self.fill_slots();
+    }
+
+    fn evict_last(&mut self, priority: Priority) {
+        if let Some((_, req)) = self.backlogs[priority.index()].pop_last() {
+            self.abandon(req, AcquireError::Overloaded);
+        }
+    }
+
+    /// Drop a queued request and give back its virtual time to the flow.
+    fn abandon(&mut self, request: Request<FlowId>, error: AcquireError) {
+        if let Some(flow) = self.flows.get_mut(&request.flow_id) {
+            flow.previous_finish_tag = flow.previous_finish_tag.saturating_sub(request.cost);
+        }
+        let _ = request.start_signal.send(Err(error));
+    }
+
+    /// Drop the queued requests whose deadline has passed or whose requester has gone.
+    fn drop_expired(&mut self) {
+        let now = Instant::now();
+        for priority in Priority::ALL {
+            let backlog = core::mem::replace(&mut self.backlogs[priority.index()], RBTree::new());
+            for (start_tag, request) in backlog {
+                if request.start_signal.is_closed() {
+                    self.abandon(request, AcquireError::Canceled);
+                } else if matches!(request.deadline, Some(deadline) if deadline <= now) {
+                    self.abandon(request, AcquireError::DeadlineExceeded);
+                } else {
+                    self.backlogs[priority.index()].insert(start_tag, request);
+                }
+            }
+        }
+    }
+
+    /// Pop the next request to serve, dropping the expired ones.
+    fn pop_next(&mut self) -> Option<Request<FlowId>> {
+        let now = Instant::now();
+        for priority in Priority::ALL {
+            while let Some((_, request)) = self.backlogs[priority.index()].pop_first() {
+                if matches!(request.deadline, Some(deadline) if deadline <= now) {
+                    self.abandon(request, AcquireError::DeadlineExceeded);
+                    continue;
+                }
+                return Some(request);
+            }
+        }
+        None
+    }
+
+    fn fill_slots(&mut self) {
+        while self.serving < self.depth {
+            match self.pop_next() {
+                Some(request) => self.dispatch(request),
+                None => break,
+            }
         }
     }
 
@@ -209,20 +399,23 @@ impl<FlowId: FlowIdType> SchedulerInner<FlowId> {
         self.serving += 1;
         self.virtual_time = request.start_tag;
         let guard = ServingGuard {
-            queue: RequestScheduler {
+            queue: Some(RequestScheduler {
                 inner: self
                     .weak_self
                     .upgrade()
                     .expect("fair queue: Failed to upgrade weak self"),
-            },
+            }),
             flow_id: request.flow_id,
             start_time: Instant::now(),
             actual_cost: None,
         };
 
-        // If the receiver side has been dropped, the ServingGuard would be dropped here
-        // and would further try to pickup next request.
-        let _ = request.start_signal.send(guard);
+        // If the receiver side has been dropped, take back the slot here rather than dropping
+        // the guard, which would lock the scheduler again.
+        if let Err(Ok(mut guard)) = request.start_signal.send(Ok(guard)) {
+            guard.queue = None;
+            self.serving -= 1;
+        }
     }
 
     fn purge_inactive_flows(&mut self, duration: Duration) {
@@ -260,6 +453,114 @@ mod test {
         tokio::time::sleep(Duration::from_millis(t)).await;
     }
 
+    fn enqueue(
+        q: &RequestScheduler<u32>,
+        flow_id: u32,
+        priority: Priority,
+        deadline: Option<Instant>,
+    ) -> Receiver<AcquireResult<u32>> {
+        q.inner
+            .lock()
+            .unwrap()
+            .acquire(flow_id, 1, AcquireOptions { priority, deadline })
+            .unwrap()
+    }
+
+    fn served(rx: &mut Receiver<AcquireResult<u32>>) -> Option<ServingGuard<u32>> {
+        match rx.try_recv() {
+            Ok(Ok(guard)) => Some(guard),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_higher_priority_served_first() {
+        let queue = RequestScheduler::new(10, 1);
+        let guard = served(&mut enqueue(&queue, 0, Priority::Normal, None)).unwrap();
+        let mut low = enqueue(&queue, 1, Priority::Low, None);
+        let mut high = enqueue(&queue, 2, Priority::High, None);
+        drop(guard);
+        assert!(served(&mut low).is_none());
+        let guard = served(&mut high).unwrap();
+        drop(guard);
+        assert!(served(&mut low).is_some());
+    }
+
+    #[test]
+    fn test_overload_evicts_lower_priority() {
+        let queue = RequestScheduler::new(1, 1);
+        let _guard = served(&mut enqueue(&queue, 0, Priority::Normal, None)).unwrap();
+        let mut low = enqueue(&queue, 1, Priority::Low, None);
+        let _normal = enqueue(&queue, 2, Priority::Normal, None);
+        assert!(matches!(low.try_recv(), Ok(Err(AcquireError::Overloaded))));
+        let result = queue.inner.lock().unwrap().acquire(
+            3,
+            1,
+            AcquireOptions {
+                priority: Priority::Low,
+                deadline: None,
+            },
+        );
+        assert!(matches!(result, Err(AcquireError::Overloaded)));
+    }
+
+    #[test]
+    fn test_expired_request_dropped() {
+        let queue = RequestScheduler::new(10, 1);
+        let guard = served(&mut enqueue(&queue, 0, Priority::Normal, None)).unwrap();
+        let deadline = Instant::now() + Duration::from_millis(1);
+        let mut expiring = enqueue(&queue, 1, Priority::High, Some(deadline));
+        let mut normal = enqueue(&queue, 2, Priority::Normal, None);
+        std::thread::sleep(Duration::from_millis(5));
+        drop(guard);
+        assert!(matches!(
+            expiring.try_recv(),
+            Ok(Err(AcquireError::DeadlineExceeded))
+        ));
+        assert!(served(&mut normal).is_some());
+    }
+
+    #[test]
+    fn test_expired_request_pruned_when_full() {
+        let queue = RequestScheduler::new(2, 1);
+        let _guard = served(&mut enqueue(&queue, 0, Priority::Normal, None)).unwrap();
+        let deadline = Instant::now() + Duration::from_millis(1);
+        let mut expiring = enqueue(&queue, 1, Priority::Normal, Some(deadline));
+        let abandoned = enqueue(&queue, 2, Priority::Normal, None);
+        drop(abandoned);
+        std::thread::sleep(Duration::from_millis(5));
+        // The backlog is full of dead requests, which give their places to the new ones
+        let _low = enqueue(&queue, 3, Priority::Low, None);
+        let _normal = enqueue(&queue, 4, Priority::Normal, None);
+        assert!(matches!(
+            expiring.try_recv(),
+            Ok(Err(AcquireError::DeadlineExceeded))
+        ));
+        let info = queue.dump();
+        assert_eq!(info.classes[1].backlog.len(), 1);
+        assert_eq!(info.classes[2].backlog.len(), 1);
+    }
+
+    #[test]
+    fn test_resize() {
+        let queue = RequestScheduler::new(10, 1);
+        let _guard = served(&mut enqueue(&queue, 0, Priority::Normal, None)).unwrap();
+        let mut first = enqueue(&queue, 1, Priority::Normal, None);
+        let mut second = enqueue(&queue, 1, Priority::Normal, None);
+        let mut third = enqueue(&queue, 1, Priority::Normal, None);
+        queue.set_backlog_cap(2);
+        assert!(matches!(
+            third.try_recv(),
+            Ok(Err(AcquireError::Overloaded))
+        ));
+        queue.set_depth(2);
+        assert!(served(&mut first).is_some());
+        assert!(served(&mut second).is_none());
+        let info = queue.dump();
+        assert_eq!(info.serving, 2);
+        assert_eq!(info.classes[1].backlog.len(), 1);
+    }
+
     #[tokio::test]
     #[ignore]
     async fn test_eq_cost_eq_weight_normal() {
@@ -56,11 +56,19 @@ pub mod messaging {
     bind_topic!(ClusterEvent, b"phala/cluster/event");
     #[derive(Encode, Decode, Debug)]
     pub enum ClusterEvent {
-        // TODO.shelven: enable add and remove workers
         DeployCluster {
             cluster: ContractClusterId,
             workers: Vec<WorkerIdentity>,
         },
+        /// Share the key of a deployed cluster to the newly added workers.
+        ///
+        /// The `pubkey` is the on-chain cluster pubkey, which tells the Gatekeeper which master
+        /// key the cluster key was derived from.
+        AddWorkers {
+            cluster: ContractClusterId,
+            pubkey: ClusterPublicKey,
+            workers: Vec<WorkerIdentity>,
+        },
     }
 
     bind_topic!(ContractOperation<CodeHash, AccountId>, b"phala/contract/op");
@@ -110,6 +118,22 @@ pub mod messaging {
             cluster_id: ContractClusterId,
             deployer: AccountId,
         },
+        /// The contract usage of a cluster in the period ending at `block_number`.
+        ///
+        /// All the workers of the cluster report the same commands and storage, which are
+        /// charged only once. The queries are served by the reporting worker alone.
+        ClusterUsage {
+            cluster_id: ContractClusterId,
+            /// The last block of the period.
+            block_number: u32,
+            /// The number of contract commands processed, by the account sending them. The
+            /// commands not sent by an account are counted under `None`.
+            commands: Vec<(Option<MqAccountId>, u32)>,
+            /// The number of contract queries served by the reporting worker.
+            queries: u32,
+            /// The size of the cluster storage at the end of the period, in bytes.
+            storage_bytes: u64,
+        },
     }
 
     #[derive(Encode, Decode, Clone, PartialEq, Eq, Debug)]
@@ -143,6 +167,25 @@ pub mod messaging {
             resource_type: ResourceType,
             resource_data: Vec<u8>,
         },
+        /// Set the gas schedule version used by the contracts and sidevm instances in the cluster.
+        ///
+        /// `None` to fall back to the legacy schedules.
+        SetGasSchedule {
+            cluster: ContractClusterId,
+            version: Option<u32>,
+        },
+        /// Remove a worker from the cluster.
+        ///
+        /// The removed worker drops the cluster and all the contracts in it.
+        RemoveWorker {
+            cluster: ContractClusterId,
+            worker: WorkerPublicKey,
+        },
+        /// Destroy a single contract in the cluster.
+        DestroyContract {
+            cluster: ContractClusterId,
+            contract: ContractId,
+        },
     }
 
     impl<AccountId, BlockNumber> ClusterOperation<AccountId, BlockNumber> {
@@ -162,10 +205,41 @@ pub mod messaging {
 
 }
 
+/// Who can deploy contracts and upload code to a cluster.
 #[derive(Encode, Decode, Clone, PartialEq, Eq, Debug, TypeInfo)]
 pub enum ClusterPermission<AccountId> {
     Public,
     OnlyOwner(AccountId),
+    /// The cluster owner and the accounts granted the `Deployer` or `CodeUploader` role.
+    AllowList,
+}
+
+/// The rights in a cluster that can be granted to accounts besides the cluster owner.
+///
+/// The cluster owner implicitly holds all the roles. Deploying and uploading code to a
+/// `Public` or `OnlyOwner` cluster are governed by the `ClusterPermission` instead of the roles.
+#[derive(Encode, Decode, Clone, Copy, PartialEq, Eq, Debug, TypeInfo)]
+pub enum ClusterRole {
+    /// Instantiate contracts in the cluster.
+    Deployer,
+    /// Upload ink and sidevm code to the cluster.
+    CodeUploader,
+    /// Set the log handler of the cluster.
+    LogHandlerAdmin,
+    /// Destroy any contract in the cluster.
+    Destroyer,
+    /// Send contract commands paid by the cluster deposit rather than by the sender.
+    SponsoredSender,
+}
+
+impl ClusterRole {
+    pub const ALL: [ClusterRole; 5] = [
+        ClusterRole::Deployer,
+        ClusterRole::CodeUploader,
+        ClusterRole::LogHandlerAdmin,
+        ClusterRole::Destroyer,
+        ClusterRole::SponsoredSender,
+    ];
 }
 
 #[derive(Encode, Decode, Clone, PartialEq, Eq, Debug, TypeInfo)]
@@ -579,6 +579,21 @@ pub mod messaging {
         PhalaLaunched,
         /// Fix the payout duration problem in unresponsive state
         UnrespFix,
+        /// A gatekeeper's share of the master key history for a newly registered gatekeeper
+        ///
+        /// MessageOrigin::Worker -> ALL
+        ///
+        /// The new gatekeeper recovers the master keys once it receives `threshold` shares, so no
+        /// single gatekeeper ever sends a whole master key.
+        MasterKeyShare(MasterKeyShareEvent),
+        /// A gatekeeper's random contribution to a master key rotation
+        ///
+        /// MessageOrigin::Worker -> ALL
+        ///
+        /// The contribution is split into chunks of a bounded number of gatekeepers. The rotated
+        /// master key is derived from the first `threshold` contributions with all their chunks on
+        /// chain, so no single gatekeeper decides it.
+        MasterKeyContribution(MasterKeyContributionEvent),
     }
 
     impl GatekeeperEvent {
@@ -595,6 +610,30 @@ pub mod messaging {
         }
     }
 
+    #[derive(Encode, Decode, Clone, Debug, PartialEq, Eq, TypeInfo)]
+    pub struct MasterKeyShareEvent {
+        /// The newly registered gatekeeper to recover the master key
+        pub dest: WorkerPublicKey,
+        /// The number of shares needed to recover the master key
+        pub threshold: u8,
+        /// The index of the share, the position of the sender among the gatekeepers on chain
+        /// except `dest` when `dest` was registered, starting from 1
+        pub index: u8,
+        /// The share of each historical master key with its rotation id and block height
+        pub encrypted_shares: Vec<(u64, u32, EncryptedKey)>,
+    }
+
+    #[derive(Encode, Decode, Clone, Debug, PartialEq, Eq, TypeInfo)]
+    pub struct MasterKeyContributionEvent {
+        pub rotation_id: u64,
+        /// The index of this chunk in the contribution of the sender
+        pub chunk: u32,
+        /// The number of chunks the contribution of the sender is split into
+        pub chunks: u32,
+        /// The contribution encrypted to each gatekeeper covered by this chunk
+        pub secret_keys: BTreeMap<WorkerPublicKey, EncryptedKey>,
+    }
+
     pub type RandomNumber = [u8; 32];
     #[derive(Encode, Decode, Clone, Debug, PartialEq, Eq, TypeInfo)]
     pub struct RandomNumberEvent {
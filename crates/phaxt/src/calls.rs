@@ -0,0 +1,89 @@
+//! Calls not covered by the bundled metadata yet.
+//!
+//! Their indices are resolved from the metadata of the connected node, so signing them fails with
+//! a metadata error if the runtime of the node doesn't have the call.
+
+use crate::{khala, AccountId, Config, ExtrinsicParams, RpcClient};
+use parity_scale_codec::{Decode, Encode};
+use phala_types::{messaging::SignedMessage, WorkerRegistrationInfo};
+
+pub type Submittable<'client, C> = subxt::SubmittableExtrinsic<
+    'client,
+    Config,
+    ExtrinsicParams,
+    C,
+    khala::DispatchError,
+    khala::Event,
+>;
+
+/// `PhalaMq::sync_offchain_messages`, syncing a batch of egress messages in one extrinsic.
+#[derive(Encode, Debug)]
+pub struct SyncOffchainMessages {
+    pub signed_messages: Vec<SignedMessage>,
+}
+
+impl subxt::Call for SyncOffchainMessages {
+    const PALLET: &'static str = "PhalaMq";
+    const FUNCTION: &'static str = "sync_offchain_messages";
+}
+
+pub fn sync_offchain_messages(
+    client: &RpcClient,
+    signed_messages: Vec<SignedMessage>,
+) -> Submittable<'_, SyncOffchainMessages> {
+    subxt::SubmittableExtrinsic::new(client, SyncOffchainMessages { signed_messages })
+}
+
+/// The attestation of `PhalaRegistry::register_worker`, with the `SgxDcap` variant missing in the
+/// bundled metadata.
+#[derive(Encode, Debug)]
+pub enum Attestation {
+    SgxIas {
+        ra_report: Vec<u8>,
+        signature: Vec<u8>,
+        raw_signing_cert: Vec<u8>,
+    },
+    SgxDcap {
+        quote: Vec<u8>,
+        collateral: SgxQuoteCollateral,
+    },
+}
+
+/// The collateral to validate a DCAP quote, as served by the PCCS
+#[derive(Encode, Decode, Debug)]
+pub struct SgxQuoteCollateral {
+    pub root_ca_crl: Vec<u8>,
+    pub pck_crl: Vec<u8>,
+    pub tcb_info_issuer_chain: Vec<u8>,
+    pub tcb_info: Vec<u8>,
+    pub tcb_info_signature: Vec<u8>,
+    pub qe_identity_issuer_chain: Vec<u8>,
+    pub qe_identity: Vec<u8>,
+    pub qe_identity_signature: Vec<u8>,
+}
+
+/// `PhalaRegistry::register_worker`, accepting both IAS reports and DCAP quotes.
+#[derive(Encode, Debug)]
+pub struct RegisterWorker {
+    pub pruntime_info: WorkerRegistrationInfo<AccountId>,
+    pub attestation: Attestation,
+}
+
+impl subxt::Call for RegisterWorker {
+    const PALLET: &'static str = "PhalaRegistry";
+    const FUNCTION: &'static str = "register_worker";
+}
+
+pub fn register_worker(
+    client: &RpcClient,
+    pruntime_info: WorkerRegistrationInfo<AccountId>,
+    attestation: Attestation,
+) -> Submittable<'_, RegisterWorker> {
+    subxt::SubmittableExtrinsic::new(
+        client,
+        RegisterWorker {
+            pruntime_info,
+            attestation,
+        },
+    )
+}
@@ -15,6 +15,7 @@ pub mod kusama {}
 #[derive(Encode, Decode, Clone, PartialEq, Eq, TypeInfo, PartialOrd, Ord, Debug)]
 pub struct ParaId(pub u32);
 
+pub mod calls;
 pub mod rpc;
 
 pub type ParachainApi = khala::RuntimeApi<DefaultConfig, ExtrinsicParams>;
@@ -62,6 +62,11 @@ pub enum PinkEvent {
     SidevmMessage(Vec<u8>),
     /// CacheOperation
     CacheOp(CacheOp),
+    /// Upgrade the running side VM to the given code, keeping its pending input messages.
+    UpgradeSidevm {
+        /// The hash of the new sidevm code.
+        code_hash: Hash,
+    },
 }
 
 #[derive(Encode, Decode, Debug)]
@@ -127,6 +132,14 @@ pub fn start_sidevm(code_hash: Hash, auto_restart: bool) {
     emit_event::<PinkEnvironment, _>(PinkEvent::StartSidevm { code_hash, auto_restart })
 }
 
+/// Upgrade the associated sidevm instance to the given code.
+///
+/// The code must have been uploaded to the cluster. The instance keeps its VmId, cache and
+/// pending input messages, and the new program can read an upgrade hint on start.
+pub fn upgrade_sidevm(code_hash: Hash) {
+    emit_event::<PinkEnvironment, _>(PinkEvent::UpgradeSidevm { code_hash })
+}
+
 /// Push a message to the associated sidevm instance.
 pub fn push_sidevm_message(message: Vec<u8>) {
     emit_event::<PinkEnvironment, _>(PinkEvent::SidevmMessage(message))
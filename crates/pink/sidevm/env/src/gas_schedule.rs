@@ -0,0 +1,217 @@
+//! Versioned gas schedules shared by the pink runtime and the sidevm instrumentation.
+//!
+//! A cluster selects one schedule by version so that the ink contracts and the sidevm programs
+//! running in it are charged with the same per-instruction weights. Published versions must never
+//! be changed, add a new version instead. New weights can be generated on the reference machine
+//! with the `gas_schedule` benchmark in the `pink-sidevm-host-runtime` crate.
+
+use scale::{Decode, Encode};
+
+/// Weights of the wasm instructions, in picoseconds on the reference machine.
+///
+/// The field names and semantics follow `pallet_contracts::InstructionWeights`, plus the float
+/// instructions which are allowed in sidevm.
+#[derive(Encode, Decode, Clone, Debug, PartialEq, Eq)]
+pub struct InstructionWeights {
+    pub i64const: u32,
+    pub i64load: u32,
+    pub i64store: u32,
+    pub select: u32,
+    pub r#if: u32,
+    pub br: u32,
+    pub br_if: u32,
+    pub br_table: u32,
+    pub br_table_per_entry: u32,
+    pub call: u32,
+    pub call_indirect: u32,
+    pub call_indirect_per_param: u32,
+    pub local_get: u32,
+    pub local_set: u32,
+    pub local_tee: u32,
+    pub global_get: u32,
+    pub global_set: u32,
+    pub memory_current: u32,
+    pub memory_grow: u32,
+    pub i64clz: u32,
+    pub i64ctz: u32,
+    pub i64popcnt: u32,
+    pub i64eqz: u32,
+    pub i64extendsi32: u32,
+    pub i64extendui32: u32,
+    pub i32wrapi64: u32,
+    pub i64eq: u32,
+    pub i64ne: u32,
+    pub i64lts: u32,
+    pub i64ltu: u32,
+    pub i64gts: u32,
+    pub i64gtu: u32,
+    pub i64les: u32,
+    pub i64leu: u32,
+    pub i64ges: u32,
+    pub i64geu: u32,
+    pub i64add: u32,
+    pub i64sub: u32,
+    pub i64mul: u32,
+    pub i64divs: u32,
+    pub i64divu: u32,
+    pub i64rems: u32,
+    pub i64remu: u32,
+    pub i64and: u32,
+    pub i64or: u32,
+    pub i64xor: u32,
+    pub i64shl: u32,
+    pub i64shrs: u32,
+    pub i64shru: u32,
+    pub i64rotl: u32,
+    pub i64rotr: u32,
+    pub f64const: u32,
+    pub f64load: u32,
+    pub f64store: u32,
+    pub f64convert: u32,
+    pub f64cmp: u32,
+    pub f64low: u32,
+    pub f64calc: u32,
+}
+
+/// A versioned gas schedule.
+#[derive(Encode, Decode, Clone, Debug, PartialEq, Eq)]
+pub struct GasSchedule {
+    /// The version of the schedule. Identical versions always carry identical weights.
+    pub version: u32,
+    /// Weights of the wasm instructions.
+    pub instruction_weights: InstructionWeights,
+}
+
+static SCHEDULE_V1: GasSchedule = GasSchedule {
+    version: 1,
+    instruction_weights: InstructionWeights::v1(),
+};
+
+impl GasSchedule {
+    /// The latest published version.
+    pub const LATEST_VERSION: u32 = 1;
+
+    /// Get the schedule of given version. Returns `None` if the version is unknown.
+    pub fn by_version(version: u32) -> Option<&'static Self> {
+        match version {
+            1 => Some(&SCHEDULE_V1),
+            _ => None,
+        }
+    }
+
+    /// Get the latest schedule.
+    pub fn latest() -> &'static Self {
+        Self::by_version(Self::LATEST_VERSION).expect("The latest version must exist")
+    }
+}
+
+impl InstructionWeights {
+    // Values are taken from the pallet-contracts at polkadot-v0.9.27
+    const fn v1() -> Self {
+        Self {
+            i64const: 2960,
+            i64load: 7280,
+            i64store: 8360,
+            select: 5980,
+            r#if: 9990,
+            br: 3060,
+            br_if: 5770,
+            br_table: 7170,
+            br_table_per_entry: 40,
+            call: 68540,
+            call_indirect: 85180,
+            call_indirect_per_param: 1760,
+            local_get: 3050,
+            local_set: 3900,
+            local_tee: 3030,
+            global_get: 9050,
+            global_set: 11140,
+            memory_current: 3640,
+            memory_grow: 3640,
+            i64clz: 3140,
+            i64ctz: 3040,
+            i64popcnt: 2970,
+            i64eqz: 3160,
+            i64extendsi32: 2890,
+            i64extendui32: 2830,
+            i32wrapi64: 3140,
+            i64eq: 4740,
+            i64ne: 4720,
+            i64lts: 4680,
+            i64ltu: 4690,
+            i64gts: 4720,
+            i64gtu: 4840,
+            i64les: 4730,
+            i64leu: 4710,
+            i64ges: 4660,
+            i64geu: 4690,
+            i64add: 4450,
+            i64sub: 4520,
+            i64mul: 4520,
+            i64divs: 11070,
+            i64divu: 11620,
+            i64rems: 11090,
+            i64remu: 11730,
+            i64and: 4500,
+            i64or: 4480,
+            i64xor: 4570,
+            i64shl: 4740,
+            i64shrs: 4680,
+            i64shru: 4700,
+            i64rotl: 4690,
+            i64rotr: 4700,
+            f64const: 2960,
+            f64load: 7280,
+            f64store: 8360,
+            f64convert: 4700,
+            f64cmp: 4700,
+            f64low: 4700,
+            f64calc: 21620,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Fingerprints of the published schedules. A version must keep its fingerprint forever.
+    const PUBLISHED: &[(u32, u64)] = &[(1, 0x3a2a7e5c25a4c213)];
+
+    fn fingerprint(schedule: &GasSchedule) -> u64 {
+        // FNV-1a
+        schedule
+            .encode()
+            .iter()
+            .fold(0xcbf29ce484222325, |hash, byte| {
+                (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+            })
+    }
+
+    #[test]
+    fn published_schedules_never_change() {
+        for (version, expected) in PUBLISHED {
+            let schedule = GasSchedule::by_version(*version).expect("Published version missing");
+            assert_eq!(schedule.version, *version);
+            assert_eq!(
+                fingerprint(schedule),
+                *expected,
+                "Gas schedule v{} has changed, publish a new version instead",
+                version
+            );
+        }
+    }
+
+    #[test]
+    fn every_version_is_published() {
+        for version in 1..=GasSchedule::LATEST_VERSION {
+            assert!(
+                PUBLISHED.iter().any(|(v, _)| *v == version),
+                "Pin the fingerprint of gas schedule v{}",
+                version
+            );
+        }
+        assert!(GasSchedule::by_version(0).is_none());
+        assert!(GasSchedule::by_version(GasSchedule::LATEST_VERSION + 1).is_none());
+    }
+}
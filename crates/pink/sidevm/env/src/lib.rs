@@ -21,6 +21,7 @@ pub use pink_sidevm_macro::main;
 pub use tasks::spawn;
 
 mod args_stack;
+pub mod gas_schedule;
 mod ocall_def;
 pub mod tasks;
 pub mod messages;
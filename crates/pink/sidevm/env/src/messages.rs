@@ -35,3 +35,24 @@ pub enum SystemMessage {
         output: Vec<u8>,
     },
 }
+
+/// Requests emitted by a sidevm instance to be carried out by the host on behalf of the owning
+/// contract.
+#[derive(Encode, Decode, Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum OutgoingRequest {
+    /// Push a phala-mq message to the given topic, signed as the owning contract.
+    Message { topic: Vec<u8>, payload: Vec<u8> },
+    /// Submit an ink message to the owning contract as a command.
+    ///
+    /// The command goes through the chain before being dispatched to the contract, so that every
+    /// worker in the cluster executes it deterministically.
+    ContractCommand { message: Vec<u8> },
+}
+
+/// Hint given to a sidevm instance which was started by a hot-upgrade of a running instance.
+#[derive(Encode, Decode, Debug, Clone, PartialEq, Eq)]
+pub struct UpgradeHint {
+    /// The code hash of the program running before the upgrade.
+    pub previous_code_hash: H256,
+}
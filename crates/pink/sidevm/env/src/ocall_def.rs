@@ -1,5 +1,6 @@
 use super::*;
 use crate::args_stack::{I32Convertible, RetDecode, StackedArgs};
+use crate::messages::{OutgoingRequest, UpgradeHint};
 use crate::tls::{TlsClientConfig, TlsServerConfig};
 use std::borrow::Cow;
 
@@ -89,6 +90,10 @@ pub trait OcallFuncs {
     #[ocall(id = 220)]
     fn log(level: log::Level, message: &str) -> Result<()>;
 
+    /// Report the panic message of the program. It is kept by the host for crash diagnostics.
+    #[ocall(id = 221)]
+    fn report_panic(message: &str) -> Result<()>;
+
     /// Get value from the local cache.
     #[ocall(id = 230, encode_output)]
     fn local_cache_get(key: &[u8]) -> Result<Option<Vec<u8>>>;
@@ -110,6 +115,16 @@ pub trait OcallFuncs {
     /// Create input channel
     #[ocall(id = 240, encode_output)]
     fn create_input_channel(ch: InputChannel) -> Result<i32>;
+
+    /// Emit a request to push a message to the chain or a command to the owning ink contract.
+    ///
+    /// Returns `ResourceLimited` if the outgoing rate limit or queue capacity is exceeded.
+    #[ocall(id = 241, encode_input)]
+    fn emit_outgoing_request(request: OutgoingRequest) -> Result<()>;
+
+    /// Get the upgrade hint if the instance was started by a hot-upgrade.
+    #[ocall(id = 242, encode_output)]
+    fn upgrade_hint() -> Result<Option<UpgradeHint>>;
 }
 
 #[repr(u8)]
@@ -68,6 +68,8 @@ impl App {
                 inner.args.gas_per_breath,
                 crate::simple_cache(),
                 weight,
+                None,
+                None,
             )
             .unwrap();
         inner.instances.insert(id, sender);
@@ -0,0 +1,237 @@
+//! Generate the instruction weights for a new gas schedule version.
+//!
+//! Run it on the reference machine with:
+//!
+//!     cargo bench -p pink-sidevm-host-runtime --bench gas_schedule
+//!
+//! Each instruction is measured by running a loop of the instruction along with its operands, and
+//! then subtracting the time of running a loop of the operands only. The result is printed as an
+//! `InstructionWeights` literal in picoseconds, which can be added as a new version in
+//! `pink_sidevm_env::gas_schedule`. Published versions must never be changed.
+
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use wasmer::{imports, Instance, Module, NativeFunc, Store, Universal};
+use wasmer_compiler_singlepass::Singlepass;
+
+/// Number of loop iterations in each run.
+const ITERATIONS: i32 = 100_000;
+/// Number of times the body is repeated in each iteration, to amortize the loop overhead.
+const REPEAT: usize = 20;
+/// Number of runs for each body. The fastest one is taken.
+const RUNS: usize = 10;
+
+struct Bench {
+    /// Name of the field in `InstructionWeights`.
+    name: &'static str,
+    /// The instruction with its operands.
+    body: String,
+    /// The operands only.
+    baseline: String,
+    /// Number of measured units in the body.
+    units: u32,
+}
+
+fn bench(name: &'static str, body: &str, baseline: &str) -> Bench {
+    Bench {
+        name,
+        body: body.into(),
+        baseline: baseline.into(),
+        units: 1,
+    }
+}
+
+fn unary(name: &'static str, operand: &str, op: &str) -> Bench {
+    bench(
+        name,
+        &format!("{operand} {op} drop"),
+        &format!("{operand} drop"),
+    )
+}
+
+fn binary(name: &'static str, operand: &str, op: &str) -> Bench {
+    bench(
+        name,
+        &format!("{operand} {operand} {op} drop"),
+        &format!("{operand} {operand} drop drop"),
+    )
+}
+
+fn all_benches() -> Vec<Bench> {
+    const L: &str = "local.get $l";
+    const I: &str = "local.get $i";
+    const F: &str = "local.get $f";
+
+    let br_table_entries = 100;
+    let targets = "0 ".repeat(br_table_entries);
+    let params = "local.get $l ".repeat(10);
+    let drops = "drop ".repeat(10);
+
+    let mut benches = vec![
+        bench("i64const", "i64.const 3 drop", ""),
+        bench("i64load", "i32.const 8 i64.load drop", "i32.const 8 drop"),
+        bench(
+            "i64store",
+            &format!("i32.const 8 {L} i64.store"),
+            &format!("i32.const 8 {L} drop drop"),
+        ),
+        bench(
+            "select",
+            &format!("{L} {L} i32.const 1 select drop"),
+            &format!("{L} {L} i32.const 1 drop drop drop"),
+        ),
+        bench("if", "i32.const 1 if end", "i32.const 1 drop"),
+        bench("br", "block br 0 end", "block end"),
+        bench("br_if", "block i32.const 0 br_if 0 end", "block i32.const 0 drop end"),
+        bench(
+            "br_table",
+            "block i32.const 0 br_table 0 end",
+            "block i32.const 0 drop end",
+        ),
+        Bench {
+            name: "br_table_per_entry",
+            body: format!("block i32.const 0 br_table {targets} 0 end"),
+            baseline: "block i32.const 0 br_table 0 end".into(),
+            units: br_table_entries as u32,
+        },
+        bench("call", "call $nop0", ""),
+        bench(
+            "call_indirect",
+            "i32.const 0 call_indirect (type $t0)",
+            "i32.const 0 drop",
+        ),
+        Bench {
+            name: "call_indirect_per_param",
+            body: format!("{params} i32.const 1 call_indirect (type $t10)"),
+            baseline: format!("{params} {drops} i32.const 0 call_indirect (type $t0)"),
+            units: 10,
+        },
+        bench("local_get", &format!("{L} drop"), ""),
+        bench("local_set", &format!("{L} local.set $l"), &format!("{L} drop")),
+        bench(
+            "local_tee",
+            &format!("{L} local.tee $l drop"),
+            &format!("{L} drop"),
+        ),
+        bench("global_get", "global.get $g drop", ""),
+        bench("global_set", &format!("{L} global.set $g"), &format!("{L} drop")),
+        bench("memory_current", "memory.size drop", ""),
+        bench("memory_grow", "i32.const 0 memory.grow drop", "i32.const 0 drop"),
+        unary("i64clz", L, "i64.clz"),
+        unary("i64ctz", L, "i64.ctz"),
+        unary("i64popcnt", L, "i64.popcnt"),
+        unary("i64eqz", L, "i64.eqz"),
+        unary("i64extendsi32", I, "i64.extend_i32_s"),
+        unary("i64extendui32", I, "i64.extend_i32_u"),
+        unary("i32wrapi64", L, "i32.wrap_i64"),
+    ];
+    for (name, op) in [
+        ("i64eq", "i64.eq"),
+        ("i64ne", "i64.ne"),
+        ("i64lts", "i64.lt_s"),
+        ("i64ltu", "i64.lt_u"),
+        ("i64gts", "i64.gt_s"),
+        ("i64gtu", "i64.gt_u"),
+        ("i64les", "i64.le_s"),
+        ("i64leu", "i64.le_u"),
+        ("i64ges", "i64.ge_s"),
+        ("i64geu", "i64.ge_u"),
+        ("i64add", "i64.add"),
+        ("i64sub", "i64.sub"),
+        ("i64mul", "i64.mul"),
+        ("i64divs", "i64.div_s"),
+        ("i64divu", "i64.div_u"),
+        ("i64rems", "i64.rem_s"),
+        ("i64remu", "i64.rem_u"),
+        ("i64and", "i64.and"),
+        ("i64or", "i64.or"),
+        ("i64xor", "i64.xor"),
+        ("i64shl", "i64.shl"),
+        ("i64shrs", "i64.shr_s"),
+        ("i64shru", "i64.shr_u"),
+        ("i64rotl", "i64.rotl"),
+        ("i64rotr", "i64.rotr"),
+    ] {
+        benches.push(binary(name, L, op));
+    }
+    benches.extend([
+        bench("f64const", "f64.const 1.5 drop", ""),
+        bench("f64load", "i32.const 8 f64.load drop", "i32.const 8 drop"),
+        bench(
+            "f64store",
+            &format!("i32.const 8 {F} f64.store"),
+            &format!("i32.const 8 {F} drop drop"),
+        ),
+        unary("f64convert", L, "f64.convert_i64_s"),
+        binary("f64cmp", F, "f64.lt"),
+        unary("f64low", F, "f64.neg"),
+        binary("f64calc", F, "f64.div"),
+    ]);
+    benches
+}
+
+fn module_wat(body: &str) -> String {
+    let body = format!("{body}\n").repeat(REPEAT);
+    format!(
+        r#"
+        (module
+            (type $t0 (func))
+            (type $t10 (func (param i64 i64 i64 i64 i64 i64 i64 i64 i64 i64)))
+            (memory 2)
+            (global $g (mut i64) (i64.const 0))
+            (table 2 funcref)
+            (elem (i32.const 0) $nop0 $nop10)
+            (func $nop0 (type $t0))
+            (func $nop10 (type $t10))
+            (func (export "run") (param $n i32)
+                (local $l i64) (local $i i32) (local $f f64)
+                (local.set $l (i64.const 0x7fffffff))
+                (local.set $i (i32.const 0x7fff))
+                (local.set $f (f64.const 3.1415926))
+                (loop $loop
+                    {body}
+                    (br_if $loop (local.tee $n (i32.sub (local.get $n) (i32.const 1))))
+                )
+            )
+        )
+        "#
+    )
+}
+
+fn measure(store: &Store, body: &str) -> Result<Duration> {
+    let module = Module::new(store, module_wat(body))?;
+    let instance = Instance::new(&module, &imports! {})?;
+    let run: NativeFunc<i32, ()> = instance.exports.get_native_function("run")?;
+    let mut best = Duration::MAX;
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        run.call(ITERATIONS)?;
+        best = best.min(start.elapsed());
+    }
+    Ok(best)
+}
+
+fn main() -> Result<()> {
+    // No metering middleware here, we are measuring the raw cost of the instructions.
+    let store = Store::new(&Universal::new(Singlepass::default()).engine());
+    let executed = ITERATIONS as u128 * REPEAT as u128;
+
+    println!("InstructionWeights {{");
+    for bench in all_benches() {
+        let body = measure(&store, &bench.body)?;
+        let baseline = measure(&store, &bench.baseline)?;
+        let picos = body.saturating_sub(baseline).as_nanos() * 1000;
+        let weight = picos / executed / bench.units as u128;
+        println!("    {}: {},", field_name(bench.name), weight);
+    }
+    println!("}}");
+    Ok(())
+}
+
+fn field_name(name: &str) -> String {
+    match name {
+        "if" => "r#if".into(),
+        _ => name.into(),
+    }
+}
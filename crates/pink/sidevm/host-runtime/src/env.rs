@@ -6,7 +6,7 @@ use std::{
     future::Future,
     sync::{Arc, Mutex},
     task::Poll::{Pending, Ready},
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 use serde::{Deserialize, Serialize};
@@ -18,18 +18,19 @@ use tokio::{
 use wasmer::{imports, Function, ImportObject, Instance, Memory, Store, WasmerEnv};
 
 use env::{
-    messages::{AccountId, QueryRequest, SystemMessage},
+    messages::{AccountId, OutgoingRequest, QueryRequest, SystemMessage, UpgradeHint},
     tls::{TlsClientConfig, TlsServerConfig},
     IntPtr, IntRet, OcallError, OcallFuncs, Result, RetEncode,
 };
 use pink_sidevm_env as env;
-use scale::Encode;
+use scale::{Decode, Encode};
 use thread_local::ThreadLocal;
 use wasmer_middlewares::metering;
 
 use crate::{
     async_context::{get_task_cx, set_task_env, GuestWaker},
     resource::{Resource, ResourceKeeper},
+    service::OutgoingRequestSender,
     tls::{load_tls_config, TlsStream},
     VmId,
 };
@@ -50,8 +51,13 @@ fn _sizeof_i32_must_eq_to_intptr() {
     let _ = core::mem::transmute::<i32, IntPtr>;
 }
 
-pub fn create_env(id: VmId, store: &Store, cache_ops: DynCacheOps) -> (Env, ImportObject) {
-    let env = Env::new(id, cache_ops);
+pub fn create_env(
+    id: VmId,
+    store: &Store,
+    cache_ops: DynCacheOps,
+    outgoing_tx: Option<OutgoingRequestSender>,
+) -> (Env, ImportObject) {
+    let env = Env::new(id, cache_ops, outgoing_tx);
     let wasi_imports = wasi_env::wasi_imports(store, &env);
     (
         env.clone(),
@@ -125,6 +131,54 @@ pub trait CacheOps {
 
 pub type DynCacheOps = &'static (dyn CacheOps + Send + Sync);
 
+/// Number of the latest guest log lines kept for crash diagnostics.
+const MAX_RECENT_LOGS: usize = 32;
+/// Max length of a single kept log line.
+const MAX_RECENT_LOG_LEN: usize = 1024;
+/// Max length of the kept guest panic message.
+const MAX_PANIC_MESSAGE_LEN: usize = 4096;
+
+/// Max encoded size of a single outgoing request.
+const MAX_OUTGOING_REQUEST_SIZE: usize = 64 * 1024;
+/// Max number of outgoing requests can be emitted in a burst.
+const OUTGOING_BURST: u32 = 16;
+/// One outgoing request token is refilled per interval.
+const OUTGOING_REFILL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A token bucket to limit the rate of outgoing requests emitted by the guest.
+struct OutgoingRateLimiter {
+    tokens: u32,
+    last_refill: Instant,
+}
+
+impl OutgoingRateLimiter {
+    fn new() -> Self {
+        Self {
+            tokens: OUTGOING_BURST,
+            last_refill: Instant::now(),
+        }
+    }
+
+    fn try_acquire(&mut self) -> bool {
+        let now = Instant::now();
+        let elapsed = now.saturating_duration_since(self.last_refill);
+        let refill = (elapsed.as_millis() / OUTGOING_REFILL_INTERVAL.as_millis()) as u32;
+        if refill > 0 {
+            self.tokens = self.tokens.saturating_add(refill).min(OUTGOING_BURST);
+            self.last_refill = if self.tokens == OUTGOING_BURST {
+                now
+            } else {
+                self.last_refill + OUTGOING_REFILL_INTERVAL * refill
+            };
+        }
+        if self.tokens == 0 {
+            return false;
+        }
+        self.tokens -= 1;
+        true
+    }
+}
+
 struct State {
     id: VmId,
     gas_per_breath: u64,
@@ -134,11 +188,19 @@ struct State {
     message_tx: Option<Sender<Vec<u8>>>,
     query_tx: Option<Sender<Vec<u8>>>,
     sys_message_tx: Option<Sender<Vec<u8>>>,
+    /// The resource ids of the receivers of the input channels created by the program.
+    input_channels: Vec<(env::InputChannel, i32)>,
     awake_tasks: Arc<TaskSet>,
     current_task: i32,
     cache_ops: DynCacheOps,
     weight: u32,
     instance: Option<Instance>,
+    outgoing_tx: Option<OutgoingRequestSender>,
+    outgoing_limiter: OutgoingRateLimiter,
+    gas_used: u64,
+    upgrade_hint: Option<UpgradeHint>,
+    recent_logs: VecDeque<String>,
+    panic_message: Option<String>,
 }
 
 struct VmMemory(Option<Memory>);
@@ -154,13 +216,24 @@ impl VmMemory {
     }
 }
 
+/// An input sent to the program but not received by it yet.
+pub enum PendingInput {
+    Message(Vec<u8>),
+    SystemMessage(SystemMessage),
+    Query {
+        origin: Option<AccountId>,
+        payload: Vec<u8>,
+        reply_tx: OneshotSender<Vec<u8>>,
+    },
+}
+
 #[derive(WasmerEnv, Clone)]
 pub struct Env {
     pub(crate) inner: Arc<Mutex<EnvInner>>,
 }
 
 impl Env {
-    fn new(id: VmId, cache_ops: DynCacheOps) -> Self {
+    fn new(id: VmId, cache_ops: DynCacheOps, outgoing_tx: Option<OutgoingRequestSender>) -> Self {
         Self {
             inner: Arc::new(Mutex::new(EnvInner {
                 memory: VmMemory(None),
@@ -173,11 +246,18 @@ impl Env {
                     message_tx: None,
                     sys_message_tx: None,
                     query_tx: None,
+                    input_channels: vec![],
                     awake_tasks: Arc::new(TaskSet::with_task0()),
                     current_task: 0,
                     cache_ops,
                     weight: 1,
                     instance: None,
+                    outgoing_tx,
+                    outgoing_limiter: OutgoingRateLimiter::new(),
+                    gas_used: 0,
+                    upgrade_hint: None,
+                    recent_logs: VecDeque::with_capacity(MAX_RECENT_LOGS),
+                    panic_message: None,
                 },
             })),
         }
@@ -257,6 +337,93 @@ impl Env {
         metering::set_remaining_points(&instance, guard.state.gas_per_breath);
     }
 
+    /// Add the gas consumed in current breath to the total gas used.
+    pub fn settle_breath_gas(&self) {
+        let mut guard = self.inner.lock().unwrap();
+        let state = &mut guard.state;
+        let consumed = state.gas_per_breath.saturating_sub(state.gas_to_breath());
+        state.gas_used = state.gas_used.saturating_add(consumed);
+    }
+
+    /// Total gas used since the instance started.
+    pub fn gas_used(&self) -> u64 {
+        self.inner.lock().unwrap().state.gas_used
+    }
+
+    /// The last log lines printed by the guest, oldest first.
+    pub fn recent_logs(&self) -> Vec<String> {
+        self.inner
+            .lock()
+            .unwrap()
+            .state
+            .recent_logs
+            .iter()
+            .cloned()
+            .collect()
+    }
+
+    /// The panic message reported by the guest panic hook.
+    pub fn panic_message(&self) -> Option<String> {
+        self.inner.lock().unwrap().state.panic_message.clone()
+    }
+
+    /// Set the hint to be read by the program started by a hot-upgrade.
+    pub fn set_upgrade_hint(&self, hint: UpgradeHint) {
+        self.inner.lock().unwrap().state.upgrade_hint = Some(hint);
+    }
+
+    /// Whether the program has created the input channel for general messages.
+    pub fn accepts_messages(&self) -> bool {
+        self.inner.lock().unwrap().state.message_tx.is_some()
+    }
+
+    /// Close the input channels and take the inputs not received by the program yet, so that
+    /// they can be handed over to the program started by a hot-upgrade.
+    pub fn take_pending_inputs(&self) -> Vec<PendingInput> {
+        let mut guard = self.inner.lock().unwrap();
+        let state = &mut guard.state;
+        state.message_tx = None;
+        state.sys_message_tx = None;
+        state.query_tx = None;
+        let mut inputs = vec![];
+        for (ch, resource_id) in core::mem::take(&mut state.input_channels) {
+            let mut rx = match state.resources.take(resource_id) {
+                Some(Resource::ChannelRx(rx)) => rx,
+                _ => continue,
+            };
+            rx.close();
+            while let Ok(data) = rx.try_recv() {
+                let input = match ch {
+                    env::InputChannel::GeneralMessage => PendingInput::Message(data),
+                    env::InputChannel::SystemMessage => {
+                        let message = match SystemMessage::decode(&mut &data[..]) {
+                            Ok(message) => message,
+                            Err(_) => continue,
+                        };
+                        PendingInput::SystemMessage(message)
+                    }
+                    env::InputChannel::Query => {
+                        let query = match QueryRequest::decode(&mut &data[..]) {
+                            Ok(query) => query,
+                            Err(_) => continue,
+                        };
+                        let reply_tx = match state.resources.take(query.reply_tx) {
+                            Some(Resource::OneshotTx(Some(reply_tx))) => reply_tx,
+                            _ => continue,
+                        };
+                        PendingInput::Query {
+                            origin: query.origin,
+                            payload: query.payload,
+                            reply_tx,
+                        }
+                    }
+                };
+                inputs.push(input);
+            }
+        }
+        inputs
+    }
+
     pub fn has_more_ready(&self) -> bool {
         !self.inner.lock().unwrap().state.awake_tasks.is_empty()
     }
@@ -453,6 +620,17 @@ impl env::OcallFuncs for State {
         let task = self.current_task;
         let vm_id = ShortId(&self.id);
         log::log!(target: "sidevm", level, "[{vm_id}][tid={task:<3}] {message}");
+        if self.recent_logs.len() == MAX_RECENT_LOGS {
+            self.recent_logs.pop_front();
+        }
+        let line = format!("[{level}] {message}");
+        self.recent_logs
+            .push_back(truncated(&line, MAX_RECENT_LOG_LEN).into());
+        Ok(())
+    }
+
+    fn report_panic(&mut self, message: &str) -> Result<()> {
+        self.panic_message = Some(truncated(message, MAX_PANIC_MESSAGE_LEN).into());
         Ok(())
     }
 
@@ -513,6 +691,7 @@ impl env::OcallFuncs for State {
                 }
                 let (tx, rx) = tokio::sync::mpsc::channel(20);
                 let res = self.resources.push(Resource::ChannelRx(rx))?;
+                self.input_channels.push((ch, res));
                 $field = Some(tx);
                 Ok(res)
             }};
@@ -533,6 +712,27 @@ impl env::OcallFuncs for State {
         })
     }
 
+    fn emit_outgoing_request(&mut self, request: OutgoingRequest) -> Result<()> {
+        const OUTGOING_BYTE_WEIGHT: usize = 100_000;
+
+        let size = request.encoded_size();
+        if size > MAX_OUTGOING_REQUEST_SIZE {
+            return Err(OcallError::ResourceLimited);
+        }
+        self.pay((OUTGOING_BYTE_WEIGHT * size) as _)?;
+        let tx = self
+            .outgoing_tx
+            .as_ref()
+            .ok_or(OcallError::UnsupportedOperation)?;
+        if !self.outgoing_limiter.try_acquire() {
+            return Err(OcallError::ResourceLimited);
+        }
+        tx.try_send(request).or(Err(OcallError::ResourceLimited))
+    }
+
+    fn upgrade_hint(&mut self) -> Result<Option<UpgradeHint>> {
+        Ok(self.upgrade_hint.clone())
+    }
 }
 
 impl State {
@@ -654,7 +854,19 @@ fn convert(result: Result<i32, OcallError>) -> Result<IntRet, OcallAborted> {
     }
 }
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+/// Cut the string to at most `max_len` bytes at a char boundary.
+fn truncated(s: &str, max_len: usize) -> &str {
+    if s.len() <= max_len {
+        return s;
+    }
+    let mut end = max_len;
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    &s[..end]
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Encode, Decode)]
 pub enum OcallAborted {
     GasExhausted,
     Stifled,
@@ -1,148 +1,20 @@
 use anyhow::{anyhow, Result};
 use parity_wasm::elements::{Instruction, Module};
+use pink_sidevm_env::gas_schedule::{GasSchedule, InstructionWeights};
 use wasm_instrument::gas_metering::{inject, MemoryGrowCost, Rules};
 
-struct InstructionWeights {
-    i64const: u32,
-    i64load: u32,
-    i64store: u32,
-    select: u32,
-    r#if: u32,
-    br: u32,
-    br_if: u32,
-    br_table: u32,
-    br_table_per_entry: u32,
-    call: u32,
-    call_indirect: u32,
-    call_indirect_per_param: u32,
-    local_get: u32,
-    local_set: u32,
-    local_tee: u32,
-    global_get: u32,
-    global_set: u32,
-    memory_current: u32,
-    memory_grow: u32,
-    i64clz: u32,
-    i64ctz: u32,
-    i64popcnt: u32,
-    i64eqz: u32,
-    i64extendsi32: u32,
-    i64extendui32: u32,
-    i32wrapi64: u32,
-    i64eq: u32,
-    i64ne: u32,
-    i64lts: u32,
-    i64ltu: u32,
-    i64gts: u32,
-    i64gtu: u32,
-    i64les: u32,
-    i64leu: u32,
-    i64ges: u32,
-    i64geu: u32,
-    i64add: u32,
-    i64sub: u32,
-    i64mul: u32,
-    i64divs: u32,
-    i64divu: u32,
-    i64rems: u32,
-    i64remu: u32,
-    i64and: u32,
-    i64or: u32,
-    i64xor: u32,
-    i64shl: u32,
-    i64shrs: u32,
-    i64shru: u32,
-    i64rotl: u32,
-    i64rotr: u32,
-    f64const: u32,
-    f64load: u32,
-    f64store: u32,
-    f64convert: u32,
-    f64cmp: u32,
-    f64low: u32,
-    f64calc: u32,
-}
-
-impl InstructionWeights {
-    // Values are taken from the pallet-contract
-    const fn default_weights() -> Self {
-        Self {
-            i64const: 2960,
-            i64load: 7280,
-            i64store: 8360,
-            select: 5980,
-            r#if: 9990,
-            br: 3060,
-            br_if: 5770,
-            br_table: 7170,
-            br_table_per_entry: 40,
-            call: 68540,
-            call_indirect: 85180,
-            call_indirect_per_param: 1760,
-            local_get: 3050,
-            local_set: 3900,
-            local_tee: 3030,
-            global_get: 9050,
-            global_set: 11140,
-            memory_current: 3640,
-            memory_grow: 3640,
-            i64clz: 3140,
-            i64ctz: 3040,
-            i64popcnt: 2970,
-            i64eqz: 3160,
-            i64extendsi32: 2890,
-            i64extendui32: 2830,
-            i32wrapi64: 3140,
-            i64eq: 4740,
-            i64ne: 4720,
-            i64lts: 4680,
-            i64ltu: 4690,
-            i64gts: 4720,
-            i64gtu: 4840,
-            i64les: 4730,
-            i64leu: 4710,
-            i64ges: 4660,
-            i64geu: 4690,
-            i64add: 4450,
-            i64sub: 4520,
-            i64mul: 4520,
-            i64divs: 11070,
-            i64divu: 11620,
-            i64rems: 11090,
-            i64remu: 11730,
-            i64and: 4500,
-            i64or: 4480,
-            i64xor: 4570,
-            i64shl: 4740,
-            i64shrs: 4680,
-            i64shru: 4700,
-            i64rotl: 4690,
-            i64rotr: 4700,
-            f64const: 2960,
-            f64load: 7280,
-            f64store: 8360,
-            f64convert: 4700,
-            f64cmp: 4700,
-            f64low: 4700,
-            f64calc: 21620,
-        }
-    }
-}
-
-impl InstructionWeights {
-    fn rules<'a>(&'a self, module: &Module) -> InstrumentRules<'a> {
-        InstrumentRules {
-            weights: &self,
-            params: module
-                .type_section()
-                .iter()
-                .flat_map(|section| section.types())
-                .map(|func| {
-                    let parity_wasm::elements::Type::Function(func) = func;
-                    func.params().len() as u32
-                })
-                .collect(),
-        }
+fn rules<'a>(weights: &'a InstructionWeights, module: &Module) -> InstrumentRules<'a> {
+    InstrumentRules {
+        weights,
+        params: module
+            .type_section()
+            .iter()
+            .flat_map(|section| section.types())
+            .map(|func| {
+                let parity_wasm::elements::Type::Function(func) = func;
+                func.params().len() as u32
+            })
+            .collect(),
     }
 }
 
@@ -263,10 +135,9 @@ impl Rules for InstrumentRules<'_> {
     }
 }
 
-pub fn instrument(wasm: &[u8]) -> Result<Vec<u8>> {
-    const WEIGHTS: InstructionWeights = InstructionWeights::default_weights();
+pub fn instrument(wasm: &[u8], schedule: &GasSchedule) -> Result<Vec<u8>> {
     let module = Module::from_bytes(wasm)?;
-    let rules = WEIGHTS.rules(&module);
+    let rules = rules(&schedule.instruction_weights, &module);
     let module = inject(module, &rules, "sidevm").or(Err(anyhow!("Invalid module")))?;
     Ok(module.to_bytes()?)
 }
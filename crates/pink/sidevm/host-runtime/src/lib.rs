@@ -12,4 +12,4 @@ pub use env::{CacheOps, DynCacheOps, OcallAborted, ShortId};
 pub type VmId = [u8; 32];
 pub use run::WasmRun;
 
-pub use pink_sidevm_env::OcallError;
+pub use pink_sidevm_env::{gas_schedule, OcallError};
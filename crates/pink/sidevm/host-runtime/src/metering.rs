@@ -1,16 +1,161 @@
+use pink_sidevm_env::gas_schedule::{GasSchedule, InstructionWeights};
 use std::sync::Arc;
-use wasmer::{wasmparser::Operator, CompilerConfig};
+use wasmer::{
+    wasmparser::{Operator, Parser, Payload, TypeDef},
+    CompilerConfig,
+};
 use wasmer_middlewares::metering::Metering;
 
-pub(crate) fn metering<C: CompilerConfig>(mut compiler: C) -> C {
-    compiler.push_middleware(Arc::new(Metering::new(u64::MAX, cost_function)));
+/// Install the metering middleware to the compiler, which is used to compile `code` only.
+///
+/// If no schedule is given, the legacy cost table is used.
+pub(crate) fn metering<C: CompilerConfig>(
+    mut compiler: C,
+    schedule: Option<&'static GasSchedule>,
+    code: &[u8],
+) -> C {
+    let params = match schedule {
+        Some(_) => type_params(code),
+        None => vec![],
+    };
+    let metering = Metering::new(u64::MAX, move |operator: &Operator| -> u64 {
+        let cost = match schedule {
+            Some(schedule) => scheduled_cost(&schedule.instruction_weights, operator, &params),
+            None => legacy_cost(operator),
+        };
+        1.max(cost / 100)
+    });
+    compiler.push_middleware(Arc::new(metering));
     compiler
 }
 
-fn cost_function(operator: &Operator) -> u64 {
+/// The number of params of each type declared in the module, indexed by the type index.
+fn type_params(code: &[u8]) -> Vec<u32> {
+    let mut params = vec![];
+    for payload in Parser::new(0).parse_all(code) {
+        let reader = match payload {
+            Ok(Payload::TypeSection(reader)) => reader,
+            Ok(_) => continue,
+            // The module is rejected by the compiler anyway.
+            Err(_) => break,
+        };
+        for ty in reader {
+            match ty {
+                Ok(TypeDef::Func(func)) => params.push(func.params.len() as u32),
+                Ok(_) => params.push(0),
+                Err(_) => break,
+            }
+        }
+    }
+    params
+}
+
+/// Cost of an operator in the given schedule, given the number of params of each type declared
+/// in the module.
+///
+/// Operators not covered by the schedule are charged as the legacy table does.
+fn scheduled_cost(w: &InstructionWeights, operator: &Operator, params: &[u32]) -> u64 {
     use Operator::*;
 
     let cost = match operator {
+        End | Unreachable | Return | Else => 0,
+        I32Const { .. } | I64Const { .. } | Block { .. } | Loop { .. } | Nop | Drop => w.i64const,
+        I32Load { .. }
+        | I32Load8S { .. }
+        | I32Load8U { .. }
+        | I32Load16S { .. }
+        | I32Load16U { .. }
+        | I64Load { .. }
+        | I64Load8S { .. }
+        | I64Load8U { .. }
+        | I64Load16S { .. }
+        | I64Load16U { .. }
+        | I64Load32S { .. }
+        | I64Load32U { .. } => w.i64load,
+        I32Store { .. }
+        | I32Store8 { .. }
+        | I32Store16 { .. }
+        | I64Store { .. }
+        | I64Store8 { .. }
+        | I64Store16 { .. }
+        | I64Store32 { .. } => w.i64store,
+        Select | TypedSelect { .. } => w.select,
+        If { .. } => w.r#if,
+        Br { .. } => w.br,
+        BrIf { .. } => w.br_if,
+        BrTable { table } => w
+            .br_table
+            .saturating_add(w.br_table_per_entry.saturating_mul(table.len() as u32)),
+        Call { .. } => w.call,
+        // Charged as the most expensive signature if the type is unknown, as the instrumentation
+        // does.
+        CallIndirect { index, .. } => {
+            let nargs = *params.get(*index as usize).unwrap_or(&128);
+            w.call_indirect
+                .saturating_add(w.call_indirect_per_param.saturating_mul(nargs))
+        }
+        LocalGet { .. } => w.local_get,
+        LocalSet { .. } => w.local_set,
+        LocalTee { .. } => w.local_tee,
+        GlobalGet { .. } => w.global_get,
+        GlobalSet { .. } => w.global_set,
+        MemorySize { .. } => w.memory_current,
+        MemoryGrow { .. } => w.memory_grow,
+        I32Clz | I64Clz => w.i64clz,
+        I32Ctz | I64Ctz => w.i64ctz,
+        I32Popcnt | I64Popcnt => w.i64popcnt,
+        I32Eqz | I64Eqz => w.i64eqz,
+        I64ExtendI32S => w.i64extendsi32,
+        I64ExtendI32U => w.i64extendui32,
+        I32WrapI64 => w.i32wrapi64,
+        I32Eq | I64Eq => w.i64eq,
+        I32Ne | I64Ne => w.i64ne,
+        I32LtS | I64LtS => w.i64lts,
+        I32LtU | I64LtU => w.i64ltu,
+        I32GtS | I64GtS => w.i64gts,
+        I32GtU | I64GtU => w.i64gtu,
+        I32LeS | I64LeS => w.i64les,
+        I32LeU | I64LeU => w.i64leu,
+        I32GeS | I64GeS => w.i64ges,
+        I32GeU | I64GeU => w.i64geu,
+        I32Add | I64Add => w.i64add,
+        I32Sub | I64Sub => w.i64sub,
+        I32Mul | I64Mul => w.i64mul,
+        I32DivS | I64DivS => w.i64divs,
+        I32DivU | I64DivU => w.i64divu,
+        I32RemS | I64RemS => w.i64rems,
+        I32RemU | I64RemU => w.i64remu,
+        I32And | I64And => w.i64and,
+        I32Or | I64Or => w.i64or,
+        I32Xor | I64Xor => w.i64xor,
+        I32Shl | I64Shl => w.i64shl,
+        I32ShrS | I64ShrS => w.i64shrs,
+        I32ShrU | I64ShrU => w.i64shru,
+        I32Rotl | I64Rotl => w.i64rotl,
+        I32Rotr | I64Rotr => w.i64rotr,
+        F32Load { .. } | F64Load { .. } => w.f64load,
+        F32Store { .. } | F64Store { .. } => w.f64store,
+        F32Const { .. } | F64Const { .. } => w.f64const,
+        F32Eq | F32Ne | F32Lt | F32Gt | F32Le | F32Ge | F64Eq | F64Ne | F64Lt | F64Gt | F64Le
+        | F64Ge | F32Min | F32Max | F64Min | F64Max => w.f64cmp,
+        F32Abs | F32Neg | F32Ceil | F32Floor | F32Trunc | F32Nearest | F32Copysign | F64Abs
+        | F64Neg | F64Ceil | F64Floor | F64Trunc | F64Nearest | F64Copysign => w.f64low,
+        F32Sqrt | F32Add | F32Sub | F32Mul | F32Div | F64Sqrt | F64Add | F64Sub | F64Mul
+        | F64Div => w.f64calc,
+        I32TruncF32S | I32TruncF32U | I32TruncF64S | I32TruncF64U | I64TruncF32S
+        | I64TruncF32U | I64TruncF64S | I64TruncF64U | F32ConvertI32S | F32ConvertI32U
+        | F32ConvertI64S | F32ConvertI64U | F32DemoteF64 | F64ConvertI32S | F64ConvertI32U
+        | F64ConvertI64S | F64ConvertI64U | F64PromoteF32 | I32ReinterpretF32
+        | I64ReinterpretF64 | F32ReinterpretI32 | F64ReinterpretI64 => w.f64convert,
+        _ => return legacy_cost(operator),
+    };
+    cost as u64
+}
+
+fn legacy_cost(operator: &Operator) -> u64 {
+    use Operator::*;
+
+    match operator {
         I64Const { .. } => 2960,
         I64Load { .. } => 7280,
         I64Store { .. } => 8360,
@@ -225,6 +370,29 @@ fn cost_function(operator: &Operator) -> u64 {
         MemoryAtomicWait64 { .. } => 8000,
         AtomicFence { .. } => 1000,
         _ => 100000,
-    };
-    1.max(cost / 100)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn call_indirect_charges_per_param() {
+        let w = &GasSchedule::latest().instruction_weights;
+        let call = |index| Operator::CallIndirect {
+            index,
+            table_index: 0,
+        };
+        let params = [0, 3];
+        assert_eq!(scheduled_cost(w, &call(0), &params), w.call_indirect as u64);
+        assert_eq!(
+            scheduled_cost(w, &call(1), &params),
+            (w.call_indirect + 3 * w.call_indirect_per_param) as u64
+        );
+        assert_eq!(
+            scheduled_cost(w, &call(2), &params),
+            (w.call_indirect + 128 * w.call_indirect_per_param) as u64
+        );
+    }
 }
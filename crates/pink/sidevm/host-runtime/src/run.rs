@@ -1,5 +1,6 @@
 use anyhow::{Context as _, Result};
 use phala_scheduler::TaskScheduler;
+use pink_sidevm_env::gas_schedule::GasSchedule;
 use std::future::Future;
 use std::pin::Pin;
 use std::task::{Context, Poll};
@@ -12,6 +13,7 @@ use wasmer_compiler_singlepass::Singlepass;
 use wasmer_tunables::LimitingTunables;
 
 use crate::env::DynCacheOps;
+use crate::service::OutgoingRequestSender;
 use crate::{async_context, env, metering::metering, VmId};
 
 pub struct WasmRun {
@@ -36,6 +38,8 @@ impl WasmRun {
         cache_ops: DynCacheOps,
         scheduler: TaskScheduler<VmId>,
         weight: u32,
+        outgoing_tx: Option<OutgoingRequestSender>,
+        gas_schedule: Option<&'static GasSchedule>,
     ) -> Result<(WasmRun, env::Env)> {
         let compiler_env = std::env::var("WASMER_COMPILER");
         let compiler_env = compiler_env
@@ -44,9 +48,9 @@ impl WasmRun {
             .unwrap_or("singlepass");
 
         let engine = match compiler_env {
-            "singlepass" => Universal::new(metering(Singlepass::default())),
+            "singlepass" => Universal::new(metering(Singlepass::default(), gas_schedule, code)),
             #[cfg(feature = "wasmer-compiler-cranelift")]
-            "cranelift" => Universal::new(metering(Cranelift::default())),
+            "cranelift" => Universal::new(metering(Cranelift::default(), gas_schedule, code)),
             #[cfg(feature = "wasmer-compiler-llvm")]
             "llvm" => Universal::new(LLVM::default()),
             _ => panic!("Unsupported compiler engine: {}", compiler_env),
@@ -61,7 +65,7 @@ impl WasmRun {
         let tunables = LimitingTunables::new(base, Pages(max_pages));
         let store = Store::new_with_tunables(&engine, tunables);
         let module = Module::new(&store, code)?;
-        let (env, import_object) = env::create_env(id, &store, cache_ops);
+        let (env, import_object) = env::create_env(id, &store, cache_ops, outgoing_tx);
         let instance = Instance::new(&module, &import_object)?;
         let memory = instance
             .exports
@@ -90,7 +94,9 @@ impl Future for WasmRun {
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         let _guard = futures::ready!(self.scheduler.poll_resume(cx, &self.id, self.env.weight()));
         self.env.reset_gas_to_breath();
-        match async_context::set_task_cx(cx, || self.wasm_poll_entry.call()) {
+        let rv = async_context::set_task_cx(cx, || self.wasm_poll_entry.call());
+        self.env.settle_breath_gas();
+        match rv {
             Ok(rv) => {
                 if rv == 0 {
                     if self.env.has_more_ready() {
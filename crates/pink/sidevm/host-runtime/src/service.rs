@@ -1,27 +1,46 @@
-use crate::env::DynCacheOps;
+use crate::env::{DynCacheOps, PendingInput};
 use crate::{env::OcallAborted, run::WasmRun};
 use crate::{ShortId, VmId};
 use anyhow::{Context as _, Result};
 use log::{debug, error, info, trace, warn};
 use phala_scheduler::TaskScheduler;
-use pink_sidevm_env::messages::AccountId;
+use pink_sidevm_env::{gas_schedule::GasSchedule, messages::AccountId};
+use scale::{Decode, Encode};
 use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
 use std::future::Future;
+use std::time::{Duration, Instant};
 use tokio::{
     sync::mpsc::{channel, Receiver, Sender},
     sync::oneshot::Sender as OneshotSender,
     task::JoinHandle,
 };
 
-pub use pink_sidevm_env::messages::SystemMessage;
+pub use pink_sidevm_env::messages::{OutgoingRequest, SystemMessage, UpgradeHint};
 pub type CommandSender = Sender<Command>;
+pub type OutgoingRequestSender = Sender<OutgoingRequest>;
 
 #[derive(Debug)]
 pub enum Report {
-    VmTerminated { id: VmId, reason: ExitReason },
+    VmTerminated {
+        id: VmId,
+        reason: ExitReason,
+        crash: Option<CrashInfo>,
+    },
+}
+
+/// Diagnostics collected when a sidevm instance panicked.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Encode, Decode)]
+pub struct CrashInfo {
+    /// The panic message reported by the guest panic hook, if any.
+    pub panic_message: Option<String>,
+    /// The trap raised by the wasm runtime, including the guest stack.
+    pub trap: Option<String>,
+    /// The last log lines printed by the guest.
+    pub recent_logs: Vec<String>,
 }
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Encode, Decode)]
 pub enum ExitReason {
     /// The program returned from `fn main`.
     Exited(i32),
@@ -34,7 +53,11 @@ pub enum ExitReason {
     /// The task future has beed dropped, likely caused by a Stop command.
     Cancelled,
     /// Terminated due to gas checking.
-    OcallAborted(OcallAborted),
+    OcallAborted {
+        reason: OcallAborted,
+        /// Total gas used by the instance before it was aborted.
+        gas_used: u64,
+    },
     /// When a previous running instance restored from a checkpoint.
     Restore,
 }
@@ -52,8 +75,37 @@ pub enum Command {
         payload: Vec<u8>,
         reply_tx: OneshotSender<Vec<u8>>,
     },
+    // Replace the running program with a new one, keeping the VmId and the command channel.
+    // Whether the new program has been started is sent back through the `result_tx`.
+    Upgrade {
+        code: Vec<u8>,
+        hint: UpgradeHint,
+        result_tx: OneshotSender<bool>,
+    },
 }
 
+impl From<PendingInput> for Command {
+    fn from(input: PendingInput) -> Self {
+        match input {
+            PendingInput::Message(msg) => Command::PushMessage(msg),
+            PendingInput::SystemMessage(msg) => Command::PushSystemMessage(msg),
+            PendingInput::Query {
+                origin,
+                payload,
+                reply_tx,
+            } => Command::PushQuery {
+                origin,
+                payload,
+                reply_tx,
+            },
+        }
+    }
+}
+
+/// Max time to hold the pending commands after an upgrade, waiting for the new program to
+/// create its message channel.
+const UPGRADE_HANDOVER_TIMEOUT: Duration = Duration::from_secs(10);
+
 pub struct ServiceRun {
     runtime: tokio::runtime::Runtime,
     report_rx: Receiver<Report>,
@@ -118,21 +170,36 @@ impl Spawner {
         gas_per_breath: u64,
         cache_ops: DynCacheOps,
         weight: u32,
-    ) -> Result<(CommandSender, JoinHandle<ExitReason>)> {
+        outgoing_tx: Option<OutgoingRequestSender>,
+        gas_schedule: Option<&'static GasSchedule>,
+    ) -> Result<(CommandSender, JoinHandle<(ExitReason, Option<CrashInfo>)>)> {
         let (cmd_tx, mut cmd_rx) = channel(128);
-        let (mut wasm_run, env) = WasmRun::run(
-            wasm_bytes,
-            max_memory_pages,
-            id,
-            gas_per_breath,
-            cache_ops,
-            self.scheduler.clone(),
-            weight,
-        )
-        .context("Failed to create sidevm instance")?;
+        let scheduler = self.scheduler.clone();
+        let create_instance = move |code: &[u8]| {
+            WasmRun::run(
+                code,
+                max_memory_pages,
+                id,
+                gas_per_breath,
+                cache_ops,
+                scheduler.clone(),
+                weight,
+                outgoing_tx.clone(),
+                gas_schedule,
+            )
+            .context("Failed to create sidevm instance")
+        };
+        let (mut wasm_run, mut env) = create_instance(wasm_bytes)?;
         let spawner = self.runtime_handle.clone();
         let handle = self.spawn(async move {
             let vmid = ShortId(&id);
+            // After an upgrade, the pending commands are held until the new program is ready to
+            // receive messages, so that they are handed over rather than dropped.
+            let mut handover_deadline: Option<Instant> = None;
+            // The inputs not received by the previous program, to be pushed to the new one
+            // ahead of the commands still in the channel.
+            let mut handover = VecDeque::new();
+            let mut crash = None;
             macro_rules! spawn_push_msg {
                 ($expr: expr, $level: ident, $msg: expr) => {
                     $level!(target: "sidevm", "[{vmid}] Pushing {} to sidevm", $msg);
@@ -151,9 +218,15 @@ impl Spawner {
                     });
                 };
             }
-            loop {
+            let reason = loop {
+                if let Some(deadline) = handover_deadline {
+                    if env.accepts_messages() || Instant::now() >= deadline {
+                        handover_deadline = None;
+                    }
+                }
                 tokio::select! {
-                    cmd = cmd_rx.recv() => {
+                    _ = tokio::time::sleep(Duration::from_millis(50)), if handover_deadline.is_some() => {}
+                    cmd = next_command(&mut handover, &mut cmd_rx), if handover_deadline.is_none() => {
                         match cmd {
                             None => {
                                 info!(target: "sidevm", "[{vmid}] The command channel is closed. Exiting...");
@@ -172,6 +245,27 @@ impl Spawner {
                             Some(Command::PushQuery{ origin, payload, reply_tx }) => {
                                 spawn_push_msg!(env.push_query(origin, payload, reply_tx), debug, "query");
                             }
+                            Some(Command::Upgrade { code, hint, result_tx }) => {
+                                info!(target: "sidevm", "[{vmid}] Upgrading sidevm program...");
+                                let upgraded = match create_instance(&code) {
+                                    Ok((new_run, new_env)) => {
+                                        new_env.set_upgrade_hint(hint);
+                                        let pending = env.take_pending_inputs();
+                                        info!(target: "sidevm", "[{vmid}] Handing over {} pending inputs", pending.len());
+                                        handover.extend(pending.into_iter().map(Command::from));
+                                        wasm_run = new_run;
+                                        env = new_env;
+                                        handover_deadline = Some(Instant::now() + UPGRADE_HANDOVER_TIMEOUT);
+                                        info!(target: "sidevm", "[{vmid}] Sidevm program upgraded");
+                                        true
+                                    }
+                                    Err(err) => {
+                                        error!(target: "sidevm", "[{vmid}] Failed to upgrade, keep running the old program: {err:?}");
+                                        false
+                                    }
+                                };
+                                let _ = result_tx.send(upgraded);
+                            }
                         }
                     }
                     rv = &mut wasm_run => {
@@ -183,10 +277,17 @@ impl Spawner {
                             Err(err) => {
                                 info!(target: "sidevm", "[{vmid}] The sidevm instance exited with error: {}", err);
                                 match err.downcast::<crate::env::OcallAborted>() {
-                                    Ok(err) => {
-                                        break ExitReason::OcallAborted(err);
+                                    Ok(reason) => {
+                                        let gas_used = env.gas_used();
+                                        info!(target: "sidevm", "[{vmid}] The sidevm instance aborted after used {gas_used} gas.");
+                                        break ExitReason::OcallAborted { reason, gas_used };
                                     }
-                                    Err(_) => {
+                                    Err(err) => {
+                                        crash = Some(CrashInfo {
+                                            panic_message: env.panic_message(),
+                                            trap: Some(err.to_string()),
+                                            recent_logs: env.recent_logs(),
+                                        });
                                         break ExitReason::Panicked;
                                     }
                                 }
@@ -194,26 +295,32 @@ impl Spawner {
                         }
                     }
                 }
-            }
+            };
+            (reason, crash)
         });
         let report_tx = self.report_tx.clone();
         let handle = self.spawn(async move {
             let vmid = ShortId(&id);
-            let reason = match handle.await {
+            let (reason, crash) = match handle.await {
                 Ok(r) => r,
                 Err(err) => {
                     warn!(target: "sidevm", "[{vmid}] The sidevm instance exited with error: {}", err);
                     if err.is_cancelled() {
-                        ExitReason::Cancelled
+                        (ExitReason::Cancelled, None)
                     } else {
-                        ExitReason::Panicked
+                        (ExitReason::Panicked, None)
                     }
                 }
             };
-            if let Err(err) = report_tx.send(Report::VmTerminated { id, reason }).await {
+            let report = Report::VmTerminated {
+                id,
+                reason,
+                crash: crash.clone(),
+            };
+            if let Err(err) = report_tx.send(report).await {
                 warn!(target: "sidevm", "[{vmid}] Failed to send report to sidevm service: {}", err);
             }
-            reason
+            (reason, crash)
         });
         Ok((cmd_tx, handle))
     }
@@ -225,3 +332,14 @@ impl Spawner {
         self.runtime_handle.spawn(fut)
     }
 }
+
+/// Receive the next command, the ones handed over from the previous program first.
+async fn next_command(
+    handover: &mut VecDeque<Command>,
+    cmd_rx: &mut Receiver<Command>,
+) -> Option<Command> {
+    match handover.pop_front() {
+        Some(cmd) => Some(cmd),
+        None => cmd_rx.recv().await,
+    }
+}
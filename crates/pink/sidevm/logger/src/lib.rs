@@ -15,9 +15,17 @@ impl Logger {
     }
 
     /// Install the logger as the global logger.
+    ///
+    /// A panic hook is installed as well, which logs the panic and reports it to the host for
+    /// crash diagnostics.
     pub fn init(self) {
         log::set_max_level(self.max_level);
         log::set_boxed_logger(Box::new(self)).unwrap();
+        std::panic::set_hook(Box::new(|info| {
+            let message = info.to_string();
+            let _ = ocall::log(log::Level::Error, &message);
+            let _ = ocall::report_panic(&message);
+        }));
     }
 }
 
@@ -14,7 +14,13 @@ pub use env::tasks as task;
 
 pub mod channel;
 pub mod net;
+pub mod outgoing;
 pub mod time;
 pub mod exec;
 
 mod res_id;
+
+/// Get the upgrade hint if this program was started by a hot-upgrade of a running instance.
+pub fn upgrade_hint() -> Option<env::messages::UpgradeHint> {
+    ocall::upgrade_hint().ok().flatten()
+}
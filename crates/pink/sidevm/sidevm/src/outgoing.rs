@@ -0,0 +1,23 @@
+//! Emit messages from the sidevm program on behalf of the owning contract.
+//!
+//! Outgoing requests are rate limited by the host and flushed to the chain with a per-block
+//! quota. A request failing with `OcallError::ResourceLimited` should be retried later.
+//!
+//! The same program runs on every worker of the cluster, but only the requests emitted on one of
+//! them, the relay, are carried out. The requests emitted on the other workers are dropped.
+use pink_sidevm_env::{messages::OutgoingRequest, OcallError};
+
+use super::ocall;
+
+/// Push a phala-mq message to the given topic, signed as the owning contract.
+pub fn push_message(topic: Vec<u8>, payload: Vec<u8>) -> Result<(), OcallError> {
+    ocall::emit_outgoing_request(OutgoingRequest::Message { topic, payload })
+}
+
+/// Submit an encoded ink message as a command to the owning contract.
+///
+/// The command is sent to the chain first and then dispatched to the contract as if it was
+/// submitted by the contract itself.
+pub fn submit_contract_command(message: Vec<u8>) -> Result<(), OcallError> {
+    ocall::emit_outgoing_request(OutgoingRequest::ContractCommand { message })
+}
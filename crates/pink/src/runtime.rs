@@ -7,10 +7,11 @@ use std::time::{Duration, Instant};
 use crate::types::{AccountId, Balance, BlockNumber, Hash, Hashing, Index};
 use frame_support::{
     parameter_types,
-    traits::ConstU128,
+    traits::{ConstU128, Get},
     weights::{constants::WEIGHT_PER_SECOND, Weight},
 };
 use pallet_contracts::{Config, Frame, Schedule};
+use pink_sidevm_env::gas_schedule::GasSchedule;
 use sp_runtime::{
     generic::Header,
     traits::{Convert, IdentityLookup},
@@ -101,8 +102,86 @@ parameter_types! {
     pub const RelaxedMaxCodeLen: u32 = 2 * 1024 * 1024;
     pub const TransactionByteFee: u64 = 0;
     pub const MaxStorageKeyLen: u32 = 128;
+}
+
+/// The contract schedule of the cluster.
+///
+/// Uses the pallet-contracts default schedule unless the cluster has selected a shared gas
+/// schedule version, in which case the instruction weights are replaced by that version.
+pub struct PinkSchedule;
+
+/// The instruction weights versions of the shared gas schedules start from here.
+///
+/// pallet-contracts bumps the version of its default weights independently of the shared gas
+/// schedules. Keeping the two ranges apart makes a version always map to the same weights, so
+/// that code instrumented with other weights is never reused.
+pub const SHARED_GAS_SCHEDULE_VERSION_BASE: u32 = 1 << 16;
+
+impl Get<Schedule<PinkRuntime>> for PinkSchedule {
+    fn get() -> Schedule<PinkRuntime> {
+        let mut schedule = Schedule::<PinkRuntime>::default();
+        if let Some(shared) = Pink::gas_schedule_version().and_then(GasSchedule::by_version) {
+            apply_gas_schedule(&mut schedule, shared);
+        }
+        schedule
+    }
+}
 
-    pub DefaultSchedule: Schedule<PinkRuntime> = Default::default();
+fn apply_gas_schedule(schedule: &mut Schedule<PinkRuntime>, shared: &GasSchedule) {
+    let w = &shared.instruction_weights;
+    let weights = &mut schedule.instruction_weights;
+    weights.version = SHARED_GAS_SCHEDULE_VERSION_BASE + shared.version;
+    weights.i64const = w.i64const;
+    weights.i64load = w.i64load;
+    weights.i64store = w.i64store;
+    weights.select = w.select;
+    weights.r#if = w.r#if;
+    weights.br = w.br;
+    weights.br_if = w.br_if;
+    weights.br_table = w.br_table;
+    weights.br_table_per_entry = w.br_table_per_entry;
+    weights.call = w.call;
+    weights.call_indirect = w.call_indirect;
+    weights.call_indirect_per_param = w.call_indirect_per_param;
+    weights.local_get = w.local_get;
+    weights.local_set = w.local_set;
+    weights.local_tee = w.local_tee;
+    weights.global_get = w.global_get;
+    weights.global_set = w.global_set;
+    weights.memory_current = w.memory_current;
+    weights.memory_grow = w.memory_grow;
+    weights.i64clz = w.i64clz;
+    weights.i64ctz = w.i64ctz;
+    weights.i64popcnt = w.i64popcnt;
+    weights.i64eqz = w.i64eqz;
+    weights.i64extendsi32 = w.i64extendsi32;
+    weights.i64extendui32 = w.i64extendui32;
+    weights.i32wrapi64 = w.i32wrapi64;
+    weights.i64eq = w.i64eq;
+    weights.i64ne = w.i64ne;
+    weights.i64lts = w.i64lts;
+    weights.i64ltu = w.i64ltu;
+    weights.i64gts = w.i64gts;
+    weights.i64gtu = w.i64gtu;
+    weights.i64les = w.i64les;
+    weights.i64leu = w.i64leu;
+    weights.i64ges = w.i64ges;
+    weights.i64geu = w.i64geu;
+    weights.i64add = w.i64add;
+    weights.i64sub = w.i64sub;
+    weights.i64mul = w.i64mul;
+    weights.i64divs = w.i64divs;
+    weights.i64divu = w.i64divu;
+    weights.i64rems = w.i64rems;
+    weights.i64remu = w.i64remu;
+    weights.i64and = w.i64and;
+    weights.i64or = w.i64or;
+    weights.i64xor = w.i64xor;
+    weights.i64shl = w.i64shl;
+    weights.i64shrs = w.i64shrs;
+    weights.i64shru = w.i64shru;
+    weights.i64rotl = w.i64rotl;
+    weights.i64rotr = w.i64rotr;
 }
 
 impl Convert<Weight, Balance> for PinkRuntime {
@@ -124,7 +203,7 @@ impl Config for PinkRuntime {
     type ChainExtension = extension::PinkExtension;
     type DeletionQueueDepth = DeletionQueueDepth;
     type DeletionWeightLimit = DeletionWeightLimit;
-    type Schedule = DefaultSchedule;
+    type Schedule = PinkSchedule;
     type DepositPerByte = ConstU128<0>;
     type DepositPerItem = ConstU128<0>;
     type AddressGenerator = Pink;
@@ -294,6 +373,40 @@ mod tests {
         })
     }
 
+    #[test]
+    pub fn gas_schedule_version_test() {
+        use super::{Pink, PinkSchedule, SHARED_GAS_SCHEDULE_VERSION_BASE};
+        use frame_support::traits::Get;
+        use pink_sidevm_env::gas_schedule::GasSchedule;
+
+        let default_version = <PinkRuntime as Config>::Schedule::get()
+            .instruction_weights
+            .version;
+        assert!(default_version < SHARED_GAS_SCHEDULE_VERSION_BASE);
+
+        for version in 1..=GasSchedule::LATEST_VERSION {
+            let schedule = exec::execute_with(|| {
+                Pink::set_gas_schedule_version(Some(version));
+                PinkSchedule::get()
+            });
+            let weights = &schedule.instruction_weights;
+            let shared = &GasSchedule::by_version(version)
+                .unwrap()
+                .instruction_weights;
+            assert_eq!(weights.version, SHARED_GAS_SCHEDULE_VERSION_BASE + version);
+            assert_eq!(weights.i64const, shared.i64const);
+            assert_eq!(weights.call, shared.call);
+            assert_eq!(weights.i64rotr, shared.i64rotr);
+        }
+
+        // Unknown versions fall back to the default schedule
+        let schedule = exec::execute_with(|| {
+            Pink::set_gas_schedule_version(Some(GasSchedule::LATEST_VERSION + 1));
+            PinkSchedule::get()
+        });
+        assert_eq!(schedule.instruction_weights.version, default_version);
+    }
+
     pub mod exec {
         use sp_runtime::traits::BlakeTwo256;
         use sp_state_machine::{Backend, Ext, OverlayedChanges, StorageTransactionCache};
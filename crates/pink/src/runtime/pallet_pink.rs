@@ -38,6 +38,12 @@ pub mod pallet {
     pub(crate) type SidevmCodes<T: Config> =
         StorageMap<_, Twox64Concat, T::Hash, WasmCode<T::AccountId>>;
 
+    /// The shared gas schedule version selected by the cluster. `None` for the pallet-contracts
+    /// default schedule.
+    #[pallet::storage]
+    #[pallet::getter(fn gas_schedule_version)]
+    pub(crate) type GasScheduleVersion<T: Config> = StorageValue<_, u32>;
+
     #[pallet::pallet]
     #[pallet::without_storage_info]
     pub struct Pallet<T>(PhantomData<T>);
@@ -73,6 +79,10 @@ pub mod pallet {
             <KeySeed<T>>::put(seed);
         }
 
+        pub fn set_gas_schedule_version(version: Option<u32>) {
+            <GasScheduleVersion<T>>::set(version);
+        }
+
         pub fn put_sidevm_code(owner: T::AccountId, code: Vec<u8>) -> T::Hash {
             let hash = T::Hashing::hash(&code);
             <SidevmCodes<T>>::insert(hash, WasmCode { owner, code });
@@ -125,6 +125,12 @@ where
         });
     }
 
+    pub fn set_gas_schedule_version(&mut self, version: Option<u32>) {
+        self.execute_with(false, None, || {
+            crate::runtime::Pink::set_gas_schedule_version(version);
+        });
+    }
+
     pub fn upload_code(
         &mut self,
         account: AccountId,
@@ -153,6 +159,15 @@ where
         })
         .0
     }
+
+    /// The total size of the keys and values in the storage, in bytes.
+    pub fn size(&self) -> u64 {
+        self.backend
+            .pairs()
+            .iter()
+            .map(|(key, value)| (key.len() + value.len()) as u64)
+            .sum()
+    }
 }
 
 impl Serialize for Storage<InMemoryBackend> {
@@ -541,6 +541,15 @@ describe('A full stack', function () {
             }, 4 * 6000), 'cluster not deployed');
         });
 
+        it('can deposit to cluster', async function () {
+            await assert.txAccepted(
+                api.tx.phalaFatContracts.clusterDeposit(clusterId, 10n * 10n ** 12n),
+                alice,
+            );
+            const deposit = await api.query.phalaFatContracts.clusterDeposits(clusterId);
+            assert.isTrue(deposit.gtn(0));
+        });
+
         it('can upload code with access control', async function () {
             let code = fs.readFileSync(wasmFile, 'hex');
             let InkCode = 0;
@@ -114,7 +114,12 @@ const types = {
         "freeStake": "Balance",
         "releasingStake": "Balance",
         "workers": "Vec<WorkerPublicKey>",
-        "withdrawQueue": "Vec<WithdrawInfo>"
+        "withdrawQueue": "Vec<WithdrawInfo>",
+        "pendingCommission": "Option<CommissionChange>"
+    },
+    "CommissionChange": {
+        "commission": "Permill",
+        "applyAt": "u64"
     },
     "WithdrawInfo": {
         "user": "AccountId",
@@ -237,8 +242,18 @@ const types = {
     "ClusterPermission": {
         "_enum": {
             "Public": null,
-            "OnlyOwner": "AccountId"
+            "OnlyOwner": "AccountId",
+            "AllowList": null
         }
+    },
+    "ClusterRole": {
+        "_enum": [
+            "Deployer",
+            "CodeUploader",
+            "LogHandlerAdmin",
+            "Destroyer",
+            "SponsoredSender"
+        ]
     }
 };
 
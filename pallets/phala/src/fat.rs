@@ -5,22 +5,37 @@ pub use self::pallet::*;
 #[frame_support::pallet]
 pub mod pallet {
 	use codec::Encode;
-	use frame_support::{dispatch::DispatchResult, pallet_prelude::*, traits::StorageVersion};
+	use frame_support::{
+		dispatch::DispatchResult,
+		pallet_prelude::*,
+		traits::{
+			Currency,
+			ExistenceRequirement::{AllowDeath, KeepAlive},
+			StorageVersion,
+		},
+		PalletId,
+	};
 	use frame_system::pallet_prelude::*;
 	use sp_core::H256;
-	use sp_runtime::AccountId32;
+	use sp_runtime::{
+		traits::{AccountIdConversion, SaturatedConversion, Saturating, Zero},
+		AccountId32,
+	};
 	use sp_std::prelude::*;
 
 	use crate::{mq::MessageOriginInfo, registry};
+
+	use super::WeightInfo;
 	// Re-export
-	pub use crate::attestation::{Attestation, IasValidator};
+	pub use crate::attestation::{Attestation, SgxValidator};
 	use phala_types::{
 		contract::{
 			messaging::{
 				ClusterEvent, ClusterOperation, ContractOperation, ResourceType,
 				WorkerClusterReport, WorkerContractReport,
 			},
-			ClusterInfo, ClusterPermission, CodeIndex, ContractClusterId, ContractId, ContractInfo,
+			ClusterInfo, ClusterPermission, ClusterRole, CodeIndex, ContractClusterId, ContractId,
+			ContractInfo,
 		},
 		messaging::{bind_topic, DecodedMessage, MessageOrigin},
 		ClusterPublicKey, ContractPublicKey, WorkerIdentity, WorkerPublicKey,
@@ -44,11 +59,50 @@ pub mod pallet {
 		},
 	}
 
+	const FAT_PALLETID: PalletId = PalletId(*b"phala/fc");
+
+	/// The max number of accounts granted a role in a cluster.
+	pub(crate) const MAX_ROLE_ALLOWLIST_LEN: u32 = 100;
+
+	/// The latest gas schedule version the workers support. Every version from 1 up to it is
+	/// supported, as the published schedules are never removed.
+	///
+	/// Must be kept in sync with `GasSchedule::LATEST_VERSION` of `pink-sidevm-env`.
+	pub const LATEST_GAS_SCHEDULE_VERSION: u32 = 1;
+
+	/// The number of cluster workers charged by the calls paying all the workers of a cluster.
+	///
+	/// The cluster size isn't bounded, so it's an estimation rather than a hard limit.
+	pub(crate) const CLUSTER_WORKERS_WEIGHT_LEN: u32 = 20;
+
 	#[pallet::config]
 	pub trait Config: frame_system::Config {
 		type Event: From<Event<Self>> + IsType<<Self as frame_system::Config>::Event>;
 		type InkCodeSizeLimit: Get<u32>;
 		type SidevmCodeSizeLimit: Get<u32>;
+
+		/// The currency to pay for the contract usage.
+		type Currency: Currency<Self::AccountId>;
+		/// The fee charged for each contract command processed by the cluster.
+		#[pallet::constant]
+		type CommandFee: Get<BalanceOf<Self>>;
+		/// The fee charged for each contract query served by a worker of the cluster.
+		#[pallet::constant]
+		type QueryFee: Get<BalanceOf<Self>>;
+		/// The max number of queries charged for each worker in a usage period.
+		///
+		/// Bounds what a worker can charge the cluster by over-reporting its queries.
+		#[pallet::constant]
+		type QueryQuota: Get<u32>;
+		/// The fee charged for each byte of the cluster storage in each block.
+		#[pallet::constant]
+		type StorageByteFee: Get<BalanceOf<Self>>;
+		/// The fee charged for each byte of code uploaded to a cluster.
+		#[pallet::constant]
+		type CodeByteFee: Get<BalanceOf<Self>>;
+
+		/// Weight information for the extrinsics of this pallet.
+		type WeightInfo: WeightInfo;
 	}
 
 	const STORAGE_VERSION: StorageVersion = StorageVersion::new(5);
@@ -75,10 +129,56 @@ pub mod pallet {
 	pub type ClusterContracts<T: Config> =
 		StorageMap<_, Twox64Concat, ContractClusterId, Vec<ContractId>, ValueQuery>;
 
+	/// The number of contracts of each cluster requested to be instantiated and not destroyed,
+	/// including the ones whose instantiation is not reported yet.
+	#[pallet::storage]
+	pub type ClusterInstantiations<T> =
+		StorageMap<_, Twox64Concat, ContractClusterId, u32, ValueQuery>;
+
 	#[pallet::storage]
 	pub type ClusterWorkers<T> =
 		StorageMap<_, Twox64Concat, ContractClusterId, Vec<WorkerPublicKey>, ValueQuery>;
 
+	/// The deposit of each cluster to pay for the contract usage.
+	#[pallet::storage]
+	pub type ClusterDeposits<T: Config> =
+		StorageMap<_, Twox64Concat, ContractClusterId, BalanceOf<T>, ValueQuery>;
+
+	/// The last block of the latest usage period charged for each cluster.
+	///
+	/// All the workers of a cluster report the commands and storage of each period, only the
+	/// first report is charged.
+	#[pallet::storage]
+	pub type ClusterUsageChargedAt<T: Config> =
+		StorageMap<_, Twox64Concat, ContractClusterId, T::BlockNumber>;
+
+	/// The last block of the latest usage period whose queries are charged for each worker of a
+	/// cluster.
+	#[pallet::storage]
+	pub type WorkerQueriesChargedAt<T: Config> = StorageDoubleMap<
+		_,
+		Twox64Concat,
+		ContractClusterId,
+		Twox64Concat,
+		WorkerPublicKey,
+		T::BlockNumber,
+	>;
+
+	/// The fees earned by each worker serving the clusters, to be claimed by the operator.
+	#[pallet::storage]
+	pub type WorkerPendingPayouts<T: Config> =
+		StorageMap<_, Twox64Concat, WorkerPublicKey, BalanceOf<T>, ValueQuery>;
+
+	/// The accounts granted a role in a cluster, besides the cluster owner.
+	#[pallet::storage]
+	pub type ClusterRoleAllowList<T: Config> = StorageMap<
+		_,
+		Twox64Concat,
+		(ContractClusterId, ClusterRole),
+		Vec<T::AccountId>,
+		ValueQuery,
+	>;
+
 	#[pallet::event]
 	#[pallet::generate_deposit(pub(super) fn deposit_event)]
 	pub enum Event<T: Config> {
@@ -125,6 +225,72 @@ pub mod pallet {
 		ClusterDestroyed {
 			cluster: ContractClusterId,
 		},
+		ClusterSetGasSchedule {
+			cluster: ContractClusterId,
+			version: Option<u32>,
+		},
+		ClusterWorkersAdded {
+			cluster: ContractClusterId,
+			workers: Vec<WorkerPublicKey>,
+		},
+		ClusterWorkerRemoved {
+			cluster: ContractClusterId,
+			worker: WorkerPublicKey,
+		},
+		ClusterOwnershipTransferred {
+			cluster: ContractClusterId,
+			owner: T::AccountId,
+		},
+		ClusterSetPermission {
+			cluster: ContractClusterId,
+		},
+		ContractDestroyed {
+			contract: ContractId,
+			cluster: ContractClusterId,
+		},
+		ClusterRoleGranted {
+			cluster: ContractClusterId,
+			role: ClusterRole,
+			account: T::AccountId,
+		},
+		ClusterRoleRevoked {
+			cluster: ContractClusterId,
+			role: ClusterRole,
+			account: T::AccountId,
+		},
+		ClusterDeposited {
+			cluster: ContractClusterId,
+			account: T::AccountId,
+			amount: BalanceOf<T>,
+		},
+		ClusterDepositWithdrawn {
+			cluster: ContractClusterId,
+			account: T::AccountId,
+			amount: BalanceOf<T>,
+		},
+		/// The fee of uploading code or the usage reported by the workers is charged.
+		///
+		/// `amount` may be less than the fee if the deposit is exhausted.
+		ClusterCharged {
+			cluster: ContractClusterId,
+			amount: BalanceOf<T>,
+		},
+		ClusterDepositExhausted {
+			cluster: ContractClusterId,
+		},
+		/// The commands sent by an account not sponsored by the cluster are charged from it.
+		///
+		/// `amount` may be less than the fee if the free balance of the account is insufficient.
+		AccountCharged {
+			cluster: ContractClusterId,
+			account: T::AccountId,
+			amount: BalanceOf<T>,
+		},
+		WorkerPayoutClaimed {
+			worker: WorkerPublicKey,
+			account: T::AccountId,
+			amount: BalanceOf<T>,
+		},
 	}
 
 	#[pallet::error]
@@ -139,18 +305,44 @@ pub mod pallet {
 		InvalidSender,
 		WorkerNotFound,
 		PayloadTooLarge,
+		WorkerNotInCluster,
+		CannotRemoveLastWorker,
+		ContractNotFound,
+		ContractPermissionDenied,
+		AlreadyInRoleAllowList,
+		NotInRoleAllowList,
+		ExceedRoleAllowListMaxLen,
+		InsufficientClusterDeposit,
+		NotWorkerOperator,
+		NoPayout,
+		/// The cluster has contracts, instantiated or being instantiated.
+		ClusterHasContracts,
+		InvalidUsagePeriod,
+		/// The gas schedule version is not supported by the workers.
+		UnknownGasSchedule,
 	}
 
 	type CodeHash<T> = <T as frame_system::Config>::Hash;
 
+	pub(crate) type BalanceOf<T> =
+		<<T as Config>::Currency as Currency<<T as frame_system::Config>::AccountId>>::Balance;
+
+	/// Checks whether `who` holds `role` in the cluster.
 	fn check_cluster_permission<T: Config>(
-		deployer: &T::AccountId,
+		who: &T::AccountId,
+		cluster_id: &ContractClusterId,
 		cluster: &ClusterInfo<T::AccountId>,
+		role: ClusterRole,
 	) -> bool {
-		match &cluster.permission {
-			ClusterPermission::Public => true,
-			ClusterPermission::OnlyOwner(owner) => deployer == owner,
+		if matches!(role, ClusterRole::Deployer | ClusterRole::CodeUploader) {
+			match &cluster.permission {
+				ClusterPermission::Public => return true,
+				ClusterPermission::OnlyOwner(owner) => return who == owner,
+				ClusterPermission::AllowList => (),
+			}
 		}
+		who == &cluster.owner
+			|| ClusterRoleAllowList::<T>::get((*cluster_id, role)).contains(who)
 	}
 
 	#[pallet::call]
@@ -159,7 +351,7 @@ pub mod pallet {
 		T: crate::mq::Config + crate::registry::Config,
 		T: frame_system::Config<AccountId = AccountId32>,
 	{
-		#[pallet::weight(0)]
+		#[pallet::weight(<T as Config>::WeightInfo::add_cluster(deploy_workers.len() as u32))]
 		pub fn add_cluster(
 			origin: OriginFor<T>,
 			permission: ClusterPermission<T::AccountId>,
@@ -195,12 +387,17 @@ pub mod pallet {
 			let cluster = ContractClusterId::from_low_u64_be(cluster_id);
 
 			Clusters::<T>::insert(&cluster, &cluster_info);
+			// The storage is charged from the creation of the cluster
+			ClusterUsageChargedAt::<T>::insert(&cluster, frame_system::Pallet::<T>::block_number());
 			Self::deposit_event(Event::ClusterCreated { cluster });
 			Self::push_message(ClusterEvent::DeployCluster { cluster, workers });
 			Ok(())
 		}
 
-		#[pallet::weight(0)]
+		#[pallet::weight(<T as Config>::WeightInfo::cluster_upload_resource(
+			resource_data.len() as u32,
+			CLUSTER_WORKERS_WEIGHT_LEN
+		))]
 		pub fn cluster_upload_resource(
 			origin: OriginFor<T>,
 			cluster_id: ContractClusterId,
@@ -210,7 +407,12 @@ pub mod pallet {
 			let origin: T::AccountId = ensure_signed(origin)?;
 			let cluster_info = Clusters::<T>::get(cluster_id).ok_or(Error::<T>::ClusterNotFound)?;
 			ensure!(
-				check_cluster_permission::<T>(&origin, &cluster_info),
+				check_cluster_permission::<T>(
+					&origin,
+					&cluster_id,
+					&cluster_info,
+					ClusterRole::CodeUploader
+				),
 				Error::<T>::ClusterPermissionDenied
 			);
 
@@ -223,6 +425,16 @@ pub mod pallet {
 				Error::<T>::PayloadTooLarge
 			);
 
+			// The code is stored by all the workers of the cluster
+			let workers = ClusterWorkers::<T>::get(&cluster_id);
+			ensure!(!workers.is_empty(), Error::<T>::ClusterNotDeployed);
+			let fee = T::CodeByteFee::get().saturating_mul((resource_data.len() as u32).into());
+			ensure!(
+				ClusterDeposits::<T>::get(&cluster_id) >= fee,
+				Error::<T>::InsufficientClusterDeposit
+			);
+			Self::charge_cluster(&cluster_id, fee, &workers);
+
 			Self::push_message(ClusterOperation::<_, T::BlockNumber>::UploadResource {
 				origin,
 				cluster_id,
@@ -232,7 +444,9 @@ pub mod pallet {
 			Ok(())
 		}
 
-		#[pallet::weight(0)]
+		#[pallet::weight(
+			<T as Config>::WeightInfo::instantiate_contract((data.len() + salt.len()) as u32)
+		)]
 		pub fn instantiate_contract(
 			origin: OriginFor<T>,
 			code_index: CodeIndex<CodeHash<T>>,
@@ -243,7 +457,12 @@ pub mod pallet {
 			let deployer = ensure_signed(origin)?;
 			let cluster_info = Clusters::<T>::get(cluster_id).ok_or(Error::<T>::ClusterNotFound)?;
 			ensure!(
-				check_cluster_permission::<T>(&deployer, &cluster_info),
+				check_cluster_permission::<T>(
+					&deployer,
+					&cluster_id,
+					&cluster_info,
+					ClusterRole::Deployer
+				),
 				Error::<T>::ClusterPermissionDenied
 			);
 
@@ -260,6 +479,7 @@ pub mod pallet {
 				Error::<T>::DuplicatedContract
 			);
 			Contracts::<T>::insert(&contract_id, &contract_info);
+			ClusterInstantiations::<T>::mutate(&cluster_id, |n| *n = n.saturating_add(1));
 
 			Self::push_message(ContractOperation::instantiate_code(contract_info.clone()));
 			Self::deposit_event(Event::Instantiating {
@@ -271,7 +491,7 @@ pub mod pallet {
 			Ok(())
 		}
 
-		#[pallet::weight(0)]
+		#[pallet::weight(<T as Config>::WeightInfo::cluster_set_log_handler())]
 		pub fn cluster_set_log_handler(
 			origin: OriginFor<T>,
 			cluster: ContractClusterId,
@@ -280,7 +500,12 @@ pub mod pallet {
 			let origin = ensure_signed(origin)?;
 			let cluster_info = Clusters::<T>::get(&cluster).ok_or(Error::<T>::ClusterNotFound)?;
 			ensure!(
-				origin == cluster_info.owner,
+				check_cluster_permission::<T>(
+					&origin,
+					&cluster,
+					&cluster_info,
+					ClusterRole::LogHandlerAdmin
+				),
 				Error::<T>::ClusterPermissionDenied
 			);
 
@@ -297,22 +522,492 @@ pub mod pallet {
 			Ok(())
 		}
 
-		#[pallet::weight(0)]
+		#[pallet::weight(<T as Config>::WeightInfo::cluster_destroy())]
 		pub fn cluster_destroy(origin: OriginFor<T>, cluster: ContractClusterId) -> DispatchResult {
 			ensure_root(origin)?;
 
-			Clusters::<T>::take(&cluster).ok_or(Error::<T>::ClusterNotFound)?;
+			let cluster_info = Clusters::<T>::take(&cluster).ok_or(Error::<T>::ClusterNotFound)?;
+			// Refund the remaining deposit to the owner
+			let deposit = ClusterDeposits::<T>::take(&cluster);
+			if !deposit.is_zero() {
+				T::Currency::transfer(
+					&Self::account_id(),
+					&cluster_info.owner,
+					deposit,
+					AllowDeath,
+				)?;
+			}
+			ClusterUsageChargedAt::<T>::remove(&cluster);
+			for worker in ClusterWorkers::<T>::get(&cluster) {
+				WorkerQueriesChargedAt::<T>::remove(&cluster, &worker);
+			}
+			for role in ClusterRole::ALL {
+				ClusterRoleAllowList::<T>::remove((cluster, role));
+			}
 			Self::push_message(
 				ClusterOperation::<T::AccountId, T::BlockNumber>::DestroyCluster(cluster),
 			);
 			Self::deposit_event(Event::ClusterDestroyed { cluster });
 			Ok(())
 		}
+
+		#[pallet::weight(<T as Config>::WeightInfo::cluster_set_gas_schedule())]
+		pub fn cluster_set_gas_schedule(
+			origin: OriginFor<T>,
+			cluster: ContractClusterId,
+			version: Option<u32>,
+		) -> DispatchResult {
+			let origin = ensure_signed(origin)?;
+			let cluster_info = Clusters::<T>::get(&cluster).ok_or(Error::<T>::ClusterNotFound)?;
+			ensure!(
+				origin == cluster_info.owner,
+				Error::<T>::ClusterPermissionDenied
+			);
+			if let Some(version) = version {
+				ensure!(
+					(1..=LATEST_GAS_SCHEDULE_VERSION).contains(&version),
+					Error::<T>::UnknownGasSchedule
+				);
+			}
+
+			Self::push_message(
+				ClusterOperation::<T::AccountId, T::BlockNumber>::SetGasSchedule {
+					cluster,
+					version,
+				},
+			);
+			Self::deposit_event(Event::ClusterSetGasSchedule { cluster, version });
+			Ok(())
+		}
+
+		/// Deploys the cluster to more workers.
+		///
+		/// The Gatekeeper shares the existing cluster key to the new workers. They only serve the
+		/// operations of the cluster dispatched after they are added. Replaying the instantiations
+		/// to them would not recover the state the contracts have built up since, so a cluster
+		/// can only take new workers before any contract is requested to be instantiated in it.
+		///
+		/// Can only be called by the cluster owner.
+		#[pallet::weight(<T as Config>::WeightInfo::cluster_add_workers(workers.len() as u32))]
+		pub fn cluster_add_workers(
+			origin: OriginFor<T>,
+			cluster: ContractClusterId,
+			workers: Vec<WorkerPublicKey>,
+		) -> DispatchResult {
+			let origin = ensure_signed(origin)?;
+			let mut cluster_info =
+				Clusters::<T>::get(&cluster).ok_or(Error::<T>::ClusterNotFound)?;
+			ensure!(
+				origin == cluster_info.owner,
+				Error::<T>::ClusterPermissionDenied
+			);
+			ensure!(!workers.is_empty(), Error::<T>::NoWorkerSpecified);
+			// The contracts instantiated before the instantiations were counted are only in
+			// `ClusterContracts`.
+			ensure!(
+				ClusterInstantiations::<T>::get(&cluster) == 0
+					&& ClusterContracts::<T>::get(&cluster).is_empty(),
+				Error::<T>::ClusterHasContracts
+			);
+			// The Gatekeeper needs the cluster pubkey to recover the cluster key
+			let pubkey =
+				registry::ClusterKeys::<T>::get(&cluster).ok_or(Error::<T>::ClusterNotDeployed)?;
+
+			let mut identities = Vec::with_capacity(workers.len());
+			for worker in workers.iter() {
+				ensure!(
+					!cluster_info.workers.contains(worker),
+					Error::<T>::DuplicatedDeployment
+				);
+				let worker_info =
+					registry::Workers::<T>::get(worker).ok_or(Error::<T>::WorkerNotFound)?;
+				identities.push(WorkerIdentity {
+					pubkey: worker_info.pubkey,
+					ecdh_pubkey: worker_info.ecdh_pubkey,
+				});
+				cluster_info.workers.push(*worker);
+			}
+
+			Clusters::<T>::insert(&cluster, &cluster_info);
+			Self::push_message(ClusterEvent::AddWorkers {
+				cluster,
+				pubkey,
+				workers: identities,
+			});
+			Self::deposit_event(Event::ClusterWorkersAdded { cluster, workers });
+			Ok(())
+		}
+
+		/// Removes a worker from the cluster.
+		///
+		/// The removed worker drops the cluster and all the contracts in it. Note that the cluster
+		/// key it received can not be revoked.
+		///
+		/// Can only be called by the cluster owner. The last worker can't be removed, destroy the
+		/// cluster instead.
+		#[pallet::weight(<T as Config>::WeightInfo::cluster_remove_worker())]
+		pub fn cluster_remove_worker(
+			origin: OriginFor<T>,
+			cluster: ContractClusterId,
+			worker: WorkerPublicKey,
+		) -> DispatchResult {
+			let origin = ensure_signed(origin)?;
+			let mut cluster_info =
+				Clusters::<T>::get(&cluster).ok_or(Error::<T>::ClusterNotFound)?;
+			ensure!(
+				origin == cluster_info.owner,
+				Error::<T>::ClusterPermissionDenied
+			);
+			ensure!(
+				cluster_info.workers.contains(&worker),
+				Error::<T>::WorkerNotInCluster
+			);
+			ensure!(
+				cluster_info.workers.len() > 1,
+				Error::<T>::CannotRemoveLastWorker
+			);
+
+			cluster_info.workers.retain(|w| w != &worker);
+			Clusters::<T>::insert(&cluster, &cluster_info);
+			ClusterWorkers::<T>::mutate(&cluster, |workers| workers.retain(|w| w != &worker));
+			WorkerQueriesChargedAt::<T>::remove(&cluster, &worker);
+			Self::push_message(
+				ClusterOperation::<T::AccountId, T::BlockNumber>::RemoveWorker { cluster, worker },
+			);
+			Self::deposit_event(Event::ClusterWorkerRemoved { cluster, worker });
+			Ok(())
+		}
+
+		/// Transfers the ownership of the cluster.
+		///
+		/// An `OnlyOwner` permission of the previous owner is moved to the new owner as well.
+		///
+		/// Can only be called by the cluster owner.
+		#[pallet::weight(<T as Config>::WeightInfo::cluster_transfer_ownership())]
+		pub fn cluster_transfer_ownership(
+			origin: OriginFor<T>,
+			cluster: ContractClusterId,
+			new_owner: T::AccountId,
+		) -> DispatchResult {
+			let origin = ensure_signed(origin)?;
+			let mut cluster_info =
+				Clusters::<T>::get(&cluster).ok_or(Error::<T>::ClusterNotFound)?;
+			ensure!(
+				origin == cluster_info.owner,
+				Error::<T>::ClusterPermissionDenied
+			);
+
+			if cluster_info.permission == ClusterPermission::OnlyOwner(origin) {
+				cluster_info.permission = ClusterPermission::OnlyOwner(new_owner.clone());
+			}
+			cluster_info.owner = new_owner.clone();
+			Clusters::<T>::insert(&cluster, &cluster_info);
+			Self::deposit_event(Event::ClusterOwnershipTransferred {
+				cluster,
+				owner: new_owner,
+			});
+			Ok(())
+		}
+
+		/// Changes the permission to deploy contracts to the cluster.
+		///
+		/// Can only be called by the cluster owner.
+		#[pallet::weight(<T as Config>::WeightInfo::cluster_set_permission())]
+		pub fn cluster_set_permission(
+			origin: OriginFor<T>,
+			cluster: ContractClusterId,
+			permission: ClusterPermission<T::AccountId>,
+		) -> DispatchResult {
+			let origin = ensure_signed(origin)?;
+			let mut cluster_info =
+				Clusters::<T>::get(&cluster).ok_or(Error::<T>::ClusterNotFound)?;
+			ensure!(
+				origin == cluster_info.owner,
+				Error::<T>::ClusterPermissionDenied
+			);
+
+			cluster_info.permission = permission;
+			Clusters::<T>::insert(&cluster, &cluster_info);
+			Self::deposit_event(Event::ClusterSetPermission { cluster });
+			Ok(())
+		}
+
+		/// Destroys a contract.
+		///
+		/// Can be called by the contract deployer or the accounts holding the `Destroyer` role in
+		/// the cluster.
+		#[pallet::weight(<T as Config>::WeightInfo::contract_destroy())]
+		pub fn contract_destroy(origin: OriginFor<T>, contract: ContractId) -> DispatchResult {
+			let origin = ensure_signed(origin)?;
+			let contract_info = Contracts::<T>::get(&contract).ok_or(Error::<T>::ContractNotFound)?;
+			let cluster = contract_info.cluster_id;
+			let permitted = origin == contract_info.deployer
+				|| Clusters::<T>::get(&cluster)
+					.map(|cluster_info| {
+						check_cluster_permission::<T>(
+							&origin,
+							&cluster,
+							&cluster_info,
+							ClusterRole::Destroyer,
+						)
+					})
+					.unwrap_or(false);
+			ensure!(permitted, Error::<T>::ContractPermissionDenied);
+
+			Contracts::<T>::remove(&contract);
+			ClusterContracts::<T>::mutate(&cluster, |contracts| {
+				contracts.retain(|c| c != &contract)
+			});
+			ClusterInstantiations::<T>::mutate(&cluster, |n| *n = n.saturating_sub(1));
+			registry::ContractKeys::<T>::remove(&contract);
+			Self::push_message(
+				ClusterOperation::<T::AccountId, T::BlockNumber>::DestroyContract {
+					cluster,
+					contract,
+				},
+			);
+			Self::deposit_event(Event::ContractDestroyed { contract, cluster });
+			Ok(())
+		}
+
+		/// Grants a role in the cluster to an account.
+		///
+		/// Can only be called by the cluster owner. At most 100 accounts can hold the same role.
+		#[pallet::weight(<T as Config>::WeightInfo::cluster_grant_role())]
+		pub fn cluster_grant_role(
+			origin: OriginFor<T>,
+			cluster: ContractClusterId,
+			role: ClusterRole,
+			account: T::AccountId,
+		) -> DispatchResult {
+			let origin = ensure_signed(origin)?;
+			let cluster_info = Clusters::<T>::get(&cluster).ok_or(Error::<T>::ClusterNotFound)?;
+			ensure!(
+				origin == cluster_info.owner,
+				Error::<T>::ClusterPermissionDenied
+			);
+
+			let mut allowlist = ClusterRoleAllowList::<T>::get((cluster, role));
+			ensure!(
+				!allowlist.contains(&account),
+				Error::<T>::AlreadyInRoleAllowList
+			);
+			ensure!(
+				(allowlist.len() as u32) < MAX_ROLE_ALLOWLIST_LEN,
+				Error::<T>::ExceedRoleAllowListMaxLen
+			);
+			allowlist.push(account.clone());
+			ClusterRoleAllowList::<T>::insert((cluster, role), &allowlist);
+			Self::deposit_event(Event::ClusterRoleGranted {
+				cluster,
+				role,
+				account,
+			});
+			Ok(())
+		}
+
+		/// Revokes a role in the cluster from an account.
+		///
+		/// Can only be called by the cluster owner.
+		#[pallet::weight(<T as Config>::WeightInfo::cluster_revoke_role())]
+		pub fn cluster_revoke_role(
+			origin: OriginFor<T>,
+			cluster: ContractClusterId,
+			role: ClusterRole,
+			account: T::AccountId,
+		) -> DispatchResult {
+			let origin = ensure_signed(origin)?;
+			let cluster_info = Clusters::<T>::get(&cluster).ok_or(Error::<T>::ClusterNotFound)?;
+			ensure!(
+				origin == cluster_info.owner,
+				Error::<T>::ClusterPermissionDenied
+			);
+
+			let mut allowlist = ClusterRoleAllowList::<T>::get((cluster, role));
+			ensure!(
+				allowlist.contains(&account),
+				Error::<T>::NotInRoleAllowList
+			);
+			allowlist.retain(|a| a != &account);
+			if allowlist.is_empty() {
+				ClusterRoleAllowList::<T>::remove((cluster, role));
+			} else {
+				ClusterRoleAllowList::<T>::insert((cluster, role), &allowlist);
+			}
+			Self::deposit_event(Event::ClusterRoleRevoked {
+				cluster,
+				role,
+				account,
+			});
+			Ok(())
+		}
+
+		/// Deposits to the cluster to pay for its contract usage.
+		///
+		/// Anyone can deposit to a cluster.
+		#[pallet::weight(<T as Config>::WeightInfo::cluster_deposit())]
+		pub fn cluster_deposit(
+			origin: OriginFor<T>,
+			cluster: ContractClusterId,
+			amount: BalanceOf<T>,
+		) -> DispatchResult {
+			let origin = ensure_signed(origin)?;
+			ensure!(
+				Clusters::<T>::contains_key(&cluster),
+				Error::<T>::ClusterNotFound
+			);
+
+			T::Currency::transfer(&origin, &Self::account_id(), amount, AllowDeath)?;
+			ClusterDeposits::<T>::mutate(&cluster, |deposit| {
+				*deposit = deposit.saturating_add(amount)
+			});
+			Self::deposit_event(Event::ClusterDeposited {
+				cluster,
+				account: origin,
+				amount,
+			});
+			Ok(())
+		}
+
+		/// Withdraws the unused deposit of the cluster.
+		///
+		/// Can only be called by the cluster owner.
+		#[pallet::weight(<T as Config>::WeightInfo::cluster_withdraw_deposit())]
+		pub fn cluster_withdraw_deposit(
+			origin: OriginFor<T>,
+			cluster: ContractClusterId,
+			amount: BalanceOf<T>,
+		) -> DispatchResult {
+			let origin = ensure_signed(origin)?;
+			let cluster_info = Clusters::<T>::get(&cluster).ok_or(Error::<T>::ClusterNotFound)?;
+			ensure!(
+				origin == cluster_info.owner,
+				Error::<T>::ClusterPermissionDenied
+			);
+			let deposit = ClusterDeposits::<T>::get(&cluster);
+			ensure!(deposit >= amount, Error::<T>::InsufficientClusterDeposit);
+
+			T::Currency::transfer(&Self::account_id(), &origin, amount, AllowDeath)?;
+			ClusterDeposits::<T>::insert(&cluster, deposit - amount);
+			Self::deposit_event(Event::ClusterDepositWithdrawn {
+				cluster,
+				account: origin,
+				amount,
+			});
+			Ok(())
+		}
+
+		/// Claims the fees earned by a worker serving the clusters.
+		///
+		/// Can only be called by the operator of the worker.
+		#[pallet::weight(<T as Config>::WeightInfo::claim_worker_payout())]
+		pub fn claim_worker_payout(
+			origin: OriginFor<T>,
+			worker: WorkerPublicKey,
+		) -> DispatchResult {
+			let origin = ensure_signed(origin)?;
+			let worker_info =
+				registry::Workers::<T>::get(&worker).ok_or(Error::<T>::WorkerNotFound)?;
+			ensure!(
+				worker_info.operator.as_ref() == Some(&origin),
+				Error::<T>::NotWorkerOperator
+			);
+			let amount = WorkerPendingPayouts::<T>::take(&worker);
+			ensure!(!amount.is_zero(), Error::<T>::NoPayout);
+
+			T::Currency::transfer(&Self::account_id(), &origin, amount, AllowDeath)?;
+			Self::deposit_event(Event::WorkerPayoutClaimed {
+				worker,
+				account: origin,
+				amount,
+			});
+			Ok(())
+		}
+	}
+
+	impl<T: Config> Pallet<T> {
+		pub fn account_id() -> T::AccountId {
+			FAT_PALLETID.into_account_truncating()
+		}
+
+		/// Charges `fee` from the cluster deposit and shares it among `workers`.
+		///
+		/// Charges the whole deposit if it's insufficient. Returns the amount charged.
+		fn charge_cluster(
+			cluster: &ContractClusterId,
+			fee: BalanceOf<T>,
+			workers: &[WorkerPublicKey],
+		) -> BalanceOf<T> {
+			if fee.is_zero() || workers.is_empty() {
+				return Zero::zero();
+			}
+			let amount = ClusterDeposits::<T>::mutate(cluster, |deposit| {
+				let amount = fee.min(*deposit);
+				*deposit -= amount;
+				amount
+			});
+			if amount < fee {
+				Self::deposit_event(Event::ClusterDepositExhausted { cluster: *cluster });
+			}
+			if amount.is_zero() {
+				return amount;
+			}
+			Self::pay_workers(amount, workers);
+			Self::deposit_event(Event::ClusterCharged {
+				cluster: *cluster,
+				amount,
+			});
+			amount
+		}
+
+		/// Charges `fee` from the free balance of `who` and shares it among `workers`.
+		///
+		/// Charges what `who` can pay without being reaped if the balance is insufficient.
+		/// Returns the amount charged.
+		fn charge_account(
+			cluster: &ContractClusterId,
+			who: &T::AccountId,
+			fee: BalanceOf<T>,
+			workers: &[WorkerPublicKey],
+		) -> BalanceOf<T> {
+			if fee.is_zero() || workers.is_empty() {
+				return Zero::zero();
+			}
+			let available =
+				T::Currency::free_balance(who).saturating_sub(T::Currency::minimum_balance());
+			let amount = fee.min(available);
+			if amount.is_zero()
+				|| T::Currency::transfer(who, &Self::account_id(), amount, KeepAlive).is_err()
+			{
+				return Zero::zero();
+			}
+			Self::pay_workers(amount, workers);
+			Self::deposit_event(Event::AccountCharged {
+				cluster: *cluster,
+				account: who.clone(),
+				amount,
+			});
+			amount
+		}
+
+		/// Shares `amount` among `workers`, the first worker takes the dust.
+		fn pay_workers(amount: BalanceOf<T>, workers: &[WorkerPublicKey]) {
+			let n: BalanceOf<T> = (workers.len() as u32).into();
+			let share = amount / n;
+			let dust = amount - share * n;
+			for (i, worker) in workers.iter().enumerate() {
+				let payout = if i == 0 { share + dust } else { share };
+				WorkerPendingPayouts::<T>::mutate(worker, |pending| {
+					*pending = pending.saturating_add(payout)
+				});
+			}
+		}
 	}
 
 	impl<T: Config> Pallet<T>
 	where
 		T: crate::mq::Config + crate::registry::Config,
+		T: frame_system::Config<AccountId = AccountId32>,
 	{
 		pub fn on_cluster_message_received(
 			message: DecodedMessage<ClusterRegistryEvent>,
@@ -360,8 +1055,18 @@ pub mod pallet {
 			};
 			match message.payload {
 				WorkerClusterReport::ClusterDeployed { id, pubkey } => {
+					// The worker may have been removed before its report arrives
+					let assigned = Clusters::<T>::get(&id)
+						.map(|info| info.workers.contains(&worker_pubkey))
+						.unwrap_or(false);
+					if !assigned {
+						return Ok(());
+					}
 					// TODO.shelven: scalability concern for large number of workers
-					ClusterWorkers::<T>::append(&id, &worker_pubkey);
+					let workers = ClusterWorkers::<T>::get(&id);
+					if !workers.contains(&worker_pubkey) {
+						ClusterWorkers::<T>::append(&id, &worker_pubkey);
+					}
 					Self::deposit_event(Event::ClusterDeployed {
 						cluster: id,
 						pubkey,
@@ -381,7 +1086,7 @@ pub mod pallet {
 		pub fn on_worker_contract_message_received(
 			message: DecodedMessage<WorkerContractReport>,
 		) -> DispatchResult {
-			let _worker_pubkey = match &message.sender {
+			let worker_pubkey = match &message.sender {
 				MessageOrigin::Worker(worker_pubkey) => worker_pubkey,
 				_ => return Err(Error::<T>::InvalidSender.into()),
 			};
@@ -393,7 +1098,8 @@ pub mod pallet {
 					pubkey: _,
 				} => {
 					let contracts = ClusterContracts::<T>::get(&cluster_id);
-					if !contracts.contains(&id) {
+					// Skip the contracts destroyed before their instantiation were reported
+					if Contracts::<T>::contains_key(&id) && !contracts.contains(&id) {
 						ClusterContracts::<T>::append(&cluster_id, &id);
 					}
 					Self::deposit_event(Event::Instantiated {
@@ -414,6 +1120,75 @@ pub mod pallet {
 					});
 					// TODO.shelven: some cleanup?
 				}
+				WorkerContractReport::ClusterUsage {
+					cluster_id,
+					block_number,
+					commands,
+					queries,
+					storage_bytes,
+				} => {
+					let cluster_info =
+						Clusters::<T>::get(&cluster_id).ok_or(Error::<T>::ClusterNotFound)?;
+					let workers = ClusterWorkers::<T>::get(&cluster_id);
+					ensure!(
+						workers.contains(worker_pubkey),
+						Error::<T>::WorkerNotInCluster
+					);
+					let block_number: T::BlockNumber = block_number.into();
+					ensure!(
+						block_number <= frame_system::Pallet::<T>::block_number(),
+						Error::<T>::InvalidUsagePeriod
+					);
+					// The queries are served by the reporting worker alone, so it takes the fee
+					let queries_charged_at =
+						WorkerQueriesChargedAt::<T>::get(&cluster_id, worker_pubkey);
+					if queries_charged_at.map_or(true, |charged_at| block_number > charged_at) {
+						WorkerQueriesChargedAt::<T>::insert(
+							&cluster_id,
+							worker_pubkey,
+							block_number,
+						);
+						let queries = queries.min(T::QueryQuota::get());
+						let fee = T::QueryFee::get().saturating_mul(queries.into());
+						Self::charge_cluster(
+							&cluster_id,
+							fee,
+							sp_std::slice::from_ref(worker_pubkey),
+						);
+					}
+					// The commands and storage are already charged by the report of another worker
+					let charged_at = ClusterUsageChargedAt::<T>::get(&cluster_id);
+					if let Some(charged_at) = charged_at {
+						if block_number <= charged_at {
+							return Ok(());
+						}
+					}
+					ClusterUsageChargedAt::<T>::insert(&cluster_id, block_number);
+					// The commands are processed and the storage is kept by all the workers, so
+					// they share the fees
+					let elapsed: u64 = block_number
+						.saturating_sub(charged_at.unwrap_or(block_number))
+						.saturated_into();
+					let mut fee = T::StorageByteFee::get()
+						.saturating_mul(storage_bytes.saturating_mul(elapsed).saturated_into());
+					for (sender, count) in commands {
+						let command_fee = T::CommandFee::get().saturating_mul(count.into());
+						match sender.map(|sender| AccountId32::from(sender.0)) {
+							Some(sender)
+								if !check_cluster_permission::<T>(
+									&sender,
+									&cluster_id,
+									&cluster_info,
+									ClusterRole::SponsoredSender,
+								) =>
+							{
+								Self::charge_account(&cluster_id, &sender, command_fee, &workers);
+							}
+							_ => fee = fee.saturating_add(command_fee),
+						}
+					}
+					Self::charge_cluster(&cluster_id, fee, &workers);
+				}
 			}
 			Ok(())
 		}
@@ -422,4 +1197,911 @@ pub mod pallet {
 	impl<T: Config + crate::mq::Config> MessageOriginInfo for Pallet<T> {
 		type Config = T;
 	}
+
+	#[cfg(test)]
+	mod test {
+		use frame_support::{assert_noop, assert_ok};
+		use phala_types::messaging::Topic;
+
+		use super::*;
+		use crate::fat::mock::{
+			account, new_test_ext, setup_workers, take_events, worker_pubkey, Balance, CodeByteFee,
+			Event as TestEvent, Origin, QueryFee, QueryQuota, StorageByteFee, Test, CENTS, DOLLARS,
+		};
+		// Pallets
+		use crate::fat::mock::{Balances, PhalaFat, System};
+
+		#[test]
+		fn test_cluster_add_workers() {
+			new_test_ext().execute_with(|| {
+				setup_workers(4);
+				let cluster = setup_cluster(&[1]);
+				// Only the owner can add workers
+				assert_noop!(
+					PhalaFat::cluster_add_workers(
+						Origin::signed(account(2)),
+						cluster,
+						vec![worker_pubkey(2)]
+					),
+					Error::<Test>::ClusterPermissionDenied
+				);
+				assert_noop!(
+					PhalaFat::cluster_add_workers(Origin::signed(account(1)), cluster, vec![]),
+					Error::<Test>::NoWorkerSpecified
+				);
+				assert_noop!(
+					PhalaFat::cluster_add_workers(
+						Origin::signed(account(1)),
+						cluster,
+						vec![worker_pubkey(1)]
+					),
+					Error::<Test>::DuplicatedDeployment
+				);
+				assert_noop!(
+					PhalaFat::cluster_add_workers(
+						Origin::signed(account(1)),
+						cluster,
+						vec![worker_pubkey(9)]
+					),
+					Error::<Test>::WorkerNotFound
+				);
+				let _ = take_events();
+				assert_ok!(PhalaFat::cluster_add_workers(
+					Origin::signed(account(1)),
+					cluster,
+					vec![worker_pubkey(2)]
+				));
+				assert_eq!(
+					Clusters::<Test>::get(cluster).unwrap().workers,
+					vec![worker_pubkey(1), worker_pubkey(2)]
+				);
+				assert_eq!(
+					take_events(),
+					vec![TestEvent::PhalaFat(Event::ClusterWorkersAdded {
+						cluster,
+						workers: vec![worker_pubkey(2)],
+					})]
+				);
+				// The new workers can't replay the contracts, even the ones being instantiated
+				assert_ok!(PhalaFat::instantiate_contract(
+					Origin::signed(account(1)),
+					CodeIndex::WasmCode(H256::repeat_byte(1)),
+					vec![],
+					vec![],
+					cluster,
+				));
+				assert!(ClusterContracts::<Test>::get(cluster).is_empty());
+				let add_worker_3 = || {
+					PhalaFat::cluster_add_workers(
+						Origin::signed(account(1)),
+						cluster,
+						vec![worker_pubkey(3)],
+					)
+				};
+				assert_noop!(add_worker_3(), Error::<Test>::ClusterHasContracts);
+				// Until all of them are destroyed
+				let contract = Contracts::<Test>::iter_keys().next().unwrap();
+				assert_ok!(PhalaFat::contract_destroy(
+					Origin::signed(account(1)),
+					contract
+				));
+				assert_ok!(add_worker_3());
+				// The contracts instantiated before the instantiations were counted
+				ClusterContracts::<Test>::insert(cluster, vec![ContractId::repeat_byte(1)]);
+				assert_noop!(
+					PhalaFat::cluster_add_workers(
+						Origin::signed(account(1)),
+						cluster,
+						vec![worker_pubkey(4)]
+					),
+					Error::<Test>::ClusterHasContracts
+				);
+			});
+		}
+
+		#[test]
+		fn test_cluster_set_gas_schedule() {
+			new_test_ext().execute_with(|| {
+				setup_workers(1);
+				let cluster = setup_cluster(&[1]);
+				for version in [Some(0), Some(LATEST_GAS_SCHEDULE_VERSION + 1)] {
+					assert_noop!(
+						PhalaFat::cluster_set_gas_schedule(
+							Origin::signed(account(1)),
+							cluster,
+							version
+						),
+						Error::<Test>::UnknownGasSchedule
+					);
+				}
+				assert_noop!(
+					PhalaFat::cluster_set_gas_schedule(
+						Origin::signed(account(2)),
+						cluster,
+						Some(LATEST_GAS_SCHEDULE_VERSION)
+					),
+					Error::<Test>::ClusterPermissionDenied
+				);
+				let _ = take_events();
+				for version in [Some(LATEST_GAS_SCHEDULE_VERSION), None] {
+					assert_ok!(PhalaFat::cluster_set_gas_schedule(
+						Origin::signed(account(1)),
+						cluster,
+						version
+					));
+					assert_eq!(
+						take_events(),
+						vec![TestEvent::PhalaFat(Event::ClusterSetGasSchedule {
+							cluster,
+							version
+						})]
+					);
+				}
+			});
+		}
+
+		#[test]
+		fn test_cluster_add_workers_before_deployed() {
+			new_test_ext().execute_with(|| {
+				setup_workers(2);
+				assert_ok!(PhalaFat::add_cluster(
+					Origin::signed(account(1)),
+					ClusterPermission::Public,
+					vec![worker_pubkey(1)]
+				));
+				// No cluster key to share yet
+				assert_noop!(
+					PhalaFat::cluster_add_workers(
+						Origin::signed(account(1)),
+						ContractClusterId::from_low_u64_be(0),
+						vec![worker_pubkey(2)]
+					),
+					Error::<Test>::ClusterNotDeployed
+				);
+			});
+		}
+
+		#[test]
+		fn test_cluster_remove_worker() {
+			new_test_ext().execute_with(|| {
+				setup_workers(2);
+				let cluster = setup_cluster(&[1, 2]);
+				assert_noop!(
+					PhalaFat::cluster_remove_worker(
+						Origin::signed(account(2)),
+						cluster,
+						worker_pubkey(2)
+					),
+					Error::<Test>::ClusterPermissionDenied
+				);
+				assert_noop!(
+					PhalaFat::cluster_remove_worker(
+						Origin::signed(account(1)),
+						cluster,
+						worker_pubkey(3)
+					),
+					Error::<Test>::WorkerNotInCluster
+				);
+				assert_ok!(PhalaFat::cluster_remove_worker(
+					Origin::signed(account(1)),
+					cluster,
+					worker_pubkey(2)
+				));
+				assert_eq!(
+					Clusters::<Test>::get(cluster).unwrap().workers,
+					vec![worker_pubkey(1)]
+				);
+				assert_eq!(ClusterWorkers::<Test>::get(cluster), vec![worker_pubkey(1)]);
+				// The late deployment report of a removed worker is ignored
+				report_deployed(cluster, 2);
+				assert_eq!(ClusterWorkers::<Test>::get(cluster), vec![worker_pubkey(1)]);
+				assert_noop!(
+					PhalaFat::cluster_remove_worker(
+						Origin::signed(account(1)),
+						cluster,
+						worker_pubkey(1)
+					),
+					Error::<Test>::CannotRemoveLastWorker
+				);
+			});
+		}
+
+		#[test]
+		fn test_cluster_transfer_ownership() {
+			new_test_ext().execute_with(|| {
+				setup_workers(1);
+				let cluster = setup_cluster(&[1]);
+				assert_ok!(PhalaFat::cluster_set_permission(
+					Origin::signed(account(1)),
+					cluster,
+					ClusterPermission::OnlyOwner(account(1))
+				));
+				assert_noop!(
+					PhalaFat::cluster_transfer_ownership(
+						Origin::signed(account(2)),
+						cluster,
+						account(2)
+					),
+					Error::<Test>::ClusterPermissionDenied
+				);
+				assert_ok!(PhalaFat::cluster_transfer_ownership(
+					Origin::signed(account(1)),
+					cluster,
+					account(2)
+				));
+				// The `OnlyOwner` permission follows the owner
+				let cluster_info = Clusters::<Test>::get(cluster).unwrap();
+				assert_eq!(cluster_info.owner, account(2));
+				assert_eq!(
+					cluster_info.permission,
+					ClusterPermission::OnlyOwner(account(2))
+				);
+				// The previous owner loses the control
+				assert_noop!(
+					PhalaFat::cluster_set_permission(
+						Origin::signed(account(1)),
+						cluster,
+						ClusterPermission::Public
+					),
+					Error::<Test>::ClusterPermissionDenied
+				);
+				assert_ok!(PhalaFat::cluster_set_permission(
+					Origin::signed(account(2)),
+					cluster,
+					ClusterPermission::AllowList
+				));
+				// Other permissions are kept on transfer
+				assert_ok!(PhalaFat::cluster_transfer_ownership(
+					Origin::signed(account(2)),
+					cluster,
+					account(3)
+				));
+				let cluster_info = Clusters::<Test>::get(cluster).unwrap();
+				assert_eq!(cluster_info.owner, account(3));
+				assert_eq!(cluster_info.permission, ClusterPermission::AllowList);
+			});
+		}
+
+		#[test]
+		fn test_check_cluster_permission() {
+			let owner = account(1);
+			let granted = account(2);
+			let stranger = account(3);
+			let cluster_id = ContractClusterId::from_low_u64_be(0);
+			let check = |who: &AccountId32, permission: &ClusterPermission<AccountId32>, role| {
+				let cluster = ClusterInfo {
+					owner: owner.clone(),
+					permission: permission.clone(),
+					workers: vec![],
+				};
+				check_cluster_permission::<Test>(who, &cluster_id, &cluster, role)
+			};
+			new_test_ext().execute_with(|| {
+				for role in ClusterRole::ALL {
+					ClusterRoleAllowList::<Test>::insert((cluster_id, role), vec![granted.clone()]);
+				}
+				for permission in [
+					ClusterPermission::Public,
+					ClusterPermission::OnlyOwner(owner.clone()),
+					ClusterPermission::AllowList,
+				] {
+					// The owner holds all the roles
+					for role in ClusterRole::ALL {
+						assert!(check(&owner, &permission, role));
+					}
+					// The other roles always follow the allowlists
+					for role in [
+						ClusterRole::LogHandlerAdmin,
+						ClusterRole::Destroyer,
+						ClusterRole::SponsoredSender,
+					] {
+						assert!(check(&granted, &permission, role));
+						assert!(!check(&stranger, &permission, role));
+					}
+				}
+				// Deploying and uploading code follow the cluster permission
+				for role in [ClusterRole::Deployer, ClusterRole::CodeUploader] {
+					let public = ClusterPermission::Public;
+					assert!(check(&granted, &public, role));
+					assert!(check(&stranger, &public, role));
+					let only_owner = ClusterPermission::OnlyOwner(owner.clone());
+					assert!(!check(&granted, &only_owner, role));
+					assert!(!check(&stranger, &only_owner, role));
+					let allowlist = ClusterPermission::AllowList;
+					assert!(check(&granted, &allowlist, role));
+					assert!(!check(&stranger, &allowlist, role));
+				}
+			});
+		}
+
+		#[test]
+		fn test_cluster_grant_revoke_role() {
+			new_test_ext().execute_with(|| {
+				setup_workers(1);
+				let cluster = setup_cluster(&[1]);
+				let role = ClusterRole::Deployer;
+				// Only the owner can grant or revoke roles
+				assert_noop!(
+					PhalaFat::cluster_grant_role(
+						Origin::signed(account(2)),
+						cluster,
+						role,
+						account(2)
+					),
+					Error::<Test>::ClusterPermissionDenied
+				);
+				assert_ok!(PhalaFat::cluster_grant_role(
+					Origin::signed(account(1)),
+					cluster,
+					role,
+					account(2)
+				));
+				assert_noop!(
+					PhalaFat::cluster_grant_role(
+						Origin::signed(account(1)),
+						cluster,
+						role,
+						account(2)
+					),
+					Error::<Test>::AlreadyInRoleAllowList
+				);
+				// The roles are granted separately
+				assert_eq!(
+					ClusterRoleAllowList::<Test>::get((cluster, ClusterRole::Destroyer)),
+					vec![]
+				);
+				assert_noop!(
+					PhalaFat::cluster_revoke_role(
+						Origin::signed(account(2)),
+						cluster,
+						role,
+						account(2)
+					),
+					Error::<Test>::ClusterPermissionDenied
+				);
+				assert_noop!(
+					PhalaFat::cluster_revoke_role(
+						Origin::signed(account(1)),
+						cluster,
+						role,
+						account(3)
+					),
+					Error::<Test>::NotInRoleAllowList
+				);
+				assert_ok!(PhalaFat::cluster_revoke_role(
+					Origin::signed(account(1)),
+					cluster,
+					role,
+					account(2)
+				));
+				// The empty allowlist is removed from the storage
+				assert!(!ClusterRoleAllowList::<Test>::contains_key((cluster, role)));
+			});
+		}
+
+		#[test]
+		fn test_cluster_role_allowlist_max_len() {
+			new_test_ext().execute_with(|| {
+				setup_workers(1);
+				let cluster = setup_cluster(&[1]);
+				let role = ClusterRole::Destroyer;
+				for i in 0..MAX_ROLE_ALLOWLIST_LEN {
+					let mut raw = [0xffu8; 32];
+					raw[..4].copy_from_slice(&i.to_be_bytes());
+					assert_ok!(PhalaFat::cluster_grant_role(
+						Origin::signed(account(1)),
+						cluster,
+						role,
+						AccountId32::new(raw)
+					));
+				}
+				assert_noop!(
+					PhalaFat::cluster_grant_role(
+						Origin::signed(account(1)),
+						cluster,
+						role,
+						account(2)
+					),
+					Error::<Test>::ExceedRoleAllowListMaxLen
+				);
+			});
+		}
+
+		#[test]
+		fn test_cluster_roles_gate_calls() {
+			new_test_ext().execute_with(|| {
+				setup_workers(1);
+				let cluster = setup_cluster(&[1]);
+				assert_ok!(PhalaFat::cluster_set_permission(
+					Origin::signed(account(1)),
+					cluster,
+					ClusterPermission::AllowList
+				));
+				let instantiate = |who: u8| {
+					PhalaFat::instantiate_contract(
+						Origin::signed(account(who)),
+						CodeIndex::WasmCode(H256::repeat_byte(1)),
+						vec![],
+						vec![who],
+						cluster,
+					)
+				};
+				// Deployer
+				assert_noop!(instantiate(2), Error::<Test>::ClusterPermissionDenied);
+				grant(cluster, ClusterRole::Deployer, 2);
+				assert_ok!(instantiate(2));
+				let contract = Contracts::<Test>::iter_keys().next().unwrap();
+				// LogHandlerAdmin
+				assert_noop!(
+					PhalaFat::cluster_set_log_handler(
+						Origin::signed(account(3)),
+						cluster,
+						contract
+					),
+					Error::<Test>::ClusterPermissionDenied
+				);
+				grant(cluster, ClusterRole::LogHandlerAdmin, 3);
+				assert_ok!(PhalaFat::cluster_set_log_handler(
+					Origin::signed(account(3)),
+					cluster,
+					contract
+				));
+				// Destroyer
+				assert_noop!(
+					PhalaFat::contract_destroy(Origin::signed(account(3)), contract),
+					Error::<Test>::ContractPermissionDenied
+				);
+				grant(cluster, ClusterRole::Destroyer, 3);
+				assert_ok!(PhalaFat::contract_destroy(
+					Origin::signed(account(3)),
+					contract
+				));
+				// The allowlists are dropped with the cluster
+				assert_ok!(PhalaFat::cluster_destroy(Origin::root(), cluster));
+				for role in ClusterRole::ALL {
+					assert!(!ClusterRoleAllowList::<Test>::contains_key((cluster, role)));
+				}
+			});
+		}
+
+		#[test]
+		fn test_cluster_deposit() {
+			new_test_ext().execute_with(|| {
+				setup_workers(1);
+				let cluster = setup_cluster(&[1]);
+				assert_noop!(
+					PhalaFat::cluster_deposit(
+						Origin::signed(account(2)),
+						ContractClusterId::from_low_u64_be(9),
+						1 * DOLLARS
+					),
+					Error::<Test>::ClusterNotFound
+				);
+				// Anyone can deposit
+				assert_ok!(PhalaFat::cluster_deposit(
+					Origin::signed(account(2)),
+					cluster,
+					10 * DOLLARS
+				));
+				assert_eq!(ClusterDeposits::<Test>::get(cluster), 10 * DOLLARS);
+				assert_eq!(Balances::free_balance(account(2)), 1990 * DOLLARS);
+				assert_eq!(Balances::free_balance(PhalaFat::account_id()), 10 * DOLLARS);
+				// Only the owner can withdraw
+				assert_noop!(
+					PhalaFat::cluster_withdraw_deposit(
+						Origin::signed(account(2)),
+						cluster,
+						1 * DOLLARS
+					),
+					Error::<Test>::ClusterPermissionDenied
+				);
+				assert_noop!(
+					PhalaFat::cluster_withdraw_deposit(
+						Origin::signed(account(1)),
+						cluster,
+						11 * DOLLARS
+					),
+					Error::<Test>::InsufficientClusterDeposit
+				);
+				assert_ok!(PhalaFat::cluster_withdraw_deposit(
+					Origin::signed(account(1)),
+					cluster,
+					4 * DOLLARS
+				));
+				assert_eq!(ClusterDeposits::<Test>::get(cluster), 6 * DOLLARS);
+				assert_eq!(Balances::free_balance(account(1)), 1004 * DOLLARS);
+				// The remaining deposit is refunded to the owner on destruction
+				assert_ok!(PhalaFat::cluster_destroy(Origin::root(), cluster));
+				assert_eq!(ClusterDeposits::<Test>::get(cluster), 0);
+				assert_eq!(Balances::free_balance(account(1)), 1010 * DOLLARS);
+			});
+		}
+
+		#[test]
+		fn test_cluster_usage_charged_once() {
+			new_test_ext().execute_with(|| {
+				setup_workers(3);
+				let cluster = setup_cluster(&[1, 2]);
+				assert_ok!(PhalaFat::cluster_deposit(
+					Origin::signed(account(2)),
+					cluster,
+					10 * DOLLARS
+				));
+				System::set_block_number(100);
+				let _ = take_events();
+				assert_ok!(report_usage(cluster, 1, 100, 10));
+				assert_eq!(
+					take_events(),
+					vec![TestEvent::PhalaFat(Event::ClusterCharged {
+						cluster,
+						amount: 10 * CENTS,
+					})]
+				);
+				assert_eq!(
+					ClusterDeposits::<Test>::get(cluster),
+					10 * DOLLARS - 10 * CENTS
+				);
+				// Shared by all the workers of the cluster
+				assert_eq!(
+					WorkerPendingPayouts::<Test>::get(worker_pubkey(1)),
+					5 * CENTS
+				);
+				assert_eq!(
+					WorkerPendingPayouts::<Test>::get(worker_pubkey(2)),
+					5 * CENTS
+				);
+				// The same period reported by the other worker isn't charged again
+				assert_ok!(report_usage(cluster, 2, 100, 10));
+				// Neither are the earlier periods
+				assert_ok!(report_usage(cluster, 2, 50, 10));
+				assert_eq!(take_events(), vec![]);
+				assert_eq!(
+					ClusterDeposits::<Test>::get(cluster),
+					10 * DOLLARS - 10 * CENTS
+				);
+				// Only the workers of the cluster can report
+				assert_noop!(
+					report_usage(cluster, 3, 100, 10),
+					Error::<Test>::WorkerNotInCluster
+				);
+				// The period must have ended
+				assert_noop!(
+					report_usage(cluster, 1, 200, 10),
+					Error::<Test>::InvalidUsagePeriod
+				);
+				System::set_block_number(200);
+				assert_ok!(report_usage(cluster, 2, 200, 1));
+				assert_eq!(
+					ClusterDeposits::<Test>::get(cluster),
+					10 * DOLLARS - 11 * CENTS
+				);
+				assert_eq!(
+					WorkerPendingPayouts::<Test>::get(worker_pubkey(1)),
+					5 * CENTS + CENTS / 2
+				);
+				assert_eq!(
+					WorkerPendingPayouts::<Test>::get(worker_pubkey(2)),
+					5 * CENTS + CENTS / 2
+				);
+			});
+		}
+
+		#[test]
+		fn test_cluster_queries_charged_per_worker() {
+			new_test_ext().execute_with(|| {
+				setup_workers(2);
+				let cluster = setup_cluster(&[1, 2]);
+				assert_ok!(PhalaFat::cluster_deposit(
+					Origin::signed(account(2)),
+					cluster,
+					10 * DOLLARS
+				));
+				System::set_block_number(100);
+				let _ = take_events();
+				// Paid to the reporting worker alone
+				assert_ok!(report_full_usage(cluster, 1, 100, vec![], 10, 0));
+				assert_eq!(
+					take_events(),
+					vec![TestEvent::PhalaFat(Event::ClusterCharged {
+						cluster,
+						amount: 10 * QueryFee::get(),
+					})]
+				);
+				assert_eq!(
+					WorkerPendingPayouts::<Test>::get(worker_pubkey(1)),
+					10 * QueryFee::get()
+				);
+				assert_eq!(WorkerPendingPayouts::<Test>::get(worker_pubkey(2)), 0);
+				// Charged for each worker, up to the quota
+				assert_ok!(report_full_usage(cluster, 2, 100, vec![], 1000, 0));
+				assert_eq!(
+					WorkerPendingPayouts::<Test>::get(worker_pubkey(2)),
+					QueryQuota::get() as Balance * QueryFee::get()
+				);
+				// But only once for each period
+				assert_ok!(report_full_usage(cluster, 1, 100, vec![], 10, 0));
+				assert_eq!(
+					WorkerPendingPayouts::<Test>::get(worker_pubkey(1)),
+					10 * QueryFee::get()
+				);
+				assert_eq!(
+					ClusterDeposits::<Test>::get(cluster),
+					10 * DOLLARS - (10 + QueryQuota::get() as Balance) * QueryFee::get()
+				);
+			});
+		}
+
+		#[test]
+		fn test_cluster_commands_charged_to_sender() {
+			new_test_ext().execute_with(|| {
+				setup_workers(1);
+				let cluster = setup_cluster(&[1]);
+				assert_ok!(PhalaFat::cluster_deposit(
+					Origin::signed(account(2)),
+					cluster,
+					10 * DOLLARS
+				));
+				grant(cluster, ClusterRole::SponsoredSender, 4);
+				System::set_block_number(100);
+				let _ = take_events();
+				assert_ok!(report_full_usage(
+					cluster,
+					1,
+					100,
+					vec![(None, 3), (sender(1), 5), (sender(3), 10), (sender(4), 2)],
+					0,
+					0
+				));
+				// The owner, the sponsored senders and the commands not sent by an account are
+				// paid by the cluster, the others by their senders
+				assert_eq!(
+					take_events(),
+					vec![
+						TestEvent::PhalaFat(Event::AccountCharged {
+							cluster,
+							account: account(3),
+							amount: 10 * CENTS,
+						}),
+						TestEvent::PhalaFat(Event::ClusterCharged {
+							cluster,
+							amount: 10 * CENTS,
+						}),
+					]
+				);
+				assert_eq!(
+					Balances::free_balance(account(3)),
+					1000 * DOLLARS - 10 * CENTS
+				);
+				assert_eq!(
+					ClusterDeposits::<Test>::get(cluster),
+					10 * DOLLARS - 10 * CENTS
+				);
+				assert_eq!(
+					WorkerPendingPayouts::<Test>::get(worker_pubkey(1)),
+					20 * CENTS
+				);
+			});
+		}
+
+		#[test]
+		fn test_cluster_storage_charged_over_time() {
+			new_test_ext().execute_with(|| {
+				setup_workers(2);
+				// Created at block 1
+				let cluster = setup_cluster(&[1, 2]);
+				assert_ok!(PhalaFat::cluster_deposit(
+					Origin::signed(account(2)),
+					cluster,
+					10 * DOLLARS
+				));
+				System::set_block_number(101);
+				assert_ok!(report_full_usage(cluster, 1, 101, vec![], 0, 1000));
+				let fee = 1000 * 100 * StorageByteFee::get();
+				assert_eq!(ClusterDeposits::<Test>::get(cluster), 10 * DOLLARS - fee);
+				// Kept by all the workers, so charged once
+				assert_ok!(report_full_usage(cluster, 2, 101, vec![], 0, 1000));
+				assert_eq!(ClusterDeposits::<Test>::get(cluster), 10 * DOLLARS - fee);
+				System::set_block_number(151);
+				assert_ok!(report_full_usage(cluster, 2, 151, vec![], 0, 2000));
+				let fee = fee + 2000 * 50 * StorageByteFee::get();
+				assert_eq!(ClusterDeposits::<Test>::get(cluster), 10 * DOLLARS - fee);
+				assert_eq!(
+					WorkerPendingPayouts::<Test>::get(worker_pubkey(1))
+						+ WorkerPendingPayouts::<Test>::get(worker_pubkey(2)),
+					fee
+				);
+			});
+		}
+
+		#[test]
+		fn test_cluster_deposit_exhausted() {
+			new_test_ext().execute_with(|| {
+				setup_workers(1);
+				let cluster = setup_cluster(&[1]);
+				assert_ok!(PhalaFat::cluster_deposit(
+					Origin::signed(account(2)),
+					cluster,
+					5 * CENTS
+				));
+				System::set_block_number(100);
+				let _ = take_events();
+				assert_ok!(report_usage(cluster, 1, 100, 10));
+				// Charges whatever is left
+				assert_eq!(
+					take_events(),
+					vec![
+						TestEvent::PhalaFat(Event::ClusterDepositExhausted { cluster }),
+						TestEvent::PhalaFat(Event::ClusterCharged {
+							cluster,
+							amount: 5 * CENTS,
+						}),
+					]
+				);
+				assert_eq!(ClusterDeposits::<Test>::get(cluster), 0);
+				assert_eq!(
+					WorkerPendingPayouts::<Test>::get(worker_pubkey(1)),
+					5 * CENTS
+				);
+			});
+		}
+
+		#[test]
+		fn test_cluster_upload_resource_charged() {
+			new_test_ext().execute_with(|| {
+				setup_workers(1);
+				let cluster = setup_cluster(&[1]);
+				let code = vec![0u8; 1000];
+				let fee = 1000 * CodeByteFee::get();
+				assert_noop!(
+					PhalaFat::cluster_upload_resource(
+						Origin::signed(account(1)),
+						cluster,
+						ResourceType::InkCode,
+						code.clone()
+					),
+					Error::<Test>::InsufficientClusterDeposit
+				);
+				assert_ok!(PhalaFat::cluster_deposit(
+					Origin::signed(account(2)),
+					cluster,
+					fee + 1 * DOLLARS
+				));
+				assert_ok!(PhalaFat::cluster_upload_resource(
+					Origin::signed(account(1)),
+					cluster,
+					ResourceType::InkCode,
+					code
+				));
+				assert_eq!(ClusterDeposits::<Test>::get(cluster), 1 * DOLLARS);
+				assert_eq!(WorkerPendingPayouts::<Test>::get(worker_pubkey(1)), fee);
+			});
+		}
+
+		#[test]
+		fn test_claim_worker_payout() {
+			new_test_ext().execute_with(|| {
+				setup_workers(1);
+				let cluster = setup_cluster(&[1]);
+				assert_ok!(PhalaFat::cluster_deposit(
+					Origin::signed(account(2)),
+					cluster,
+					10 * DOLLARS
+				));
+				assert_noop!(
+					PhalaFat::claim_worker_payout(Origin::signed(account(1)), worker_pubkey(1)),
+					Error::<Test>::NoPayout
+				);
+				System::set_block_number(100);
+				assert_ok!(report_usage(cluster, 1, 100, 100));
+				assert_noop!(
+					PhalaFat::claim_worker_payout(Origin::signed(account(1)), worker_pubkey(9)),
+					Error::<Test>::WorkerNotFound
+				);
+				// Only the operator of the worker can claim
+				assert_noop!(
+					PhalaFat::claim_worker_payout(Origin::signed(account(2)), worker_pubkey(1)),
+					Error::<Test>::NotWorkerOperator
+				);
+				assert_ok!(PhalaFat::claim_worker_payout(
+					Origin::signed(account(1)),
+					worker_pubkey(1)
+				));
+				assert_eq!(Balances::free_balance(account(1)), 1001 * DOLLARS);
+				assert_eq!(Balances::free_balance(PhalaFat::account_id()), 9 * DOLLARS);
+				assert_eq!(WorkerPendingPayouts::<Test>::get(worker_pubkey(1)), 0);
+				assert_noop!(
+					PhalaFat::claim_worker_payout(Origin::signed(account(1)), worker_pubkey(1)),
+					Error::<Test>::NoPayout
+				);
+			});
+		}
+
+		/// Reports `commands` not sent by an account, without any query or storage.
+		fn report_usage(
+			cluster: ContractClusterId,
+			worker: u8,
+			block_number: u32,
+			commands: u32,
+		) -> DispatchResult {
+			report_full_usage(cluster, worker, block_number, vec![(None, commands)], 0, 0)
+		}
+
+		fn report_full_usage(
+			cluster: ContractClusterId,
+			worker: u8,
+			block_number: u32,
+			commands: Vec<(Option<H256>, u32)>,
+			queries: u32,
+			storage_bytes: u64,
+		) -> DispatchResult {
+			PhalaFat::on_worker_contract_message_received(DecodedMessage {
+				sender: MessageOrigin::Worker(worker_pubkey(worker)),
+				destination: Topic::new(*b"phala/contract/worker/report"),
+				payload: WorkerContractReport::ClusterUsage {
+					cluster_id: cluster,
+					block_number,
+					commands,
+					queries,
+					storage_bytes,
+				},
+			})
+		}
+
+		fn sender(who: u8) -> Option<H256> {
+			Some(H256(account(who).into()))
+		}
+
+		fn grant(cluster: ContractClusterId, role: ClusterRole, who: u8) {
+			assert_ok!(PhalaFat::cluster_grant_role(
+				Origin::signed(account(1)),
+				cluster,
+				role,
+				account(who)
+			));
+		}
+
+		/// Creates a cluster owned by account 1 and deployed to the given workers.
+		fn setup_cluster(workers: &[u8]) -> ContractClusterId {
+			let cluster = ContractClusterId::from_low_u64_be(ClusterCounter::<Test>::get());
+			assert_ok!(PhalaFat::add_cluster(
+				Origin::signed(account(1)),
+				ClusterPermission::Public,
+				workers.iter().map(|i| worker_pubkey(*i)).collect()
+			));
+			assert_ok!(PhalaFat::on_cluster_message_received(DecodedMessage {
+				sender: MessageOrigin::Gatekeeper,
+				destination: Topic::new(*b"^phala/registry/cluster"),
+				payload: ClusterRegistryEvent::PubkeyAvailable {
+					cluster,
+					pubkey: ClusterPublicKey::from_raw([1u8; 32]),
+				},
+			}));
+			for worker in workers {
+				report_deployed(cluster, *worker);
+			}
+			cluster
+		}
+
+		fn report_deployed(cluster: ContractClusterId, worker: u8) {
+			assert_ok!(PhalaFat::on_worker_cluster_message_received(
+				DecodedMessage {
+					sender: MessageOrigin::Worker(worker_pubkey(worker)),
+					destination: Topic::new(*b"phala/cluster/worker/report"),
+					payload: WorkerClusterReport::ClusterDeployed {
+						id: cluster,
+						pubkey: ClusterPublicKey::from_raw([1u8; 32]),
+					},
+				}
+			));
+		}
+	}
 }
+
+pub mod weights;
+pub use weights::WeightInfo;
+
+#[cfg(feature = "runtime-benchmarks")]
+mod benchmarking;
+
+#[cfg(test)]
+mod mock;
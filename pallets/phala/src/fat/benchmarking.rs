@@ -0,0 +1,247 @@
+//! Benchmarks of the fat contract pallet
+
+use super::*;
+
+use crate::registry::benchmarking::{register_worker, worker_pubkey};
+use frame_benchmarking::{benchmarks, whitelisted_caller};
+use frame_support::traits::{Currency, Get};
+use frame_system::RawOrigin;
+use phala_types::{
+	contract::{
+		messaging::ResourceType, ClusterInfo, ClusterPermission, ClusterRole, CodeIndex,
+		ContractClusterId, ContractId, ContractInfo,
+	},
+	ClusterPublicKey,
+};
+use sp_runtime::{
+	traits::{Saturating, Zero},
+	AccountId32,
+};
+use sp_std::{vec, vec::Vec};
+
+/// The upper bound of the workers to deploy a cluster in the benchmarks.
+const MAX_CLUSTER_WORKERS: u32 = 100;
+
+/// The upper bound of the instantiation data in the benchmarks.
+const MAX_INSTANTIATE_DATA_LEN: u32 = 64 * 1024;
+
+/// Returns an account derived from `index`.
+fn account(index: u32) -> AccountId32 {
+	let mut raw = [0u8; 32];
+	raw[..4].copy_from_slice(&index.to_be_bytes());
+	AccountId32::new(raw)
+}
+
+/// Fills the allowlist of `role` with `n` accounts.
+fn setup_role_allowlist<T: Config>(cluster: ContractClusterId, role: ClusterRole, n: u32)
+where
+	T: frame_system::Config<AccountId = AccountId32>,
+{
+	ClusterRoleAllowList::<T>::insert((cluster, role), (0..n).map(account).collect::<Vec<_>>());
+}
+
+/// Returns `n` times of the existential deposit.
+fn units<T: Config>(n: u32) -> BalanceOf<T> {
+	T::Currency::minimum_balance().saturating_mul(n.into())
+}
+
+/// Funds the cluster with a deposit held by the pallet account.
+fn setup_deposit<T: Config>(cluster: ContractClusterId, amount: BalanceOf<T>) {
+	let balance = amount.saturating_add(units::<T>(1));
+	T::Currency::make_free_balance_be(&Pallet::<T>::account_id(), balance);
+	ClusterDeposits::<T>::insert(cluster, amount);
+}
+
+/// Creates a public cluster owned by `owner`, deployed to a single worker.
+fn setup_cluster<T: Config>(owner: T::AccountId) -> ContractClusterId {
+	let cluster_id = ClusterCounter::<T>::mutate(|counter| {
+		let cluster_id = *counter;
+		*counter += 1;
+		cluster_id
+	});
+	let cluster = ContractClusterId::from_low_u64_be(cluster_id);
+	Clusters::<T>::insert(
+		&cluster,
+		ClusterInfo {
+			owner,
+			permission: ClusterPermission::Public,
+			workers: vec![worker_pubkey(0)],
+		},
+	);
+	cluster
+}
+
+benchmarks! {
+	where_clause { where
+		T: crate::mq::Config + crate::registry::Config,
+		T: frame_system::Config<AccountId = AccountId32>,
+	}
+
+	add_cluster {
+		let w in 1 .. MAX_CLUSTER_WORKERS;
+		let caller: T::AccountId = whitelisted_caller();
+		let workers: Vec<_> = (0..w).map(worker_pubkey).collect();
+		for worker in workers.iter() {
+			register_worker::<T>(*worker, None);
+		}
+		let cluster_id = ClusterCounter::<T>::get();
+	}: _(RawOrigin::Signed(caller), ClusterPermission::Public, workers)
+	verify {
+		assert!(Clusters::<T>::contains_key(ContractClusterId::from_low_u64_be(cluster_id)));
+	}
+
+	cluster_upload_resource {
+		let n in 0 .. T::SidevmCodeSizeLimit::get();
+		let w in 1 .. CLUSTER_WORKERS_WEIGHT_LEN;
+		let caller: T::AccountId = whitelisted_caller();
+		let cluster = setup_cluster::<T>(caller.clone());
+		ClusterWorkers::<T>::insert(&cluster, (0..w).map(worker_pubkey).collect::<Vec<_>>());
+		let fee = T::CodeByteFee::get().saturating_mul(n.into());
+		setup_deposit::<T>(cluster, fee);
+	}: _(RawOrigin::Signed(caller), cluster, ResourceType::SidevmCode, vec![0u8; n as usize])
+
+	instantiate_contract {
+		let n in 0 .. MAX_INSTANTIATE_DATA_LEN;
+		let caller: T::AccountId = whitelisted_caller();
+		let cluster = setup_cluster::<T>(caller.clone());
+		let code_index = CodeIndex::WasmCode(T::Hash::default());
+	}: _(RawOrigin::Signed(caller), code_index, vec![0u8; n as usize], vec![0u8; 32], cluster)
+	verify {
+		assert!(Contracts::<T>::iter().next().is_some());
+	}
+
+	cluster_set_log_handler {
+		let caller: T::AccountId = whitelisted_caller();
+		let cluster = setup_cluster::<T>(caller.clone());
+	}: _(RawOrigin::Signed(caller), cluster, ContractId::repeat_byte(1))
+
+	cluster_destroy {
+		let caller: T::AccountId = whitelisted_caller();
+		let cluster = setup_cluster::<T>(caller);
+		setup_deposit::<T>(cluster, units::<T>(100));
+	}: _(RawOrigin::Root, cluster)
+	verify {
+		assert!(!Clusters::<T>::contains_key(cluster));
+	}
+
+	cluster_set_gas_schedule {
+		let caller: T::AccountId = whitelisted_caller();
+		let cluster = setup_cluster::<T>(caller.clone());
+	}: _(RawOrigin::Signed(caller), cluster, Some(1))
+
+	cluster_add_workers {
+		let w in 1 .. MAX_CLUSTER_WORKERS;
+		let caller: T::AccountId = whitelisted_caller();
+		let cluster = setup_cluster::<T>(caller.clone());
+		crate::registry::ClusterKeys::<T>::insert(&cluster, ClusterPublicKey::from_raw([1u8; 32]));
+		let workers: Vec<_> = (1..=w).map(worker_pubkey).collect();
+		for worker in workers.iter() {
+			register_worker::<T>(*worker, None);
+		}
+	}: _(RawOrigin::Signed(caller), cluster, workers)
+	verify {
+		assert_eq!(Clusters::<T>::get(cluster).unwrap().workers.len(), w as usize + 1);
+	}
+
+	cluster_remove_worker {
+		let caller: T::AccountId = whitelisted_caller();
+		let cluster = setup_cluster::<T>(caller.clone());
+		Clusters::<T>::mutate(&cluster, |info| {
+			info.as_mut().unwrap().workers.push(worker_pubkey(1));
+		});
+		ClusterWorkers::<T>::insert(&cluster, vec![worker_pubkey(0), worker_pubkey(1)]);
+	}: _(RawOrigin::Signed(caller), cluster, worker_pubkey(1))
+	verify {
+		assert_eq!(ClusterWorkers::<T>::get(cluster), vec![worker_pubkey(0)]);
+	}
+
+	cluster_transfer_ownership {
+		let caller: T::AccountId = whitelisted_caller();
+		let cluster = setup_cluster::<T>(caller.clone());
+		let new_owner: T::AccountId = AccountId32::new([1u8; 32]);
+	}: _(RawOrigin::Signed(caller), cluster, new_owner.clone())
+	verify {
+		assert_eq!(Clusters::<T>::get(cluster).unwrap().owner, new_owner);
+	}
+
+	cluster_set_permission {
+		let caller: T::AccountId = whitelisted_caller();
+		let cluster = setup_cluster::<T>(caller.clone());
+		let permission = ClusterPermission::OnlyOwner(caller.clone());
+	}: _(RawOrigin::Signed(caller), cluster, permission.clone())
+	verify {
+		assert_eq!(Clusters::<T>::get(cluster).unwrap().permission, permission);
+	}
+
+	contract_destroy {
+		let caller: T::AccountId = whitelisted_caller();
+		let cluster = setup_cluster::<T>(caller.clone());
+		let contract_info = ContractInfo {
+			deployer: caller.clone(),
+			code_index: CodeIndex::WasmCode(T::Hash::default()),
+			salt: vec![],
+			cluster_id: cluster,
+			instantiate_data: vec![],
+		};
+		let contract = contract_info.contract_id(crate::hashing::blake2_256);
+		Contracts::<T>::insert(&contract, &contract_info);
+		ClusterContracts::<T>::insert(&cluster, vec![contract]);
+	}: _(RawOrigin::Signed(caller), contract)
+	verify {
+		assert!(!Contracts::<T>::contains_key(contract));
+	}
+
+	cluster_grant_role {
+		let caller: T::AccountId = whitelisted_caller();
+		let cluster = setup_cluster::<T>(caller.clone());
+		setup_role_allowlist::<T>(cluster, ClusterRole::Deployer, MAX_ROLE_ALLOWLIST_LEN - 1);
+		let grantee = account(u32::MAX);
+	}: _(RawOrigin::Signed(caller), cluster, ClusterRole::Deployer, grantee.clone())
+	verify {
+		let allowlist = ClusterRoleAllowList::<T>::get((cluster, ClusterRole::Deployer));
+		assert!(allowlist.contains(&grantee));
+	}
+
+	cluster_revoke_role {
+		let caller: T::AccountId = whitelisted_caller();
+		let cluster = setup_cluster::<T>(caller.clone());
+		setup_role_allowlist::<T>(cluster, ClusterRole::Deployer, MAX_ROLE_ALLOWLIST_LEN);
+		let grantee = account(MAX_ROLE_ALLOWLIST_LEN - 1);
+	}: _(RawOrigin::Signed(caller), cluster, ClusterRole::Deployer, grantee.clone())
+	verify {
+		let allowlist = ClusterRoleAllowList::<T>::get((cluster, ClusterRole::Deployer));
+		assert!(!allowlist.contains(&grantee));
+	}
+
+	cluster_deposit {
+		let caller: T::AccountId = whitelisted_caller();
+		let cluster = setup_cluster::<T>(caller.clone());
+		T::Currency::make_free_balance_be(&caller, units::<T>(1000));
+	}: _(RawOrigin::Signed(caller), cluster, units::<T>(100))
+	verify {
+		assert_eq!(ClusterDeposits::<T>::get(cluster), units::<T>(100));
+	}
+
+	cluster_withdraw_deposit {
+		let caller: T::AccountId = whitelisted_caller();
+		let cluster = setup_cluster::<T>(caller.clone());
+		setup_deposit::<T>(cluster, units::<T>(100));
+	}: _(RawOrigin::Signed(caller), cluster, units::<T>(100))
+	verify {
+		assert!(ClusterDeposits::<T>::get(cluster).is_zero());
+	}
+
+	claim_worker_payout {
+		let caller: T::AccountId = whitelisted_caller();
+		let worker = worker_pubkey(0);
+		register_worker::<T>(worker, Some(caller.clone()));
+		let cluster = setup_cluster::<T>(caller.clone());
+		setup_deposit::<T>(cluster, units::<T>(100));
+		WorkerPendingPayouts::<T>::insert(worker, units::<T>(100));
+	}: _(RawOrigin::Signed(caller), worker)
+	verify {
+		assert!(WorkerPendingPayouts::<T>::get(worker).is_zero());
+	}
+
+	impl_benchmark_test_suite!(Pallet, crate::fat::mock::new_bench_ext(), crate::fat::mock::Test);
+}
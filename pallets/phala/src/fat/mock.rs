@@ -0,0 +1,230 @@
+//! The mock runtime of the fat contract pallet.
+//!
+//! The fat contract pallet requires `AccountId32` accounts, while the shared mock runtime in
+//! `crate::mock` uses `u64` accounts, so it gets a runtime of its own.
+
+use crate::{fat, mock::MockValidator, mq, registry};
+
+use frame_support::{pallet_prelude::ConstU32, parameter_types, traits::GenesisBuild};
+use frame_system as system;
+use phala_types::{messaging::Message, EcdhPublicKey, WorkerPublicKey};
+use sp_core::H256;
+use sp_runtime::{
+	testing::Header,
+	traits::{BlakeTwo256, IdentityLookup},
+	AccountId32,
+};
+
+pub(crate) type Balance = u128;
+
+type UncheckedExtrinsic = frame_system::mocking::MockUncheckedExtrinsic<Test>;
+type Block = frame_system::mocking::MockBlock<Test>;
+
+frame_support::construct_runtime!(
+	pub enum Test where
+		Block = Block,
+		NodeBlock = Block,
+		UncheckedExtrinsic = UncheckedExtrinsic,
+	{
+		System: frame_system::{Pallet, Call, Config, Storage, Event<T>},
+		Timestamp: pallet_timestamp::{Pallet, Call, Storage, Inherent},
+		Balances: pallet_balances::{Pallet, Call, Storage, Config<T>, Event<T>},
+		PhalaMq: mq::{Pallet, Call, Event<T>},
+		PhalaRegistry: registry::{Pallet, Event<T>, Storage, Config<T>},
+		// The pallet to test
+		PhalaFat: fat::{Pallet, Call, Event<T>, Storage},
+	}
+);
+
+pub const DOLLARS: Balance = 1_000_000_000_000;
+pub const CENTS: Balance = DOLLARS / 100;
+
+parameter_types! {
+	pub const ExistentialDeposit: Balance = 1 * CENTS;
+	pub const BlockHashCount: u64 = 250;
+	pub const SS58Prefix: u8 = 20;
+	pub const MinimumPeriod: u64 = 1;
+	pub const VerifyPRuntime: bool = false;
+	pub const VerifyRelaychainGenesisBlockHash: bool = true;
+	pub const MasterKeyRotationRetryInterval: u64 = 10;
+	pub const InkCodeSizeLimit: u32 = 1024 * 1024;
+	pub const SidevmCodeSizeLimit: u32 = 1024 * 1024;
+	pub const CommandFee: Balance = 1 * CENTS;
+	pub const QueryFee: Balance = 1 * CENTS / 10;
+	pub const QueryQuota: u32 = 100;
+	pub const StorageByteFee: Balance = 1 * CENTS / 1000;
+	pub const CodeByteFee: Balance = 1 * CENTS / 1000;
+}
+
+impl system::Config for Test {
+	type BaseCallFilter = frame_support::traits::Everything;
+	type BlockWeights = ();
+	type BlockLength = ();
+	type Origin = Origin;
+	type Call = Call;
+	type Index = u64;
+	type BlockNumber = u64;
+	type Hash = H256;
+	type Hashing = BlakeTwo256;
+	type AccountId = AccountId32;
+	type Lookup = IdentityLookup<Self::AccountId>;
+	type Header = Header;
+	type Event = Event;
+	type BlockHashCount = BlockHashCount;
+	type DbWeight = ();
+	type Version = ();
+	type PalletInfo = PalletInfo;
+	type AccountData = pallet_balances::AccountData<Balance>;
+	type OnNewAccount = ();
+	type OnKilledAccount = ();
+	type SystemWeightInfo = ();
+	type SS58Prefix = SS58Prefix;
+	type OnSetCode = ();
+	type MaxConsumers = ConstU32<2>;
+}
+
+impl pallet_balances::Config for Test {
+	type Balance = Balance;
+	type DustRemoval = ();
+	type Event = Event;
+	type ExistentialDeposit = ExistentialDeposit;
+	type AccountStore = System;
+	type WeightInfo = ();
+	type MaxLocks = ();
+	type MaxReserves = ();
+	type ReserveIdentifier = [u8; 8];
+}
+
+impl pallet_timestamp::Config for Test {
+	type Moment = u64;
+	type OnTimestampSet = ();
+	type MinimumPeriod = MinimumPeriod;
+	type WeightInfo = ();
+}
+
+impl mq::Config for Test {
+	type Event = Event;
+	type QueueNotifyConfig = ();
+	type CallMatcher = MqCallMatcher;
+	type WeightInfo = ();
+}
+
+pub struct MqCallMatcher;
+impl mq::CallMatcher<Test> for MqCallMatcher {
+	fn match_call(call: &Call) -> Option<&mq::Call<Test>> {
+		match call {
+			Call::PhalaMq(mq_call) => Some(mq_call),
+			_ => None,
+		}
+	}
+}
+
+impl registry::Config for Test {
+	type Event = Event;
+	type Currency = Balances;
+	type AttestationValidator = MockValidator;
+	type UnixTime = Timestamp;
+	type VerifyPRuntime = VerifyPRuntime;
+	type VerifyRelaychainGenesisBlockHash = VerifyRelaychainGenesisBlockHash;
+	type MasterKeyRotationRetryInterval = MasterKeyRotationRetryInterval;
+	type GovernanceOrigin = frame_system::EnsureRoot<Self::AccountId>;
+	type WeightInfo = ();
+}
+
+impl fat::Config for Test {
+	type Event = Event;
+	type InkCodeSizeLimit = InkCodeSizeLimit;
+	type SidevmCodeSizeLimit = SidevmCodeSizeLimit;
+	type Currency = Balances;
+	type CommandFee = CommandFee;
+	type QueryFee = QueryFee;
+	type QueryQuota = QueryQuota;
+	type StorageByteFee = StorageByteFee;
+	type CodeByteFee = CodeByteFee;
+	type WeightInfo = ();
+}
+
+/// Returns the account derived from `i`.
+pub fn account(i: u8) -> AccountId32 {
+	AccountId32::new([i; 32])
+}
+
+pub fn new_test_ext() -> sp_io::TestExternalities {
+	let mut t = system::GenesisConfig::default()
+		.build_storage::<Test>()
+		.unwrap();
+	let zero_pubkey = sp_core::sr25519::Public::from_raw([0u8; 32]);
+	let zero_ecdh_pubkey = Vec::from(&[0u8; 32][..]);
+	pallet_balances::GenesisConfig::<Test> {
+		balances: vec![
+			(account(1), 1000 * DOLLARS),
+			(account(2), 2000 * DOLLARS),
+			(account(3), 1000 * DOLLARS),
+		],
+	}
+	.assimilate_storage(&mut t)
+	.unwrap();
+	registry::GenesisConfig::<Test> {
+		workers: vec![(zero_pubkey.clone(), zero_ecdh_pubkey, None)],
+		gatekeepers: vec![(zero_pubkey.clone())],
+		benchmark_duration: 0u32,
+	}
+	.assimilate_storage(&mut t)
+	.unwrap();
+	let mut ext = sp_io::TestExternalities::new(t);
+	ext.execute_with(|| System::set_block_number(1));
+	ext
+}
+
+/// Builds the test externalities with a keystore, required by the benchmarks signing messages.
+#[cfg(feature = "runtime-benchmarks")]
+pub fn new_bench_ext() -> sp_io::TestExternalities {
+	use sp_keystore::{testing::KeyStore, KeystoreExt};
+	let mut ext = new_test_ext();
+	ext.register_extension(KeystoreExt(std::sync::Arc::new(KeyStore::new())));
+	ext
+}
+
+pub fn take_events() -> Vec<Event> {
+	let evt = System::events()
+		.into_iter()
+		.map(|evt| evt.event)
+		.collect::<Vec<_>>();
+	println!("event(): {:?}", evt);
+	System::reset_events();
+	evt
+}
+
+pub fn take_messages() -> Vec<Message> {
+	let messages = PhalaMq::messages();
+	println!("messages(): {:?}", messages);
+	mq::OutboundMessages::<Test>::kill();
+	messages
+}
+
+pub fn worker_pubkey(i: u8) -> WorkerPublicKey {
+	let mut raw = [0u8; 32];
+	raw[31] = i;
+	raw[30] = 1; // distinguish with the genesis config
+	WorkerPublicKey::from_raw(raw)
+}
+
+pub fn ecdh_pubkey(i: u8) -> EcdhPublicKey {
+	let mut raw = [0u8; 32];
+	raw[31] = i;
+	raw[30] = 1; // distinguish with the genesis config
+	EcdhPublicKey(raw)
+}
+
+/// Sets up `n` workers starting from 1, registered and owned by account 1.
+pub fn setup_workers(n: u8) {
+	use frame_support::assert_ok;
+	for i in 1..=n {
+		assert_ok!(PhalaRegistry::force_register_worker(
+			Origin::root(),
+			worker_pubkey(i),
+			ecdh_pubkey(i),
+			Some(account(1))
+		));
+	}
+}
@@ -0,0 +1,273 @@
+//! Weights for pallet_fat
+//!
+//! PLACEHOLDER WEIGHTS, NOT GENERATED BY THE BENCHMARK CLI.
+//!
+//! The values are hand-estimated from the storage accesses of each call and have never been
+//! measured. They must be regenerated on the reference machine with
+//! `scripts/benchmark-pallets.sh fat` before being relied on.
+
+#![cfg_attr(rustfmt, rustfmt_skip)]
+#![allow(unused_parens)]
+#![allow(unused_imports)]
+
+use frame_support::{traits::Get, weights::{Weight, constants::RocksDbWeight}};
+use sp_std::marker::PhantomData;
+
+/// Weight functions needed for pallet_fat.
+pub trait WeightInfo {
+	fn add_cluster(w: u32, ) -> Weight;
+	fn cluster_upload_resource(n: u32, w: u32, ) -> Weight;
+	fn instantiate_contract(n: u32, ) -> Weight;
+	fn cluster_set_log_handler() -> Weight;
+	fn cluster_destroy() -> Weight;
+	fn cluster_set_gas_schedule() -> Weight;
+	fn cluster_add_workers(w: u32, ) -> Weight;
+	fn cluster_remove_worker() -> Weight;
+	fn cluster_transfer_ownership() -> Weight;
+	fn cluster_set_permission() -> Weight;
+	fn contract_destroy() -> Weight;
+	fn cluster_grant_role() -> Weight;
+	fn cluster_revoke_role() -> Weight;
+	fn cluster_deposit() -> Weight;
+	fn cluster_withdraw_deposit() -> Weight;
+	fn claim_worker_payout() -> Weight;
+}
+
+/// Weights for pallet_fat using the Substrate node and recommended hardware.
+pub struct SubstrateWeight<T>(PhantomData<T>);
+impl<T: frame_system::Config> WeightInfo for SubstrateWeight<T> {
+	// Storage: PhalaRegistry Workers (r:1 w:0)
+	// Storage: PhalaFatContracts ClusterCounter (r:1 w:1)
+	// Storage: PhalaFatContracts Clusters (r:0 w:1)
+	// Storage: PhalaMq OutboundMessages (r:0 w:1)
+	fn add_cluster(w: u32, ) -> Weight {
+		(32_000_000 as Weight)
+			.saturating_add((6_000_000 as Weight).saturating_mul(w as Weight))
+			.saturating_add(T::DbWeight::get().reads(1 as Weight))
+			.saturating_add(T::DbWeight::get().reads((1 as Weight).saturating_mul(w as Weight)))
+			.saturating_add(T::DbWeight::get().writes(3 as Weight))
+	}
+	// Storage: PhalaFatContracts Clusters (r:1 w:0)
+	// Storage: PhalaFatContracts ClusterRoleAllowList (r:1 w:0)
+	// Storage: PhalaFatContracts ClusterWorkers (r:1 w:0)
+	// Storage: PhalaFatContracts ClusterDeposits (r:1 w:1)
+	// Storage: PhalaFatContracts WorkerPendingPayouts (r:1 w:1)
+	// Storage: PhalaMq OutboundMessages (r:0 w:1)
+	fn cluster_upload_resource(n: u32, w: u32, ) -> Weight {
+		(38_000_000 as Weight)
+			.saturating_add((2_000 as Weight).saturating_mul(n as Weight))
+			.saturating_add((3_000_000 as Weight).saturating_mul(w as Weight))
+			.saturating_add(T::DbWeight::get().reads(4 as Weight))
+			.saturating_add(T::DbWeight::get().reads((1 as Weight).saturating_mul(w as Weight)))
+			.saturating_add(T::DbWeight::get().writes(2 as Weight))
+			.saturating_add(T::DbWeight::get().writes((1 as Weight).saturating_mul(w as Weight)))
+	}
+	// Storage: PhalaFatContracts Clusters (r:1 w:0)
+	// Storage: PhalaFatContracts Contracts (r:1 w:1)
+	// Storage: PhalaFatContracts ClusterRoleAllowList (r:1 w:0)
+	// Storage: PhalaMq OutboundMessages (r:0 w:1)
+	fn instantiate_contract(n: u32, ) -> Weight {
+		(46_000_000 as Weight)
+			.saturating_add((3_000 as Weight).saturating_mul(n as Weight))
+			.saturating_add(T::DbWeight::get().reads(3 as Weight))
+			.saturating_add(T::DbWeight::get().writes(2 as Weight))
+	}
+	// Storage: PhalaFatContracts Clusters (r:1 w:0)
+	// Storage: PhalaFatContracts ClusterRoleAllowList (r:1 w:0)
+	// Storage: PhalaMq OutboundMessages (r:0 w:1)
+	fn cluster_set_log_handler() -> Weight {
+		(30_000_000 as Weight)
+			.saturating_add(T::DbWeight::get().reads(2 as Weight))
+			.saturating_add(T::DbWeight::get().writes(1 as Weight))
+	}
+	// Storage: PhalaFatContracts Clusters (r:1 w:1)
+	// Storage: PhalaFatContracts ClusterDeposits (r:1 w:1)
+	// Storage: System Account (r:1 w:1)
+	// Storage: PhalaFatContracts ClusterRoleAllowList (r:0 w:4)
+	// Storage: PhalaMq OutboundMessages (r:0 w:1)
+	fn cluster_destroy() -> Weight {
+		(62_000_000 as Weight)
+			.saturating_add(T::DbWeight::get().reads(3 as Weight))
+			.saturating_add(T::DbWeight::get().writes(9 as Weight))
+	}
+	// Storage: PhalaFatContracts Clusters (r:1 w:0)
+	// Storage: PhalaMq OutboundMessages (r:0 w:1)
+	fn cluster_set_gas_schedule() -> Weight {
+		(26_000_000 as Weight)
+			.saturating_add(T::DbWeight::get().reads(1 as Weight))
+			.saturating_add(T::DbWeight::get().writes(1 as Weight))
+	}
+	// Storage: PhalaFatContracts Clusters (r:1 w:1)
+	// Storage: PhalaRegistry ClusterKeys (r:1 w:0)
+	// Storage: PhalaRegistry Workers (r:1 w:0)
+	// Storage: PhalaMq OutboundMessages (r:0 w:1)
+	fn cluster_add_workers(w: u32, ) -> Weight {
+		(34_000_000 as Weight)
+			.saturating_add((6_000_000 as Weight).saturating_mul(w as Weight))
+			.saturating_add(T::DbWeight::get().reads(2 as Weight))
+			.saturating_add(T::DbWeight::get().reads((1 as Weight).saturating_mul(w as Weight)))
+			.saturating_add(T::DbWeight::get().writes(2 as Weight))
+	}
+	// Storage: PhalaFatContracts Clusters (r:1 w:1)
+	// Storage: PhalaFatContracts ClusterWorkers (r:1 w:1)
+	// Storage: PhalaMq OutboundMessages (r:0 w:1)
+	fn cluster_remove_worker() -> Weight {
+		(30_000_000 as Weight)
+			.saturating_add(T::DbWeight::get().reads(2 as Weight))
+			.saturating_add(T::DbWeight::get().writes(3 as Weight))
+	}
+	// Storage: PhalaFatContracts Clusters (r:1 w:1)
+	fn cluster_transfer_ownership() -> Weight {
+		(22_000_000 as Weight)
+			.saturating_add(T::DbWeight::get().reads(1 as Weight))
+			.saturating_add(T::DbWeight::get().writes(1 as Weight))
+	}
+	// Storage: PhalaFatContracts Clusters (r:1 w:1)
+	fn cluster_set_permission() -> Weight {
+		(22_000_000 as Weight)
+			.saturating_add(T::DbWeight::get().reads(1 as Weight))
+			.saturating_add(T::DbWeight::get().writes(1 as Weight))
+	}
+	// Storage: PhalaFatContracts Contracts (r:1 w:1)
+	// Storage: PhalaFatContracts ClusterContracts (r:1 w:1)
+	// Storage: PhalaFatContracts Clusters (r:1 w:0)
+	// Storage: PhalaFatContracts ClusterRoleAllowList (r:1 w:0)
+	// Storage: PhalaRegistry ContractKeys (r:0 w:1)
+	// Storage: PhalaMq OutboundMessages (r:0 w:1)
+	fn contract_destroy() -> Weight {
+		(34_000_000 as Weight)
+			.saturating_add(T::DbWeight::get().reads(4 as Weight))
+			.saturating_add(T::DbWeight::get().writes(4 as Weight))
+	}
+	// Storage: PhalaFatContracts Clusters (r:1 w:0)
+	// Storage: PhalaFatContracts ClusterRoleAllowList (r:1 w:1)
+	fn cluster_grant_role() -> Weight {
+		(30_000_000 as Weight)
+			.saturating_add(T::DbWeight::get().reads(2 as Weight))
+			.saturating_add(T::DbWeight::get().writes(1 as Weight))
+	}
+	// Storage: PhalaFatContracts Clusters (r:1 w:0)
+	// Storage: PhalaFatContracts ClusterRoleAllowList (r:1 w:1)
+	fn cluster_revoke_role() -> Weight {
+		(30_000_000 as Weight)
+			.saturating_add(T::DbWeight::get().reads(2 as Weight))
+			.saturating_add(T::DbWeight::get().writes(1 as Weight))
+	}
+	// Storage: PhalaFatContracts Clusters (r:1 w:0)
+	// Storage: PhalaFatContracts ClusterDeposits (r:1 w:1)
+	// Storage: System Account (r:1 w:1)
+	fn cluster_deposit() -> Weight {
+		(52_000_000 as Weight)
+			.saturating_add(T::DbWeight::get().reads(2 as Weight))
+			.saturating_add(T::DbWeight::get().writes(2 as Weight))
+	}
+	// Storage: PhalaFatContracts Clusters (r:1 w:0)
+	// Storage: PhalaFatContracts ClusterDeposits (r:1 w:1)
+	// Storage: System Account (r:1 w:1)
+	fn cluster_withdraw_deposit() -> Weight {
+		(54_000_000 as Weight)
+			.saturating_add(T::DbWeight::get().reads(3 as Weight))
+			.saturating_add(T::DbWeight::get().writes(2 as Weight))
+	}
+	// Storage: PhalaRegistry Workers (r:1 w:0)
+	// Storage: PhalaFatContracts WorkerPendingPayouts (r:1 w:1)
+	// Storage: System Account (r:1 w:1)
+	fn claim_worker_payout() -> Weight {
+		(56_000_000 as Weight)
+			.saturating_add(T::DbWeight::get().reads(3 as Weight))
+			.saturating_add(T::DbWeight::get().writes(2 as Weight))
+	}
+}
+
+// For backwards compatibility and tests
+impl WeightInfo for () {
+	fn add_cluster(w: u32, ) -> Weight {
+		(32_000_000 as Weight)
+			.saturating_add((6_000_000 as Weight).saturating_mul(w as Weight))
+			.saturating_add(RocksDbWeight::get().reads(1 as Weight))
+			.saturating_add(RocksDbWeight::get().reads((1 as Weight).saturating_mul(w as Weight)))
+			.saturating_add(RocksDbWeight::get().writes(3 as Weight))
+	}
+	fn cluster_upload_resource(n: u32, w: u32, ) -> Weight {
+		(38_000_000 as Weight)
+			.saturating_add((2_000 as Weight).saturating_mul(n as Weight))
+			.saturating_add((3_000_000 as Weight).saturating_mul(w as Weight))
+			.saturating_add(RocksDbWeight::get().reads(4 as Weight))
+			.saturating_add(RocksDbWeight::get().reads((1 as Weight).saturating_mul(w as Weight)))
+			.saturating_add(RocksDbWeight::get().writes(2 as Weight))
+			.saturating_add(RocksDbWeight::get().writes((1 as Weight).saturating_mul(w as Weight)))
+	}
+	fn instantiate_contract(n: u32, ) -> Weight {
+		(46_000_000 as Weight)
+			.saturating_add((3_000 as Weight).saturating_mul(n as Weight))
+			.saturating_add(RocksDbWeight::get().reads(3 as Weight))
+			.saturating_add(RocksDbWeight::get().writes(2 as Weight))
+	}
+	fn cluster_set_log_handler() -> Weight {
+		(30_000_000 as Weight)
+			.saturating_add(RocksDbWeight::get().reads(2 as Weight))
+			.saturating_add(RocksDbWeight::get().writes(1 as Weight))
+	}
+	fn cluster_destroy() -> Weight {
+		(62_000_000 as Weight)
+			.saturating_add(RocksDbWeight::get().reads(3 as Weight))
+			.saturating_add(RocksDbWeight::get().writes(9 as Weight))
+	}
+	fn cluster_set_gas_schedule() -> Weight {
+		(26_000_000 as Weight)
+			.saturating_add(RocksDbWeight::get().reads(1 as Weight))
+			.saturating_add(RocksDbWeight::get().writes(1 as Weight))
+	}
+	fn cluster_add_workers(w: u32, ) -> Weight {
+		(34_000_000 as Weight)
+			.saturating_add((6_000_000 as Weight).saturating_mul(w as Weight))
+			.saturating_add(RocksDbWeight::get().reads(2 as Weight))
+			.saturating_add(RocksDbWeight::get().reads((1 as Weight).saturating_mul(w as Weight)))
+			.saturating_add(RocksDbWeight::get().writes(2 as Weight))
+	}
+	fn cluster_remove_worker() -> Weight {
+		(30_000_000 as Weight)
+			.saturating_add(RocksDbWeight::get().reads(2 as Weight))
+			.saturating_add(RocksDbWeight::get().writes(3 as Weight))
+	}
+	fn cluster_transfer_ownership() -> Weight {
+		(22_000_000 as Weight)
+			.saturating_add(RocksDbWeight::get().reads(1 as Weight))
+			.saturating_add(RocksDbWeight::get().writes(1 as Weight))
+	}
+	fn cluster_set_permission() -> Weight {
+		(22_000_000 as Weight)
+			.saturating_add(RocksDbWeight::get().reads(1 as Weight))
+			.saturating_add(RocksDbWeight::get().writes(1 as Weight))
+	}
+	fn contract_destroy() -> Weight {
+		(34_000_000 as Weight)
+			.saturating_add(RocksDbWeight::get().reads(4 as Weight))
+			.saturating_add(RocksDbWeight::get().writes(4 as Weight))
+	}
+	fn cluster_grant_role() -> Weight {
+		(30_000_000 as Weight)
+			.saturating_add(RocksDbWeight::get().reads(2 as Weight))
+			.saturating_add(RocksDbWeight::get().writes(1 as Weight))
+	}
+	fn cluster_revoke_role() -> Weight {
+		(30_000_000 as Weight)
+			.saturating_add(RocksDbWeight::get().reads(2 as Weight))
+			.saturating_add(RocksDbWeight::get().writes(1 as Weight))
+	}
+	fn cluster_deposit() -> Weight {
+		(52_000_000 as Weight)
+			.saturating_add(RocksDbWeight::get().reads(2 as Weight))
+			.saturating_add(RocksDbWeight::get().writes(2 as Weight))
+	}
+	fn cluster_withdraw_deposit() -> Weight {
+		(54_000_000 as Weight)
+			.saturating_add(RocksDbWeight::get().reads(3 as Weight))
+			.saturating_add(RocksDbWeight::get().writes(2 as Weight))
+	}
+	fn claim_worker_payout() -> Weight {
+		(56_000_000 as Weight)
+			.saturating_add(RocksDbWeight::get().reads(3 as Weight))
+			.saturating_add(RocksDbWeight::get().writes(2 as Weight))
+	}
+}
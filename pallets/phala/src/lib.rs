@@ -6,6 +6,8 @@
 
 #[cfg(target_arch = "wasm32")]
 extern crate webpki_wasm as webpki;
+#[cfg(target_arch = "wasm32")]
+extern crate ring_wasm as ring;
 
 #[cfg(not(feature = "std"))]
 extern crate alloc;
@@ -96,3 +96,89 @@ pub mod v6 {
 		Ok(())
 	}
 }
+
+pub mod v7 {
+	use super::*;
+
+	#[cfg(feature = "try-runtime")]
+	pub fn pre_migrate<T: PhalaPallets>() -> Result<(), &'static str> {
+		frame_support::ensure!(
+			get_versions::<T>() == unified_versions::<T>(6),
+			"incorrect pallet versions"
+		);
+		Ok(())
+	}
+
+	pub fn migrate<T>() -> Weight
+	where
+		T: PhalaPallets,
+		MiningBalanceOf<T>: balance_convert::FixedPointConvert + sp_std::fmt::Display,
+		T: mining::pallet::Config<Currency = <T as stakepool::pallet::Config>::Currency>,
+	{
+		if get_versions::<T>() == unified_versions::<T>(6) {
+			let mut weight: Weight = 0;
+			log::info!("Ᵽ migrating phala-pallets to v7");
+			weight += stakepool::Pallet::<T>::migration_add_pending_commission();
+			log::info!("Ᵽ pallets migrated to v7");
+
+			set_unified_versoin::<T>(7);
+			weight += T::DbWeight::get().reads_writes(5, 5);
+			weight
+		} else {
+			T::DbWeight::get().reads(5)
+		}
+	}
+
+	#[cfg(feature = "try-runtime")]
+	pub fn post_migrate<T: PhalaPallets>() -> Result<(), &'static str> {
+		frame_support::ensure!(
+			get_versions::<T>() == unified_versions::<T>(7),
+			"incorrect pallet versions postmigrate"
+		);
+		log::info!("Ᵽ phala pallet migration passes POST migrate checks ✅",);
+		Ok(())
+	}
+}
+
+pub mod v8 {
+	use super::*;
+
+	#[cfg(feature = "try-runtime")]
+	pub fn pre_migrate<T: PhalaPallets>() -> Result<(), &'static str> {
+		frame_support::ensure!(
+			get_versions::<T>() == unified_versions::<T>(7),
+			"incorrect pallet versions"
+		);
+		Ok(())
+	}
+
+	pub fn migrate<T>() -> Weight
+	where
+		T: PhalaPallets,
+		MiningBalanceOf<T>: balance_convert::FixedPointConvert + sp_std::fmt::Display,
+		T: mining::pallet::Config<Currency = <T as stakepool::pallet::Config>::Currency>,
+	{
+		if get_versions::<T>() == unified_versions::<T>(7) {
+			let mut weight: Weight = 0;
+			log::info!("Ᵽ migrating phala-pallets to v8");
+			weight += stakepool::Pallet::<T>::migration_reconcile_withdraw_queues();
+			log::info!("Ᵽ pallets migrated to v8");
+
+			set_unified_versoin::<T>(8);
+			weight += T::DbWeight::get().reads_writes(5, 5);
+			weight
+		} else {
+			T::DbWeight::get().reads(5)
+		}
+	}
+
+	#[cfg(feature = "try-runtime")]
+	pub fn post_migrate<T: PhalaPallets>() -> Result<(), &'static str> {
+		frame_support::ensure!(
+			get_versions::<T>() == unified_versions::<T>(8),
+			"incorrect pallet versions postmigrate"
+		);
+		log::info!("Ᵽ phala pallet migration passes POST migrate checks ✅",);
+		Ok(())
+	}
+}
@@ -7,6 +7,8 @@ pub use self::pallet::*;
 pub mod pallet {
 	use crate::mq::{self, MessageOriginInfo};
 	use crate::registry;
+
+	use super::WeightInfo;
 	use frame_support::traits::WithdrawReasons;
 	use frame_support::{
 		dispatch::DispatchResult,
@@ -70,7 +72,7 @@ pub mod pallet {
 				MinerState::MiningIdle | MinerState::MiningUnresponsive
 			)
 		}
-		fn is_mining(&self) -> bool {
+		pub fn is_mining(&self) -> bool {
 			matches!(
 				self,
 				MinerState::MiningIdle | MinerState::MiningUnresponsive
@@ -205,6 +207,9 @@ pub mod pallet {
 
 		/// The origin to update tokenomic.
 		type UpdateTokenomicOrigin: EnsureOrigin<Self::Origin>;
+
+		/// Weight information for the extrinsics of this pallet.
+		type WeightInfo: WeightInfo;
 	}
 
 	const STORAGE_VERSION: StorageVersion = StorageVersion::new(5);
@@ -414,7 +419,7 @@ pub mod pallet {
 		InternalErrorCannotStartWithExistingStake,
 	}
 
-	type BalanceOf<T> =
+	pub(crate) type BalanceOf<T> =
 		<<T as Config>::Currency as Currency<<T as frame_system::Config>::AccountId>>::Balance;
 
 	type NegativeImbalanceOf<T> = <<T as Config>::Currency as Currency<
@@ -429,7 +434,7 @@ pub mod pallet {
 		/// Sets the cool down expiration time in seconds.
 		///
 		/// Can only be called by root.
-		#[pallet::weight(0)]
+		#[pallet::weight(<T as Config>::WeightInfo::set_cool_down_expiration())]
 		pub fn set_cool_down_expiration(origin: OriginFor<T>, period: u64) -> DispatchResult {
 			ensure_root(origin)?;
 
@@ -442,7 +447,7 @@ pub mod pallet {
 		///
 		/// It will trigger a force stop of mining if the miner is still in mining state. Anyone
 		/// can call it.
-		#[pallet::weight(0)]
+		#[pallet::weight(<T as Config>::WeightInfo::unbind())]
 		pub fn unbind(origin: OriginFor<T>, miner: T::AccountId) -> DispatchResult {
 			let who = ensure_signed(origin)?;
 			let pubkey = Self::ensure_miner_bound(&miner)?;
@@ -457,7 +462,7 @@ pub mod pallet {
 		/// Triggers a force heartbeat request to all workers by sending a MAX pow target
 		///
 		/// Only for integration test.
-		#[pallet::weight(1)]
+		#[pallet::weight(<T as Config>::WeightInfo::force_heartbeat())]
 		pub fn force_heartbeat(origin: OriginFor<T>) -> DispatchResult {
 			ensure_root(origin)?;
 			Self::push_message(SystemEvent::HeartbeatChallenge(HeartbeatChallenge {
@@ -470,7 +475,7 @@ pub mod pallet {
 		/// Start mining
 		///
 		/// Only for integration test.
-		#[pallet::weight(1)]
+		#[pallet::weight(<T as Config>::WeightInfo::force_start_mining())]
 		pub fn force_start_mining(
 			origin: OriginFor<T>,
 			miner: T::AccountId,
@@ -484,7 +489,7 @@ pub mod pallet {
 		/// Stop mining
 		///
 		/// Only for integration test.
-		#[pallet::weight(1)]
+		#[pallet::weight(<T as Config>::WeightInfo::force_stop_mining())]
 		pub fn force_stop_mining(origin: OriginFor<T>, miner: T::AccountId) -> DispatchResult {
 			ensure_root(origin)?;
 			Self::stop_mining(miner)?;
@@ -494,7 +499,7 @@ pub mod pallet {
 		/// Updates the tokenomic parameters at the end of this block.
 		///
 		/// Can only be called by the tokenomic admin.
-		#[pallet::weight(1)]
+		#[pallet::weight(<T as Config>::WeightInfo::update_tokenomic())]
 		pub fn update_tokenomic(
 			origin: OriginFor<T>,
 			new_params: TokenomicParams,
@@ -1661,3 +1666,9 @@ pub mod pallet {
 		}
 	}
 }
+
+pub mod weights;
+pub use weights::WeightInfo;
+
+#[cfg(feature = "runtime-benchmarks")]
+mod benchmarking;
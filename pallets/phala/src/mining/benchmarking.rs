@@ -0,0 +1,79 @@
+//! Benchmarks of the mining pallet
+
+use super::*;
+
+use super::pallet::BalanceOf;
+use crate::balance_convert::FixedPointConvert;
+use crate::stakepool::{
+	self,
+	benchmarking::{pha, setup_pool},
+	pallet::pool_sub_account,
+};
+use frame_benchmarking::{benchmarks, whitelisted_caller};
+use frame_support::traits::EnsureOrigin;
+use frame_system::RawOrigin;
+use phala_types::messaging::TokenomicParameters as TokenomicParams;
+use sp_std::fmt::Display;
+
+/// The stake of the benchmarked miners, in PHA.
+const MINER_STAKE: u32 = 1000;
+
+benchmarks! {
+	where_clause { where
+		T: stakepool::Config,
+		T: Config<Currency = <T as stakepool::Config>::Currency>,
+		BalanceOf<T>: FixedPointConvert + Display,
+	}
+
+	set_cool_down_expiration {
+	}: _(RawOrigin::Root, 3600)
+	verify {
+		assert_eq!(CoolDownPeriod::<T>::get(), 3600);
+	}
+
+	unbind {
+		let caller: T::AccountId = whitelisted_caller();
+		let (pid, workers) = setup_pool::<T>(&caller, 1);
+		let miner: T::AccountId = pool_sub_account(pid, &workers[0]);
+		// The worst case: a mining miner is force stopped before unbinding
+		Pallet::<T>::start_mining(miner.clone(), pha(MINER_STAKE))?;
+	}: _(RawOrigin::Signed(caller), miner.clone())
+	verify {
+		assert!(MinerBindings::<T>::get(&miner).is_none());
+	}
+
+	force_heartbeat {
+	}: _(RawOrigin::Root)
+
+	force_start_mining {
+		let caller: T::AccountId = whitelisted_caller();
+		let (pid, workers) = setup_pool::<T>(&caller, 1);
+		let miner: T::AccountId = pool_sub_account(pid, &workers[0]);
+	}: _(RawOrigin::Root, miner.clone(), pha(MINER_STAKE))
+	verify {
+		assert_eq!(Miners::<T>::get(&miner).unwrap().state, MinerState::MiningIdle);
+	}
+
+	force_stop_mining {
+		let caller: T::AccountId = whitelisted_caller();
+		let (pid, workers) = setup_pool::<T>(&caller, 1);
+		let miner: T::AccountId = pool_sub_account(pid, &workers[0]);
+		Pallet::<T>::start_mining(miner.clone(), pha(MINER_STAKE))?;
+	}: _(RawOrigin::Root, miner.clone())
+	verify {
+		assert_eq!(Miners::<T>::get(&miner).unwrap().state, MinerState::MiningCoolingDown);
+	}
+
+	update_tokenomic {
+		let params: TokenomicParams = {
+			pallet::migrations::initialize::<T>();
+			TokenomicParameters::<T>::get().expect("Tokenomic was just initialized; qed.")
+		};
+		let origin = T::UpdateTokenomicOrigin::successful_origin();
+	}: _<T::Origin>(origin, params)
+	verify {
+		assert!(ScheduledTokenomicUpdate::<T>::get().is_some());
+	}
+
+	impl_benchmark_test_suite!(Pallet, crate::mock::new_bench_ext(), crate::mock::Test);
+}
@@ -0,0 +1,117 @@
+//! Weights for pallet_mining
+//!
+//! PLACEHOLDER WEIGHTS, NOT GENERATED BY THE BENCHMARK CLI.
+//!
+//! The values are hand-estimated from the storage accesses of each call and have never been
+//! measured. They must be regenerated on the reference machine with
+//! `scripts/benchmark-pallets.sh mining` before being relied on.
+
+#![cfg_attr(rustfmt, rustfmt_skip)]
+#![allow(unused_parens)]
+#![allow(unused_imports)]
+
+use frame_support::{traits::Get, weights::{Weight, constants::RocksDbWeight}};
+use sp_std::marker::PhantomData;
+
+/// Weight functions needed for pallet_mining.
+pub trait WeightInfo {
+	fn set_cool_down_expiration() -> Weight;
+	fn unbind() -> Weight;
+	fn force_heartbeat() -> Weight;
+	fn force_start_mining() -> Weight;
+	fn force_stop_mining() -> Weight;
+	fn update_tokenomic() -> Weight;
+}
+
+/// Weights for pallet_mining using the Substrate node and recommended hardware.
+pub struct SubstrateWeight<T>(PhantomData<T>);
+impl<T: frame_system::Config> WeightInfo for SubstrateWeight<T> {
+	// Storage: PhalaMining CoolDownPeriod (r:0 w:1)
+	fn set_cool_down_expiration() -> Weight {
+		(11_000_000 as Weight)
+			.saturating_add(T::DbWeight::get().writes(1 as Weight))
+	}
+	// Storage: PhalaMining MinerBindings (r:1 w:1)
+	// Storage: PhalaRegistry Workers (r:1 w:0)
+	// Storage: PhalaMining Miners (r:1 w:1)
+	// Storage: Timestamp Now (r:1 w:0)
+	// Storage: PhalaMining Stakes (r:1 w:0)
+	// Storage: PhalaMining OnlineMiners (r:1 w:1)
+	// Storage: PhalaStakePool WorkerAssignments (r:1 w:1)
+	// Storage: PhalaStakePool StakePools (r:1 w:1)
+	// Storage: PhalaMq OutboundMessages (r:0 w:1)
+	// Storage: PhalaMining WorkerBindings (r:0 w:1)
+	fn unbind() -> Weight {
+		(78_000_000 as Weight)
+			.saturating_add(T::DbWeight::get().reads(8 as Weight))
+			.saturating_add(T::DbWeight::get().writes(7 as Weight))
+	}
+	// Storage: PhalaMq OutboundMessages (r:0 w:1)
+	fn force_heartbeat() -> Weight {
+		(12_000_000 as Weight)
+			.saturating_add(T::DbWeight::get().writes(1 as Weight))
+	}
+	// Storage: PhalaMining MinerBindings (r:1 w:0)
+	// Storage: PhalaMining Miners (r:1 w:1)
+	// Storage: PhalaMining Stakes (r:1 w:1)
+	// Storage: PhalaRegistry Workers (r:1 w:0)
+	// Storage: PhalaMining TokenomicParameters (r:1 w:0)
+	// Storage: Timestamp Now (r:1 w:0)
+	// Storage: PhalaMining OnlineMiners (r:1 w:1)
+	// Storage: PhalaMining NextSessionId (r:1 w:1)
+	// Storage: PhalaMq OutboundMessages (r:0 w:1)
+	fn force_start_mining() -> Weight {
+		(52_000_000 as Weight)
+			.saturating_add(T::DbWeight::get().reads(8 as Weight))
+			.saturating_add(T::DbWeight::get().writes(5 as Weight))
+	}
+	// Storage: PhalaMining MinerBindings (r:1 w:0)
+	// Storage: PhalaMining Miners (r:1 w:1)
+	// Storage: Timestamp Now (r:1 w:0)
+	// Storage: PhalaMining OnlineMiners (r:1 w:1)
+	// Storage: PhalaMining Stakes (r:1 w:0)
+	// Storage: PhalaStakePool WorkerAssignments (r:1 w:0)
+	// Storage: PhalaStakePool StakePools (r:1 w:1)
+	// Storage: PhalaMq OutboundMessages (r:0 w:1)
+	fn force_stop_mining() -> Weight {
+		(48_000_000 as Weight)
+			.saturating_add(T::DbWeight::get().reads(7 as Weight))
+			.saturating_add(T::DbWeight::get().writes(4 as Weight))
+	}
+	// Storage: PhalaMining ScheduledTokenomicUpdate (r:0 w:1)
+	fn update_tokenomic() -> Weight {
+		(14_000_000 as Weight)
+			.saturating_add(T::DbWeight::get().writes(1 as Weight))
+	}
+}
+
+// For backwards compatibility and tests
+impl WeightInfo for () {
+	fn set_cool_down_expiration() -> Weight {
+		(11_000_000 as Weight)
+			.saturating_add(RocksDbWeight::get().writes(1 as Weight))
+	}
+	fn unbind() -> Weight {
+		(78_000_000 as Weight)
+			.saturating_add(RocksDbWeight::get().reads(8 as Weight))
+			.saturating_add(RocksDbWeight::get().writes(7 as Weight))
+	}
+	fn force_heartbeat() -> Weight {
+		(12_000_000 as Weight)
+			.saturating_add(RocksDbWeight::get().writes(1 as Weight))
+	}
+	fn force_start_mining() -> Weight {
+		(52_000_000 as Weight)
+			.saturating_add(RocksDbWeight::get().reads(8 as Weight))
+			.saturating_add(RocksDbWeight::get().writes(5 as Weight))
+	}
+	fn force_stop_mining() -> Weight {
+		(48_000_000 as Weight)
+			.saturating_add(RocksDbWeight::get().reads(7 as Weight))
+			.saturating_add(RocksDbWeight::get().writes(4 as Weight))
+	}
+	fn update_tokenomic() -> Weight {
+		(14_000_000 as Weight)
+			.saturating_add(RocksDbWeight::get().writes(1 as Weight))
+	}
+}
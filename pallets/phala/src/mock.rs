@@ -15,6 +15,7 @@ use sp_core::H256;
 use sp_runtime::{
 	testing::Header,
 	traits::{BlakeTwo256, IdentityLookup},
+	Permill,
 };
 
 pub(crate) type Balance = u128;
@@ -34,7 +35,7 @@ frame_support::construct_runtime!(
 		Timestamp: pallet_timestamp::{Pallet, Call, Storage, Inherent},
 		Balances: pallet_balances::{Pallet, Call, Storage, Config<T>, Event<T>},
 		// Pallets to test
-		PhalaMq: mq::{Pallet, Call},
+		PhalaMq: mq::{Pallet, Call, Event<T>},
 		PhalaRegistry: registry::{Pallet, Event<T>, Storage, Config<T>},
 		PhalaMining: mining::{Pallet, Event<T>, Storage, Config},
 		PhalaStakePool: stakepool::{Pallet, Event<T>},
@@ -54,8 +55,11 @@ parameter_types! {
 	pub const MinInitP: u32 = 1;
 	pub const MiningEnabledByDefault: bool = true;
 	pub const MaxPoolWorkers: u32 = 10;
+	pub const CommissionChangeDelay: BlockNumber = 10;
+	pub const MaxCommissionIncrease: Permill = Permill::from_percent(20);
 	pub const VerifyPRuntime: bool = false;
 	pub const VerifyRelaychainGenesisBlockHash: bool = true;
+	pub const MasterKeyRotationRetryInterval: u64 = 10;
 }
 impl system::Config for Test {
 	type BaseCallFilter = frame_support::traits::Everything;
@@ -107,8 +111,10 @@ pub const DOLLARS: Balance = 1_000_000_000_000;
 pub const CENTS: Balance = DOLLARS / 100;
 
 impl mq::Config for Test {
+	type Event = Event;
 	type QueueNotifyConfig = ();
 	type CallMatcher = MqCallMatcher;
+	type WeightInfo = ();
 }
 
 pub struct MqCallMatcher;
@@ -128,7 +134,9 @@ impl registry::Config for Test {
 	type UnixTime = Timestamp;
 	type VerifyPRuntime = VerifyPRuntime;
 	type VerifyRelaychainGenesisBlockHash = VerifyRelaychainGenesisBlockHash;
+	type MasterKeyRotationRetryInterval = MasterKeyRotationRetryInterval;
 	type GovernanceOrigin = frame_system::EnsureRoot<Self::AccountId>;
+	type WeightInfo = ();
 }
 
 impl mining::Config for Test {
@@ -142,6 +150,7 @@ impl mining::Config for Test {
 	type OnStopped = PhalaStakePool;
 	type OnTreasurySettled = ();
 	type UpdateTokenomicOrigin = frame_system::EnsureRoot<Self::AccountId>;
+	type WeightInfo = ();
 }
 
 impl stakepool::Config for Test {
@@ -151,9 +160,12 @@ impl stakepool::Config for Test {
 	type GracePeriod = MiningGracePeriod;
 	type MiningEnabledByDefault = MiningEnabledByDefault;
 	type MaxPoolWorkers = MaxPoolWorkers;
+	type CommissionChangeDelay = CommissionChangeDelay;
+	type MaxCommissionIncrease = MaxCommissionIncrease;
 	type OnSlashed = ();
 	type MiningSwitchOrigin = frame_system::EnsureRoot<Self::AccountId>;
 	type BackfillOrigin = frame_system::EnsureRoot<Self::AccountId>;
+	type WeightInfo = ();
 }
 
 impl ott::Config for Test {
@@ -169,6 +181,7 @@ impl AttestationValidator for MockValidator {
 		_now: u64,
 		_verify_pruntime: bool,
 		_pruntime_allowlist: Vec<Vec<u8>>,
+		_dcap_trusted_root_certs: Vec<Vec<u8>>,
 	) -> Result<IasFields, AttestationError> {
 		Ok(IasFields {
 			mr_enclave: [0u8; 32],
@@ -213,6 +226,15 @@ pub fn new_test_ext() -> sp_io::TestExternalities {
 	sp_io::TestExternalities::new(t)
 }
 
+/// Builds the test externalities with a keystore, required by the benchmarks signing messages.
+#[cfg(feature = "runtime-benchmarks")]
+pub fn new_bench_ext() -> sp_io::TestExternalities {
+	use sp_keystore::{testing::KeyStore, KeystoreExt};
+	let mut ext = new_test_ext();
+	ext.register_extension(KeystoreExt(std::sync::Arc::new(KeyStore::new())));
+	ext
+}
+
 pub fn set_block_1() {
 	System::set_block_number(1);
 }
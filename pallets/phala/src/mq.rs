@@ -20,10 +20,15 @@ pub mod pallet {
 	use primitive_types::H256;
 	use sp_std::vec::Vec;
 
+	use super::WeightInfo;
+
 	#[pallet::config]
 	pub trait Config: frame_system::Config + crate::registry::Config {
+		type Event: From<Event<Self>> + IsType<<Self as frame_system::Config>::Event>;
 		type QueueNotifyConfig: QueueNotifyConfig;
 		type CallMatcher: CallMatcher<Self>;
+		/// Weight information for the extrinsics of this pallet.
+		type WeightInfo: WeightInfo;
 	}
 
 	const STORAGE_VERSION: StorageVersion = StorageVersion::new(5);
@@ -48,11 +53,25 @@ pub mod pallet {
 	#[pallet::getter(fn messages)]
 	pub type OutboundMessages<T> = StorageValue<_, Vec<Message>, ValueQuery>;
 
+	#[pallet::event]
+	#[pallet::generate_deposit(pub(super) fn deposit_event)]
+	pub enum Event<T: Config> {
+		/// A message in a batch of offchain messages failed the checks and was skipped.
+		OffchainMessageSkipped {
+			/// The position of the message in the batch
+			index: u32,
+			sender: MessageOrigin,
+			sequence: u64,
+			error: DispatchError,
+		},
+	}
+
 	#[pallet::error]
 	pub enum Error<T> {
 		BadSender,
 		BadSequence,
 		BadDestination,
+		EmptyBatch,
 	}
 
 	#[pallet::call]
@@ -62,41 +81,19 @@ pub mod pallet {
 		T::AccountId: IntoH256,
 	{
 		/// Syncs an unverified offchain message to the message queue
-		#[pallet::weight(10_000 + T::DbWeight::get().writes(1))]
+		#[pallet::weight(<T as Config>::WeightInfo::sync_offchain_message(
+			signed_message.message.payload.len() as u32
+		))]
 		pub fn sync_offchain_message(
 			origin: OriginFor<T>,
 			signed_message: SignedMessage,
 		) -> DispatchResult {
 			ensure_signed(origin)?;
-
-			// Check sender
-			let sender = &signed_message.message.sender;
-			ensure!(sender.is_offchain(), Error::<T>::BadSender);
-
-			// Check destination
-			ensure!(
-				signed_message.message.destination.is_valid(),
-				Error::<T>::BadDestination
-			);
-
-			// Check ingress sequence
-			let expected_seq = OffchainIngress::<T>::get(sender).unwrap_or(0);
-			ensure!(
-				signed_message.sequence == expected_seq,
-				Error::<T>::BadSequence
-			);
-			// Validate signature
-			crate::registry::Pallet::<T>::check_message(&signed_message)?;
-			// Update ingress
-			OffchainIngress::<T>::insert(sender.clone(), expected_seq + 1);
-			// Call dispatch_message
-			Self::dispatch_message(signed_message.message);
-			Ok(())
+			Self::do_sync_offchain_message(signed_message)
 		}
 
 		// Messaging API for end user.
-		// TODO.kevin: confirm the weight
-		#[pallet::weight(10_000 + T::DbWeight::get().writes(1))]
+		#[pallet::weight(<T as Config>::WeightInfo::push_message(payload.len() as u32))]
 		pub fn push_message(
 			origin: OriginFor<T>,
 			destination: Vec<u8>,
@@ -110,7 +107,9 @@ pub mod pallet {
 		}
 
 		// Force push a from-pallet message.
-		#[pallet::weight(10_000 + T::DbWeight::get().writes(1))]
+		#[pallet::weight(<T as Config>::WeightInfo::force_push_pallet_message(
+			payload.len() as u32
+		))]
 		pub fn force_push_pallet_message(
 			origin: OriginFor<T>,
 			destination: Vec<u8>,
@@ -122,9 +121,67 @@ pub mod pallet {
 			Self::dispatch_message(message);
 			Ok(())
 		}
+
+		/// Syncs a batch of unverified offchain messages to the message queue
+		///
+		/// The messages are processed in order. A message failing the checks is skipped without
+		/// affecting the others, except the following messages from the same sender, which then
+		/// fail with `BadSequence`. An `OffchainMessageSkipped` event is emitted for each skipped
+		/// message, which is left to be synced again.
+		#[pallet::weight(signed_messages.iter().fold(0 as Weight, |weight, signed_message| {
+			weight.saturating_add(<T as Config>::WeightInfo::sync_offchain_message(
+				signed_message.message.payload.len() as u32,
+			))
+		}))]
+		pub fn sync_offchain_messages(
+			origin: OriginFor<T>,
+			signed_messages: Vec<SignedMessage>,
+		) -> DispatchResult {
+			ensure_signed(origin)?;
+			ensure!(!signed_messages.is_empty(), Error::<T>::EmptyBatch);
+			for (index, signed_message) in signed_messages.into_iter().enumerate() {
+				let sender = signed_message.message.sender.clone();
+				let sequence = signed_message.sequence;
+				if let Err(error) = Self::do_sync_offchain_message(signed_message) {
+					Self::deposit_event(Event::<T>::OffchainMessageSkipped {
+						index: index as u32,
+						sender,
+						sequence,
+						error,
+					});
+				}
+			}
+			Ok(())
+		}
 	}
 
 	impl<T: Config> Pallet<T> {
+		fn do_sync_offchain_message(signed_message: SignedMessage) -> DispatchResult {
+			// Check sender
+			let sender = &signed_message.message.sender;
+			ensure!(sender.is_offchain(), Error::<T>::BadSender);
+
+			// Check destination
+			ensure!(
+				signed_message.message.destination.is_valid(),
+				Error::<T>::BadDestination
+			);
+
+			// Check ingress sequence
+			let expected_seq = OffchainIngress::<T>::get(sender).unwrap_or(0);
+			ensure!(
+				signed_message.sequence == expected_seq,
+				Error::<T>::BadSequence
+			);
+			// Validate signature
+			crate::registry::Pallet::<T>::check_message(&signed_message)?;
+			// Update ingress
+			OffchainIngress::<T>::insert(sender.clone(), expected_seq + 1);
+			// Call dispatch_message
+			Self::dispatch_message(signed_message.message);
+			Ok(())
+		}
+
 		/// Push a validated message to the queue
 		pub fn dispatch_message(message: Message) {
 			// Notify subcribers
@@ -280,8 +337,126 @@ pub mod pallet {
 			Pallet::<Self::Config>::queue_bound_message(Self::message_origin(), payload);
 		}
 	}
+
+	#[cfg(test)]
+	mod test {
+		use frame_support::{assert_noop, assert_ok, weights::GetDispatchInfo};
+		use sp_core::{sr25519, Pair};
+
+		use super::*;
+		use crate::mock::{new_test_ext, set_block_1, take_events, Origin, Test};
+		// Pallets
+		use crate::mock::PhalaMq;
+
+		fn worker(i: u8) -> sr25519::Pair {
+			sr25519::Pair::from_seed(&[i; 32])
+		}
+
+		fn sender(i: u8) -> MessageOrigin {
+			MessageOrigin::Worker(worker(i).public())
+		}
+
+		fn signed_msg(i: u8, sequence: u64) -> SignedMessage {
+			let mut signed_message = SignedMessage {
+				message: Message::new(sender(i), b"phala/test/mq".to_vec(), vec![i; 8]),
+				sequence,
+				signature: Vec::new(),
+			};
+			signed_message.signature = worker(i).sign(&signed_message.data_be_signed()).0.to_vec();
+			signed_message
+		}
+
+		fn skipped(i: u8, index: u32, sequence: u64, error: DispatchError) -> crate::mock::Event {
+			crate::mock::Event::PhalaMq(Event::OffchainMessageSkipped {
+				index,
+				sender: sender(i),
+				sequence,
+				error,
+			})
+		}
+
+		#[test]
+		fn test_sync_offchain_messages_skips_bad_messages() {
+			new_test_ext().execute_with(|| {
+				set_block_1();
+				let mut forged = signed_msg(1, 1);
+				forged.signature = signed_msg(2, 1).signature;
+				assert_ok!(PhalaMq::sync_offchain_messages(
+					Origin::signed(1),
+					vec![
+						signed_msg(1, 0),
+						forged,
+						// Follows the skipped message
+						signed_msg(1, 2),
+						signed_msg(2, 0),
+						signed_msg(2, 1),
+					]
+				));
+				assert_eq!(OffchainIngress::<Test>::get(sender(1)), Some(1));
+				assert_eq!(OffchainIngress::<Test>::get(sender(2)), Some(2));
+				assert_eq!(
+					take_events(),
+					vec![
+						skipped(
+							1,
+							1,
+							1,
+							crate::registry::Error::<Test>::InvalidSignature.into()
+						),
+						skipped(1, 2, 2, Error::<Test>::BadSequence.into()),
+					]
+				);
+				// The skipped messages can be synced again
+				assert_ok!(PhalaMq::sync_offchain_messages(
+					Origin::signed(1),
+					vec![signed_msg(1, 1), signed_msg(1, 2)]
+				));
+				assert_eq!(OffchainIngress::<Test>::get(sender(1)), Some(3));
+				assert_eq!(take_events(), vec![]);
+			});
+		}
+
+		#[test]
+		fn test_sync_offchain_messages_rejects_empty_batch() {
+			new_test_ext().execute_with(|| {
+				set_block_1();
+				assert_noop!(
+					PhalaMq::sync_offchain_messages(Origin::signed(1), vec![]),
+					Error::<Test>::EmptyBatch
+				);
+			});
+		}
+
+		#[test]
+		fn test_sync_offchain_messages_weight() {
+			let messages: Vec<_> = (1..=3).map(|i| signed_msg(i, 0)).collect();
+			let single_weights: Vec<_> = messages
+				.iter()
+				.map(|signed_message| {
+					Call::<Test>::sync_offchain_message {
+						signed_message: signed_message.clone(),
+					}
+					.get_dispatch_info()
+					.weight
+				})
+				.collect();
+			let info = Call::<Test>::sync_offchain_messages {
+				signed_messages: messages,
+			}
+			.get_dispatch_info();
+			// Charged as if the messages were synced one by one, no matter how many are skipped
+			assert_eq!(info.weight, single_weights.iter().sum::<Weight>());
+			assert!(single_weights.iter().all(|weight| *weight > 0));
+		}
+	}
 }
 
 /// Provides `SignedExtension` to check message sequence.
 mod check_seq;
 pub use check_seq::{tag, CheckMqSequence};
+
+pub mod weights;
+pub use weights::WeightInfo;
+
+#[cfg(feature = "runtime-benchmarks")]
+mod benchmarking;
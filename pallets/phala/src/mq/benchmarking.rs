@@ -0,0 +1,61 @@
+//! Benchmarks of the message queue pallet
+
+use super::*;
+
+use frame_benchmarking::{benchmarks, whitelisted_caller};
+use frame_system::RawOrigin;
+use phala_types::messaging::{Message, MessageOrigin, SignedMessage};
+use sp_core::crypto::KeyTypeId;
+use sp_std::{vec, vec::Vec};
+
+/// The key type used to sign the messages in the benchmarks.
+const KEY_TYPE: KeyTypeId = KeyTypeId(*b"phmq");
+
+/// The upper bound of the payload length in the benchmarks.
+const MAX_PAYLOAD_LEN: u32 = 64 * 1024;
+
+/// A destination ignored by the on-chain handlers, so only the queueing cost is measured.
+const DESTINATION: &[u8] = b"phala/benchmarking/mq";
+
+benchmarks! {
+	where_clause { where T::AccountId: IntoH256 }
+
+	sync_offchain_message {
+		let n in 0 .. MAX_PAYLOAD_LEN;
+		let caller: T::AccountId = whitelisted_caller();
+		let pubkey = sp_io::crypto::sr25519_generate(KEY_TYPE, None);
+		let sender = MessageOrigin::Worker(pubkey);
+		let mut signed_message = SignedMessage {
+			message: Message::new(sender.clone(), DESTINATION, vec![0u8; n as usize]),
+			sequence: 0,
+			signature: Vec::new(),
+		};
+		let signature = sp_io::crypto::sr25519_sign(
+			KEY_TYPE,
+			&pubkey,
+			&signed_message.data_be_signed(),
+		)
+		.expect("The key was just generated; qed.");
+		signed_message.signature = signature.0.to_vec();
+	}: _(RawOrigin::Signed(caller), signed_message)
+	verify {
+		assert_eq!(OffchainIngress::<T>::get(&sender), Some(1));
+	}
+
+	push_message {
+		let n in 0 .. MAX_PAYLOAD_LEN;
+		let caller: T::AccountId = whitelisted_caller();
+	}: _(RawOrigin::Signed(caller), DESTINATION.to_vec(), vec![0u8; n as usize])
+	verify {
+		assert_eq!(OutboundMessages::<T>::get().len(), 1);
+	}
+
+	force_push_pallet_message {
+		let n in 0 .. MAX_PAYLOAD_LEN;
+	}: _(RawOrigin::Root, DESTINATION.to_vec(), vec![0u8; n as usize])
+	verify {
+		assert_eq!(OutboundMessages::<T>::get().len(), 1);
+	}
+
+	impl_benchmark_test_suite!(Pallet, crate::mock::new_bench_ext(), crate::mock::Test);
+}
@@ -2,7 +2,7 @@ use super::{Call, CallMatcher, Config, IntoH256, OffchainIngress};
 
 use codec::{Decode, Encode};
 use frame_support::weights::DispatchInfo;
-use phala_types::messaging::MessageOrigin;
+use phala_types::messaging::{MessageOrigin, SignedMessage};
 use scale_info::TypeInfo;
 use sp_runtime::traits::{DispatchInfoOf, Dispatchable, SignedExtension};
 use sp_runtime::transaction_validity::{
@@ -14,7 +14,7 @@ use sp_std::vec::Vec;
 
 /// Requires a message queue message must has correct sequence id.
 ///
-/// We only care about `sync_offchain_message` call.
+/// We only care about `sync_offchain_message` and `sync_offchain_messages` calls.
 ///
 /// When a message comes to the transaction pool, we drop it immediately if its sequence is
 /// less than the expected one. Otherwise we keep the message in the pool for a while, hoping there
@@ -27,6 +27,26 @@ pub fn tag(sender: &MessageOrigin, seq: u64) -> Vec<u8> {
 	("PhalaMqOffchainMessages", sender, seq).encode()
 }
 
+/// Returns the offchain messages carried by the call.
+fn offchain_messages<T: Config>(call: &T::Call) -> Vec<&SignedMessage>
+where
+	T::AccountId: IntoH256,
+{
+	match T::CallMatcher::match_call(call) {
+		Some(Call::sync_offchain_message { signed_message }) => vec![signed_message],
+		Some(Call::sync_offchain_messages { signed_messages }) => signed_messages.iter().collect(),
+		_ => Vec::new(),
+	}
+}
+
+/// Whether the message comes right after another message of the same sender in the batch.
+fn follows_batched(batch: &[&SignedMessage], signed_message: &SignedMessage) -> bool {
+	batch.iter().any(|other| {
+		other.message.sender == signed_message.message.sender
+			&& signed_message.sequence.checked_sub(1) == Some(other.sequence)
+	})
+}
+
 impl<T> Default for CheckMqSequence<T> {
 	fn default() -> Self {
 		Self(Default::default())
@@ -74,21 +94,23 @@ where
 		_info: &DispatchInfoOf<Self::Call>,
 		_len: usize,
 	) -> Result<(), TransactionValidityError> {
-		let signed_message = match T::CallMatcher::match_call(call) {
-			Some(Call::sync_offchain_message { signed_message }) => signed_message,
-			_ => return Ok(()),
-		};
-		let sender = &signed_message.message.sender;
-		let sequence = signed_message.sequence;
-		let expected_seq = OffchainIngress::<T>::get(sender).unwrap_or(0);
-		// Strictly require the message to include must match the expected sequence id
-		if sequence != expected_seq {
-			return Err(if sequence < expected_seq {
-				InvalidTransaction::Stale
-			} else {
-				InvalidTransaction::Future
+		let batch = offchain_messages::<T>(call);
+		for signed_message in batch.iter() {
+			if follows_batched(&batch, signed_message) {
+				continue;
+			}
+			let sender = &signed_message.message.sender;
+			let sequence = signed_message.sequence;
+			let expected_seq = OffchainIngress::<T>::get(sender).unwrap_or(0);
+			// Strictly require the message to include must match the expected sequence id
+			if sequence != expected_seq {
+				return Err(if sequence < expected_seq {
+					InvalidTransaction::Stale
+				} else {
+					InvalidTransaction::Future
+				}
+				.into());
 			}
-			.into());
 		}
 		Ok(())
 	}
@@ -100,26 +122,28 @@ where
 		_info: &DispatchInfoOf<Self::Call>,
 		_len: usize,
 	) -> TransactionValidity {
-		let signed_message = match T::CallMatcher::match_call(call) {
-			Some(Call::sync_offchain_message { signed_message }) => signed_message,
-			_ => return Ok(ValidTransaction::default()),
-		};
-		let sender = &signed_message.message.sender;
-		let sequence = signed_message.sequence;
-		let expected_seq = OffchainIngress::<T>::get(sender).unwrap_or(0);
-		// Drop the stale message immediately
-		if sequence < expected_seq {
-			return InvalidTransaction::Stale.into();
+		let batch = offchain_messages::<T>(call);
+		if batch.is_empty() {
+			return Ok(ValidTransaction::default());
 		}
+		let mut provides = Vec::new();
+		let mut requires = Vec::new();
+		for signed_message in batch.iter() {
+			let sender = &signed_message.message.sender;
+			let sequence = signed_message.sequence;
+			let expected_seq = OffchainIngress::<T>::get(sender).unwrap_or(0);
+			// Drop the stale message immediately
+			if sequence < expected_seq {
+				return InvalidTransaction::Stale.into();
+			}
 
-		// Otherwise build a dependency graph based on (sender, sequence), hoping that it can be
-		// included later
-		let provides = vec![tag(sender, sequence)];
-		let requires = if sequence > expected_seq {
-			vec![tag(sender, sequence - 1)]
-		} else {
-			vec![]
-		};
+			// Otherwise build a dependency graph based on (sender, sequence), hoping that it can
+			// be included later
+			provides.push(tag(sender, sequence));
+			if sequence > expected_seq && !follows_batched(&batch, signed_message) {
+				requires.push(tag(sender, sequence - 1));
+			}
+		}
 		Ok(ValidTransaction {
 			provides,
 			requires,
@@ -166,21 +190,66 @@ mod tests {
 		})
 	}
 
+	#[test]
+	fn test_check_mq_seq_of_batch_works() {
+		new_test_ext().execute_with(|| {
+			OffchainIngress::<Test>::insert(&MessageOrigin::Worker(worker_pubkey(1)), 1);
+			OffchainIngress::<Test>::insert(&MessageOrigin::Worker(worker_pubkey(2)), 2);
+			let info = DispatchInfo::default();
+			let len = 0_usize;
+			// stale
+			assert_noop!(
+				extra().validate(&1, &sync_batch_call(&[(1, 0), (1, 1)]), &info, len),
+				InvalidTransaction::Stale
+			);
+			// correct, with messages from multiple senders
+			let call = sync_batch_call(&[(1, 1), (1, 2), (2, 2)]);
+			let valid = extra().validate(&1, &call, &info, len).unwrap();
+			assert_eq!(valid.provides.len(), 3);
+			assert!(valid.requires.is_empty());
+			assert_ok!(extra().pre_dispatch(&1, &call, &info, len));
+			// future, requiring only the message before the first one in the batch
+			let call = sync_batch_call(&[(1, 2), (1, 3)]);
+			let valid = extra().validate(&1, &call, &info, len).unwrap();
+			assert_eq!(
+				valid.requires,
+				vec![tag(&MessageOrigin::Worker(worker_pubkey(1)), 1)]
+			);
+			assert_noop!(
+				extra().pre_dispatch(&1, &call, &info, len),
+				InvalidTransaction::Future
+			);
+		})
+	}
+
 	fn extra() -> CheckMqSequence<Test> {
 		CheckMqSequence::<Test>::new()
 	}
 
+	fn signed_msg(i: u8, seq: u64) -> SignedMessage {
+		SignedMessage {
+			message: Message::new(
+				MessageOrigin::Worker(worker_pubkey(i)),
+				Topic::new(*b""),
+				Vec::new(),
+			),
+			sequence: seq,
+			signature: Vec::new(),
+		}
+	}
+
 	fn sync_msg_call(i: u8, seq: u64) -> TestCall {
 		TestCall::PhalaMq(Call::<Test>::sync_offchain_message {
-			signed_message: SignedMessage {
-				message: Message::new(
-					MessageOrigin::Worker(worker_pubkey(i)),
-					Topic::new(*b""),
-					Vec::new(),
-				),
-				sequence: seq,
-				signature: Vec::new(),
-			},
+			signed_message: signed_msg(i, seq),
+		})
+	}
+
+	fn sync_batch_call(messages: &[(u8, u64)]) -> TestCall {
+		TestCall::PhalaMq(Call::<Test>::sync_offchain_messages {
+			signed_messages: messages
+				.iter()
+				.map(|&(i, seq)| signed_msg(i, seq))
+				.collect(),
 		})
 	}
 }
@@ -0,0 +1,66 @@
+//! Weights for pallet_mq
+//!
+//! PLACEHOLDER WEIGHTS, NOT GENERATED BY THE BENCHMARK CLI.
+//!
+//! The values are hand-estimated from the storage accesses of each call and have never been
+//! measured. They must be regenerated on the reference machine with
+//! `scripts/benchmark-pallets.sh mq` before being relied on.
+
+#![cfg_attr(rustfmt, rustfmt_skip)]
+#![allow(unused_parens)]
+#![allow(unused_imports)]
+
+use frame_support::{traits::Get, weights::{Weight, constants::RocksDbWeight}};
+use sp_std::marker::PhantomData;
+
+/// Weight functions needed for pallet_mq.
+pub trait WeightInfo {
+	fn sync_offchain_message(n: u32, ) -> Weight;
+	fn push_message(n: u32, ) -> Weight;
+	fn force_push_pallet_message(n: u32, ) -> Weight;
+}
+
+/// Weights for pallet_mq using the Substrate node and recommended hardware.
+pub struct SubstrateWeight<T>(PhantomData<T>);
+impl<T: frame_system::Config> WeightInfo for SubstrateWeight<T> {
+	// Storage: PhalaMq OffchainIngress (r:1 w:1)
+	// Storage: PhalaMq OutboundMessages (r:0 w:1)
+	fn sync_offchain_message(n: u32, ) -> Weight {
+		(68_000_000 as Weight)
+			.saturating_add((3_000 as Weight).saturating_mul(n as Weight))
+			.saturating_add(T::DbWeight::get().reads(1 as Weight))
+			.saturating_add(T::DbWeight::get().writes(2 as Weight))
+	}
+	// Storage: PhalaMq OutboundMessages (r:0 w:1)
+	fn push_message(n: u32, ) -> Weight {
+		(14_000_000 as Weight)
+			.saturating_add((1_000 as Weight).saturating_mul(n as Weight))
+			.saturating_add(T::DbWeight::get().writes(1 as Weight))
+	}
+	// Storage: PhalaMq OutboundMessages (r:0 w:1)
+	fn force_push_pallet_message(n: u32, ) -> Weight {
+		(13_000_000 as Weight)
+			.saturating_add((1_000 as Weight).saturating_mul(n as Weight))
+			.saturating_add(T::DbWeight::get().writes(1 as Weight))
+	}
+}
+
+// For backwards compatibility and tests
+impl WeightInfo for () {
+	fn sync_offchain_message(n: u32, ) -> Weight {
+		(68_000_000 as Weight)
+			.saturating_add((3_000 as Weight).saturating_mul(n as Weight))
+			.saturating_add(RocksDbWeight::get().reads(1 as Weight))
+			.saturating_add(RocksDbWeight::get().writes(2 as Weight))
+	}
+	fn push_message(n: u32, ) -> Weight {
+		(14_000_000 as Weight)
+			.saturating_add((1_000 as Weight).saturating_mul(n as Weight))
+			.saturating_add(RocksDbWeight::get().writes(1 as Weight))
+	}
+	fn force_push_pallet_message(n: u32, ) -> Weight {
+		(13_000_000 as Weight)
+			.saturating_add((1_000 as Weight).saturating_mul(n as Weight))
+			.saturating_add(RocksDbWeight::get().writes(1 as Weight))
+	}
+}
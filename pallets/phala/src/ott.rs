@@ -31,6 +31,9 @@ pub mod pallet {
 
 	const STORAGE_VERSION: StorageVersion = StorageVersion::new(1);
 
+	/// The execution weight of a balance transfer, excluding the storage accesses.
+	const TRANSFER_WEIGHT: Weight = 40_000_000;
+
 	#[pallet::pallet]
 	#[pallet::generate_store(pub(super) trait Store)]
 	#[pallet::storage_version(STORAGE_VERSION)]
@@ -62,7 +65,14 @@ pub mod pallet {
 	impl<T: Config> Pallet<T> {
 		/// Distributes some amounts to each specified accounts and mark the sender and destination
 		/// accounts as blacklisted.
-		#[pallet::weight(0)]
+		///
+		/// Charged for the blacklist checks and inserts, and a balance transfer per destination.
+		#[pallet::weight({
+			let n = transfers.len() as u64;
+			T::DbWeight::get()
+				.reads_writes(1 + 3 * n, 1 + 3 * n)
+				.saturating_add(TRANSFER_WEIGHT.saturating_mul(n))
+		})]
 		#[transactional]
 		pub fn distribute(
 			origin: OriginFor<T>,
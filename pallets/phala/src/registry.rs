@@ -13,14 +13,25 @@ pub mod pallet {
 	use frame_system::pallet_prelude::*;
 	use scale_info::TypeInfo;
 	use sp_core::{sr25519, H256};
-	use sp_runtime::SaturatedConversion;
+	use sp_runtime::{
+		traits::{Saturating, Zero},
+		SaturatedConversion,
+	};
 	use sp_std::prelude::*;
 	use sp_std::{convert::TryFrom, vec};
 
 	use crate::attestation::Error as AttestationError;
 	use crate::mq::MessageOriginInfo;
 	// Re-export
-	pub use crate::attestation::{Attestation, AttestationValidator, IasFields, IasValidator};
+	pub use crate::attestation::{
+		Attestation, AttestationValidator, IasFields, SgxQuoteCollateral, SgxValidator,
+	};
+	#[allow(deprecated)]
+	pub use crate::attestation::IasValidator;
+	#[cfg(feature = "runtime-benchmarks")]
+	pub use crate::attestation::benchmarking::BenchmarkIasValidator;
+
+	use super::WeightInfo;
 
 	use phala_types::{
 		messaging::{
@@ -46,6 +57,13 @@ pub mod pallet {
 		MasterPubkey {
 			master_pubkey: MasterPublicKey,
 		},
+		///	MessageOrigin::Worker -> Pallet
+		///
+		/// Sent by each gatekeeper once it switches to the rotated master key.
+		RotatedMasterKeyConfirmed {
+			rotation_id: u64,
+			master_pubkey: MasterPublicKey,
+		},
 	}
 
 	bind_topic!(GatekeeperRegistryEvent, b"^phala/registry/gk_event");
@@ -81,6 +99,16 @@ pub mod pallet {
 
 		/// Origin used to govern the pallet
 		type GovernanceOrigin: EnsureOrigin<Self::Origin>;
+
+		/// The number of blocks to wait for the gatekeepers to confirm a master key rotation
+		/// before it's requested again automatically
+		///
+		/// Zero disables the automatic retries.
+		#[pallet::constant]
+		type MasterKeyRotationRetryInterval: Get<Self::BlockNumber>;
+
+		/// Weight information for the extrinsics of this pallet.
+		type WeightInfo: WeightInfo;
 	}
 
 	const STORAGE_VERSION: StorageVersion = StorageVersion::new(5);
@@ -112,6 +140,13 @@ pub mod pallet {
 	#[pallet::storage]
 	pub type MasterKeyRotationLock<T: Config> = StorageValue<_, Option<u64>, ValueQuery>;
 
+	/// Progress of the latest master key rotation
+	///
+	/// Kept after the rotation is finished until the next rotation starts.
+	#[pallet::storage]
+	pub type MasterKeyRotationStatus<T: Config> =
+		StorageValue<_, MasterKeyRotationProgress<T::BlockNumber>>;
+
 	/// Mapping from worker pubkey to WorkerInfo
 	#[pallet::storage]
 	pub type Workers<T: Config> =
@@ -143,6 +178,12 @@ pub mod pallet {
 	#[pallet::storage]
 	pub type PRuntimeAddedAt<T: Config> = StorageMap<_, Twox64Concat, Vec<u8>, T::BlockNumber>;
 
+	/// The root certificates trusted to validate the DCAP quotes, in DER
+	///
+	/// Only the quotes certified by one of them can register.
+	#[pallet::storage]
+	pub type DcapTrustedRootCerts<T: Config> = StorageValue<_, Vec<Vec<u8>>, ValueQuery>;
+
 	/// Allow list of relaychain genesis
 	///
 	/// Only genesis within the list can do register.
@@ -180,6 +221,20 @@ pub mod pallet {
 			rotation_lock: Option<u64>,
 			gatekeeper_rotation_id: u64,
 		},
+		/// A gatekeeper has switched to the rotated master key
+		MasterKeyRotationConfirmed {
+			rotation_id: u64,
+			pubkey: WorkerPublicKey,
+		},
+		/// The master key rotation is requested to the gatekeepers again
+		MasterKeyRotationRetried {
+			rotation_id: u64,
+			retries: u32,
+		},
+		/// All the gatekeepers in the rotation have switched to the rotated master key
+		MasterKeyRotationCompleted {
+			rotation_id: u64,
+		},
 	}
 
 	#[pallet::error]
@@ -198,6 +253,17 @@ pub mod pallet {
 		BadIASReport,
 		OutdatedIASReport,
 		UnknownQuoteBodyFormat,
+		// DCAP related
+		UnsupportedDcapQuoteFormat,
+		InvalidDcapQuoteSignature,
+		InvalidDcapCertChain,
+		InvalidDcapCollateral,
+		OutdatedDcapCollateral,
+		RevokedDcapCert,
+		QuotingEnclaveRejected,
+		DebugEnclave,
+		DcapRootCertAlreadyExists,
+		DcapRootCertNotFound,
 		// Report validation
 		InvalidRuntimeInfoHash,
 		InvalidRuntimeInfo,
@@ -223,10 +289,39 @@ pub mod pallet {
 		CannotRemoveLastGatekeeper,
 		MasterKeyInRotation,
 		InvalidRotatedMasterPubkey,
+		NoPendingMasterKeyRotation,
+		InvalidRotationConfirmation,
 		// PRouter related
 		InvalidEndpointSigningTime,
 	}
 
+	#[pallet::hooks]
+	impl<T: Config> Hooks<T::BlockNumber> for Pallet<T>
+	where
+		T: crate::mq::Config,
+	{
+		fn on_initialize(n: T::BlockNumber) -> Weight {
+			let interval = T::MasterKeyRotationRetryInterval::get();
+			if interval.is_zero() {
+				return 0;
+			}
+			let weight = T::DbWeight::get().reads(1);
+			let status = match MasterKeyRotationStatus::<T>::get() {
+				Some(status) => status,
+				None => return weight,
+			};
+			if n.saturating_sub(status.last_requested_at) < interval
+				|| status.is_completed(&Gatekeeper::<T>::get())
+			{
+				return weight.saturating_add(T::DbWeight::get().reads(1));
+			}
+			if let Err(err) = Self::request_master_key_rotation_again(status) {
+				log::warn!("Failed to retry the master key rotation: {:?}", err);
+			}
+			weight.saturating_add(<T as Config>::WeightInfo::retry_master_key_rotation())
+		}
+	}
+
 	#[pallet::call]
 	impl<T: Config> Pallet<T>
 	where
@@ -235,7 +330,7 @@ pub mod pallet {
 		/// Sets [`BenchmarkDuration`]
 		///
 		/// Can only be called by `GovernanceOrigin`.
-		#[pallet::weight(10_000 + T::DbWeight::get().writes(1))]
+		#[pallet::weight(<T as Config>::WeightInfo::force_set_benchmark_duration())]
 		pub fn force_set_benchmark_duration(origin: OriginFor<T>, value: u32) -> DispatchResult {
 			T::GovernanceOrigin::ensure_origin(origin)?;
 			BenchmarkDuration::<T>::put(value);
@@ -245,7 +340,7 @@ pub mod pallet {
 		/// Force register a worker with the given pubkey with sudo permission
 		///
 		/// For test only.
-		#[pallet::weight(10_000 + T::DbWeight::get().writes(1))]
+		#[pallet::weight(<T as Config>::WeightInfo::force_register_worker())]
 		pub fn force_register_worker(
 			origin: OriginFor<T>,
 			pubkey: WorkerPublicKey,
@@ -278,7 +373,7 @@ pub mod pallet {
 		/// Force register a topic pubkey
 		///
 		/// For test only.
-		#[pallet::weight(10_000 + T::DbWeight::get().writes(1))]
+		#[pallet::weight(<T as Config>::WeightInfo::force_register_topic_pubkey())]
 		pub fn force_register_topic_pubkey(
 			origin: OriginFor<T>,
 			topic: Vec<u8>,
@@ -291,8 +386,14 @@ pub mod pallet {
 
 		/// Register a gatekeeper.
 		///
+		/// The new gatekeeper recovers the master key history from the shares sent by a threshold
+		/// of the existing gatekeepers. Each existing gatekeeper derives its share from the full
+		/// master key it holds, so the threshold only keeps the recovery going when some of them
+		/// are offline. It does not protect the master key: any single gatekeeper still holds the
+		/// whole key.
+		///
 		/// Can only be called by `GovernanceOrigin`.
-		#[pallet::weight(10_000 + T::DbWeight::get().writes(1))]
+		#[pallet::weight(<T as Config>::WeightInfo::register_gatekeeper())]
 		pub fn register_gatekeeper(
 			origin: OriginFor<T>,
 			gatekeeper: WorkerPublicKey,
@@ -337,7 +438,7 @@ pub mod pallet {
 		/// Unregister a gatekeeper
 		///
 		/// At least one gatekeeper should be available
-		#[pallet::weight(10_000 + T::DbWeight::get().writes(1))]
+		#[pallet::weight(<T as Config>::WeightInfo::unregister_gatekeeper())]
 		pub fn unregister_gatekeeper(
 			origin: OriginFor<T>,
 			gatekeeper: WorkerPublicKey,
@@ -358,14 +459,24 @@ pub mod pallet {
 				Error::<T>::CannotRemoveLastGatekeeper
 			);
 
+			let pending_rotation = MasterKeyRotationStatus::<T>::get()
+				.filter(|status| !status.is_completed(&gatekeepers));
 			gatekeepers.retain(|g| *g != gatekeeper);
+			// The rotation may only be waiting for the confirmation of the removed gatekeeper
+			if let Some(status) = pending_rotation {
+				if status.is_completed(&gatekeepers) {
+					Self::deposit_event(Event::<T>::MasterKeyRotationCompleted {
+						rotation_id: status.rotation_id,
+					});
+				}
+			}
 			Gatekeeper::<T>::put(gatekeepers);
 			Self::push_message(GatekeeperChange::gatekeeper_unregistered(gatekeeper));
 			Ok(())
 		}
 
 		/// Rotate the master key
-		#[pallet::weight(10_000 + T::DbWeight::get().writes(1))]
+		#[pallet::weight(<T as Config>::WeightInfo::rotate_master_key())]
 		pub fn rotate_master_key(origin: OriginFor<T>) -> DispatchResult {
 			T::GovernanceOrigin::ensure_origin(origin)?;
 
@@ -389,7 +500,17 @@ pub mod pallet {
 				*counter
 			});
 
+			let now = frame_system::Pallet::<T>::block_number();
 			MasterKeyRotationLock::<T>::put(Some(rotation_id));
+			MasterKeyRotationStatus::<T>::put(MasterKeyRotationProgress {
+				rotation_id,
+				started_at: now,
+				last_requested_at: now,
+				gatekeepers,
+				confirmed: Vec::new(),
+				master_pubkey: None,
+				retries: 0,
+			});
 			Self::push_message(GatekeeperLaunch::rotate_master_key(
 				rotation_id,
 				gk_identities,
@@ -397,11 +518,33 @@ pub mod pallet {
 			Ok(())
 		}
 
+		/// Request the ongoing master key rotation to the gatekeepers again
+		///
+		/// The gatekeepers resend the missing chunks of their contributions, and the ones that have
+		/// switched to the rotated master key confirm it again. It can be called until all the
+		/// gatekeepers in the rotation still registered have confirmed.
+		///
+		/// The rotation is also requested again automatically every
+		/// `MasterKeyRotationRetryInterval` blocks until then.
+		///
+		/// Can only be called by `GovernanceOrigin`.
+		#[pallet::weight(<T as Config>::WeightInfo::retry_master_key_rotation())]
+		pub fn retry_master_key_rotation(origin: OriginFor<T>) -> DispatchResult {
+			T::GovernanceOrigin::ensure_origin(origin)?;
+
+			let status = MasterKeyRotationStatus::<T>::get()
+				.ok_or(Error::<T>::NoPendingMasterKeyRotation)?;
+			Self::request_master_key_rotation_again(status)
+		}
+
 		/// Registers a worker on the blockchain
 		///
 		/// Usually called by a bridging relayer program (`pherry` and `prb`). Can be called by
 		/// anyone on behalf of a worker.
-		#[pallet::weight(0)]
+		#[pallet::weight(match attestation {
+			Attestation::SgxIas { .. } => <T as Config>::WeightInfo::register_worker(),
+			Attestation::SgxDcap { .. } => <T as Config>::WeightInfo::register_worker_dcap(),
+		})]
 		pub fn register_worker(
 			origin: OriginFor<T>,
 			pruntime_info: WorkerRegistrationInfo<T::AccountId>,
@@ -417,6 +560,7 @@ pub mod pallet {
 				now,
 				T::VerifyPRuntime::get(),
 				PRuntimeAllowList::<T>::get(),
+				DcapTrustedRootCerts::<T>::get(),
 			)
 			.map_err(Into::<Error<T>>::into)?;
 
@@ -482,7 +626,7 @@ pub mod pallet {
 			Ok(())
 		}
 
-		#[pallet::weight(0)]
+		#[pallet::weight(<T as Config>::WeightInfo::update_worker_endpoint())]
 		pub fn update_worker_endpoint(
 			origin: OriginFor<T>,
 			endpoint_payload: WorkerEndpointPayload,
@@ -527,7 +671,7 @@ pub mod pallet {
 		/// Registers a pruntime binary to [`PRuntimeAllowList`]
 		///
 		/// Can only be called by `GovernanceOrigin`.
-		#[pallet::weight(0)]
+		#[pallet::weight(<T as Config>::WeightInfo::add_pruntime())]
 		pub fn add_pruntime(origin: OriginFor<T>, pruntime_hash: Vec<u8>) -> DispatchResult {
 			T::GovernanceOrigin::ensure_origin(origin)?;
 
@@ -549,7 +693,7 @@ pub mod pallet {
 		/// Removes a pruntime binary from [`PRuntimeAllowList`]
 		///
 		/// Can only be called by `GovernanceOrigin`.
-		#[pallet::weight(0)]
+		#[pallet::weight(<T as Config>::WeightInfo::remove_pruntime())]
 		pub fn remove_pruntime(origin: OriginFor<T>, pruntime_hash: Vec<u8>) -> DispatchResult {
 			T::GovernanceOrigin::ensure_origin(origin)?;
 
@@ -567,10 +711,48 @@ pub mod pallet {
 			Ok(())
 		}
 
+		/// Adds a DER encoded root certificate to [`DcapTrustedRootCerts`]
+		///
+		/// Can only be called by `GovernanceOrigin`.
+		#[pallet::weight(<T as Config>::WeightInfo::add_dcap_trusted_root_cert())]
+		pub fn add_dcap_trusted_root_cert(origin: OriginFor<T>, cert: Vec<u8>) -> DispatchResult {
+			T::GovernanceOrigin::ensure_origin(origin)?;
+
+			let mut certs = DcapTrustedRootCerts::<T>::get();
+			ensure!(
+				!certs.contains(&cert),
+				Error::<T>::DcapRootCertAlreadyExists
+			);
+
+			certs.push(cert);
+			DcapTrustedRootCerts::<T>::put(certs);
+
+			Ok(())
+		}
+
+		/// Removes a root certificate from [`DcapTrustedRootCerts`]
+		///
+		/// Can only be called by `GovernanceOrigin`.
+		#[pallet::weight(<T as Config>::WeightInfo::remove_dcap_trusted_root_cert())]
+		pub fn remove_dcap_trusted_root_cert(
+			origin: OriginFor<T>,
+			cert: Vec<u8>,
+		) -> DispatchResult {
+			T::GovernanceOrigin::ensure_origin(origin)?;
+
+			let mut certs = DcapTrustedRootCerts::<T>::get();
+			ensure!(certs.contains(&cert), Error::<T>::DcapRootCertNotFound);
+
+			certs.retain(|c| *c != cert);
+			DcapTrustedRootCerts::<T>::put(certs);
+
+			Ok(())
+		}
+
 		/// Adds an entry in [`RelaychainGenesisBlockHashAllowList`]
 		///
 		/// Can only be called by `GovernanceOrigin`.
-		#[pallet::weight(0)]
+		#[pallet::weight(<T as Config>::WeightInfo::add_relaychain_genesis_block_hash())]
 		pub fn add_relaychain_genesis_block_hash(
 			origin: OriginFor<T>,
 			genesis_block_hash: H256,
@@ -592,7 +774,7 @@ pub mod pallet {
 		/// Deletes an entry in [`RelaychainGenesisBlockHashAllowList`]
 		///
 		/// Can only be called by `GovernanceOrigin`.
-		#[pallet::weight(0)]
+		#[pallet::weight(<T as Config>::WeightInfo::remove_relaychain_genesis_block_hash())]
 		pub fn remove_relaychain_genesis_block_hash(
 			origin: OriginFor<T>,
 			genesis_block_hash: H256,
@@ -708,6 +890,36 @@ pub mod pallet {
 						}
 					}
 				}
+				RegistryEvent::RotatedMasterKeyConfirmed {
+					rotation_id,
+					master_pubkey,
+				} => {
+					let mut status = MasterKeyRotationStatus::<T>::get()
+						.filter(|status| status.rotation_id == rotation_id)
+						.ok_or(Error::<T>::InvalidRotationConfirmation)?;
+					ensure!(
+						status.gatekeepers.contains(worker_pubkey),
+						Error::<T>::InvalidGatekeeper
+					);
+					if let Some(saved_pubkey) = status.master_pubkey {
+						ensure!(
+							saved_pubkey == master_pubkey,
+							Error::<T>::MasterKeyMismatch // Oops, this is really bad
+						);
+					}
+					if status.confirmed.iter().any(|(gk, _)| gk == worker_pubkey) {
+						return Ok(());
+					}
+					status.confirmed.push((*worker_pubkey, master_pubkey));
+					Self::deposit_event(Event::<T>::MasterKeyRotationConfirmed {
+						rotation_id,
+						pubkey: *worker_pubkey,
+					});
+					if status.is_completed(&Gatekeeper::<T>::get()) {
+						Self::deposit_event(Event::<T>::MasterKeyRotationCompleted { rotation_id });
+					}
+					MasterKeyRotationStatus::<T>::put(status);
+				}
 			}
 			Ok(())
 		}
@@ -740,12 +952,66 @@ pub mod pallet {
 						master_pubkey,
 					});
 					Self::push_message(GatekeeperLaunch::master_pubkey_rotated(master_pubkey));
+
+					MasterKeyRotationStatus::<T>::mutate(|status| {
+						if let Some(status) = status.as_mut() {
+							// Only the confirmations of the rotated master pubkey count
+							status.master_pubkey = Some(master_pubkey);
+							status
+								.confirmed
+								.retain(|(_, pubkey)| *pubkey == master_pubkey);
+							if status.is_completed(&Gatekeeper::<T>::get()) {
+								Self::deposit_event(Event::<T>::MasterKeyRotationCompleted {
+									rotation_id,
+								});
+							}
+						}
+					});
 				}
 			}
 			Ok(())
 		}
 
-		#[cfg(test)]
+		/// Requests the rotation to the gatekeepers in it still registered again
+		fn request_master_key_rotation_again(
+			mut status: MasterKeyRotationProgress<T::BlockNumber>,
+		) -> DispatchResult {
+			let gatekeepers = Gatekeeper::<T>::get();
+			ensure!(
+				!status.is_completed(&gatekeepers),
+				Error::<T>::NoPendingMasterKeyRotation
+			);
+
+			let gk_identities = status
+				.gatekeepers
+				.iter()
+				.filter(|gk| gatekeepers.contains(*gk))
+				.map(|gk| {
+					let worker_info = Workers::<T>::get(gk).ok_or(Error::<T>::WorkerNotFound)?;
+					Ok(WorkerIdentity {
+						pubkey: worker_info.pubkey,
+						ecdh_pubkey: worker_info.ecdh_pubkey,
+					})
+				})
+				.collect::<Result<Vec<WorkerIdentity>, Error<T>>>()?;
+
+			status.retries += 1;
+			status.last_requested_at = frame_system::Pallet::<T>::block_number();
+			let rotation_id = status.rotation_id;
+			let retries = status.retries;
+			MasterKeyRotationStatus::<T>::put(status);
+			Self::push_message(GatekeeperLaunch::rotate_master_key(
+				rotation_id,
+				gk_identities,
+			));
+			Self::deposit_event(Event::<T>::MasterKeyRotationRetried {
+				rotation_id,
+				retries,
+			});
+			Ok(())
+		}
+
+		#[cfg(any(test, feature = "runtime-benchmarks"))]
 		pub(crate) fn internal_set_benchmark(worker: &WorkerPublicKey, score: Option<u32>) {
 			Workers::<T>::mutate(worker, |w| {
 				if let Some(w) = w {
@@ -872,6 +1138,38 @@ pub mod pallet {
 		pub features: Vec<u32>,
 	}
 
+	/// The progress of a master key rotation
+	#[derive(Encode, Decode, TypeInfo, Debug, Clone, PartialEq, Eq)]
+	pub struct MasterKeyRotationProgress<BlockNumber> {
+		pub rotation_id: u64,
+		/// The block number when the rotation is requested
+		pub started_at: BlockNumber,
+		/// The block number when the rotation is requested the last time, including the retries
+		pub last_requested_at: BlockNumber,
+		/// The gatekeepers taking part in the rotation
+		pub gatekeepers: Vec<WorkerPublicKey>,
+		/// The gatekeepers switched to the rotated master key and the pubkeys they confirm
+		pub confirmed: Vec<(WorkerPublicKey, MasterPublicKey)>,
+		/// The rotated master pubkey, available once the gatekeepers report it
+		pub master_pubkey: Option<MasterPublicKey>,
+		/// The number of times the rotation has been requested again
+		pub retries: u32,
+	}
+
+	impl<BlockNumber> MasterKeyRotationProgress<BlockNumber> {
+		/// Whether all the gatekeepers in the rotation have confirmed the rotated master key
+		///
+		/// The gatekeepers not in `registered` any more are not waited for.
+		pub fn is_completed(&self, registered: &[WorkerPublicKey]) -> bool {
+			self.master_pubkey.is_some()
+				&& self
+					.gatekeepers
+					.iter()
+					.filter(|gk| registered.contains(*gk))
+					.all(|gk| self.confirmed.iter().any(|(confirmed, _)| confirmed == gk))
+		}
+	}
+
 	impl<T: Config> From<AttestationError> for Error<T> {
 		fn from(err: AttestationError) -> Self {
 			match err {
@@ -883,6 +1181,14 @@ pub mod pallet {
 				AttestationError::OutdatedIASReport => Self::OutdatedIASReport,
 				AttestationError::UnknownQuoteBodyFormat => Self::UnknownQuoteBodyFormat,
 				AttestationError::InvalidUserDataHash => Self::InvalidRuntimeInfoHash,
+				AttestationError::UnsupportedDcapQuoteFormat => Self::UnsupportedDcapQuoteFormat,
+				AttestationError::InvalidDcapQuoteSignature => Self::InvalidDcapQuoteSignature,
+				AttestationError::InvalidDcapCertChain => Self::InvalidDcapCertChain,
+				AttestationError::InvalidDcapCollateral => Self::InvalidDcapCollateral,
+				AttestationError::OutdatedDcapCollateral => Self::OutdatedDcapCollateral,
+				AttestationError::RevokedDcapCert => Self::RevokedDcapCert,
+				AttestationError::QuotingEnclaveRejected => Self::QuotingEnclaveRejected,
+				AttestationError::DebugEnclave => Self::DebugEnclave,
 			}
 		}
 	}
@@ -1032,5 +1338,235 @@ pub mod pallet {
 				assert_eq!(RelaychainGenesisBlockHashAllowList::<Test>::get().len(), 0);
 			});
 		}
+
+		#[test]
+		fn test_dcap_trusted_root_certs_works() {
+			new_test_ext().execute_with(|| {
+				set_block_1();
+
+				let sample: Vec<u8> = [1, 2, 3, 4].to_vec();
+				assert_ok!(PhalaRegistry::add_dcap_trusted_root_cert(
+					Origin::root(),
+					sample.clone()
+				));
+				assert_noop!(
+					PhalaRegistry::add_dcap_trusted_root_cert(Origin::root(), sample.clone()),
+					Error::<Test>::DcapRootCertAlreadyExists
+				);
+				assert_eq!(DcapTrustedRootCerts::<Test>::get(), vec![sample.clone()]);
+				assert_ok!(PhalaRegistry::remove_dcap_trusted_root_cert(
+					Origin::root(),
+					sample.clone()
+				));
+				assert_noop!(
+					PhalaRegistry::remove_dcap_trusted_root_cert(Origin::root(), sample.clone()),
+					Error::<Test>::DcapRootCertNotFound
+				);
+				assert!(DcapTrustedRootCerts::<Test>::get().is_empty());
+			});
+		}
+
+		#[test]
+		fn test_master_key_rotation_progress() {
+			use crate::mock::{setup_workers, take_events, take_messages, Event as TestEvent};
+			use phala_types::messaging::Topic;
+
+			fn confirm(sender: WorkerPublicKey, rotation_id: u64, pubkey: u8) -> DispatchResult {
+				PhalaRegistry::on_message_received(DecodedMessage::<RegistryEvent> {
+					sender: MessageOrigin::Worker(sender),
+					destination: Topic::new(*b"^phala/registry/event"),
+					payload: RegistryEvent::RotatedMasterKeyConfirmed {
+						rotation_id,
+						master_pubkey: MasterPublicKey::from_raw([pubkey; 32]),
+					},
+				})
+			}
+
+			new_test_ext().execute_with(|| {
+				set_block_1();
+				setup_workers(2);
+				let genesis_gatekeeper = WorkerPublicKey::from_raw([0u8; 32]);
+				GatekeeperMasterPubkey::<Test>::put(MasterPublicKey::from_raw([1u8; 32]));
+				assert_ok!(PhalaRegistry::register_gatekeeper(
+					Origin::root(),
+					worker_pubkey(1)
+				));
+				assert_noop!(
+					PhalaRegistry::retry_master_key_rotation(Origin::root()),
+					Error::<Test>::NoPendingMasterKeyRotation
+				);
+
+				assert_ok!(PhalaRegistry::rotate_master_key(Origin::root()));
+				assert_eq!(
+					MasterKeyRotationStatus::<Test>::get(),
+					Some(MasterKeyRotationProgress {
+						rotation_id: 1,
+						started_at: 1,
+						last_requested_at: 1,
+						gatekeepers: vec![genesis_gatekeeper, worker_pubkey(1)],
+						confirmed: vec![],
+						master_pubkey: None,
+						retries: 0,
+					})
+				);
+				// Retry with the same rotation id
+				let _ = take_messages();
+				let _ = take_events();
+				assert_ok!(PhalaRegistry::retry_master_key_rotation(Origin::root()));
+				assert_eq!(take_messages().len(), 1);
+				assert_eq!(
+					take_events().as_slice(),
+					[TestEvent::PhalaRegistry(Event::MasterKeyRotationRetried {
+						rotation_id: 1,
+						retries: 1,
+					})]
+				);
+
+				// Only the gatekeepers in the ongoing rotation can confirm
+				assert_noop!(
+					confirm(worker_pubkey(2), 1, 2),
+					Error::<Test>::InvalidGatekeeper
+				);
+				assert_noop!(
+					confirm(worker_pubkey(1), 2, 2),
+					Error::<Test>::InvalidRotationConfirmation
+				);
+				// A confirmation before the rotated pubkey is on chain only counts if it matches
+				assert_ok!(confirm(worker_pubkey(1), 1, 3));
+				assert_ok!(PhalaRegistry::on_gk_message_received(DecodedMessage::<
+					GatekeeperRegistryEvent,
+				> {
+					sender: MessageOrigin::Gatekeeper,
+					destination: Topic::new(*b"^phala/registry/gk_event"),
+					payload: GatekeeperRegistryEvent::RotatedMasterPubkey {
+						rotation_id: 1,
+						master_pubkey: MasterPublicKey::from_raw([2u8; 32]),
+					},
+				}));
+				assert_eq!(MasterKeyRotationLock::<Test>::get(), None);
+				let status = MasterKeyRotationStatus::<Test>::get().unwrap();
+				assert_eq!(
+					status.master_pubkey,
+					Some(MasterPublicKey::from_raw([2u8; 32]))
+				);
+				assert!(status.confirmed.is_empty());
+				assert_noop!(
+					confirm(genesis_gatekeeper, 1, 3),
+					Error::<Test>::MasterKeyMismatch
+				);
+
+				// The missing confirmations can still be retried after the lock is released
+				assert_ok!(confirm(genesis_gatekeeper, 1, 2));
+				assert_ok!(PhalaRegistry::retry_master_key_rotation(Origin::root()));
+				let _ = take_events();
+				assert_ok!(confirm(worker_pubkey(1), 1, 2));
+				assert_eq!(
+					take_events().as_slice(),
+					[
+						TestEvent::PhalaRegistry(Event::MasterKeyRotationConfirmed {
+							rotation_id: 1,
+							pubkey: worker_pubkey(1),
+						}),
+						TestEvent::PhalaRegistry(Event::MasterKeyRotationCompleted {
+							rotation_id: 1
+						}),
+					]
+				);
+				assert!(MasterKeyRotationStatus::<Test>::get()
+					.unwrap()
+					.is_completed(&Gatekeeper::<Test>::get()));
+				assert_noop!(
+					PhalaRegistry::retry_master_key_rotation(Origin::root()),
+					Error::<Test>::NoPendingMasterKeyRotation
+				);
+			});
+		}
+
+		#[test]
+		fn test_master_key_rotation_retried_until_registered_gatekeepers_confirm() {
+			use crate::mock::{
+				setup_workers, take_events, take_messages, Event as TestEvent,
+				MasterKeyRotationRetryInterval, System,
+			};
+			use frame_support::traits::Hooks;
+			use phala_types::messaging::Topic;
+
+			new_test_ext().execute_with(|| {
+				set_block_1();
+				setup_workers(1);
+				let genesis_gatekeeper = WorkerPublicKey::from_raw([0u8; 32]);
+				let master_pubkey = MasterPublicKey::from_raw([2u8; 32]);
+				assert_ok!(PhalaRegistry::register_gatekeeper(
+					Origin::root(),
+					worker_pubkey(1)
+				));
+				assert_ok!(PhalaRegistry::rotate_master_key(Origin::root()));
+				let _ = take_messages();
+				let _ = take_events();
+
+				// Not retried before the interval elapses
+				let interval = MasterKeyRotationRetryInterval::get();
+				System::set_block_number(interval);
+				PhalaRegistry::on_initialize(interval);
+				assert!(take_messages().is_empty());
+				System::set_block_number(1 + interval);
+				PhalaRegistry::on_initialize(1 + interval);
+				assert_eq!(take_messages().len(), 1);
+				assert_eq!(
+					take_events().as_slice(),
+					[TestEvent::PhalaRegistry(Event::MasterKeyRotationRetried {
+						rotation_id: 1,
+						retries: 1,
+					})]
+				);
+
+				// The rotated pubkey is on chain, but only the genesis gatekeeper confirms it
+				assert_ok!(PhalaRegistry::on_gk_message_received(DecodedMessage::<
+					GatekeeperRegistryEvent,
+				> {
+					sender: MessageOrigin::Gatekeeper,
+					destination: Topic::new(*b"^phala/registry/gk_event"),
+					payload: GatekeeperRegistryEvent::RotatedMasterPubkey {
+						rotation_id: 1,
+						master_pubkey,
+					},
+				}));
+				assert_ok!(PhalaRegistry::on_message_received(DecodedMessage::<
+					RegistryEvent,
+				> {
+					sender: MessageOrigin::Worker(genesis_gatekeeper),
+					destination: Topic::new(*b"^phala/registry/event"),
+					payload: RegistryEvent::RotatedMasterKeyConfirmed {
+						rotation_id: 1,
+						master_pubkey,
+					},
+				}));
+				let _ = take_messages();
+				let _ = take_events();
+
+				// The unregistered gatekeeper is not waited for
+				assert_ok!(PhalaRegistry::unregister_gatekeeper(
+					Origin::root(),
+					worker_pubkey(1)
+				));
+				assert!(take_events().contains(&TestEvent::PhalaRegistry(
+					Event::MasterKeyRotationCompleted { rotation_id: 1 }
+				)));
+				let _ = take_messages();
+				System::set_block_number(1 + 2 * interval);
+				PhalaRegistry::on_initialize(1 + 2 * interval);
+				assert!(take_messages().is_empty());
+				assert_noop!(
+					PhalaRegistry::retry_master_key_rotation(Origin::root()),
+					Error::<Test>::NoPendingMasterKeyRotation
+				);
+			});
+		}
 	}
 }
+
+pub mod weights;
+pub use weights::WeightInfo;
+
+#[cfg(feature = "runtime-benchmarks")]
+pub(crate) mod benchmarking;
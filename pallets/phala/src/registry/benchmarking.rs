@@ -0,0 +1,266 @@
+//! Benchmarks of the registry pallet
+
+use super::*;
+
+use crate::attestation::benchmarking::{
+	sample_attestation, sample_dcap_attestation, PRUNTIME_HASH,
+};
+use codec::Encode;
+use frame_benchmarking::{benchmarks, whitelisted_caller};
+use frame_support::traits::EnsureOrigin;
+use frame_system::RawOrigin;
+use phala_types::{
+	worker_endpoint_v1::{EndpointInfo, WorkerEndpoint},
+	EcdhPublicKey, VersionedWorkerEndpoints, WorkerEndpointPayload, WorkerPublicKey,
+	WorkerRegistrationInfo,
+};
+use sp_core::{crypto::KeyTypeId, H256};
+use sp_std::{vec, vec::Vec};
+
+/// The key type used to sign the payloads in the benchmarks.
+const KEY_TYPE: KeyTypeId = KeyTypeId(*b"phrg");
+
+/// The number of gatekeepers in the benchmarks walking through the gatekeeper list.
+const GATEKEEPERS: u32 = 10;
+
+/// The number of entries in the allowlists in the benchmarks.
+const ALLOWLIST_LEN: u32 = 100;
+
+/// The initial score of the workers registered by [`register_worker`].
+pub(crate) const INITIAL_SCORE: u32 = 100;
+
+/// An arbitrary time in the benchmarks, in milliseconds.
+const NOW_MS: u64 = 1_660_000_000_000;
+
+/// Returns a worker pubkey derived from `index`.
+pub(crate) fn worker_pubkey(index: u32) -> WorkerPublicKey {
+	let mut raw = [0u8; 32];
+	raw[..4].copy_from_slice(&index.to_be_bytes());
+	raw[31] = 0xbe; // distinguish with the genesis config
+	WorkerPublicKey::from_raw(raw)
+}
+
+/// Registers a benchmarked worker operated by `operator`.
+pub(crate) fn register_worker<T: Config>(pubkey: WorkerPublicKey, operator: Option<T::AccountId>) {
+	Workers::<T>::insert(
+		&pubkey,
+		WorkerInfo {
+			pubkey,
+			ecdh_pubkey: EcdhPublicKey(pubkey.0),
+			runtime_version: 0,
+			last_updated: 0,
+			operator,
+			confidence_level: 1,
+			initial_score: Some(INITIAL_SCORE),
+			features: vec![1, 4],
+		},
+	);
+}
+
+/// Sets up `n` gatekeepers with the master key uploaded.
+fn setup_gatekeepers<T: Config>(n: u32) -> Vec<WorkerPublicKey> {
+	let gatekeepers: Vec<_> = (0..n).map(worker_pubkey).collect();
+	for gk in gatekeepers.iter() {
+		register_worker::<T>(*gk, None);
+	}
+	Gatekeeper::<T>::put(gatekeepers.clone());
+	GatekeeperMasterPubkey::<T>::put(worker_pubkey(u32::MAX));
+	gatekeepers
+}
+
+fn pruntime_hash(index: u32) -> Vec<u8> {
+	let mut hash = vec![0u8; 68];
+	hash[..4].copy_from_slice(&index.to_be_bytes());
+	hash
+}
+
+/// Sets up the allowlists for a worker running `hash` to register.
+fn setup_register_worker<T: Config>(
+	hash: Vec<u8>,
+) -> (T::AccountId, WorkerRegistrationInfo<T::AccountId>) {
+	let caller: T::AccountId = whitelisted_caller();
+	let genesis_block_hash = H256::repeat_byte(1);
+	let mut pruntimes: Vec<_> = (1..ALLOWLIST_LEN).map(pruntime_hash).collect();
+	pruntimes.push(hash);
+	PRuntimeAllowList::<T>::put(pruntimes);
+	let mut genesis_hashes: Vec<_> = (1..ALLOWLIST_LEN)
+		.map(|i| H256::from_low_u64_be(i as u64))
+		.collect();
+	genesis_hashes.push(genesis_block_hash);
+	RelaychainGenesisBlockHashAllowList::<T>::put(genesis_hashes);
+	let pubkey = worker_pubkey(1);
+	let pruntime_info = WorkerRegistrationInfo::<T::AccountId> {
+		version: 1,
+		machine_id: vec![0u8; 16],
+		pubkey,
+		ecdh_pubkey: EcdhPublicKey(pubkey.0),
+		genesis_block_hash,
+		features: vec![1, 4],
+		operator: Some(caller.clone()),
+	};
+	(caller, pruntime_info)
+}
+
+fn set_now<T: pallet_timestamp::Config<Moment = u64>>(now: u64) {
+	pallet_timestamp::Pallet::<T>::set_timestamp(now);
+}
+
+benchmarks! {
+	where_clause { where
+		T: crate::mq::Config,
+		T: pallet_timestamp::Config<Moment = u64>,
+	}
+
+	force_set_benchmark_duration {
+		let origin = T::GovernanceOrigin::successful_origin();
+	}: _<T::Origin>(origin, 50)
+	verify {
+		assert_eq!(BenchmarkDuration::<T>::get(), Some(50));
+	}
+
+	force_register_worker {
+		let pubkey = worker_pubkey(1);
+		let operator: T::AccountId = whitelisted_caller();
+	}: _(RawOrigin::Root, pubkey, EcdhPublicKey(pubkey.0), Some(operator))
+	verify {
+		assert!(Workers::<T>::contains_key(&pubkey));
+	}
+
+	force_register_topic_pubkey {
+		let topic = b"^phala/benchmarking/topic".to_vec();
+	}: _(RawOrigin::Root, topic.clone(), vec![0u8; 32])
+	verify {
+		assert!(TopicKey::<T>::contains_key(&topic));
+	}
+
+	register_gatekeeper {
+		setup_gatekeepers::<T>(GATEKEEPERS - 1);
+		let gatekeeper = worker_pubkey(GATEKEEPERS);
+		register_worker::<T>(gatekeeper, None);
+		let origin = T::GovernanceOrigin::successful_origin();
+	}: _<T::Origin>(origin, gatekeeper)
+	verify {
+		assert!(Gatekeeper::<T>::get().contains(&gatekeeper));
+	}
+
+	unregister_gatekeeper {
+		let gatekeepers = setup_gatekeepers::<T>(GATEKEEPERS);
+		let gatekeeper = gatekeepers[gatekeepers.len() - 1];
+		let origin = T::GovernanceOrigin::successful_origin();
+	}: _<T::Origin>(origin, gatekeeper)
+	verify {
+		assert!(!Gatekeeper::<T>::get().contains(&gatekeeper));
+	}
+
+	rotate_master_key {
+		setup_gatekeepers::<T>(GATEKEEPERS);
+		let origin = T::GovernanceOrigin::successful_origin();
+	}: _<T::Origin>(origin)
+	verify {
+		assert!(MasterKeyRotationLock::<T>::get().is_some());
+	}
+
+	retry_master_key_rotation {
+		setup_gatekeepers::<T>(GATEKEEPERS);
+		let origin = T::GovernanceOrigin::successful_origin();
+		Pallet::<T>::rotate_master_key(origin.clone())?;
+	}: _<T::Origin>(origin)
+	verify {
+		assert_eq!(MasterKeyRotationStatus::<T>::get().map(|status| status.retries), Some(1));
+	}
+
+	register_worker {
+		let hash = hex::decode(PRUNTIME_HASH).expect("Bad pruntime hash");
+		let (caller, pruntime_info) = setup_register_worker::<T>(hash);
+		let pubkey = pruntime_info.pubkey;
+	}: _(RawOrigin::Signed(caller), pruntime_info, sample_attestation())
+	verify {
+		assert!(Workers::<T>::contains_key(&pubkey));
+	}
+
+	register_worker_dcap {
+		let (attestation, root_cert, hash) = sample_dcap_attestation();
+		DcapTrustedRootCerts::<T>::put(vec![root_cert]);
+		let (caller, pruntime_info) = setup_register_worker::<T>(hash);
+		let pubkey = pruntime_info.pubkey;
+	}: register_worker(RawOrigin::Signed(caller), pruntime_info, attestation)
+	verify {
+		assert!(Workers::<T>::contains_key(&pubkey));
+	}
+
+	update_worker_endpoint {
+		let caller: T::AccountId = whitelisted_caller();
+		let pubkey = sp_io::crypto::sr25519_generate(KEY_TYPE, None);
+		register_worker::<T>(pubkey, None);
+		set_now::<T>(NOW_MS);
+		let endpoint_payload = WorkerEndpointPayload {
+			pubkey,
+			versioned_endpoints: VersionedWorkerEndpoints::V1(vec![EndpointInfo {
+				endpoint: WorkerEndpoint::I2P(vec![b'x'; 516]),
+			}]),
+			signing_time: NOW_MS - 1000,
+		};
+		let signature = sp_io::crypto::sr25519_sign(KEY_TYPE, &pubkey, &endpoint_payload.encode())
+			.expect("The key was just generated; qed.");
+	}: _(RawOrigin::Signed(caller), endpoint_payload, signature.0.to_vec())
+	verify {
+		assert!(Endpoints::<T>::contains_key(&pubkey));
+	}
+
+	add_pruntime {
+		PRuntimeAllowList::<T>::put((1..ALLOWLIST_LEN).map(pruntime_hash).collect::<Vec<_>>());
+		let origin = T::GovernanceOrigin::successful_origin();
+	}: _<T::Origin>(origin, pruntime_hash(ALLOWLIST_LEN))
+	verify {
+		assert!(PRuntimeAddedAt::<T>::contains_key(pruntime_hash(ALLOWLIST_LEN)));
+	}
+
+	remove_pruntime {
+		PRuntimeAllowList::<T>::put((1..=ALLOWLIST_LEN).map(pruntime_hash).collect::<Vec<_>>());
+		let origin = T::GovernanceOrigin::successful_origin();
+	}: _<T::Origin>(origin, pruntime_hash(ALLOWLIST_LEN))
+	verify {
+		assert!(!PRuntimeAllowList::<T>::get().contains(&pruntime_hash(ALLOWLIST_LEN)));
+	}
+
+	add_dcap_trusted_root_cert {
+		let origin = T::GovernanceOrigin::successful_origin();
+		let (_, root_cert, _) = sample_dcap_attestation();
+	}: _<T::Origin>(origin, root_cert.clone())
+	verify {
+		assert!(DcapTrustedRootCerts::<T>::get().contains(&root_cert));
+	}
+
+	remove_dcap_trusted_root_cert {
+		let origin = T::GovernanceOrigin::successful_origin();
+		let (_, root_cert, _) = sample_dcap_attestation();
+		DcapTrustedRootCerts::<T>::put(vec![root_cert.clone()]);
+	}: _<T::Origin>(origin, root_cert.clone())
+	verify {
+		assert!(!DcapTrustedRootCerts::<T>::get().contains(&root_cert));
+	}
+
+	add_relaychain_genesis_block_hash {
+		RelaychainGenesisBlockHashAllowList::<T>::put(
+			(1..ALLOWLIST_LEN).map(|i| H256::from_low_u64_be(i as u64)).collect::<Vec<_>>()
+		);
+		let hash = H256::from_low_u64_be(ALLOWLIST_LEN as u64);
+		let origin = T::GovernanceOrigin::successful_origin();
+	}: _<T::Origin>(origin, hash)
+	verify {
+		assert!(RelaychainGenesisBlockHashAllowList::<T>::get().contains(&hash));
+	}
+
+	remove_relaychain_genesis_block_hash {
+		RelaychainGenesisBlockHashAllowList::<T>::put(
+			(1..=ALLOWLIST_LEN).map(|i| H256::from_low_u64_be(i as u64)).collect::<Vec<_>>()
+		);
+		let hash = H256::from_low_u64_be(ALLOWLIST_LEN as u64);
+		let origin = T::GovernanceOrigin::successful_origin();
+	}: _<T::Origin>(origin, hash)
+	verify {
+		assert!(!RelaychainGenesisBlockHashAllowList::<T>::get().contains(&hash));
+	}
+
+	impl_benchmark_test_suite!(Pallet, crate::mock::new_bench_ext(), crate::mock::Test);
+}
@@ -0,0 +1,243 @@
+//! Weights for pallet_registry
+//!
+//! PLACEHOLDER WEIGHTS, NOT GENERATED BY THE BENCHMARK CLI.
+//!
+//! The values are hand-estimated from the storage accesses of each call and have never been
+//! measured. They must be regenerated on the reference machine with
+//! `scripts/benchmark-pallets.sh registry` before being relied on.
+
+#![cfg_attr(rustfmt, rustfmt_skip)]
+#![allow(unused_parens)]
+#![allow(unused_imports)]
+
+use frame_support::{traits::Get, weights::{Weight, constants::RocksDbWeight}};
+use sp_std::marker::PhantomData;
+
+/// Weight functions needed for pallet_registry.
+pub trait WeightInfo {
+	fn force_set_benchmark_duration() -> Weight;
+	fn force_register_worker() -> Weight;
+	fn force_register_topic_pubkey() -> Weight;
+	fn register_gatekeeper() -> Weight;
+	fn unregister_gatekeeper() -> Weight;
+	fn rotate_master_key() -> Weight;
+	fn retry_master_key_rotation() -> Weight;
+	fn register_worker() -> Weight;
+	fn register_worker_dcap() -> Weight;
+	fn update_worker_endpoint() -> Weight;
+	fn add_pruntime() -> Weight;
+	fn remove_pruntime() -> Weight;
+	fn add_dcap_trusted_root_cert() -> Weight;
+	fn remove_dcap_trusted_root_cert() -> Weight;
+	fn add_relaychain_genesis_block_hash() -> Weight;
+	fn remove_relaychain_genesis_block_hash() -> Weight;
+}
+
+/// Weights for pallet_registry using the Substrate node and recommended hardware.
+pub struct SubstrateWeight<T>(PhantomData<T>);
+impl<T: frame_system::Config> WeightInfo for SubstrateWeight<T> {
+	// Storage: PhalaRegistry BenchmarkDuration (r:0 w:1)
+	fn force_set_benchmark_duration() -> Weight {
+		(10_000_000 as Weight)
+			.saturating_add(T::DbWeight::get().writes(1 as Weight))
+	}
+	// Storage: PhalaRegistry Workers (r:0 w:1)
+	// Storage: PhalaMq OutboundMessages (r:0 w:1)
+	fn force_register_worker() -> Weight {
+		(28_000_000 as Weight)
+			.saturating_add(T::DbWeight::get().writes(2 as Weight))
+	}
+	// Storage: PhalaRegistry TopicKey (r:0 w:1)
+	fn force_register_topic_pubkey() -> Weight {
+		(12_000_000 as Weight)
+			.saturating_add(T::DbWeight::get().writes(1 as Weight))
+	}
+	// Storage: PhalaRegistry MasterKeyRotationLock (r:1 w:0)
+	// Storage: PhalaRegistry Gatekeeper (r:1 w:1)
+	// Storage: PhalaRegistry GatekeeperMasterPubkey (r:1 w:0)
+	// Storage: PhalaRegistry Workers (r:1 w:0)
+	// Storage: PhalaMq OutboundMessages (r:0 w:1)
+	fn register_gatekeeper() -> Weight {
+		(36_000_000 as Weight)
+			.saturating_add(T::DbWeight::get().reads(4 as Weight))
+			.saturating_add(T::DbWeight::get().writes(2 as Weight))
+	}
+	// Storage: PhalaRegistry MasterKeyRotationLock (r:1 w:0)
+	// Storage: PhalaRegistry Gatekeeper (r:1 w:1)
+	// Storage: PhalaMq OutboundMessages (r:0 w:1)
+	fn unregister_gatekeeper() -> Weight {
+		(30_000_000 as Weight)
+			.saturating_add(T::DbWeight::get().reads(2 as Weight))
+			.saturating_add(T::DbWeight::get().writes(2 as Weight))
+	}
+	// Storage: PhalaRegistry MasterKeyRotationLock (r:1 w:1)
+	// Storage: PhalaRegistry Gatekeeper (r:1 w:0)
+	// Storage: PhalaRegistry Workers (r:10 w:0)
+	// Storage: PhalaRegistry RotationCounter (r:1 w:1)
+	// Storage: PhalaRegistry MasterKeyRotationStatus (r:0 w:1)
+	// Storage: PhalaMq OutboundMessages (r:0 w:1)
+	fn rotate_master_key() -> Weight {
+		(95_000_000 as Weight)
+			.saturating_add(T::DbWeight::get().reads(12 as Weight))
+			.saturating_add(T::DbWeight::get().writes(4 as Weight))
+	}
+	// Storage: PhalaRegistry MasterKeyRotationStatus (r:1 w:1)
+	// Storage: PhalaRegistry Workers (r:10 w:0)
+	// Storage: PhalaMq OutboundMessages (r:0 w:1)
+	fn retry_master_key_rotation() -> Weight {
+		(84_000_000 as Weight)
+			.saturating_add(T::DbWeight::get().reads(11 as Weight))
+			.saturating_add(T::DbWeight::get().writes(2 as Weight))
+	}
+	// Storage: Timestamp Now (r:1 w:0)
+	// Storage: PhalaRegistry PRuntimeAllowList (r:1 w:0)
+	// Storage: PhalaRegistry DcapTrustedRootCerts (r:1 w:0)
+	// Storage: PhalaRegistry RelaychainGenesisBlockHashAllowList (r:1 w:0)
+	// Storage: PhalaRegistry Workers (r:1 w:1)
+	// Storage: PhalaRegistry BenchmarkDuration (r:1 w:0)
+	// Storage: PhalaMq OutboundMessages (r:0 w:1)
+	fn register_worker() -> Weight {
+		(4_802_000_000 as Weight)
+			.saturating_add(T::DbWeight::get().reads(6 as Weight))
+			.saturating_add(T::DbWeight::get().writes(2 as Weight))
+	}
+	// Storage: Timestamp Now (r:1 w:0)
+	// Storage: PhalaRegistry PRuntimeAllowList (r:1 w:0)
+	// Storage: PhalaRegistry DcapTrustedRootCerts (r:1 w:0)
+	// Storage: PhalaRegistry RelaychainGenesisBlockHashAllowList (r:1 w:0)
+	// Storage: PhalaRegistry Workers (r:1 w:1)
+	// Storage: PhalaRegistry BenchmarkDuration (r:1 w:0)
+	// Storage: PhalaMq OutboundMessages (r:0 w:1)
+	fn register_worker_dcap() -> Weight {
+		(9_650_000_000 as Weight)
+			.saturating_add(T::DbWeight::get().reads(6 as Weight))
+			.saturating_add(T::DbWeight::get().writes(2 as Weight))
+	}
+	// Storage: Timestamp Now (r:1 w:0)
+	// Storage: PhalaRegistry Workers (r:1 w:0)
+	// Storage: PhalaRegistry Endpoints (r:0 w:1)
+	fn update_worker_endpoint() -> Weight {
+		(82_000_000 as Weight)
+			.saturating_add(T::DbWeight::get().reads(2 as Weight))
+			.saturating_add(T::DbWeight::get().writes(1 as Weight))
+	}
+	// Storage: PhalaRegistry PRuntimeAllowList (r:1 w:1)
+	// Storage: PhalaRegistry PRuntimeAddedAt (r:0 w:1)
+	fn add_pruntime() -> Weight {
+		(26_000_000 as Weight)
+			.saturating_add(T::DbWeight::get().reads(1 as Weight))
+			.saturating_add(T::DbWeight::get().writes(2 as Weight))
+	}
+	// Storage: PhalaRegistry PRuntimeAllowList (r:1 w:1)
+	// Storage: PhalaRegistry PRuntimeAddedAt (r:0 w:1)
+	fn remove_pruntime() -> Weight {
+		(24_000_000 as Weight)
+			.saturating_add(T::DbWeight::get().reads(1 as Weight))
+			.saturating_add(T::DbWeight::get().writes(2 as Weight))
+	}
+	// Storage: PhalaRegistry DcapTrustedRootCerts (r:1 w:1)
+	fn add_dcap_trusted_root_cert() -> Weight {
+		(21_000_000 as Weight)
+			.saturating_add(T::DbWeight::get().reads(1 as Weight))
+			.saturating_add(T::DbWeight::get().writes(1 as Weight))
+	}
+	// Storage: PhalaRegistry DcapTrustedRootCerts (r:1 w:1)
+	fn remove_dcap_trusted_root_cert() -> Weight {
+		(22_000_000 as Weight)
+			.saturating_add(T::DbWeight::get().reads(1 as Weight))
+			.saturating_add(T::DbWeight::get().writes(1 as Weight))
+	}
+	// Storage: PhalaRegistry RelaychainGenesisBlockHashAllowList (r:1 w:1)
+	fn add_relaychain_genesis_block_hash() -> Weight {
+		(20_000_000 as Weight)
+			.saturating_add(T::DbWeight::get().reads(1 as Weight))
+			.saturating_add(T::DbWeight::get().writes(1 as Weight))
+	}
+	// Storage: PhalaRegistry RelaychainGenesisBlockHashAllowList (r:1 w:1)
+	fn remove_relaychain_genesis_block_hash() -> Weight {
+		(20_000_000 as Weight)
+			.saturating_add(T::DbWeight::get().reads(1 as Weight))
+			.saturating_add(T::DbWeight::get().writes(1 as Weight))
+	}
+}
+
+// For backwards compatibility and tests
+impl WeightInfo for () {
+	fn force_set_benchmark_duration() -> Weight {
+		(10_000_000 as Weight)
+			.saturating_add(RocksDbWeight::get().writes(1 as Weight))
+	}
+	fn force_register_worker() -> Weight {
+		(28_000_000 as Weight)
+			.saturating_add(RocksDbWeight::get().writes(2 as Weight))
+	}
+	fn force_register_topic_pubkey() -> Weight {
+		(12_000_000 as Weight)
+			.saturating_add(RocksDbWeight::get().writes(1 as Weight))
+	}
+	fn register_gatekeeper() -> Weight {
+		(36_000_000 as Weight)
+			.saturating_add(RocksDbWeight::get().reads(4 as Weight))
+			.saturating_add(RocksDbWeight::get().writes(2 as Weight))
+	}
+	fn unregister_gatekeeper() -> Weight {
+		(30_000_000 as Weight)
+			.saturating_add(RocksDbWeight::get().reads(2 as Weight))
+			.saturating_add(RocksDbWeight::get().writes(2 as Weight))
+	}
+	fn rotate_master_key() -> Weight {
+		(95_000_000 as Weight)
+			.saturating_add(RocksDbWeight::get().reads(12 as Weight))
+			.saturating_add(RocksDbWeight::get().writes(4 as Weight))
+	}
+	fn retry_master_key_rotation() -> Weight {
+		(84_000_000 as Weight)
+			.saturating_add(RocksDbWeight::get().reads(11 as Weight))
+			.saturating_add(RocksDbWeight::get().writes(2 as Weight))
+	}
+	fn register_worker() -> Weight {
+		(4_802_000_000 as Weight)
+			.saturating_add(RocksDbWeight::get().reads(6 as Weight))
+			.saturating_add(RocksDbWeight::get().writes(2 as Weight))
+	}
+	fn register_worker_dcap() -> Weight {
+		(9_650_000_000 as Weight)
+			.saturating_add(RocksDbWeight::get().reads(6 as Weight))
+			.saturating_add(RocksDbWeight::get().writes(2 as Weight))
+	}
+	fn update_worker_endpoint() -> Weight {
+		(82_000_000 as Weight)
+			.saturating_add(RocksDbWeight::get().reads(2 as Weight))
+			.saturating_add(RocksDbWeight::get().writes(1 as Weight))
+	}
+	fn add_pruntime() -> Weight {
+		(26_000_000 as Weight)
+			.saturating_add(RocksDbWeight::get().reads(1 as Weight))
+			.saturating_add(RocksDbWeight::get().writes(2 as Weight))
+	}
+	fn remove_pruntime() -> Weight {
+		(24_000_000 as Weight)
+			.saturating_add(RocksDbWeight::get().reads(1 as Weight))
+			.saturating_add(RocksDbWeight::get().writes(2 as Weight))
+	}
+	fn add_dcap_trusted_root_cert() -> Weight {
+		(21_000_000 as Weight)
+			.saturating_add(RocksDbWeight::get().reads(1 as Weight))
+			.saturating_add(RocksDbWeight::get().writes(1 as Weight))
+	}
+	fn remove_dcap_trusted_root_cert() -> Weight {
+		(22_000_000 as Weight)
+			.saturating_add(RocksDbWeight::get().reads(1 as Weight))
+			.saturating_add(RocksDbWeight::get().writes(1 as Weight))
+	}
+	fn add_relaychain_genesis_block_hash() -> Weight {
+		(20_000_000 as Weight)
+			.saturating_add(RocksDbWeight::get().reads(1 as Weight))
+			.saturating_add(RocksDbWeight::get().writes(1 as Weight))
+	}
+	fn remove_relaychain_genesis_block_hash() -> Weight {
+		(20_000_000 as Weight)
+			.saturating_add(RocksDbWeight::get().reads(1 as Weight))
+			.saturating_add(RocksDbWeight::get().writes(1 as Weight))
+	}
+}
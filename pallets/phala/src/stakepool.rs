@@ -31,8 +31,8 @@ pub mod pallet {
 		dispatch::DispatchResult,
 		pallet_prelude::*,
 		traits::{
-			Currency, Imbalance, LockIdentifier, LockableCurrency, OnUnbalanced, StorageVersion,
-			UnixTime, WithdrawReasons,
+			Currency, ExistenceRequirement::AllowDeath, Imbalance, LockIdentifier, LockableCurrency,
+			OnUnbalanced, StorageVersion, UnixTime, WithdrawReasons,
 		},
 	};
 	use frame_system::pallet_prelude::*;
@@ -45,9 +45,11 @@ pub mod pallet {
 
 	use phala_types::{messaging::SettleInfo, WorkerPublicKey};
 
+	use super::WeightInfo;
+
 	const STAKING_ID: LockIdentifier = *b"phala/sp";
 
-	const MAX_WHITELIST_LEN: u32 = 100;
+	pub(crate) const MAX_WHITELIST_LEN: u32 = 100;
 
 	pub struct DescMaxLen;
 
@@ -91,6 +93,14 @@ pub mod pallet {
 		#[pallet::constant]
 		type MaxPoolWorkers: Get<u32>;
 
+		/// The number of blocks an announced commission increase waits before taking effect.
+		#[pallet::constant]
+		type CommissionChangeDelay: Get<Self::BlockNumber>;
+
+		/// The max commission increase allowed in a single announcement.
+		#[pallet::constant]
+		type MaxCommissionIncrease: Get<Permill>;
+
 		/// The handler to absorb the slashed amount.
 		type OnSlashed: OnUnbalanced<NegativeImbalanceOf<Self>>;
 
@@ -99,6 +109,9 @@ pub mod pallet {
 
 		/// The origin that can trigger backfill tasks.
 		type BackfillOrigin: EnsureOrigin<Self::Origin>;
+
+		/// Weight information for the extrinsics of this pallet.
+		type WeightInfo: WeightInfo;
 	}
 
 	const STORAGE_VERSION: StorageVersion = StorageVersion::new(5);
@@ -180,6 +193,12 @@ pub mod pallet {
 	pub type PoolDescriptions<T: Config> =
 		StorageMap<_, Twox64Concat, u64, BoundedVec<u8, super::DescMaxLen>>;
 
+	/// Pools whose owners have disabled the share transfer
+	#[pallet::storage]
+	#[pallet::getter(fn share_transfer_disabled)]
+	pub type PoolShareTransferDisabled<T: Config> =
+		StorageMap<_, Twox64Concat, u64, bool, ValueQuery>;
+
 	#[pallet::event]
 	#[pallet::generate_deposit(pub(super) fn deposit_event)]
 	pub enum Event<T: Config> {
@@ -344,7 +363,45 @@ pub mod pallet {
 			pid: u64,
 			worker: WorkerPublicKey,
 			amount: BalanceOf<T>,
-		}
+		},
+		/// Some shares are transferred from one staker to another
+		///
+		/// Affected states:
+		/// - the pending rewards of both stakers are settled to their `available_rewards`
+		/// - the shares, the locked stake and the reward debt of both stakers in [`PoolStakers`]
+		///   are updated
+		/// - the locked `amount` is moved from the sender's [`StakeLedger`] to the receiver's,
+		///   and the sender's withdraw request is reduced to their remaining shares
+		SharesTransferred {
+			pid: u64,
+			from: T::AccountId,
+			to: T::AccountId,
+			shares: BalanceOf<T>,
+			amount: BalanceOf<T>,
+		},
+		/// A commission increase is announced by the owner, and will take effect at block
+		/// `apply_at`
+		///
+		/// The commission is represented in the same way as [`Event::PoolCommissionSet`].
+		///
+		/// Affected states:
+		/// - the `pending_commission` field in [`StakePools`] is set
+		PoolCommissionChangeScheduled {
+			pid: u64,
+			commission: u32,
+			apply_at: u64,
+		},
+		/// A pending commission increase is cancelled by setting a commission not higher than
+		/// the current one
+		///
+		/// Affected states:
+		/// - the `pending_commission` field in [`StakePools`] is cleared
+		PoolCommissionChangeCancelled { pid: u64 },
+		/// The owner enables or disables the share transfer of a pool
+		///
+		/// Affected states:
+		/// - the entry of the pool in [`PoolShareTransferDisabled`] is updated
+		ShareTransferSet { pid: u64, enabled: bool },
 	}
 
 	#[pallet::error]
@@ -411,6 +468,12 @@ pub mod pallet {
 		NoWhitelistCreated,
 		/// Too long for pool description length
 		ExceedMaxDescriptionLen,
+		/// The pool owner has disabled the share transfer
+		ShareTransferDisabled,
+		/// The shares to transfer are dust, exceed the owned shares, or the receiver is the sender
+		InvalidShareTransfer,
+		/// The commission increase exceeds the `MaxCommissionIncrease` limit
+		CommissionIncreaseTooLarge,
 	}
 
 	#[pallet::hooks]
@@ -434,7 +497,7 @@ pub mod pallet {
 		BalanceOf<T>: FixedPointConvert + Display,
 	{
 		/// Creates a new stake pool
-		#[pallet::weight(0)]
+		#[pallet::weight(<T as Config>::WeightInfo::create())]
 		pub fn create(origin: OriginFor<T>) -> DispatchResult {
 			let owner = ensure_signed(origin)?;
 
@@ -454,6 +517,7 @@ pub mod pallet {
 					releasing_stake: Zero::zero(),
 					workers: vec![],
 					withdraw_queue: VecDeque::new(),
+					pending_commission: None,
 				},
 			);
 			PoolCount::<T>::put(pid + 1);
@@ -471,7 +535,7 @@ pub mod pallet {
 		/// Requires:
 		/// 1. The worker is registered and benchmarked
 		/// 2. The worker is not bound a pool
-		#[pallet::weight(0)]
+		#[pallet::weight(<T as Config>::WeightInfo::add_worker())]
 		pub fn add_worker(
 			origin: OriginFor<T>,
 			pid: u64,
@@ -536,7 +600,7 @@ pub mod pallet {
 		/// 1. The worker is registered
 		/// 2. The worker is associated with a pool
 		/// 3. The worker is removable (not in mining)
-		#[pallet::weight(0)]
+		#[pallet::weight(<T as Config>::WeightInfo::remove_worker())]
 		pub fn remove_worker(
 			origin: OriginFor<T>,
 			pid: u64,
@@ -576,7 +640,7 @@ pub mod pallet {
 		/// Note: a smaller cap than current total_stake if not allowed.
 		/// Requires:
 		/// 1. The sender is the owner
-		#[pallet::weight(0)]
+		#[pallet::weight(<T as Config>::WeightInfo::set_cap())]
 		pub fn set_cap(origin: OriginFor<T>, pid: u64, cap: BalanceOf<T>) -> DispatchResult {
 			let owner = ensure_signed(origin)?;
 			let mut pool_info = Self::ensure_pool(pid)?;
@@ -595,9 +659,16 @@ pub mod pallet {
 
 		/// Change the pool commission rate
 		///
+		/// A decrease takes effect immediately, and cancels the pending increase if any. An
+		/// increase is announced first, and only takes effect after `CommissionChangeDelay`
+		/// blocks, so that the stakers have time to leave. A new announcement replaces the
+		/// pending one. Pools without any stake are not protected, and get the new commission
+		/// immediately.
+		///
 		/// Requires:
 		/// 1. The sender is the owner
-		#[pallet::weight(0)]
+		/// 2. The increase doesn't exceed `MaxCommissionIncrease`
+		#[pallet::weight(<T as Config>::WeightInfo::set_payout_pref())]
 		pub fn set_payout_pref(
 			origin: OriginFor<T>,
 			pid: u64,
@@ -608,14 +679,36 @@ pub mod pallet {
 			// origin must be owner of pool
 			ensure!(pool_info.owner == owner, Error::<T>::UnauthorizedPoolOwner);
 
-			pool_info.payout_commission = Some(payout_commission);
+			Self::maybe_apply_commission_change(&mut pool_info);
+			let current = pool_info.payout_commission.unwrap_or_default();
+			if payout_commission <= current || pool_info.total_shares == Zero::zero() {
+				if pool_info.pending_commission.take().is_some() {
+					Self::deposit_event(Event::<T>::PoolCommissionChangeCancelled { pid });
+				}
+				pool_info.payout_commission = Some(payout_commission);
+				Self::deposit_event(Event::<T>::PoolCommissionSet {
+					pid,
+					commission: payout_commission.deconstruct(),
+				});
+			} else {
+				ensure!(
+					payout_commission.saturating_sub(current) <= T::MaxCommissionIncrease::get(),
+					Error::<T>::CommissionIncreaseTooLarge
+				);
+				let now = frame_system::Pallet::<T>::block_number();
+				let apply_at = (now + T::CommissionChangeDelay::get()).saturated_into::<u64>();
+				pool_info.pending_commission = Some(CommissionChange {
+					commission: payout_commission,
+					apply_at,
+				});
+				Self::deposit_event(Event::<T>::PoolCommissionChangeScheduled {
+					pid,
+					commission: payout_commission.deconstruct(),
+					apply_at,
+				});
+			}
 			StakePools::<T>::insert(&pid, &pool_info);
 
-			Self::deposit_event(Event::<T>::PoolCommissionSet {
-				pid,
-				commission: payout_commission.deconstruct(),
-			});
-
 			Ok(())
 		}
 
@@ -625,7 +718,7 @@ pub mod pallet {
 		/// The caller must be the owner of the pool.
 		/// If a pool hasn't registed in the wihtelist map, any staker could contribute as what they use to do.
 		/// The whitelist has a lmit len of 100 stakers.
-		#[pallet::weight(0)]
+		#[pallet::weight(<T as Config>::WeightInfo::add_staker_to_whitelist())]
 		pub fn add_staker_to_whitelist(
 			origin: OriginFor<T>,
 			pid: u64,
@@ -658,7 +751,7 @@ pub mod pallet {
 		/// Add a description to the pool
 		///
 		/// The caller must be the owner of the pool.
-		#[pallet::weight(0)]
+		#[pallet::weight(<T as Config>::WeightInfo::set_pool_description(description.len() as u32))]
 		pub fn set_pool_description(
 			origin: OriginFor<T>,
 			pid: u64,
@@ -676,7 +769,7 @@ pub mod pallet {
 		///
 		/// The caller must be the owner of the pool.
 		/// If the last staker in the whitelist is removed, the pool will return back to a normal pool that allow anyone to contribute.
-		#[pallet::weight(0)]
+		#[pallet::weight(<T as Config>::WeightInfo::remove_staker_from_whitelist())]
 		pub fn remove_staker_from_whitelist(
 			origin: OriginFor<T>,
 			pid: u64,
@@ -717,7 +810,7 @@ pub mod pallet {
 		/// 1. The caller is root
 		/// 2. Assigned pool must currently exist
 		/// 3. Reward is positive
-		#[pallet::weight(0)]
+		#[pallet::weight(<T as Config>::WeightInfo::force_assign_reward(reward_arr.len() as u32))]
 		pub fn force_assign_reward(
 			origin: OriginFor<T>,
 			reward_arr: Vec<(u64, BalanceOf<T>)>,
@@ -745,7 +838,7 @@ pub mod pallet {
 		///
 		/// Requires:
 		/// 1. The sender is a pool owner
-		#[pallet::weight(0)]
+		#[pallet::weight(<T as Config>::WeightInfo::claim_owner_rewards())]
 		pub fn claim_owner_rewards(
 			origin: OriginFor<T>,
 			pid: u64,
@@ -777,7 +870,7 @@ pub mod pallet {
 		/// Requires:
 		///
 		/// 1. The sender is a staker
-		#[pallet::weight(0)]
+		#[pallet::weight(<T as Config>::WeightInfo::claim_staker_rewards())]
 		pub fn claim_staker_rewards(
 			origin: OriginFor<T>,
 			pid: u64,
@@ -810,7 +903,7 @@ pub mod pallet {
 		/// Requires:
 		///
 		/// 1. The sender is a pool owner or staker
-		#[pallet::weight(0)]
+		#[pallet::weight(<T as Config>::WeightInfo::claim_rewards())]
 		pub fn claim_rewards(
 			origin: OriginFor<T>,
 			pid: u64,
@@ -854,7 +947,9 @@ pub mod pallet {
 		/// Requires:
 		/// 1. The pool exists
 		/// 2. After the deposit, the pool doesn't reach the cap
-		#[pallet::weight(0)]
+		#[pallet::weight(
+			<T as Config>::WeightInfo::contribute(Pallet::<T>::withdraw_queue_len(*pid))
+		)]
 		#[frame_support::transactional]
 		pub fn contribute(origin: OriginFor<T>, pid: u64, amount: BalanceOf<T>) -> DispatchResult {
 			let who = ensure_signed(origin)?;
@@ -937,8 +1032,12 @@ pub mod pallet {
 		/// - if the pool has free stake and the amount of the free stake is greater than or equal
 		///     to the withdrawal amount (e.g. pool.free_stake >= amount), the withdrawal would
 		///     take effect immediately.
-		/// - else the withdrawal would be queued and delayed until there is enough free stake.
-		#[pallet::weight(0)]
+		/// - else the withdrawal would be queued and delayed until there is enough free stake. If
+		///     it's still not fulfilled after `GracePeriod`, the least productive miners of the
+		///     pool are stopped to release the stake for it.
+		#[pallet::weight(
+			<T as Config>::WeightInfo::withdraw(Pallet::<T>::withdraw_queue_len(*pid))
+		)]
 		pub fn withdraw(origin: OriginFor<T>, pid: u64, shares: BalanceOf<T>) -> DispatchResult {
 			let who = ensure_signed(origin)?;
 			let info_key = (pid, who.clone());
@@ -953,7 +1052,7 @@ pub mod pallet {
 			// https://github.com/Phala-Network/phala-blockchain/issues/490
 
 			let mut pool_info = Self::ensure_pool(pid)?;
-			Self::try_withdraw(&mut pool_info, &mut user_info, shares);
+			Self::try_withdraw(&mut pool_info, &mut user_info, shares)?;
 
 			PoolStakers::<T>::insert(&info_key, &user_info);
 			StakePools::<T>::insert(&pid, &pool_info);
@@ -961,12 +1060,132 @@ pub mod pallet {
 			Ok(())
 		}
 
+		/// Transfers some shares of a pool to another account
+		///
+		/// The pending rewards of both accounts are settled before the transfer, and the stake
+		/// backing the shares is moved to the receiver along with the shares. If the remaining
+		/// shares of the sender would be dust, all the shares are transferred. The sender's
+		/// withdraw request, if any, is reduced to the remaining shares.
+		///
+		/// Requires:
+		/// 1. The pool exists and its owner hasn't disabled the share transfer
+		/// 2. The receiver is in the contribution whitelist of the pool, if there's one
+		/// 3. The shares are not dust and don't exceed the shares of the sender
+		/// 4. The pool isn't bankrupt
+		#[pallet::weight(
+			<T as Config>::WeightInfo::transfer_shares(Pallet::<T>::withdraw_queue_len(*pid))
+		)]
+		#[frame_support::transactional]
+		pub fn transfer_shares(
+			origin: OriginFor<T>,
+			pid: u64,
+			to: T::AccountId,
+			shares: BalanceOf<T>,
+		) -> DispatchResult {
+			let who = ensure_signed(origin)?;
+			let mut pool_info = Self::ensure_pool(pid)?;
+			ensure!(
+				!PoolShareTransferDisabled::<T>::get(pid),
+				Error::<T>::ShareTransferDisabled
+			);
+			ensure!(who != to, Error::<T>::InvalidShareTransfer);
+			// The receiver becomes a staker of the pool, so it must be allowed to contribute
+			if let Some(whitelist) = PoolContributionWhitelists::<T>::get(&pid) {
+				ensure!(
+					whitelist.contains(&to) || pool_info.owner == to,
+					Error::<T>::NotInContributeWhitelist
+				);
+			}
+			let from_key = (pid, who.clone());
+			let mut from_info =
+				Self::pool_stakers(&from_key).ok_or(Error::<T>::PoolStakeNotFound)?;
+			ensure!(
+				is_nondust_balance(shares) && shares <= from_info.shares,
+				Error::<T>::InvalidShareTransfer
+			);
+			// The sender has shares, so a pool without stake is bankrupt
+			ensure!(
+				pool_info.total_stake > Zero::zero(),
+				Error::<T>::PoolBankrupt
+			);
+
+			let to_key = (pid, to.clone());
+			let mut to_info = Self::pool_stakers(&to_key).unwrap_or(UserStakeInfo {
+				user: to.clone(),
+				locked: Zero::zero(),
+				shares: Zero::zero(),
+				available_rewards: Zero::zero(),
+				reward_debt: Zero::zero(),
+			});
+			// Clear the pending rewards and slash of both sides before moving the shares
+			pool_info.settle_user_pending_reward(&mut from_info);
+			Self::maybe_settle_slash(&pool_info, &mut from_info);
+			pool_info.settle_user_pending_reward(&mut to_info);
+			Self::maybe_settle_slash(&pool_info, &mut to_info);
+			let (shares, amount) = pool_info
+				.transfer_shares(&mut from_info, &mut to_info, shares)
+				.ok_or(Error::<T>::PoolBankrupt)?;
+
+			// Move the locked funds to the receiver
+			Self::ledger_reduce(&who, amount, Zero::zero());
+			<T as Config>::Currency::transfer(&who, &to, amount, AllowDeath)?;
+			Self::ledger_accrue(&to, amount);
+
+			// The sender can't withdraw more than the remaining shares
+			if let Some(idx) = pool_info
+				.withdraw_queue
+				.iter()
+				.position(|req| req.user == who)
+			{
+				let (available_shares, _) = extract_dust(from_info.shares);
+				if available_shares == Zero::zero() {
+					pool_info.withdraw_queue.remove(idx);
+				} else {
+					let request = pool_info
+						.withdraw_queue
+						.get_mut(idx)
+						.expect("The index was just found; qed.");
+					request.shares = request.shares.min(available_shares);
+				}
+			}
+
+			// Persist
+			PoolStakers::<T>::insert(&from_key, &from_info);
+			PoolStakers::<T>::insert(&to_key, &to_info);
+			StakePools::<T>::insert(&pid, &pool_info);
+			Self::deposit_event(Event::<T>::SharesTransferred {
+				pid,
+				from: who,
+				to,
+				shares,
+				amount,
+			});
+			Ok(())
+		}
+
+		/// Enables or disables the share transfer of a pool
+		///
+		/// The caller must be the owner of the pool. The share transfer is enabled by default.
+		#[pallet::weight(<T as Config>::WeightInfo::set_share_transfer())]
+		pub fn set_share_transfer(origin: OriginFor<T>, pid: u64, enabled: bool) -> DispatchResult {
+			let owner = ensure_signed(origin)?;
+			let pool_info = Self::ensure_pool(pid)?;
+			ensure!(pool_info.owner == owner, Error::<T>::UnauthorizedPoolOwner);
+			if enabled {
+				PoolShareTransferDisabled::<T>::remove(pid);
+			} else {
+				PoolShareTransferDisabled::<T>::insert(pid, true);
+			}
+			Self::deposit_event(Event::<T>::ShareTransferSet { pid, enabled });
+			Ok(())
+		}
+
 		/// Starts a miner on behalf of the stake pool
 		///
 		/// Requires:
 		/// 1. The miner is bound to the pool and is in Ready state
 		/// 2. The remaining stake in the pool can cover the minimal stake required
-		#[pallet::weight(0)]
+		#[pallet::weight(<T as Config>::WeightInfo::start_mining())]
 		pub fn start_mining(
 			origin: OriginFor<T>,
 			pid: u64,
@@ -982,7 +1201,7 @@ pub mod pallet {
 		///
 		/// Requires:
 		/// 1. There miner is bound to the pool and is in a stoppable state
-		#[pallet::weight(0)]
+		#[pallet::weight(<T as Config>::WeightInfo::stop_mining())]
 		pub fn stop_mining(
 			origin: OriginFor<T>,
 			pid: u64,
@@ -993,7 +1212,9 @@ pub mod pallet {
 		}
 
 		/// Reclaims the releasing stake of a miner in a pool.
-		#[pallet::weight(0)]
+		#[pallet::weight(
+			<T as Config>::WeightInfo::reclaim_pool_worker(Pallet::<T>::withdraw_queue_len(*pid))
+		)]
 		pub fn reclaim_pool_worker(
 			origin: OriginFor<T>,
 			pid: u64,
@@ -1006,47 +1227,15 @@ pub mod pallet {
 		}
 
 		/// Enables or disables mining. Must be called with the council or root permission.
-		#[pallet::weight(0)]
+		#[pallet::weight(<T as Config>::WeightInfo::set_mining_enable())]
 		pub fn set_mining_enable(origin: OriginFor<T>, enable: bool) -> DispatchResult {
 			T::MiningSwitchOrigin::ensure_origin(origin)?;
 			MiningEnabled::<T>::put(enable);
 			Ok(())
 		}
 
-		// TODO(hangyin): remove once after issue 527 is closed.
-		/// Temporary function to reconcile incorrect withdraw queue (issue 527).
-		#[pallet::weight(195_000_000)]
-		pub fn reconcile_withdraw_queue(
-			origin: OriginFor<T>,
-			pid: u64,
-			account: T::AccountId,
-		) -> DispatchResult {
-			ensure_signed(origin)?;
-			let mut pool_info = Self::ensure_pool(pid)?;
-			let info_key = (pid, account.clone());
-			let user_info = Self::pool_stakers(&info_key).ok_or(Error::<T>::PoolStakeNotFound)?;
-			// We don't care about dust
-			let (available_shares, _dust) = extract_dust(user_info.shares);
-			// Update the withdraw request if it exceeds the actual available shares, or remove it
-			// if there's no share at all.
-			if let Some(idx) = pool_info
-				.withdraw_queue
-				.iter()
-				.position(|req| req.user == account && req.shares > available_shares)
-			{
-				if available_shares == Zero::zero() {
-					pool_info.withdraw_queue.remove(idx);
-				} else {
-					pool_info.withdraw_queue.get_mut(idx).unwrap().shares = available_shares;
-				}
-				StakePools::<T>::insert(pid, pool_info);
-			}
-
-			Ok(())
-		}
-
 		/// Restart the miner with a higher stake
-		#[pallet::weight(195_000_000)]
+		#[pallet::weight(<T as Config>::WeightInfo::restart_mining())]
 		#[frame_support::transactional]
 		pub fn restart_mining(
 			origin: OriginFor<T>,
@@ -1145,6 +1334,7 @@ pub mod pallet {
 			pool_info: &mut PoolInfo<T::AccountId, BalanceOf<T>>,
 			rewards: BalanceOf<T>,
 		) {
+			Self::maybe_apply_commission_change(pool_info);
 			if rewards > Zero::zero() {
 				if balance_close_to_zero(pool_info.total_shares) {
 					Self::deposit_event(Event::<T>::RewardDismissedNoShare {
@@ -1224,7 +1414,7 @@ pub mod pallet {
 			pool_info: &mut PoolInfo<T::AccountId, BalanceOf<T>>,
 			user_info: &mut UserStakeInfo<T::AccountId, BalanceOf<T>>,
 			shares: BalanceOf<T>,
-		) {
+		) -> DispatchResult {
 			pool_info.settle_user_pending_reward(user_info);
 			let free_shares = match pool_info.share_price() {
 				Some(price) if price != fp!(0) => bdiv(pool_info.free_stake, &price),
@@ -1282,6 +1472,7 @@ pub mod pallet {
 			}
 			// Update the pending reward after changing the staked amount
 			pool_info.reset_pending_reward(user_info);
+			Ok(())
 		}
 
 		/// Tries to fulfill the withdraw queue with the newly freed stake
@@ -1305,6 +1496,16 @@ pub mod pallet {
 							continue;
 						}
 					};
+					// The request can't exceed the shares the user actually has (issue 527).
+					// Reduce it, or drop it if there's nothing left to withdraw.
+					let (available_shares, _) = extract_dust(user_info.shares);
+					if withdraw.shares > available_shares {
+						withdraw.shares = available_shares;
+					}
+					if withdraw.shares == Zero::zero() {
+						pool_info.withdraw_queue.pop_front();
+						continue;
+					}
 					pool_info.settle_user_pending_reward(&mut user_info);
 					// Try to fulfill the withdraw requests as much as possible
 					let free_shares = if price == fp!(0) {
@@ -1378,6 +1579,32 @@ pub mod pallet {
 			}
 		}
 
+		/// Applies the pending commission change of a pool if it's due
+		fn maybe_apply_commission_change(pool_info: &mut PoolInfo<T::AccountId, BalanceOf<T>>) {
+			let now = frame_system::Pallet::<T>::block_number().saturated_into::<u64>();
+			match pool_info.pending_commission {
+				Some(CommissionChange { commission, apply_at }) if apply_at <= now => {
+					pool_info.payout_commission = Some(commission);
+					pool_info.pending_commission = None;
+					Self::deposit_event(Event::<T>::PoolCommissionSet {
+						pid: pool_info.pid,
+						commission: commission.deconstruct(),
+					});
+				}
+				_ => (),
+			}
+		}
+
+		/// The length of the withdraw queue of a pool, charged by the calls walking through it.
+		///
+		/// The queue holds at most one request per staker, as a new request of a user replaces
+		/// the previous one.
+		pub(crate) fn withdraw_queue_len(pid: u64) -> u32 {
+			StakePools::<T>::get(pid)
+				.map(|pool_info| pool_info.withdraw_queue.len() as u32)
+				.unwrap_or_default()
+		}
+
 		/// Gets the pool record by `pid`. Returns error if not exist
 		fn ensure_pool(pid: u64) -> Result<PoolInfo<T::AccountId, BalanceOf<T>>, Error<T>> {
 			Self::stake_pools(&pid).ok_or(Error::<T>::PoolDoesNotExist)
@@ -1451,6 +1678,40 @@ pub mod pallet {
 			}
 		}
 
+		/// Stops the least productive miners of a pool until their stake covers `amount`
+		///
+		/// The miners are ranked by their current V, so the ones earning the least are stopped
+		/// first. The stake of the stopped miners becomes releasing stake, and is used to fulfill
+		/// the withdraw queue once reclaimed after the cool down period.
+		fn stop_least_productive_miners(
+			pool: &PoolInfo<T::AccountId, BalanceOf<T>>,
+			amount: BalanceOf<T>,
+		) {
+			let mut miners: Vec<_> = pool
+				.workers
+				.iter()
+				.filter_map(|worker| {
+					let miner: T::AccountId = pool_sub_account(pool.pid, worker);
+					let miner_info = mining::pallet::Miners::<T>::get(&miner)?;
+					if !miner_info.state.is_mining() {
+						return None;
+					}
+					Some((miner_info.v, miner))
+				})
+				.collect();
+			miners.sort_by_key(|(v, _)| *v);
+			let mut released: BalanceOf<T> = Zero::zero();
+			for (_, miner) in miners {
+				if released >= amount {
+					break;
+				}
+				let stake = mining::pallet::Stakes::<T>::get(&miner).unwrap_or_default();
+				if <mining::pallet::Pallet<T>>::stop_mining(miner).is_ok() {
+					released.saturating_accrue(stake);
+				}
+			}
+		}
+
 		/// Tries to enforce expired withdraw requests
 		///
 		/// TODO: carefully examine the caveat in this function
@@ -1470,13 +1731,9 @@ pub mod pallet {
 					.expect("Pool list must exist; qed.");
 				for &pid in pools.iter() {
 					let pool = Self::ensure_pool(pid).expect("Stake pool must exist; qed.");
-					if pool.has_expired_withdrawal(now, grace_period) {
-						// Force shutdown all miners
-						for worker in pool.workers {
-							let miner: T::AccountId = pool_sub_account(pid, &worker);
-							// TODO: avoid stop mining multiple times?
-							let _ = <mining::pallet::Pallet<T>>::stop_mining(miner);
-						}
+					let shortfall = pool.expired_withdrawal_shortfall(now, grace_period);
+					if shortfall > Zero::zero() {
+						Self::stop_least_productive_miners(&pool, shortfall);
 					}
 				}
 				// pop front timestamp
@@ -1489,6 +1746,75 @@ pub mod pallet {
 			let writes = SubAccountAssignments::<T>::drain().count();
 			T::DbWeight::get().writes(writes as _)
 		}
+
+		/// Adds the empty `pending_commission` to all the pools
+		pub(crate) fn migration_add_pending_commission() -> Weight {
+			#[derive(Decode)]
+			struct OldPoolInfo<AccountId, Balance> {
+				pid: u64,
+				owner: AccountId,
+				payout_commission: Option<Permill>,
+				owner_reward: Balance,
+				cap: Option<Balance>,
+				reward_acc: CodecFixedPoint,
+				total_shares: Balance,
+				total_stake: Balance,
+				free_stake: Balance,
+				releasing_stake: Balance,
+				workers: Vec<WorkerPublicKey>,
+				withdraw_queue: VecDeque<WithdrawInfo<AccountId, Balance>>,
+			}
+
+			let mut translated = 0u64;
+			StakePools::<T>::translate::<OldPoolInfo<T::AccountId, BalanceOf<T>>, _>(|_, old| {
+				translated += 1;
+				Some(PoolInfo {
+					pid: old.pid,
+					owner: old.owner,
+					payout_commission: old.payout_commission,
+					owner_reward: old.owner_reward,
+					cap: old.cap,
+					reward_acc: old.reward_acc,
+					total_shares: old.total_shares,
+					total_stake: old.total_stake,
+					free_stake: old.free_stake,
+					releasing_stake: old.releasing_stake,
+					workers: old.workers,
+					withdraw_queue: old.withdraw_queue,
+					pending_commission: None,
+				})
+			});
+			T::DbWeight::get().reads_writes(translated, translated)
+		}
+
+		/// Reduces or removes the withdraw requests exceeding the stakers' shares (issue 527)
+		///
+		/// The withdraw queue is reconciled when processed since then. This fixes the existing
+		/// requests, which may be counted when forcing the miners to stop.
+		pub(crate) fn migration_reconcile_withdraw_queues() -> Weight {
+			let mut reads = 0u64;
+			let mut writes = 0u64;
+			for (pid, mut pool_info) in StakePools::<T>::iter() {
+				reads += 1;
+				let mut changed = false;
+				pool_info.withdraw_queue.retain_mut(|request| {
+					reads += 1;
+					let shares = Self::pool_stakers((pid, request.user.clone()))
+						.map(|user_info| extract_dust(user_info.shares).0)
+						.unwrap_or_default();
+					if request.shares > shares {
+						request.shares = shares;
+						changed = true;
+					}
+					request.shares > Zero::zero()
+				});
+				if changed {
+					StakePools::<T>::insert(pid, pool_info);
+					writes += 1;
+				}
+			}
+			T::DbWeight::get().reads_writes(reads, writes)
+		}
 	}
 
 	impl<T: Config> mining::OnReward for Pallet<T>
@@ -1586,7 +1912,7 @@ pub mod pallet {
 		}
 	}
 
-	fn pool_sub_account<T>(pid: u64, pubkey: &WorkerPublicKey) -> T
+	pub(crate) fn pool_sub_account<T>(pid: u64, pubkey: &WorkerPublicKey) -> T
 	where
 		T: Encode + Decode,
 	{
@@ -1646,6 +1972,8 @@ pub mod pallet {
 		pub workers: Vec<WorkerPublicKey>,
 		/// The queue of withdraw requests
 		pub withdraw_queue: VecDeque<WithdrawInfo<AccountId, Balance>>,
+		/// The announced commission increase waiting to take effect
+		pub pending_commission: Option<CommissionChange>,
 	}
 
 	impl<AccountId, Balance> PoolInfo<AccountId, Balance>
@@ -1756,6 +2084,49 @@ pub mod pallet {
 			Some((amount, user_dust, removed_shares))
 		}
 
+		/// Moves some shares and the stake behind them from one user to another.
+		///
+		/// No dirty slash or pending reward allowed on both users. The pool totals are not changed.
+		/// If the remaining shares or stake of `from` would be dust, they are moved as well, so
+		/// that no dust is left behind.
+		///
+		/// It returns `None` and makes no change if there's any error. Otherwise it returns a
+		/// tuple with the actual moved shares and stake.
+		fn transfer_shares(
+			&self,
+			from: &mut UserStakeInfo<AccountId, Balance>,
+			to: &mut UserStakeInfo<AccountId, Balance>,
+			shares: Balance,
+		) -> Option<(Balance, Balance)> {
+			debug_assert!(is_nondust_balance(shares));
+			self.assert_slash_clean(from);
+			self.assert_reward_clean(from);
+			self.assert_slash_clean(to);
+			self.assert_reward_clean(to);
+
+			let price = self.share_price()?;
+			let remaining_shares = from.shares.checked_sub(&shares)?;
+			let shares = if is_nondust_balance(remaining_shares) {
+				shares
+			} else {
+				from.shares
+			};
+			// Never move more than the user has locked, due to the fixed point precision loss
+			let amount = bmul(shares, &price).min(from.locked);
+			let amount = if shares == from.shares || !is_nondust_balance(from.locked - amount) {
+				from.locked
+			} else {
+				amount
+			};
+			from.shares -= shares;
+			from.locked -= amount;
+			to.shares.saturating_accrue(shares);
+			to.locked.saturating_accrue(amount);
+			self.reset_pending_reward(from);
+			self.reset_pending_reward(to);
+			Some((shares, amount))
+		}
+
 		/// Slashes the pool with dust removed.
 		fn slash(&mut self, amount: Balance) {
 			debug_assert!(
@@ -1870,8 +2241,12 @@ pub mod pallet {
 			self.workers.retain(|w| w != worker);
 		}
 
-		/// Returns if the pool has expired withdrawal requests
-		fn has_expired_withdrawal(&self, now: u64, grace_period: u64) -> bool {
+		/// Returns the stake to release to fulfill all the expired withdrawal requests
+		///
+		/// The requests are fulfilled in the queue order. So the stake to release covers all the
+		/// requests up to the last expired one, after the free and the releasing stake is used.
+		/// Returns zero if there's no expired request, or they are already covered.
+		fn expired_withdrawal_shortfall(&self, now: u64, grace_period: u64) -> Balance {
 			debug_assert!(
 				self.free_stake == Zero::zero(),
 				"We really don't want to have free stake and withdraw requests at the same time"
@@ -1884,20 +2259,19 @@ pub mod pallet {
 			// If the pool is bankrupt, or there's no share, we just skip this pool.
 			let price = match self.share_price() {
 				Some(price) if price != fp!(0) => price,
-				_ => return false,
+				_ => return Zero::zero(),
 			};
-			let mut budget = self.free_stake + self.releasing_stake;
+			let budget = self.free_stake + self.releasing_stake;
+			let mut required: Balance = Zero::zero();
+			let mut shortfall: Balance = Zero::zero();
 			for request in &self.withdraw_queue {
-				let amount = bmul(request.shares, &price);
-				if amount > budget {
-					// Run out of budget, let's check if the request is still in the grace period
-					return now - request.start_time > grace_period;
-				} else {
-					// Otherwise we allocate some budget to virtually fulfill the request
-					budget -= amount;
+				// Virtually fulfill the requests one by one
+				required.saturating_accrue(bmul(request.shares, &price));
+				if now.saturating_sub(request.start_time) > grace_period {
+					shortfall = required.saturating_sub(budget);
 				}
 			}
-			false
+			shortfall
 		}
 	}
 
@@ -1935,6 +2309,15 @@ pub mod pallet {
 		pub start_time: u64,
 	}
 
+	/// A commission increase announced by the pool owner
+	#[derive(Encode, Decode, TypeInfo, Clone, PartialEq, Eq, RuntimeDebug)]
+	pub struct CommissionChange {
+		/// The new commission
+		pub commission: Permill,
+		/// The block number when the new commission takes effect
+		pub apply_at: u64,
+	}
+
 	#[cfg(test)]
 	mod test {
 		use assert_matches::assert_matches;
@@ -1994,6 +2377,7 @@ pub mod pallet {
 						releasing_stake: 0,
 						workers: Vec::new(),
 						withdraw_queue: VecDeque::new(),
+						pending_commission: None,
 					})
 				);
 				assert_eq!(PoolCount::<Test>::get(), 2);
@@ -2623,6 +3007,11 @@ pub mod pallet {
 				set_block_1();
 				setup_workers(1);
 				setup_pool_with_workers(1, &[1]); // pid = 0
+				assert_ok!(PhalaStakePool::set_payout_pref(
+					Origin::signed(1),
+					0,
+					Permill::from_percent(50)
+				));
 
 				// Check stake before receiving any rewards
 				assert_ok!(PhalaStakePool::contribute(
@@ -2635,11 +3024,6 @@ pub mod pallet {
 					0,
 					400 * DOLLARS
 				));
-				assert_ok!(PhalaStakePool::set_payout_pref(
-					Origin::signed(1),
-					0,
-					Permill::from_percent(50)
-				));
 				let pool = PhalaStakePool::stake_pools(0).unwrap();
 				assert_eq!(pool.reward_acc.get(), fp!(0));
 				assert_eq!(pool.owner_reward, fp!(0));
@@ -2651,17 +3035,11 @@ pub mod pallet {
 			});
 		}
 		#[test]
-		fn test_divided_claim_rewards() {
-			use crate::mining::pallet::OnReward;
+		fn test_delayed_commission_increase() {
 			new_test_ext().execute_with(|| {
 				set_block_1();
 				setup_workers(1);
 				setup_pool_with_workers(1, &[1]); // pid = 0
-				assert_ok!(PhalaStakePool::set_payout_pref(
-					Origin::signed(1),
-					0,
-					Permill::from_percent(50)
-				));
 				assert_ok!(PhalaStakePool::contribute(
 					Origin::signed(1),
 					0,
@@ -2672,49 +3050,208 @@ pub mod pallet {
 					0,
 					400 * DOLLARS
 				));
-				PhalaStakePool::on_reward(&vec![SettleInfo {
-					pubkey: worker_pubkey(1),
-					v: FixedPoint::from_num(1u32).to_bits(),
-					payout: FixedPoint::from_num(1000u32).to_bits(),
-					treasury: 0,
-				}]);
-				let pool = PhalaStakePool::stake_pools(0).unwrap();
-				let staker1 = PhalaStakePool::pool_stakers((0, 1)).unwrap();
-				let staker2 = PhalaStakePool::pool_stakers((0, 2)).unwrap();
-				assert_eq!(pool.pending_reward(&staker1), 100 * DOLLARS);
-				assert_eq!(pool.pending_reward(&staker2), 400 * DOLLARS);
-				assert_eq!(pool.owner_reward, 500 * DOLLARS);
-				assert_ok!(PhalaStakePool::claim_owner_rewards(Origin::signed(1), 0, 1));
-				let pool = PhalaStakePool::stake_pools(0).unwrap();
-				let staker1 = PhalaStakePool::pool_stakers((0, 1)).unwrap();
-				let staker2 = PhalaStakePool::pool_stakers((0, 2)).unwrap();
-				assert_eq!(pool.pending_reward(&staker1), 100 * DOLLARS);
-				assert_eq!(pool.pending_reward(&staker2), 400 * DOLLARS);
-				assert_eq!(pool.owner_reward, 0 * DOLLARS);
-				assert_ok!(PhalaStakePool::claim_staker_rewards(
+				// Cannot raise more than MaxCommissionIncrease at a time
+				assert_noop!(
+					PhalaStakePool::set_payout_pref(
+						Origin::signed(1),
+						0,
+						Permill::from_percent(30)
+					),
+					Error::<Test>::CommissionIncreaseTooLarge
+				);
+				let _ = take_events();
+				assert_ok!(PhalaStakePool::set_payout_pref(
 					Origin::signed(1),
 					0,
-					1
+					Permill::from_percent(20)
 				));
+				assert_eq!(
+					take_events().as_slice(),
+					[TestEvent::PhalaStakePool(
+						Event::PoolCommissionChangeScheduled {
+							pid: 0,
+							commission: 200_000,
+							apply_at: 11,
+						}
+					)]
+				);
 				let pool = PhalaStakePool::stake_pools(0).unwrap();
-				let staker1 = PhalaStakePool::pool_stakers((0, 1)).unwrap();
-				let staker2 = PhalaStakePool::pool_stakers((0, 2)).unwrap();
-				assert_eq!(pool.pending_reward(&staker1), 0 * DOLLARS);
-				assert_eq!(pool.pending_reward(&staker2), 400 * DOLLARS);
-				assert_eq!(pool.owner_reward, 0 * DOLLARS);
+				assert_eq!(pool.payout_commission, None);
+				assert_eq!(
+					pool.pending_commission,
+					Some(CommissionChange {
+						commission: Permill::from_percent(20),
+						apply_at: 11,
+					})
+				);
+
+				// Rewards in the announcement window are distributed with the old commission
+				teleport_to_block(10);
+				assert_ok!(PhalaStakePool::force_assign_reward(
+					Origin::root(),
+					vec![(0, 500 * DOLLARS)]
+				));
+				let pool = PhalaStakePool::stake_pools(0).unwrap();
+				assert_eq!(pool.owner_reward, 0);
+				assert_eq!(pool.reward_acc.get(), fp!(1));
+				assert!(pool.pending_commission.is_some());
+
+				// The new commission takes effect after the delay
+				teleport_to_block(11);
+				let _ = take_events();
+				assert_ok!(PhalaStakePool::force_assign_reward(
+					Origin::root(),
+					vec![(0, 500 * DOLLARS)]
+				));
+				assert_eq!(
+					take_events().as_slice(),
+					[
+						TestEvent::PhalaStakePool(Event::PoolCommissionSet {
+							pid: 0,
+							commission: 200_000,
+						}),
+						TestEvent::PhalaStakePool(Event::RewardReceived {
+							pid: 0,
+							to_owner: 100 * DOLLARS,
+							to_stakers: 400 * DOLLARS,
+						}),
+					]
+				);
+				let pool = PhalaStakePool::stake_pools(0).unwrap();
+				assert_eq!(pool.payout_commission, Some(Permill::from_percent(20)));
+				assert_eq!(pool.pending_commission, None);
+				assert_eq!(pool.owner_reward, 100 * DOLLARS);
 			});
 		}
+
 		#[test]
-		fn test_staker_whitelist() {
+		fn test_commission_decrease_cancels_pending_increase() {
 			new_test_ext().execute_with(|| {
 				set_block_1();
 				setup_workers(1);
-				setup_pool_with_workers(1, &[1]);
-
-				assert_ok!(PhalaStakePool::contribute(
+				setup_pool_with_workers(1, &[1]); // pid = 0
+				// No staker to protect, applied immediately
+				assert_ok!(PhalaStakePool::set_payout_pref(
 					Origin::signed(1),
 					0,
-					40 * DOLLARS
+					Permill::from_percent(50)
+				));
+				assert_ok!(PhalaStakePool::contribute(
+					Origin::signed(2),
+					0,
+					100 * DOLLARS
+				));
+				let pool = PhalaStakePool::stake_pools(0).unwrap();
+				assert_eq!(pool.payout_commission, Some(Permill::from_percent(50)));
+				// The increase is capped relative to the current commission
+				assert_ok!(PhalaStakePool::set_payout_pref(
+					Origin::signed(1),
+					0,
+					Permill::from_percent(70)
+				));
+				assert_noop!(
+					PhalaStakePool::set_payout_pref(
+						Origin::signed(1),
+						0,
+						Permill::from_percent(71)
+					),
+					Error::<Test>::CommissionIncreaseTooLarge
+				);
+				// A decrease is applied immediately and cancels the pending increase
+				let _ = take_events();
+				assert_ok!(PhalaStakePool::set_payout_pref(
+					Origin::signed(1),
+					0,
+					Permill::from_percent(40)
+				));
+				assert_eq!(
+					take_events().as_slice(),
+					[
+						TestEvent::PhalaStakePool(Event::PoolCommissionChangeCancelled {
+							pid: 0
+						}),
+						TestEvent::PhalaStakePool(Event::PoolCommissionSet {
+							pid: 0,
+							commission: 400_000,
+						}),
+					]
+				);
+				teleport_to_block(20);
+				assert_ok!(PhalaStakePool::force_assign_reward(
+					Origin::root(),
+					vec![(0, 100 * DOLLARS)]
+				));
+				let pool = PhalaStakePool::stake_pools(0).unwrap();
+				assert_eq!(pool.payout_commission, Some(Permill::from_percent(40)));
+				assert_eq!(pool.owner_reward, 40 * DOLLARS);
+			});
+		}
+
+		#[test]
+		fn test_divided_claim_rewards() {
+			use crate::mining::pallet::OnReward;
+			new_test_ext().execute_with(|| {
+				set_block_1();
+				setup_workers(1);
+				setup_pool_with_workers(1, &[1]); // pid = 0
+				assert_ok!(PhalaStakePool::set_payout_pref(
+					Origin::signed(1),
+					0,
+					Permill::from_percent(50)
+				));
+				assert_ok!(PhalaStakePool::contribute(
+					Origin::signed(1),
+					0,
+					100 * DOLLARS
+				));
+				assert_ok!(PhalaStakePool::contribute(
+					Origin::signed(2),
+					0,
+					400 * DOLLARS
+				));
+				PhalaStakePool::on_reward(&vec![SettleInfo {
+					pubkey: worker_pubkey(1),
+					v: FixedPoint::from_num(1u32).to_bits(),
+					payout: FixedPoint::from_num(1000u32).to_bits(),
+					treasury: 0,
+				}]);
+				let pool = PhalaStakePool::stake_pools(0).unwrap();
+				let staker1 = PhalaStakePool::pool_stakers((0, 1)).unwrap();
+				let staker2 = PhalaStakePool::pool_stakers((0, 2)).unwrap();
+				assert_eq!(pool.pending_reward(&staker1), 100 * DOLLARS);
+				assert_eq!(pool.pending_reward(&staker2), 400 * DOLLARS);
+				assert_eq!(pool.owner_reward, 500 * DOLLARS);
+				assert_ok!(PhalaStakePool::claim_owner_rewards(Origin::signed(1), 0, 1));
+				let pool = PhalaStakePool::stake_pools(0).unwrap();
+				let staker1 = PhalaStakePool::pool_stakers((0, 1)).unwrap();
+				let staker2 = PhalaStakePool::pool_stakers((0, 2)).unwrap();
+				assert_eq!(pool.pending_reward(&staker1), 100 * DOLLARS);
+				assert_eq!(pool.pending_reward(&staker2), 400 * DOLLARS);
+				assert_eq!(pool.owner_reward, 0 * DOLLARS);
+				assert_ok!(PhalaStakePool::claim_staker_rewards(
+					Origin::signed(1),
+					0,
+					1
+				));
+				let pool = PhalaStakePool::stake_pools(0).unwrap();
+				let staker1 = PhalaStakePool::pool_stakers((0, 1)).unwrap();
+				let staker2 = PhalaStakePool::pool_stakers((0, 2)).unwrap();
+				assert_eq!(pool.pending_reward(&staker1), 0 * DOLLARS);
+				assert_eq!(pool.pending_reward(&staker2), 400 * DOLLARS);
+				assert_eq!(pool.owner_reward, 0 * DOLLARS);
+			});
+		}
+		#[test]
+		fn test_staker_whitelist() {
+			new_test_ext().execute_with(|| {
+				set_block_1();
+				setup_workers(1);
+				setup_pool_with_workers(1, &[1]);
+
+				assert_ok!(PhalaStakePool::contribute(
+					Origin::signed(1),
+					0,
+					40 * DOLLARS
 				));
 				assert_ok!(PhalaStakePool::contribute(
 					Origin::signed(2),
@@ -2924,6 +3461,241 @@ pub mod pallet {
 			});
 		}
 
+		#[test]
+		fn test_transfer_shares() {
+			use crate::mining::pallet::OnReward;
+			new_test_ext().execute_with(|| {
+				set_block_1();
+				setup_workers(1);
+				setup_pool_with_workers(1, &[1]); // pid = 0
+				assert_ok!(PhalaStakePool::contribute(
+					Origin::signed(1),
+					0,
+					100 * DOLLARS
+				));
+				assert_ok!(PhalaStakePool::contribute(
+					Origin::signed(2),
+					0,
+					400 * DOLLARS
+				));
+				// Mined 500 PHA, 100 and 400 PHA pending for staker 1 & 2
+				PhalaStakePool::on_reward(&vec![SettleInfo {
+					pubkey: worker_pubkey(1),
+					v: FixedPoint::from_num(1u32).to_bits(),
+					payout: FixedPoint::from_num(500u32).to_bits(),
+					treasury: 0,
+				}]);
+
+				// Staker2 transfers 100 shares to a new staker 3
+				let _ = take_events();
+				assert_ok!(PhalaStakePool::transfer_shares(
+					Origin::signed(2),
+					0,
+					3,
+					100 * DOLLARS
+				));
+				assert_eq!(
+					take_events().as_slice(),
+					[
+						TestEvent::Balances(pallet_balances::Event::<Test>::Transfer {
+							from: 2,
+							to: 3,
+							amount: 100 * DOLLARS
+						}),
+						TestEvent::PhalaStakePool(Event::SharesTransferred {
+							pid: 0,
+							from: 2,
+							to: 3,
+							shares: 100 * DOLLARS,
+							amount: 100 * DOLLARS
+						})
+					]
+				);
+				// The pending reward goes to the sender only, and the pool is unchanged
+				let pool = PhalaStakePool::stake_pools(0).unwrap();
+				let staker2 = PhalaStakePool::pool_stakers((0, 2)).unwrap();
+				let staker3 = PhalaStakePool::pool_stakers((0, 3)).unwrap();
+				assert_eq!(pool.total_shares, 500 * DOLLARS);
+				assert_eq!(pool.total_stake, 500 * DOLLARS);
+				assert_eq!(staker2.shares, 300 * DOLLARS);
+				assert_eq!(staker2.locked, 300 * DOLLARS);
+				assert_eq!(staker2.available_rewards, 400 * DOLLARS);
+				assert_eq!(staker2.reward_debt, 300 * DOLLARS);
+				assert_eq!(staker3.shares, 100 * DOLLARS);
+				assert_eq!(staker3.locked, 100 * DOLLARS);
+				assert_eq!(staker3.available_rewards, 0);
+				assert_eq!(staker3.reward_debt, 100 * DOLLARS);
+				assert_eq!(pool.pending_reward(&staker2), 0);
+				assert_eq!(pool.pending_reward(&staker3), 0);
+				// The locked funds are moved
+				assert_eq!(Balances::free_balance(2), 1900 * DOLLARS);
+				assert_eq!(Balances::free_balance(3), 1100 * DOLLARS);
+				assert_eq!(Balances::locks(2), vec![the_lock(300 * DOLLARS)]);
+				assert_eq!(Balances::locks(3), vec![the_lock(100 * DOLLARS)]);
+				assert_eq!(PhalaStakePool::stake_ledger(2), Some(300 * DOLLARS));
+				assert_eq!(PhalaStakePool::stake_ledger(3), Some(100 * DOLLARS));
+
+				// Mined 500 PHA, shared by 1:3:1
+				PhalaStakePool::on_reward(&vec![SettleInfo {
+					pubkey: worker_pubkey(1),
+					v: FixedPoint::from_num(1u32).to_bits(),
+					payout: FixedPoint::from_num(500u32).to_bits(),
+					treasury: 0,
+				}]);
+				let pool = PhalaStakePool::stake_pools(0).unwrap();
+				let staker2 = PhalaStakePool::pool_stakers((0, 2)).unwrap();
+				let staker3 = PhalaStakePool::pool_stakers((0, 3)).unwrap();
+				assert_eq!(pool.pending_reward(&staker2), 300 * DOLLARS);
+				assert_eq!(pool.pending_reward(&staker3), 100 * DOLLARS);
+
+				// Staker3 transfers all its shares to staker1, settling the rewards on both sides
+				assert_ok!(PhalaStakePool::transfer_shares(
+					Origin::signed(3),
+					0,
+					1,
+					100 * DOLLARS
+				));
+				let pool = PhalaStakePool::stake_pools(0).unwrap();
+				let staker1 = PhalaStakePool::pool_stakers((0, 1)).unwrap();
+				let staker3 = PhalaStakePool::pool_stakers((0, 3)).unwrap();
+				assert_eq!(staker1.shares, 200 * DOLLARS);
+				assert_eq!(staker1.available_rewards, 200 * DOLLARS);
+				assert_eq!(staker1.reward_debt, 400 * DOLLARS);
+				assert_eq!(pool.pending_reward(&staker1), 0);
+				assert_eq!(staker3.shares, 0);
+				assert_eq!(staker3.locked, 0);
+				assert_eq!(staker3.available_rewards, 100 * DOLLARS);
+				assert_eq!(Balances::locks(1), vec![the_lock(200 * DOLLARS)]);
+				assert_eq!(Balances::locks(3), vec![]);
+				assert_eq!(PhalaStakePool::stake_ledger(3), Some(0));
+			});
+		}
+
+		#[test]
+		fn test_transfer_shares_reduces_withdraw_request() {
+			new_test_ext().execute_with(|| {
+				set_block_1();
+				setup_workers(1);
+				setup_pool_with_workers(1, &[1]); // pid = 0
+				assert_ok!(PhalaStakePool::contribute(
+					Origin::signed(2),
+					0,
+					500 * DOLLARS
+				));
+				assert_ok!(PhalaStakePool::start_mining(
+					Origin::signed(1),
+					0,
+					worker_pubkey(1),
+					500 * DOLLARS
+				));
+				// All the stake is mining, so the withdrawal is queued
+				assert_ok!(PhalaStakePool::withdraw(
+					Origin::signed(2),
+					0,
+					400 * DOLLARS
+				));
+				assert_ok!(PhalaStakePool::transfer_shares(
+					Origin::signed(2),
+					0,
+					3,
+					300 * DOLLARS
+				));
+				let req = StakePools::<Test>::get(0)
+					.unwrap()
+					.withdraw_queue
+					.get(0)
+					.cloned()
+					.unwrap();
+				assert_eq!(req.user, 2);
+				assert_eq!(req.shares, 200 * DOLLARS);
+				// Transferring all the shares removes the request
+				assert_ok!(PhalaStakePool::transfer_shares(
+					Origin::signed(2),
+					0,
+					3,
+					200 * DOLLARS
+				));
+				assert!(StakePools::<Test>::get(0)
+					.unwrap()
+					.withdraw_queue
+					.is_empty());
+				let staker3 = PhalaStakePool::pool_stakers((0, 3)).unwrap();
+				assert_eq!(staker3.shares, 500 * DOLLARS);
+			});
+		}
+
+		#[test]
+		fn test_share_transfer_restrictions() {
+			new_test_ext().execute_with(|| {
+				set_block_1();
+				setup_workers(1);
+				setup_pool_with_workers(1, &[1]); // pid = 0
+				assert_ok!(PhalaStakePool::contribute(
+					Origin::signed(2),
+					0,
+					100 * DOLLARS
+				));
+				// Invalid amounts or receiver
+				assert_noop!(
+					PhalaStakePool::transfer_shares(Origin::signed(2), 0, 3, 101 * DOLLARS),
+					Error::<Test>::InvalidShareTransfer
+				);
+				assert_noop!(
+					PhalaStakePool::transfer_shares(Origin::signed(2), 0, 3, 1),
+					Error::<Test>::InvalidShareTransfer
+				);
+				assert_noop!(
+					PhalaStakePool::transfer_shares(Origin::signed(2), 0, 2, 10 * DOLLARS),
+					Error::<Test>::InvalidShareTransfer
+				);
+				assert_noop!(
+					PhalaStakePool::transfer_shares(Origin::signed(3), 0, 2, 10 * DOLLARS),
+					Error::<Test>::PoolStakeNotFound
+				);
+				// Only the owner can disable the transfer
+				assert_noop!(
+					PhalaStakePool::set_share_transfer(Origin::signed(2), 0, false),
+					Error::<Test>::UnauthorizedPoolOwner
+				);
+				assert_ok!(PhalaStakePool::set_share_transfer(Origin::signed(1), 0, false));
+				assert_noop!(
+					PhalaStakePool::transfer_shares(Origin::signed(2), 0, 3, 10 * DOLLARS),
+					Error::<Test>::ShareTransferDisabled
+				);
+				assert_ok!(PhalaStakePool::set_share_transfer(Origin::signed(1), 0, true));
+				assert!(!PhalaStakePool::share_transfer_disabled(0));
+				// The receiver must be in the whitelist, or be the owner
+				assert_ok!(PhalaStakePool::add_staker_to_whitelist(
+					Origin::signed(1),
+					0,
+					2,
+				));
+				assert_noop!(
+					PhalaStakePool::transfer_shares(Origin::signed(2), 0, 3, 10 * DOLLARS),
+					Error::<Test>::NotInContributeWhitelist
+				);
+				assert_ok!(PhalaStakePool::transfer_shares(
+					Origin::signed(2),
+					0,
+					1,
+					10 * DOLLARS
+				));
+				assert_ok!(PhalaStakePool::add_staker_to_whitelist(
+					Origin::signed(1),
+					0,
+					3,
+				));
+				assert_ok!(PhalaStakePool::transfer_shares(
+					Origin::signed(2),
+					0,
+					3,
+					10 * DOLLARS
+				));
+				let staker2 = PhalaStakePool::pool_stakers((0, 2)).unwrap();
+				assert_eq!(staker2.shares, 80 * DOLLARS);
+			});
+		}
+
 		#[test]
 		fn dismiss_dust_reward() {
 			use crate::mining::pallet::OnReward;
@@ -3256,7 +4028,61 @@ pub mod pallet {
 		}
 
 		#[test]
-		fn test_pool_has_expired_withdraw() {
+		fn test_withdraw_queue_one_request_per_user() {
+			new_test_ext().execute_with(|| {
+				set_block_1();
+				setup_workers(1);
+				setup_pool_with_workers(1, &[1]); // pid = 0
+				let stakers = 1000..1003;
+				for staker in stakers.clone().chain([2]) {
+					assert_ok!(Balances::set_balance(
+						Origin::root(),
+						staker,
+						10 * DOLLARS,
+						0
+					));
+					assert_ok!(PhalaStakePool::contribute(
+						Origin::signed(staker),
+						0,
+						1 * DOLLARS
+					));
+				}
+				// Lock all the stake in the miner, so that all the withdrawals get queued
+				assert_ok!(PhalaStakePool::start_mining(
+					Origin::signed(1),
+					0,
+					worker_pubkey(1),
+					4 * DOLLARS
+				));
+				for staker in stakers.chain([2]) {
+					assert_ok!(PhalaStakePool::withdraw(
+						Origin::signed(staker),
+						0,
+						1 * DOLLARS
+					));
+				}
+				assert_eq!(PhalaStakePool::withdraw_queue_len(0), 4);
+				// A new request replaces the previous one of the same user
+				assert_ok!(PhalaStakePool::withdraw(
+					Origin::signed(1000),
+					0,
+					1 * DOLLARS / 2
+				));
+				let pool = PhalaStakePool::stake_pools(0).unwrap();
+				assert_eq!(pool.withdraw_queue.len(), 4);
+				assert_eq!(
+					pool.withdraw_queue.back(),
+					Some(&WithdrawInfo {
+						user: 1000,
+						shares: 1 * DOLLARS / 2,
+						start_time: 0
+					})
+				);
+			});
+		}
+
+		#[test]
+		fn test_pool_expired_withdrawal_shortfall() {
 			// Default pool setup
 			let mut pool: PoolInfo<u64, Balance> = Default::default();
 			pool.total_shares = 1000 * DOLLARS;
@@ -3281,26 +4107,36 @@ pub mod pallet {
 				releasing_stake: 0,
 				..pool.clone()
 			};
-			assert!(!pool1.has_expired_withdrawal(0, 100), "All in grace period");
-			assert!(
-				!pool1.has_expired_withdrawal(100, 100),
+			assert_eq!(
+				pool1.expired_withdrawal_shortfall(0, 100),
+				0,
+				"All in grace period"
+			);
+			assert_eq!(
+				pool1.expired_withdrawal_shortfall(100, 100),
+				0,
 				"Still all in grace period"
 			);
 			assert!(
-				pool1.has_expired_withdrawal(101, 100),
+				balances_nearly_equal(pool1.expired_withdrawal_shortfall(101, 100), 90 * DOLLARS),
 				"First withdraw request expired"
 			);
+			assert!(
+				balances_nearly_equal(pool1.expired_withdrawal_shortfall(201, 100), 270 * DOLLARS),
+				"The first two withdraw requests expired"
+			);
 			// Releasing stake to cover the first request
 			let pool2 = PoolInfo::<u64, Balance> {
 				releasing_stake: 90 * DOLLARS,
 				..pool.clone()
 			};
-			assert!(
-				!pool2.has_expired_withdrawal(101, 100),
+			assert_eq!(
+				pool2.expired_withdrawal_shortfall(101, 100),
+				0,
 				"First withdraw request fulfilled"
 			);
 			assert!(
-				pool2.has_expired_withdrawal(201, 100),
+				balances_nearly_equal(pool2.expired_withdrawal_shortfall(201, 100), 180 * DOLLARS),
 				"Second withdraw request expired"
 			);
 			let pool3 = PoolInfo::<u64, Balance> {
@@ -3308,14 +4144,18 @@ pub mod pallet {
 				..pool.clone()
 			};
 			assert!(
-				pool3.has_expired_withdrawal(1000, 100),
+				pool3.expired_withdrawal_shortfall(1000, 100) > 0,
 				"No enought releasing stake to fulfill all"
 			);
 			let pool4 = PoolInfo::<u64, Balance> {
 				releasing_stake: 630 * DOLLARS,
 				..pool.clone()
 			};
-			assert!(!pool4.has_expired_withdrawal(1000, 100), "Enough stake");
+			assert_eq!(
+				pool4.expired_withdrawal_shortfall(1000, 100),
+				0,
+				"Enough stake"
+			);
 		}
 
 		#[test]
@@ -3739,6 +4579,7 @@ pub mod pallet {
 							});
 							q
 						},
+						pending_commission: None,
 					},
 				);
 				PoolStakers::<Test>::insert(
@@ -3841,7 +4682,7 @@ pub mod pallet {
 		}
 
 		#[test]
-		fn issue257_reconcile_reduced_withdraw() {
+		fn issue257_reduce_exceeded_withdraw_request() {
 			new_test_ext().execute_with(|| {
 				set_block_1();
 				setup_workers(1);
@@ -3851,7 +4692,6 @@ pub mod pallet {
 					0,
 					500 * DOLLARS
 				));
-				let orig_pool = StakePools::<Test>::get(0);
 				StakePools::<Test>::mutate(0, |pool_info| {
 					pool_info
 						.as_mut()
@@ -3863,24 +4703,34 @@ pub mod pallet {
 							start_time: 1u64,
 						});
 				});
-				// Should reduce the requested shares to 500
-				assert_ok!(PhalaStakePool::reconcile_withdraw_queue(
-					Origin::signed(1),
+				// The new free stake fulfills the request, reduced to the 500 shares staker2 has
+				let _ = take_events();
+				assert_ok!(PhalaStakePool::contribute(
+					Origin::signed(3),
 					0,
-					2
+					100 * DOLLARS
 				));
-				let req = StakePools::<Test>::get(0)
-					.unwrap()
-					.withdraw_queue
-					.get(0)
-					.cloned()
-					.unwrap();
-				assert_eq!(req.shares, 500 * DOLLARS);
+				assert_matches!(
+					take_events().as_slice(),
+					[
+						TestEvent::PhalaStakePool(Event::Withdrawal {
+							pid: 0,
+							user: 2,
+							amount: 500_000000000000,
+							shares: 500_000000000000,
+						}),
+						TestEvent::PhalaStakePool(Event::Contribution { .. }),
+					]
+				);
+				let pool = StakePools::<Test>::get(0).unwrap();
+				assert!(pool.withdraw_queue.is_empty());
+				assert_eq!(pool.total_stake, 100 * DOLLARS);
+				assert_eq!(PhalaStakePool::pool_stakers((0, 2)).unwrap().shares, 0);
 			});
 		}
 
 		#[test]
-		fn issue257_reconcile_removed_withdraw() {
+		fn issue257_drop_withdraw_request_without_shares() {
 			new_test_ext().execute_with(|| {
 				set_block_1();
 				setup_workers(1);
@@ -3896,7 +4746,6 @@ pub mod pallet {
 					0,
 					500 * DOLLARS
 				));
-				let orig_pool = StakePools::<Test>::get(0);
 				StakePools::<Test>::mutate(0, |pool_info| {
 					pool_info
 						.as_mut()
@@ -3908,16 +4757,80 @@ pub mod pallet {
 							start_time: 1u64,
 						});
 				});
-				// Should reduce the requested shares to 500
-				assert_ok!(PhalaStakePool::reconcile_withdraw_queue(
+				// The request is dropped without touching the new free stake
+				assert_ok!(PhalaStakePool::contribute(
+					Origin::signed(3),
+					0,
+					100 * DOLLARS
+				));
+				let pool = StakePools::<Test>::get(0).unwrap();
+				assert!(pool.withdraw_queue.is_empty());
+				assert_eq!(pool.free_stake, 100 * DOLLARS);
+			});
+		}
+
+		#[test]
+		fn test_force_withdraw_stops_least_productive_miners() {
+			new_test_ext().execute_with(|| {
+				set_block_1();
+				setup_workers(3);
+				setup_pool_with_workers(1, &[1, 2, 3]); // pid = 0
+				assert_ok!(PhalaStakePool::contribute(
+					Origin::signed(2),
+					0,
+					900 * DOLLARS
+				));
+				for worker in 1..=3 {
+					assert_ok!(PhalaStakePool::start_mining(
+						Origin::signed(1),
+						0,
+						worker_pubkey(worker),
+						300 * DOLLARS
+					));
+				}
+				// Worker 2 is the least productive, followed by worker 3
+				for (worker, v) in [(1, 300u32), (2, 100), (3, 200)] {
+					let miner: u64 = pool_sub_account(0, &worker_pubkey(worker));
+					mining::Miners::<Test>::mutate(miner, |info| {
+						info.as_mut().unwrap().v = FixedPoint::from_num(v).to_bits();
+					});
+				}
+				assert_ok!(PhalaStakePool::withdraw(
+					Origin::signed(2),
+					0,
+					400 * DOLLARS
+				));
+				// Expire the request. Two miners are enough to cover it.
+				let grace_period = <Test as Config>::GracePeriod::get();
+				elapse_seconds(grace_period + 1);
+				teleport_to_block(2);
+				let state = |worker| {
+					let miner: u64 = pool_sub_account(0, &worker_pubkey(worker));
+					PhalaMining::miners(miner).unwrap().state
+				};
+				assert_eq!(state(1), mining::MinerState::MiningIdle);
+				assert_eq!(state(2), mining::MinerState::MiningCoolingDown);
+				assert_eq!(state(3), mining::MinerState::MiningCoolingDown);
+				let pool = PhalaStakePool::stake_pools(0).unwrap();
+				assert_eq!(pool.releasing_stake, 600 * DOLLARS);
+				// The reclaimed stake fulfills the request
+				elapse_cool_down();
+				assert_ok!(PhalaStakePool::reclaim_pool_worker(
 					Origin::signed(1),
 					0,
-					2
+					worker_pubkey(2)
 				));
-				assert!(StakePools::<Test>::get(0)
-					.unwrap()
-					.withdraw_queue
-					.is_empty());
+				let pool = PhalaStakePool::stake_pools(0).unwrap();
+				assert_eq!(pool.withdraw_queue.len(), 1);
+				assert_ok!(PhalaStakePool::reclaim_pool_worker(
+					Origin::signed(1),
+					0,
+					worker_pubkey(3)
+				));
+				let pool = PhalaStakePool::stake_pools(0).unwrap();
+				assert!(pool.withdraw_queue.is_empty());
+				assert_eq!(pool.total_stake, 500 * DOLLARS);
+				assert_eq!(pool.free_stake, 200 * DOLLARS);
 			});
 		}
 
@@ -4065,3 +4978,9 @@ fn extract_dust<B: AtLeast32BitUnsigned + Copy>(n: B) -> (B, B) {
 		(n, Zero::zero())
 	}
 }
+
+pub mod weights;
+pub use weights::WeightInfo;
+
+#[cfg(feature = "runtime-benchmarks")]
+pub(crate) mod benchmarking;
@@ -0,0 +1,401 @@
+//! Benchmarks of the stake pool pallet
+
+use super::*;
+
+use super::pallet::{pool_sub_account, MAX_WHITELIST_LEN};
+use crate::balance_convert::FixedPointConvert;
+use crate::mining;
+use crate::registry::benchmarking::{register_worker, worker_pubkey};
+use fixed::types::U64F64 as FixedPoint;
+use frame_benchmarking::{account, benchmarks, whitelisted_caller};
+use frame_support::{
+	traits::{Currency, EnsureOrigin, Get},
+	BoundedVec,
+};
+use frame_system::RawOrigin;
+use phala_types::WorkerPublicKey;
+use sp_runtime::Permill;
+use sp_std::{fmt::Display, vec, vec::Vec};
+
+const SEED: u32 = 0;
+
+/// The stake the pool owner contributes in the benchmarks, in PHA.
+const OWNER_STAKE: u32 = 1000;
+
+/// The stake each of the queued stakers contributes in the benchmarks, in PHA.
+const STAKER_STAKE: u32 = 10;
+
+/// The upper bound of the withdraw queue length in the benchmarks. The weights are linear in it.
+const MAX_WITHDRAW_QUEUE_LEN: u32 = 100;
+
+/// The upper bound of the pools rewarded in a single `force_assign_reward` in the benchmarks.
+const MAX_REWARD_ASSIGNMENTS: u32 = 100;
+
+/// Converts `n` PHA to the balance.
+pub(crate) fn pha<B: FixedPointConvert>(n: u32) -> B {
+	B::from_fixed(&FixedPoint::from_num(n))
+}
+
+/// Returns an account with plenty of free balance.
+pub(crate) fn funded_account<T>(name: &'static str, index: u32) -> T::AccountId
+where
+	T: Config,
+	BalanceOf<T>: FixedPointConvert,
+{
+	let who: T::AccountId = account(name, index, SEED);
+	<T as Config>::Currency::make_free_balance_be(&who, pha(1_000_000));
+	who
+}
+
+/// Creates a pool owned by `owner` with `n` registered and benchmarked workers added.
+pub(crate) fn setup_pool<T>(owner: &T::AccountId, n: u32) -> (u64, Vec<WorkerPublicKey>)
+where
+	T: Config + mining::Config<Currency = <T as Config>::Currency>,
+	BalanceOf<T>: FixedPointConvert + Display,
+{
+	if mining::TokenomicParameters::<T>::get().is_none() {
+		mining::pallet::migrations::initialize::<T>();
+	}
+	MiningEnabled::<T>::put(true);
+	let pid = PoolCount::<T>::get();
+	Pallet::<T>::create(RawOrigin::Signed(owner.clone()).into()).expect("Failed to create pool");
+	let workers: Vec<_> = (0..n).map(|i| worker_pubkey(((pid as u32) << 16) + i)).collect();
+	for worker in workers.iter() {
+		register_worker::<T>(*worker, Some(owner.clone()));
+		Pallet::<T>::add_worker(RawOrigin::Signed(owner.clone()).into(), pid, *worker)
+			.expect("Failed to add worker");
+	}
+	(pid, workers)
+}
+
+fn contribute<T>(pid: u64, who: &T::AccountId, amount: BalanceOf<T>)
+where
+	T: Config + mining::Config<Currency = <T as Config>::Currency>,
+	BalanceOf<T>: FixedPointConvert + Display,
+{
+	Pallet::<T>::contribute(RawOrigin::Signed(who.clone()).into(), pid, amount)
+		.expect("Failed to contribute");
+}
+
+/// Starts mining `worker` with all the free stake in the pool.
+fn start_mining<T>(owner: &T::AccountId, pid: u64, worker: WorkerPublicKey)
+where
+	T: Config + mining::Config<Currency = <T as Config>::Currency>,
+	BalanceOf<T>: FixedPointConvert + Display,
+{
+	let free_stake = StakePools::<T>::get(pid).expect("Pool must exist; qed.").free_stake;
+	Pallet::<T>::start_mining(RawOrigin::Signed(owner.clone()).into(), pid, worker, free_stake)
+		.expect("Failed to start mining");
+}
+
+/// Sets up a pool with a mining worker taking all the stake and `q` queued withdrawals.
+///
+/// Returns the pool id, the worker and the stakers in the withdraw queue.
+fn setup_withdraw_queue<T>(
+	owner: &T::AccountId,
+	q: u32,
+) -> (u64, WorkerPublicKey, Vec<T::AccountId>)
+where
+	T: Config + mining::Config<Currency = <T as Config>::Currency>,
+	BalanceOf<T>: FixedPointConvert + Display,
+{
+	let (pid, workers) = setup_pool::<T>(owner, 1);
+	contribute::<T>(pid, owner, pha(OWNER_STAKE));
+	let stakers: Vec<_> = (0..q).map(|i| funded_account::<T>("staker", i)).collect();
+	for staker in stakers.iter() {
+		contribute::<T>(pid, staker, pha(STAKER_STAKE));
+	}
+	start_mining::<T>(owner, pid, workers[0]);
+	for staker in stakers.iter() {
+		let shares = PoolStakers::<T>::get((pid, staker.clone()))
+			.expect("Staker must exist; qed.")
+			.shares;
+		Pallet::<T>::withdraw(RawOrigin::Signed(staker.clone()).into(), pid, shares)
+			.expect("Failed to withdraw");
+	}
+	(pid, workers[0], stakers)
+}
+
+benchmarks! {
+	where_clause { where
+		T: mining::Config<Currency = <T as Config>::Currency>,
+		BalanceOf<T>: FixedPointConvert + Display,
+	}
+
+	create {
+		let caller: T::AccountId = whitelisted_caller();
+		let pid = PoolCount::<T>::get();
+	}: _(RawOrigin::Signed(caller))
+	verify {
+		assert!(StakePools::<T>::contains_key(pid));
+	}
+
+	add_worker {
+		let caller: T::AccountId = whitelisted_caller();
+		let (pid, _) = setup_pool::<T>(&caller, T::MaxPoolWorkers::get() - 1);
+		let worker = worker_pubkey(u32::MAX);
+		register_worker::<T>(worker, Some(caller.clone()));
+	}: _(RawOrigin::Signed(caller), pid, worker)
+	verify {
+		assert_eq!(WorkerAssignments::<T>::get(&worker), Some(pid));
+	}
+
+	remove_worker {
+		let caller: T::AccountId = whitelisted_caller();
+		let (pid, workers) = setup_pool::<T>(&caller, T::MaxPoolWorkers::get());
+		let worker = workers[workers.len() - 1];
+	}: _(RawOrigin::Signed(caller), pid, worker)
+	verify {
+		assert_eq!(WorkerAssignments::<T>::get(&worker), None);
+	}
+
+	set_cap {
+		let caller: T::AccountId = whitelisted_caller();
+		let (pid, _) = setup_pool::<T>(&caller, 0);
+		let cap: BalanceOf<T> = pha(OWNER_STAKE);
+	}: _(RawOrigin::Signed(caller), pid, cap)
+	verify {
+		assert_eq!(StakePools::<T>::get(pid).unwrap().cap, Some(cap));
+	}
+
+	set_payout_pref {
+		let caller = funded_account::<T>("owner", 0);
+		let (pid, _) = setup_pool::<T>(&caller, 0);
+		// The increase to a pool with stake is scheduled
+		contribute::<T>(pid, &caller, pha(OWNER_STAKE));
+		let commission = T::MaxCommissionIncrease::get();
+	}: _(RawOrigin::Signed(caller), pid, commission)
+	verify {
+		let pool_info = StakePools::<T>::get(pid).unwrap();
+		assert_eq!(pool_info.pending_commission.unwrap().commission, commission);
+	}
+
+	add_staker_to_whitelist {
+		let caller: T::AccountId = whitelisted_caller();
+		let (pid, _) = setup_pool::<T>(&caller, 0);
+		let whitelist: Vec<T::AccountId> =
+			(0..MAX_WHITELIST_LEN - 1).map(|i| account("staker", i, SEED)).collect();
+		PoolContributionWhitelists::<T>::insert(pid, whitelist);
+		let staker: T::AccountId = account("staker", MAX_WHITELIST_LEN, SEED);
+	}: _(RawOrigin::Signed(caller), pid, staker.clone())
+	verify {
+		assert!(PoolContributionWhitelists::<T>::get(pid).unwrap().contains(&staker));
+	}
+
+	set_pool_description {
+		let d in 0 .. DescMaxLen::get();
+		let caller: T::AccountId = whitelisted_caller();
+		let (pid, _) = setup_pool::<T>(&caller, 0);
+		let description: BoundedVec<u8, DescMaxLen> = vec![b'x'; d as usize]
+			.try_into()
+			.expect("Description is within the limit; qed.");
+	}: _(RawOrigin::Signed(caller), pid, description)
+	verify {
+		assert!(PoolDescriptions::<T>::contains_key(pid));
+	}
+
+	remove_staker_from_whitelist {
+		let caller: T::AccountId = whitelisted_caller();
+		let (pid, _) = setup_pool::<T>(&caller, 0);
+		let whitelist: Vec<T::AccountId> =
+			(0..MAX_WHITELIST_LEN).map(|i| account("staker", i, SEED)).collect();
+		let staker = whitelist[whitelist.len() - 1].clone();
+		PoolContributionWhitelists::<T>::insert(pid, whitelist);
+	}: _(RawOrigin::Signed(caller), pid, staker.clone())
+	verify {
+		assert!(!PoolContributionWhitelists::<T>::get(pid).unwrap().contains(&staker));
+	}
+
+	force_assign_reward {
+		let n in 1 .. MAX_REWARD_ASSIGNMENTS;
+		let mut reward_arr = Vec::new();
+		for i in 0..n {
+			let owner = funded_account::<T>("owner", i);
+			let (pid, _) = setup_pool::<T>(&owner, 0);
+			Pallet::<T>::set_payout_pref(
+				RawOrigin::Signed(owner.clone()).into(),
+				pid,
+				Permill::from_percent(50),
+			)?;
+			contribute::<T>(pid, &owner, pha(OWNER_STAKE));
+			reward_arr.push((pid, pha(100)));
+		}
+		let first_pid = reward_arr[0].0;
+		let origin = T::MiningSwitchOrigin::successful_origin();
+	}: _<T::Origin>(origin, reward_arr)
+	verify {
+		assert!(StakePools::<T>::get(first_pid).unwrap().owner_reward > Zero::zero());
+	}
+
+	claim_owner_rewards {
+		let caller = funded_account::<T>("owner", 0);
+		let (pid, _) = setup_pool::<T>(&caller, 0);
+		Pallet::<T>::set_payout_pref(
+			RawOrigin::Signed(caller.clone()).into(),
+			pid,
+			Permill::from_percent(50),
+		)?;
+		contribute::<T>(pid, &caller, pha(OWNER_STAKE));
+		Pallet::<T>::force_assign_reward(
+			T::MiningSwitchOrigin::successful_origin(),
+			vec![(pid, pha(100))],
+		)?;
+		<T as Config>::Currency::make_free_balance_be(
+			&mining::Pallet::<T>::account_id(),
+			pha(1_000_000),
+		);
+	}: _(RawOrigin::Signed(caller.clone()), pid, caller.clone())
+	verify {
+		assert_eq!(StakePools::<T>::get(pid).unwrap().owner_reward, Zero::zero());
+	}
+
+	claim_staker_rewards {
+		let caller = funded_account::<T>("owner", 0);
+		let (pid, _) = setup_pool::<T>(&caller, 0);
+		Pallet::<T>::set_payout_pref(
+			RawOrigin::Signed(caller.clone()).into(),
+			pid,
+			Permill::from_percent(50),
+		)?;
+		contribute::<T>(pid, &caller, pha(OWNER_STAKE));
+		Pallet::<T>::force_assign_reward(
+			T::MiningSwitchOrigin::successful_origin(),
+			vec![(pid, pha(100))],
+		)?;
+		<T as Config>::Currency::make_free_balance_be(
+			&mining::Pallet::<T>::account_id(),
+			pha(1_000_000),
+		);
+	}: _(RawOrigin::Signed(caller.clone()), pid, caller.clone())
+	verify {
+		let user_info = PoolStakers::<T>::get((pid, caller)).unwrap();
+		assert_eq!(user_info.available_rewards, Zero::zero());
+	}
+
+	claim_rewards {
+		let caller = funded_account::<T>("owner", 0);
+		let (pid, _) = setup_pool::<T>(&caller, 0);
+		Pallet::<T>::set_payout_pref(
+			RawOrigin::Signed(caller.clone()).into(),
+			pid,
+			Permill::from_percent(50),
+		)?;
+		contribute::<T>(pid, &caller, pha(OWNER_STAKE));
+		Pallet::<T>::force_assign_reward(
+			T::MiningSwitchOrigin::successful_origin(),
+			vec![(pid, pha(100))],
+		)?;
+		<T as Config>::Currency::make_free_balance_be(
+			&mining::Pallet::<T>::account_id(),
+			pha(1_000_000),
+		);
+	}: _(RawOrigin::Signed(caller.clone()), pid, caller.clone())
+	verify {
+		assert_eq!(StakePools::<T>::get(pid).unwrap().owner_reward, Zero::zero());
+	}
+
+	contribute {
+		let q in 0 .. MAX_WITHDRAW_QUEUE_LEN;
+		let owner = funded_account::<T>("owner", 0);
+		let (pid, _, _) = setup_withdraw_queue::<T>(&owner, q);
+		let caller = funded_account::<T>("contributor", 0);
+		// Enough to fulfill all the queued withdrawals
+		let amount: BalanceOf<T> = pha(STAKER_STAKE * (q + 1));
+	}: _(RawOrigin::Signed(caller), pid, amount)
+	verify {
+		assert!(StakePools::<T>::get(pid).unwrap().withdraw_queue.is_empty());
+	}
+
+	withdraw {
+		// The owner's request is appended to the queue
+		let q in 0 .. MAX_WITHDRAW_QUEUE_LEN - 1;
+		let owner = funded_account::<T>("owner", 0);
+		let (pid, _, _) = setup_withdraw_queue::<T>(&owner, q);
+		let shares = PoolStakers::<T>::get((pid, owner.clone())).unwrap().shares;
+	}: _(RawOrigin::Signed(owner), pid, shares)
+	verify {
+		assert_eq!(StakePools::<T>::get(pid).unwrap().withdraw_queue.len(), q as usize + 1);
+	}
+
+	transfer_shares {
+		// The owner's request is appended to the queue
+		let q in 0 .. MAX_WITHDRAW_QUEUE_LEN - 1;
+		let owner = funded_account::<T>("owner", 0);
+		let (pid, _, _) = setup_withdraw_queue::<T>(&owner, q);
+		// The owner's withdraw request is the last one in the queue, reduced by the transfer
+		let shares = PoolStakers::<T>::get((pid, owner.clone())).unwrap().shares;
+		Pallet::<T>::withdraw(RawOrigin::Signed(owner.clone()).into(), pid, shares)?;
+		let receiver = funded_account::<T>("receiver", 0);
+		PoolContributionWhitelists::<T>::insert(pid, vec![receiver.clone()]);
+		let half = shares / 2u32.into();
+	}: _(RawOrigin::Signed(owner.clone()), pid, receiver.clone(), half)
+	verify {
+		let user_info = PoolStakers::<T>::get((pid, receiver)).unwrap();
+		assert_eq!(user_info.shares, half);
+	}
+
+	set_share_transfer {
+		let caller: T::AccountId = whitelisted_caller();
+		let (pid, _) = setup_pool::<T>(&caller, 0);
+	}: _(RawOrigin::Signed(caller), pid, false)
+	verify {
+		assert!(PoolShareTransferDisabled::<T>::get(pid));
+	}
+
+	start_mining {
+		let caller = funded_account::<T>("owner", 0);
+		let (pid, workers) = setup_pool::<T>(&caller, 1);
+		contribute::<T>(pid, &caller, pha(OWNER_STAKE));
+	}: _(RawOrigin::Signed(caller), pid, workers[0], pha(OWNER_STAKE))
+	verify {
+		assert_eq!(StakePools::<T>::get(pid).unwrap().free_stake, Zero::zero());
+	}
+
+	stop_mining {
+		let caller = funded_account::<T>("owner", 0);
+		let (pid, workers) = setup_pool::<T>(&caller, 1);
+		contribute::<T>(pid, &caller, pha(OWNER_STAKE));
+		start_mining::<T>(&caller, pid, workers[0]);
+	}: _(RawOrigin::Signed(caller), pid, workers[0])
+	verify {
+		assert!(StakePools::<T>::get(pid).unwrap().releasing_stake > Zero::zero());
+	}
+
+	reclaim_pool_worker {
+		let q in 0 .. MAX_WITHDRAW_QUEUE_LEN;
+		let owner = funded_account::<T>("owner", 0);
+		let (pid, worker, _) = setup_withdraw_queue::<T>(&owner, q);
+		Pallet::<T>::stop_mining(RawOrigin::Signed(owner.clone()).into(), pid, worker)?;
+		mining::CoolDownPeriod::<T>::put(0);
+		let caller: T::AccountId = whitelisted_caller();
+	}: _(RawOrigin::Signed(caller), pid, worker)
+	verify {
+		assert!(StakePools::<T>::get(pid).unwrap().withdraw_queue.is_empty());
+	}
+
+	set_mining_enable {
+		let origin = T::MiningSwitchOrigin::successful_origin();
+	}: _<T::Origin>(origin, true)
+	verify {
+		assert!(MiningEnabled::<T>::get());
+	}
+
+	restart_mining {
+		let caller = funded_account::<T>("owner", 0);
+		let (pid, workers) = setup_pool::<T>(&caller, 1);
+		contribute::<T>(pid, &caller, pha(OWNER_STAKE * 2));
+		Pallet::<T>::start_mining(
+			RawOrigin::Signed(caller.clone()).into(),
+			pid,
+			workers[0],
+			pha(OWNER_STAKE),
+		)?;
+		let stake: BalanceOf<T> = pha(OWNER_STAKE * 3 / 2);
+	}: _(RawOrigin::Signed(caller), pid, workers[0], stake)
+	verify {
+		let miner: T::AccountId = pool_sub_account(pid, &workers[0]);
+		assert_eq!(mining::Stakes::<T>::get(&miner), Some(stake));
+	}
+
+	impl_benchmark_test_suite!(Pallet, crate::mock::new_bench_ext(), crate::mock::Test);
+}
@@ -0,0 +1,369 @@
+//! Weights for pallet_stakepool
+//!
+//! PLACEHOLDER WEIGHTS, NOT GENERATED BY THE BENCHMARK CLI.
+//!
+//! The values are hand-estimated from the storage accesses of each call and have never been
+//! measured. They must be regenerated on the reference machine with
+//! `scripts/benchmark-pallets.sh stakepool` before being relied on.
+
+#![cfg_attr(rustfmt, rustfmt_skip)]
+#![allow(unused_parens)]
+#![allow(unused_imports)]
+
+use frame_support::{traits::Get, weights::{Weight, constants::RocksDbWeight}};
+use sp_std::marker::PhantomData;
+
+/// Weight functions needed for pallet_stakepool.
+pub trait WeightInfo {
+	fn create() -> Weight;
+	fn add_worker() -> Weight;
+	fn remove_worker() -> Weight;
+	fn set_cap() -> Weight;
+	fn set_payout_pref() -> Weight;
+	fn add_staker_to_whitelist() -> Weight;
+	fn set_pool_description(d: u32, ) -> Weight;
+	fn remove_staker_from_whitelist() -> Weight;
+	fn force_assign_reward(n: u32, ) -> Weight;
+	fn claim_owner_rewards() -> Weight;
+	fn claim_staker_rewards() -> Weight;
+	fn claim_rewards() -> Weight;
+	fn contribute(q: u32, ) -> Weight;
+	fn withdraw(q: u32, ) -> Weight;
+	fn transfer_shares(q: u32, ) -> Weight;
+	fn set_share_transfer() -> Weight;
+	fn start_mining() -> Weight;
+	fn stop_mining() -> Weight;
+	fn reclaim_pool_worker(q: u32, ) -> Weight;
+	fn set_mining_enable() -> Weight;
+	fn restart_mining() -> Weight;
+}
+
+/// Weights for pallet_stakepool using the Substrate node and recommended hardware.
+pub struct SubstrateWeight<T>(PhantomData<T>);
+impl<T: frame_system::Config> WeightInfo for SubstrateWeight<T> {
+	// Storage: PhalaStakePool PoolCount (r:1 w:1)
+	// Storage: PhalaStakePool StakePools (r:0 w:1)
+	fn create() -> Weight {
+		(24_000_000 as Weight)
+			.saturating_add(T::DbWeight::get().reads(1 as Weight))
+			.saturating_add(T::DbWeight::get().writes(2 as Weight))
+	}
+	// Storage: PhalaRegistry Workers (r:1 w:0)
+	// Storage: PhalaStakePool StakePools (r:1 w:1)
+	// Storage: PhalaMining MinerBindings (r:1 w:1)
+	// Storage: PhalaMining WorkerBindings (r:1 w:1)
+	// Storage: PhalaMining Miners (r:1 w:1)
+	// Storage: Timestamp Now (r:1 w:0)
+	// Storage: PhalaStakePool SubAccountPreimages (r:0 w:1)
+	// Storage: PhalaStakePool WorkerAssignments (r:0 w:1)
+	fn add_worker() -> Weight {
+		(72_000_000 as Weight)
+			.saturating_add(T::DbWeight::get().reads(6 as Weight))
+			.saturating_add(T::DbWeight::get().writes(7 as Weight))
+	}
+	// Storage: PhalaStakePool StakePools (r:1 w:1)
+	// Storage: PhalaStakePool WorkerAssignments (r:1 w:1)
+	// Storage: PhalaMining MinerBindings (r:1 w:1)
+	// Storage: PhalaMining Miners (r:1 w:0)
+	// Storage: PhalaMining WorkerBindings (r:0 w:1)
+	fn remove_worker() -> Weight {
+		(58_000_000 as Weight)
+			.saturating_add(T::DbWeight::get().reads(4 as Weight))
+			.saturating_add(T::DbWeight::get().writes(4 as Weight))
+	}
+	// Storage: PhalaStakePool StakePools (r:1 w:1)
+	fn set_cap() -> Weight {
+		(26_000_000 as Weight)
+			.saturating_add(T::DbWeight::get().reads(1 as Weight))
+			.saturating_add(T::DbWeight::get().writes(1 as Weight))
+	}
+	// Storage: PhalaStakePool StakePools (r:1 w:1)
+	fn set_payout_pref() -> Weight {
+		(26_000_000 as Weight)
+			.saturating_add(T::DbWeight::get().reads(1 as Weight))
+			.saturating_add(T::DbWeight::get().writes(1 as Weight))
+	}
+	// Storage: PhalaStakePool StakePools (r:1 w:0)
+	// Storage: PhalaStakePool PoolContributionWhitelists (r:1 w:1)
+	fn add_staker_to_whitelist() -> Weight {
+		(34_000_000 as Weight)
+			.saturating_add(T::DbWeight::get().reads(2 as Weight))
+			.saturating_add(T::DbWeight::get().writes(1 as Weight))
+	}
+	// Storage: PhalaStakePool StakePools (r:1 w:0)
+	// Storage: PhalaStakePool PoolDescriptions (r:0 w:1)
+	fn set_pool_description(d: u32, ) -> Weight {
+		(22_000_000 as Weight)
+			.saturating_add((1_000 as Weight).saturating_mul(d as Weight))
+			.saturating_add(T::DbWeight::get().reads(1 as Weight))
+			.saturating_add(T::DbWeight::get().writes(1 as Weight))
+	}
+	// Storage: PhalaStakePool StakePools (r:1 w:0)
+	// Storage: PhalaStakePool PoolContributionWhitelists (r:1 w:1)
+	fn remove_staker_from_whitelist() -> Weight {
+		(36_000_000 as Weight)
+			.saturating_add(T::DbWeight::get().reads(2 as Weight))
+			.saturating_add(T::DbWeight::get().writes(1 as Weight))
+	}
+	// Storage: PhalaStakePool StakePools (r:1 w:1)
+	fn force_assign_reward(n: u32, ) -> Weight {
+		(4_000_000 as Weight)
+			.saturating_add((17_000_000 as Weight).saturating_mul(n as Weight))
+			.saturating_add(T::DbWeight::get().reads((1 as Weight).saturating_mul(n as Weight)))
+			.saturating_add(T::DbWeight::get().writes((1 as Weight).saturating_mul(n as Weight)))
+	}
+	// Storage: PhalaStakePool StakePools (r:1 w:1)
+	// Storage: System Account (r:2 w:2)
+	fn claim_owner_rewards() -> Weight {
+		(62_000_000 as Weight)
+			.saturating_add(T::DbWeight::get().reads(3 as Weight))
+			.saturating_add(T::DbWeight::get().writes(3 as Weight))
+	}
+	// Storage: PhalaStakePool StakePools (r:1 w:1)
+	// Storage: PhalaStakePool PoolStakers (r:1 w:1)
+	// Storage: System Account (r:2 w:2)
+	fn claim_staker_rewards() -> Weight {
+		(68_000_000 as Weight)
+			.saturating_add(T::DbWeight::get().reads(4 as Weight))
+			.saturating_add(T::DbWeight::get().writes(4 as Weight))
+	}
+	// Storage: PhalaStakePool StakePools (r:1 w:1)
+	// Storage: PhalaStakePool PoolStakers (r:1 w:1)
+	// Storage: System Account (r:2 w:2)
+	fn claim_rewards() -> Weight {
+		(70_000_000 as Weight)
+			.saturating_add(T::DbWeight::get().reads(4 as Weight))
+			.saturating_add(T::DbWeight::get().writes(4 as Weight))
+	}
+	// Storage: PhalaStakePool StakePools (r:1 w:1)
+	// Storage: PhalaStakePool PoolContributionWhitelists (r:1 w:0)
+	// Storage: System Account (r:1 w:0)
+	// Storage: PhalaStakePool StakeLedger (r:1 w:1)
+	// Storage: PhalaStakePool PoolStakers (r:1 w:1)
+	// Storage: Balances Locks (r:1 w:1)
+	// Storage: Timestamp Now (r:1 w:0)
+	// Storage: System Account (r:0 w:1)
+	fn contribute(q: u32, ) -> Weight {
+		(96_000_000 as Weight)
+			.saturating_add((46_000_000 as Weight).saturating_mul(q as Weight))
+			.saturating_add(T::DbWeight::get().reads(7 as Weight))
+			.saturating_add(T::DbWeight::get().reads((3 as Weight).saturating_mul(q as Weight)))
+			.saturating_add(T::DbWeight::get().writes(5 as Weight))
+			.saturating_add(T::DbWeight::get().writes((4 as Weight).saturating_mul(q as Weight)))
+	}
+	// Storage: PhalaStakePool PoolStakers (r:1 w:1)
+	// Storage: PhalaStakePool StakePools (r:1 w:1)
+	// Storage: Timestamp Now (r:1 w:0)
+	// Storage: PhalaStakePool WithdrawalTimestamps (r:1 w:1)
+	// Storage: PhalaStakePool WithdrawalQueuedPools (r:1 w:1)
+	// Storage: PhalaStakePool StakeLedger (r:1 w:1)
+	// Storage: Balances Locks (r:0 w:1)
+	fn withdraw(q: u32, ) -> Weight {
+		(82_000_000 as Weight)
+			.saturating_add((1_000_000 as Weight).saturating_mul(q as Weight))
+			.saturating_add(T::DbWeight::get().reads(6 as Weight))
+			.saturating_add(T::DbWeight::get().writes(6 as Weight))
+	}
+	// Storage: PhalaStakePool StakePools (r:1 w:1)
+	// Storage: PhalaStakePool PoolShareTransferDisabled (r:1 w:0)
+	// Storage: PhalaStakePool PoolContributionWhitelists (r:1 w:0)
+	// Storage: PhalaStakePool PoolStakers (r:2 w:2)
+	// Storage: PhalaStakePool StakeLedger (r:2 w:2)
+	// Storage: Balances Locks (r:2 w:2)
+	// Storage: System Account (r:1 w:2)
+	fn transfer_shares(q: u32, ) -> Weight {
+		(118_000_000 as Weight)
+			.saturating_add((300_000 as Weight).saturating_mul(q as Weight))
+			.saturating_add(T::DbWeight::get().reads(10 as Weight))
+			.saturating_add(T::DbWeight::get().writes(9 as Weight))
+	}
+	// Storage: PhalaStakePool StakePools (r:1 w:0)
+	// Storage: PhalaStakePool PoolShareTransferDisabled (r:0 w:1)
+	fn set_share_transfer() -> Weight {
+		(24_000_000 as Weight)
+			.saturating_add(T::DbWeight::get().reads(1 as Weight))
+			.saturating_add(T::DbWeight::get().writes(1 as Weight))
+	}
+	// Storage: PhalaStakePool StakePools (r:1 w:1)
+	// Storage: PhalaMining MinerBindings (r:1 w:0)
+	// Storage: PhalaMining Miners (r:1 w:1)
+	// Storage: PhalaMining Stakes (r:1 w:1)
+	// Storage: PhalaRegistry Workers (r:1 w:0)
+	// Storage: PhalaMining TokenomicParameters (r:1 w:0)
+	// Storage: Timestamp Now (r:1 w:0)
+	// Storage: PhalaMining OnlineMiners (r:1 w:1)
+	// Storage: PhalaMining NextSessionId (r:0 w:1)
+	// Storage: PhalaMq OutboundMessages (r:0 w:1)
+	fn start_mining() -> Weight {
+		(64_000_000 as Weight)
+			.saturating_add(T::DbWeight::get().reads(8 as Weight))
+			.saturating_add(T::DbWeight::get().writes(6 as Weight))
+	}
+	// Storage: PhalaStakePool MiningEnabled (r:1 w:0)
+	// Storage: PhalaStakePool StakePools (r:1 w:1)
+	// Storage: PhalaMining MinerBindings (r:1 w:0)
+	// Storage: PhalaMining Miners (r:1 w:1)
+	// Storage: Timestamp Now (r:1 w:0)
+	// Storage: PhalaMining OnlineMiners (r:1 w:1)
+	// Storage: PhalaMining Stakes (r:1 w:0)
+	// Storage: PhalaStakePool WorkerAssignments (r:1 w:0)
+	// Storage: PhalaMq OutboundMessages (r:0 w:1)
+	fn stop_mining() -> Weight {
+		(60_000_000 as Weight)
+			.saturating_add(T::DbWeight::get().reads(8 as Weight))
+			.saturating_add(T::DbWeight::get().writes(4 as Weight))
+	}
+	// Storage: PhalaStakePool StakePools (r:1 w:1)
+	// Storage: PhalaMining Miners (r:1 w:1)
+	// Storage: Timestamp Now (r:1 w:0)
+	// Storage: PhalaMining CoolDownPeriod (r:1 w:0)
+	// Storage: PhalaMining Stakes (r:1 w:1)
+	fn reclaim_pool_worker(q: u32, ) -> Weight {
+		(70_000_000 as Weight)
+			.saturating_add((44_000_000 as Weight).saturating_mul(q as Weight))
+			.saturating_add(T::DbWeight::get().reads(5 as Weight))
+			.saturating_add(T::DbWeight::get().reads((3 as Weight).saturating_mul(q as Weight)))
+			.saturating_add(T::DbWeight::get().writes(3 as Weight))
+			.saturating_add(T::DbWeight::get().writes((3 as Weight).saturating_mul(q as Weight)))
+	}
+	// Storage: PhalaStakePool MiningEnabled (r:0 w:1)
+	fn set_mining_enable() -> Weight {
+		(12_000_000 as Weight)
+			.saturating_add(T::DbWeight::get().writes(1 as Weight))
+	}
+	// Storage: PhalaStakePool StakePools (r:1 w:1)
+	// Storage: PhalaStakePool MiningEnabled (r:1 w:0)
+	// Storage: PhalaMining MinerBindings (r:1 w:0)
+	// Storage: PhalaMining Miners (r:1 w:1)
+	// Storage: Timestamp Now (r:1 w:0)
+	// Storage: PhalaMining OnlineMiners (r:1 w:1)
+	// Storage: PhalaMining Stakes (r:1 w:1)
+	// Storage: PhalaStakePool WorkerAssignments (r:1 w:0)
+	// Storage: PhalaRegistry Workers (r:1 w:0)
+	// Storage: PhalaMining TokenomicParameters (r:1 w:0)
+	// Storage: PhalaMining NextSessionId (r:1 w:1)
+	// Storage: PhalaMq OutboundMessages (r:0 w:2)
+	fn restart_mining() -> Weight {
+		(142_000_000 as Weight)
+			.saturating_add(T::DbWeight::get().reads(12 as Weight))
+			.saturating_add(T::DbWeight::get().writes(9 as Weight))
+	}
+}
+
+// For backwards compatibility and tests
+impl WeightInfo for () {
+	fn create() -> Weight {
+		(24_000_000 as Weight)
+			.saturating_add(RocksDbWeight::get().reads(1 as Weight))
+			.saturating_add(RocksDbWeight::get().writes(2 as Weight))
+	}
+	fn add_worker() -> Weight {
+		(72_000_000 as Weight)
+			.saturating_add(RocksDbWeight::get().reads(6 as Weight))
+			.saturating_add(RocksDbWeight::get().writes(7 as Weight))
+	}
+	fn remove_worker() -> Weight {
+		(58_000_000 as Weight)
+			.saturating_add(RocksDbWeight::get().reads(4 as Weight))
+			.saturating_add(RocksDbWeight::get().writes(4 as Weight))
+	}
+	fn set_cap() -> Weight {
+		(26_000_000 as Weight)
+			.saturating_add(RocksDbWeight::get().reads(1 as Weight))
+			.saturating_add(RocksDbWeight::get().writes(1 as Weight))
+	}
+	fn set_payout_pref() -> Weight {
+		(26_000_000 as Weight)
+			.saturating_add(RocksDbWeight::get().reads(1 as Weight))
+			.saturating_add(RocksDbWeight::get().writes(1 as Weight))
+	}
+	fn add_staker_to_whitelist() -> Weight {
+		(34_000_000 as Weight)
+			.saturating_add(RocksDbWeight::get().reads(2 as Weight))
+			.saturating_add(RocksDbWeight::get().writes(1 as Weight))
+	}
+	fn set_pool_description(d: u32, ) -> Weight {
+		(22_000_000 as Weight)
+			.saturating_add((1_000 as Weight).saturating_mul(d as Weight))
+			.saturating_add(RocksDbWeight::get().reads(1 as Weight))
+			.saturating_add(RocksDbWeight::get().writes(1 as Weight))
+	}
+	fn remove_staker_from_whitelist() -> Weight {
+		(36_000_000 as Weight)
+			.saturating_add(RocksDbWeight::get().reads(2 as Weight))
+			.saturating_add(RocksDbWeight::get().writes(1 as Weight))
+	}
+	fn force_assign_reward(n: u32, ) -> Weight {
+		(4_000_000 as Weight)
+			.saturating_add((17_000_000 as Weight).saturating_mul(n as Weight))
+			.saturating_add(RocksDbWeight::get().reads((1 as Weight).saturating_mul(n as Weight)))
+			.saturating_add(RocksDbWeight::get().writes((1 as Weight).saturating_mul(n as Weight)))
+	}
+	fn claim_owner_rewards() -> Weight {
+		(62_000_000 as Weight)
+			.saturating_add(RocksDbWeight::get().reads(3 as Weight))
+			.saturating_add(RocksDbWeight::get().writes(3 as Weight))
+	}
+	fn claim_staker_rewards() -> Weight {
+		(68_000_000 as Weight)
+			.saturating_add(RocksDbWeight::get().reads(4 as Weight))
+			.saturating_add(RocksDbWeight::get().writes(4 as Weight))
+	}
+	fn claim_rewards() -> Weight {
+		(70_000_000 as Weight)
+			.saturating_add(RocksDbWeight::get().reads(4 as Weight))
+			.saturating_add(RocksDbWeight::get().writes(4 as Weight))
+	}
+	fn contribute(q: u32, ) -> Weight {
+		(96_000_000 as Weight)
+			.saturating_add((46_000_000 as Weight).saturating_mul(q as Weight))
+			.saturating_add(RocksDbWeight::get().reads(7 as Weight))
+			.saturating_add(RocksDbWeight::get().reads((3 as Weight).saturating_mul(q as Weight)))
+			.saturating_add(RocksDbWeight::get().writes(5 as Weight))
+			.saturating_add(RocksDbWeight::get().writes((4 as Weight).saturating_mul(q as Weight)))
+	}
+	fn withdraw(q: u32, ) -> Weight {
+		(82_000_000 as Weight)
+			.saturating_add((1_000_000 as Weight).saturating_mul(q as Weight))
+			.saturating_add(RocksDbWeight::get().reads(6 as Weight))
+			.saturating_add(RocksDbWeight::get().writes(6 as Weight))
+	}
+	fn transfer_shares(q: u32, ) -> Weight {
+		(118_000_000 as Weight)
+			.saturating_add((300_000 as Weight).saturating_mul(q as Weight))
+			.saturating_add(RocksDbWeight::get().reads(10 as Weight))
+			.saturating_add(RocksDbWeight::get().writes(9 as Weight))
+	}
+	fn set_share_transfer() -> Weight {
+		(24_000_000 as Weight)
+			.saturating_add(RocksDbWeight::get().reads(1 as Weight))
+			.saturating_add(RocksDbWeight::get().writes(1 as Weight))
+	}
+	fn start_mining() -> Weight {
+		(64_000_000 as Weight)
+			.saturating_add(RocksDbWeight::get().reads(8 as Weight))
+			.saturating_add(RocksDbWeight::get().writes(6 as Weight))
+	}
+	fn stop_mining() -> Weight {
+		(60_000_000 as Weight)
+			.saturating_add(RocksDbWeight::get().reads(8 as Weight))
+			.saturating_add(RocksDbWeight::get().writes(4 as Weight))
+	}
+	fn reclaim_pool_worker(q: u32, ) -> Weight {
+		(70_000_000 as Weight)
+			.saturating_add((44_000_000 as Weight).saturating_mul(q as Weight))
+			.saturating_add(RocksDbWeight::get().reads(5 as Weight))
+			.saturating_add(RocksDbWeight::get().reads((3 as Weight).saturating_mul(q as Weight)))
+			.saturating_add(RocksDbWeight::get().writes(3 as Weight))
+			.saturating_add(RocksDbWeight::get().writes((3 as Weight).saturating_mul(q as Weight)))
+	}
+	fn set_mining_enable() -> Weight {
+		(12_000_000 as Weight)
+			.saturating_add(RocksDbWeight::get().writes(1 as Weight))
+	}
+	fn restart_mining() -> Weight {
+		(142_000_000 as Weight)
+			.saturating_add(RocksDbWeight::get().reads(12 as Weight))
+			.saturating_add(RocksDbWeight::get().writes(9 as Weight))
+	}
+}
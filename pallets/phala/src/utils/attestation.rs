@@ -1,5 +1,8 @@
 use crate::constants::*;
 
+mod dcap;
+pub use dcap::{validate_dcap_quote, SgxQuoteCollateral};
+
 use codec::{Decode, Encode};
 use scale_info::TypeInfo;
 use sp_std::{
@@ -15,16 +18,34 @@ pub enum Attestation {
 		signature: Vec<u8>,
 		raw_signing_cert: Vec<u8>,
 	},
+	SgxDcap {
+		quote: Vec<u8>,
+		collateral: SgxQuoteCollateral,
+	},
+}
+
+impl Attestation {
+	/// Returns the extended mrenclave of the attested enclave, without validating the attestation.
+	pub fn extend_mrenclave(&self) -> Result<Vec<u8>, Error> {
+		match self {
+			Attestation::SgxIas { ra_report, .. } => IasFields::from_ias_report(ra_report)
+				.map(|(ias_fields, _)| ias_fields.extend_mrenclave()),
+			Attestation::SgxDcap { quote, .. } => dcap::quote_extend_mrenclave(quote),
+		}
+	}
 }
 
 pub trait AttestationValidator {
 	/// Validates the attestation as well as the user data hash it commits to.
+	///
+	/// The DCAP quotes must chain up to one of `dcap_trusted_root_certs`, in DER.
 	fn validate(
 		attestation: &Attestation,
 		user_data_hash: &[u8; 32],
 		now: u64,
 		verify_pruntime_hash: bool,
 		pruntime_allowlist: Vec<Vec<u8>>,
+		dcap_trusted_root_certs: Vec<Vec<u8>>,
 	) -> Result<IasFields, Error>;
 }
 
@@ -38,6 +59,15 @@ pub enum Error {
 	OutdatedIASReport,
 	UnknownQuoteBodyFormat,
 	InvalidUserDataHash,
+	UnsupportedDcapQuoteFormat,
+	InvalidDcapQuoteSignature,
+	InvalidDcapCertChain,
+	InvalidDcapCollateral,
+	OutdatedDcapCollateral,
+	RevokedDcapCert,
+	QuotingEnclaveRejected,
+	/// The enclave is launched in debug mode, so its memory can be inspected
+	DebugEnclave,
 }
 
 #[derive(Encode, Decode, TypeInfo, Debug, Clone, PartialEq, Eq)]
@@ -128,15 +158,19 @@ impl IasFields {
 	}
 }
 
-/// Attestation validator implementation for IAS
-pub struct IasValidator;
-impl AttestationValidator for IasValidator {
+/// Attestation validator implementation for IAS reports and DCAP quotes
+pub struct SgxValidator;
+
+#[deprecated(note = "validates DCAP quotes as well, use `SgxValidator` instead")]
+pub type IasValidator = SgxValidator;
+impl AttestationValidator for SgxValidator {
 	fn validate(
 		attestation: &Attestation,
 		user_data_hash: &[u8; 32],
 		now: u64,
 		verify_pruntime: bool,
 		pruntime_allowlist: Vec<Vec<u8>>,
+		dcap_trusted_root_certs: Vec<Vec<u8>>,
 	) -> Result<IasFields, Error> {
 		let fields = match attestation {
 			Attestation::SgxIas {
@@ -151,6 +185,14 @@ impl AttestationValidator for IasValidator {
 				verify_pruntime,
 				pruntime_allowlist,
 			),
+			Attestation::SgxDcap { quote, collateral } => validate_dcap_quote(
+				quote,
+				collateral,
+				&dcap_trusted_root_certs,
+				now,
+				verify_pruntime,
+				pruntime_allowlist,
+			),
 		}?;
 		let commit = &fields.report_data[..32];
 		if commit != user_data_hash {
@@ -204,6 +246,110 @@ pub fn validate_ias_report(
 	Ok(ias_fields)
 }
 
+#[cfg(feature = "runtime-benchmarks")]
+pub mod benchmarking {
+	use super::*;
+
+	const ATTESTATION_SAMPLE: &[u8] = include_bytes!("../../sample/ias_attestation.json");
+	/// The time when the sample report was issued.
+	pub const ATTESTATION_TIMESTAMP: u64 = 1631441180;
+	/// The extended mrenclave of the pRuntime in the sample report.
+	pub const PRUNTIME_HASH: &str = "518422fa769d2d55982015a0e0417c6a8521fdfc7308f5ec18aaa1b6924bd0f300000000815f42f11cf64430c30bab7816ba596a1da0130c3b028b673133a66cf9a3e0e6";
+
+	/// Returns a real IAS attestation to feed the benchmarks.
+	pub fn sample_attestation() -> Attestation {
+		let sample: serde_json::Value =
+			serde_json::from_slice(ATTESTATION_SAMPLE).expect("Bad attestation sample");
+		let field = |name: &str| {
+			sample[name]
+				.as_str()
+				.expect("Bad attestation sample")
+				.as_bytes()
+				.to_vec()
+		};
+		Attestation::SgxIas {
+			ra_report: field("raReport"),
+			signature: hex::decode(field("signature")).expect("Bad attestation sample"),
+			raw_signing_cert: hex::decode(field("rawSigningCert"))
+				.expect("Bad attestation sample"),
+		}
+	}
+
+	const DCAP_ATTESTATION_SAMPLE: &[u8] = include_bytes!("../../sample/dcap_attestation.json");
+	/// The time when the collateral of the sample quote was issued.
+	pub const DCAP_ATTESTATION_TIMESTAMP: u64 = 1664582400;
+
+	/// Returns a DCAP attestation signed by a test PKI to feed the benchmarks.
+	///
+	/// Also returns the root certificate of the test PKI and the extended mrenclave in the quote.
+	pub fn sample_dcap_attestation() -> (Attestation, Vec<u8>, Vec<u8>) {
+		let sample: serde_json::Value =
+			serde_json::from_slice(DCAP_ATTESTATION_SAMPLE).expect("Bad attestation sample");
+		let text = |name: &str| {
+			sample[name]
+				.as_str()
+				.expect("Bad attestation sample")
+				.as_bytes()
+				.to_vec()
+		};
+		let bytes = |name: &str| hex::decode(text(name)).expect("Bad attestation sample");
+		let attestation = Attestation::SgxDcap {
+			quote: bytes("quote"),
+			collateral: SgxQuoteCollateral {
+				root_ca_crl: bytes("rootCaCrl"),
+				pck_crl: bytes("pckCrl"),
+				tcb_info_issuer_chain: text("tcbInfoIssuerChain"),
+				tcb_info: text("tcbInfo"),
+				tcb_info_signature: bytes("tcbInfoSignature"),
+				qe_identity_issuer_chain: text("qeIdentityIssuerChain"),
+				qe_identity: text("qeIdentity"),
+				qe_identity_signature: bytes("qeIdentitySignature"),
+			},
+		};
+		(attestation, bytes("rootCert"), bytes("pruntimeHash"))
+	}
+
+	/// Attestation validator for the runtime benchmarks
+	///
+	/// It does the full validation of the attestations at the time the samples were issued, but
+	/// doesn't check the user data hash, since the benchmarks can't produce a report committing
+	/// to an arbitrary worker.
+	pub struct BenchmarkIasValidator;
+	impl AttestationValidator for BenchmarkIasValidator {
+		fn validate(
+			attestation: &Attestation,
+			_user_data_hash: &[u8; 32],
+			_now: u64,
+			verify_pruntime: bool,
+			pruntime_allowlist: Vec<Vec<u8>>,
+			dcap_trusted_root_certs: Vec<Vec<u8>>,
+		) -> Result<IasFields, Error> {
+			match attestation {
+				Attestation::SgxIas {
+					ra_report,
+					signature,
+					raw_signing_cert,
+				} => validate_ias_report(
+					ra_report,
+					signature,
+					raw_signing_cert,
+					ATTESTATION_TIMESTAMP,
+					verify_pruntime,
+					pruntime_allowlist,
+				),
+				Attestation::SgxDcap { quote, collateral } => validate_dcap_quote(
+					quote,
+					collateral,
+					&dcap_trusted_root_certs,
+					DCAP_ATTESTATION_TIMESTAMP,
+					verify_pruntime,
+					pruntime_allowlist,
+				),
+			}
+		}
+	}
+}
+
 #[cfg(test)]
 mod test {
 	use super::*;
@@ -212,6 +358,10 @@ mod test {
 	pub const ATTESTATION_SAMPLE: &[u8] = include_bytes!("../../sample/ias_attestation.json");
 	pub const ATTESTATION_TIMESTAMP: u64 = 1631441180; // 2021-09-12T18:06:20.402478
 	pub const PRUNTIME_HASH: &str = "518422fa769d2d55982015a0e0417c6a8521fdfc7308f5ec18aaa1b6924bd0f300000000815f42f11cf64430c30bab7816ba596a1da0130c3b028b673133a66cf9a3e0e6";
+	/// A quote and its collateral signed by a test PKI.
+	///
+	/// Generated by `scripts/gen-dcap-attestation-sample.py`.
+	pub const DCAP_ATTESTATION_SAMPLE: &[u8] = include_bytes!("../../sample/dcap_attestation.json");
 
 	#[test]
 	fn test_ias_validator() {
@@ -255,4 +405,119 @@ mod test {
 			vec![hex::decode(PRUNTIME_HASH).unwrap()]
 		));
 	}
+
+	#[test]
+	fn test_dcap_validator() {
+		let sample: serde_json::Value = serde_json::from_slice(DCAP_ATTESTATION_SAMPLE).unwrap();
+		let text = |name: &str| sample[name].as_str().unwrap().as_bytes().to_vec();
+		let bytes = |name: &str| hex::decode(text(name)).unwrap();
+
+		let now = sample["timestamp"].as_u64().unwrap();
+		let root_certs = vec![bytes("rootCert")];
+		let pruntime_hash = bytes("pruntimeHash");
+		let quote = bytes("quote");
+		let collateral = SgxQuoteCollateral {
+			root_ca_crl: bytes("rootCaCrl"),
+			pck_crl: bytes("pckCrl"),
+			tcb_info_issuer_chain: text("tcbInfoIssuerChain"),
+			tcb_info: text("tcbInfo"),
+			tcb_info_signature: bytes("tcbInfoSignature"),
+			qe_identity_issuer_chain: text("qeIdentityIssuerChain"),
+			qe_identity: text("qeIdentity"),
+			qe_identity_signature: bytes("qeIdentitySignature"),
+		};
+
+		assert_eq!(
+			validate_dcap_quote(&quote, &collateral, &[], now, false, vec![]),
+			Err(Error::InvalidDcapCertChain)
+		);
+
+		assert_eq!(
+			validate_dcap_quote(
+				&quote,
+				&collateral,
+				&root_certs,
+				now + 31 * 86400,
+				false,
+				vec![]
+			),
+			Err(Error::OutdatedDcapCollateral)
+		);
+
+		assert_eq!(
+			validate_dcap_quote(&quote, &collateral, &root_certs, now, true, vec![]),
+			Err(Error::PRuntimeRejected)
+		);
+
+		let mut tampered_quote = quote.clone();
+		tampered_quote[48 + 320] ^= 1;
+		assert_eq!(
+			validate_dcap_quote(
+				&tampered_quote,
+				&collateral,
+				&root_certs,
+				now,
+				false,
+				vec![]
+			),
+			Err(Error::InvalidDcapQuoteSignature)
+		);
+
+		let mut debug_quote = quote.clone();
+		debug_quote[48 + 48] |= 0x02;
+		assert_eq!(
+			validate_dcap_quote(&debug_quote, &collateral, &root_certs, now, false, vec![]),
+			Err(Error::DebugEnclave)
+		);
+
+		let mut tampered_collateral = collateral.clone();
+		tampered_collateral.tcb_info = String::from_utf8(collateral.tcb_info.clone())
+			.unwrap()
+			.replace("SWHardeningNeeded", "UpToDate")
+			.into_bytes();
+		assert_eq!(
+			validate_dcap_quote(
+				&quote,
+				&tampered_collateral,
+				&root_certs,
+				now,
+				false,
+				vec![]
+			),
+			Err(Error::InvalidDcapCollateral)
+		);
+
+		let revoking_collateral = SgxQuoteCollateral {
+			pck_crl: bytes("revokingPckCrl"),
+			..collateral.clone()
+		};
+		assert_eq!(
+			validate_dcap_quote(
+				&quote,
+				&revoking_collateral,
+				&root_certs,
+				now,
+				false,
+				vec![]
+			),
+			Err(Error::RevokedDcapCert)
+		);
+
+		let fields = validate_dcap_quote(
+			&quote,
+			&collateral,
+			&root_certs,
+			now,
+			true,
+			vec![pruntime_hash.clone()],
+		)
+		.unwrap();
+		assert_eq!(fields.extend_mrenclave(), pruntime_hash);
+		// SWHardeningNeeded with a whitelisted advisory
+		assert_eq!(fields.confidence_level, 2);
+		assert_eq!(
+			Attestation::SgxDcap { quote, collateral }.extend_mrenclave(),
+			Ok(pruntime_hash)
+		);
+	}
 }
@@ -0,0 +1,775 @@
+//! Validation of the SGX DCAP ECDSA quotes
+//!
+//! A DCAP quote is signed by the attestation key of the Quoting Enclave (QE), whose own report is
+//! signed by the Provisioning Certification Key (PCK) certified by Intel. The collateral, fetched
+//! from a PCCS along with the quote, tells the TCB status of the platform and the expected identity
+//! of the QE. Everything must chain up to one of the trusted root certificates configured on chain.
+
+use super::{Error, IasFields};
+use crate::constants::*;
+
+use codec::{Decode, Encode};
+use hex_literal::hex;
+use scale_info::TypeInfo;
+use sp_std::{
+	convert::{TryFrom, TryInto},
+	vec::Vec,
+};
+
+/// The collateral to validate a DCAP quote, as served by the PCCS
+#[derive(Encode, Decode, TypeInfo, Debug, Clone, PartialEq, Eq)]
+pub struct SgxQuoteCollateral {
+	/// The DER encoded CRL of the root CA, revoking the intermediate CAs
+	pub root_ca_crl: Vec<u8>,
+	/// The DER encoded CRL of the PCK CA, revoking the PCK certificates
+	pub pck_crl: Vec<u8>,
+	/// The PEM encoded certificate chain of the TCB info signing key
+	pub tcb_info_issuer_chain: Vec<u8>,
+	/// The `tcbInfo` JSON object of the platform, exactly as signed
+	pub tcb_info: Vec<u8>,
+	/// The raw `r || s` ECDSA signature of `tcb_info`
+	pub tcb_info_signature: Vec<u8>,
+	/// The PEM encoded certificate chain of the QE identity signing key
+	pub qe_identity_issuer_chain: Vec<u8>,
+	/// The `enclaveIdentity` JSON object of the QE, exactly as signed
+	pub qe_identity: Vec<u8>,
+	/// The raw `r || s` ECDSA signature of `qe_identity`
+	pub qe_identity_signature: Vec<u8>,
+}
+
+const QUOTE_HEADER_LEN: usize = 48;
+const REPORT_BODY_LEN: usize = 384;
+const QUOTE_VERSION: u16 = 3;
+const ATTESTATION_KEY_TYPE_ECDSA_P256: u16 = 2;
+const CERT_DATA_TYPE_PCK_CERT_CHAIN: u16 = 5;
+const INTEL_QE_VENDOR_ID: [u8; 16] = hex!("939a7233f79c4ca9940a0db3957f0607");
+
+/// OID 1.2.840.113741.1.13.1 of the SGX extension in the PCK certificates
+const SGX_EXTENSION_OID: &[u8] = &hex!("2a864886f84d010d01");
+/// OID 1.2.840.113741.1.13.1.2 of the TCB in the SGX extension
+const SGX_TCB_OID: &[u8] = &hex!("2a864886f84d010d0102");
+
+// Offsets of the fields in an enclave report body
+const MISC_SELECT: core::ops::Range<usize> = 16..20;
+const ATTRIBUTES: core::ops::Range<usize> = 48..64;
+/// The DEBUG flag in the first byte of the attributes
+const ATTRIBUTE_DEBUG: u8 = 0x02;
+const MR_ENCLAVE: core::ops::Range<usize> = 64..96;
+const MR_SIGNER: core::ops::Range<usize> = 128..160;
+const ISV_PROD_ID: core::ops::Range<usize> = 256..258;
+const ISV_SVN: core::ops::Range<usize> = 258..260;
+const REPORT_DATA: core::ops::Range<usize> = 320..384;
+
+/// Validates a DCAP quote against its collateral, returning the fields of the attested enclave
+///
+/// Unlike the IAS reports, a quote carries no timestamp. Instead, the collateral must not have
+/// expired at `now`, which is in seconds.
+pub fn validate_dcap_quote(
+	quote: &[u8],
+	collateral: &SgxQuoteCollateral,
+	trusted_root_certs: &[Vec<u8>],
+	now: u64,
+	verify_pruntime: bool,
+	pruntime_allowlist: Vec<Vec<u8>>,
+) -> Result<IasFields, Error> {
+	let quote = Quote::parse(quote)?;
+
+	// The memory of a debug enclave is open to the host, so it can't be trusted whatever it runs
+	if quote.report_body[ATTRIBUTES][0] & ATTRIBUTE_DEBUG != 0 {
+		return Err(Error::DebugEnclave);
+	}
+
+	// Validate the PCK certificate and the platform it certifies
+	let pck_cert = verify_cert_chain(&quote.pck_cert_chain, trusted_root_certs, now)?;
+	let pck_ext = parse_cert(&quote.pck_cert_chain[0])
+		.and_then(|cert| find_extension(cert.extensions, SGX_EXTENSION_OID))
+		.and_then(parse_sgx_extension)
+		.ok_or(Error::InvalidDcapCertChain)?;
+
+	// Validate the signatures from the PCK down to the enclave
+	if !verify_raw_signature(&pck_cert, quote.qe_report, quote.qe_report_signature) {
+		return Err(Error::InvalidDcapQuoteSignature);
+	}
+	let mut key_hash_data = quote.attestation_key.to_vec();
+	key_hash_data.extend_from_slice(quote.qe_auth_data);
+	let key_hash = sp_io::hashing::sha2_256(&key_hash_data);
+	let qe_report_data = &quote.qe_report[REPORT_DATA];
+	if qe_report_data[..32] != key_hash || qe_report_data[32..].iter().any(|b| *b != 0) {
+		return Err(Error::InvalidDcapQuoteSignature);
+	}
+	let mut attestation_key = [4u8; 65];
+	attestation_key[1..].copy_from_slice(quote.attestation_key);
+	ring::signature::UnparsedPublicKey::new(
+		&ring::signature::ECDSA_P256_SHA256_FIXED,
+		&attestation_key[..],
+	)
+	.verify(quote.signed_data, quote.isv_signature)
+	.or(Err(Error::InvalidDcapQuoteSignature))?;
+
+	// Validate the collateral
+	let tcb_info_chain =
+		parse_pem_certs(&collateral.tcb_info_issuer_chain).ok_or(Error::InvalidDcapCollateral)?;
+	let tcb_info_signer = verify_cert_chain(&tcb_info_chain, trusted_root_certs, now)?;
+	if !verify_raw_signature(
+		&tcb_info_signer,
+		&collateral.tcb_info,
+		&collateral.tcb_info_signature,
+	) {
+		return Err(Error::InvalidDcapCollateral);
+	}
+	let qe_identity_chain = parse_pem_certs(&collateral.qe_identity_issuer_chain)
+		.ok_or(Error::InvalidDcapCollateral)?;
+	let qe_identity_signer = verify_cert_chain(&qe_identity_chain, trusted_root_certs, now)?;
+	if !verify_raw_signature(
+		&qe_identity_signer,
+		&collateral.qe_identity,
+		&collateral.qe_identity_signature,
+	) {
+		return Err(Error::InvalidDcapCollateral);
+	}
+
+	// Check the revocation of all the certificates involved
+	let root_ca_crl = parse_crl(&collateral.root_ca_crl).ok_or(Error::InvalidDcapCollateral)?;
+	let root_ca_crl_signed = trusted_root_certs.iter().any(|root| {
+		webpki::EndEntityCert::try_from(root.as_slice())
+			.map(|root| root_ca_crl.verify(&root))
+			.unwrap_or(false)
+	});
+	if !root_ca_crl_signed {
+		return Err(Error::InvalidDcapCollateral);
+	}
+	let pck_ca = quote
+		.pck_cert_chain
+		.get(1)
+		.and_then(|cert| webpki::EndEntityCert::try_from(cert.as_slice()).ok())
+		.ok_or(Error::InvalidDcapCertChain)?;
+	let pck_crl = parse_crl(&collateral.pck_crl).ok_or(Error::InvalidDcapCollateral)?;
+	if !pck_crl.verify(&pck_ca) {
+		return Err(Error::InvalidDcapCollateral);
+	}
+	if root_ca_crl.next_update <= now || pck_crl.next_update <= now {
+		return Err(Error::OutdatedDcapCollateral);
+	}
+	if pck_crl.is_revoked(&quote.pck_cert_chain[0]) {
+		return Err(Error::RevokedDcapCert);
+	}
+	let root_ca_issued = quote.pck_cert_chain[1..]
+		.iter()
+		.chain(tcb_info_chain.iter())
+		.chain(qe_identity_chain.iter())
+		.filter(|cert| !trusted_root_certs.contains(cert));
+	for cert in root_ca_issued {
+		if root_ca_crl.is_revoked(cert) {
+			return Err(Error::RevokedDcapCert);
+		}
+	}
+
+	// Evaluate the TCB status of the platform and the QE
+	let tcb_info: serde_json::Value =
+		serde_json::from_slice(&collateral.tcb_info).or(Err(Error::InvalidDcapCollateral))?;
+	let mut confidence_level = platform_confidence_level(&tcb_info, &pck_ext, now)?;
+	let qe_identity: serde_json::Value =
+		serde_json::from_slice(&collateral.qe_identity).or(Err(Error::InvalidDcapCollateral))?;
+	confidence_level =
+		confidence_level.max(qe_confidence_level(&qe_identity, quote.qe_report, now)?);
+
+	let fields = report_fields(quote.report_body, confidence_level);
+
+	// Validate PRuntime
+	if verify_pruntime {
+		let t_mrenclave = fields.extend_mrenclave();
+		if !pruntime_allowlist.contains(&t_mrenclave) {
+			return Err(Error::PRuntimeRejected);
+		}
+	}
+
+	Ok(fields)
+}
+
+/// Returns the extended mrenclave of the enclave in the quote, without validating anything
+pub fn quote_extend_mrenclave(quote: &[u8]) -> Result<Vec<u8>, Error> {
+	let quote = Quote::parse(quote)?;
+	Ok(report_fields(quote.report_body, 0).extend_mrenclave())
+}
+
+/// Extracts the fields of an enclave report body
+fn report_fields(body: &[u8], confidence_level: u8) -> IasFields {
+	IasFields {
+		mr_enclave: body[MR_ENCLAVE].try_into().unwrap(),
+		mr_signer: body[MR_SIGNER].try_into().unwrap(),
+		isv_prod_id: body[ISV_PROD_ID].try_into().unwrap(),
+		isv_svn: body[ISV_SVN].try_into().unwrap(),
+		report_data: body[REPORT_DATA].try_into().unwrap(),
+		confidence_level,
+	}
+}
+
+/// A cursor over the little-endian fields of a quote
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+	fn take(&mut self, len: usize) -> Result<&'a [u8], Error> {
+		if self.0.len() < len {
+			return Err(Error::UnsupportedDcapQuoteFormat);
+		}
+		let (head, tail) = self.0.split_at(len);
+		self.0 = tail;
+		Ok(head)
+	}
+
+	fn u16(&mut self) -> Result<u16, Error> {
+		Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+	}
+
+	fn u32(&mut self) -> Result<u32, Error> {
+		Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+	}
+}
+
+/// A version 3 ECDSA quote
+struct Quote<'a> {
+	/// The header and the report body, signed by the attestation key
+	signed_data: &'a [u8],
+	report_body: &'a [u8],
+	isv_signature: &'a [u8],
+	attestation_key: &'a [u8],
+	qe_report: &'a [u8],
+	qe_report_signature: &'a [u8],
+	qe_auth_data: &'a [u8],
+	/// The DER encoded PCK certificate chain, leaf first
+	pck_cert_chain: Vec<Vec<u8>>,
+}
+
+impl<'a> Quote<'a> {
+	fn parse(quote: &'a [u8]) -> Result<Self, Error> {
+		let mut reader = Reader(quote);
+		let header = reader.take(QUOTE_HEADER_LEN)?;
+		let report_body = reader.take(REPORT_BODY_LEN)?;
+		let version = u16::from_le_bytes([header[0], header[1]]);
+		let key_type = u16::from_le_bytes([header[2], header[3]]);
+		if version != QUOTE_VERSION
+			|| key_type != ATTESTATION_KEY_TYPE_ECDSA_P256
+			|| header[12..28] != INTEL_QE_VENDOR_ID
+		{
+			return Err(Error::UnsupportedDcapQuoteFormat);
+		}
+		let signature_len = reader.u32()? as usize;
+		let mut reader = Reader(reader.take(signature_len)?);
+		let isv_signature = reader.take(64)?;
+		let attestation_key = reader.take(64)?;
+		let qe_report = reader.take(REPORT_BODY_LEN)?;
+		let qe_report_signature = reader.take(64)?;
+		let qe_auth_data_len = reader.u16()? as usize;
+		let qe_auth_data = reader.take(qe_auth_data_len)?;
+		let cert_data_type = reader.u16()?;
+		let cert_data_len = reader.u32()? as usize;
+		let cert_data = reader.take(cert_data_len)?;
+		if cert_data_type != CERT_DATA_TYPE_PCK_CERT_CHAIN {
+			return Err(Error::UnsupportedDcapQuoteFormat);
+		}
+		let pck_cert_chain = parse_pem_certs(cert_data).ok_or(Error::InvalidDcapCertChain)?;
+		Ok(Quote {
+			signed_data: &quote[..QUOTE_HEADER_LEN + REPORT_BODY_LEN],
+			report_body,
+			isv_signature,
+			attestation_key,
+			qe_report,
+			qe_report_signature,
+			qe_auth_data,
+			pck_cert_chain,
+		})
+	}
+}
+
+/// Decodes the certificates in a PEM bundle
+fn parse_pem_certs(pem: &[u8]) -> Option<Vec<Vec<u8>>> {
+	const BEGIN: &[u8] = b"-----BEGIN CERTIFICATE-----";
+	const END: &[u8] = b"-----END CERTIFICATE-----";
+	let find = |haystack: &[u8], needle: &[u8]| {
+		haystack
+			.windows(needle.len())
+			.position(|window| window == needle)
+	};
+	let mut certs = Vec::new();
+	let mut rest = pem;
+	while let Some(begin) = find(rest, BEGIN) {
+		rest = &rest[begin + BEGIN.len()..];
+		let end = find(rest, END)?;
+		let encoded: Vec<u8> = rest[..end]
+			.iter()
+			.copied()
+			.filter(|c| !c.is_ascii_whitespace())
+			.collect();
+		certs.push(base64::decode(&encoded).ok()?);
+		rest = &rest[end + END.len()..];
+	}
+	if certs.is_empty() {
+		None
+	} else {
+		Some(certs)
+	}
+}
+
+/// Verifies the certificate chain, leaf first, up to one of the trusted root certificates
+fn verify_cert_chain<'a>(
+	chain: &'a [Vec<u8>],
+	trusted_root_certs: &[Vec<u8>],
+	now: u64,
+) -> Result<webpki::EndEntityCert<'a>, Error> {
+	let (leaf, intermediates) = chain.split_first().ok_or(Error::InvalidDcapCertChain)?;
+	let anchors: Vec<_> = trusted_root_certs
+		.iter()
+		.filter_map(|cert| webpki::TrustAnchor::try_from_cert_der(cert).ok())
+		.collect();
+	let intermediates: Vec<&[u8]> = intermediates
+		.iter()
+		.filter(|cert| !trusted_root_certs.contains(cert))
+		.map(|cert| cert.as_slice())
+		.collect();
+	let leaf =
+		webpki::EndEntityCert::try_from(leaf.as_slice()).or(Err(Error::InvalidDcapCertChain))?;
+	leaf.verify_is_valid_tls_server_cert(
+		DCAP_SIG_ALGS,
+		&webpki::TlsServerTrustAnchors(&anchors),
+		&intermediates,
+		webpki::Time::from_seconds_since_unix_epoch(now),
+	)
+	.or(Err(Error::InvalidDcapCertChain))?;
+	Ok(leaf)
+}
+
+/// Verifies a raw `r || s` P-256 ECDSA signature made by the key of `cert`
+fn verify_raw_signature(cert: &webpki::EndEntityCert, message: &[u8], signature: &[u8]) -> bool {
+	if signature.len() != 64 {
+		return false;
+	}
+	// webpki only takes the ASN.1 DER encoded signatures
+	let der_integer = |bytes: &[u8]| {
+		let mut bytes = bytes;
+		while bytes.len() > 1 && bytes[0] == 0 {
+			bytes = &bytes[1..];
+		}
+		let mut encoded = Vec::with_capacity(bytes.len() + 3);
+		encoded.push(der::INTEGER);
+		if bytes[0] & 0x80 != 0 {
+			encoded.extend_from_slice(&[bytes.len() as u8 + 1, 0]);
+		} else {
+			encoded.push(bytes.len() as u8);
+		}
+		encoded.extend_from_slice(bytes);
+		encoded
+	};
+	let r = der_integer(&signature[..32]);
+	let s = der_integer(&signature[32..]);
+	let mut encoded = Vec::with_capacity(r.len() + s.len() + 2);
+	encoded.extend_from_slice(&[der::SEQUENCE, (r.len() + s.len()) as u8]);
+	encoded.extend_from_slice(&r);
+	encoded.extend_from_slice(&s);
+	cert.verify_signature(&webpki::ECDSA_P256_SHA256, message, &encoded)
+		.is_ok()
+}
+
+/// A minimal DER reader, enough to walk through the certificates and CRLs issued by Intel
+mod der {
+	pub const BOOLEAN: u8 = 0x01;
+	pub const INTEGER: u8 = 0x02;
+	pub const BIT_STRING: u8 = 0x03;
+	pub const OCTET_STRING: u8 = 0x04;
+	pub const OID: u8 = 0x06;
+	pub const UTC_TIME: u8 = 0x17;
+	pub const GENERALIZED_TIME: u8 = 0x18;
+	pub const SEQUENCE: u8 = 0x30;
+	pub const CONTEXT_0: u8 = 0xa0;
+	pub const CONTEXT_3: u8 = 0xa3;
+
+	pub struct Reader<'a>(pub &'a [u8]);
+
+	impl<'a> Reader<'a> {
+		pub fn is_empty(&self) -> bool {
+			self.0.is_empty()
+		}
+
+		pub fn peek(&self) -> Option<u8> {
+			self.0.first().copied()
+		}
+
+		/// Reads an element, returning its tag, its content and its whole encoding
+		pub fn read_any(&mut self) -> Option<(u8, &'a [u8], &'a [u8])> {
+			let tag = *self.0.first()?;
+			let first = *self.0.get(1)? as usize;
+			let (len, header_len) = if first < 0x80 {
+				(first, 2)
+			} else {
+				let len_len = first & 0x7f;
+				if len_len == 0 || len_len > 4 {
+					return None;
+				}
+				let len = self
+					.0
+					.get(2..2 + len_len)?
+					.iter()
+					.fold(0usize, |len, b| (len << 8) | *b as usize);
+				(len, 2 + len_len)
+			};
+			let encoded = self.0.get(..header_len.checked_add(len)?)?;
+			self.0 = &self.0[encoded.len()..];
+			Some((tag, &encoded[header_len..], encoded))
+		}
+
+		pub fn read(&mut self, tag: u8) -> Option<&'a [u8]> {
+			match self.read_any()? {
+				(t, content, _) if t == tag => Some(content),
+				_ => None,
+			}
+		}
+
+		/// Skips the element if it has the given tag
+		pub fn skip_optional(&mut self, tag: u8) -> Option<()> {
+			if self.peek() == Some(tag) {
+				self.read(tag)?;
+			}
+			Some(())
+		}
+	}
+
+	/// Decodes a non-negative integer fitting in `u32`
+	pub fn uint(content: &[u8]) -> Option<u32> {
+		let content = match content {
+			[0, rest @ ..] if !rest.is_empty() => rest,
+			_ => content,
+		};
+		if content.is_empty() || content.len() > 4 {
+			return None;
+		}
+		Some(content.iter().fold(0, |n, b| (n << 8) | *b as u32))
+	}
+
+	/// Decodes an `UTCTime` or a `GeneralizedTime` to seconds since the unix epoch
+	pub fn time(tag: u8, content: &[u8]) -> Option<u64> {
+		let digits = |range: core::ops::Range<usize>| -> Option<u32> {
+			content.get(range)?.iter().try_fold(0u32, |n, c| {
+				c.is_ascii_digit().then(|| n * 10 + (c - b'0') as u32)
+			})
+		};
+		let (year, rest) = match tag {
+			UTC_TIME => match digits(0..2)? {
+				year if year >= 50 => (1900 + year, 2),
+				year => (2000 + year, 2),
+			},
+			GENERALIZED_TIME => (digits(0..4)?, 4),
+			_ => return None,
+		};
+		if content.get(rest + 10..) != Some(&b"Z"[..]) {
+			return None;
+		}
+		let timestamp = chrono::NaiveDate::from_ymd_opt(
+			year as i32,
+			digits(rest..rest + 2)?,
+			digits(rest + 2..rest + 4)?,
+		)?
+		.and_hms_opt(
+			digits(rest + 4..rest + 6)?,
+			digits(rest + 6..rest + 8)?,
+			digits(rest + 8..rest + 10)?,
+		)?
+		.timestamp();
+		u64::try_from(timestamp).ok()
+	}
+}
+
+/// The fields of a certificate not covered by webpki
+struct CertFields<'a> {
+	serial: &'a [u8],
+	/// The content of the extensions sequence
+	extensions: &'a [u8],
+}
+
+fn parse_cert(cert: &[u8]) -> Option<CertFields> {
+	let mut cert = der::Reader(der::Reader(cert).read(der::SEQUENCE)?);
+	let mut tbs = der::Reader(cert.read(der::SEQUENCE)?);
+	tbs.skip_optional(der::CONTEXT_0)?;
+	let serial = tbs.read(der::INTEGER)?;
+	// signature, issuer, validity, subject and subjectPublicKeyInfo
+	for _ in 0..5 {
+		tbs.read(der::SEQUENCE)?;
+	}
+	let mut extensions: &[u8] = &[];
+	while let Some((tag, content, _)) = tbs.read_any() {
+		if tag == der::CONTEXT_3 {
+			extensions = der::Reader(content).read(der::SEQUENCE)?;
+		}
+	}
+	Some(CertFields { serial, extensions })
+}
+
+fn find_extension<'a>(extensions: &'a [u8], oid: &[u8]) -> Option<&'a [u8]> {
+	let mut reader = der::Reader(extensions);
+	while !reader.is_empty() {
+		let mut extension = der::Reader(reader.read(der::SEQUENCE)?);
+		let id = extension.read(der::OID)?;
+		extension.skip_optional(der::BOOLEAN)?;
+		let value = extension.read(der::OCTET_STRING)?;
+		if id == oid {
+			return Some(value);
+		}
+	}
+	None
+}
+
+/// The platform described by the SGX extension of a PCK certificate
+struct SgxExtension {
+	tcb_components: [u8; 16],
+	pce_svn: u16,
+	pce_id: Vec<u8>,
+	fmspc: Vec<u8>,
+}
+
+fn parse_sgx_extension(value: &[u8]) -> Option<SgxExtension> {
+	let mut items = der::Reader(der::Reader(value).read(der::SEQUENCE)?);
+	let mut tcb = None;
+	let mut pce_id = None;
+	let mut fmspc = None;
+	while !items.is_empty() {
+		let mut item = der::Reader(items.read(der::SEQUENCE)?);
+		match item.read(der::OID)?.strip_prefix(SGX_EXTENSION_OID) {
+			Some([2]) => tcb = Some(parse_sgx_tcb(item.read(der::SEQUENCE)?)?),
+			Some([3]) => pce_id = Some(item.read(der::OCTET_STRING)?.to_vec()),
+			Some([4]) => fmspc = Some(item.read(der::OCTET_STRING)?.to_vec()),
+			_ => {}
+		}
+	}
+	let (tcb_components, pce_svn) = tcb?;
+	Some(SgxExtension {
+		tcb_components,
+		pce_svn,
+		pce_id: pce_id?,
+		fmspc: fmspc?,
+	})
+}
+
+fn parse_sgx_tcb(tcb: &[u8]) -> Option<([u8; 16], u16)> {
+	let mut items = der::Reader(tcb);
+	let mut components = [0u8; 16];
+	let mut pce_svn = None;
+	while !items.is_empty() {
+		let mut item = der::Reader(items.read(der::SEQUENCE)?);
+		match item.read(der::OID)?.strip_prefix(SGX_TCB_OID) {
+			Some(&[n]) if (1..=16).contains(&n) => {
+				components[n as usize - 1] = der::uint(item.read(der::INTEGER)?)?.try_into().ok()?
+			}
+			Some([17]) => pce_svn = Some(der::uint(item.read(der::INTEGER)?)?.try_into().ok()?),
+			_ => {}
+		}
+	}
+	Some((components, pce_svn?))
+}
+
+/// A certificate revocation list
+struct Crl<'a> {
+	/// The encoded `tbsCertList`, covered by the signature
+	tbs: &'a [u8],
+	signature: &'a [u8],
+	next_update: u64,
+	revoked_serials: Vec<&'a [u8]>,
+}
+
+fn parse_crl(crl: &[u8]) -> Option<Crl> {
+	let mut crl = der::Reader(der::Reader(crl).read(der::SEQUENCE)?);
+	let (tag, tbs_content, tbs) = crl.read_any()?;
+	if tag != der::SEQUENCE {
+		return None;
+	}
+	crl.read(der::SEQUENCE)?;
+	// The first byte of a bit string is the number of the unused bits
+	let signature = match crl.read(der::BIT_STRING)? {
+		[0, signature @ ..] => signature,
+		_ => return None,
+	};
+	let mut tbs_reader = der::Reader(tbs_content);
+	tbs_reader.skip_optional(der::INTEGER)?;
+	// signature and issuer
+	tbs_reader.read(der::SEQUENCE)?;
+	tbs_reader.read(der::SEQUENCE)?;
+	let (tag, this_update, _) = tbs_reader.read_any()?;
+	der::time(tag, this_update)?;
+	let (tag, next_update, _) = tbs_reader.read_any()?;
+	let next_update = der::time(tag, next_update)?;
+	let mut revoked_serials = Vec::new();
+	if tbs_reader.peek() == Some(der::SEQUENCE) {
+		let mut entries = der::Reader(tbs_reader.read(der::SEQUENCE)?);
+		while !entries.is_empty() {
+			let mut entry = der::Reader(entries.read(der::SEQUENCE)?);
+			revoked_serials.push(entry.read(der::INTEGER)?);
+		}
+	}
+	Some(Crl {
+		tbs,
+		signature,
+		next_update,
+		revoked_serials,
+	})
+}
+
+impl<'a> Crl<'a> {
+	/// Checks the CRL is signed by `issuer`, which always uses P-256 ECDSA in Intel's PKI
+	fn verify(&self, issuer: &webpki::EndEntityCert) -> bool {
+		issuer
+			.verify_signature(&webpki::ECDSA_P256_SHA256, self.tbs, self.signature)
+			.is_ok()
+	}
+
+	fn is_revoked(&self, cert: &[u8]) -> bool {
+		match parse_cert(cert) {
+			Some(cert) => self.revoked_serials.contains(&cert.serial),
+			None => true,
+		}
+	}
+}
+
+/// Maps a TCB status to the confidence level, taking the advisories into account
+fn confidence_level_of(status: &str, advisory_ids: &[&str]) -> Result<u8, Error> {
+	let mut confidence_level = if DCAP_TCB_STATUS_LEVEL_1.contains(&status) {
+		1
+	} else if DCAP_TCB_STATUS_LEVEL_2.contains(&status) {
+		2
+	} else if DCAP_TCB_STATUS_LEVEL_3.contains(&status) {
+		3
+	} else if DCAP_TCB_STATUS_LEVEL_5.contains(&status) {
+		5
+	} else {
+		return Err(Error::InvalidQuoteStatus);
+	};
+	if confidence_level < 5
+		&& advisory_ids
+			.iter()
+			.any(|id| !IAS_QUOTE_ADVISORY_ID_WHITELIST.contains(id))
+	{
+		confidence_level = 4;
+	}
+	Ok(confidence_level)
+}
+
+/// Checks the signed JSON collateral has not expired at `now`
+fn ensure_up_to_date(collateral: &serde_json::Value, now: u64) -> Result<(), Error> {
+	let next_update = collateral["nextUpdate"]
+		.as_str()
+		.ok_or(Error::InvalidDcapCollateral)?;
+	let next_update = chrono::DateTime::parse_from_rfc3339(next_update)
+		.or(Err(Error::InvalidDcapCollateral))?
+		.timestamp();
+	if next_update <= now as i64 {
+		return Err(Error::OutdatedDcapCollateral);
+	}
+	Ok(())
+}
+
+fn json_hex(value: &serde_json::Value) -> Result<Vec<u8>, Error> {
+	value
+		.as_str()
+		.and_then(|value| hex::decode(value).ok())
+		.ok_or(Error::InvalidDcapCollateral)
+}
+
+/// Finds the confidence level of the platform in the TCB info, of either version 2 or 3
+fn platform_confidence_level(
+	tcb_info: &serde_json::Value,
+	pck_ext: &SgxExtension,
+	now: u64,
+) -> Result<u8, Error> {
+	ensure_up_to_date(tcb_info, now)?;
+	if json_hex(&tcb_info["fmspc"])? != pck_ext.fmspc
+		|| json_hex(&tcb_info["pceId"])? != pck_ext.pce_id
+	{
+		return Err(Error::InvalidDcapCollateral);
+	}
+	let levels = tcb_info["tcbLevels"]
+		.as_array()
+		.ok_or(Error::InvalidDcapCollateral)?;
+	// The levels are sorted from the latest to the oldest
+	for level in levels {
+		let tcb = &level["tcb"];
+		let svn = |value: &serde_json::Value| value.as_u64().ok_or(Error::InvalidDcapCollateral);
+		let mut components = [0u64; 16];
+		for (i, component) in components.iter_mut().enumerate() {
+			*component = match tcb["sgxtcbcomponents"].as_array() {
+				Some(v3_components) => {
+					let v3_component = v3_components.get(i).ok_or(Error::InvalidDcapCollateral)?;
+					svn(&v3_component["svn"])?
+				}
+				None => {
+					let mut name = *b"sgxtcbcomp00svn";
+					name[10] += (i as u8 + 1) / 10;
+					name[11] += (i as u8 + 1) % 10;
+					svn(&tcb[core::str::from_utf8(&name).unwrap()])?
+				}
+			};
+		}
+		let pce_svn = svn(&tcb["pcesvn"])?;
+		let matched = pck_ext
+			.tcb_components
+			.iter()
+			.zip(components.iter())
+			.all(|(platform, level)| *platform as u64 >= *level)
+			&& pck_ext.pce_svn as u64 >= pce_svn;
+		if matched {
+			let status = level["tcbStatus"]
+				.as_str()
+				.ok_or(Error::InvalidDcapCollateral)?;
+			let advisory_ids: Vec<&str> = match level["advisoryIDs"].as_array() {
+				Some(ids) => ids
+					.iter()
+					.map(|id| id.as_str().ok_or(Error::InvalidDcapCollateral))
+					.collect::<Result<_, _>>()?,
+				None => Vec::new(),
+			};
+			return confidence_level_of(status, &advisory_ids);
+		}
+	}
+	Err(Error::InvalidQuoteStatus)
+}
+
+/// Checks the QE against its identity, returning the confidence level of its TCB
+fn qe_confidence_level(
+	qe_identity: &serde_json::Value,
+	qe_report: &[u8],
+	now: u64,
+) -> Result<u8, Error> {
+	ensure_up_to_date(qe_identity, now)?;
+	let masked = |value: &[u8], mask: &[u8]| -> Vec<u8> {
+		value.iter().zip(mask).map(|(v, m)| v & m).collect()
+	};
+	// `miscselect` is a number in hex, while the report keeps it in little-endian
+	let mut misc_select = json_hex(&qe_identity["miscselect"])?;
+	let mut misc_select_mask = json_hex(&qe_identity["miscselectMask"])?;
+	misc_select.reverse();
+	misc_select_mask.reverse();
+	let attributes_mask = json_hex(&qe_identity["attributesMask"])?;
+	let isv_prod_id = qe_identity["isvprodid"]
+		.as_u64()
+		.ok_or(Error::InvalidDcapCollateral)?;
+	let report_isv_prod_id = u16::from_le_bytes(qe_report[ISV_PROD_ID].try_into().unwrap());
+	let report_isv_svn = u16::from_le_bytes(qe_report[ISV_SVN].try_into().unwrap());
+	if masked(&qe_report[MISC_SELECT], &misc_select_mask) != masked(&misc_select, &misc_select_mask)
+		|| masked(&qe_report[ATTRIBUTES], &attributes_mask)
+			!= masked(&json_hex(&qe_identity["attributes"])?, &attributes_mask)
+		|| qe_report[MR_SIGNER] != json_hex(&qe_identity["mrsigner"])?[..]
+		|| report_isv_prod_id as u64 != isv_prod_id
+	{
+		return Err(Error::QuotingEnclaveRejected);
+	}
+	let levels = qe_identity["tcbLevels"]
+		.as_array()
+		.ok_or(Error::InvalidDcapCollateral)?;
+	for level in levels {
+		let isv_svn = level["tcb"]["isvsvn"]
+			.as_u64()
+			.ok_or(Error::InvalidDcapCollateral)?;
+		if report_isv_svn as u64 >= isv_svn {
+			let status = level["tcbStatus"]
+				.as_str()
+				.ok_or(Error::InvalidDcapCollateral)?;
+			return confidence_level_of(status, &[]).or(Err(Error::QuotingEnclaveRejected));
+		}
+	}
+	Err(Error::QuotingEnclaveRejected)
+}
@@ -14,6 +14,14 @@ pub const IAS_QUOTE_ADVISORY_ID_WHITELIST: &[&str] = &[
 	"INTEL-SA-00381",
 	"INTEL-SA-00389",
 ];
+pub const DCAP_TCB_STATUS_LEVEL_1: &[&str] = &["UpToDate"];
+pub const DCAP_TCB_STATUS_LEVEL_2: &[&str] = &["SWHardeningNeeded"];
+pub const DCAP_TCB_STATUS_LEVEL_3: &[&str] = &[
+	"ConfigurationNeeded",
+	"ConfigurationAndSWHardeningNeeded",
+];
+// LEVEL 4 is the TCB levels below 5 with advisories not included in the whitelist
+pub const DCAP_TCB_STATUS_LEVEL_5: &[&str] = &["OutOfDate", "OutOfDateConfigurationNeeded"];
 pub type SignatureAlgorithms = &'static [&'static webpki::SignatureAlgorithm];
 pub static SUPPORTED_SIG_ALGS: SignatureAlgorithms = &[
 	// &webpki::ECDSA_P256_SHA256,
@@ -25,6 +33,12 @@ pub static SUPPORTED_SIG_ALGS: SignatureAlgorithms = &[
 	&webpki::RSA_PKCS1_2048_8192_SHA512,
 	&webpki::RSA_PKCS1_3072_8192_SHA384,
 ];
+pub static DCAP_SIG_ALGS: SignatureAlgorithms = &[
+	&webpki::ECDSA_P256_SHA256,
+	&webpki::ECDSA_P256_SHA384,
+	&webpki::ECDSA_P384_SHA256,
+	&webpki::ECDSA_P384_SHA384,
+];
 
 pub static IAS_SERVER_ROOTS: webpki::TlsServerTrustAnchors = webpki::TlsServerTrustAnchors(&[
     /*
@@ -0,0 +1,28 @@
+#!/bin/bash
+
+# Regenerates the weights of the phala pallets on the reference machine.
+#
+# Usage: ./scripts/benchmark-pallets.sh [pallet...]
+# e.g. ./scripts/benchmark-pallets.sh stakepool fat
+
+set -e
+
+PALLETS=${@:-mq registry mining stakepool fat}
+NODE=./target/release/phala-node
+
+cargo build --release -p phala-node --features runtime-benchmarks
+
+for pallet in $PALLETS; do
+    echo "Benchmarking $pallet"
+    $NODE benchmark pallet \
+        --chain=dev \
+        --steps=50 \
+        --repeat=20 \
+        --execution=wasm \
+        --wasm-execution=compiled \
+        --heap-pages=4096 \
+        --pallet="pallet_$pallet" \
+        --extrinsic='*' \
+        --output="pallets/phala/src/$pallet/weights.rs" \
+        --template=scripts/frame-weight-template.hbs
+done
@@ -0,0 +1,294 @@
+#!/usr/bin/env python3
+"""Generates a DCAP attestation sample signed by a throwaway test PKI.
+
+The PKI mirrors the layout of Intel's SGX PKI: a root CA, a PCK platform CA issuing the PCK
+certificate with the SGX extension, and a TCB signing certificate signing the TCB info and the QE
+identity. The output is consumed by the DCAP tests of the phala pallets.
+
+Usage: gen-dcap-attestation-sample.py > pallets/phala/sample/dcap_attestation.json
+
+Requires the `cryptography` package.
+"""
+
+import datetime
+import hashlib
+import json
+import os
+import struct
+import sys
+
+from cryptography import x509
+from cryptography.hazmat.primitives import hashes, serialization
+from cryptography.hazmat.primitives.asymmetric import ec, utils
+from cryptography.x509.oid import NameOID
+
+# Collateral issued at 2022-10-01T00:00:00Z, valid for 30 days
+ISSUE_DATE = datetime.datetime(2022, 10, 1, tzinfo=datetime.timezone.utc)
+NEXT_UPDATE = ISSUE_DATE + datetime.timedelta(days=30)
+
+SGX_EXTENSION_OID = "1.2.840.113741.1.13.1"
+INTEL_QE_VENDOR_ID = bytes.fromhex("939a7233f79c4ca9940a0db3957f0607")
+
+FMSPC = bytes.fromhex("00906ed50000")
+PCE_ID = bytes.fromhex("0000")
+PLATFORM_TCB_COMPONENTS = [15, 15, 2, 4, 1, 128, 11, 0, 0, 0, 0, 0, 0, 0, 0, 0]
+PLATFORM_PCE_SVN = 11
+QE_MR_SIGNER = bytes.fromhex("8c4f5775d796503e96137f77c68a829a0056ac8ded70140b081b094490c57bff")
+QE_ISV_SVN = 8
+
+MR_ENCLAVE = hashlib.sha256(b"pruntime").digest()
+MR_SIGNER = hashlib.sha256(b"phala").digest()
+USER_DATA_HASH = hashlib.sha256(b"runtime info").digest()
+
+
+def der(tag, content):
+    if len(content) < 0x80:
+        length = bytes([len(content)])
+    else:
+        encoded = len(content).to_bytes((len(content).bit_length() + 7) // 8, "big")
+        length = bytes([0x80 | len(encoded)]) + encoded
+    return bytes([tag]) + length + content
+
+
+def der_oid(oid):
+    arcs = [int(arc) for arc in oid.split(".")]
+    encoded = bytes([arcs[0] * 40 + arcs[1]])
+    for arc in arcs[2:]:
+        chunk = [arc & 0x7F]
+        arc >>= 7
+        while arc:
+            chunk.insert(0, 0x80 | (arc & 0x7F))
+            arc >>= 7
+        encoded += bytes(chunk)
+    return der(0x06, encoded)
+
+
+def der_int(value):
+    return der(0x02, value.to_bytes(value.bit_length() // 8 + 1, "big"))
+
+
+def der_seq(*items):
+    return der(0x30, b"".join(items))
+
+
+def sgx_extension():
+    tcb = [
+        der_seq(der_oid(f"{SGX_EXTENSION_OID}.2.{i + 1}"), der_int(svn))
+        for i, svn in enumerate(PLATFORM_TCB_COMPONENTS)
+    ]
+    tcb.append(der_seq(der_oid(f"{SGX_EXTENSION_OID}.2.17"), der_int(PLATFORM_PCE_SVN)))
+    cpusvn = der(0x04, bytes(PLATFORM_TCB_COMPONENTS))
+    tcb.append(der_seq(der_oid(f"{SGX_EXTENSION_OID}.2.18"), cpusvn))
+    return der_seq(
+        der_seq(der_oid(f"{SGX_EXTENSION_OID}.1"), der(0x04, os.urandom(16))),
+        der_seq(der_oid(f"{SGX_EXTENSION_OID}.2"), der_seq(*tcb)),
+        der_seq(der_oid(f"{SGX_EXTENSION_OID}.3"), der(0x04, PCE_ID)),
+        der_seq(der_oid(f"{SGX_EXTENSION_OID}.4"), der(0x04, FMSPC)),
+        der_seq(der_oid(f"{SGX_EXTENSION_OID}.5"), der(0x0A, b"\x00")),
+    )
+
+
+def name(common_name):
+    return x509.Name([
+        x509.NameAttribute(NameOID.COMMON_NAME, common_name),
+        x509.NameAttribute(NameOID.ORGANIZATION_NAME, "Phala Network Test"),
+        x509.NameAttribute(NameOID.COUNTRY_NAME, "US"),
+    ])
+
+
+def issue(subject, key, issuer, issuer_key, path_length=None, extensions=()):
+    ca = path_length is not None
+    builder = (
+        x509.CertificateBuilder()
+        .subject_name(name(subject))
+        .issuer_name(name(issuer))
+        .public_key(key.public_key())
+        .serial_number(x509.random_serial_number())
+        .not_valid_before(ISSUE_DATE - datetime.timedelta(days=365))
+        .not_valid_after(ISSUE_DATE + datetime.timedelta(days=3650))
+        .add_extension(x509.BasicConstraints(ca=ca, path_length=path_length), critical=True)
+        .add_extension(x509.SubjectKeyIdentifier.from_public_key(key.public_key()), critical=False)
+        .add_extension(
+            x509.AuthorityKeyIdentifier.from_issuer_public_key(issuer_key.public_key()),
+            critical=False,
+        )
+        .add_extension(
+            x509.KeyUsage(
+                digital_signature=not ca,
+                content_commitment=not ca,
+                key_encipherment=False,
+                data_encipherment=False,
+                key_agreement=False,
+                key_cert_sign=ca,
+                crl_sign=ca,
+                encipher_only=False,
+                decipher_only=False,
+            ),
+            critical=True,
+        )
+    )
+    for extension in extensions:
+        builder = builder.add_extension(extension, critical=False)
+    return builder.sign(issuer_key, hashes.SHA256())
+
+
+def crl(issuer, issuer_key, revoked):
+    builder = (
+        x509.CertificateRevocationListBuilder()
+        .issuer_name(name(issuer))
+        .last_update(ISSUE_DATE)
+        .next_update(NEXT_UPDATE)
+    )
+    for serial in revoked:
+        builder = builder.add_revoked_certificate(
+            x509.RevokedCertificateBuilder()
+            .serial_number(serial)
+            .revocation_date(ISSUE_DATE)
+            .build()
+        )
+    return builder.sign(issuer_key, hashes.SHA256()).public_bytes(serialization.Encoding.DER)
+
+
+def raw_sign(key, data):
+    r, s = utils.decode_dss_signature(key.sign(data, ec.ECDSA(hashes.SHA256())))
+    return r.to_bytes(32, "big") + s.to_bytes(32, "big")
+
+
+def pem(*certs):
+    return b"".join(cert.public_bytes(serialization.Encoding.PEM) for cert in certs)
+
+
+def report_body(mr_enclave, mr_signer, isv_prod_id, isv_svn, report_data, attributes):
+    body = bytearray(384)
+    body[0:16] = bytes(PLATFORM_TCB_COMPONENTS)
+    body[48:64] = attributes
+    body[64:96] = mr_enclave
+    body[128:160] = mr_signer
+    body[256:258] = struct.pack("<H", isv_prod_id)
+    body[258:260] = struct.pack("<H", isv_svn)
+    body[320:384] = report_data
+    return bytes(body)
+
+
+def timestamp(time):
+    return time.strftime("%Y-%m-%dT%H:%M:%SZ")
+
+
+def main():
+    new_key = lambda: ec.generate_private_key(ec.SECP256R1())
+    root_key, pck_ca_key, pck_key, tcb_key, attestation_key = (new_key() for _ in range(5))
+
+    root_cert = issue("Test SGX Root CA", root_key, "Test SGX Root CA", root_key, 1)
+    pck_ca_cert = issue("Test SGX PCK Platform CA", pck_ca_key, "Test SGX Root CA", root_key, 0)
+    pck_cert = issue(
+        "Test SGX PCK Certificate",
+        pck_key,
+        "Test SGX PCK Platform CA",
+        pck_ca_key,
+        extensions=[
+            x509.UnrecognizedExtension(x509.ObjectIdentifier(SGX_EXTENSION_OID), sgx_extension()),
+        ],
+    )
+    tcb_cert = issue("Test SGX TCB Signing", tcb_key, "Test SGX Root CA", root_key)
+
+    # The quote
+    header = struct.pack("<HHIHH", 3, 2, 0, QE_ISV_SVN, PLATFORM_PCE_SVN)
+    header += INTEL_QE_VENDOR_ID + bytes(20)
+    enclave_report_data = USER_DATA_HASH + bytes(32)
+    # INIT and MODE64BIT, without DEBUG
+    attributes = bytes.fromhex("05" + "00" * 7 + "e7" + "00" * 7)
+    body = report_body(MR_ENCLAVE, MR_SIGNER, 0, 0, enclave_report_data, attributes)
+    isv_signature = raw_sign(attestation_key, header + body)
+    attestation_pubkey = attestation_key.public_key().public_bytes(
+        serialization.Encoding.X962, serialization.PublicFormat.UncompressedPoint
+    )[1:]
+    qe_auth_data = os.urandom(32)
+    qe_report_data = hashlib.sha256(attestation_pubkey + qe_auth_data).digest() + bytes(32)
+    qe_report = report_body(
+        os.urandom(32), QE_MR_SIGNER, 1, QE_ISV_SVN, qe_report_data,
+        bytes.fromhex("15" + "00" * 7 + "e7" + "00" * 7),
+    )
+    qe_report_signature = raw_sign(pck_key, qe_report)
+    cert_data = pem(pck_cert, pck_ca_cert, root_cert)
+    signature_data = (
+        isv_signature
+        + attestation_pubkey
+        + qe_report
+        + qe_report_signature
+        + struct.pack("<H", len(qe_auth_data))
+        + qe_auth_data
+        + struct.pack("<HI", 5, len(cert_data))
+        + cert_data
+    )
+    quote = header + body + struct.pack("<I", len(signature_data)) + signature_data
+
+    # The collateral
+    def tcb_level(components, pce_svn, status, advisory_ids=()):
+        level = {
+            "tcb": {
+                "sgxtcbcomponents": [{"svn": svn} for svn in components],
+                "pcesvn": pce_svn,
+            },
+            "tcbDate": "2022-08-10T00:00:00Z",
+            "tcbStatus": status,
+        }
+        if advisory_ids:
+            level["advisoryIDs"] = list(advisory_ids)
+        return level
+
+    tcb_info = json.dumps({
+        "id": "SGX",
+        "version": 3,
+        "issueDate": timestamp(ISSUE_DATE),
+        "nextUpdate": timestamp(NEXT_UPDATE),
+        "fmspc": FMSPC.hex(),
+        "pceId": PCE_ID.hex(),
+        "tcbType": 0,
+        "tcbEvaluationDataNumber": 12,
+        "tcbLevels": [
+            tcb_level([16] * 2 + PLATFORM_TCB_COMPONENTS[2:], 13, "UpToDate"),
+            tcb_level(PLATFORM_TCB_COMPONENTS, 11, "SWHardeningNeeded", ["INTEL-SA-00334"]),
+            tcb_level([0] * 16, 5, "OutOfDate", ["INTEL-SA-00334", "INTEL-SA-00615"]),
+        ],
+    }, separators=(",", ":")).encode()
+    qe_identity = json.dumps({
+        "id": "QE",
+        "version": 2,
+        "issueDate": timestamp(ISSUE_DATE),
+        "nextUpdate": timestamp(NEXT_UPDATE),
+        "tcbEvaluationDataNumber": 12,
+        "miscselect": "00000000",
+        "miscselectMask": "FFFFFFFF",
+        "attributes": "11000000000000000000000000000000",
+        "attributesMask": "FBFFFFFFFFFFFFFF0000000000000000",
+        "mrsigner": QE_MR_SIGNER.hex().upper(),
+        "isvprodid": 1,
+        "tcbLevels": [
+            {"tcb": {"isvsvn": 8}, "tcbDate": "2022-08-10T00:00:00Z", "tcbStatus": "UpToDate"},
+            {"tcb": {"isvsvn": 0}, "tcbDate": "2018-01-04T00:00:00Z", "tcbStatus": "OutOfDate"},
+        ],
+    }, separators=(",", ":")).encode()
+
+    sample = {
+        "timestamp": int(ISSUE_DATE.timestamp()),
+        "rootCert": root_cert.public_bytes(serialization.Encoding.DER).hex(),
+        "quote": quote.hex(),
+        "rootCaCrl": crl("Test SGX Root CA", root_key, []).hex(),
+        "pckCrl": crl("Test SGX PCK Platform CA", pck_ca_key, [x509.random_serial_number()]).hex(),
+        "revokingPckCrl": crl(
+            "Test SGX PCK Platform CA", pck_ca_key, [pck_cert.serial_number]
+        ).hex(),
+        "tcbInfoIssuerChain": pem(tcb_cert, root_cert).decode(),
+        "tcbInfo": tcb_info.decode(),
+        "tcbInfoSignature": raw_sign(tcb_key, tcb_info).hex(),
+        "qeIdentityIssuerChain": pem(tcb_cert, root_cert).decode(),
+        "qeIdentity": qe_identity.decode(),
+        "qeIdentitySignature": raw_sign(tcb_key, qe_identity).hex(),
+        "pruntimeHash": (MR_ENCLAVE + bytes(4) + MR_SIGNER).hex(),
+    }
+    json.dump(sample, sys.stdout, indent=2)
+    sys.stdout.write("\n")
+
+
+if __name__ == "__main__":
+    main()
@@ -1,7 +1,7 @@
 use crate::BlockNumber;
 
 use anyhow::Result;
-use rocksdb::DB;
+use rocksdb::{Direction, IteratorMode, DB};
 use std::mem::size_of;
 
 use serde::{Deserialize, Serialize};
@@ -75,6 +75,39 @@ impl CacheDB {
         Ok(())
     }
 
+    /// Returns the lowest and the highest block numbers stored under the prefix.
+    fn block_range(&self, prefix: u8) -> Option<(BlockNumber, BlockNumber)> {
+        let number = |key: &[u8]| -> Option<BlockNumber> {
+            if key.len() != size_of::<BlockNumber>() + 1 || key[0] != prefix {
+                return None;
+            }
+            Some(BlockNumber::from_be_bytes(key[1..].try_into().ok()?))
+        };
+        let first_key = [prefix];
+        let last_key = mk_key(prefix, BlockNumber::MAX);
+        let mut forward = self
+            .0
+            .iterator(IteratorMode::From(&first_key, Direction::Forward));
+        let mut reverse = self
+            .0
+            .iterator(IteratorMode::From(&last_key, Direction::Reverse));
+        let first = number(&forward.next()?.0)?;
+        let last = number(&reverse.next()?.0)?;
+        Some((first, last))
+    }
+
+    pub fn header_range(&self) -> Option<(BlockNumber, BlockNumber)> {
+        self.block_range(b'h')
+    }
+
+    pub fn para_header_range(&self) -> Option<(BlockNumber, BlockNumber)> {
+        self.block_range(b'p')
+    }
+
+    pub fn storage_changes_range(&self) -> Option<(BlockNumber, BlockNumber)> {
+        self.block_range(b'c')
+    }
+
     pub fn get_header(&self, block: BlockNumber) -> Option<Vec<u8>> {
         self.get(b'h', block)
     }
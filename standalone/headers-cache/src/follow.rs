@@ -0,0 +1,335 @@
+//! Keeps the cache database up to date with the finalized relaychain and parachain.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use futures::StreamExt as _;
+use log::{info, warn};
+use pherry::types::{Header, ParachainApi, RelaychainApi};
+use scale::Encode;
+
+use crate::cache;
+use crate::db::{CacheDB, Metadata};
+use crate::BlockNumber;
+
+/// Max number of blocks to grab before saving the progress.
+const CHUNK_SIZE: BlockNumber = 1000;
+
+#[derive(clap::Args, Clone)]
+pub struct FollowArgs {
+    /// The relaychain RPC endpoint
+    #[clap(long, default_value = "ws://localhost:9945")]
+    node_uri: String,
+    /// The parachain RPC endpoint
+    #[clap(long, default_value = "ws://localhost:9944")]
+    para_node_uri: String,
+    /// The relaychain block to start at if no header has been imported
+    #[clap(long, default_value_t = 1)]
+    from_block: BlockNumber,
+    /// The parachain block to start at if no parachain header or storage changes has been imported
+    #[clap(long, default_value_t = 0)]
+    para_from_block: BlockNumber,
+    /// Prefered minimum number of blocks between justification
+    #[clap(long, default_value_t = 1000)]
+    justification_interval: BlockNumber,
+    /// Number of blocks of storage changes requested in a single RPC.
+    #[clap(long, default_value_t = 10)]
+    batch_size: BlockNumber,
+}
+
+struct Follower {
+    db: Arc<CacheDB>,
+    args: FollowArgs,
+    metadata: Metadata,
+    /// The last relaychain block grabbed with justification.
+    last_justified: Option<BlockNumber>,
+}
+
+/// Grabs the finalized blocks into the database, reconnecting to the nodes on error.
+pub async fn follow(db: Arc<CacheDB>, args: FollowArgs) -> Result<()> {
+    let mut follower = Follower::new(db, args)?;
+    loop {
+        if let Err(err) = follower.run().await {
+            warn!("Following the chain failed: {:?}", err);
+        }
+        tokio::time::sleep(Duration::from_secs(5)).await;
+        info!("Reconnecting to the chain...");
+    }
+}
+
+impl Follower {
+    /// Creates a follower resuming from the blocks already in the database.
+    fn new(db: Arc<CacheDB>, args: FollowArgs) -> Result<Self> {
+        let metadata = db.get_metadata()?.unwrap_or_default();
+        Ok(Self {
+            db,
+            args,
+            metadata,
+            last_justified: None,
+        })
+    }
+
+    async fn run(&mut self) -> Result<()> {
+        let api: RelaychainApi = pherry::subxt_connect(&self.args.node_uri).await?.into();
+        let para_api: ParachainApi = pherry::subxt_connect(&self.args.para_node_uri)
+            .await?
+            .into();
+        let mut relay_heads = api.client.rpc().subscribe_finalized_blocks().await?;
+        let mut para_heads = para_api.client.rpc().subscribe_finalized_blocks().await?;
+        let mut relay_finalized = pherry::get_header_at(&api.client, None).await?.0.number;
+        let mut para_finalized = pherry::get_header_at(&para_api.client, None)
+            .await?
+            .0
+            .number;
+        info!("Following the chain from relay={relay_finalized} para={para_finalized}");
+
+        loop {
+            // Fill the gaps from the last run chunk by chunk before waiting for the new blocks.
+            let relay_grabbed = self.grab_headers(&api, &para_api, relay_finalized).await?;
+            let para_grabbed = self.grab_para_headers(&para_api, para_finalized).await?;
+            let changes_grabbed = self.grab_storage_changes(&para_api, para_finalized).await?;
+            if relay_grabbed + para_grabbed + changes_grabbed > 0 {
+                self.db.put_metadata(self.metadata.clone())?;
+                continue;
+            }
+            tokio::select! {
+                head = relay_heads.next() => {
+                    let head = head.ok_or(anyhow!("Relaychain subscription closed"))??;
+                    relay_finalized = relay_finalized.max(head.number);
+                }
+                head = para_heads.next() => {
+                    let head = head.ok_or(anyhow!("Parachain subscription closed"))??;
+                    para_finalized = para_finalized.max(head.number);
+                }
+            }
+        }
+    }
+
+    /// Returns the number of blocks to grab in this chunk, starting at `next`.
+    fn chunk(next: BlockNumber, finalized: BlockNumber) -> BlockNumber {
+        if next > finalized {
+            0
+        } else {
+            (finalized - next + 1).min(CHUNK_SIZE)
+        }
+    }
+
+    async fn grab_headers(
+        &mut self,
+        api: &RelaychainApi,
+        para_api: &ParachainApi,
+        finalized: BlockNumber,
+    ) -> Result<BlockNumber> {
+        let next = self.next_header();
+        let count = Self::chunk(next, finalized);
+        if count == 0 {
+            return Ok(0);
+        }
+        let interval = self.args.justification_interval;
+        let skip = self.justification_skip(next);
+        let grabbed = cache::grab_headers(api, para_api, next, count, interval, skip, |info| {
+            self.import_header(info)
+        })
+        .await?;
+        if grabbed > 0 {
+            info!("Grabbed headers {}-{}", next, next + grabbed - 1);
+        }
+        Ok(grabbed)
+    }
+
+    async fn grab_para_headers(
+        &mut self,
+        para_api: &ParachainApi,
+        finalized: BlockNumber,
+    ) -> Result<BlockNumber> {
+        let next = self.next_para_header();
+        let count = Self::chunk(next, finalized);
+        if count == 0 {
+            return Ok(0);
+        }
+        let grabbed = cache::grab_para_headers(para_api, next, count, |header| {
+            self.import_para_header(header)
+        })
+        .await?;
+        if grabbed > 0 {
+            info!("Grabbed parachain headers {}-{}", next, next + grabbed - 1);
+        }
+        Ok(grabbed)
+    }
+
+    async fn grab_storage_changes(
+        &mut self,
+        para_api: &ParachainApi,
+        finalized: BlockNumber,
+    ) -> Result<BlockNumber> {
+        let next = self.next_storage_changes();
+        let count = Self::chunk(next, finalized);
+        if count == 0 {
+            return Ok(0);
+        }
+        let batch_size = self.args.batch_size;
+        let grabbed = cache::grab_storage_changes(para_api, next, count, batch_size, |changes| {
+            self.import_storage_changes(changes)
+        })
+        .await?;
+        if grabbed > 0 {
+            info!("Grabbed storage changes {}-{}", next, next + grabbed - 1);
+        }
+        Ok(grabbed)
+    }
+
+    fn next_header(&self) -> BlockNumber {
+        match self.metadata.higest.header {
+            Some(higest) => higest + 1,
+            None => self.args.from_block,
+        }
+    }
+
+    fn next_para_header(&self) -> BlockNumber {
+        match self.metadata.higest.para_header {
+            Some(higest) => higest + 1,
+            None => self.args.para_from_block,
+        }
+    }
+
+    fn next_storage_changes(&self) -> BlockNumber {
+        match self.metadata.higest.storage_changes {
+            Some(higest) => higest + 1,
+            None => self.args.para_from_block,
+        }
+    }
+
+    /// Returns the number of blocks to grab from `next` before requesting a justification,
+    /// continuing the interval between the justifications across the chunks.
+    fn justification_skip(&self, next: BlockNumber) -> BlockNumber {
+        match self.last_justified {
+            Some(block) => (block + self.args.justification_interval + 1).saturating_sub(next),
+            None => 0,
+        }
+    }
+
+    fn import_header(&mut self, info: cache::BlockInfo) -> Result<()> {
+        let number = info.header.number;
+        if info.justification.is_some() {
+            self.last_justified = Some(number);
+        }
+        self.db.put_header(number, &info.encode())?;
+        self.metadata.update_header(number);
+        Ok(())
+    }
+
+    fn import_para_header(&mut self, header: Header) -> Result<()> {
+        self.db.put_para_header(header.number, &header.encode())?;
+        self.metadata.update_para_header(header.number);
+        Ok(())
+    }
+
+    fn import_storage_changes(&mut self, changes: cache::BlockHeaderWithChanges) -> Result<()> {
+        let number = changes.block_header.number;
+        self.db.put_storage_changes(number, &changes.encode())?;
+        self.metadata.update_storage_changes(number);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use scale::Decode;
+
+    fn header(number: BlockNumber) -> Header {
+        Header {
+            parent_hash: Default::default(),
+            number,
+            state_root: Default::default(),
+            extrinsics_root: Default::default(),
+            digest: Default::default(),
+        }
+    }
+
+    fn block_info(number: BlockNumber, justified: bool) -> cache::BlockInfo {
+        cache::BlockInfo {
+            header: header(number),
+            justification: justified.then(|| vec![1]),
+            para_header: None,
+            authority_set_change: None,
+        }
+    }
+
+    fn storage_changes(number: BlockNumber) -> cache::BlockHeaderWithChanges {
+        cache::BlockHeaderWithChanges {
+            block_header: header(number),
+            storage_changes: Default::default(),
+        }
+    }
+
+    fn follow_args() -> FollowArgs {
+        FollowArgs {
+            node_uri: Default::default(),
+            para_node_uri: Default::default(),
+            from_block: 1,
+            para_from_block: 0,
+            justification_interval: 10,
+            batch_size: 10,
+        }
+    }
+
+    #[test]
+    fn chunk_is_bounded() {
+        assert_eq!(Follower::chunk(1, 0), 0);
+        assert_eq!(Follower::chunk(5, 5), 1);
+        assert_eq!(Follower::chunk(1, 10), 10);
+        assert_eq!(Follower::chunk(1, CHUNK_SIZE * 3), CHUNK_SIZE);
+    }
+
+    #[test]
+    fn catch_up_resumes_from_the_imported_blocks() {
+        let path =
+            std::env::temp_dir().join(format!("headers-cache-follow-{}", std::process::id()));
+        let db_path = path.to_str().unwrap().to_string();
+        {
+            let db = Arc::new(CacheDB::open(&db_path).unwrap());
+            let mut follower = Follower::new(db.clone(), follow_args()).unwrap();
+            assert_eq!(follower.next_header(), 1);
+            assert_eq!(follower.next_para_header(), 0);
+            assert_eq!(follower.next_storage_changes(), 0);
+            assert_eq!(follower.justification_skip(1), 0);
+
+            for number in 1..=3 {
+                follower
+                    .import_header(block_info(number, number == 2))
+                    .unwrap();
+            }
+            for number in 0..=1 {
+                follower.import_para_header(header(number)).unwrap();
+            }
+            follower.import_storage_changes(storage_changes(0)).unwrap();
+
+            assert_eq!(follower.next_header(), 4);
+            assert_eq!(follower.next_para_header(), 2);
+            assert_eq!(follower.next_storage_changes(), 1);
+            // The next justification is 10 blocks after block 2
+            assert_eq!(follower.justification_skip(4), 9);
+
+            let stored = db.get_header(3).unwrap();
+            let stored = cache::BlockInfo::decode(&mut &stored[..]).unwrap();
+            assert_eq!(stored.header.number, 3);
+            let stored = db.get_para_header(1).unwrap();
+            assert_eq!(Header::decode(&mut &stored[..]).unwrap().number, 1);
+            assert!(db.get_storage_changes(0).is_some());
+
+            // Save the progress as at the end of a chunk
+            db.put_metadata(follower.metadata.clone()).unwrap();
+        }
+        {
+            // A new run resumes after the imported blocks
+            let db = Arc::new(CacheDB::open(&db_path).unwrap());
+            let follower = Follower::new(db, follow_args()).unwrap();
+            assert_eq!(follower.next_header(), 4);
+            assert_eq!(follower.next_para_header(), 2);
+            assert_eq!(follower.next_storage_changes(), 1);
+        }
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+}
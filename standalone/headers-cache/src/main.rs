@@ -1,14 +1,18 @@
 use std::fs::File;
 use std::io::Write;
+use std::sync::Arc;
+use std::time::Duration;
 
 use anyhow::Context;
-use log::info;
+use log::{error, info};
 use scale::{Decode, Encode};
 
 use clap::{AppSettings, Parser, Subcommand};
 use pherry::headers_cache as cache;
 
 mod db;
+mod follow;
+mod verify;
 mod web_api;
 
 type BlockNumber = u32;
@@ -140,6 +144,16 @@ enum Action {
         /// The database file to use
         #[clap(long, default_value = "cache.db")]
         db: String,
+        /// Keep grabbing the finalized blocks from the chain into the database while serving
+        #[clap(long)]
+        follow: bool,
+        #[clap(flatten)]
+        follow_args: follow::FollowArgs,
+    },
+    /// Check the integrity of the cache database and optionally re-grab the bad blocks
+    Verify {
+        #[clap(flatten)]
+        args: verify::VerifyArgs,
     },
     /// Split given grabbed headers file into chunks
     Split {
@@ -179,6 +193,19 @@ enum Action {
     },
 }
 
+/// Keeps the follower running, restarting it if it fails or panics.
+async fn supervise_follower(db: Arc<db::CacheDB>, args: follow::FollowArgs) {
+    loop {
+        match tokio::spawn(follow::follow(db.clone(), args.clone())).await {
+            Ok(Ok(())) => error!("The follower stopped unexpectedly"),
+            Ok(Err(err)) => error!("The follower failed: {:?}", err),
+            Err(err) => error!("The follower panicked: {:?}", err),
+        }
+        tokio::time::sleep(Duration::from_secs(5)).await;
+        info!("Restarting the follower...");
+    }
+}
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
@@ -316,8 +343,19 @@ async fn main() -> anyhow::Result<()> {
             }
             cache.flush()?;
         }
-        Action::Serve { db } => {
-            web_api::serve(&db).await?;
+        Action::Serve {
+            db,
+            follow,
+            follow_args,
+        } => {
+            let db = Arc::new(db::CacheDB::open(&db)?);
+            if follow {
+                tokio::spawn(supervise_follower(db.clone(), follow_args));
+            }
+            web_api::serve(db).await?;
+        }
+        Action::Verify { args } => {
+            verify::verify(args).await?;
         }
         Action::ShowSetId { uri, block } => {
             let api = pherry::subxt_connect(&uri).await?.into();
@@ -0,0 +1,561 @@
+//! Integrity verification of the cache database.
+//!
+//! Walks the relaychain headers, the parachain headers and the storage changes in the database
+//! block by block, reports the missing and the bad ones, and optionally re-grabs them from the
+//! nodes.
+
+use anyhow::{anyhow, Context, Result};
+use log::info;
+use phala_trie_storage::TrieStorage;
+use pherry::types::{
+    phaxt::{rpc::ExtraRpcExt as _, StorageKey},
+    Hash, Header, ParachainApi, RelaychainApi,
+};
+use scale::{Decode, Encode};
+use sp_runtime::traits::{BlakeTwo256, Header as _};
+use std::collections::BTreeMap;
+
+use crate::cache::{self, AuthoritySet, BlockHeaderWithChanges, BlockInfo, GenesisBlockInfo};
+use crate::db::CacheDB;
+use crate::BlockNumber;
+
+type RelayBlock = sp_runtime::generic::Block<Header, sp_runtime::OpaqueExtrinsic>;
+type GrandpaJustification = sc_finality_grandpa::GrandpaJustification<RelayBlock>;
+
+#[derive(clap::Args)]
+pub struct VerifyArgs {
+    /// The database file to use
+    #[clap(long, default_value = "cache.db")]
+    db: String,
+    /// Replay the storage changes on the state fetched from the parachain node to check them
+    /// against the state roots. Requires an archive node
+    #[clap(long)]
+    check_state_roots: bool,
+    /// Re-grab the missing and bad blocks from the nodes
+    #[clap(long)]
+    repair: bool,
+    /// The relaychain RPC endpoint
+    #[clap(long, default_value = "ws://localhost:9945")]
+    node_uri: String,
+    /// The parachain RPC endpoint
+    #[clap(long, default_value = "ws://localhost:9944")]
+    para_node_uri: String,
+    /// Prefered minimum number of blocks between justification when re-grabbing headers
+    #[clap(long, default_value_t = 1000)]
+    justification_interval: BlockNumber,
+    /// Number of blocks of storage changes requested in a single RPC when re-grabbing
+    #[clap(long, default_value_t = 10)]
+    batch_size: BlockNumber,
+}
+
+/// The problems found in one kind of data.
+#[derive(Default)]
+struct Findings {
+    /// The lowest and the highest block in the database.
+    range: Option<(BlockNumber, BlockNumber)>,
+    /// The ranges of the blocks missing between the lowest and the highest one.
+    missing: Vec<(BlockNumber, BlockNumber)>,
+    /// The blocks failed to pass the checks, with the reasons.
+    bad: BTreeMap<BlockNumber, String>,
+    /// What was not checked and why.
+    notes: Vec<String>,
+}
+
+impl Findings {
+    fn missing(&mut self, block: BlockNumber) {
+        match self.missing.last_mut() {
+            Some((_, to)) if *to + 1 == block => *to = block,
+            _ => self.missing.push((block, block)),
+        }
+    }
+
+    fn bad(&mut self, block: BlockNumber, reason: String) {
+        self.bad.entry(block).or_insert(reason);
+    }
+
+    /// Checks the header stored at `number` follows the `parent`.
+    fn check_header(&mut self, number: BlockNumber, header: &Header, parent: Option<Hash>) {
+        if header.number != number {
+            self.bad(number, format!("stored block {} instead", header.number));
+        }
+        match parent {
+            Some(parent) if header.parent_hash != parent => {
+                self.bad(number - 1, format!("not the parent of block {number}"));
+                self.bad(
+                    number,
+                    format!("parent hash mismatch {:?}", header.parent_hash),
+                );
+            }
+            _ => (),
+        }
+    }
+
+    fn is_clean(&self) -> bool {
+        self.missing.is_empty() && self.bad.is_empty()
+    }
+
+    /// The ranges of the missing and the bad blocks, merged.
+    fn bad_ranges(&self) -> Vec<(BlockNumber, BlockNumber)> {
+        let mut ranges: Vec<_> = self
+            .missing
+            .iter()
+            .copied()
+            .chain(self.bad.keys().map(|&block| (block, block)))
+            .collect();
+        ranges.sort_unstable();
+        let mut merged: Vec<(BlockNumber, BlockNumber)> = vec![];
+        for (from, to) in ranges {
+            match merged.last_mut() {
+                Some((_, last)) if from <= last.saturating_add(1) => *last = to.max(*last),
+                _ => merged.push((from, to)),
+            }
+        }
+        merged
+    }
+
+    fn print(&self, what: &str) {
+        match self.range {
+            None => println!("{what}: no blocks"),
+            Some((first, last)) if self.is_clean() => println!("{what}: {first}-{last} ok"),
+            Some((first, last)) => println!("{what}: {first}-{last}"),
+        }
+        for (from, to) in &self.missing {
+            println!("  missing {from}-{to}");
+        }
+        for (block, reason) in &self.bad {
+            println!("  bad {block}: {reason}");
+        }
+        for note in &self.notes {
+            println!("  note: {note}");
+        }
+    }
+
+    /// Decodes the blocks in `range` one by one and passes them to `f`.
+    ///
+    /// The missing and undecodable blocks are recorded and passed as `None`, which breaks the
+    /// chain of the checks.
+    fn walk<T: Decode>(
+        &mut self,
+        range: Option<(BlockNumber, BlockNumber)>,
+        get: impl Fn(BlockNumber) -> Option<Vec<u8>>,
+        mut f: impl FnMut(&mut Self, BlockNumber, Option<T>) -> Result<()>,
+    ) -> Result<()> {
+        self.range = range;
+        let (first, last) = match range {
+            Some(range) => range,
+            None => return Ok(()),
+        };
+        for number in first..=last {
+            let item = match get(number) {
+                None => {
+                    self.missing(number);
+                    None
+                }
+                Some(encoded) => match T::decode(&mut &encoded[..]) {
+                    Ok(item) => Some(item),
+                    Err(err) => {
+                        self.bad(number, format!("undecodable: {err}"));
+                        None
+                    }
+                },
+            };
+            f(self, number, item)?;
+            if number % 100000 == 0 {
+                info!("Verified to {number}");
+            }
+        }
+        Ok(())
+    }
+}
+
+pub async fn verify(args: VerifyArgs) -> Result<()> {
+    let db = CacheDB::open(&args.db)?;
+
+    println!("Verifying relaychain headers");
+    let headers = verify_headers(&db)?;
+    println!("Verifying parachain headers");
+    let para_headers = verify_para_headers(&db)?;
+    println!("Verifying storage changes");
+    let storage_changes = verify_storage_changes(&db, &args).await?;
+
+    headers.print("Headers");
+    para_headers.print("Parachain headers");
+    storage_changes.print("Storage changes");
+
+    let all = [&headers, &para_headers, &storage_changes];
+    if all.iter().all(|findings| findings.is_clean()) {
+        return Ok(());
+    }
+    if !args.repair {
+        return Err(anyhow!(
+            "Integrity check failed, run with --repair to re-grab the bad blocks"
+        ));
+    }
+    repair(&db, &args, &headers, &para_headers, &storage_changes).await?;
+    println!("Repaired, run verify again to confirm");
+    Ok(())
+}
+
+fn verify_headers(db: &CacheDB) -> Result<Findings> {
+    let genesis_blocks = db.get_metadata()?.unwrap_or_default().genesis;
+    let mut findings = Findings::default();
+    let mut parent: Option<Hash> = None;
+    // The authority set to verify the justifications with, known from a genesis right before the
+    // headers and tracked through the authority set changes.
+    let mut authorities: Option<AuthoritySet> = None;
+    let mut unverified = 0_u64;
+    findings.walk(
+        db.header_range(),
+        |n| db.get_header(n),
+        |findings, number, info| {
+            if authorities.is_none() && number > 0 && genesis_blocks.contains(&(number - 1)) {
+                let encoded = db
+                    .get_genesis(number - 1)
+                    .ok_or(anyhow!("Genesis {} not found", number - 1))?;
+                let genesis = GenesisBlockInfo::decode(&mut &encoded[..])
+                    .with_context(|| format!("Failed to decode genesis {}", number - 1))?;
+                parent = Some(genesis.block_header.hash());
+                authorities = Some(genesis.authority_set);
+            }
+            let info: BlockInfo = match info {
+                Some(info) => info,
+                None => {
+                    // The authority set could have changed in the missing blocks.
+                    parent = None;
+                    authorities = None;
+                    return Ok(());
+                }
+            };
+            let header = &info.header;
+            let hash = header.hash();
+            findings.check_header(number, header, parent);
+            match (&info.justification, &authorities) {
+                (Some(justification), Some(set)) => {
+                    if let Err(err) = verify_justification(justification, number, hash, set) {
+                        findings.bad(number, format!("{err:#}"));
+                    }
+                }
+                (Some(_), None) => unverified += 1,
+                (None, _) => (),
+            }
+            if let Some(change) = &info.authority_set_change {
+                if info.justification.is_none() {
+                    findings.bad(number, "authority set change without justification".into());
+                }
+                if let Some(set) = &authorities {
+                    if change.authority_set.id != set.id + 1 {
+                        findings.bad(
+                            number,
+                            format!(
+                                "authority set id changed from {} to {}",
+                                set.id, change.authority_set.id
+                            ),
+                        );
+                    }
+                }
+                authorities = Some(change.authority_set.clone());
+            }
+            parent = Some(hash);
+            Ok(())
+        },
+    )?;
+    if unverified > 0 {
+        findings.notes.push(format!(
+            "{unverified} justifications not verified for unknown authority set, \
+            import the genesis right before the headers to verify them"
+        ));
+    }
+    Ok(findings)
+}
+
+fn verify_justification(
+    encoded: &[u8],
+    number: BlockNumber,
+    hash: Hash,
+    set: &AuthoritySet,
+) -> Result<()> {
+    let justification = GrandpaJustification::decode(&mut &encoded[..])
+        .context("Failed to decode the justification")?;
+    if justification.target() != (number, hash) {
+        return Err(anyhow!("justification for another block"));
+    }
+    justification
+        .verify(set.id, &set.list)
+        .with_context(|| format!("bad justification for authority set {}", set.id))
+}
+
+fn verify_para_headers(db: &CacheDB) -> Result<Findings> {
+    let mut findings = Findings::default();
+    let mut parent: Option<Hash> = None;
+    findings.walk(
+        db.para_header_range(),
+        |n| db.get_para_header(n),
+        |findings, number, header| {
+            let header: Header = match header {
+                Some(header) => header,
+                None => {
+                    parent = None;
+                    return Ok(());
+                }
+            };
+            findings.check_header(number, &header, parent);
+            parent = Some(header.hash());
+            Ok(())
+        },
+    )?;
+    Ok(findings)
+}
+
+async fn verify_storage_changes(db: &CacheDB, args: &VerifyArgs) -> Result<Findings> {
+    let range = db.storage_changes_range();
+    // The state before the first block checked against the state roots.
+    let mut state = None;
+    let mut base = 0;
+    if let (true, Some((first, _))) = (args.check_state_roots, range) {
+        // There is no state before the genesis, so start checking from block 1 in that case.
+        base = first.saturating_sub(1);
+        let para_api: ParachainApi = pherry::subxt_connect(&args.para_node_uri).await?.into();
+        println!("Fetching the state at parachain block {base}");
+        state = Some(fetch_state(&para_api, base).await?);
+    }
+
+    let mut findings = Findings::default();
+    let mut parent: Option<Hash> = None;
+    findings.walk(
+        range,
+        |n| db.get_storage_changes(n),
+        |findings, number, changes| {
+            let changes: BlockHeaderWithChanges = match changes {
+                Some(changes) => changes,
+                None => {
+                    parent = None;
+                    if state.take().is_some() {
+                        findings
+                            .notes
+                            .push(format!("state roots not checked from block {number}"));
+                    }
+                    return Ok(());
+                }
+            };
+            let header = &changes.block_header;
+            let hash = header.hash();
+            findings.check_header(number, header, parent);
+            if let Some(para_header) = db.get_para_header(number) {
+                if let Ok(para_header) = Header::decode(&mut &para_header[..]) {
+                    if para_header.hash() != hash {
+                        findings.bad(number, "header differs from the parachain header".into());
+                    }
+                }
+            }
+            match &mut state {
+                Some(storage) if number > base => {
+                    let (root, transaction) = storage.calc_root_if_changes(
+                        &changes.storage_changes.main_storage_changes,
+                        &changes.storage_changes.child_storage_changes,
+                    );
+                    if root == header.state_root {
+                        storage.apply_changes(root, transaction);
+                    } else {
+                        findings.bad(
+                            number,
+                            format!("state root mismatch {:?} != {:?}", root, header.state_root),
+                        );
+                        // The state is broken since then.
+                        state = None;
+                        findings
+                            .notes
+                            .push(format!("state roots not checked after block {number}"));
+                    }
+                }
+                _ => (),
+            }
+            parent = Some(hash);
+            Ok(())
+        },
+    )?;
+    Ok(findings)
+}
+
+async fn fetch_state(
+    para_api: &ParachainApi,
+    block: BlockNumber,
+) -> Result<TrieStorage<BlakeTwo256>> {
+    let (_, hash) = pherry::get_header_at(&para_api.client, Some(block)).await?;
+    let pairs = para_api
+        .client
+        .extra_rpc()
+        .storage_pairs(StorageKey(vec![]), Some(hash))
+        .await?;
+    let mut state = TrieStorage::default();
+    state.load(pairs.into_iter().map(|(key, value)| (key.0, value.0)));
+    Ok(state)
+}
+
+async fn repair(
+    db: &CacheDB,
+    args: &VerifyArgs,
+    headers: &Findings,
+    para_headers: &Findings,
+    storage_changes: &Findings,
+) -> Result<()> {
+    let mut metadata = db.get_metadata()?.unwrap_or_default();
+    let para_api: ParachainApi = pherry::subxt_connect(&args.para_node_uri).await?.into();
+
+    let ranges = headers.bad_ranges();
+    if !ranges.is_empty() {
+        let api: RelaychainApi = pherry::subxt_connect(&args.node_uri).await?.into();
+        for (from, to) in ranges {
+            let interval = args.justification_interval;
+            let count = to - from + 1;
+            let grabbed = cache::grab_headers(&api, &para_api, from, count, interval, 0, |info| {
+                db.put_header(info.header.number, &info.encode())?;
+                metadata.update_header(info.header.number);
+                Ok(())
+            })
+            .await?;
+            println!("Re-grabbed {grabbed} headers from {from}");
+        }
+    }
+    for (from, to) in para_headers.bad_ranges() {
+        let grabbed = cache::grab_para_headers(&para_api, from, to - from + 1, |header| {
+            db.put_para_header(header.number, &header.encode())?;
+            metadata.update_para_header(header.number);
+            Ok(())
+        })
+        .await?;
+        println!("Re-grabbed {grabbed} parachain headers from {from}");
+    }
+    for (from, to) in storage_changes.bad_ranges() {
+        let count = to - from + 1;
+        let grabbed =
+            cache::grab_storage_changes(&para_api, from, count, args.batch_size, |changes| {
+                let number = changes.block_header.number;
+                db.put_storage_changes(number, &changes.encode())?;
+                metadata.update_storage_changes(number);
+                Ok(())
+            })
+            .await?;
+        println!("Re-grabbed {grabbed} blocks of storage changes from {from}");
+    }
+    db.put_metadata(metadata)?;
+    db.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(number: BlockNumber, parent_hash: Hash) -> Header {
+        Header {
+            parent_hash,
+            number,
+            state_root: Default::default(),
+            extrinsics_root: Default::default(),
+            digest: Default::default(),
+        }
+    }
+
+    #[test]
+    fn missing_blocks_are_merged_into_ranges() {
+        let mut findings = Findings::default();
+        for block in [3, 4, 5, 7, 9, 10] {
+            findings.missing(block);
+        }
+        assert_eq!(findings.missing, vec![(3, 5), (7, 7), (9, 10)]);
+        assert!(!findings.is_clean());
+    }
+
+    #[test]
+    fn bad_block_keeps_the_first_reason() {
+        let mut findings = Findings::default();
+        assert!(findings.is_clean());
+        findings.bad(3, "first".into());
+        findings.bad(3, "second".into());
+        assert_eq!(findings.bad.get(&3).map(String::as_str), Some("first"));
+        assert!(!findings.is_clean());
+    }
+
+    #[test]
+    fn check_header_follows_parent() {
+        let parent = header(1, Default::default());
+        let child = header(2, parent.hash());
+
+        let mut findings = Findings::default();
+        findings.check_header(2, &child, Some(parent.hash()));
+        findings.check_header(2, &child, None);
+        assert!(findings.is_clean());
+
+        // Stored at a wrong number
+        findings.check_header(3, &child, None);
+        assert_eq!(findings.bad.keys().collect::<Vec<_>>(), vec![&3]);
+
+        // Both the parent and the child are suspected
+        let mut findings = Findings::default();
+        findings.check_header(2, &child, Some(Hash::repeat_byte(1)));
+        assert_eq!(findings.bad.keys().collect::<Vec<_>>(), vec![&1, &2]);
+    }
+
+    #[test]
+    fn bad_ranges_merge_missing_and_bad_blocks() {
+        let mut findings = Findings::default();
+        assert_eq!(findings.bad_ranges(), vec![]);
+        findings.missing = vec![(3, 5), (10, 12), (20, 20)];
+        // Adjacent to 3-5
+        findings.bad(6, "bad".into());
+        // Inside 10-12
+        findings.bad(11, "bad".into());
+        // Separated
+        findings.bad(15, "bad".into());
+        // Before all
+        findings.bad(1, "bad".into());
+        assert_eq!(
+            findings.bad_ranges(),
+            vec![(1, 1), (3, 6), (10, 12), (15, 15), (20, 20)]
+        );
+    }
+
+    #[test]
+    fn walk_records_missing_and_undecodable_blocks() {
+        let mut findings = Findings::default();
+        let mut seen = vec![];
+        findings
+            .walk(
+                Some((1, 5)),
+                |number| match number {
+                    2 => None,
+                    // Too short for a u32
+                    4 => Some(vec![0u8]),
+                    _ => Some((number * 10).encode()),
+                },
+                |_, number, item: Option<u32>| {
+                    seen.push((number, item));
+                    Ok(())
+                },
+            )
+            .unwrap();
+        assert_eq!(findings.range, Some((1, 5)));
+        assert_eq!(
+            seen,
+            vec![
+                (1, Some(10)),
+                (2, None),
+                (3, Some(30)),
+                (4, None),
+                (5, Some(50))
+            ]
+        );
+        assert_eq!(findings.missing, vec![(2, 2)]);
+        assert_eq!(findings.bad.keys().collect::<Vec<_>>(), vec![&4]);
+
+        let mut findings = Findings::default();
+        findings
+            .walk(
+                None,
+                |_| unreachable!(),
+                |_, _, _: Option<u32>| unreachable!(),
+            )
+            .unwrap();
+        assert_eq!(findings.range, None);
+        assert!(findings.is_clean());
+    }
+}
@@ -1,14 +1,249 @@
-use rocket::response::status::NotFound;
+use std::io::{self, Write as _};
+use std::sync::Arc;
+
+use pherry::headers_cache::{BlockRange, Manifest};
+use pherry::types::subxt::sp_core::blake2_128;
+use rocket::http::Status;
+use rocket::request::Request;
+use rocket::response::stream::ByteStream;
+use rocket::response::{self, status::NotFound, Debug, Responder, Response};
+use rocket::serde::json::Json;
 use rocket::State;
 use rocket::{get, routes};
 
-use scale::{Decode, Encode};
+use scale::{Compact, Decode, Encode};
 
 use crate::db::CacheDB;
 use crate::BlockNumber;
 
+/// Size of the uncompressed data read from the database before compressing it into the body.
+const BODY_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Number of compressed chunks read ahead of the client.
+const BODY_CHANNEL_SIZE: usize = 2;
+
+/// Max number of blocks served by one request to a range endpoint.
+const MAX_RANGE_COUNT: BlockNumber = 10000;
+
 struct App {
-    db: CacheDB,
+    db: Arc<CacheDB>,
+}
+
+#[derive(Clone, Copy)]
+enum Encoding {
+    Zstd,
+    Gzip,
+}
+
+impl Encoding {
+    /// Picks the content encoding accepted by the client, prefering zstd.
+    fn negotiate(accept_encoding: Option<&str>) -> Option<Self> {
+        let accepted: Vec<&str> = accept_encoding?
+            .split(',')
+            .filter_map(|item| {
+                let mut params = item.split(';');
+                let name = params.next()?.trim();
+                let rejected = params.any(|param| {
+                    let quality = param.trim().strip_prefix("q=");
+                    quality.and_then(|q| q.parse::<f32>().ok()) == Some(0.0)
+                });
+                if rejected {
+                    None
+                } else {
+                    Some(name)
+                }
+            })
+            .collect();
+        if accepted.contains(&"zstd") {
+            Some(Encoding::Zstd)
+        } else if accepted.contains(&"gzip") {
+            Some(Encoding::Gzip)
+        } else {
+            None
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Encoding::Zstd => "zstd",
+            Encoding::Gzip => "gzip",
+        }
+    }
+}
+
+enum Compressor {
+    Identity,
+    Zstd(zstd::stream::write::Encoder<'static, Vec<u8>>),
+    Gzip(flate2::write::GzEncoder<Vec<u8>>),
+}
+
+impl Compressor {
+    fn new(encoding: Option<Encoding>) -> io::Result<Self> {
+        Ok(match encoding {
+            None => Compressor::Identity,
+            Some(Encoding::Zstd) => Compressor::Zstd(zstd::stream::write::Encoder::new(vec![], 0)?),
+            Some(Encoding::Gzip) => Compressor::Gzip(flate2::write::GzEncoder::new(
+                vec![],
+                flate2::Compression::default(),
+            )),
+        })
+    }
+
+    /// Compresses the data and returns the compressed output produced so far.
+    fn compress(&mut self, data: Vec<u8>) -> io::Result<Vec<u8>> {
+        match self {
+            Compressor::Identity => Ok(data),
+            Compressor::Zstd(encoder) => {
+                encoder.write_all(&data)?;
+                Ok(std::mem::take(encoder.get_mut()))
+            }
+            Compressor::Gzip(encoder) => {
+                encoder.write_all(&data)?;
+                Ok(std::mem::take(encoder.get_mut()))
+            }
+        }
+    }
+
+    /// Compresses the last piece of data and returns the rest of the compressed output.
+    fn finish(self, data: Vec<u8>) -> io::Result<Vec<u8>> {
+        match self {
+            Compressor::Identity => Ok(data),
+            Compressor::Zstd(mut encoder) => {
+                encoder.write_all(&data)?;
+                encoder.finish()
+            }
+            Compressor::Gzip(mut encoder) => {
+                encoder.write_all(&data)?;
+                encoder.finish()
+            }
+        }
+    }
+}
+
+type Items = Box<dyn Iterator<Item = Vec<u8>> + Send>;
+
+/// The chunks of the response body, read from the database and compressed lazily.
+struct Body {
+    items: Items,
+    /// Taken once the body is finished.
+    compressor: Option<Compressor>,
+}
+
+impl Iterator for Body {
+    type Item = Vec<u8>;
+
+    fn next(&mut self) -> Option<Vec<u8>> {
+        let mut compressor = self.compressor.take()?;
+        let mut data = vec![];
+        for item in self.items.by_ref() {
+            data.extend_from_slice(&item);
+            if data.len() >= BODY_CHUNK_SIZE {
+                break;
+            }
+        }
+        let result = if data.len() >= BODY_CHUNK_SIZE {
+            let result = compressor.compress(data);
+            self.compressor = Some(compressor);
+            result
+        } else {
+            compressor.finish(data)
+        };
+        result
+            .map_err(|err| log::error!("Failed to compress the response: {}", err))
+            .ok()
+    }
+}
+
+/// A SCALE encoded `Vec` concatenated from the encoded items in the database.
+///
+/// The body is read and compressed on a blocking thread and streamed in the content encoding
+/// negotiated with the client. The response carries an ETag derived from the range and the last
+/// item, which commits to the previous ones through its parent hash, for the clients to revalidate
+/// it. The data of a complete range of finalized blocks doesn't change, so it can be cached for a
+/// while if `immutable`.
+struct EncodedVec {
+    etag: String,
+    immutable: bool,
+    count: BlockNumber,
+    items: Items,
+}
+
+impl EncodedVec {
+    fn new(
+        kind: &str,
+        start: BlockNumber,
+        count: BlockNumber,
+        last: &[u8],
+        immutable: bool,
+        items: impl Iterator<Item = Vec<u8>> + Send + 'static,
+    ) -> Self {
+        let hash = u128::from_be_bytes(blake2_128(last));
+        Self {
+            etag: format!("W/\"{kind}-{start}-{count}-{hash:032x}\""),
+            immutable,
+            count,
+            items: Box::new(items),
+        }
+    }
+}
+
+impl<'r> Responder<'r, 'r> for EncodedVec {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'r> {
+        let cached = request
+            .headers()
+            .get("If-None-Match")
+            .flat_map(|tags| tags.split(','))
+            .any(|tag| tag.trim() == self.etag || tag.trim() == "*");
+        if cached {
+            return Response::build()
+                .status(Status::NotModified)
+                .raw_header("ETag", self.etag)
+                .ok();
+        }
+        let encoding = Encoding::negotiate(request.headers().get_one("Accept-Encoding"));
+        let compressor = Compressor::new(encoding).map_err(|_| Status::InternalServerError)?;
+        let prefix = Compact(self.count).encode();
+        let body = Body {
+            items: Box::new(std::iter::once(prefix).chain(self.items)),
+            compressor: Some(compressor),
+        };
+        let (tx, rx) = tokio::sync::mpsc::channel(BODY_CHANNEL_SIZE);
+        tokio::task::spawn_blocking(move || {
+            for chunk in body {
+                if tx.blocking_send(chunk).is_err() {
+                    // The client has gone away.
+                    break;
+                }
+            }
+        });
+        let chunks = futures::stream::unfold(rx, |mut rx| async move {
+            rx.recv().await.map(|chunk| (chunk, rx))
+        });
+        let mut response = ByteStream(chunks).respond_to(request)?;
+        response.set_raw_header("ETag", self.etag);
+        if self.immutable {
+            response.set_raw_header("Cache-Control", "public, max-age=3600");
+        }
+        response.set_raw_header("Vary", "Accept-Encoding");
+        if let Some(encoding) = encoding {
+            response.set_raw_header("Content-Encoding", encoding.name());
+        }
+        Ok(response)
+    }
+}
+
+#[get("/manifest")]
+fn get_manifest(app: &State<App>) -> Result<Json<Manifest>, Debug<anyhow::Error>> {
+    let range =
+        |range: Option<(BlockNumber, BlockNumber)>| range.map(|(from, to)| BlockRange { from, to });
+    let mut genesis = app.db.get_metadata()?.unwrap_or_default().genesis;
+    genesis.sort_unstable();
+    Ok(Json(Manifest {
+        genesis,
+        headers: range(app.db.header_range()),
+        parachain_headers: range(app.db.para_header_range()),
+        storage_changes: range(app.db.storage_changes_range()),
+    }))
 }
 
 #[get("/genesis/<block_number>")]
@@ -25,17 +260,20 @@ fn get_header(app: &State<App>, block_number: BlockNumber) -> Result<Vec<u8>, No
         .ok_or(NotFound(format!("header not found")))
 }
 
+/// Returns the headers from `start` to the first one with a justification.
+///
+/// The response changes as the database grows until a justification is found, so it is not
+/// marked cacheable.
 #[get("/headers/<start>")]
-fn get_headers(app: &State<App>, start: BlockNumber) -> Result<Vec<u8>, NotFound<String>> {
+fn get_headers(app: &State<App>, start: BlockNumber) -> Result<EncodedVec, NotFound<String>> {
     let mut headers = vec![];
     for block in start..start + 10000 {
         match app.db.get_header(block) {
             Some(data) => {
                 let info = crate::cache::BlockInfo::decode(&mut &data[..])
                     .or(Err(NotFound("Codec error".into())))?;
-                let end = info.justification.is_some();
-                headers.push(info);
-                if end {
+                headers.push(data);
+                if info.justification.is_some() {
                     break;
                 }
             }
@@ -46,7 +284,55 @@ fn get_headers(app: &State<App>, start: BlockNumber) -> Result<Vec<u8>, NotFound
         }
     }
     log::info!("Got {} headers", headers.len());
-    Ok(headers.encode())
+    let count = headers.len() as BlockNumber;
+    let last = headers.last().cloned().unwrap_or_default();
+    Ok(EncodedVec::new(
+        "headers",
+        start,
+        count,
+        &last,
+        false,
+        headers.into_iter(),
+    ))
+}
+
+/// Streams the items stored at `start..start + count`.
+///
+/// Only the first and the last items are read before responding, to check the range is available
+/// and to derive the ETag. The items are imported in order, so the blocks in between are expected
+/// to exist too. If one is missing anyway, the body is truncated there and fails to decode.
+fn get_range(
+    app: &State<App>,
+    kind: &'static str,
+    get: fn(&CacheDB, BlockNumber) -> Option<Vec<u8>>,
+    start: BlockNumber,
+    count: BlockNumber,
+) -> Result<EncodedVec, (Status, String)> {
+    if count == 0 || count > MAX_RANGE_COUNT {
+        return Err((
+            Status::BadRequest,
+            format!("count must be between 1 and {MAX_RANGE_COUNT}"),
+        ));
+    }
+    let end = start
+        .checked_add(count)
+        .ok_or((Status::BadRequest, "invalid range".into()))?;
+    let not_found = |block| {
+        log::warn!("{} at {} not found", kind, block);
+        (Status::NotFound, format!("{kind} not found"))
+    };
+    get(&app.db, start).ok_or_else(|| not_found(start))?;
+    let last = get(&app.db, end - 1).ok_or_else(|| not_found(end - 1))?;
+    let db = app.db.clone();
+    let items = (start..end).map_while(move |block| {
+        let item = get(&db, block);
+        if item.is_none() {
+            log::warn!("{} at {} not found", kind, block);
+        }
+        item
+    });
+    log::info!("Streaming {} {} from {}", count, kind, start);
+    Ok(EncodedVec::new(kind, start, count, &last, true, items))
 }
 
 #[get("/parachain-headers/<start>/<count>")]
@@ -54,24 +340,14 @@ fn get_parachain_headers(
     app: &State<App>,
     start: BlockNumber,
     count: BlockNumber,
-) -> Result<Vec<u8>, NotFound<String>> {
-    let mut headers = vec![];
-    for block in start..start + count {
-        match app.db.get_para_header(block) {
-            Some(data) => {
-                use pherry::types::Header;
-                let header =
-                    Header::decode(&mut &data[..]).or(Err(NotFound("Codec error".into())))?;
-                headers.push(header);
-            }
-            None => {
-                log::warn!("header at {} not found", block);
-                return Err(NotFound("header not found".into()));
-            }
-        }
-    }
-    log::info!("Got {} parachain headers", headers.len());
-    Ok(headers.encode())
+) -> Result<EncodedVec, (Status, String)> {
+    get_range(
+        app,
+        "parachain-headers",
+        CacheDB::get_para_header,
+        start,
+        count,
+    )
 }
 
 #[get("/storage-changes/<start>/<count>")]
@@ -79,33 +355,23 @@ fn get_storage_changes(
     app: &State<App>,
     start: BlockNumber,
     count: BlockNumber,
-) -> Result<Vec<u8>, NotFound<String>> {
-    let mut changes = vec![];
-    for block in start..start + count {
-        match app.db.get_storage_changes(block) {
-            Some(data) => {
-                let header = crate::cache::BlockHeaderWithChanges::decode(&mut &data[..])
-                    .or(Err(NotFound("Codec error".into())))?;
-                changes.push(header);
-            }
-            None => {
-                log::warn!("changes at {} not found", block);
-                return Err(NotFound("header not found".into()));
-            }
-        }
-    }
-    log::info!("Got {} storage changes", changes.len());
-    Ok(changes.encode())
+) -> Result<EncodedVec, (Status, String)> {
+    get_range(
+        app,
+        "storage-changes",
+        CacheDB::get_storage_changes,
+        start,
+        count,
+    )
 }
 
-pub(crate) async fn serve(db: &str) -> anyhow::Result<()> {
+pub(crate) async fn serve(db: Arc<CacheDB>) -> anyhow::Result<()> {
     let _rocket = rocket::build()
-        .manage(App {
-            db: CacheDB::open(db)?,
-        })
+        .manage(App { db })
         .mount(
             "/",
             routes![
+                get_manifest,
                 get_genesis,
                 get_header,
                 get_headers,
@@ -1,5 +1,5 @@
 use crate::{
-    types::{utils::raw_proof, Hash, ParachainApi, RelaychainApi, StorageKey},
+    types::{utils::raw_proof, AccountId, Hash, Index, ParachainApi, RelaychainApi, StorageKey},
     Error,
 };
 use anyhow::Result;
@@ -113,3 +113,9 @@ pub async fn update_signer_nonce(api: &ParachainApi, signer: &mut SrSigner) -> R
     log::info!("Fetch account {} nonce={}", account_id, nonce);
     Ok(())
 }
+
+/// Gets the nonce of the account at the best block, ignoring the mempool
+pub async fn account_chain_nonce(api: &ParachainApi, account: &AccountId) -> Result<Index> {
+    let info = api.storage().system().account(account, None).await?;
+    Ok(info.nonce)
+}
@@ -1,17 +1,22 @@
 use crate::GRANDPA_ENGINE_ID;
 use anyhow::{anyhow, Result};
 use codec::{Decode, Encode};
+use futures::{StreamExt as _, TryStreamExt as _};
 use phaxt::{
     subxt::{self, rpc::NumberOrHex},
     BlockNumber, Header, ParachainApi, RelaychainApi,
 };
+use reqwest::header::{ACCEPT_ENCODING, CONTENT_ENCODING};
+use serde::{Deserialize, Serialize};
 use std::io::{Read, Write};
 
 use log::info;
 
-pub use phactory_api::blocks::{AuthoritySetChange, BlockHeaderWithChanges, GenesisBlockInfo};
+pub use phactory_api::blocks::{
+    AuthoritySet, AuthoritySetChange, BlockHeaderWithChanges, GenesisBlockInfo,
+};
 
-#[derive(Decode, Encode, Debug)]
+#[derive(Decode, Encode, Debug, Clone)]
 pub struct BlockInfo {
     pub header: Header,
     pub justification: Option<Vec<u8>>,
@@ -19,7 +24,7 @@ pub struct BlockInfo {
     pub authority_set_change: Option<AuthoritySetChange>,
 }
 
-#[derive(Decode, Encode, Debug)]
+#[derive(Decode, Encode, Debug, Clone)]
 pub struct ParaHeader {
     /// Finalized parachain header number
     pub fin_header_num: BlockNumber,
@@ -107,6 +112,7 @@ pub async fn grap_headers_to_file(
         start_at,
         count,
         justification_interval,
+        justification_interval,
         |info| {
             if info.justification.is_some() {
                 info!("Got justification at {}", info.header.number);
@@ -162,12 +168,17 @@ pub async fn get_set_id(api: &RelaychainApi, block: BlockNumber) -> Result<(u64,
     Ok((set_id, block.justifications.is_some()))
 }
 
-async fn grab_headers(
+/// Grabs the relaychain headers and passes them to `f`.
+///
+/// The justifications are fetched once per `justification_interval` blocks, after skipping the
+/// first `skip_justification` blocks.
+pub async fn grab_headers(
     api: &RelaychainApi,
     para_api: &ParachainApi,
     start_at: BlockNumber,
     count: BlockNumber,
     justification_interval: u32,
+    skip_justification: u32,
     mut f: impl FnMut(BlockInfo) -> Result<()>,
 ) -> Result<BlockNumber> {
     if start_at == 0 {
@@ -183,7 +194,7 @@ async fn grab_headers(
         .grandpa()
         .current_set_id(Some(header_hash))
         .await?;
-    let mut skip_justitication = justification_interval;
+    let mut skip_justitication = skip_justification;
     let mut grabbed = 0;
 
     let para_id = crate::get_paraid(para_api, None).await?;
@@ -278,7 +289,8 @@ async fn grab_headers(
     Ok(grabbed)
 }
 
-async fn grab_para_headers(
+/// Grabs the parachain headers and passes them to `f`.
+pub async fn grab_para_headers(
     api: &ParachainApi,
     start_at: BlockNumber,
     count: BlockNumber,
@@ -306,7 +318,8 @@ async fn grab_para_headers(
     Ok(grabbed)
 }
 
-async fn grab_storage_changes(
+/// Grabs the storage changes of the parachain blocks and passes them to `f`.
+pub async fn grab_storage_changes(
     api: &ParachainApi,
     start_at: BlockNumber,
     count: BlockNumber,
@@ -356,21 +369,112 @@ pub async fn fetch_genesis_info(
     })
 }
 
+/// The first and the last block of a kind of data in the cache server.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct BlockRange {
+    pub from: BlockNumber,
+    pub to: BlockNumber,
+}
+
+/// The data available in the cache server, served at `/manifest`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Manifest {
+    pub genesis: Vec<BlockNumber>,
+    pub headers: Option<BlockRange>,
+    pub parachain_headers: Option<BlockRange>,
+    pub storage_changes: Option<BlockRange>,
+}
+
+/// Max number of blocks requested in one request to a range endpoint.
+const RANGE_CHUNK_SIZE: BlockNumber = 1000;
+
 #[derive(Clone)]
 pub struct Client {
     base_uri: String,
+    http: reqwest::Client,
+    /// Max number of concurrent requests when fetching a range of blocks.
+    parallelism: usize,
 }
 
 impl Client {
     pub fn new(uri: &str) -> Self {
         Self {
             base_uri: uri.to_string(),
+            http: reqwest::Client::new(),
+            parallelism: 4,
         }
     }
 
-    async fn request<T: Decode>(&self, url: &str) -> Result<T> {
-        let body = reqwest::get(url).await?.bytes().await?;
-        Ok(T::decode(&mut &body[..])?)
+    pub fn with_parallelism(mut self, parallelism: usize) -> Self {
+        self.parallelism = parallelism.max(1);
+        self
+    }
+
+    async fn request<T: Decode + Send + 'static>(&self, url: &str) -> Result<T> {
+        let response = self
+            .http
+            .get(url)
+            .header(ACCEPT_ENCODING, "zstd, gzip")
+            .send()
+            .await?
+            .error_for_status()?;
+        let encoding = response
+            .headers()
+            .get(CONTENT_ENCODING)
+            .map(|value| value.as_bytes().to_vec());
+        let body = response.bytes().await?;
+        // The bodies can be large, decompress and decode them off the async runtime.
+        tokio::task::spawn_blocking(move || {
+            let decompressed;
+            let body = match encoding.as_deref() {
+                None | Some(b"identity") => &body[..],
+                Some(b"zstd") => {
+                    decompressed = zstd::decode_all(&body[..])?;
+                    &decompressed[..]
+                }
+                Some(b"gzip") => {
+                    let mut buffer = vec![];
+                    flate2::read::GzDecoder::new(&body[..]).read_to_end(&mut buffer)?;
+                    decompressed = buffer;
+                    &decompressed[..]
+                }
+                Some(encoding) => {
+                    return Err(anyhow!(
+                        "Unsupported content encoding {}",
+                        String::from_utf8_lossy(encoding)
+                    ))
+                }
+            };
+            Ok(T::decode(&mut &body[..])?)
+        })
+        .await?
+    }
+
+    /// Fetches the blocks in chunks from a range endpoint, `parallelism` chunks at a time.
+    async fn request_range<T: Decode + Send + 'static>(
+        &self,
+        path: &str,
+        start_number: BlockNumber,
+        count: BlockNumber,
+    ) -> Result<Vec<T>> {
+        let end = start_number.saturating_add(count);
+        let starts = (start_number..end).step_by(RANGE_CHUNK_SIZE as usize);
+        let chunks: Vec<Vec<T>> = futures::stream::iter(starts)
+            .map(|from| {
+                let count = RANGE_CHUNK_SIZE.min(end - from);
+                let url = format!("{}/{}/{}/{}", self.base_uri, path, from, count);
+                async move { self.request::<Vec<T>>(&url).await }
+            })
+            .buffered(self.parallelism)
+            .try_collect()
+            .await?;
+        Ok(chunks.into_iter().flatten().collect())
+    }
+
+    pub async fn get_manifest(&self) -> Result<Manifest> {
+        let url = format!("{}/manifest", self.base_uri);
+        let response = self.http.get(url).send().await?.error_for_status()?;
+        Ok(response.json().await?)
     }
 
     pub async fn get_headers(&self, block_number: BlockNumber) -> Result<Vec<BlockInfo>> {
@@ -383,11 +487,8 @@ impl Client {
         start_number: BlockNumber,
         count: BlockNumber,
     ) -> Result<Vec<Header>> {
-        let url = format!(
-            "{}/parachain-headers/{}/{}",
-            self.base_uri, start_number, count
-        );
-        self.request(&url).await
+        self.request_range("parachain-headers", start_number, count)
+            .await
     }
 
     pub async fn get_storage_changes(
@@ -395,11 +496,8 @@ impl Client {
         start_number: BlockNumber,
         count: BlockNumber,
     ) -> Result<Vec<BlockHeaderWithChanges>> {
-        let url = format!(
-            "{}/storage-changes/{}/{}",
-            self.base_uri, start_number, count
-        );
-        self.request(&url).await
+        self.request_range("storage-changes", start_number, count)
+            .await
     }
 
     pub async fn get_genesis(&self, block_number: BlockNumber) -> Result<GenesisBlockInfo> {
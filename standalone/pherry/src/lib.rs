@@ -3,7 +3,10 @@ use log::{debug, error, info, warn};
 use sp_core::crypto::AccountId32;
 use sp_runtime::generic::Era;
 use std::cmp;
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
 use std::str::FromStr;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 use tokio::time::sleep;
 
@@ -15,9 +18,12 @@ use sp_finality_grandpa::{AuthorityList, SetId, VersionedAuthorityList, GRANDPA_
 
 mod endpoint;
 mod error;
+mod metrics;
 mod msg_sync;
+mod multi_worker;
 mod notify_client;
 mod prefetcher;
+mod tx_tracker;
 
 pub mod chain_client;
 pub mod headers_cache;
@@ -37,10 +43,13 @@ use phactory_api::pruntime_client;
 
 use clap::{AppSettings, Parser};
 use headers_cache::Client as CacheClient;
-use msg_sync::{Error as MsgSyncError, Receiver, Sender};
+use metrics::{Metrics, Phase, WorkerMetrics};
+use msg_sync::{Error as MsgSyncError, Receiver, Sender, SubmitOptions};
 use notify_client::NotifyClient;
+use prefetcher::PrefetchClient;
+use tx_tracker::TxTracker;
 
-#[derive(Parser, Debug)]
+#[derive(Parser, Debug, Clone)]
 #[clap(
     about = "Sync messages between pruntime and the blockchain.",
     version,
@@ -175,6 +184,12 @@ pub struct Args {
         help = "The charge transaction payment, unit: balance"
     )]
     tip: u128,
+    #[clap(
+        default_value = "1000000000",
+        long,
+        help = "The tip added each time a stuck or lost message is resubmitted, unit: balance"
+    )]
+    tip_bump: u128,
     #[clap(
         default_value = "4",
         long,
@@ -187,6 +202,12 @@ pub struct Args {
         help = "Max number of messages to be submitted per-round"
     )]
     max_sync_msgs_per_round: u64,
+    #[clap(
+        default_value = "1",
+        long,
+        help = "Max number of messages packed into one extrinsic. Values above 1 require the runtime to support PhalaMq::sync_offchain_messages"
+    )]
+    max_msgs_per_batch: usize,
 
     #[clap(long, help = "Auto restart self after an error occurred")]
     auto_restart: bool,
@@ -205,6 +226,13 @@ pub struct Args {
     #[clap(default_value = "")]
     headers_cache_uri: String,
 
+    #[clap(
+        default_value = "4",
+        long,
+        help = "Max number of concurrent requests to the headers cache when fetching a range of blocks"
+    )]
+    headers_cache_parallelism: usize,
+
     #[clap(long, help = "Stop when synced to given parachain block")]
     #[clap(default_value_t = BlockNumber::MAX)]
     to_block: BlockNumber,
@@ -214,6 +242,18 @@ pub struct Args {
         help = "Disable syncing waiting parachain blocks in the beginning of each round"
     )]
     disable_sync_waiting_paraheaders: bool,
+
+    #[clap(
+        long,
+        help = "Drive the workers listed in the given JSON file instead of a single pRuntime. The chain data is fetched once and shared by the workers."
+    )]
+    workers_config: Option<String>,
+
+    #[clap(
+        long,
+        help = "Serve the Prometheus metrics at /metrics and the sync status at /status on the given address"
+    )]
+    metrics_addr: Option<SocketAddr>,
 }
 
 struct RunningFlags {
@@ -222,6 +262,102 @@ struct RunningFlags {
     restart_failure_count: u32,
 }
 
+/// The chain data sources of the bridge.
+///
+/// In the multi-worker mode, one instance is shared by all the workers so that the headers and
+/// the storage changes are fetched from the nodes only once.
+#[derive(Clone)]
+struct ChainSources {
+    relay_client: RpcClient,
+    para_client: RpcClient,
+    cache: Option<CacheClient>,
+    fetcher: PrefetchClient,
+    blocks: BlockCache,
+}
+
+impl ChainSources {
+    /// Connects to the chain for `workers` workers. The fetched data is only kept for reuse if
+    /// there are several workers.
+    async fn connect(args: &Args, workers: usize) -> Result<Self> {
+        let relay_client = subxt_connect(&args.substrate_ws_endpoint).await?;
+        info!("Connected to relaychain at: {}", args.substrate_ws_endpoint);
+
+        let para_uri: &str = if args.parachain {
+            &args.collator_ws_endpoint
+        } else {
+            &args.substrate_ws_endpoint
+        };
+        let para_client = subxt_connect(para_uri).await?;
+        info!(
+            "Connected to parachain node at: {}",
+            args.collator_ws_endpoint
+        );
+
+        if !args.no_wait {
+            // Don't start our worker until the substrate node is synced
+            info!("Waiting for substrate to sync blocks...");
+            wait_until_synced(&relay_client).await?;
+            wait_until_synced(&para_client).await?;
+            info!("Substrate sync blocks done");
+        }
+
+        let cache = if !args.headers_cache_uri.is_empty() {
+            Some(
+                CacheClient::new(&args.headers_cache_uri)
+                    .with_parallelism(args.headers_cache_parallelism),
+            )
+        } else {
+            None
+        };
+        // Keep enough data for the workers falling behind the leader within one fetch batch.
+        let (block_cache_size, recent_batches) = if workers > 1 {
+            (args.fetch_blocks as usize * 2, workers * 2)
+        } else {
+            (0, 0)
+        };
+        Ok(Self {
+            relay_client,
+            para_client,
+            cache,
+            fetcher: PrefetchClient::new(recent_batches),
+            blocks: BlockCache::new(block_cache_size),
+        })
+    }
+}
+
+/// The relaychain blocks recently fetched by the workers sharing the [`ChainSources`].
+#[derive(Clone)]
+struct BlockCache {
+    capacity: usize,
+    blocks: Arc<Mutex<BTreeMap<BlockNumber, Block>>>,
+}
+
+impl BlockCache {
+    /// Creates a cache holding at most `capacity` blocks. Nothing is cached if it's zero.
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            blocks: Default::default(),
+        }
+    }
+
+    async fn get(&self, api: &RelaychainApi, number: BlockNumber) -> Result<Block> {
+        if let Some(block) = self.blocks.lock().unwrap().get(&number) {
+            return Ok(block.clone());
+        }
+        let block = get_block_without_storage_changes(api, Some(number)).await?;
+        if self.capacity > 0 {
+            let mut blocks = self.blocks.lock().unwrap();
+            blocks.insert(number, block.clone());
+            while blocks.len() > self.capacity {
+                let oldest = *blocks.keys().next().expect("The cache is not empty; qed.");
+                blocks.remove(&oldest);
+            }
+        }
+        Ok(block)
+    }
+}
+
 struct BlockSyncState {
     blocks: Vec<Block>,
     /// Tracks the latest known authority set id at a certain block.
@@ -325,6 +461,7 @@ pub async fn batch_sync_storage_changes(
     pr: &PrClient,
     api: &ParachainApi,
     cache: Option<&CacheClient>,
+    fetcher: &PrefetchClient,
     from: BlockNumber,
     to: BlockNumber,
     batch_size: BlockNumber,
@@ -334,8 +471,6 @@ pub async fn batch_sync_storage_changes(
         to as i64 - from as i64 + 1
     );
 
-    let mut fetcher = prefetcher::PrefetchClient::new();
-
     for from in (from..=to).step_by(batch_size as _) {
         let to = to.min(from.saturating_add(batch_size - 1));
         let storage_changes = fetcher
@@ -475,6 +610,7 @@ async fn batch_sync_block(
     api: &RelaychainApi,
     paraclient: &ParachainApi,
     cache: Option<&CacheClient>,
+    fetcher: &PrefetchClient,
     pr: &PrClient,
     sync_state: &mut BlockSyncState,
     batch_window: BlockNumber,
@@ -499,8 +635,16 @@ async fn batch_sync_block(
     macro_rules! sync_blocks_to {
         ($to: expr) => {
             if next_blocknum <= $to {
-                batch_sync_storage_changes(pr, paraclient, cache, next_blocknum, $to, batch_window)
-                    .await?;
+                batch_sync_storage_changes(
+                    pr,
+                    paraclient,
+                    cache,
+                    fetcher,
+                    next_blocknum,
+                    $to,
+                    batch_window,
+                )
+                .await?;
                 synced_blocks += $to - next_blocknum + 1;
                 next_blocknum = $to + 1;
             };
@@ -614,12 +758,13 @@ async fn batch_sync_block(
 
         let hdr_synced_to = if parachain {
             let hdr_synced_to =
-                match get_finalized_header(api, paraclient, last_header_hash).await? {
+                match get_finalized_header(api, paraclient, fetcher, last_header_hash).await? {
                     Some((fin_header, proof)) => {
                         sync_parachain_header(
                             pr,
                             paraclient,
                             cache,
+                            fetcher,
                             fin_header.number,
                             next_para_headernum,
                             proof,
@@ -649,10 +794,15 @@ async fn batch_sync_block(
 async fn get_finalized_header(
     api: &RelaychainApi,
     para_api: &ParachainApi,
+    fetcher: &PrefetchClient,
     last_header_hash: Hash,
 ) -> Result<Option<(Header, Vec<Vec<u8>> /*proof*/)>> {
-    let para_id = get_paraid(para_api, None).await?;
-    get_finalized_header_with_paraid(api, para_id, last_header_hash).await
+    fetcher
+        .fetch_para_fin_header(last_header_hash, || async {
+            let para_id = get_paraid(para_api, None).await?;
+            get_finalized_header_with_paraid(api, para_id, last_header_hash).await
+        })
+        .await
 }
 
 async fn get_finalized_header_with_paraid(
@@ -693,13 +843,14 @@ async fn maybe_sync_waiting_parablocks(
     api: &RelaychainApi,
     para_api: &ParachainApi,
     cache_client: &Option<CacheClient>,
+    fetcher: &PrefetchClient,
     info: &PhactoryInfo,
     batch_window: BlockNumber,
 ) -> Result<()> {
     let mut fin_header = None;
     if let Some(cache) = &cache_client {
-        let mut cached_headers = cache
-            .get_headers(info.headernum - 1)
+        let mut cached_headers = fetcher
+            .fetch_cached_headers(cache, info.headernum - 1)
             .await
             .unwrap_or_default();
         if cached_headers.len() == 1 {
@@ -711,7 +862,7 @@ async fn maybe_sync_waiting_parablocks(
     }
     if fin_header.is_none() {
         let last_header_hash = get_header_hash(&api.client, Some(info.headernum - 1)).await?;
-        fin_header = get_finalized_header(&api, &para_api, last_header_hash)
+        fin_header = get_finalized_header(&api, &para_api, fetcher, last_header_hash)
             .await?
             .map(|(h, proof)| (h.number, proof));
     }
@@ -731,6 +882,7 @@ async fn maybe_sync_waiting_parablocks(
             &pr,
             &para_api,
             cache_client.as_ref(),
+            fetcher,
             fin_header_num,
             info.para_headernum,
             proof,
@@ -741,6 +893,7 @@ async fn maybe_sync_waiting_parablocks(
                 &pr,
                 &para_api,
                 cache_client.as_ref(),
+                fetcher,
                 info.blocknum,
                 hdr_synced_to,
                 batch_window,
@@ -760,6 +913,7 @@ async fn sync_parachain_header(
     pr: &PrClient,
     para_api: &ParachainApi,
     cache: Option<&CacheClient>,
+    fetcher: &PrefetchClient,
     para_fin_block_number: BlockNumber,
     next_headernum: BlockNumber,
     header_proof: Vec<Vec<u8>>,
@@ -773,8 +927,8 @@ async fn sync_parachain_header(
     }
     let mut para_headers = if let Some(cache) = cache {
         let count = para_fin_block_number - next_headernum + 1;
-        cache
-            .get_parachain_headers(next_headernum, count)
+        fetcher
+            .fetch_cached_para_headers(cache, next_headernum, count)
             .await
             .unwrap_or_default()
     } else {
@@ -793,12 +947,7 @@ async fn sync_parachain_header(
                     return Ok(next_headernum - 1);
                 }
             };
-            let header = para_api
-                .client
-                .rpc()
-                .header(Some(hash))
-                .await?
-                .ok_or(Error::BlockNotFound)?;
+            let header = fetcher.fetch_para_header(&para_api.client, hash).await?;
             para_headers.push(header);
         }
     } else {
@@ -915,22 +1064,37 @@ async fn register_worker(
     let payload = attestation
         .payload
         .ok_or(anyhow!("Missing attestation payload"))?;
-    let pruntime_info = Decode::decode(&mut &encoded_runtime_info[..])
-        .map_err(|_| anyhow!("Decode pruntime info failed"))?;
-    let attestation =
-        phaxt::khala::runtime_types::phala_pallets::utils::attestation::Attestation::SgxIas {
-            ra_report: payload.report.as_bytes().to_vec(),
-            signature: payload.signature,
-            raw_signing_cert: payload.signing_cert,
-        };
     chain_client::update_signer_nonce(para_api, signer).await?;
     let params = mk_params(para_api, args.longevity, args.tip).await?;
-    let ret = para_api
-        .tx()
-        .phala_registry()
-        .register_worker(pruntime_info, attestation)?
-        .sign_and_submit_then_watch(signer, params)
-        .await;
+    let ret = if attestation.provider == "SGX_DCAP" {
+        // The bundled metadata predates `Attestation::SgxDcap`, so the call is encoded by hand. The
+        // collateral comes SCALE encoded in `signing_cert`.
+        let pruntime_info = Decode::decode(&mut &encoded_runtime_info[..])
+            .map_err(|_| anyhow!("Decode pruntime info failed"))?;
+        let attestation = phaxt::calls::Attestation::SgxDcap {
+            quote: payload.signature,
+            collateral: Decode::decode(&mut &payload.signing_cert[..])
+                .map_err(|_| anyhow!("Decode DCAP collateral failed"))?,
+        };
+        phaxt::calls::register_worker(&para_api.client, pruntime_info, attestation)
+            .sign_and_submit_then_watch(signer, params)
+            .await
+    } else {
+        let pruntime_info = Decode::decode(&mut &encoded_runtime_info[..])
+            .map_err(|_| anyhow!("Decode pruntime info failed"))?;
+        let attestation =
+            phaxt::khala::runtime_types::phala_pallets::utils::attestation::Attestation::SgxIas {
+                ra_report: payload.report.as_bytes().to_vec(),
+                signature: payload.signature,
+                raw_signing_cert: payload.signing_cert,
+            };
+        para_api
+            .tx()
+            .phala_registry()
+            .register_worker(pruntime_info, attestation)?
+            .sign_and_submit_then_watch(signer, params)
+            .await
+    };
     if ret.is_err() {
         error!("FailedToCallRegisterWorker: {:?}", ret);
         return Err(anyhow!(Error::FailedToCallRegisterWorker));
@@ -992,43 +1156,41 @@ pub async fn subxt_connect<T: subxt::Config>(uri: &str) -> Result<subxt::Client<
 async fn bridge(
     args: &Args,
     flags: &mut RunningFlags,
+    metrics: &WorkerMetrics,
     err_report: Sender<MsgSyncError>,
 ) -> Result<()> {
     // Connect to substrate
+    metrics.set_phase(Phase::Connecting);
+    let sources = ChainSources::connect(args, 1).await?;
+    sync_worker(args, &sources, flags, metrics, err_report).await
+}
 
-    let api: RelaychainApi = subxt_connect(&args.substrate_ws_endpoint).await?.into();
-    info!("Connected to relaychain at: {}", args.substrate_ws_endpoint);
-
-    let para_uri: &str = if args.parachain {
-        &args.collator_ws_endpoint
-    } else {
-        &args.substrate_ws_endpoint
-    };
-    let para_api: ParachainApi = subxt_connect(para_uri).await?.into();
-    info!(
-        "Connected to parachain node at: {}",
-        args.collator_ws_endpoint
-    );
-
-    if !args.no_wait {
-        // Don't start our worker until the substrate node is synced
-        info!("Waiting for substrate to sync blocks...");
-        wait_until_synced(&api.client).await?;
-        wait_until_synced(&para_api.client).await?;
-        info!("Substrate sync blocks done");
-    }
-
-    let cache_client = if !args.headers_cache_uri.is_empty() {
-        Some(CacheClient::new(&args.headers_cache_uri))
-    } else {
-        None
-    };
+/// Initializes, registers and syncs the pRuntime at `args.pruntime_endpoint`.
+async fn sync_worker(
+    args: &Args,
+    sources: &ChainSources,
+    flags: &mut RunningFlags,
+    metrics: &WorkerMetrics,
+    err_report: Sender<MsgSyncError>,
+) -> Result<()> {
+    let api: RelaychainApi = sources.relay_client.clone().into();
+    let para_api: ParachainApi = sources.para_client.clone().into();
+    let cache_client = sources.cache.clone();
+    let fetcher = &sources.fetcher;
 
     // Other initialization
     let pr = pruntime_client::new_pruntime_client(args.pruntime_endpoint.clone());
     let pair = <sr25519::Pair as Pair>::from_string(&args.mnemonic, None)
         .expect("Bad privkey derive path");
     let mut signer: SrSigner = subxt::PairSigner::new(pair);
+    let tx_tracker = TxTracker::new();
+    let submit_options = SubmitOptions {
+        tip: args.tip,
+        tip_bump: args.tip_bump,
+        longevity: args.longevity,
+        max_sync_msgs_per_round: args.max_sync_msgs_per_round,
+        max_msgs_per_batch: args.max_msgs_per_batch,
+    };
     let nc = NotifyClient::new(&args.notify_endpoint);
     let mut pruntime_initialized = false;
     let mut pruntime_new_init = false;
@@ -1047,6 +1209,7 @@ async fn bridge(
     if !args.no_init {
         if !info.initialized {
             warn!("pRuntime not initialized. Requesting init...");
+            metrics.set_phase(Phase::InitializingRuntime);
             let start_header =
                 resolve_start_header(&para_api, args.parachain, args.start_header).await?;
             info!("Resolved start header at {}", start_header);
@@ -1097,8 +1260,10 @@ async fn bridge(
 
     if args.no_sync {
         if !args.no_register {
+            metrics.set_phase(Phase::Registering);
             try_register_worker(&pr, &para_api, &mut signer, operator, &args).await?;
             flags.worker_registered = true;
+            metrics.set_worker_registered(true);
         }
         // Try bind worker endpoint
         if !args.no_bind && info.public_key.is_some() {
@@ -1113,6 +1278,7 @@ async fn bridge(
             }
         }
         warn!("Block sync disabled.");
+        metrics.set_phase(Phase::Stopped);
         return Ok(());
     }
 
@@ -1126,6 +1292,10 @@ async fn bridge(
         // update the latest pRuntime state
         let info = pr.get_info(()).await?;
         info!("pRuntime get_info response: {:#?}", info);
+        metrics.set_pruntime_heights(
+            info.headernum.saturating_sub(1),
+            info.blocknum.saturating_sub(1),
+        );
         if info.blocknum >= args.to_block {
             info!("Reached target block: {}", args.to_block);
             return Ok(());
@@ -1154,6 +1324,7 @@ async fn bridge(
                 &pr,
                 &para_api,
                 cache_client.as_ref(),
+                fetcher,
                 info.blocknum,
                 next_headernum - 1,
                 args.sync_blocks,
@@ -1169,6 +1340,7 @@ async fn bridge(
                 &api,
                 &para_api,
                 &cache_client,
+                fetcher,
                 &info,
                 args.sync_blocks,
             )
@@ -1178,7 +1350,10 @@ async fn bridge(
         // Sync the relaychain and parachain data from the cache service as much as possible
         if let (true, Some(cache)) = (args.parachain, &cache_client) {
             info!("Fetching headers at {} from cache...", info.headernum);
-            let cached_headers = cache.get_headers(info.headernum).await.unwrap_or_default();
+            let cached_headers = fetcher
+                .fetch_cached_headers(cache, info.headernum)
+                .await
+                .unwrap_or_default();
             if cached_headers.is_empty() {
                 info!("Header cache missing at {}", info.headernum);
             } else {
@@ -1189,10 +1364,12 @@ async fn bridge(
                 );
                 sync_state.authory_set_state = None;
                 sync_state.blocks.clear();
+                metrics.set_phase(Phase::CatchingUp);
                 sync_with_cached_headers(
                     &pr,
                     &para_api,
                     cache_client.as_ref(),
+                    fetcher,
                     info.blocknum,
                     info.para_headernum,
                     cached_headers,
@@ -1204,6 +1381,15 @@ async fn bridge(
         }
 
         let latest_block = get_block_at(&api.client, None).await?.0.block;
+        // Only query the parachain tip for the metrics when they are served
+        if args.metrics_addr.is_some() {
+            let para_tip = if args.parachain {
+                get_header_at(&para_api.client, None).await?.0.number
+            } else {
+                latest_block.header.number
+            };
+            metrics.set_node_heights(latest_block.header.number, para_tip);
+        }
         // remove the blocks not needed in the buffer. info.blocknum is the next required block
         while let Some(ref b) = sync_state.blocks.first() {
             if b.block.header.number >= info.blocknum {
@@ -1245,7 +1431,7 @@ async fn bridge(
         };
 
         for b in next_block..=batch_end {
-            let block = get_block_without_storage_changes(&api, Some(b)).await?;
+            let block = sources.blocks.get(&api, b).await?;
 
             if block.justifications.is_some() {
                 debug!("block with justification at: {}", block.block.header.number);
@@ -1258,6 +1444,7 @@ async fn bridge(
             &api,
             &para_api,
             cache_client.as_ref(),
+            fetcher,
             &pr,
             &mut sync_state,
             args.sync_blocks,
@@ -1270,9 +1457,11 @@ async fn bridge(
         if synced_blocks == 0 && !more_blocks {
             if !initial_sync_finished && !args.no_register {
                 if !flags.worker_registered {
+                    metrics.set_phase(Phase::Registering);
                     try_register_worker(&pr, &para_api, &mut signer, operator.clone(), &args)
                         .await?;
                     flags.worker_registered = true;
+                    metrics.set_worker_registered(true);
                 }
             }
 
@@ -1294,6 +1483,7 @@ async fn bridge(
 
             // STATUS: initial_sync_finished = true
             initial_sync_finished = true;
+            metrics.set_phase(Phase::Synced);
             nc.notify(&NotifyReq {
                 headernum: info.headernum,
                 blocknum: info.blocknum,
@@ -1310,9 +1500,9 @@ async fn bridge(
                     &para_api,
                     &pr,
                     &mut signer,
-                    args.tip,
-                    args.longevity,
-                    args.max_sync_msgs_per_round,
+                    &tx_tracker,
+                    &submit_options,
+                    metrics,
                     err_report.clone(),
                 )
                 .await?;
@@ -1325,12 +1515,14 @@ async fn bridge(
                 let next_pr = pruntime_client::new_pruntime_client(
                     args.next_pruntime_endpoint.clone().unwrap(),
                 );
+                metrics.set_phase(Phase::HandingOver);
                 handover_worker_key(&pr, &next_pr).await?;
             }
 
             sleep(Duration::from_millis(args.dev_wait_block_ms)).await;
             continue;
         }
+        metrics.set_phase(Phase::CatchingUp);
     }
     Ok(())
 }
@@ -1354,16 +1546,19 @@ fn preprocess_args(args: &mut Args) {
 async fn collect_async_errors(
     mut threshold: Option<u64>,
     mut err_receiver: Receiver<MsgSyncError>,
+    metrics: &WorkerMetrics,
 ) {
     let threshold_bak = threshold.unwrap_or_default();
     loop {
         match err_receiver.recv().await {
             Some(error) => match error {
                 MsgSyncError::BadSignature => {
+                    metrics.rpc_error("bad_signature");
                     warn!("tx received bad signature, restarting...");
                     return;
                 }
                 MsgSyncError::OtherRpcError => {
+                    metrics.rpc_error("other");
                     if let Some(threshold) = &mut threshold {
                         if *threshold == 0 {
                             warn!("{} tx errors reported, restarting...", threshold_bak);
@@ -1431,29 +1626,47 @@ pub async fn pherry_main() {
     let mut args = Args::parse();
     preprocess_args(&mut args);
 
+    let metrics = Metrics::new();
+    if let Some(addr) = args.metrics_addr {
+        let metrics = metrics.clone();
+        tokio::spawn(async move {
+            if let Err(err) = metrics::serve(metrics, addr).await {
+                error!("Failed to serve the metrics: {:?}", err);
+            }
+        });
+    }
+
+    if let Some(config) = &args.workers_config {
+        let code = multi_worker::run(&args, config, &metrics).await;
+        std::process::exit(code);
+    }
+
     let mut flags = RunningFlags {
         worker_registered: false,
         endpoint_registered: false,
         restart_failure_count: 0,
     };
+    let worker_metrics = metrics.worker(metrics::DEFAULT_WORKER);
 
     loop {
         let (sender, receiver) = msg_sync::create_report_channel();
         let threshold = args.restart_on_rpc_error_threshold;
         tokio::select! {
-            res = bridge(&args, &mut flags, sender) => {
+            res = bridge(&args, &mut flags, &worker_metrics, sender) => {
                 if let Err(err) = res {
                     info!("bridge() exited with error: {:?}", err);
+                    worker_metrics.set_error(format!("{:?}", err));
                 } else {
                     break;
                 }
             }
-            () = collect_async_errors(threshold, receiver) => ()
+            () = collect_async_errors(threshold, receiver, &worker_metrics) => ()
         };
         if !args.auto_restart || flags.restart_failure_count > args.max_restart_retries {
             std::process::exit(if flags.worker_registered { 1 } else { 2 });
         }
         flags.restart_failure_count += 1;
+        worker_metrics.restarted();
         sleep(Duration::from_secs(2)).await;
         info!("Restarting...");
     }
@@ -1463,6 +1676,7 @@ async fn sync_with_cached_headers(
     pr: &PrClient,
     para_api: &ParachainApi,
     cache: Option<&CacheClient>,
+    fetcher: &PrefetchClient,
     next_blocknum: BlockNumber,
     next_para_headernum: BlockNumber,
     mut headers: Vec<headers_cache::BlockInfo>,
@@ -1490,6 +1704,7 @@ async fn sync_with_cached_headers(
             pr,
             para_api,
             cache,
+            fetcher,
             para_header.fin_header_num,
             next_para_headernum,
             para_header.proof,
@@ -1500,6 +1715,7 @@ async fn sync_with_cached_headers(
                 pr,
                 para_api,
                 cache,
+                fetcher,
                 next_blocknum,
                 hdr_synced_to,
                 batch_window,
@@ -0,0 +1,383 @@
+//! Prometheus metrics and the sync status of the workers driven by pherry.
+//!
+//! Served at `/metrics` and `/status` when `--metrics-addr` is given.
+
+use anyhow::Result;
+use prometheus::{Encoder as _, IntCounterVec, IntGaugeVec, Opts, Registry, TextEncoder};
+use rocket::response::Debug;
+use rocket::serde::json::Json;
+use rocket::{get, routes, State};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+use crate::types::BlockNumber;
+
+/// The name of the worker in the single worker mode.
+pub const DEFAULT_WORKER: &str = "default";
+
+/// What the bridge of a worker is doing.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Phase {
+    Connecting,
+    InitializingRuntime,
+    Registering,
+    CatchingUp,
+    Synced,
+    HandingOver,
+    Restarting,
+    Stopped,
+}
+
+impl Default for Phase {
+    fn default() -> Self {
+        Phase::Connecting
+    }
+}
+
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct WorkerStatus {
+    pub phase: Phase,
+    pub relay_node_height: BlockNumber,
+    pub relay_pruntime_height: BlockNumber,
+    pub para_node_height: BlockNumber,
+    pub para_pruntime_height: BlockNumber,
+    pub worker_registered: bool,
+    pub restarts: u64,
+    pub last_error: Option<String>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct StatusReport {
+    pub workers: BTreeMap<String, WorkerStatus>,
+}
+
+pub struct Metrics {
+    registry: Registry,
+    relay_block_height: IntGaugeVec,
+    para_block_height: IntGaugeVec,
+    egress_queue_length: IntGaugeVec,
+    messages_submitted: IntCounterVec,
+    messages_failed: IntCounterVec,
+    messages_resubmitted: IntCounterVec,
+    rpc_errors: IntCounterVec,
+    restarts: IntCounterVec,
+    status: Mutex<BTreeMap<String, WorkerStatus>>,
+}
+
+impl Metrics {
+    pub fn new() -> Arc<Self> {
+        let registry = Registry::new();
+        macro_rules! register {
+            ($kind: ident, $name: expr, $help: expr, $labels: expr) => {{
+                let metric = $kind::new(Opts::new($name, $help), $labels)
+                    .expect("The metric options are valid; qed.");
+                registry
+                    .register(Box::new(metric.clone()))
+                    .expect("The metric names are unique; qed.");
+                metric
+            }};
+        }
+        Arc::new(Self {
+            relay_block_height: register!(
+                IntGaugeVec,
+                "pherry_relay_block_height",
+                "The relaychain height of the node and the pRuntime",
+                &["worker", "source"]
+            ),
+            para_block_height: register!(
+                IntGaugeVec,
+                "pherry_para_block_height",
+                "The parachain height of the node and the pRuntime",
+                &["worker", "source"]
+            ),
+            egress_queue_length: register!(
+                IntGaugeVec,
+                "pherry_egress_queue_length",
+                "The number of egress messages held by the pRuntime",
+                &["worker"]
+            ),
+            messages_submitted: register!(
+                IntCounterVec,
+                "pherry_messages_submitted_total",
+                "The number of egress messages included in a block",
+                &["worker", "sender"]
+            ),
+            messages_failed: register!(
+                IntCounterVec,
+                "pherry_messages_failed_total",
+                "The number of egress messages failed to submit",
+                &["worker", "sender"]
+            ),
+            messages_resubmitted: register!(
+                IntCounterVec,
+                "pherry_messages_resubmitted_total",
+                "The number of egress messages resubmitted after stuck or lost in the txpool",
+                &["worker", "sender"]
+            ),
+            rpc_errors: register!(
+                IntCounterVec,
+                "pherry_rpc_errors_total",
+                "The number of tx submission errors counted towards the restart threshold",
+                &["worker", "kind"]
+            ),
+            restarts: register!(
+                IntCounterVec,
+                "pherry_restarts_total",
+                "The number of bridge restarts",
+                &["worker"]
+            ),
+            registry,
+            status: Default::default(),
+        })
+    }
+
+    /// Returns the handle to update the metrics of the given worker.
+    pub fn worker(self: &Arc<Self>, name: &str) -> WorkerMetrics {
+        self.status
+            .lock()
+            .unwrap()
+            .entry(name.to_string())
+            .or_default();
+        WorkerMetrics {
+            name: name.to_string(),
+            metrics: self.clone(),
+        }
+    }
+
+    pub fn encode(&self) -> Result<String> {
+        let mut buffer = vec![];
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+        Ok(String::from_utf8(buffer)?)
+    }
+
+    pub fn status(&self) -> StatusReport {
+        StatusReport {
+            workers: self.status.lock().unwrap().clone(),
+        }
+    }
+}
+
+/// The metrics of one worker.
+#[derive(Clone)]
+pub struct WorkerMetrics {
+    name: String,
+    metrics: Arc<Metrics>,
+}
+
+impl WorkerMetrics {
+    fn update_status(&self, f: impl FnOnce(&mut WorkerStatus)) {
+        let mut status = self.metrics.status.lock().unwrap();
+        f(status.entry(self.name.clone()).or_default());
+    }
+
+    pub fn set_phase(&self, phase: Phase) {
+        self.update_status(|status| status.phase = phase);
+    }
+
+    pub fn set_worker_registered(&self, registered: bool) {
+        self.update_status(|status| status.worker_registered = registered);
+    }
+
+    pub fn set_error(&self, error: String) {
+        self.update_status(|status| status.last_error = Some(error));
+    }
+
+    pub fn set_pruntime_heights(&self, relay: BlockNumber, para: BlockNumber) {
+        let m = &self.metrics;
+        m.relay_block_height
+            .with_label_values(&[&self.name, "pruntime"])
+            .set(relay as _);
+        m.para_block_height
+            .with_label_values(&[&self.name, "pruntime"])
+            .set(para as _);
+        self.update_status(|status| {
+            status.relay_pruntime_height = relay;
+            status.para_pruntime_height = para;
+        });
+    }
+
+    pub fn set_node_heights(&self, relay: BlockNumber, para: BlockNumber) {
+        let m = &self.metrics;
+        m.relay_block_height
+            .with_label_values(&[&self.name, "node"])
+            .set(relay as _);
+        m.para_block_height
+            .with_label_values(&[&self.name, "node"])
+            .set(para as _);
+        self.update_status(|status| {
+            status.relay_node_height = relay;
+            status.para_node_height = para;
+        });
+    }
+
+    pub fn set_egress_queue_length(&self, len: usize) {
+        self.metrics
+            .egress_queue_length
+            .with_label_values(&[&self.name])
+            .set(len as _);
+    }
+
+    pub fn message_submitted(&self, sender: &str) {
+        self.metrics
+            .messages_submitted
+            .with_label_values(&[&self.name, sender])
+            .inc();
+    }
+
+    pub fn message_failed(&self, sender: &str) {
+        self.metrics
+            .messages_failed
+            .with_label_values(&[&self.name, sender])
+            .inc();
+    }
+
+    pub fn message_resubmitted(&self, sender: &str) {
+        self.metrics
+            .messages_resubmitted
+            .with_label_values(&[&self.name, sender])
+            .inc();
+    }
+
+    pub fn rpc_error(&self, kind: &str) {
+        self.metrics
+            .rpc_errors
+            .with_label_values(&[&self.name, kind])
+            .inc();
+    }
+
+    pub fn restarted(&self) {
+        self.metrics.restarts.with_label_values(&[&self.name]).inc();
+        self.update_status(|status| {
+            status.phase = Phase::Restarting;
+            status.restarts += 1;
+        });
+    }
+}
+
+#[get("/metrics")]
+fn get_metrics(metrics: &State<Arc<Metrics>>) -> Result<String, Debug<anyhow::Error>> {
+    Ok(metrics.encode()?)
+}
+
+#[get("/status")]
+fn get_status(metrics: &State<Arc<Metrics>>) -> Json<StatusReport> {
+    Json(metrics.status())
+}
+
+pub async fn serve(metrics: Arc<Metrics>, addr: SocketAddr) -> Result<()> {
+    let mut config = rocket::Config {
+        address: addr.ip(),
+        port: addr.port(),
+        ..rocket::Config::release_default()
+    };
+    // Leave the signals to the bridge
+    config.shutdown.ctrlc = false;
+    let _rocket = rocket::custom(config)
+        .manage(metrics)
+        .mount("/", routes![get_metrics, get_status])
+        .launch()
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counters_are_labelled_by_worker() {
+        let metrics = Metrics::new();
+        let alice = metrics.worker("alice");
+        let bob = metrics.worker("bob");
+        alice.message_submitted("gatekeeper");
+        alice.message_submitted("gatekeeper");
+        alice.message_failed("gatekeeper");
+        bob.message_resubmitted("worker");
+        bob.rpc_error("bad_signature");
+
+        let submitted = |worker: &str| {
+            metrics
+                .messages_submitted
+                .with_label_values(&[worker, "gatekeeper"])
+                .get()
+        };
+        assert_eq!(submitted("alice"), 2);
+        assert_eq!(submitted("bob"), 0);
+        assert_eq!(
+            metrics
+                .messages_failed
+                .with_label_values(&["alice", "gatekeeper"])
+                .get(),
+            1
+        );
+        assert_eq!(
+            metrics
+                .messages_resubmitted
+                .with_label_values(&["bob", "worker"])
+                .get(),
+            1
+        );
+        assert_eq!(
+            metrics
+                .rpc_errors
+                .with_label_values(&["bob", "bad_signature"])
+                .get(),
+            1
+        );
+    }
+
+    #[test]
+    fn status_follows_the_updates() {
+        let metrics = Metrics::new();
+        let worker = metrics.worker(DEFAULT_WORKER);
+        assert_eq!(
+            metrics.status().workers[DEFAULT_WORKER].phase,
+            Phase::Connecting
+        );
+        worker.set_phase(Phase::Synced);
+        worker.set_node_heights(100, 50);
+        worker.set_pruntime_heights(90, 40);
+        worker.restarted();
+        worker.set_error("lost connection".into());
+
+        let status = metrics.status().workers[DEFAULT_WORKER].clone();
+        assert_eq!(status.phase, Phase::Restarting);
+        assert_eq!(status.restarts, 1);
+        assert_eq!(status.relay_node_height, 100);
+        assert_eq!(status.para_node_height, 50);
+        assert_eq!(status.relay_pruntime_height, 90);
+        assert_eq!(status.para_pruntime_height, 40);
+        assert_eq!(status.last_error.as_deref(), Some("lost connection"));
+    }
+
+    #[test]
+    fn encode_renders_the_exposition() {
+        let metrics = Metrics::new();
+        let worker = metrics.worker(DEFAULT_WORKER);
+        worker.set_node_heights(100, 50);
+        worker.set_egress_queue_length(3);
+        worker.message_submitted("gatekeeper");
+        worker.restarted();
+
+        let text = metrics.encode().unwrap();
+        for line in [
+            "# TYPE pherry_relay_block_height gauge",
+            r#"pherry_relay_block_height{source="node",worker="default"} 100"#,
+            r#"pherry_para_block_height{source="node",worker="default"} 50"#,
+            r#"pherry_egress_queue_length{worker="default"} 3"#,
+            "# TYPE pherry_messages_submitted_total counter",
+            r#"pherry_messages_submitted_total{sender="gatekeeper",worker="default"} 1"#,
+            r#"pherry_restarts_total{worker="default"} 1"#,
+        ] {
+            assert!(
+                text.lines().any(|l| l == line),
+                "{:?} not found in:\n{}",
+                line,
+                text
+            );
+        }
+    }
+}
@@ -1,14 +1,24 @@
-use anyhow::Result;
-use log::{error, info};
-use phaxt::subxt::extrinsic::Signer;
+use anyhow::{anyhow, Result};
+use futures::StreamExt as _;
+use log::{error, info, warn};
+use phala_types::messaging::SignedMessage;
+use phaxt::subxt::{extrinsic::Signer, rpc::SubstrateTxStatus};
+use std::collections::{BTreeMap, BTreeSet};
 use std::time::Duration;
 
 use crate::{
-    chain_client::{mq_next_sequence, update_signer_nonce},
-    types::{ParachainApi, PrClient, SrSigner},
+    chain_client::{account_chain_nonce, mq_next_sequence, update_signer_nonce},
+    metrics::WorkerMetrics,
+    tx_tracker::{Action, ChainView, MessageKey, TxId, TxStatus, TxTracker},
+    types::{Index, ParachainApi, PrClient, SrSigner},
 };
 pub use tokio::sync::mpsc::{channel, Receiver, Sender};
 
+/// How long to wait for the node to accept an extrinsic.
+const SUBMIT_TIMEOUT: Duration = Duration::from_secs(120);
+/// How long to follow the status of an accepted extrinsic.
+const WATCH_TIMEOUT: Duration = Duration::from_secs(600);
+
 pub enum Error {
     BadSignature, // Might due to runtime updated.
     OtherRpcError,
@@ -18,17 +28,41 @@ pub fn create_report_channel() -> (Sender<Error>, Receiver<Error>) {
     channel(1024)
 }
 
+/// The options of submitting the egress messages.
+pub struct SubmitOptions {
+    pub tip: u128,
+    pub tip_bump: u128,
+    pub longevity: u64,
+    pub max_sync_msgs_per_round: u64,
+    /// Max number of messages in one extrinsic. Batches with more than one message are submitted
+    /// with `PhalaMq::sync_offchain_messages`.
+    pub max_msgs_per_batch: usize,
+}
+
+type Batch = Vec<(MessageKey, SignedMessage)>;
+
 pub async fn maybe_sync_mq_egress(
     api: &ParachainApi,
     pr: &PrClient,
     signer: &mut SrSigner,
-    tip: u128,
-    longevity: u64,
-    max_sync_msgs_per_round: u64,
+    tracker: &TxTracker,
+    options: &SubmitOptions,
+    metrics: &WorkerMetrics,
     err_report: Sender<Error>,
 ) -> Result<()> {
     // Send the query
     let messages = pr.get_egress_messages(()).await?.decode_messages()?;
+    metrics.set_egress_queue_length(messages.iter().map(|(_, messages)| messages.len()).sum());
+
+    let pending: BTreeSet<MessageKey> = messages
+        .iter()
+        .flat_map(|(sender, messages)| {
+            messages
+                .iter()
+                .map(move |message| (sender.clone(), message.sequence))
+        })
+        .collect();
+    tracker.retain(&pending);
 
     // No pending message. We are done.
     if messages.is_empty() {
@@ -36,7 +70,19 @@ pub async fn maybe_sync_mq_egress(
     }
 
     update_signer_nonce(api, signer).await?;
+    let pool_nonce = signer.nonce().expect("The nonce was just updated; qed.");
+    let chain_nonce = account_chain_nonce(api, signer.account_id()).await?;
+    if chain_nonce < pool_nonce {
+        info!(
+            "{} extrinsics of the signer in the txpool",
+            pool_nonce - chain_nonce
+        );
+    }
 
+    // The messages to submit with fresh nonces, in the order of the sequences of each sender.
+    let mut fresh: Vec<(MessageKey, SignedMessage, u128)> = vec![];
+    // The messages to replace the stuck extrinsics with, by the nonce.
+    let mut replacing: BTreeMap<Index, (u128, Batch)> = BTreeMap::new();
     let mut sync_msgs_count = 0;
 
     'sync_outer: for (sender, messages) in messages {
@@ -47,73 +93,252 @@ pub async fn maybe_sync_mq_egress(
 
         info!("Next seq for {} is {}", sender, min_seq);
 
+        let view = ChainView {
+            chain_nonce,
+            pool_nonce,
+            next_sequence: min_seq,
+        };
         for message in messages {
-            if message.sequence < min_seq {
-                info!("{} has been submitted. Skipping...", message.sequence);
-                continue;
-            }
-            let msg_info = format!(
-                "sender={} seq={} dest={} nonce={:?}",
-                sender,
-                message.sequence,
-                String::from_utf8_lossy(&message.message.destination.path()[..]),
-                signer.nonce()
-            );
-            info!("Submitting message: {}", msg_info);
-
-            let params = crate::mk_params(api, longevity, tip).await?;
-            let extrinsic = api
-                .tx()
-                .phala_mq()
-                .sync_offchain_message(message)?
-                .create_signed(signer, params)
-                .await;
-            signer.increment_nonce();
-            match extrinsic {
-                Ok(extrinsic) => {
-                    let api = ParachainApi::from(api.client.clone());
-                    let err_report = err_report.clone();
-                    let extrinsic = crate::subxt::Encoded(extrinsic.encoded().to_vec());
-                    tokio::spawn(async move {
-                        const TIMEOUT: u64 = 120;
-                        let fut = api.client.rpc().submit_extrinsic(extrinsic);
-                        let result = tokio::time::timeout(Duration::from_secs(TIMEOUT), fut).await;
-                        match result {
-                            Err(_) => {
-                                error!("Submit message timed out: {}", msg_info);
-                                let _ = err_report.send(Error::OtherRpcError).await;
-                            }
-                            Ok(Err(err)) => {
-                                error!("Error submitting message {}: {:?}", msg_info, err);
-                                use phaxt::subxt::{rpc::RpcError, BasicError as SubxtError};
-                                let report = match err {
-                                    SubxtError::Rpc(RpcError::Custom(err)) => {
-                                        if err.contains("bad signature") {
-                                            Error::BadSignature
-                                        } else {
-                                            Error::OtherRpcError
-                                        }
-                                    }
-                                    _ => Error::OtherRpcError,
-                                };
-                                let _ = err_report.send(report).await;
-                            }
-                            Ok(Ok(hash)) => {
-                                info!("Message submited: {} xt-hash={:?}", msg_info, hash);
-                            }
-                        }
-                    });
+            let key = (sender.clone(), message.sequence);
+            match tracker.decide(&key, &view, options.tip, options.tip_bump) {
+                Action::Wait => {
+                    info!("{} has been submitted. Skipping...", message.sequence);
+                    continue;
                 }
-                Err(err) => {
-                    panic!("Failed to sign the call: {:?}", err);
+                Action::Submit { tip } => fresh.push((key, message, tip)),
+                Action::Replace { nonce, tip } => {
+                    warn!(
+                        "Message {} of {} stuck at nonce {}, replacing with tip {}",
+                        message.sequence, sender, nonce, tip
+                    );
+                    metrics.message_resubmitted(&sender.to_string());
+                    let (batch_tip, batch) = replacing.entry(nonce).or_default();
+                    *batch_tip = tip.max(*batch_tip);
+                    batch.push((key, message));
+                }
+                Action::Resubmit { tip } => {
+                    warn!(
+                        "Message {} of {} lost, resubmitting with tip {}",
+                        message.sequence, sender, tip
+                    );
+                    metrics.message_resubmitted(&sender.to_string());
+                    fresh.push((key, message, tip));
                 }
             }
             sync_msgs_count += 1;
-            if sync_msgs_count >= max_sync_msgs_per_round {
+            if sync_msgs_count >= options.max_sync_msgs_per_round {
                 info!("Synced {} messages, take a break", sync_msgs_count);
                 break 'sync_outer;
             }
         }
     }
+
+    let submitter = Submitter {
+        api,
+        tracker,
+        options,
+        metrics,
+        err_report: &err_report,
+    };
+    for (nonce, (tip, batch)) in replacing {
+        submitter.submit(signer, nonce, tip, batch).await?;
+    }
+    for chunk in fresh.chunks(options.max_msgs_per_batch.max(1)) {
+        let nonce = signer.nonce().expect("The nonce was updated above; qed.");
+        let tip = chunk.iter().map(|(_, _, tip)| *tip).max();
+        let batch = chunk
+            .iter()
+            .map(|(key, message, _)| (key.clone(), message.clone()))
+            .collect();
+        submitter
+            .submit(signer, nonce, tip.unwrap_or_default(), batch)
+            .await?;
+    }
     Ok(())
 }
+
+struct Submitter<'a> {
+    api: &'a ParachainApi,
+    tracker: &'a TxTracker,
+    options: &'a SubmitOptions,
+    metrics: &'a WorkerMetrics,
+    err_report: &'a Sender<Error>,
+}
+
+impl Submitter<'_> {
+    /// Signs the messages into one extrinsic and submits it in background.
+    ///
+    /// The nonce of the signer is advanced only if `nonce` is the next fresh one.
+    async fn submit(
+        &self,
+        signer: &mut SrSigner,
+        nonce: Index,
+        tip: u128,
+        batch: Batch,
+    ) -> Result<()> {
+        let api = self.api;
+        let keys: Vec<MessageKey> = batch.iter().map(|(key, _)| key.clone()).collect();
+        let msg_info = format!(
+            "{} nonce={} tip={}",
+            batch
+                .iter()
+                .map(|((sender, seq), message)| format!(
+                    "sender={} seq={} dest={}",
+                    sender,
+                    seq,
+                    String::from_utf8_lossy(&message.message.destination.path()[..]),
+                ))
+                .collect::<Vec<_>>()
+                .join(", "),
+            nonce,
+            tip
+        );
+        info!("Submitting message: {}", msg_info);
+
+        let next_nonce = signer.nonce();
+        signer.set_nonce(nonce);
+        let params = crate::mk_params(api, self.options.longevity, tip).await?;
+        let batched = batch.len() > 1;
+        let mut messages: Vec<SignedMessage> =
+            batch.into_iter().map(|(_, message)| message).collect();
+        let extrinsic = if batched {
+            phaxt::calls::sync_offchain_messages(&api.client, messages)
+                .create_signed(signer, params)
+                .await
+                .map(|extrinsic| extrinsic.encoded().to_vec())
+        } else {
+            api.tx()
+                .phala_mq()
+                .sync_offchain_message(messages.remove(0))?
+                .create_signed(signer, params)
+                .await
+                .map(|extrinsic| extrinsic.encoded().to_vec())
+        };
+        match next_nonce {
+            Some(next_nonce) if next_nonce != nonce => signer.set_nonce(next_nonce),
+            _ => signer.increment_nonce(),
+        }
+        let extrinsic = match extrinsic {
+            Ok(extrinsic) => crate::subxt::Encoded(extrinsic),
+            // Most likely the runtime of the node doesn't support the batch call
+            Err(err) if batched => {
+                return Err(anyhow!(
+                    "Failed to sign the batch of messages, try --max-msgs-per-batch=1: {:?}",
+                    err
+                ))
+            }
+            Err(err) => {
+                panic!("Failed to sign the call: {:?}", err);
+            }
+        };
+        let tx_id = self.tracker.submitted(&keys, nonce, tip);
+        tokio::spawn(watch_extrinsic(
+            ParachainApi::from(api.client.clone()),
+            self.tracker.clone(),
+            tx_id,
+            extrinsic,
+            msg_info,
+            self.metrics.clone(),
+            self.err_report.clone(),
+        ));
+        Ok(())
+    }
+}
+
+/// Submits the extrinsic and follows its status until it is finalized or gone from the txpool.
+async fn watch_extrinsic(
+    api: ParachainApi,
+    tracker: TxTracker,
+    tx_id: TxId,
+    extrinsic: crate::subxt::Encoded,
+    msg_info: String,
+    metrics: WorkerMetrics,
+    err_report: Sender<Error>,
+) {
+    let update = |status: TxStatus| -> Vec<String> {
+        tracker
+            .update(tx_id, status)
+            .into_iter()
+            .map(|(sender, _)| sender.to_string())
+            .collect()
+    };
+    let fut = api.client.rpc().watch_extrinsic(extrinsic);
+    let mut subscription = match tokio::time::timeout(SUBMIT_TIMEOUT, fut).await {
+        Err(_) => {
+            error!("Submit message timed out: {}", msg_info);
+            for sender in update(TxStatus::Failed) {
+                metrics.message_failed(&sender);
+            }
+            let _ = err_report.send(Error::OtherRpcError).await;
+            return;
+        }
+        Ok(Err(err)) => {
+            error!("Error submitting message {}: {:?}", msg_info, err);
+            for sender in update(TxStatus::Failed) {
+                metrics.message_failed(&sender);
+            }
+            use phaxt::subxt::{rpc::RpcError, BasicError as SubxtError};
+            let report = match err {
+                SubxtError::Rpc(RpcError::Custom(err)) => {
+                    if err.contains("bad signature") {
+                        Error::BadSignature
+                    } else {
+                        Error::OtherRpcError
+                    }
+                }
+                _ => Error::OtherRpcError,
+            };
+            let _ = err_report.send(report).await;
+            return;
+        }
+        Ok(Ok(subscription)) => subscription,
+    };
+    info!("Message submited: {}", msg_info);
+
+    let watch = async {
+        while let Some(status) = subscription.next().await {
+            let status = match status {
+                Ok(status) => status,
+                Err(err) => {
+                    // Left to the stuck detection in the later rounds.
+                    warn!("Lost the status of message {}: {:?}", msg_info, err);
+                    return;
+                }
+            };
+            match status {
+                SubstrateTxStatus::Future
+                | SubstrateTxStatus::Ready
+                | SubstrateTxStatus::Broadcast(_) => (),
+                SubstrateTxStatus::InBlock(block) => {
+                    info!("Message included: {} block={:?}", msg_info, block);
+                    for sender in update(TxStatus::InBlock) {
+                        metrics.message_submitted(&sender);
+                    }
+                }
+                SubstrateTxStatus::Retracted(block) => {
+                    warn!("Message retracted: {} block={:?}", msg_info, block);
+                    update(TxStatus::Pending);
+                }
+                SubstrateTxStatus::Finalized(block) => {
+                    info!("Message finalized: {} block={:?}", msg_info, block);
+                    update(TxStatus::Finalized);
+                    return;
+                }
+                SubstrateTxStatus::FinalityTimeout(_) => return,
+                SubstrateTxStatus::Usurped(_)
+                | SubstrateTxStatus::Dropped
+                | SubstrateTxStatus::Invalid => {
+                    warn!("Message extrinsic {:?}: {}", status, msg_info);
+                    // Not counted if replaced by a later attempt of the same message.
+                    for sender in update(TxStatus::Failed) {
+                        metrics.message_failed(&sender);
+                    }
+                    return;
+                }
+            }
+        }
+    };
+    if tokio::time::timeout(WATCH_TIMEOUT, watch).await.is_err() {
+        warn!("Stopped watching message: {}", msg_info);
+    }
+}
@@ -0,0 +1,214 @@
+//! Drives multiple pRuntimes from one pherry process.
+//!
+//! The workers share the connections to the chain nodes, the headers cache client and the fetched
+//! chain data, while each of them syncs its own pRuntime and restarts on its own errors.
+
+use anyhow::{anyhow, Context, Result};
+use log::{error, info, warn};
+use serde::Deserialize;
+use std::collections::BTreeSet;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::sleep;
+
+use crate::metrics::{Metrics, Phase, WorkerMetrics};
+use crate::{collect_async_errors, msg_sync, sync_worker, Args, ChainSources, RunningFlags};
+
+/// How often to check the connections to the chain nodes.
+const CONNECTION_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// The workers to drive, loaded from the file given by `--workers-config`.
+///
+/// ```json
+/// {
+///     "workers": [
+///         {
+///             "name": "worker-1",
+///             "pruntime_endpoint": "http://localhost:8000",
+///             "mnemonic": "//Alice",
+///             "operator": "5GrwvaEF5zXb26Fz9rcQpDWS57CtERHpNehXCPcNoHGKutQY"
+///         }
+///     ]
+/// }
+/// ```
+#[derive(Deserialize, Debug)]
+pub struct WorkersConfig {
+    pub workers: Vec<WorkerConfig>,
+}
+
+/// The per-worker settings overriding the command line arguments.
+#[derive(Deserialize, Debug, Clone)]
+pub struct WorkerConfig {
+    /// The name to identify the worker in the logs.
+    pub name: String,
+    pub pruntime_endpoint: String,
+    /// Controller SR25519 private key mnemonic, private key seed, or derive path.
+    pub mnemonic: String,
+    /// The operator account to set the miner for the worker.
+    #[serde(default)]
+    pub operator: Option<String>,
+    /// pRuntime endpoint to handover the key to.
+    #[serde(default)]
+    pub next_pruntime_endpoint: Option<String>,
+}
+
+impl WorkerConfig {
+    fn apply(&self, args: &Args) -> Args {
+        let mut args = args.clone();
+        args.pruntime_endpoint = self.pruntime_endpoint.clone();
+        args.mnemonic = self.mnemonic.clone();
+        args.operator = self.operator.clone();
+        args.next_pruntime_endpoint = self.next_pruntime_endpoint.clone();
+        args
+    }
+}
+
+pub fn load_config(path: &str) -> Result<WorkersConfig> {
+    let content =
+        std::fs::read(path).with_context(|| format!("Failed to read workers config {}", path))?;
+    let config: WorkersConfig =
+        serde_json::from_slice(&content).context("Failed to parse workers config")?;
+    if config.workers.is_empty() {
+        return Err(anyhow!("No worker found in the workers config"));
+    }
+    let mut names = BTreeSet::new();
+    let mut endpoints = BTreeSet::new();
+    for worker in config.workers.iter() {
+        if !names.insert(&worker.name) {
+            return Err(anyhow!("Duplicated worker name {}", worker.name));
+        }
+        if !endpoints.insert(&worker.pruntime_endpoint) {
+            return Err(anyhow!(
+                "Duplicated pRuntime endpoint {}",
+                worker.pruntime_endpoint
+            ));
+        }
+    }
+    Ok(config)
+}
+
+struct WorkerState {
+    name: String,
+    args: Args,
+    flags: RunningFlags,
+    metrics: WorkerMetrics,
+    /// The result of the worker once it stopped.
+    stopped: Option<Result<()>>,
+}
+
+/// Runs the worker until it finishes or fails more than `--max-restart-retries` times.
+async fn run_worker(sources: &ChainSources, state: &mut WorkerState) -> Result<()> {
+    let args = &state.args;
+    let metrics = &state.metrics;
+    loop {
+        let (sender, receiver) = msg_sync::create_report_channel();
+        let threshold = args.restart_on_rpc_error_threshold;
+        tokio::select! {
+            res = sync_worker(args, sources, &mut state.flags, metrics, sender) => {
+                if let Err(err) = res {
+                    info!("[{}] sync_worker() exited with error: {:?}", state.name, err);
+                    metrics.set_error(format!("{:?}", err));
+                } else {
+                    return Ok(());
+                }
+            }
+            () = collect_async_errors(threshold, receiver, metrics) => ()
+        };
+        if !args.auto_restart || state.flags.restart_failure_count > args.max_restart_retries {
+            return Err(anyhow!(
+                "gave up after {} restarts",
+                state.flags.restart_failure_count
+            ));
+        }
+        state.flags.restart_failure_count += 1;
+        metrics.restarted();
+        sleep(Duration::from_secs(2)).await;
+        info!("[{}] Restarting...", state.name);
+    }
+}
+
+/// Returns when the connection to any of the chain nodes is lost.
+async fn watch_connections(sources: &ChainSources) {
+    loop {
+        sleep(CONNECTION_CHECK_INTERVAL).await;
+        for client in [&sources.relay_client, &sources.para_client] {
+            if let Err(err) = client.rpc().finalized_head().await {
+                warn!("Lost the connection to the chain: {:?}", err);
+                return;
+            }
+        }
+    }
+}
+
+/// Runs all the workers in the config and returns the exit code of the process.
+pub async fn run(args: &Args, config_path: &str, metrics: &Arc<Metrics>) -> i32 {
+    let config = match load_config(config_path) {
+        Ok(config) => config,
+        Err(err) => {
+            error!("{:?}", err);
+            return 2;
+        }
+    };
+    info!("Running {} workers", config.workers.len());
+    let mut workers: Vec<_> = config
+        .workers
+        .iter()
+        .map(|worker| WorkerState {
+            name: worker.name.clone(),
+            args: worker.apply(args),
+            flags: RunningFlags {
+                worker_registered: false,
+                endpoint_registered: false,
+                restart_failure_count: 0,
+            },
+            metrics: metrics.worker(&worker.name),
+            stopped: None,
+        })
+        .collect();
+
+    loop {
+        for worker in workers.iter().filter(|worker| worker.stopped.is_none()) {
+            worker.metrics.set_phase(Phase::Connecting);
+        }
+        let sources = match ChainSources::connect(args, config.workers.len()).await {
+            Ok(sources) => sources,
+            Err(err) => {
+                error!("Failed to connect to the chain: {:?}", err);
+                sleep(Duration::from_secs(5)).await;
+                continue;
+            }
+        };
+        let running = workers
+            .iter_mut()
+            .filter(|worker| worker.stopped.is_none())
+            .map(|worker| {
+                let sources = &sources;
+                async move {
+                    let result = run_worker(sources, worker).await;
+                    match &result {
+                        Ok(()) => info!("[{}] Worker finished", worker.name),
+                        Err(err) => error!("[{}] Worker stopped: {:?}", worker.name, err),
+                    }
+                    worker.metrics.set_phase(Phase::Stopped);
+                    worker.stopped = Some(result);
+                }
+            });
+        tokio::select! {
+            _ = futures::future::join_all(running) => break,
+            () = watch_connections(&sources) => (),
+        }
+        sleep(Duration::from_secs(2)).await;
+        info!("Reconnecting...");
+    }
+
+    let failed = workers
+        .iter()
+        .filter(|worker| matches!(worker.stopped, Some(Err(_))))
+        .count();
+    if failed > 0 {
+        error!("{} of {} workers stopped with error", failed, workers.len());
+        1
+    } else {
+        0
+    }
+}
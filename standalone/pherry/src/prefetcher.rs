@@ -1,67 +1,242 @@
 use anyhow::Result;
 use phactory_api::blocks::BlockHeaderWithChanges;
 use phaxt::{BlockNumber, RpcClient};
+use std::collections::{BTreeMap, VecDeque};
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use tokio::sync::OnceCell;
 use tokio::task::JoinHandle;
 
+use crate::headers_cache::BlockInfo;
+use crate::types::{Hash, Header};
+
+type Batch = Arc<OnceCell<Vec<BlockHeaderWithChanges>>>;
+
+/// The results recently fetched by the workers sharing a [`PrefetchClient`], keyed by what was
+/// asked for.
+///
+/// A result being fetched is waited for by the other workers asking for the same key rather than
+/// fetched again. The oldest results are dropped once there are more than `capacity` of them.
+struct RecentResults<K, V> {
+    capacity: usize,
+    state: Mutex<RecentState<K, V>>,
+}
+
+struct RecentState<K, V> {
+    results: BTreeMap<K, Arc<OnceCell<V>>>,
+    order: VecDeque<K>,
+}
+
+impl<K: Ord + Clone, V: Clone> RecentResults<K, V> {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            state: Mutex::new(RecentState {
+                results: Default::default(),
+                order: Default::default(),
+            }),
+        }
+    }
+
+    /// Returns the slot of `key`. The slot is not shared if nothing is kept.
+    fn slot(&self, key: K) -> Arc<OnceCell<V>> {
+        if self.capacity == 0 {
+            return Default::default();
+        }
+        let mut state = self.state.lock().unwrap();
+        if let Some(slot) = state.results.get(&key) {
+            return slot.clone();
+        }
+        let slot: Arc<OnceCell<V>> = Default::default();
+        state.results.insert(key.clone(), slot.clone());
+        state.order.push_back(key);
+        while state.order.len() > self.capacity {
+            let oldest = state.order.pop_front().expect("Over capacity; qed.");
+            state.results.remove(&oldest);
+        }
+        slot
+    }
+
+    async fn get_or_fetch<F, Fut>(&self, key: K, fetch: F) -> Result<V>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<V>>,
+    {
+        let slot = self.slot(key);
+        let result = slot.get_or_try_init(fetch).await?;
+        Ok(result.clone())
+    }
+}
+
 struct StoragePrefetchState {
     from: BlockNumber,
     to: BlockNumber,
-    handle: JoinHandle<Result<Vec<BlockHeaderWithChanges>>>,
+    batch: Batch,
+    handle: JoinHandle<()>,
 }
 
+struct PrefetchState {
+    prefetching_storage_changes: Mutex<Option<StoragePrefetchState>>,
+    storage_changes: RecentResults<(BlockNumber, BlockNumber), Vec<BlockHeaderWithChanges>>,
+    cached_headers: RecentResults<BlockNumber, Vec<BlockInfo>>,
+    cached_para_headers: RecentResults<(BlockNumber, BlockNumber), Vec<Header>>,
+    para_headers: RecentResults<Hash, Header>,
+    para_fin_headers: RecentResults<Hash, Option<(Header, Vec<Vec<u8>>)>>,
+}
+
+/// Fetches the storage changes and prefetches the next batch in background.
+///
+/// The client can be cloned and shared among the workers syncing the same chain. Given a
+/// non-zero `capacity`, the workers catching up at the same pace reuse the storage changes, the
+/// cached headers with their justifications and the parachain headers fetched by each other. Up
+/// to `capacity` results of each kind are kept, so it should only be set with several workers.
+#[derive(Clone)]
 pub struct PrefetchClient {
-    prefetching_storage_changes: Option<StoragePrefetchState>,
+    state: Arc<PrefetchState>,
 }
 
 impl PrefetchClient {
-    pub fn new() -> Self {
+    pub fn new(capacity: usize) -> Self {
         Self {
-            prefetching_storage_changes: None,
+            state: Arc::new(PrefetchState {
+                prefetching_storage_changes: Default::default(),
+                storage_changes: RecentResults::new(capacity),
+                cached_headers: RecentResults::new(capacity),
+                cached_para_headers: RecentResults::new(capacity),
+                para_headers: RecentResults::new(capacity),
+                para_fin_headers: RecentResults::new(capacity),
+            }),
         }
     }
 
     pub async fn fetch_storage_changes(
-        &mut self,
+        &self,
         client: &RpcClient,
         cache: Option<&crate::CacheClient>,
         from: BlockNumber,
         to: BlockNumber,
     ) -> Result<Vec<BlockHeaderWithChanges>> {
-        let count = to + 1 - from;
-        let result = if let Some(state) = self.prefetching_storage_changes.take() {
-            if state.from == from && state.to == to {
-                log::info!("use prefetched storage changes ({from}-{to})",);
-                state.handle.await?.ok()
-            } else {
-                log::info!(
-                    "cancelling the prefetch ({}-{}), requesting ({from}-{to})",
-                    state.from,
-                    state.to,
-                );
-                state.handle.abort();
-                None
-            }
-        } else {
-            None
+        let batch = match self.take_prefetched(from, to) {
+            Some(batch) => batch,
+            None => self.state.storage_changes.slot((from, to)),
         };
+        let result = batch
+            .get_or_try_init(|| crate::fetch_storage_changes(client, cache, from, to))
+            .await?
+            .clone();
+        let count = to + 1 - from;
+        self.prefetch_storage_changes(client, cache, from + count, to + count);
+        Ok(result)
+    }
 
-        let result = if let Some(result) = result {
-            result
-        } else {
-            crate::fetch_storage_changes(client, cache, from, to).await?
-        };
-        let next_from = from + count;
-        let next_to = next_from + count - 1;
+    fn take_prefetched(&self, from: BlockNumber, to: BlockNumber) -> Option<Batch> {
+        let prefetch = self
+            .state
+            .prefetching_storage_changes
+            .lock()
+            .unwrap()
+            .take()?;
+        if prefetch.from == from && prefetch.to == to {
+            log::info!("use prefetched storage changes ({from}-{to})");
+            return Some(prefetch.batch);
+        }
+        // The prefetched batch is kept for the other workers if shared, otherwise nobody needs it.
+        if self.state.storage_changes.capacity == 0 {
+            log::info!(
+                "cancelling the prefetch ({}-{}), requesting ({from}-{to})",
+                prefetch.from,
+                prefetch.to,
+            );
+            prefetch.handle.abort();
+        }
+        None
+    }
+
+    fn prefetch_storage_changes(
+        &self,
+        client: &RpcClient,
+        cache: Option<&crate::CacheClient>,
+        from: BlockNumber,
+        to: BlockNumber,
+    ) {
+        let batch = self.state.storage_changes.slot((from, to));
+        if batch.initialized() {
+            return;
+        }
         let client = client.clone();
         let cache = cache.cloned();
-        self.prefetching_storage_changes = Some(StoragePrefetchState {
-            from: next_from,
-            to: next_to,
-            handle: tokio::spawn(async move {
-                log::info!("prefetching ({next_from}-{next_to})");
-                crate::fetch_storage_changes(&client, (&cache).as_ref(), next_from, next_to).await
-            }),
+        let handle = tokio::spawn({
+            let batch = batch.clone();
+            async move {
+                log::info!("prefetching ({from}-{to})");
+                let result = batch
+                    .get_or_try_init(|| {
+                        crate::fetch_storage_changes(&client, cache.as_ref(), from, to)
+                    })
+                    .await;
+                if let Err(err) = result {
+                    log::warn!("prefetching ({from}-{to}) failed: {err:?}");
+                }
+            }
         });
-        Ok(result)
+        *self.state.prefetching_storage_changes.lock().unwrap() = Some(StoragePrefetchState {
+            from,
+            to,
+            batch,
+            handle,
+        });
+    }
+
+    /// Fetches the relaychain headers with their justifications from the headers cache.
+    pub async fn fetch_cached_headers(
+        &self,
+        cache: &crate::CacheClient,
+        from: BlockNumber,
+    ) -> Result<Vec<BlockInfo>> {
+        self.state
+            .cached_headers
+            .get_or_fetch(from, || cache.get_headers(from))
+            .await
+    }
+
+    /// Fetches `count` parachain headers starting from `from` from the headers cache.
+    pub async fn fetch_cached_para_headers(
+        &self,
+        cache: &crate::CacheClient,
+        from: BlockNumber,
+        count: BlockNumber,
+    ) -> Result<Vec<Header>> {
+        self.state
+            .cached_para_headers
+            .get_or_fetch((from, count), || cache.get_parachain_headers(from, count))
+            .await
+    }
+
+    /// Fetches the parachain header of `hash`.
+    pub async fn fetch_para_header(&self, client: &RpcClient, hash: Hash) -> Result<Header> {
+        self.state
+            .para_headers
+            .get_or_fetch(hash, || async move {
+                let header = client
+                    .rpc()
+                    .header(Some(hash))
+                    .await?
+                    .ok_or(crate::error::Error::BlockNotFound)?;
+                Ok(header)
+            })
+            .await
+    }
+
+    /// Fetches the finalized parachain header at the relaychain block `hash` with its proof.
+    pub async fn fetch_para_fin_header<F, Fut>(
+        &self,
+        hash: Hash,
+        fetch: F,
+    ) -> Result<Option<(Header, Vec<Vec<u8>>)>>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<Option<(Header, Vec<Vec<u8>>)>>>,
+    {
+        self.state.para_fin_headers.get_or_fetch(hash, fetch).await
     }
 }
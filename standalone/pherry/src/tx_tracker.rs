@@ -0,0 +1,323 @@
+//! Tracks the extrinsics submitted for the egress messages until they get included.
+//!
+//! An extrinsic carries one message, or a batch of them. The status of each extrinsic is updated
+//! by watching it through the RPC subscription. Before submitting the messages of a round,
+//! `msg_sync` asks the tracker what to do with each message: wait for the in-flight extrinsic,
+//! replace a stuck one with a higher tip, or resubmit a lost one with a fresh nonce.
+
+use phala_types::messaging::MessageOrigin;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::types::Index;
+
+/// How long an extrinsic can stay in the txpool before it is considered stuck.
+pub const STUCK_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// The egress message an extrinsic carries, identified by the sender and the sequence.
+pub type MessageKey = (MessageOrigin, u64);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TxStatus {
+    /// Submitted and waiting in the txpool.
+    Pending,
+    /// Included in a block which is not finalized yet.
+    InBlock,
+    Finalized,
+    /// Rejected by the node, or dropped, invalidated or usurped in the txpool.
+    Failed,
+}
+
+/// Identifies a submitted extrinsic in the tracker.
+pub type TxId = u64;
+
+#[derive(Clone, Debug)]
+pub struct InFlightTx {
+    /// The extrinsic carrying the message.
+    pub tx_id: TxId,
+    pub nonce: Index,
+    pub tip: u128,
+    /// The number of submissions of the message, starting from 1.
+    pub attempts: u32,
+    pub submitted_at: Instant,
+    pub status: TxStatus,
+}
+
+/// What to do with an egress message in this round.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    /// Leave the message to its in-flight extrinsic.
+    Wait,
+    /// Submit the message for the first time.
+    Submit { tip: u128 },
+    /// Replace the stuck extrinsic at the same nonce with a higher tip.
+    ///
+    /// All the messages of the stuck extrinsic are replaced together.
+    Replace { nonce: Index, tip: u128 },
+    /// Submit the message again with a fresh nonce.
+    Resubmit { tip: u128 },
+}
+
+/// The extrinsics in flight of a worker.
+///
+/// Cloned into the tasks watching the extrinsics.
+#[derive(Clone, Default)]
+pub struct TxTracker {
+    state: Arc<Mutex<TrackerState>>,
+}
+
+#[derive(Default)]
+struct TrackerState {
+    next_tx_id: TxId,
+    in_flight: BTreeMap<MessageKey, InFlightTx>,
+}
+
+/// The on-chain view of the signer account and the message sender in the current round.
+pub struct ChainView {
+    /// The nonce of the signer at the best block.
+    pub chain_nonce: Index,
+    /// The nonce of the signer considering the ready extrinsics in the txpool.
+    pub pool_nonce: Index,
+    /// The next sequence of the sender considering the txpool.
+    pub next_sequence: u64,
+}
+
+impl TxTracker {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Forgets the messages no longer held by the pRuntime, which means they have been processed
+    /// by the chain.
+    pub fn retain(&self, pending: &BTreeSet<MessageKey>) {
+        self.state
+            .lock()
+            .unwrap()
+            .in_flight
+            .retain(|key, _| pending.contains(key));
+    }
+
+    pub fn decide(&self, key: &MessageKey, view: &ChainView, tip: u128, tip_bump: u128) -> Action {
+        let state = self.state.lock().unwrap();
+        let in_pool = key.1 < view.next_sequence;
+        let tx = match state.in_flight.get(key) {
+            // Submitted before the tracker started, e.g. by the previous run of pherry.
+            None if in_pool => return Action::Wait,
+            None => return Action::Submit { tip },
+            Some(tx) => tx,
+        };
+        let tip = tx.tip.saturating_add(tip_bump);
+        match tx.status {
+            TxStatus::InBlock | TxStatus::Finalized if in_pool => Action::Wait,
+            // Included but rejected by the pallet, e.g. skipped in a batch. Keep the tip.
+            TxStatus::InBlock | TxStatus::Finalized => Action::Resubmit { tip: tx.tip },
+            TxStatus::Failed => Action::Resubmit { tip },
+            TxStatus::Pending if tx.submitted_at.elapsed() < STUCK_TIMEOUT => Action::Wait,
+            TxStatus::Pending => {
+                let lost = !in_pool;
+                // The nonce is taken by another extrinsic of the same signer.
+                let outdated = tx.nonce < view.chain_nonce;
+                // Queued in the future pool behind a missing nonce, which stalls the later ones.
+                let behind_gap = tx.nonce >= view.pool_nonce;
+                if lost || outdated || behind_gap {
+                    Action::Resubmit { tip }
+                } else {
+                    Action::Replace {
+                        nonce: tx.nonce,
+                        tip,
+                    }
+                }
+            }
+        }
+    }
+
+    /// Records a new extrinsic carrying the given messages.
+    pub fn submitted(&self, keys: &[MessageKey], nonce: Index, tip: u128) -> TxId {
+        let mut state = self.state.lock().unwrap();
+        let tx_id = state.next_tx_id;
+        state.next_tx_id += 1;
+        for key in keys {
+            let attempts = state.in_flight.get(key).map_or(0, |tx| tx.attempts) + 1;
+            state.in_flight.insert(
+                key.clone(),
+                InFlightTx {
+                    tx_id,
+                    nonce,
+                    tip,
+                    attempts,
+                    submitted_at: Instant::now(),
+                    status: TxStatus::Pending,
+                },
+            );
+        }
+        tx_id
+    }
+
+    /// Updates the status reported for the given extrinsic.
+    ///
+    /// Returns the messages whose status changed, excluding the ones submitted again since then.
+    pub fn update(&self, tx_id: TxId, status: TxStatus) -> Vec<MessageKey> {
+        let mut state = self.state.lock().unwrap();
+        state
+            .in_flight
+            .iter_mut()
+            .filter(|(_, tx)| tx.tx_id == tx_id && tx.status != status)
+            .map(|(key, tx)| {
+                tx.status = status;
+                key.clone()
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TIP: u128 = 100;
+    const BUMP: u128 = 10;
+
+    fn key(sequence: u64) -> MessageKey {
+        (MessageOrigin::Gatekeeper, sequence)
+    }
+
+    fn tracked(status: TxStatus, nonce: Index, stuck: bool) -> TxTracker {
+        let tracker = TxTracker::new();
+        let tx_id = tracker.submitted(&[key(5)], nonce, 50);
+        tracker.update(tx_id, status);
+        if stuck {
+            let mut state = tracker.state.lock().unwrap();
+            let tx = state.in_flight.get_mut(&key(5)).unwrap();
+            tx.submitted_at = Instant::now() - STUCK_TIMEOUT - Duration::from_secs(1);
+        }
+        tracker
+    }
+
+    #[test]
+    fn decide_actions() {
+        use Action::*;
+        use TxStatus::*;
+
+        // The message 5 is in the pool if the next sequence is beyond it. The tracked extrinsic
+        // was submitted with tip 50.
+        let cases = [
+            // (case, tracked (status, nonce, stuck), (chain nonce, pool nonce, next sequence), action)
+            ("untracked in pool", None, (10, 12, 6), Wait),
+            ("untracked", None, (10, 12, 5), Submit { tip: TIP }),
+            ("in block", Some((InBlock, 10, false)), (10, 12, 6), Wait),
+            ("finalized", Some((Finalized, 10, true)), (11, 12, 6), Wait),
+            (
+                "included but rejected",
+                Some((InBlock, 10, false)),
+                (11, 12, 5),
+                Resubmit { tip: 50 },
+            ),
+            (
+                "finalized but rejected",
+                Some((Finalized, 10, false)),
+                (11, 12, 5),
+                Resubmit { tip: 50 },
+            ),
+            (
+                "failed",
+                Some((Failed, 10, false)),
+                (10, 12, 6),
+                Resubmit { tip: 60 },
+            ),
+            ("pending", Some((Pending, 10, false)), (10, 12, 6), Wait),
+            (
+                "pending lost before stuck",
+                Some((Pending, 10, false)),
+                (10, 10, 5),
+                Wait,
+            ),
+            (
+                "stuck",
+                Some((Pending, 10, true)),
+                (10, 12, 6),
+                Replace { nonce: 10, tip: 60 },
+            ),
+            (
+                "stuck at the pool nonce - 1",
+                Some((Pending, 11, true)),
+                (10, 12, 6),
+                Replace { nonce: 11, tip: 60 },
+            ),
+            (
+                "lost",
+                Some((Pending, 10, true)),
+                (10, 12, 5),
+                Resubmit { tip: 60 },
+            ),
+            (
+                "nonce taken",
+                Some((Pending, 9, true)),
+                (10, 12, 6),
+                Resubmit { tip: 60 },
+            ),
+            (
+                "behind a nonce gap",
+                Some((Pending, 12, true)),
+                (10, 12, 6),
+                Resubmit { tip: 60 },
+            ),
+        ];
+        for (case, tx, (chain_nonce, pool_nonce, next_sequence), expected) in cases {
+            let tracker = match tx {
+                Some((status, nonce, stuck)) => tracked(status, nonce, stuck),
+                None => TxTracker::new(),
+            };
+            let view = ChainView {
+                chain_nonce,
+                pool_nonce,
+                next_sequence,
+            };
+            assert_eq!(
+                tracker.decide(&key(5), &view, TIP, BUMP),
+                expected,
+                "{}",
+                case
+            );
+        }
+    }
+
+    #[test]
+    fn decide_saturates_tip() {
+        let tracker = TxTracker::new();
+        let tx_id = tracker.submitted(&[key(5)], 10, u128::MAX - 1);
+        tracker.update(tx_id, TxStatus::Failed);
+        let view = ChainView {
+            chain_nonce: 10,
+            pool_nonce: 11,
+            next_sequence: 6,
+        };
+        assert_eq!(
+            tracker.decide(&key(5), &view, TIP, BUMP),
+            Action::Resubmit { tip: u128::MAX }
+        );
+    }
+
+    #[test]
+    fn update_skips_resubmitted() {
+        let tracker = TxTracker::new();
+        let first = tracker.submitted(&[key(1), key(2)], 10, TIP);
+        let second = tracker.submitted(&[key(2)], 11, TIP);
+        assert_eq!(tracker.update(first, TxStatus::Failed), vec![key(1)]);
+        assert_eq!(tracker.update(first, TxStatus::Failed), vec![]);
+        assert_eq!(tracker.update(second, TxStatus::InBlock), vec![key(2)]);
+        assert_eq!(tracker.state.lock().unwrap().in_flight[&key(2)].attempts, 2);
+
+        tracker.retain(&vec![key(2)].into_iter().collect());
+        assert_eq!(
+            tracker
+                .state
+                .lock()
+                .unwrap()
+                .in_flight
+                .keys()
+                .collect::<Vec<_>>(),
+            vec![&key(2)]
+        );
+    }
+}
@@ -1,7 +1,9 @@
 use log::info;
 use std::alloc::System;
 
-use phactory_pal::{AppInfo, AppVersion, Machine, MemoryStats, MemoryUsage, Sealing, RA};
+use phactory_pal::{
+    AppInfo, AppVersion, AttestationReport, Machine, MemoryStats, MemoryUsage, Sealing, RA,
+};
 use phala_allocator::StatSizeAllocator;
 use std::io::ErrorKind;
 use std::str::FromStr as _;
@@ -38,10 +40,7 @@ impl Sealing for GraminePlatform {
 impl RA for GraminePlatform {
     type Error = anyhow::Error;
 
-    fn create_attestation_report(
-        &self,
-        data: &[u8],
-    ) -> Result<(String, String, String), Self::Error> {
+    fn create_attestation_report(&self, data: &[u8]) -> Result<AttestationReport, Self::Error> {
         // TODO.kevin: move the key out of the binary?
         const IAS_API_KEY_STR: &str = env!("IAS_API_KEY");
         ra::create_attestation_report(data, IAS_API_KEY_STR)
@@ -1,8 +1,11 @@
 use anyhow::{anyhow, Context as _, Result};
 use log::{error, warn, info};
-use std::{fs, time::Duration};
+use std::{collections::HashMap, fs, time::Duration};
 
+use phactory_pal::{AttestationReport, SgxQuoteCollateral};
+use reqwest::header::HeaderMap;
 use reqwest_env_proxy::EnvProxyBuilder as _;
+use serde_json::value::RawValue;
 
 pub const IAS_HOST: &str = env!("IAS_HOST");
 pub const IAS_REPORT_ENDPOINT: &str = env!("IAS_REPORT_ENDPOINT");
@@ -93,8 +96,181 @@ pub fn create_quote_vec(data: &[u8]) -> Result<Vec<u8>> {
     Ok(fs::read("/dev/attestation/quote")?)
 }
 
-pub fn create_attestation_report(data: &[u8], ias_key: &str) -> Result<(String, String, String)> {
-    let quote_vec = create_quote_vec(data)?;
-    let (attn_report, sig, cert) = get_report_from_intel(&quote_vec, ias_key)?;
-    Ok((attn_report, sig, cert))
+/// Returns the attestation type configured in the Gramine manifest, `epid` or `dcap`.
+fn attestation_type() -> Result<String> {
+    let attestation_type = fs::read_to_string("/dev/attestation/attestation_type")
+        .context("Failed to read the attestation type")?;
+    Ok(attestation_type.trim().to_string())
+}
+
+pub fn create_attestation_report(data: &[u8], ias_key: &str) -> Result<AttestationReport> {
+    let quote = create_quote_vec(data)?;
+    if attestation_type()? == "dcap" {
+        let collateral = get_collateral_from_pccs(&quote)?;
+        return Ok(AttestationReport::SgxDcap { quote, collateral });
+    }
+    let (ra_report, signature, raw_signing_cert) = get_report_from_intel(&quote, ias_key)?;
+    Ok(AttestationReport::SgxIas {
+        ra_report,
+        signature,
+        raw_signing_cert,
+    })
+}
+
+/// Sends a GET request to the PCCS configured by the env `PCCS_URL`.
+///
+/// `PCCS_URL` is the base url of the certification API, e.g.
+/// `https://localhost:8081/sgx/certification/v3/`.
+fn pccs_get(path: &str) -> Result<(Vec<u8>, HeaderMap)> {
+    let base = std::env::var("PCCS_URL").context("PCCS_URL is required for DCAP attestation")?;
+    let url: reqwest::Url = format!("{}/{}", base.trim_end_matches('/'), path).parse()?;
+    info!("Getting DCAP collateral from {}", url);
+    let res = reqwest::blocking::Client::builder()
+        .timeout(Some(Duration::from_secs(8)))
+        .env_proxy(url.domain().unwrap_or_default())
+        // PCCS is usually deployed with a self-signed cert. It's fine because the collateral is
+        // signed by Intel and verified on-chain.
+        .danger_accept_invalid_certs(true)
+        .build()
+        .context("Failed to create http client, maybe invalid PCCS URI")?
+        .get(url)
+        .send()
+        .context("Failed to send http request")?;
+    let status_code = res.status().as_u16();
+    if status_code != 200 {
+        return Err(anyhow!("Bad http status from PCCS: {}", status_code));
+    }
+    let headers = res.headers().clone();
+    let body = res
+        .bytes()
+        .context("Failed to read response body from PCCS")?;
+    Ok((body.to_vec(), headers))
+}
+
+/// Reads the url encoded PEM issuer chain from the response header.
+fn issuer_chain(headers: &HeaderMap, names: &[&str]) -> Result<Vec<u8>> {
+    let chain = names
+        .iter()
+        .find_map(|name| headers.get(*name))
+        .ok_or_else(|| anyhow!("No issuer chain header {}", names[0]))?
+        .to_str()
+        .context("Failed to decode the issuer chain header")?;
+    let chain = urlencoding::decode(chain).context("Failed to urldecode the issuer chain")?;
+    Ok(chain.into_owned().into_bytes())
+}
+
+/// Decodes a CRL returned by PCCS, which might be hex encoded, PEM encoded or raw DER.
+fn decode_crl(body: Vec<u8>) -> Result<Vec<u8>> {
+    const PEM_HEADER: &str = "-----BEGIN X509 CRL-----";
+    const PEM_FOOTER: &str = "-----END X509 CRL-----";
+    if body.first() == Some(&0x30) {
+        return Ok(body);
+    }
+    let text = String::from_utf8(body).context("Invalid CRL encoding")?;
+    let text = text.trim();
+    if let Some(pem) = text.strip_prefix(PEM_HEADER) {
+        let pem = pem.trim_end().trim_end_matches(PEM_FOOTER);
+        let pem: String = pem.split_whitespace().collect();
+        return base64::decode(pem).context("Invalid PEM CRL");
+    }
+    hex::decode(text).context("Invalid hex CRL")
+}
+
+/// Splits the signed JSON returned by PCCS into the exact bytes of `field` and the signature.
+fn decode_signed_json(body: &[u8], field: &str) -> Result<(Vec<u8>, Vec<u8>)> {
+    let object: HashMap<String, &RawValue> =
+        serde_json::from_slice(body).context("Invalid signed JSON")?;
+    let data = object
+        .get(field)
+        .ok_or_else(|| anyhow!("Missing {} in the signed JSON", field))?;
+    let signature = object
+        .get("signature")
+        .ok_or_else(|| anyhow!("Missing signature in the signed JSON"))?;
+    let signature: String = serde_json::from_str(signature.get()).context("Invalid signature")?;
+    let signature = hex::decode(signature).context("Invalid signature")?;
+    Ok((data.get().as_bytes().to_vec(), signature))
+}
+
+/// Extracts the PEM encoded PCK cert chain from the certification data of an ECDSA quote.
+fn pck_cert_chain(quote: &[u8]) -> Result<&[u8]> {
+    // header(48) + report body(384) + signature data length(4)
+    const SIG_DATA_OFFSET: usize = 436;
+    // ISV signature(64) + attestation key(64) + QE report(384) + QE report signature(64)
+    const AUTH_DATA_OFFSET: usize = SIG_DATA_OFFSET + 576;
+    const CERT_DATA_TYPE_PCK_CERT_CHAIN: u16 = 5;
+    let read = |offset: usize, len: usize| {
+        quote
+            .get(offset..offset + len)
+            .ok_or_else(|| anyhow!("Truncated DCAP quote"))
+    };
+    let auth_data_len = u16::from_le_bytes(read(AUTH_DATA_OFFSET, 2)?.try_into()?) as usize;
+    let cert_offset = AUTH_DATA_OFFSET + 2 + auth_data_len;
+    let cert_type = u16::from_le_bytes(read(cert_offset, 2)?.try_into()?);
+    if cert_type != CERT_DATA_TYPE_PCK_CERT_CHAIN {
+        return Err(anyhow!("Unsupported certification data type {}", cert_type));
+    }
+    let cert_len = u32::from_le_bytes(read(cert_offset + 2, 4)?.try_into()?) as usize;
+    read(cert_offset + 6, cert_len)
+}
+
+/// Returns the FMSPC of the platform and whether the PCK cert is issued by the Platform CA.
+fn pck_cert_info(quote: &[u8]) -> Result<(String, bool)> {
+    // OID 1.2.840.113741.1.13.1.4 followed by the header of an OCTET STRING of 6 bytes
+    const FMSPC_PATTERN: &[u8] = &[
+        0x06, 0x0a, 0x2a, 0x86, 0x48, 0x86, 0xf8, 0x4d, 0x01, 0x0d, 0x01, 0x04, 0x04, 0x06,
+    ];
+    let chain = std::str::from_utf8(pck_cert_chain(quote)?).context("Invalid PCK cert chain")?;
+    let leaf: String = chain
+        .split("-----")
+        .nth(2)
+        .ok_or_else(|| anyhow!("Invalid PCK cert chain"))?
+        .split_whitespace()
+        .collect();
+    let leaf = base64::decode(leaf).context("Invalid PCK cert")?;
+    let fmspc = leaf
+        .windows(FMSPC_PATTERN.len())
+        .position(|window| window == FMSPC_PATTERN)
+        .and_then(|pos| leaf.get(pos + FMSPC_PATTERN.len()..pos + FMSPC_PATTERN.len() + 6))
+        .ok_or_else(|| anyhow!("FMSPC not found in the PCK cert"))?;
+    let from_platform_ca = leaf
+        .windows(b"Platform CA".len())
+        .any(|window| window == b"Platform CA");
+    Ok((hex::encode_upper(fmspc), from_platform_ca))
+}
+
+/// Fetches the collateral to verify the quote from PCCS.
+fn get_collateral_from_pccs(quote: &[u8]) -> Result<SgxQuoteCollateral> {
+    let (fmspc, from_platform_ca) = pck_cert_info(quote)?;
+    let ca = if from_platform_ca {
+        "platform"
+    } else {
+        "processor"
+    };
+    let (pck_crl, _) = pccs_get(&format!("pckcrl?ca={}", ca))?;
+    let (root_ca_crl, _) = pccs_get("rootcacrl")?;
+    let (tcb_info, headers) = pccs_get(&format!("tcb?fmspc={}", fmspc))?;
+    let tcb_info_issuer_chain = issuer_chain(
+        &headers,
+        &["SGX-TCB-Info-Issuer-Chain", "TCB-Info-Issuer-Chain"],
+    )?;
+    let (tcb_info, tcb_info_signature) = decode_signed_json(&tcb_info, "tcbInfo")?;
+    let (qe_identity, headers) = pccs_get("qe/identity")?;
+    let qe_identity_issuer_chain = issuer_chain(
+        &headers,
+        &[
+            "SGX-Enclave-Identity-Issuer-Chain",
+            "SGX-QE-Identity-Issuer-Chain",
+        ],
+    )?;
+    let (qe_identity, qe_identity_signature) = decode_signed_json(&qe_identity, "enclaveIdentity")?;
+    Ok(SgxQuoteCollateral {
+        root_ca_crl: decode_crl(root_ca_crl)?,
+        pck_crl: decode_crl(pck_crl)?,
+        tcb_info_issuer_chain,
+        tcb_info,
+        tcb_info_signature,
+        qe_identity_issuer_chain,
+        qe_identity,
+        qe_identity_signature,
+    })
 }
@@ -30,10 +30,37 @@ pub struct Args {
     #[clap(
         default_value = "",
         long,
-        help = "The PostgresQL database to store the events."
+        help = "Where to store the events: postgres://..., sqlite://<file>, jsonl://<dir> or an http(s) webhook. Webhook deliveries are at-least-once, a batch may be posted again after a failure or restart."
     )]
     persist_events_to: String,
 
+    #[clap(
+        default_value = "256",
+        long,
+        help = "Rotate the JSONL event file once it grows beyond the given size in MB."
+    )]
+    jsonl_rotate_size: u64,
+
+    #[clap(
+        long,
+        help = "The file to keep the last sequence delivered to the webhook event sink. Default is webhook.<hash of the URI>.sequence in the working directory."
+    )]
+    webhook_state_file: Option<String>,
+
+    #[clap(
+        default_value = "kafka",
+        long,
+        possible_values = &["kafka", "none"],
+        help = "How the records are wrapped in the webhook requests: kafka for the Kafka REST proxy format, none for a plain JSON array."
+    )]
+    webhook_envelope: String,
+
+    #[clap(
+        long,
+        help = "The Content-Type of the webhook requests. Default is application/vnd.kafka.json.v2+json with the kafka envelope, application/json otherwise."
+    )]
+    webhook_content_type: Option<String>,
+
     #[clap(
         default_value = "0",
         long,
@@ -53,6 +80,38 @@ pub struct Args {
         help = "The checkpoint file to restore from. Default is to use the latest checkpoint."
     )]
     restore_from: Option<String>,
+
+    #[clap(
+        long,
+        help = "Also replay the contract clusters, the contracts and their sidevm instances. Only takes effect when starting without a checkpoint."
+    )]
+    replay_contracts: bool,
+
+    #[clap(
+        long,
+        help = "The secret URI (e.g. //Alice) of the worker key to replay the contracts as. Default is a random key."
+    )]
+    worker_key: Option<String>,
+
+    #[clap(
+        long,
+        help = "Deploy the clusters whose keys are not dispatched to the worker with simulated keys. The contract keys derived from them differ from the real ones, so the commands encrypted to the real contract keys can never be decrypted and those contracts diverge from the chain. The failures are counted per contract in /clusters."
+    )]
+    simulate_cluster_keys: bool,
+
+    #[clap(
+        default_value = "100",
+        long,
+        help = "The number of blocks between two snapshots of the workers' tokenomic state, saved along with the checkpoints. 0 for disabled"
+    )]
+    snapshot_interval: u32,
+
+    #[clap(
+        default_value = "7200",
+        long,
+        help = "The number of recent blocks to keep the snapshots and the economic events for."
+    )]
+    snapshot_retention: u32,
 }
 
 #[tokio::main]
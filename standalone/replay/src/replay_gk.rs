@@ -1,5 +1,7 @@
 mod data_persist;
+mod history;
 mod httpserver;
+mod platform;
 
 use std::{
     fs::File,
@@ -11,15 +13,19 @@ use std::{
 
 use anyhow::Error;
 use anyhow::Result;
-use phactory::{gk, BlockInfo, SideTaskManager, StorageExt};
+use history::History;
+use phactory::{gk, BlockInfo, SideTaskManager, StorageExt, System};
 use phactory_api::blocks::BlockHeaderWithChanges;
+use phala_crypto::sr25519::KDF as _;
 use phala_mq::Path as MqPath;
-use phala_mq::{MessageDispatcher, Sr25519Signer};
+use phala_mq::{MessageDispatcher, MessageSendQueue, Sr25519Signer};
 use phala_trie_storage::TrieStorage;
 use phala_types::WorkerPublicKey;
 use phaxt::rpc::ExtraRpcExt as _;
 use pherry::types::{phaxt, subxt, BlockNumber, Hashing, NumberOrHex, ParachainApi, StorageKey};
+use platform::ReplayPlatform;
 use serde::{Deserialize, Serialize};
+use sp_core::{sr25519, Pair as _};
 use tokio::sync::{mpsc, Mutex};
 
 use crate::Args;
@@ -44,7 +50,17 @@ pub struct ReplayFactory {
     #[serde(skip)]
     #[serde(default)]
     recv_mq: MessageDispatcher,
+    /// The egress of the replayed `System`, dropped after each block.
+    #[serde(skip)]
+    #[serde(default)]
+    send_mq: MessageSendQueue,
     gk: gk::MiningEconomics<ReplayMsgChannel>,
+    /// The worker replaying the clusters and contracts, if enabled.
+    #[serde(default)]
+    system: Option<System<ReplayPlatform>>,
+    /// Absent in the checkpoints taken before the history was saved along.
+    #[serde(default)]
+    history: History,
 }
 
 impl ReplayFactory {
@@ -58,10 +74,39 @@ impl ReplayFactory {
             current_block: 0,
             storage,
             recv_mq,
+            send_mq: Default::default(),
             gk,
+            system: None,
+            history: Default::default(),
         }
     }
 
+    /// Replays the clusters and contracts as the worker of the given identity key as well.
+    fn enable_contracts(&mut self, identity_key: sr25519::Pair) {
+        log::info!(
+            "Replaying contracts as worker {}",
+            hex::encode(identity_key.public())
+        );
+        let ecdh_key = identity_key
+            .derive_ecdh_key()
+            .expect("Unable to derive ecdh key");
+        let system = System::new(
+            ReplayPlatform,
+            ".".into(),
+            ".".into(),
+            false,
+            Default::default(),
+            identity_key,
+            ecdh_key,
+            false,
+            &self.send_mq,
+            &mut self.recv_mq,
+            Default::default(),
+            2,
+        );
+        self.system = Some(system);
+    }
+
     async fn dispatch_block(
         &mut self,
         block: BlockHeaderWithChanges,
@@ -106,7 +151,7 @@ impl ReplayFactory {
             now_ms,
             storage: &self.storage,
             recv_mq: &mut self.recv_mq,
-            send_mq: &mut Default::default(),
+            send_mq: &self.send_mq,
             side_task_man: &mut SideTaskManager::default(),
         };
 
@@ -129,11 +174,31 @@ impl ReplayFactory {
         };
 
         self.gk.will_process_block(&block);
+        if let Some(system) = &mut self.system {
+            system.will_process_block(&mut block);
+        }
         for message in messages {
             block.recv_mq.dispatch(message);
             self.gk.process_messages(&mut block, &mut event_handler);
+            if let Some(system) = &mut self.system {
+                system.process_messages(&mut block);
+            }
         }
         self.gk.did_process_block(&block, &mut event_handler);
+        if let Some(system) = &mut self.system {
+            system.did_process_block(&mut block);
+        }
+
+        // Nothing is sent to the chain in replay.
+        let n_egress = self.send_mq.count_messages();
+        if n_egress > 0 {
+            log::debug!("There are {} egress messages dropped", n_egress);
+            self.send_mq.purge(|_| u64::MAX);
+        }
+
+        self.history.record_events(&records);
+        self.history
+            .on_block_processed(block_number, self.gk.workers());
 
         if let Some(tx) = event_tx.as_ref() {
             for record in records {
@@ -156,11 +221,18 @@ impl ReplayFactory {
 
     fn load(reader: impl Read) -> Self {
         let mut dispatcher = Default::default();
+        let mut send_mq = Default::default();
         let mut factory: Self =
-            phala_mq::checkpoint_helper::using_dispatcher(&mut dispatcher, move || {
-                serde_cbor::from_reader(reader).expect("Failed to load checkpoint")
+            phala_mq::checkpoint_helper::using_dispatcher(&mut dispatcher, || {
+                phala_mq::checkpoint_helper::using_send_mq(&mut send_mq, move || {
+                    serde_cbor::from_reader(reader).expect("Failed to load checkpoint")
+                })
             });
         factory.recv_mq = dispatcher;
+        factory.send_mq = send_mq;
+        if let Some(system) = &mut factory.system {
+            system.on_restored().expect("Failed to restore the system");
+        }
         factory
     }
 
@@ -243,21 +315,48 @@ pub async fn replay(args: Args) -> Result<()> {
 
     let genesis_state = fetch_genesis_storage(&api, args.start_at).await?;
     let event_tx = if !db_uri.is_empty() {
+        let options = data_persist::SinkOptions {
+            jsonl_rotate_size: args.jsonl_rotate_size * 1024 * 1024,
+            webhook_envelope: args.webhook_envelope.parse()?,
+            webhook_content_type: args.webhook_content_type,
+            webhook_state_file: args.webhook_state_file,
+        };
+        let sink = data_persist::open_sink(&db_uri, options).await?;
         let (event_tx, event_rx) = mpsc::channel(1024 * 5);
-        let _db_task =
-            tokio::spawn(async move { data_persist::run_persist(event_rx, &db_uri).await });
+        let _db_task = tokio::spawn(data_persist::run_persist(event_rx, sink));
         Some(event_tx)
     } else {
         None
     };
 
-    let factory = match get_checkpoint_path(&args.restore_from) {
+    let mut factory = match get_checkpoint_path(&args.restore_from) {
         Some(filename) => {
             log::info!("Restoring from checkpoint: {}", filename);
-            ReplayFactory::load_from_file(&filename)
+            let factory = ReplayFactory::load_from_file(&filename);
+            if args.replay_contracts && factory.system.is_none() {
+                log::warn!("No contract state in the checkpoint, contracts are not replayed");
+            }
+            factory
+        }
+        None => {
+            let mut factory = ReplayFactory::new(genesis_state);
+            if args.replay_contracts {
+                let identity_key = match &args.worker_key {
+                    Some(suri) => sr25519::Pair::from_string(suri, None)
+                        .map_err(|err| anyhow::anyhow!("Invalid worker key: {:?}", err))?,
+                    None => sr25519::Pair::generate().0,
+                };
+                factory.enable_contracts(identity_key);
+            }
+            factory
         }
-        None => ReplayFactory::new(genesis_state),
     };
+    if let Some(system) = &mut factory.system {
+        system.set_simulate_cluster_keys(args.simulate_cluster_keys);
+    }
+    factory
+        .history
+        .configure(args.snapshot_interval, args.snapshot_retention);
     let mut last_checkpoint_block: BlockNumber = factory.current_block;
     let factory = Arc::new(Mutex::new(factory));
 
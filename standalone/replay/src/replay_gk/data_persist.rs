@@ -1,20 +1,103 @@
+mod jsonl;
+mod postgres;
+mod sqlite;
+mod webhook;
+
+pub(super) use webhook::Envelope as WebhookEnvelope;
+
 use super::EventRecord;
-use anyhow::Result;
-use chrono::TimeZone as _;
-use phactory::gk;
-use sqlx::types::Decimal;
-use sqlx::{postgres::PgPoolOptions, Row};
+use anyhow::{anyhow, Result};
+use serde::Serialize;
 use std::time::Duration;
 use tokio::sync::mpsc;
 
-pub(super) async fn run_persist(mut rx: mpsc::Receiver<EventRecord>, uri: &str) {
+/// A destination the replayed events are persisted to.
+///
+/// The records are sent in batches with increasing sequences. The sink tells the last sequence it
+/// has persisted, so that the replay can be restarted from an older checkpoint without
+/// duplicating records. The database and file sinks persist a batch together with its sequence,
+/// but the webhook sink only records the sequence once a batch is delivered, so its deliveries
+/// are at-least-once.
+#[async_trait::async_trait]
+pub(super) trait EventSink: Send {
+    /// The sequence of the last persisted record, 0 if nothing has been persisted.
+    async fn last_sequence(&mut self) -> Result<i64>;
+
+    /// Persists the records, which all come after the last sequence.
+    async fn insert(&mut self, records: &[EventRecord]) -> Result<()>;
+}
+
+/// Options of the sinks which can not be given in the URI.
+pub(super) struct SinkOptions {
+    /// Rotate the JSONL file once it grows beyond this size.
+    pub jsonl_rotate_size: u64,
+    /// How the records are wrapped in the body posted to the webhook.
+    pub webhook_envelope: WebhookEnvelope,
+    /// The Content-Type of the webhook requests. Default is the one of the envelope.
+    pub webhook_content_type: Option<String>,
+    /// The file keeping the last sequence delivered to the webhook. Default is derived from the
+    /// URI.
+    pub webhook_state_file: Option<String>,
+}
+
+/// Opens the sink selected by the scheme of the URI:
+///
+/// - `postgres://...`: the `worker_finance_events` table of a PostgreSQL database.
+/// - `sqlite://<file>`: the `worker_finance_events` table of a SQLite database.
+/// - `jsonl://<dir>`: newline-delimited JSON files in a directory.
+/// - `http://...` or `https://...`: a webhook receiving the records in batches.
+pub(super) async fn open_sink(uri: &str, options: SinkOptions) -> Result<Box<dyn EventSink>> {
+    let (scheme, path) = uri
+        .split_once("://")
+        .ok_or_else(|| anyhow!("Invalid sink URI: {}", uri))?;
     log::info!("Connecting to {}", uri);
+    Ok(match scheme {
+        "postgres" | "postgresql" => Box::new(postgres::PostgresSink::connect(uri).await?),
+        "sqlite" => Box::new(sqlite::SqliteSink::connect(uri).await?),
+        "jsonl" => Box::new(jsonl::JsonlSink::open(path, options.jsonl_rotate_size)?),
+        "http" | "https" => Box::new(webhook::WebhookSink::new(
+            uri,
+            options.webhook_envelope,
+            options.webhook_content_type,
+            options.webhook_state_file,
+        )?),
+        _ => return Err(anyhow!("Unsupported sink: {}", scheme)),
+    })
+}
 
-    let pool = PgPoolOptions::new()
-        .max_connections(5)
-        .connect(uri)
-        .await
-        .expect("Connect to database failed");
+/// The JSON representation of a record, used by the JSONL and webhook sinks.
+#[derive(Serialize)]
+struct JsonRecord {
+    sequence: i64,
+    pubkey: String,
+    block: u32,
+    time_ms: u64,
+    event: &'static str,
+    v: String,
+    p: String,
+    payout: String,
+}
+
+impl From<&EventRecord> for JsonRecord {
+    fn from(rec: &EventRecord) -> Self {
+        Self {
+            sequence: rec.sequence,
+            pubkey: format!("0x{}", hex::encode(&rec.pubkey.0)),
+            block: rec.block_number,
+            time_ms: rec.time_ms,
+            event: rec.event.event_string(),
+            v: rec.v.to_string(),
+            p: rec.p.to_string(),
+            payout: rec.event.payout().to_string(),
+        }
+    }
+}
+
+pub(super) async fn run_persist(mut rx: mpsc::Receiver<EventRecord>, mut sink: Box<dyn EventSink>) {
+    match sink.last_sequence().await {
+        Ok(last_sequence) => log::info!("Persisting events after sequence {}", last_sequence),
+        Err(err) => log::error!("Failed to get the last sequence: {}", err),
+    }
 
     let mut stopped = false;
 
@@ -43,36 +126,18 @@ pub(super) async fn run_persist(mut rx: mpsc::Receiver<EventRecord>, uri: &str)
         }
         if !records.is_empty() {
             log::info!("Inserting {} records.", records.len());
-            'try_insert: loop {
-                match insert_records(&pool, &records).await {
+            loop {
+                match insert_new_records(&mut *sink, &mut records).await {
                     Ok(()) => {
                         break;
                     }
                     Err(err) => {
+                        // Error, let's try to insert again later.
+                        let delay = 5;
                         log::error!("Insert {} records error.", records.len());
                         log::error!("{}", err);
-                        match get_last_sequence(&pool).await {
-                            Ok(last_sequence) => {
-                                log::info!("last_sequence={}", last_sequence);
-                                if last_sequence
-                                    >= records.last().expect("records can not be empty").sequence
-                                {
-                                    log::info!("Insert succeeded, let's move on");
-                                    break;
-                                }
-                                records.retain(|r| r.sequence > last_sequence);
-                                log::info!("Insert records failed, try again");
-                                continue 'try_insert;
-                            }
-                            Err(err) => {
-                                // Error, let's try to insert again later.
-                                let delay = 5;
-                                log::error!("{}", err);
-                                log::error!("Try again in {}s", delay);
-                                tokio::time::sleep(Duration::from_secs(delay)).await;
-                                continue 'try_insert;
-                            }
-                        }
+                        log::error!("Try again in {}s", delay);
+                        tokio::time::sleep(Duration::from_secs(delay)).await;
                     }
                 }
             }
@@ -80,75 +145,16 @@ pub(super) async fn run_persist(mut rx: mpsc::Receiver<EventRecord>, uri: &str)
     }
 }
 
-async fn insert_records(pool: &sqlx::Pool<sqlx::Postgres>, records: &[EventRecord]) -> Result<()> {
-    // Current version of sqlx does not support bulk insertion, so we have to do it manually.
-    let mut sequences = vec![];
-    let mut pubkeys = vec![];
-    let mut block_numbers = vec![];
-    let mut timestamps = vec![];
-    let mut events = vec![];
-    let mut vs = vec![];
-    let mut ps = vec![];
-    let mut payouts = vec![];
-
-    let last_seq = get_last_sequence(&pool).await?;
-
-    for rec in records {
-        if rec.sequence <= last_seq {
-            continue;
-        }
-        sequences.push(rec.sequence);
-        pubkeys.push(rec.pubkey.0.to_vec());
-        block_numbers.push(rec.block_number);
-        timestamps.push(chrono::Utc.timestamp_millis(rec.time_ms as _));
-        events.push(rec.event.event_string());
-        vs.push(cvt_fp(rec.v));
-        ps.push(cvt_fp(rec.p));
-        payouts.push(cvt_fp(rec.event.payout()));
-    }
-
-    if sequences.is_empty() {
+/// Inserts the records not persisted yet, which may have been persisted before a restart or by
+/// a failed insertion.
+async fn insert_new_records(
+    sink: &mut dyn EventSink,
+    records: &mut Vec<EventRecord>,
+) -> Result<()> {
+    let last_sequence = sink.last_sequence().await?;
+    records.retain(|r| r.sequence > last_sequence);
+    if records.is_empty() {
         return Ok(());
     }
-
-    sqlx::query(
-        r#"
-        INSERT INTO worker_finance_events
-            (sequence, pubkey, block, time, event, v, p, payout)
-        SELECT *
-        FROM UNNEST($1, $2, $3, $4, $5, $6, $7, $8)
-        ON CONFLICT (time, sequence)
-        DO UPDATE
-        SET (pubkey, block, event, v, p, payout) = (
-            EXCLUDED.pubkey, EXCLUDED.block, EXCLUDED.event, EXCLUDED.v, EXCLUDED.p,
-            EXCLUDED.payout
-        )
-        "#,
-    )
-    .bind(&sequences)
-    .bind(&pubkeys)
-    .bind(&block_numbers)
-    .bind(&timestamps)
-    .bind(&events)
-    .bind(&vs)
-    .bind(&ps)
-    .bind(&payouts)
-    .execute(pool)
-    .await?;
-
-    log::debug!("Inserted {} records.", records.len());
-
-    Ok(())
-}
-
-fn cvt_fp(v: gk::FixedPoint) -> Decimal {
-    Decimal::from_i128_with_scale((v * 10000000000).to_num(), 10)
-}
-
-async fn get_last_sequence(pool: &sqlx::Pool<sqlx::Postgres>) -> Result<i64> {
-    let latest_row =
-        sqlx::query("SELECT sequence FROM worker_finance_events ORDER BY sequence DESC LIMIT 1")
-            .fetch_optional(pool)
-            .await?;
-    Ok(latest_row.map_or(0, |row| row.get(0)))
+    sink.insert(records).await
 }
@@ -0,0 +1,250 @@
+use super::{EventRecord, EventSink, JsonRecord};
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Appends the records as newline-delimited JSON to `events.<first sequence>.jsonl` files in a
+/// directory, starting a new file once the current one grows beyond the rotation size.
+pub(super) struct JsonlSink {
+    dir: PathBuf,
+    rotate_size: u64,
+    file: Option<File>,
+    file_size: u64,
+    last_sequence: i64,
+}
+
+#[derive(Deserialize)]
+struct SequenceOnly {
+    sequence: i64,
+}
+
+impl JsonlSink {
+    pub(super) fn open(dir: &str, rotate_size: u64) -> Result<Self> {
+        let dir = PathBuf::from(dir);
+        std::fs::create_dir_all(&dir).context("Failed to create the JSONL directory")?;
+        let mut sink = Self {
+            dir,
+            rotate_size,
+            file: None,
+            file_size: 0,
+            last_sequence: 0,
+        };
+        if let Some((first_sequence, path)) = sink.latest_file()? {
+            sink.resume(first_sequence, &path)?;
+        }
+        Ok(sink)
+    }
+
+    /// The file with the highest first sequence, along with its first sequence.
+    fn latest_file(&self) -> Result<Option<(i64, PathBuf)>> {
+        let mut latest: Option<(i64, PathBuf)> = None;
+        for entry in std::fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            let first_sequence = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.strip_prefix("events."))
+                .and_then(|name| name.strip_suffix(".jsonl"))
+                .and_then(|seq| seq.parse::<i64>().ok());
+            if let Some(seq) = first_sequence {
+                if latest.as_ref().map_or(true, |(latest, _)| seq > *latest) {
+                    latest = Some((seq, path));
+                }
+            }
+        }
+        Ok(latest)
+    }
+
+    /// Reopens the file for appending, dropping a partially written line at its end.
+    ///
+    /// The file is named after the sequence of its first record, so the records before it are
+    /// all persisted even if it has no complete line yet.
+    fn resume(&mut self, first_sequence: i64, path: &Path) -> Result<()> {
+        let content = std::fs::read(path)?;
+        let valid_len = content
+            .iter()
+            .rposition(|&b| b == b'\n')
+            .map_or(0, |pos| pos + 1);
+        self.last_sequence = match content[..valid_len].split(|&b| b == b'\n').rev().nth(1) {
+            Some(last_line) => {
+                serde_json::from_slice::<SequenceOnly>(last_line)
+                    .with_context(|| format!("Invalid last record in {}", path.display()))?
+                    .sequence
+            }
+            None => first_sequence - 1,
+        };
+        if valid_len < content.len() {
+            log::warn!(
+                "Dropping {} bytes of partially written record in {}",
+                content.len() - valid_len,
+                path.display()
+            );
+            let file = OpenOptions::new().write(true).open(path)?;
+            file.set_len(valid_len as u64)?;
+        }
+        self.file = Some(OpenOptions::new().append(true).open(path)?);
+        self.file_size = valid_len as u64;
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl EventSink for JsonlSink {
+    async fn last_sequence(&mut self) -> Result<i64> {
+        Ok(self.last_sequence)
+    }
+
+    async fn insert(&mut self, records: &[EventRecord]) -> Result<()> {
+        let first = match records.first() {
+            Some(first) => first,
+            None => return Ok(()),
+        };
+        if self.file.is_none() || self.file_size >= self.rotate_size {
+            let path = self.dir.join(format!("events.{}.jsonl", first.sequence));
+            log::info!("Writing events to {}", path.display());
+            self.file = Some(File::create(path)?);
+            self.file_size = 0;
+        }
+        let mut buffer = vec![];
+        for rec in records {
+            serde_json::to_writer(&mut buffer, &JsonRecord::from(rec))?;
+            buffer.push(b'\n');
+        }
+        let file = self.file.as_mut().expect("The file is opened above");
+        if let Err(err) = file.write_all(&buffer).and_then(|_| file.flush()) {
+            // Drop the partially written records, they are written again on retry.
+            file.set_len(self.file_size)?;
+            return Err(err.into());
+        }
+        self.file_size += buffer.len() as u64;
+        self.last_sequence = records.last().expect("records can not be empty").sequence;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use phactory::gk::EconomicEvent;
+    use phala_types::WorkerPublicKey;
+    use std::ops::RangeInclusive;
+
+    fn records(sequences: RangeInclusive<i64>) -> Vec<EventRecord> {
+        sequences
+            .map(|sequence| EventRecord {
+                sequence,
+                pubkey: WorkerPublicKey::from_raw([1u8; 32]),
+                block_number: sequence as _,
+                time_ms: 0,
+                event: EconomicEvent::MiningStart,
+                v: Default::default(),
+                p: Default::default(),
+            })
+            .collect()
+    }
+
+    fn line(sequence: i64) -> Vec<u8> {
+        let mut line =
+            serde_json::to_vec(&JsonRecord::from(&records(sequence..=sequence)[0])).unwrap();
+        line.push(b'\n');
+        line
+    }
+
+    fn open(dir: &Path, rotate_size: u64) -> JsonlSink {
+        JsonlSink::open(dir.to_str().unwrap(), rotate_size).unwrap()
+    }
+
+    fn file_names(dir: &Path) -> Vec<String> {
+        let mut names: Vec<_> = std::fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        names.sort();
+        names
+    }
+
+    fn sequences(path: &Path) -> Vec<i64> {
+        std::fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str::<SequenceOnly>(line).unwrap().sequence)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn rotates_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut sink = open(dir.path(), 1);
+        assert_eq!(sink.last_sequence().await.unwrap(), 0);
+        sink.insert(&records(1..=2)).await.unwrap();
+        sink.insert(&records(3..=5)).await.unwrap();
+        assert_eq!(sink.last_sequence().await.unwrap(), 5);
+        assert_eq!(
+            file_names(dir.path()),
+            vec!["events.1.jsonl", "events.3.jsonl"]
+        );
+        assert_eq!(sequences(&dir.path().join("events.1.jsonl")), vec![1, 2]);
+        assert_eq!(sequences(&dir.path().join("events.3.jsonl")), vec![3, 4, 5]);
+    }
+
+    #[tokio::test]
+    async fn resumes_the_latest_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut sink = open(dir.path(), 1);
+        sink.insert(&records(1..=2)).await.unwrap();
+        sink.insert(&records(3..=4)).await.unwrap();
+        drop(sink);
+
+        let mut sink = open(dir.path(), u64::MAX);
+        assert_eq!(sink.last_sequence().await.unwrap(), 4);
+        sink.insert(&records(5..=6)).await.unwrap();
+        assert_eq!(
+            file_names(dir.path()),
+            vec!["events.1.jsonl", "events.3.jsonl"]
+        );
+        assert_eq!(
+            sequences(&dir.path().join("events.3.jsonl")),
+            vec![3, 4, 5, 6]
+        );
+    }
+
+    #[tokio::test]
+    async fn drops_only_the_trailing_partial_line() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("events.1.jsonl");
+        // An unparsable line in the middle is kept, only the partial line at the end is dropped.
+        let mut content = line(1);
+        content.extend_from_slice(b"not a record\n");
+        content.extend(line(2));
+        let valid_len = content.len();
+        content.extend_from_slice(br#"{"sequence":3,"pub"#);
+        std::fs::write(&path, &content).unwrap();
+
+        let mut sink = open(dir.path(), u64::MAX);
+        assert_eq!(sink.last_sequence().await.unwrap(), 2);
+        assert_eq!(std::fs::read(&path).unwrap(), content[..valid_len]);
+
+        sink.insert(&records(3..=3)).await.unwrap();
+        let mut expected = content[..valid_len].to_vec();
+        expected.extend(line(3));
+        assert_eq!(std::fs::read(&path).unwrap(), expected);
+    }
+
+    #[tokio::test]
+    async fn resumes_a_latest_file_without_records() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut sink = open(dir.path(), 1);
+        sink.insert(&records(1..=2)).await.unwrap();
+        drop(sink);
+        let path = dir.path().join("events.3.jsonl");
+        std::fs::write(&path, br#"{"sequence":3,"pub"#).unwrap();
+
+        let mut sink = open(dir.path(), u64::MAX);
+        assert_eq!(sink.last_sequence().await.unwrap(), 2);
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), 0);
+        sink.insert(&records(3..=3)).await.unwrap();
+        assert_eq!(sequences(&path), vec![3]);
+    }
+}
@@ -0,0 +1,86 @@
+use super::{EventRecord, EventSink};
+use anyhow::Result;
+use chrono::TimeZone as _;
+use phactory::gk;
+use sqlx::types::Decimal;
+use sqlx::{postgres::PgPoolOptions, Row};
+
+/// Persists the records to the `worker_finance_events` table created by `create_tables.sql`.
+pub(super) struct PostgresSink {
+    pool: sqlx::Pool<sqlx::Postgres>,
+}
+
+impl PostgresSink {
+    pub(super) async fn connect(uri: &str) -> Result<Self> {
+        let pool = PgPoolOptions::new().max_connections(5).connect(uri).await?;
+        Ok(Self { pool })
+    }
+}
+
+#[async_trait::async_trait]
+impl EventSink for PostgresSink {
+    async fn last_sequence(&mut self) -> Result<i64> {
+        let latest_row = sqlx::query(
+            "SELECT sequence FROM worker_finance_events ORDER BY sequence DESC LIMIT 1",
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(latest_row.map_or(0, |row| row.get(0)))
+    }
+
+    async fn insert(&mut self, records: &[EventRecord]) -> Result<()> {
+        // Current version of sqlx does not support bulk insertion, so we have to do it manually.
+        let mut sequences = vec![];
+        let mut pubkeys = vec![];
+        let mut block_numbers = vec![];
+        let mut timestamps = vec![];
+        let mut events = vec![];
+        let mut vs = vec![];
+        let mut ps = vec![];
+        let mut payouts = vec![];
+
+        for rec in records {
+            sequences.push(rec.sequence);
+            pubkeys.push(rec.pubkey.0.to_vec());
+            block_numbers.push(rec.block_number);
+            timestamps.push(chrono::Utc.timestamp_millis(rec.time_ms as _));
+            events.push(rec.event.event_string());
+            vs.push(cvt_fp(rec.v));
+            ps.push(cvt_fp(rec.p));
+            payouts.push(cvt_fp(rec.event.payout()));
+        }
+
+        sqlx::query(
+            r#"
+            INSERT INTO worker_finance_events
+                (sequence, pubkey, block, time, event, v, p, payout)
+            SELECT *
+            FROM UNNEST($1, $2, $3, $4, $5, $6, $7, $8)
+            ON CONFLICT (time, sequence)
+            DO UPDATE
+            SET (pubkey, block, event, v, p, payout) = (
+                EXCLUDED.pubkey, EXCLUDED.block, EXCLUDED.event, EXCLUDED.v, EXCLUDED.p,
+                EXCLUDED.payout
+            )
+            "#,
+        )
+        .bind(&sequences)
+        .bind(&pubkeys)
+        .bind(&block_numbers)
+        .bind(&timestamps)
+        .bind(&events)
+        .bind(&vs)
+        .bind(&ps)
+        .bind(&payouts)
+        .execute(&self.pool)
+        .await?;
+
+        log::debug!("Inserted {} records.", records.len());
+
+        Ok(())
+    }
+}
+
+fn cvt_fp(v: gk::FixedPoint) -> Decimal {
+    Decimal::from_i128_with_scale((v * 10000000000).to_num(), 10)
+}
@@ -0,0 +1,78 @@
+use super::{EventRecord, EventSink};
+use anyhow::Result;
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
+use sqlx::Row;
+use std::str::FromStr;
+
+/// Persists the records to the `worker_finance_events` table of a SQLite database, created if
+/// missing.
+///
+/// SQLite has no decimal type, so the fixed point numbers are stored as text.
+pub(super) struct SqliteSink {
+    pool: sqlx::Pool<sqlx::Sqlite>,
+}
+
+impl SqliteSink {
+    pub(super) async fn connect(uri: &str) -> Result<Self> {
+        let options = SqliteConnectOptions::from_str(uri)?.create_if_missing(true);
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect_with(options)
+            .await?;
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS worker_finance_events (
+                sequence INTEGER PRIMARY KEY NOT NULL,
+                pubkey BLOB NOT NULL,
+                block INTEGER NOT NULL,
+                time_ms INTEGER NOT NULL,
+                event TEXT NOT NULL,
+                v TEXT NOT NULL,
+                p TEXT NOT NULL,
+                payout TEXT NOT NULL
+            )
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+        Ok(Self { pool })
+    }
+}
+
+#[async_trait::async_trait]
+impl EventSink for SqliteSink {
+    async fn last_sequence(&mut self) -> Result<i64> {
+        let row = sqlx::query("SELECT IFNULL(MAX(sequence), 0) FROM worker_finance_events")
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(row.get(0))
+    }
+
+    async fn insert(&mut self, records: &[EventRecord]) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        for rec in records {
+            sqlx::query(
+                r#"
+                INSERT OR REPLACE INTO worker_finance_events
+                    (sequence, pubkey, block, time_ms, event, v, p, payout)
+                VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+                "#,
+            )
+            .bind(rec.sequence)
+            .bind(rec.pubkey.0.to_vec())
+            .bind(rec.block_number)
+            .bind(rec.time_ms as i64)
+            .bind(rec.event.event_string())
+            .bind(rec.v.to_string())
+            .bind(rec.p.to_string())
+            .bind(rec.event.payout().to_string())
+            .execute(&mut tx)
+            .await?;
+        }
+        tx.commit().await?;
+
+        log::debug!("Inserted {} records.", records.len());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,168 @@
+use super::{EventRecord, EventSink, JsonRecord};
+use anyhow::{anyhow, Context, Result};
+use serde::Serialize;
+use sp_core::hashing::blake2_256;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// How the records of a batch are wrapped in the body posted to the webhook.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Envelope {
+    /// The format of the Kafka REST proxy, `{"records": [{"value": <record>}, ...]}`, so that a
+    /// topic endpoint of the proxy can be used as the webhook directly.
+    Kafka,
+    /// A plain JSON array of the records.
+    None,
+}
+
+impl Envelope {
+    fn default_content_type(self) -> &'static str {
+        match self {
+            Envelope::Kafka => "application/vnd.kafka.json.v2+json",
+            Envelope::None => "application/json",
+        }
+    }
+}
+
+impl FromStr for Envelope {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "kafka" => Ok(Envelope::Kafka),
+            "none" => Ok(Envelope::None),
+            _ => Err(anyhow!("Unknown webhook envelope: {}", s)),
+        }
+    }
+}
+
+/// The state file used when none is given, distinct for each webhook so that switching the URI
+/// does not skip the records never delivered to the new one.
+pub(super) fn default_state_file(uri: &str) -> String {
+    format!(
+        "webhook.{}.sequence",
+        hex::encode(&blake2_256(uri.as_bytes())[..8])
+    )
+}
+
+/// POSTs the records in batches to a webhook, wrapped in the given [`Envelope`].
+///
+/// The webhook can not be asked what it has received, so the last delivered sequence is kept in a
+/// local state file. The file is written after the webhook accepted a batch, so a batch is
+/// delivered again if the replay stops in between, or if the webhook processed a request that
+/// failed on our side. The receiver should deduplicate the records by their sequence.
+pub(super) struct WebhookSink {
+    uri: String,
+    client: reqwest::Client,
+    envelope: Envelope,
+    content_type: String,
+    state_file: String,
+    last_sequence: i64,
+}
+
+#[derive(Serialize)]
+struct Batch {
+    records: Vec<Value>,
+}
+
+#[derive(Serialize)]
+struct Value {
+    value: JsonRecord,
+}
+
+impl WebhookSink {
+    pub(super) fn new(
+        uri: &str,
+        envelope: Envelope,
+        content_type: Option<String>,
+        state_file: Option<String>,
+    ) -> Result<Self> {
+        let state_file = state_file.unwrap_or_else(|| default_state_file(uri));
+        log::info!("Keeping the last delivered sequence in {}", state_file);
+        let last_sequence = match std::fs::read_to_string(&state_file) {
+            Ok(content) => content
+                .trim()
+                .parse()
+                .context("Invalid webhook state file")?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => 0,
+            Err(err) => return Err(err.into()),
+        };
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(30))
+            .build()?;
+        Ok(Self {
+            uri: uri.into(),
+            client,
+            envelope,
+            content_type: content_type.unwrap_or_else(|| envelope.default_content_type().into()),
+            state_file,
+            last_sequence,
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl EventSink for WebhookSink {
+    async fn last_sequence(&mut self) -> Result<i64> {
+        Ok(self.last_sequence)
+    }
+
+    async fn insert(&mut self, records: &[EventRecord]) -> Result<()> {
+        let last = match records.last() {
+            Some(last) => last.sequence,
+            None => return Ok(()),
+        };
+        let body = match self.envelope {
+            Envelope::Kafka => serde_json::to_vec(&Batch {
+                records: records
+                    .iter()
+                    .map(|rec| Value { value: rec.into() })
+                    .collect(),
+            })?,
+            Envelope::None => {
+                serde_json::to_vec(&records.iter().map(JsonRecord::from).collect::<Vec<_>>())?
+            }
+        };
+        self.client
+            .post(&self.uri)
+            .header("Content-Type", &self.content_type)
+            .body(body)
+            .send()
+            .await?
+            .error_for_status()?;
+        self.last_sequence = last;
+        tokio::fs::write(&self.state_file, last.to_string())
+            .await
+            .context("Failed to write the webhook state file")?;
+
+        log::debug!("Delivered {} records.", records.len());
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn state_file_follows_the_uri() {
+        let file = default_state_file("https://example.com/topics/events");
+        assert!(file.starts_with("webhook.") && file.ends_with(".sequence"));
+        assert_eq!(
+            file,
+            default_state_file("https://example.com/topics/events")
+        );
+        assert_ne!(
+            file,
+            default_state_file("https://example.com/topics/others")
+        );
+    }
+
+    #[test]
+    fn parse_envelope() {
+        assert_eq!("kafka".parse::<Envelope>().unwrap(), Envelope::Kafka);
+        assert_eq!("none".parse::<Envelope>().unwrap(), Envelope::None);
+        assert!("json".parse::<Envelope>().is_err());
+    }
+}
@@ -0,0 +1,502 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::convert::TryFrom;
+
+use phactory::gk;
+use phala_types::WorkerPublicKey;
+use pherry::types::BlockNumber;
+use serde::{de::Error as _, Deserialize, Deserializer, Serialize, Serializer};
+
+use super::EventRecord;
+
+fn fp_string<S: Serializer>(value: &gk::FixedPoint, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(value)
+}
+
+fn fp_from_string<'de, D: Deserializer<'de>>(deserializer: D) -> Result<gk::FixedPoint, D::Error> {
+    String::deserialize(deserializer)?
+        .parse()
+        .map_err(D::Error::custom)
+}
+
+fn event_string<S: Serializer>(
+    event: &gk::EconomicEvent,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(event.event_string())
+}
+
+/// The event named by [`gk::EconomicEvent::event_string`], with the payout of a heartbeat.
+fn parse_event(name: &str, payout: gk::FixedPoint) -> Option<gk::EconomicEvent> {
+    use gk::EconomicEvent::*;
+    Some(match name {
+        "mining_start" => MiningStart,
+        "mining_stop" => MiningStop,
+        "heartbeat_challenge" => HeartbeatChallenge,
+        "heartbeat" => Heartbeat { payout },
+        "enter_unresponsive" => EnterUnresponsive,
+        "exit_unresponsive" => ExitUnresponsive,
+        "recover_v" => RecoverV,
+        _ => return None,
+    })
+}
+
+/// The tokenomic state of a worker in a snapshot.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct WorkerSample {
+    pub block: BlockNumber,
+    #[serde(serialize_with = "fp_string", deserialize_with = "fp_from_string")]
+    pub v: gk::FixedPoint,
+    #[serde(serialize_with = "fp_string", deserialize_with = "fp_from_string")]
+    pub p: gk::FixedPoint,
+    pub unresponsive: bool,
+    pub mining: bool,
+}
+
+impl WorkerSample {
+    fn new(block: BlockNumber, worker: &gk::WorkerInfo) -> Self {
+        Self {
+            block,
+            v: worker.tokenomic_info().v,
+            p: worker.tokenomic_info().p_instant,
+            unresponsive: worker.unresponsive(),
+            mining: worker.mining(),
+        }
+    }
+
+    /// The state of a worker right after its first event since the registration.
+    fn registered(event: &EventSample) -> Self {
+        let mut sample = Self {
+            block: event.block,
+            v: event.v,
+            p: event.p,
+            unresponsive: false,
+            mining: false,
+        };
+        sample.apply(event);
+        sample
+    }
+
+    /// Moves the state forward to right after the event.
+    fn apply(&mut self, event: &EventSample) {
+        self.block = event.block;
+        self.v = event.v;
+        self.p = event.p;
+        match event.event {
+            gk::EconomicEvent::MiningStart => self.mining = true,
+            gk::EconomicEvent::MiningStop => self.mining = false,
+            gk::EconomicEvent::EnterUnresponsive => self.unresponsive = true,
+            gk::EconomicEvent::ExitUnresponsive => self.unresponsive = false,
+            gk::EconomicEvent::HeartbeatChallenge
+            | gk::EconomicEvent::Heartbeat { .. }
+            | gk::EconomicEvent::RecoverV => {}
+        }
+    }
+}
+
+/// An economic event of a worker, with the V and P right after it.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(try_from = "RawEventSample")]
+pub struct EventSample {
+    pub block: BlockNumber,
+    pub time_ms: u64,
+    #[serde(serialize_with = "event_string")]
+    pub event: gk::EconomicEvent,
+    #[serde(serialize_with = "fp_string")]
+    pub payout: gk::FixedPoint,
+    #[serde(serialize_with = "fp_string")]
+    pub v: gk::FixedPoint,
+    #[serde(serialize_with = "fp_string")]
+    pub p: gk::FixedPoint,
+}
+
+/// An [`EventSample`] as serialized, with the event by its name.
+#[derive(Deserialize)]
+struct RawEventSample {
+    block: BlockNumber,
+    time_ms: u64,
+    event: String,
+    #[serde(deserialize_with = "fp_from_string")]
+    payout: gk::FixedPoint,
+    #[serde(deserialize_with = "fp_from_string")]
+    v: gk::FixedPoint,
+    #[serde(deserialize_with = "fp_from_string")]
+    p: gk::FixedPoint,
+}
+
+impl TryFrom<RawEventSample> for EventSample {
+    type Error = String;
+
+    fn try_from(raw: RawEventSample) -> Result<Self, String> {
+        let event = parse_event(&raw.event, raw.payout)
+            .ok_or_else(|| format!("Unknown economic event: {}", raw.event))?;
+        Ok(Self {
+            block: raw.block,
+            time_ms: raw.time_ms,
+            event,
+            payout: raw.payout,
+            v: raw.v,
+            p: raw.p,
+        })
+    }
+}
+
+/// Periodic snapshots of the workers' tokenomic state and the economic events in between, kept
+/// for the recent blocks and saved along with the checkpoints.
+///
+/// The state at a block between two snapshots is not kept. It is rebuilt from the previous
+/// snapshot and the events since then.
+#[derive(Serialize, Deserialize, Default)]
+pub struct History {
+    /// The number of blocks between two snapshots, 0 for disabled.
+    #[serde(skip)]
+    interval: BlockNumber,
+    /// The number of recent blocks to keep the history for.
+    #[serde(skip)]
+    retention: BlockNumber,
+    snapshots: BTreeMap<BlockNumber, BTreeMap<WorkerPublicKey, WorkerSample>>,
+    events: BTreeMap<WorkerPublicKey, VecDeque<EventSample>>,
+}
+
+impl History {
+    /// Applies the settings to a history restored from a checkpoint, dropping what they exclude.
+    pub fn configure(&mut self, interval: BlockNumber, retention: BlockNumber) {
+        self.interval = interval;
+        self.retention = retention;
+        if !self.enabled() {
+            self.snapshots.clear();
+            self.events.clear();
+            return;
+        }
+        if let Some((_, last)) = self.range() {
+            self.prune(last.saturating_sub(retention));
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.interval > 0
+    }
+
+    pub(super) fn record_events(&mut self, records: &[EventRecord]) {
+        if !self.enabled() {
+            return;
+        }
+        for rec in records {
+            self.events
+                .entry(rec.pubkey)
+                .or_default()
+                .push_back(EventSample {
+                    block: rec.block_number,
+                    time_ms: rec.time_ms,
+                    event: rec.event,
+                    payout: rec.event.payout(),
+                    v: rec.v,
+                    p: rec.p,
+                });
+        }
+    }
+
+    /// Takes a snapshot at every `interval` blocks and drops the history out of the retention.
+    pub fn on_block_processed<'a>(
+        &mut self,
+        block: BlockNumber,
+        workers: impl Iterator<Item = &'a gk::WorkerInfo>,
+    ) {
+        if !self.enabled() || block % self.interval != 0 {
+            return;
+        }
+        let snapshot = workers
+            .map(|worker| (*worker.pubkey(), WorkerSample::new(block, worker)))
+            .collect();
+        self.add_snapshot(block, snapshot);
+    }
+
+    fn add_snapshot(
+        &mut self,
+        block: BlockNumber,
+        snapshot: BTreeMap<WorkerPublicKey, WorkerSample>,
+    ) {
+        self.snapshots.insert(block, snapshot);
+        self.prune(block.saturating_sub(self.retention));
+    }
+
+    /// Drops the snapshots and the events before the oldest block.
+    fn prune(&mut self, oldest: BlockNumber) {
+        self.snapshots = self.snapshots.split_off(&oldest);
+        for events in self.events.values_mut() {
+            while matches!(events.front(), Some(event) if event.block < oldest) {
+                events.pop_front();
+            }
+        }
+        self.events.retain(|_, events| !events.is_empty());
+    }
+
+    /// The first and the last block of the snapshots.
+    pub fn range(&self) -> Option<(BlockNumber, BlockNumber)> {
+        let first = self.snapshots.keys().next()?;
+        let last = self.snapshots.keys().next_back()?;
+        Some((*first, *last))
+    }
+
+    /// The block of the last snapshot at or before the block.
+    pub fn snapshot_at(&self, block: BlockNumber) -> Option<BlockNumber> {
+        self.snapshots
+            .range(..=block)
+            .next_back()
+            .map(|(&block, _)| block)
+    }
+
+    /// The state of the worker as of its last event at or before the block.
+    ///
+    /// The state is rebuilt from the last snapshot at or before the block and the worker's events
+    /// since then. A worker missing in that snapshot is registered after it, so its state starts
+    /// from its first event instead.
+    pub fn worker_at(&self, pubkey: &WorkerPublicKey, block: BlockNumber) -> Option<WorkerSample> {
+        let (&snapshot_block, snapshot) = self.snapshots.range(..=block).next_back()?;
+        let mut sample = snapshot.get(pubkey).copied();
+        for event in self.worker_events(pubkey, snapshot_block + 1, block) {
+            match &mut sample {
+                Some(sample) => sample.apply(&event),
+                None => sample = Some(WorkerSample::registered(&event)),
+            }
+        }
+        sample
+    }
+
+    /// The states of the worker in the snapshots within `from..=to`.
+    pub fn worker_samples(
+        &self,
+        pubkey: &WorkerPublicKey,
+        from: BlockNumber,
+        to: BlockNumber,
+    ) -> Vec<WorkerSample> {
+        if from > to {
+            return vec![];
+        }
+        self.snapshots
+            .range(from..=to)
+            .filter_map(|(_, snapshot)| snapshot.get(pubkey).copied())
+            .collect()
+    }
+
+    /// The economic events of the worker within `from..=to`.
+    pub fn worker_events(
+        &self,
+        pubkey: &WorkerPublicKey,
+        from: BlockNumber,
+        to: BlockNumber,
+    ) -> Vec<EventSample> {
+        self.events
+            .get(pubkey)
+            .map(|events| {
+                events
+                    .iter()
+                    .skip_while(|event| event.block < from)
+                    .take_while(|event| event.block <= to)
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gk::{EconomicEvent, FixedPoint};
+
+    fn new_history(interval: BlockNumber, retention: BlockNumber) -> History {
+        let mut history = History::default();
+        history.configure(interval, retention);
+        history
+    }
+
+    fn pubkey(i: u8) -> WorkerPublicKey {
+        WorkerPublicKey::from_raw([i; 32])
+    }
+
+    fn sample(block: BlockNumber, v: u32, unresponsive: bool, mining: bool) -> WorkerSample {
+        WorkerSample {
+            block,
+            v: FixedPoint::from_num(v),
+            p: Default::default(),
+            unresponsive,
+            mining,
+        }
+    }
+
+    /// A snapshot of the given workers mining, each with its index as V.
+    fn snapshot(block: BlockNumber, workers: &[u8]) -> BTreeMap<WorkerPublicKey, WorkerSample> {
+        workers
+            .iter()
+            .map(|&i| (pubkey(i), sample(block, i as _, false, true)))
+            .collect()
+    }
+
+    fn event(worker: u8, block: BlockNumber, event: EconomicEvent, v: u32) -> EventRecord {
+        EventRecord {
+            sequence: 0,
+            pubkey: pubkey(worker),
+            block_number: block,
+            time_ms: 0,
+            event,
+            v: FixedPoint::from_num(v),
+            p: Default::default(),
+        }
+    }
+
+    fn heartbeat(worker: u8, block: BlockNumber) -> EventRecord {
+        let payout = FixedPoint::from_num(1);
+        event(worker, block, EconomicEvent::Heartbeat { payout }, 1)
+    }
+
+    fn blocks(events: Vec<EventSample>) -> Vec<BlockNumber> {
+        events.iter().map(|event| event.block).collect()
+    }
+
+    #[test]
+    fn prunes_out_of_retention() {
+        let mut history = new_history(10, 20);
+        history.record_events(&[heartbeat(1, 5), heartbeat(2, 5), heartbeat(1, 15)]);
+        for block in [10, 20, 30] {
+            history.add_snapshot(block, snapshot(block, &[1, 2]));
+        }
+        // The events before the oldest kept block are dropped, along with the workers left
+        // without events.
+        assert_eq!(history.range(), Some((10, 30)));
+        assert_eq!(blocks(history.worker_events(&pubkey(1), 0, 100)), vec![15]);
+        assert!(!history.events.contains_key(&pubkey(2)));
+
+        history.record_events(&[heartbeat(1, 35)]);
+        history.add_snapshot(40, snapshot(40, &[1, 2]));
+        assert_eq!(history.range(), Some((20, 40)));
+        assert_eq!(blocks(history.worker_events(&pubkey(1), 0, 100)), vec![35]);
+        assert_eq!(history.worker_at(&pubkey(1), 19), None);
+    }
+
+    #[test]
+    fn snapshots_are_taken_at_the_interval() {
+        let mut history = new_history(10, 100);
+        for block in 1..=25 {
+            history.on_block_processed(block, std::iter::empty());
+        }
+        assert_eq!(
+            history.snapshots.keys().copied().collect::<Vec<_>>(),
+            vec![10, 20]
+        );
+
+        let mut disabled = new_history(0, 100);
+        disabled.record_events(&[heartbeat(1, 5)]);
+        disabled.on_block_processed(10, std::iter::empty());
+        assert!(!disabled.enabled());
+        assert_eq!(disabled.range(), None);
+        assert!(disabled.worker_events(&pubkey(1), 0, 100).is_empty());
+    }
+
+    #[test]
+    fn worker_at_the_last_snapshot() {
+        let mut history = new_history(10, 100);
+        history.add_snapshot(10, snapshot(10, &[1]));
+        history.add_snapshot(20, snapshot(20, &[1]));
+        // Nothing is known before the first snapshot
+        assert_eq!(history.worker_at(&pubkey(1), 9), None);
+        assert_eq!(
+            history.worker_at(&pubkey(1), 10),
+            Some(sample(10, 1, false, true))
+        );
+        assert_eq!(
+            history.worker_at(&pubkey(1), 19),
+            Some(sample(10, 1, false, true))
+        );
+        assert_eq!(
+            history.worker_at(&pubkey(1), 25),
+            Some(sample(20, 1, false, true))
+        );
+        assert_eq!(history.worker_at(&pubkey(2), 25), None);
+    }
+
+    #[test]
+    fn worker_at_falls_back_to_events() {
+        let mut history = new_history(10, 100);
+        history.add_snapshot(10, snapshot(10, &[1]));
+        // The worker 2 is registered after the snapshot at 10
+        history.record_events(&[
+            event(2, 12, EconomicEvent::MiningStart, 5),
+            event(2, 14, EconomicEvent::EnterUnresponsive, 4),
+        ]);
+        history.add_snapshot(20, snapshot(20, &[1, 2]));
+        history.record_events(&[event(2, 22, EconomicEvent::ExitUnresponsive, 3)]);
+
+        assert_eq!(history.worker_at(&pubkey(2), 11), None);
+        assert_eq!(
+            history.worker_at(&pubkey(2), 12),
+            Some(sample(12, 5, false, true))
+        );
+        assert_eq!(
+            history.worker_at(&pubkey(2), 19),
+            Some(sample(14, 4, true, true))
+        );
+        // The events since the snapshot are applied once the worker is in it
+        assert_eq!(
+            history.worker_at(&pubkey(2), 21),
+            Some(sample(20, 2, false, true))
+        );
+        assert_eq!(
+            history.worker_at(&pubkey(2), 25),
+            Some(sample(22, 3, false, true))
+        );
+    }
+
+    #[test]
+    fn restored_from_a_checkpoint() {
+        let mut history = new_history(10, 100);
+        history.add_snapshot(10, snapshot(10, &[1]));
+        history.record_events(&[
+            heartbeat(1, 12),
+            event(1, 14, EconomicEvent::EnterUnresponsive, 4),
+        ]);
+        history.add_snapshot(20, snapshot(20, &[1]));
+
+        let data = serde_cbor::to_vec(&history).unwrap();
+        let mut restored: History = serde_cbor::from_slice(&data).unwrap();
+        restored.configure(10, 100);
+        assert_eq!(restored.range(), Some((10, 20)));
+        for block in [10, 13, 15, 25] {
+            assert_eq!(
+                restored.worker_at(&pubkey(1), block),
+                history.worker_at(&pubkey(1), block)
+            );
+        }
+        let events = restored.worker_events(&pubkey(1), 0, 100);
+        assert_eq!(blocks(events.clone()), vec![12, 14]);
+        assert_eq!(events[0].event, heartbeat(1, 12).event);
+
+        // The restored history follows the new settings
+        let mut restored: History = serde_cbor::from_slice(&data).unwrap();
+        restored.configure(10, 5);
+        assert_eq!(restored.range(), Some((20, 20)));
+        assert!(restored.worker_events(&pubkey(1), 0, 100).is_empty());
+        restored.configure(0, 5);
+        assert!(!restored.enabled());
+        assert_eq!(restored.range(), None);
+    }
+
+    #[test]
+    fn worker_events_within_bounds() {
+        let mut history = new_history(10, 100);
+        history.record_events(&[
+            heartbeat(1, 12),
+            heartbeat(1, 14),
+            heartbeat(2, 14),
+            heartbeat(1, 14),
+            heartbeat(1, 16),
+        ]);
+        let events = |from, to| blocks(history.worker_events(&pubkey(1), from, to));
+        assert_eq!(events(0, 100), vec![12, 14, 14, 16]);
+        assert_eq!(events(14, 16), vec![14, 14, 16]);
+        assert_eq!(events(13, 15), vec![14, 14]);
+        assert_eq!(events(12, 12), vec![12]);
+        assert_eq!(events(15, 14), Vec::<BlockNumber>::new());
+        assert_eq!(events(17, 100), Vec::<BlockNumber>::new());
+        assert!(history.worker_events(&pubkey(3), 0, 100).is_empty());
+    }
+}
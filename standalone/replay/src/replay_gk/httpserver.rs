@@ -8,6 +8,27 @@ struct AppState {
     factory: Arc<Mutex<ReplayFactory>>,
 }
 
+#[derive(Deserialize)]
+struct BlockRange {
+    from: Option<BlockNumber>,
+    to: Option<BlockNumber>,
+}
+
+fn parse_pubkey(pubkey: &str) -> Result<WorkerPublicKey, HttpResponse> {
+    match AccountId32::from_str(pubkey) {
+        Ok(accid) => Ok(WorkerPublicKey(accid.into())),
+        Err(_) => Err(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Invalid pubkey"
+        }))),
+    }
+}
+
+fn history_disabled() -> HttpResponse {
+    HttpResponse::NotFound().json(serde_json::json!({
+        "error": "Snapshots are disabled"
+    }))
+}
+
 #[get("/meminfo")]
 async fn meminfo(data: web::Data<AppState>) -> HttpResponse {
     let factory = data.factory.lock().await;
@@ -29,13 +50,9 @@ async fn get_worker_state(
     data: web::Data<AppState>,
 ) -> HttpResponse {
     let factory = data.factory.lock().await;
-    let pubkey = match AccountId32::from_str(pubkey.as_str()) {
-        Ok(accid) => WorkerPublicKey(accid.into()),
-        Err(_) => {
-            return HttpResponse::BadRequest().json(serde_json::json!({
-                "error": "Invalid pubkey"
-            }));
-        }
+    let pubkey = match parse_pubkey(pubkey.as_str()) {
+        Ok(pubkey) => pubkey,
+        Err(response) => return response,
     };
 
     let total_share = factory.gk.sum_share();
@@ -51,6 +68,75 @@ async fn get_worker_state(
     }
 }
 
+/// The state of the worker at a past block, rebuilt from the last snapshot at or before the block
+/// and the economic events since the snapshot.
+#[get("/worker-state/{pubkey}/at/{block}")]
+async fn get_worker_state_at(
+    path: web::Path<(String, BlockNumber)>,
+    data: web::Data<AppState>,
+) -> HttpResponse {
+    let (pubkey, block) = path.into_inner();
+    let pubkey = match parse_pubkey(&pubkey) {
+        Ok(pubkey) => pubkey,
+        Err(response) => return response,
+    };
+    let factory = data.factory.lock().await;
+    if !factory.history.enabled() {
+        return history_disabled();
+    }
+    if block > factory.current_block {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Block not replayed yet",
+            "current_block": factory.current_block,
+        }));
+    }
+    match factory.history.worker_at(&pubkey, block) {
+        None => HttpResponse::NotFound().json(serde_json::json!({
+            "error": "No snapshot of the worker at the block",
+            "snapshots": factory.history.range(),
+        })),
+        Some(state) => {
+            let snapshot_block = factory.history.snapshot_at(block);
+            let events = snapshot_block
+                .map(|from| factory.history.worker_events(&pubkey, from + 1, block))
+                .unwrap_or_default();
+            HttpResponse::Ok().json(serde_json::json!({
+                "block": block,
+                "state": state,
+                "snapshot_block": snapshot_block,
+                "events_since_snapshot": events,
+            }))
+        }
+    }
+}
+
+/// The V, P and economic events of the worker over a range of blocks, default to all the blocks
+/// in the history.
+#[get("/worker-history/{pubkey}")]
+async fn get_worker_history(
+    pubkey: web::Path<String>,
+    range: web::Query<BlockRange>,
+    data: web::Data<AppState>,
+) -> HttpResponse {
+    let pubkey = match parse_pubkey(pubkey.as_str()) {
+        Ok(pubkey) => pubkey,
+        Err(response) => return response,
+    };
+    let factory = data.factory.lock().await;
+    if !factory.history.enabled() {
+        return history_disabled();
+    }
+    let from = range.from.unwrap_or(0);
+    let to = range.to.unwrap_or(factory.current_block);
+    HttpResponse::Ok().json(serde_json::json!({
+        "current_block": factory.current_block,
+        "from": from,
+        "to": to,
+        "samples": factory.history.worker_samples(&pubkey, from, to),
+        "events": factory.history.worker_events(&pubkey, from, to),
+    }))
+}
+
 #[get("/workers")]
 async fn dump_workers(data: web::Data<AppState>) -> HttpResponse {
     let factory = data.factory.lock().await;
@@ -68,14 +154,31 @@ async fn dump_workers(data: web::Data<AppState>) -> HttpResponse {
     }))
 }
 
+#[get("/clusters")]
+async fn dump_clusters(data: web::Data<AppState>) -> HttpResponse {
+    let factory = data.factory.lock().await;
+    match &factory.system {
+        None => HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Contracts are not replayed"
+        })),
+        Some(system) => HttpResponse::Ok().json(serde_json::json!({
+            "current_block": factory.current_block,
+            "clusters": system.clusters_summary(),
+        })),
+    }
+}
+
 pub async fn serve(bind_addr: String, factory: Arc<Mutex<ReplayFactory>>) {
     HttpServer::new(move || {
         let factory = factory.clone();
         App::new()
             .app_data(web::Data::new(AppState { factory }))
             .service(get_worker_state)
+            .service(get_worker_state_at)
+            .service(get_worker_history)
             .service(meminfo)
             .service(dump_workers)
+            .service(dump_clusters)
     })
     .disable_signals()
     .bind(&bind_addr)
@@ -0,0 +1,74 @@
+use std::path::Path;
+
+use anyhow::{anyhow, Error};
+use phactory_pal::{
+    AppInfo, AppVersion, AttestationReport, Machine, MemoryStats, MemoryUsage, Sealing, RA,
+};
+use serde::{Deserialize, Serialize};
+
+/// The platform the replayed `System` runs on.
+///
+/// There is no TEE in replay, so nothing is sealed and no attestation report can be created.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct ReplayPlatform;
+
+impl Sealing for ReplayPlatform {
+    type SealError = Error;
+    type UnsealError = Error;
+
+    fn seal_data(&self, _path: impl AsRef<Path>, _data: &[u8]) -> Result<(), Self::SealError> {
+        Ok(())
+    }
+
+    fn unseal_data(&self, _path: impl AsRef<Path>) -> Result<Option<Vec<u8>>, Self::UnsealError> {
+        Ok(None)
+    }
+}
+
+impl RA for ReplayPlatform {
+    type Error = Error;
+
+    fn create_attestation_report(&self, _data: &[u8]) -> Result<AttestationReport, Self::Error> {
+        Err(anyhow!("Remote attestation is not supported in replay"))
+    }
+
+    fn quote_test(&self) -> Result<(), Self::Error> {
+        Err(anyhow!("Remote attestation is not supported in replay"))
+    }
+}
+
+impl Machine for ReplayPlatform {
+    fn machine_id(&self) -> Vec<u8> {
+        vec![]
+    }
+
+    fn cpu_core_num(&self) -> u32 {
+        1
+    }
+
+    fn cpu_feature_level(&self) -> u32 {
+        1
+    }
+}
+
+impl MemoryStats for ReplayPlatform {
+    fn memory_usage(&self) -> MemoryUsage {
+        MemoryUsage {
+            total_peak_used: 0,
+            rust_used: 0,
+            rust_peak_used: 0,
+        }
+    }
+}
+
+impl AppInfo for ReplayPlatform {
+    // The highest version, or the replayed `System` would abort once a version gets retired on
+    // chain.
+    fn app_version() -> AppVersion {
+        AppVersion {
+            major: u32::MAX,
+            minor: u32::MAX,
+            patch: u32::MAX,
+        }
+    }
+}
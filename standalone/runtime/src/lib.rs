@@ -26,6 +26,10 @@
 
 mod msg_routing;
 
+#[cfg(feature = "runtime-benchmarks")]
+#[macro_use]
+extern crate frame_benchmarking;
+
 use codec::{Decode, Encode, MaxEncodedLen};
 use frame_election_provider_support::{
 	onchain, BalancingConfig, ElectionDataProvider, SequentialPhragmen, VoteWeight,
@@ -332,6 +336,7 @@ impl InstanceFilter<Call> for ProxyType {
                     | Call::PhalaStakePool(pallet_stakepool::Call::create { .. })
                     | Call::PhalaRegistry(pallet_registry::Call::register_worker { .. })
                     | Call::PhalaMq(pallet_mq::Call::sync_offchain_message { .. })
+                    | Call::PhalaMq(pallet_mq::Call::sync_offchain_messages { .. })
             ),
 		}
 	}
@@ -1254,22 +1259,37 @@ parameter_types! {
 	pub const MinInitP: u32 = 50;
 	pub const MiningEnabledByDefault: bool = false;
 	pub const MaxPoolWorkers: u32 = 200;
+	pub const CommissionChangeDelay: BlockNumber = 3 * DAYS;
+	pub const MaxCommissionIncrease: Permill = Permill::from_percent(10);
 	pub const VerifyPRuntime: bool = false;
 	pub const VerifyRelaychainGenesisBlockHash: bool = false;
+	pub const MasterKeyRotationRetryInterval: BlockNumber = 1 * HOURS;
+	pub const ContractCommandFee: Balance = 1 * MILLICENTS;
+	pub const ContractQueryFee: Balance = 1 * MILLICENTS / 10;
+	pub const ContractQueryQuota: u32 = 100_000;
+	pub const ContractStorageByteFee: Balance = 1 * MILLICENTS / 100_000;
+	pub const CodeByteFee: Balance = 1 * MILLICENTS / 100;
 }
 
 impl pallet_registry::Config for Runtime {
 	type Event = Event;
 	type Currency = Balances;
-	type AttestationValidator = pallet_registry::IasValidator;
+	#[cfg(not(feature = "runtime-benchmarks"))]
+	type AttestationValidator = pallet_registry::SgxValidator;
+	#[cfg(feature = "runtime-benchmarks")]
+	type AttestationValidator = pallet_registry::BenchmarkIasValidator;
 	type UnixTime = Timestamp;
 	type VerifyPRuntime = VerifyPRuntime;
 	type VerifyRelaychainGenesisBlockHash = VerifyRelaychainGenesisBlockHash;
+	type MasterKeyRotationRetryInterval = MasterKeyRotationRetryInterval;
 	type GovernanceOrigin = EnsureRootOrHalfCouncil;
+	type WeightInfo = pallet_registry::weights::SubstrateWeight<Runtime>;
 }
 impl pallet_mq::Config for Runtime {
+	type Event = Event;
 	type QueueNotifyConfig = msg_routing::MessageRouteConfig;
 	type CallMatcher = MqCallMatcher;
+	type WeightInfo = pallet_mq::weights::SubstrateWeight<Runtime>;
 }
 impl pallet_mining::Config for Runtime {
 	type Event = Event;
@@ -1282,6 +1302,7 @@ impl pallet_mining::Config for Runtime {
 	type OnStopped = PhalaStakePool;
 	type OnTreasurySettled = Treasury;
 	type UpdateTokenomicOrigin = EnsureRootOrHalfCouncil;
+	type WeightInfo = pallet_mining::weights::SubstrateWeight<Runtime>;
 }
 impl pallet_stakepool::Config for Runtime {
 	type Event = Event;
@@ -1290,14 +1311,24 @@ impl pallet_stakepool::Config for Runtime {
 	type GracePeriod = MiningGracePeriod;
 	type MiningEnabledByDefault = MiningEnabledByDefault;
 	type MaxPoolWorkers = MaxPoolWorkers;
+	type CommissionChangeDelay = CommissionChangeDelay;
+	type MaxCommissionIncrease = MaxCommissionIncrease;
 	type OnSlashed = Treasury;
 	type MiningSwitchOrigin = EnsureRootOrHalfCouncil;
 	type BackfillOrigin = EnsureRootOrHalfCouncil;
+	type WeightInfo = pallet_stakepool::weights::SubstrateWeight<Runtime>;
 }
 impl pallet_fat::Config for Runtime {
 	type Event = Event;
 	type InkCodeSizeLimit = ConstU32<{1024*1024*2}>;
 	type SidevmCodeSizeLimit = ConstU32<{1024*1024*8}>;
+	type Currency = Balances;
+	type CommandFee = ContractCommandFee;
+	type QueryFee = ContractQueryFee;
+	type QueryQuota = ContractQueryQuota;
+	type StorageByteFee = ContractStorageByteFee;
+	type CodeByteFee = CodeByteFee;
+	type WeightInfo = pallet_fat::weights::SubstrateWeight<Runtime>;
 }
 
 impl puppets::parachain_info::Config for Runtime {}
@@ -1412,6 +1443,18 @@ impl pallet_mq::CallMatcher<Runtime> for MqCallMatcher {
 	}
 }
 
+#[cfg(feature = "runtime-benchmarks")]
+mod benches {
+	define_benchmarks!(
+		[frame_system, SystemBench::<Runtime>]
+		[pallet_mq, PhalaMq]
+		[pallet_registry, PhalaRegistry]
+		[pallet_mining, PhalaMining]
+		[pallet_stakepool, PhalaStakePool]
+		[pallet_fat, PhalaFatContracts]
+	);
+}
+
 impl_runtime_apis! {
 	impl sp_api::Core<Block> for Runtime {
 		fn version() -> RuntimeVersion {
@@ -1604,6 +1647,53 @@ impl_runtime_apis! {
 		}
 	}
 
+	#[cfg(feature = "runtime-benchmarks")]
+	impl frame_benchmarking::Benchmark<Block> for Runtime {
+		fn benchmark_metadata(extra: bool) -> (
+			Vec<frame_benchmarking::BenchmarkList>,
+			Vec<frame_support::traits::StorageInfo>,
+		) {
+			use frame_benchmarking::{Benchmarking, BenchmarkList};
+			use frame_support::traits::StorageInfoTrait;
+			use frame_system_benchmarking::Pallet as SystemBench;
+
+			let mut list = Vec::<BenchmarkList>::new();
+			list_benchmarks!(list, extra);
+
+			let storage_info = AllPalletsWithSystem::storage_info();
+
+			(list, storage_info)
+		}
+
+		fn dispatch_benchmark(
+			config: frame_benchmarking::BenchmarkConfig
+		) -> Result<Vec<frame_benchmarking::BenchmarkBatch>, sp_runtime::RuntimeString> {
+			use frame_benchmarking::{Benchmarking, BenchmarkBatch, TrackedStorageKey};
+			use frame_system_benchmarking::Pallet as SystemBench;
+
+			impl frame_system_benchmarking::Config for Runtime {}
+
+			let whitelist: Vec<TrackedStorageKey> = sp_std::vec![
+				// Block Number
+				hex_literal::hex!("26aa394eea5630e07c48ae0c9558cef702a5c1b19ab7a04f536c519aca4983ac").to_vec().into(),
+				// Total Issuance
+				hex_literal::hex!("c2261276cc9d1f8598ea4b6a74b15c2f57c875e4cff74148e4628f264b974c80").to_vec().into(),
+				// Execution Phase
+				hex_literal::hex!("26aa394eea5630e07c48ae0c9558cef7ff553b5a9862a516939d82b3d3d8661a").to_vec().into(),
+				// Event Count
+				hex_literal::hex!("26aa394eea5630e07c48ae0c9558cef70a98fdbe9ce6c55837576c60c7af3850").to_vec().into(),
+				// System Events
+				hex_literal::hex!("26aa394eea5630e07c48ae0c9558cef780d41e5e16056765bc8461851072c9d7").to_vec().into(),
+			];
+
+			let mut batches = Vec::<BenchmarkBatch>::new();
+			let params = (&config, &whitelist);
+			add_benchmarks!(params, batches);
+
+			Ok(batches)
+		}
+	}
+
 	#[cfg(feature = "try-runtime")]
 	impl frame_try_runtime::TryRuntime<Block> for Runtime {
 		fn on_runtime_upgrade() -> (Weight, Weight) {
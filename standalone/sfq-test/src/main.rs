@@ -62,15 +62,25 @@ async fn dump(state: &State<App>) -> String {
     let info = state.queue.dump();
 
     let mut flow_stats = HashMap::<String, usize>::new();
-    for (flow, _cost) in info.backlog.iter() {
-        let cnt = flow_stats.get(flow).cloned().unwrap_or_default();
-        flow_stats.insert(flow.to_owned(), cnt + 1);
+    for class in info.classes.iter() {
+        for (flow, _cost) in class.backlog.iter() {
+            let cnt = flow_stats.get(flow).cloned().unwrap_or_default();
+            flow_stats.insert(flow.to_owned(), cnt + 1);
+        }
     }
 
     let mut result = String::default();
     result.push_str(&format!("V time : {}\n", info.virtual_time));
-    result.push_str(&format!("Serving: {}\n", info.serving));
-    result.push_str(&format!("Backlog: {}\n", info.backlog.len()));
+    result.push_str(&format!("Serving: {}/{}\n", info.serving, info.depth));
+    let backlog_len: usize = info.classes.iter().map(|c| c.backlog.len()).sum();
+    result.push_str(&format!("Backlog: {}/{}\n", backlog_len, info.backlog_cap));
+    for class in info.classes.iter() {
+        result.push_str(&format!(
+            "  {:?}: {}\n",
+            class.priority,
+            class.backlog.len()
+        ));
+    }
     result.push_str("Flow stats:\n");
     result.push_str(&format!(
         "      flow, weight,        v clock,      time used,  avg cost,   backlog,  accepted,  rejected,     total\n"
@@ -94,6 +104,13 @@ async fn dump(state: &State<App>) -> String {
     result
 }
 
+#[get("/resize/<backlog>/<depth>")]
+async fn resize(state: &State<App>, backlog: usize, depth: u32) -> String {
+    state.queue.set_backlog_cap(backlog);
+    state.queue.set_depth(depth);
+    "OK".to_string()
+}
+
 #[launch]
 fn rocket() -> _ {
     let args = Args::parse();
@@ -104,5 +121,5 @@ fn rocket() -> _ {
             queue: RequestScheduler::new(args.backlog, args.depth),
             stats: Default::default(),
         })
-        .mount("/test", routes![test, dump])
+        .mount("/test", routes![test, dump, resize])
 }